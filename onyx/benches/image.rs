@@ -0,0 +1,40 @@
+//! Shrinking a camera frame for the preview and thumbnails, the box filter
+//! of `onyx::utils::image` against the generic resize of the image crate.
+//! Run with `cargo bench --bench image`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use image::{imageops::FilterType, DynamicImage, RgbImage};
+use onyx::utils::image::{downscale, downscale_box};
+
+/// Frame the size of the cameras on the machine, with enough detail that
+/// no filter can take a shortcut.
+fn frame() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(1280, 1024, |x, y| {
+        let value = |v: u32| u8::try_from(v % 256).unwrap();
+        image::Rgb([value(x * 7), value(y * 13), value(x ^ y)])
+    }))
+}
+
+/// Halve and quarter a frame with each filter, and shrink it to the width
+/// of the default preview.
+fn bench_downscale(c: &mut Criterion) {
+    let frame = frame();
+    let mut group = c.benchmark_group("downscale");
+    for factor in [2, 4] {
+        let (w, h) = (frame.width() / factor, frame.height() / factor);
+        group.bench_function(format!("box_{factor}x"), |b| {
+            b.iter(|| downscale_box(black_box(&frame), factor));
+        });
+        group.bench_function(format!("resize_triangle_{factor}x"), |b| {
+            b.iter(|| black_box(&frame).resize_exact(w, h, FilterType::Triangle));
+        });
+    }
+    group.bench_function("preview_640", |b| b.iter(|| downscale(black_box(&frame), 640)));
+    group.bench_function("resize_triangle_640", |b| {
+        b.iter(|| black_box(&frame).resize(640, u32::MAX, FilterType::Triangle));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_downscale);
+criterion_main!(benches);
@@ -6,6 +6,7 @@ pub mod crop_bed {
         pub mod camera_array;
     }
     /// Components that provide actuation capability.
+    #[cfg(feature = "canbus")]
     pub mod actuating {
         /// The PDM controls for lighting.
         pub mod lighting;
@@ -14,9 +15,14 @@ pub mod crop_bed {
     }
 }
 
+/// Traits shared by the components and the configs they are built from.
+pub mod traits;
+
 /// Helpful prelude when working with components.
 pub mod prelude {
+    #[cfg(feature = "canbus")]
     pub use crate::components::crop_bed::actuating::lighting::*;
+    #[cfg(feature = "canbus")]
     pub use crate::components::crop_bed::actuating::power::*;
     pub use crate::components::crop_bed::sensing::camera_array::*;
 }
@@ -1,58 +1,333 @@
 use crate::{
-    devices::hardware::pdm::{Pdm, PdmConfig},
-    messages::control::light::LightMessage,
+    components::traits::{ComponentConfig, ComponentController, ComponentError, ComponentHandle},
+    devices::hardware::{
+        ambient_light::AmbientLightSensor,
+        pdm::{
+            check_unique_addresses, frames::CHANNEL_COUNT, open_interface, validate_pdm_config_files, Pdm,
+            PdmConfig, PdmStatus, PdmVerification,
+        },
+    },
+    messages::{
+        control::{
+            heartbeat::{ComponentKind, Heartbeat},
+            light::{
+                LightMessage, LightRejection, LightRejections, LightStatusRequest, LightStatusResponse,
+                SelfTestResult,
+            },
+            response::{ControlResponse, MALFORMED_REASON},
+            weed::FULL_INTENSITY,
+        },
+        encoding::Encoding,
+        envelope::Incoming,
+        logging::{EventCode, LogLevel},
+    },
+    utils::{
+        bus::{MessageBus, Topic},
+        client::ComponentClient,
+        config::{load_yaml, ConfigError, Validate, ValidationReport},
+        health::Health,
+        heartbeat::{emit_heartbeats, HeartbeatEmitterConfig, HeartbeatPeers},
+        location::CropBed,
+        logging::{LogConfig, LogEmitter},
+        net::{FrameRead, FramedCodec, Framing},
+        serde::ordered_map,
+        tasks::{first_finished, NamedTask},
+        watchdog::Progress,
+    },
 };
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use socketcan::tokio::CanSocket as AsyncCanSocket;
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
     ffi::OsStr,
+    io,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Instant,
 };
 use tokio::{
-    io::{AsyncBufReadExt, BufReader},
-    net::{TcpListener, TcpStream},
-    sync::Mutex,
+    io::BufReader,
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::{watch, Mutex, Semaphore},
+    task::{JoinError, JoinHandle, JoinSet},
 };
+use tracing::Instrument;
 use uuid::Uuid;
 
+/// Turning the lights on and off from the ambient light.
+pub mod auto;
+/// Checking every light draws current at start up.
+pub mod self_test;
+/// Strobing the lights on the trigger events of the cameras.
+pub mod strobe;
+/// Protecting the LED bars from overheating when held on.
+pub mod thermal;
+
+use auto::{AutoLightState, AutoLightingConfig};
+use self_test::SelfTestConfig;
+use strobe::StrobeConfig;
+use thermal::{ThermalGuard, ThermalProtectionConfig};
+
+/// Time given for the off frames to leave the interface before the component
+/// reports it has shut down and the process exits.
+const LIGHTS_OFF_FLUSH: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Longest time between the passes of the listener, which waits on
+/// connections otherwise. Well inside any watchdog interval.
+const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Time in milliseconds a connection may stay silent before it is closed,
+/// when not set in the config.
+pub const DEFAULT_CONNECTION_IDLE_TIMEOUT_MS: u64 = 30_000;
+
+/// Connections served at once when not set in the config, more are refused.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 16;
+
 /// Configuration for the crop bed lighting using the utilities PDM.
-// TODO: Extend config to identify which channels are actually going
-//       to be connected to the lights, as this has not been properly 
-//       wired or documented.
 #[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
 pub struct CropBedLightingConfig {
     /// Id the crop bed lighting is attached to.
-    crop_bed_id: u8,
+    crop_bed_id: CropBed,
     /// Canbus interface name.
     canbus_id: String,
     /// Internal linux port the component will listen to messages for.
     port: i32,
     /// Map of config files used to set up the PDMs in the component.
+    #[serde(serialize_with = "ordered_map")]
     pdm_config_files: HashMap<u8, PathBuf>,
+    /// When the PDM configuration is read back and verified, only once at
+    /// start up when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pdm_verification: Option<PdmVerification>,
+    /// The channels of the light messages do not line up with how the
+    /// lights are wired to the utilities PDM, this map translates each to
+    /// the bed position of its PDM and the channel on it. Messages with a
+    /// channel not in the map are rejected.
+    #[serde(default, serialize_with = "ordered_map", skip_serializing_if = "HashMap::is_empty")]
+    light_channel_map: HashMap<u8, (u8, u8)>,
+    /// Highest level in percent each light channel is driven at, keeping
+    /// the LED bars from overheating. Brighter requests are clamped to it,
+    /// channels not in the map are not capped.
+    #[serde(default, serialize_with = "ordered_map", skip_serializing_if = "HashMap::is_empty")]
+    max_light_levels: HashMap<u8, u8>,
+    /// Pulse the lights on the camera triggers, only held on when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    strobe: Option<StrobeConfig>,
+    /// Turn every mapped light on once the PDMs are initialised, so a
+    /// restart at night comes back lit rather than waiting on a message.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    lights_on_at_boot: bool,
+    /// Cycle each mapped light on in turn at start up and check it draws
+    /// current, before any message is handled. Skipped when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    self_test: Option<SelfTestConfig>,
+    /// Turn the mapped lights on and off from an ambient light sensor, only
+    /// driven by light messages when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auto_lighting: Option<AutoLightingConfig>,
+    /// Hold lights on for too long at a safe level while they cool down,
+    /// unprotected when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    thermal_protection: Option<ThermalProtectionConfig>,
+    /// Time in milliseconds a connection may stay silent before it is
+    /// closed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    connection_idle_timeout_ms: Option<u64>,
+    /// Connections served at once, more are refused until one closes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_connections: Option<usize>,
+    /// Encoding of the light messages and responses, json lines when not
+    /// set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding: Option<Encoding>,
+    /// Framing of the connections, lines for json and length prefixed for
+    /// binary encodings when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    framing: Option<Framing>,
+    /// Bed positions of the cameras light messages may come from, any
+    /// camera when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    known_cameras: Vec<u8>,
+    /// Accept light messages for any crop bed, for bench rigs driving one
+    /// set of lights from every bed.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    allow_any_bed: bool,
+    /// Where the heartbeats of the component are sent, none are sent when
+    /// not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    heartbeat_emitter: Option<HeartbeatEmitterConfig>,
+    /// Where the structured log of the component is written, events are
+    /// only printed when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    log: Option<LogConfig>,
 }
 
 // TODO: Similar to others, extract out relevant methods to traits.
 impl CropBedLightingConfig {
     /// Crop bed lighting configuration.
     ///
-    /// * `crop_bed_id`: crop bed ids from [0 - 2]
+    /// * `crop_bed_id`: crop bed module, legacy ids from [0 - 2] convert.
     /// * `canbus_id`: String for the bus ie, can0.
-    pub fn new(crop_bed_id: u8, canbus_id: String, port: i32) -> Self {
+    pub fn new(crop_bed_id: impl Into<CropBed>, canbus_id: String, port: i32) -> Self {
         Self {
             port,
-            crop_bed_id,
+            crop_bed_id: crop_bed_id.into(),
             canbus_id,
             pdm_config_files: HashMap::new(),
+            pdm_verification: None,
+            light_channel_map: HashMap::new(),
+            max_light_levels: HashMap::new(),
+            strobe: None,
+            lights_on_at_boot: false,
+            self_test: None,
+            auto_lighting: None,
+            thermal_protection: None,
+            connection_idle_timeout_ms: None,
+            max_connections: None,
+            encoding: None,
+            framing: None,
+            known_cameras: Vec::new(),
+            allow_any_bed: false,
+            heartbeat_emitter: None,
+            log: None,
         }
     }
 
+    /// Accept light messages from a camera, once one is added messages from
+    /// other cameras are rejected.
+    ///
+    /// * `cam_id`: bed position of the camera.
+    pub fn add_known_camera(mut self, cam_id: u8) -> Self {
+        self.known_cameras.push(cam_id);
+        self
+    }
+
+    /// Accept light messages for any crop bed, not only that of the
+    /// component.
+    pub fn with_any_bed(mut self) -> Self {
+        self.allow_any_bed = true;
+        self
+    }
+
+    /// Send the heartbeats of the component.
+    ///
+    /// * `heartbeat_emitter`: where and how often they go.
+    pub fn with_heartbeat_emitter(mut self, heartbeat_emitter: HeartbeatEmitterConfig) -> Self {
+        self.heartbeat_emitter = Some(heartbeat_emitter);
+        self
+    }
+
+    /// Write the structured log of the component.
+    ///
+    /// * `log`: file or collector the events go to.
+    pub fn with_log(mut self, log: LogConfig) -> Self {
+        self.log = Some(log);
+        self
+    }
+
+    /// Set how long a connection may stay silent before it is closed.
+    ///
+    /// * `connection_idle_timeout_ms`: time in milliseconds.
+    pub fn with_connection_idle_timeout(mut self, connection_idle_timeout_ms: u64) -> Self {
+        self.connection_idle_timeout_ms = Some(connection_idle_timeout_ms);
+        self
+    }
+
+    /// Set how many connections are served at once.
+    ///
+    /// * `max_connections`: connections served, more are refused.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = Some(max_connections);
+        self
+    }
+
+    /// Set the encoding of the connections.
+    ///
+    /// * `encoding`: json lines, CBOR or MessagePack.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Set the framing of the connections.
+    ///
+    /// * `framing`: lines, length prefixed or detected on each connection.
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = Some(framing);
+        self
+    }
+
+    /// Turn every mapped light on once the component has started.
+    pub fn with_lights_on_at_boot(mut self) -> Self {
+        self.lights_on_at_boot = true;
+        self
+    }
+
+    /// Drive the lights from the ambient light between light messages.
+    ///
+    /// * `auto_lighting`: sensor, thresholds and manual hold.
+    pub fn with_auto_lighting(mut self, auto_lighting: AutoLightingConfig) -> Self {
+        self.auto_lighting = Some(auto_lighting);
+        self
+    }
+
+    /// Protect the lights from overheating when held on.
+    ///
+    /// * `thermal_protection`: time on, cool down and safe level.
+    pub fn with_thermal_protection(mut self, thermal_protection: ThermalProtectionConfig) -> Self {
+        self.thermal_protection = Some(thermal_protection);
+        self
+    }
+
+    /// Self-test every mapped light at start up.
+    ///
+    /// * `self_test`: time each light is on for.
+    pub fn with_self_test(mut self, self_test: SelfTestConfig) -> Self {
+        self.self_test = Some(self_test);
+        self
+    }
+
+    /// Pulse the lights on the trigger events of the camera array.
+    ///
+    /// * `strobe`: trigger port, pulse and channels strobed from start up.
+    pub fn with_strobe(mut self, strobe: StrobeConfig) -> Self {
+        self.strobe = Some(strobe);
+        self
+    }
+
+    /// Cap the level a light channel is driven at.
+    ///
+    /// * `channel`: channel in the light messages.
+    /// * `max_level`: highest duty cycle in percent.
+    pub fn with_max_light_level(mut self, channel: u8, max_level: u8) -> Self {
+        self.max_light_levels.insert(channel, max_level);
+        self
+    }
+
+    /// Map a channel of the light messages to where its light is wired.
+    ///
+    /// * `channel`: channel in the light messages.
+    /// * `pdm_id`: bed position of the PDM the light is on.
+    /// * `pdm_channel`: channel on the PDM.
+    pub fn map_light_channel(mut self, channel: u8, pdm_id: u8, pdm_channel: u8) -> Self {
+        self.light_channel_map.insert(channel, (pdm_id, pdm_channel));
+        self
+    }
+
+    /// Set when the PDM configuration is read back and verified.
+    ///
+    /// * `pdm_verification`: check interval and drift policy.
+    pub fn with_pdm_verification(mut self, pdm_verification: PdmVerification) -> Self {
+        self.pdm_verification = Some(pdm_verification);
+        self
+    }
+
     /// Add a PDM config file to the component this will be consumed
     /// when the component is created.
     ///
     /// * `filepath`: filepath to the config file
-    /// * `pdm_id`: Address of the PDM, 30, 31, 32, 33
+    /// * `pdm_id`: bed position of the PDM, the address is set in its config.
     pub fn add_pdm_config_file<F>(mut self, filepath: F, pdm_id: u8) -> Self
     where
         F: AsRef<OsStr>,
@@ -61,26 +336,71 @@ impl CropBedLightingConfig {
         self
     }
 
-    /// Build the config by reading a file, this is a helper function.
+    /// Load the config from a yaml file, the error naming the field that
+    /// is wrong and where it is.
     ///
     /// * `filepath`: path to config.
-    pub fn from_file<F: AsRef<OsStr>>(filepath: F) -> Self {
-        let file = Path::new(&filepath);
-        if file.is_file() {
-            let config_file = config::Config::builder()
-                .add_source(config::File::new(
-                    &file.to_string_lossy(),
-                    config::FileFormat::Yaml,
-                ))
-                .build()
-                .expect("Failed read config");
-
-            config_file
-                .try_deserialize::<CropBedLightingConfig>()
-                .expect("Failed to parse config file into struct")
-        } else {
-            panic!("Could not locate the config file {:?}", file);
+    pub fn try_from_file<F: AsRef<OsStr>>(filepath: F) -> Result<Self, ConfigError> {
+        load_yaml(Path::new(&filepath))
+    }
+
+    /// Canbus interface the PDMs are on.
+    pub fn canbus_id(&self) -> &str {
+        &self.canbus_id
+    }
+
+    /// Read the config of each PDM from its file, keyed by bed position.
+    pub fn pdm_configs(&self) -> Result<HashMap<u8, PdmConfig>, ConfigError> {
+        self.pdm_config_files
+            .iter()
+            .map(|(bed_position, pdm_config_file)| Ok((*bed_position, PdmConfig::try_from_file(pdm_config_file)?)))
+            .collect()
+    }
+
+    /// Connect to the light message socket of a component running the
+    /// config on this machine, in its encoding.
+    pub async fn connect_client(&self) -> io::Result<ComponentClient> {
+        ComponentClient::connect_tcp(
+            format!("127.0.0.1:{}", self.port),
+            self.encoding.unwrap_or_default(),
+            self.framing,
+        )
+        .await
+    }
+}
+
+impl Validate for CropBedLightingConfig {
+    fn validate(&self, report: &mut ValidationReport) {
+        report.check_port("port", self.port);
+        validate_pdm_config_files(report, &self.pdm_config_files);
+        let mut light_channels: Vec<(&u8, &(u8, u8))> = self.light_channel_map.iter().collect();
+        light_channels.sort_unstable();
+        for (channel, (pdm_id, pdm_channel)) in light_channels {
+            let field = format!("light_channel_map.{channel}");
+            if !self.pdm_config_files.contains_key(pdm_id) {
+                report.push(&field, format!("PDM {pdm_id} is not in pdm_config_files"));
+            }
+            if !(1..=CHANNEL_COUNT).contains(pdm_channel) {
+                report.push(field, format!("channel {pdm_channel} is not on the PDM, it has 1 to {CHANNEL_COUNT}"));
+            }
         }
+        let mut max_levels: Vec<(&u8, &u8)> = self.max_light_levels.iter().collect();
+        max_levels.sort_unstable();
+        for (channel, max_level) in max_levels {
+            let field = format!("max_light_levels.{channel}");
+            if *max_level > FULL_INTENSITY {
+                report.push(&field, format!("{max_level}% is above full intensity"));
+            }
+            if !self.light_channel_map.contains_key(channel) {
+                report.push(field, "the channel is not in light_channel_map");
+            }
+        }
+    }
+}
+
+impl ComponentConfig for CropBedLightingConfig {
+    fn crop_bed(&self) -> CropBed {
+        self.crop_bed_id
     }
 }
 
@@ -91,48 +411,414 @@ pub struct CropBedLighting {
     /// Unique id of the component.
     uuid: Uuid,
     /// Crop bed id the component is tied to.
-    crop_bed_id: u8,
+    crop_bed_id: CropBed,
     /// Canbus interface name.
     canbus_id: String,
     /// Map of the PDMs this component managers.
     pdms: HashMap<u8, Pdm>,
     /// Internal linux port that this component listens to.
     port: i32,
+    /// When the PDM configuration is read back and verified.
+    pdm_verification: PdmVerification,
+    /// Set once a PDM has drifted and the policy is to refuse, messages
+    /// are then ignored.
+    drifted: bool,
+    /// Bed position of the PDM and channel on it for each channel of the
+    /// light messages.
+    light_channel_map: HashMap<u8, (u8, u8)>,
+    /// Highest level in percent each light channel is driven at.
+    max_light_levels: HashMap<u8, u8>,
+    /// Trigger port and pulse of the strobe, if the lights can be strobed.
+    strobe: Option<StrobeConfig>,
+    /// Level in percent of each light channel in strobe mode.
+    strobed: BTreeMap<u8, u8>,
+    /// Pulses started for camera triggers.
+    strobe_pulses: u64,
+    /// Turn every mapped light on once started.
+    lights_on_at_boot: bool,
+    /// Time each light is on for in the start up self-test, if it is run.
+    self_test_config: Option<SelfTestConfig>,
+    /// Outcome of the self-test for each light channel.
+    self_test: BTreeMap<u8, SelfTestResult>,
+    /// Sensor, thresholds and manual hold of the automatic lighting, if the
+    /// lights follow the ambient light.
+    auto: Option<AutoLightingConfig>,
+    /// Whether the automatic lighting wants the lights on.
+    auto_state: Option<AutoLightState>,
+    /// Whether the automatic lighting last turned the lights on, unknown
+    /// once a light message has set them.
+    auto_applied: Option<bool>,
+    /// End of the hold put on the automatic lighting by a light message.
+    manual_until: Option<Instant>,
+    /// Time each light has been on and which are cooling down, if they are
+    /// protected from overheating.
+    thermal: Option<ThermalGuard>,
+    /// Level in percent each light channel was last set to.
+    levels: BTreeMap<u8, u8>,
+    /// When the component was created, for the uptime in the status and
+    /// heartbeats.
+    started_at: Instant,
+    /// Time a connection may stay silent before it is closed.
+    connection_idle_timeout: std::time::Duration,
+    /// Connections served at once.
+    max_connections: usize,
+    /// Encoding of the connections.
+    encoding: Encoding,
+    /// Framing of the connections.
+    framing: Framing,
+    /// Cameras light messages may come from, any camera when empty.
+    known_cameras: Vec<u8>,
+    /// Accept light messages for any crop bed.
+    allow_any_bed: bool,
+    /// Light messages rejected for each reason.
+    rejections: LightRejections,
+    /// Where the heartbeats of the component are sent.
+    heartbeat_emitter: Option<HeartbeatEmitterConfig>,
+    /// Latest heartbeats received from other components.
+    peers: HeartbeatPeers,
+    /// Events of the component, printed and written to the structured log.
+    log: LogEmitter,
+    /// Liveness and readiness probed by the orchestrator.
+    health: Health,
+    /// Passes of the listener, followed by the watchdog.
+    listener_progress: Progress,
+    /// Bus the camera triggers are strobed on and a shutdown is requested
+    /// on, when run in one binary with other components.
+    bus: Option<MessageBus>,
 }
 
 impl CropBedLighting {
-    /// Generate a new component by consuming a config.
+    /// Generate a new component by consuming a config, refusing PDM
+    /// configs or strobed channels it cannot run.
     ///
     /// * `config`: `CropBedLightingConfig`
-    pub fn new(config: CropBedLightingConfig) -> Self {
-        Self {
-            uuid: Uuid::new_v4(),
+    pub fn new(config: CropBedLightingConfig) -> Result<Self, ComponentError> {
+        let uuid = Uuid::new_v4();
+        let mut lighting = Self {
+            uuid,
             port: config.port,
             crop_bed_id: config.crop_bed_id,
             canbus_id: config.canbus_id.clone(),
-            pdms: Self::build_from_config(config),
+            pdm_verification: config.pdm_verification.unwrap_or_default(),
+            drifted: false,
+            light_channel_map: config.light_channel_map.clone(),
+            max_light_levels: config.max_light_levels.clone(),
+            strobe: config.strobe.clone(),
+            strobed: BTreeMap::new(),
+            strobe_pulses: 0,
+            lights_on_at_boot: config.lights_on_at_boot,
+            self_test_config: config.self_test,
+            self_test: BTreeMap::new(),
+            auto_state: config
+                .auto_lighting
+                .as_ref()
+                .map(|auto| AutoLightState::new(auto.on_below_lux, auto.off_above_lux, config.lights_on_at_boot)),
+            auto: config.auto_lighting.clone(),
+            auto_applied: None,
+            manual_until: None,
+            thermal: config.thermal_protection.clone().map(ThermalGuard::new),
+            levels: BTreeMap::new(),
+            started_at: Instant::now(),
+            connection_idle_timeout: std::time::Duration::from_millis(
+                config
+                    .connection_idle_timeout_ms
+                    .unwrap_or(DEFAULT_CONNECTION_IDLE_TIMEOUT_MS),
+            ),
+            max_connections: config.max_connections.unwrap_or(DEFAULT_MAX_CONNECTIONS),
+            encoding: config.encoding.unwrap_or_default(),
+            framing: config.encoding.unwrap_or_default().framing_or(config.framing),
+            known_cameras: config.known_cameras.clone(),
+            allow_any_bed: config.allow_any_bed,
+            rejections: LightRejections::default(),
+            heartbeat_emitter: config.heartbeat_emitter.clone(),
+            peers: HeartbeatPeers::default(),
+            log: LogEmitter::new(ComponentKind::Lighting, uuid, config.crop_bed_id).with_config(config.log.as_ref()),
+            health: Health::new(),
+            listener_progress: Progress::new(),
+            bus: None,
+            pdms: Self::build_from_config(config)?,
+        };
+        if let Some(strobe) = lighting.strobe.clone() {
+            if let Err(rejection) = lighting.start_strobe(&strobe.channels, FULL_INTENSITY) {
+                return Err(ComponentError::Invalid(format!(
+                    "Strobed light channels {:?} are invalid, {rejection}",
+                    strobe.channels
+                )));
+            }
         }
+        Ok(lighting)
     }
 
     /// Generate a new component by consuming the config stored
     /// in a file.
     ///
     /// * `filepath`: filepath to a config.
-    pub fn from_config_file<F: AsRef<OsStr>>(filepath: F) -> Self {
-        let config = CropBedLightingConfig::from_file(filepath);
+    pub fn from_config_file<F: AsRef<OsStr>>(filepath: F) -> Result<Self, ComponentError> {
+        let config = CropBedLightingConfig::try_from_file(filepath)?;
         Self::new(config)
     }
 
+    /// Strobe on the camera triggers published on a bus as well as those
+    /// received on the trigger port, and stop once a shutdown is requested
+    /// on it.
+    ///
+    /// * `bus`: bus shared with the other components of the binary.
+    pub fn with_bus(mut self, bus: MessageBus) -> Self {
+        self.bus = Some(bus);
+        self
+    }
+
+    /// Health of the component, updated once it is started.
+    pub fn health(&self) -> Health {
+        self.health.clone()
+    }
+
+    /// Passes of the listener, which locks the component at least once a
+    /// progress interval so one held by a hung task is seen.
+    pub fn listener_progress(&self) -> Progress {
+        self.listener_progress.clone()
+    }
+
+    /// Read back the configuration of every PDM, returning false if one has
+    /// drifted and the policy is to stop driving it.
+    async fn verify_pdms(&self) -> bool {
+        let mut keep_running = true;
+        for (bed_position, pdm) in &self.pdms {
+            let verified = pdm.check_drift(self.pdm_verification.on_drift).await;
+            self.health.set_pdm_ready(*bed_position, verified);
+            keep_running &= verified;
+        }
+        keep_running
+    }
+
+    /// Check a light message is for the crop bed of the component and from a
+    /// known camera.
+    ///
+    /// * `message`: light message received.
+    fn check_source(&self, message: &LightMessage) -> Result<(), LightRejection> {
+        if !self.allow_any_bed && message.crop_bed_id != self.crop_bed_id {
+            return Err(LightRejection::WrongBed(message.crop_bed_id));
+        }
+        if !self.known_cameras.is_empty() && !self.known_cameras.contains(&message.cam_id) {
+            return Err(LightRejection::UnknownCamera(message.cam_id));
+        }
+        Ok(())
+    }
+
+    /// Group the channels of a light message by the PDM their lights are
+    /// wired to and the level each is driven at under its cap, as channels
+    /// on that PDM. Returns why the first channel not in the light channel
+    /// map, or mapped outside the channels of a PDM, is invalid, so nothing
+    /// is switched for a message that is partly invalid.
+    ///
+    /// * `channels`: channels of the light message.
+    /// * `level`: duty cycle in percent asked for.
+    fn route_channels(&self, channels: &[u8], level: u8) -> Result<BTreeMap<(u8, u8), Vec<u8>>, LightRejection> {
+        let mut routed: BTreeMap<(u8, u8), Vec<u8>> = BTreeMap::new();
+        for channel in channels {
+            let (pdm_id, pdm_channel) = self
+                .light_channel_map
+                .get(channel)
+                .ok_or(LightRejection::UnmappedChannel(*channel))?;
+            if !(1..=CHANNEL_COUNT).contains(pdm_channel) {
+                return Err(LightRejection::InvalidPdmChannel(*channel));
+            }
+            let capped = self.capped_level(*channel, level);
+            if capped != level {
+                self.log.warn(
+                    EventCode::Clamped,
+                    format!("Light channel {channel} asked for {level}%, clamped to its maximum of {capped}%"),
+                );
+            }
+            routed.entry((*pdm_id, capped)).or_default().push(*pdm_channel);
+        }
+        Ok(routed)
+    }
+
+    /// Level a light channel is driven at when asked for a level, no higher
+    /// than its cap.
+    ///
+    /// * `channel`: channel in the light messages.
+    /// * `level`: duty cycle in percent asked for.
+    fn capped_level(&self, channel: u8, level: u8) -> u8 {
+        let level = self
+            .max_light_levels
+            .get(&channel)
+            .map_or(level, |max_level| level.min(*max_level));
+        self.thermal
+            .as_ref()
+            .and_then(|thermal| thermal.limit(channel))
+            .map_or(level, |limit| level.min(limit))
+    }
+
+    /// Remember the level light channels were set to, for the status and
+    /// the time they have been on.
+    ///
+    /// * `channels`: channels in the light messages.
+    /// * `level`: duty cycle in percent asked for.
+    fn record_levels(&mut self, channels: &[u8], level: u8) {
+        let now = Instant::now();
+        for channel in channels {
+            self.levels.insert(*channel, self.capped_level(*channel, level));
+            if let Some(thermal) = self.thermal.as_mut() {
+                thermal.request(*channel, level, now);
+            }
+        }
+    }
+
+    /// Heartbeat telling the other components this one is alive.
+    pub fn heartbeat(&self) -> Heartbeat {
+        Heartbeat::new(
+            ComponentKind::Lighting,
+            self.uuid,
+            self.crop_bed_id,
+            self.started_at.elapsed().as_secs_f64(),
+        )
+    }
+
+    /// Latest heartbeats received from other components.
+    pub fn peers(&self) -> &HeartbeatPeers {
+        &self.peers
+    }
+
+    /// State of the lights, answered to a status request.
+    pub fn status(&self) -> LightStatusResponse {
+        LightStatusResponse {
+            crop_bed_id: self.crop_bed_id,
+            channels: self.levels.clone(),
+            strobed: self.strobed.clone(),
+            uptime_s: self.started_at.elapsed().as_secs_f64(),
+            pdms_initialised: self
+                .pdms
+                .iter()
+                .map(|(bed_position, pdm)| (*bed_position, pdm.is_initialised()))
+                .collect(),
+            drifted: self.drifted,
+            rejected: self.rejections,
+            self_test: self.self_test.clone(),
+            cooling_down: self
+                .thermal
+                .as_ref()
+                .map(|thermal| thermal.cooling().collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Set every mapped light channel that is not strobed to a level, under
+    /// the cap of each channel.
+    ///
+    /// * `level`: duty cycle in percent.
+    async fn actuate_all(&mut self, level: u8) {
+        let channels: Vec<u8> = self
+            .light_channel_map
+            .keys()
+            .filter(|channel| !self.strobed.contains_key(channel))
+            .copied()
+            .collect();
+        // Every channel is taken from the map, so none can be unmapped.
+        let Ok(routed) = self.route_channels(&channels, level) else {
+            return;
+        };
+        self.record_levels(&channels, level);
+        for ((pdm_id, level), mut pdm_channels) in routed {
+            pdm_channels.sort_unstable();
+            match self.pdms.get(&pdm_id) {
+                Some(pdm) => pdm.actuate_channels(pdm_channels, f32::from(level)).await,
+                None => self.log.warn(
+                    EventCode::Unrouted,
+                    format!("Light channels {pdm_channels:?} are mapped to PDM {pdm_id}, which is not configured"),
+                ),
+            }
+        }
+    }
+
+    /// Stop strobing and turn every mapped light channel off, the last thing
+    /// sent before the component exits.
+    async fn turn_all_off(&mut self) {
+        self.strobed.clear();
+        self.actuate_all(0).await;
+        self.log
+            .info(EventCode::ChannelsOff, format!("Turned every light off on {}", self.canbus_id));
+    }
+
     /// Internal helper function to create a component from a config struct.
     ///
     /// * `config`: Struct with config details.
-    fn build_from_config(config: CropBedLightingConfig) -> HashMap<u8, Pdm> {
-        let mut pdms = HashMap::new();
-        for (bed_position, pdm_config_file) in config.pdm_config_files {
-            let pdm = Pdm::new(PdmConfig::from_file(pdm_config_file));
-            pdms.insert(bed_position, pdm);
+    fn build_from_config(config: CropBedLightingConfig) -> Result<HashMap<u8, Pdm>, ComponentError> {
+        let pdm_configs = config.pdm_configs()?;
+        if let Err(e) = check_unique_addresses(&pdm_configs) {
+            return Err(ComponentError::Invalid(format!(
+                "Invalid PDM configs for {}: {e}",
+                config.canbus_id
+            )));
+        }
+        Ok(pdm_configs
+            .into_iter()
+            .map(|(bed_position, pdm_config)| (bed_position, Pdm::new(pdm_config)))
+            .collect())
+    }
+}
+
+/// Running lighting component, used to stop it and see how its tasks
+/// ended.
+pub struct CropBedLightingHandle {
+    /// Component shared with the tasks.
+    lighting: Arc<Mutex<CropBedLighting>>,
+    /// Set to stop accepting messages, shared with the task stopping the
+    /// component on a shutdown requested on the bus.
+    stop_tx: Arc<watch::Sender<bool>>,
+    /// Task accepting connections, until it stops.
+    tasks: Vec<NamedTask>,
+    /// Task reading back the PDM configuration, if verified periodically.
+    monitors: Vec<JoinHandle<()>>,
+}
+
+impl CropBedLightingHandle {
+    /// Ask the component to stop, without waiting for it.
+    pub fn request_stop(&self) {
+        self.stop_tx.send_replace(true);
+    }
+
+    /// Component shared with the tasks, for status queries.
+    pub fn component(&self) -> Arc<Mutex<CropBedLighting>> {
+        self.lighting.clone()
+    }
+
+    /// Wait for the listener to stop without being asked to, returning
+    /// its name or how it panicked. Call [`CropBedLightingHandle::shutdown`]
+    /// afterwards to stop the rest.
+    pub async fn stopped(&mut self) -> Result<&'static str, JoinError> {
+        let (name, result) = first_finished(&mut self.tasks).await;
+        result.map(|()| name)
+    }
+
+    /// Stop accepting connections, stop those open and the verification,
+    /// then turn every light off, returning how many tasks panicked on the
+    /// way.
+    pub async fn shutdown(self) -> usize {
+        self.request_stop();
+        let (log, health) = {
+            let gaurd = self.lighting.lock().await;
+            (gaurd.log.clone(), gaurd.health.clone())
+        };
+        health.set_listening(false);
+        let mut tasks_failed = 0;
+        for (name, task) in self.tasks {
+            if let Err(e) = task.await {
+                log.error(EventCode::TaskFailed, format!("Crop bed lighting {name} did not stop cleanly: {e}"));
+                tasks_failed += 1;
+            }
+        }
+        for monitor in &self.monitors {
+            monitor.abort();
         }
-        pdms
+        // Turn the lights off explicitly rather than leave them to the PDM
+        // loss of CAN timeout, which faults the PDM and flickers them.
+        let mut gaurd = self.lighting.lock().await;
+        gaurd.turn_all_off().await;
+        tokio::time::sleep(LIGHTS_OFF_FLUSH).await;
+        log.info(EventCode::ShutDown, format!("Crop bed lighting on {} shut down", gaurd.canbus_id));
+        tasks_failed
     }
 }
 
@@ -140,84 +826,526 @@ impl CropBedLighting {
 pub struct CropBedLightingController;
 
 impl CropBedLightingController {
-    /// Start the component.
+    /// Start the component, returning once it is listening with a handle
+    /// used to stop it. Refuses to start when the canbus, a PDM or the
+    /// ambient light sensor cannot be reached, or a port cannot be bound.
     ///
     /// * `crop_bed_power`: consume to components
     // TODO: move this to pass by reference.
-    pub async fn start(mut crop_bed_power: CropBedLighting) {
-        let interface = Arc::new(Mutex::new(
-            AsyncCanSocket::open(&crop_bed_power.canbus_id)
-                .expect("Failed to create canbus socket"),
-        ));
+    pub async fn start(mut crop_bed_power: CropBedLighting) -> Result<CropBedLightingHandle, ComponentError> {
+        let interface = open_interface(&crop_bed_power.canbus_id).map_err(|source| ComponentError::Canbus {
+            canbus_id: crop_bed_power.canbus_id.clone(),
+            source,
+        })?;
+        crop_bed_power.health.set_canbus_open(true);
 
-        for pdm in crop_bed_power.pdms.values_mut() {
-            pdm.initialise(interface.clone()).await;
+        // An unconfigured PDM only shows up as outputs not firing in the
+        // field, so refuse to start rather than run without one.
+        for (bed_position, pdm) in &mut crop_bed_power.pdms {
+            if let Err(source) = pdm.initialise(interface.clone()).await {
+                return Err(ComponentError::Pdm {
+                    address: pdm.address(),
+                    bed_position: *bed_position,
+                    canbus_id: crop_bed_power.canbus_id.clone(),
+                    source,
+                });
+            }
+        }
+        if !crop_bed_power.verify_pdms().await {
+            return Err(ComponentError::PdmMismatch {
+                canbus_id: crop_bed_power.canbus_id.clone(),
+            });
+        }
+        // The self-test finishes before the port is bound, so no message
+        // can turn a light on part way through it.
+        if let Some(self_test) = crop_bed_power.self_test_config {
+            for pdm in crop_bed_power.pdms.values_mut() {
+                if let Err(e) = pdm.subscribe_feedback(&crop_bed_power.canbus_id) {
+                    crop_bed_power.log.warn(
+                        EventCode::Unavailable,
+                        format!("No current feedback from PDM {}: {e}", pdm.address()),
+                    );
+                }
+            }
+            crop_bed_power.run_self_test(self_test).await;
+        }
+        // Faults are followed for the readiness, the lights stay driven
+        // without them.
+        let mut status_receivers = Vec::new();
+        for (bed_position, pdm) in &mut crop_bed_power.pdms {
+            match pdm.watch_status(&crop_bed_power.canbus_id) {
+                Ok(status_rx) => status_receivers.push((*bed_position, status_rx)),
+                Err(e) => crop_bed_power.log.warn(
+                    EventCode::Unavailable,
+                    format!("No fault status from PDM {}: {e}", pdm.address()),
+                ),
+            }
         }
         // Bind on the loop back port from within the container
         let listener = TcpListener::bind(format!("0.0.0.0:{}", crop_bed_power.port))
             .await
-            .expect("Failed to bind port");
+            .map_err(ComponentError::io(format!(
+                "Failed to bind port {}",
+                crop_bed_power.port
+            )))?;
 
-        let thread_safe_crop_bed_power = Arc::new(Mutex::new(crop_bed_power));
+        let trigger_socket = match &crop_bed_power.strobe {
+            Some(strobe) => Some(
+                UdpSocket::bind(format!("0.0.0.0:{}", strobe.trigger_port))
+                    .await
+                    .map_err(ComponentError::io(format!(
+                        "Failed to bind trigger port {}",
+                        strobe.trigger_port
+                    )))?,
+            ),
+            None => None,
+        };
 
-        // TODO: Remove the continue, picked up with more strict clippy linting.
-        //       very straight forward. Good first issue. Good first issue.
-        #[allow(clippy::needless_continue)]
-        loop {
-            // TODO: review this busy loop.
-            if let Ok((socket, _)) = listener.accept().await {
-                let power_connection = thread_safe_crop_bed_power.clone();
-                tokio::spawn(async move {
-                    handle_connection(socket, power_connection).await;
-                });
-            } else {
-                continue;
+        let ambient_light = match &crop_bed_power.auto {
+            Some(auto) => {
+                Some(AmbientLightSensor::start(auto.sensor.open().map_err(
+                    ComponentError::io("Failed to open the ambient light sensor"),
+                )?))
             }
+            None => None,
+        };
+        // Only once nothing else can fail, so a refused start leaves the
+        // lights off.
+        if crop_bed_power.lights_on_at_boot {
+            crop_bed_power.actuate_all(FULL_INTENSITY).await;
         }
+
+        let verification_interval = crop_bed_power.pdm_verification.interval();
+        let thermal_protected = crop_bed_power.thermal.is_some();
+        let heartbeat_emitter = crop_bed_power.heartbeat_emitter.clone();
+        let bus = crop_bed_power.bus.clone();
+        let strobed_on_bus = crop_bed_power.strobe.is_some();
+        // Tasks do not inherit the span they are spawned in, each is given
+        // the span of the component.
+        let span = crop_bed_power.log.span();
+        let health = crop_bed_power.health.clone();
+        health.set_listening(true);
+        let thread_safe_crop_bed_power = Arc::new(Mutex::new(crop_bed_power));
+        let (stop_tx, mut stop_rx) = watch::channel(false);
+        let stop_tx = Arc::new(stop_tx);
+        let mut monitors = Vec::new();
+
+        if let Some(bus) = &bus {
+            let bus_stop = stop_tx.clone();
+            monitors.push(bus.on_shutdown("crop bed lighting", move || {
+                bus_stop.send_replace(true);
+            }));
+        }
+
+        // Periodically read back the PDM configuration, once drifted with
+        // the refuse policy the lights are no longer driven.
+        if let Some(interval) = verification_interval {
+            let lighting_verification = thread_safe_crop_bed_power.clone();
+            monitors.push(tokio::spawn(
+                async move {
+                    let mut ticker = tokio::time::interval(interval);
+                    ticker.tick().await;
+                    loop {
+                        ticker.tick().await;
+                        let mut gaurd = lighting_verification.lock().await;
+                        if !gaurd.verify_pdms().await {
+                            gaurd.log.error(
+                                EventCode::PdmDrifted,
+                                format!(
+                                    "PDM configuration has drifted, ignoring light messages on {}",
+                                    gaurd.canbus_id
+                                ),
+                            );
+                            gaurd.drifted = true;
+                            break;
+                        }
+                        drop(gaurd);
+                    }
+                }
+                .instrument(span.clone()),
+            ));
+        }
+
+        if thermal_protected {
+            monitors.push(tokio::spawn(
+                thermal::watch_thermal(thread_safe_crop_bed_power.clone()).instrument(span.clone()),
+            ));
+        }
+
+        if let Some(heartbeat_emitter) = heartbeat_emitter {
+            let lighting_heartbeat = thread_safe_crop_bed_power.clone();
+            monitors.push(tokio::spawn(
+                emit_heartbeats(heartbeat_emitter, move || {
+                    let lighting_heartbeat = lighting_heartbeat.clone();
+                    async move { Some(lighting_heartbeat.lock().await.heartbeat()) }
+                })
+                .instrument(span.clone()),
+            ));
+        }
+
+        for (bed_position, status_rx) in status_receivers {
+            monitors.push(tokio::spawn(
+                health
+                    .clone()
+                    .supervise(
+                        "PDM status",
+                        handle_pdm_status(bed_position, status_rx, thread_safe_crop_bed_power.clone()),
+                    )
+                    .instrument(span.clone()),
+            ));
+        }
+
+        let mut tasks: Vec<NamedTask> = Vec::new();
+        if let Some(trigger_socket) = trigger_socket {
+            tasks.push((
+                "strobe",
+                tokio::spawn(
+                    health
+                        .clone()
+                        .supervise(
+                            "strobe",
+                            strobe::serve_triggers(trigger_socket, thread_safe_crop_bed_power.clone(), stop_rx.clone()),
+                        )
+                        .instrument(span.clone()),
+                ),
+            ));
+        }
+
+        if let Some(bus) = bus.filter(|_| strobed_on_bus) {
+            tasks.push((
+                "bus strobe",
+                tokio::spawn(
+                    health
+                        .clone()
+                        .supervise(
+                            "bus strobe",
+                            strobe::follow_bus_triggers(
+                                bus.subscribe(Topic::CameraTrigger, "crop bed lighting strobe"),
+                                thread_safe_crop_bed_power.clone(),
+                                stop_rx.clone(),
+                            ),
+                        )
+                        .instrument(span.clone()),
+                ),
+            ));
+        }
+
+        if let Some(ambient_light) = ambient_light {
+            tasks.push((
+                "auto lighting",
+                tokio::spawn(
+                    health
+                        .clone()
+                        .supervise(
+                            "auto lighting",
+                            auto::follow_ambient_light(
+                                ambient_light,
+                                thread_safe_crop_bed_power.clone(),
+                                stop_rx.clone(),
+                            ),
+                        )
+                        .instrument(span.clone()),
+                ),
+            ));
+        }
+
+        // Accept connections until stopped, the listener is closed once the
+        // task ends so new connections are refused.
+        // Each connection holds a permit while it is served, so flaky clients
+        // reconnecting without closing cannot pile up tasks. The connections
+        // are stopped with the listener, so none turns a light back on after
+        // the shutdown turns them off.
+        let listener_lighting = thread_safe_crop_bed_power.clone();
+        let (max_connections, log, progress) = {
+            let gaurd = listener_lighting.lock().await;
+            (
+                gaurd.max_connections,
+                gaurd.log.clone(),
+                gaurd.listener_progress.clone(),
+            )
+        };
+        let mut progress_ticker = tokio::time::interval(PROGRESS_INTERVAL);
+        let connections = Arc::new(Semaphore::new(max_connections));
+        let listener = tokio::spawn(
+            health
+                .supervise("listener", async move {
+                    let mut connection_tasks = JoinSet::new();
+                    while !*stop_rx.borrow() {
+                        tokio::select! {
+                            accepted = listener.accept() => {
+                                if let Ok((socket, peer)) = accepted {
+                                    let Ok(permit) = connections.clone().try_acquire_owned() else {
+                                        log.warn(
+                                            EventCode::ConnectionRefused,
+                                            format!("Light connection from {peer} refused, too many connections open"),
+                                        );
+                                        continue;
+                                    };
+                                    let power_connection = listener_lighting.clone();
+                                    connection_tasks.spawn(
+                                        async move {
+                                            handle_connection(socket, power_connection).await;
+                                            drop(permit);
+                                        }
+                                        .in_current_span(),
+                                    );
+                                }
+                            }
+                            Some(_) = connection_tasks.join_next(), if !connection_tasks.is_empty() => {}
+                            _ = progress_ticker.tick() => {
+                                drop(listener_lighting.lock().await);
+                                progress.tick();
+                            }
+                            changed = stop_rx.changed() => {
+                                if changed.is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    connection_tasks.shutdown().await;
+                })
+                .instrument(span),
+        );
+        tasks.push(("listener", listener));
+
+        Ok(CropBedLightingHandle {
+            lighting: thread_safe_crop_bed_power,
+            stop_tx,
+            tasks,
+            monitors,
+        })
+    }
+}
+
+impl ComponentHandle for CropBedLightingHandle {
+    type Status = usize;
+
+    fn request_stop(&self) {
+        CropBedLightingHandle::request_stop(self);
+    }
+
+    async fn shutdown(self) -> usize {
+        CropBedLightingHandle::shutdown(self).await
     }
 }
 
-/// Handle new connection and stay connected to keep reading the bytes sent over the wire.
+impl ComponentController for CropBedLightingController {
+    type Config = CropBedLightingConfig;
+    type Component = CropBedLighting;
+    type Handle = CropBedLightingHandle;
+
+    fn build(config: CropBedLightingConfig) -> Result<CropBedLighting, ComponentError> {
+        CropBedLighting::new(config)
+    }
+
+    async fn start(component: CropBedLighting) -> Result<CropBedLightingHandle, ComponentError> {
+        CropBedLightingController::start(component).await
+    }
+}
+
+/// Follow the faults reported by a PDM until it stops being monitored, the
+/// PDM is not ready while it reports one. Loss of CAN is left out, nothing
+/// is sent to the PDM between light messages so it is reported whenever
+/// the lights are left alone. The canbus is taken to have failed once the
+/// monitor stops reading it.
+///
+/// * `bed_position`: key of the PDM in the component.
+/// * `status_rx`: fault state of the PDM.
+/// * `lighting`: component.
+async fn handle_pdm_status(
+    bed_position: u8,
+    mut status_rx: watch::Receiver<PdmStatus>,
+    lighting: Arc<Mutex<CropBedLighting>>,
+) {
+    let mut followed = PdmStatus::default();
+    while status_rx.changed().await.is_ok() {
+        let status = PdmStatus {
+            loss_of_can: false,
+            ..status_rx.borrow_and_update().clone()
+        };
+        if status == followed {
+            continue;
+        }
+        followed = status.clone();
+        let gaurd = lighting.lock().await;
+        gaurd.health.set_pdm_ready(bed_position, !status.has_fault());
+        if status.has_fault() {
+            gaurd.log.emit(
+                gaurd
+                    .log
+                    .event(
+                        LogLevel::Error,
+                        EventCode::PdmFault,
+                        format!("ALARM: PDM at bed position {bed_position} reported a fault"),
+                    )
+                    .with_field("status", &status),
+            );
+        }
+    }
+    let gaurd = lighting.lock().await;
+    gaurd.health.set_canbus_open(false);
+    gaurd.log.error(
+        EventCode::Unavailable,
+        format!(
+            "No longer hearing the PDM at bed position {bed_position} on {}",
+            gaurd.canbus_id
+        ),
+    );
+}
+
+/// Handle new connection and stay connected to keep reading the bytes sent over the wire,
+/// until the peer closes it or goes quiet for the idle timeout. A frame cut short by the
+/// peer closing is dropped. Every light message and heartbeat of another component is
+/// answered with a control response and a status request with the status, in the
+/// encoding of the component.
 ///
 /// * `socket`: internal linux socket.
 /// * `power`:  component.
 async fn handle_connection(mut socket: TcpStream, power: Arc<Mutex<CropBedLighting>>) {
-    let (read_stream, _) = socket.split();
+    let (idle_timeout, encoding, framing, log) = {
+        let gaurd = power.lock().await;
+        (
+            gaurd.connection_idle_timeout,
+            gaurd.encoding,
+            gaurd.framing,
+            gaurd.log.clone(),
+        )
+    };
+    let mut codec = FramedCodec::new(framing);
+    let (read_stream, mut write_stream) = socket.split();
     let mut read_stream = BufReader::new(read_stream);
     let mut data = Vec::new();
 
     loop {
-        // TODO: break loop if connection is ended,log issue if terminated prematurely.
         data.clear();
-        let bytes_read = read_stream
-            .read_until(b'\n', &mut data)
-            .await
-            .expect("Failed to read buffer");
+        match tokio::time::timeout(idle_timeout, codec.read_frame(&mut read_stream, &mut data)).await {
+            Ok(Ok(FrameRead::Closed)) => {
+                log.info(EventCode::ConnectionClosed, "Light connection closed");
+                break;
+            }
+            Ok(Ok(FrameRead::CutShort)) => {
+                log.warn(
+                    EventCode::ConnectionClosed,
+                    format!("Light connection closed mid-message, dropping {} bytes", data.len()),
+                );
+                break;
+            }
+            Ok(Ok(FrameRead::Frame)) => {}
+            Ok(Err(e)) => {
+                log.warn(EventCode::ConnectionFailed, format!("Failed to read from the light connection: {e}"));
+                break;
+            }
+            Err(_) => {
+                log.info(EventCode::ConnectionClosed, "Closing idle light connection");
+                break;
+            }
+        }
 
-        if bytes_read != 0 {
-            match serde_json::from_slice::<LightMessage>(&data) {
-                // TODO: add in logs for wrong crop bed, camera ids.
-                Ok(message) => {
-                    println!("Received a message {:?}", message);
+        if encoding.is_blank(&data) {
+            continue;
+        }
+        // Binary frames are handled as the json line they carry, one that is
+        // not a document is passed on as is to be answered as malformed.
+        let line = encoding.to_json(&data).unwrap_or_else(|e| {
+            log.warn(EventCode::MessageMalformed, format!("Received a malformed frame, {e}"));
+            Cow::Borrowed(&data[..])
+        });
+        let payload = match serde_json::from_slice::<Incoming<LightMessage>>(&line) {
+            Ok(incoming) => {
+                incoming.warn_clock_skew(Utc::now());
+                let correlation_id = incoming.id().map(|id| id.to_string());
+                match &correlation_id {
+                    Some(id) => log.info(
+                        EventCode::MessageReceived,
+                        format!("Received message {id} {:?}", incoming.payload()),
+                    ),
+                    None => log.info(
+                        EventCode::MessageReceived,
+                        format!("Received a message {:?}", incoming.payload()),
+                    ),
+                }
+                let message = incoming.into_payload();
 
-                    let gaurd = power.lock().await;
+                let mut gaurd = power.lock().await;
+                // Strobed channels are held off between the pulses.
+                let strobing = message.strobe && message.is_on;
 
-                    if message.is_on {
-                        if let Some(pdm) = gaurd.pdms.get(&0) {
-                            pdm.driver
-                                .actuate_channels(17, message.channels, 100.0)
-                                .await;
+                let response = if gaurd.drifted {
+                    log.warn(EventCode::MessageRejected, "Message ignored, PDM configuration has drifted");
+                    ControlResponse::rejected(correlation_id, "PDM configuration has drifted")
+                } else if strobing && gaurd.strobe.is_none() {
+                    log.warn(EventCode::MessageRejected, "Message rejected, strobe is not configured");
+                    ControlResponse::rejected(correlation_id, "strobe is not configured")
+                } else {
+                    let level = if strobing { 0 } else { message.duty_percent() };
+                    let routed = gaurd
+                        .check_source(&message)
+                        .and_then(|()| gaurd.route_channels(&message.channels, level));
+                    match routed {
+                        Ok(routed) => {
+                            if strobing {
+                                // Routed above, so every channel is in the map.
+                                let _ = gaurd.start_strobe(&message.channels, message.level);
+                            } else if gaurd.stop_strobe(&message.channels) {
+                                log.info(
+                                    EventCode::MessageReceived,
+                                    format!("Light channels {:?} are no longer strobed", message.channels),
+                                );
+                            }
+                            gaurd.record_levels(&message.channels, level);
+                            gaurd.hold_auto();
+                            for ((pdm_id, level), pdm_channels) in routed {
+                                match gaurd.pdms.get(&pdm_id) {
+                                    Some(pdm) => pdm.actuate_channels(pdm_channels, f32::from(level)).await,
+                                    None => log.warn(
+                                        EventCode::Unrouted,
+                                        format!(
+                                            "Light channels {pdm_channels:?} are mapped to PDM {pdm_id}, which is not configured"
+                                        ),
+                                    ),
+                                }
+                            }
+                            ControlResponse::accepted(correlation_id)
+                        }
+                        Err(rejection) => {
+                            log.warn(EventCode::MessageRejected, format!("Message rejected, {rejection}"));
+                            gaurd.rejections.record(rejection);
+                            ControlResponse::rejected(correlation_id, rejection.to_string())
                         }
-                    } else if let Some(pdm) = gaurd.pdms.get(&0) {
-                        pdm.driver.actuate_channels(17, message.channels, 0.0).await;
                     }
-                    // Make sure to drop the guard strait after using in the loop.
+                };
+                let response = response.with_component(gaurd.uuid);
+                // Make sure to drop the guard strait after using in the loop.
+                drop(gaurd);
+                encoding.encode(&response)
+            }
+            Err(e) => {
+                if let Ok(heartbeat) = serde_json::from_slice::<Incoming<Heartbeat>>(&line) {
+                    let mut gaurd = power.lock().await;
+                    gaurd.peers.record(heartbeat.into_payload(), Utc::now());
+                    let response = ControlResponse::accepted(None).with_component(gaurd.uuid);
                     drop(gaurd);
+                    encoding.encode(&response)
+                } else if serde_json::from_slice::<Incoming<LightStatusRequest>>(&line).is_ok() {
+                    let status = power.lock().await.status();
+                    encoding.encode(&status)
+                } else {
+                    log.warn(
+                        EventCode::MessageMalformed,
+                        format!("Received a malformed request {:?}, data: {:?}", e, &line),
+                    );
+                    let uuid = power.lock().await.uuid;
+                    let response = ControlResponse::rejected(None, MALFORMED_REASON).with_component(uuid);
+                    encoding.encode(&response)
                 }
-                Err(e) => {
-                    println!("Received a malformed request {:?}, data: {:?}", e, &data);
-                }
-            };
+            }
+        };
+        // The sender may not wait for the response, so failing to write it
+        // only matters for the log.
+        if let Err(e) = codec.write_frame(&mut write_stream, &payload).await {
+            log.warn(EventCode::ConnectionFailed, format!("Failed to respond on the light connection: {e}"));
+            break;
         }
     }
 }
@@ -225,52 +1353,814 @@ async fn handle_connection(mut socket: TcpStream, power: Arc<Mutex<CropBedLighti
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::messages::control::light::LightRequest;
+    use crate::utils::responses::{read_encoded_response, read_response};
+    use rstest::rstest;
     use serial_test::serial;
-    use std::fs::OpenOptions;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    /// Virtual canbus interface used by the `vcan_test` tests.
+    fn vcan_interface() -> String {
+        std::env::var("ONYX_VCAN").unwrap_or_else(|_| String::from("vcan0"))
+    }
+
+    /// Map the light channels straight onto the utilities PDM at bed
+    /// position 0, as the lights were driven before the map existed.
+    ///
+    /// * `config`: config to add the map to.
+    fn utilities_light_channels(config: CropBedLightingConfig) -> CropBedLightingConfig {
+        (1..=12).fold(config, |config, channel| config.map_light_channel(channel, 0, channel))
+    }
 
     #[test]
-    #[serial]
-    fn test_write_component_config_to_file() {
-        let pdm_config_ids: Vec<(u8, &str, i32)> = vec![(0, "can3", 17653)];
+    /// The lighting config in the repository is valid. A port that cannot
+    /// be listened on, lights mapped to missing PDMs or channels and caps
+    /// above full intensity or on unmapped channels are reported.
+    fn test_validate_lighting_config() {
+        use crate::utils::{config::validate_file, paths::repo_relative};
 
-        for (id, interface, port) in pdm_config_ids {
-            let config = CropBedLightingConfig::new(id, String::from(interface), port)
-                .add_pdm_config_file("./config/devices/crop_bed/pdm_utilities.yaml", 0);
+        let path = repo_relative("config/components/crop_bed/actuating/lighting/crop_bed_lighting.yaml").unwrap();
+        let report = validate_file::<CropBedLightingConfig>(&path);
+        assert!(report.is_valid(), "{report}");
+
+        let pdm_config_file = repo_relative("config/devices/crop_bed/pdm_utilities.yaml").unwrap();
+        let broken = CropBedLightingConfig::new(0, String::from("can3"), 70_000)
+            .add_pdm_config_file(&pdm_config_file, 0)
+            .map_light_channel(1, 0, 1)
+            .map_light_channel(2, 1, 2)
+            .map_light_channel(3, 0, 13)
+            .with_max_light_level(1, 120)
+            .with_max_light_level(4, 50);
+        let mut report = ValidationReport::new("lighting.yaml");
+        broken.validate(&mut report);
+        assert_eq!(
+            report.fields(),
+            [
+                "port",
+                "light_channel_map.2",
+                "light_channel_map.3",
+                "max_light_levels.1",
+                "max_light_levels.4"
+            ],
+            "{report}"
+        );
+    }
+
+    #[test]
+    /// Light channels are grouped by the PDM they are mapped to, and a
+    /// message with any unmapped channel is refused naming it.
+    fn test_route_light_channels() {
+        let config = CropBedLightingConfig::new(0, String::from("can3"), 17653)
+            .map_light_channel(1, 0, 7)
+            .map_light_channel(2, 1, 3)
+            .map_light_channel(3, 0, 8);
+        let lighting = CropBedLighting::new(config).unwrap();
+        assert_eq!(
+            lighting.route_channels(&[1, 2, 3], 100),
+            Ok(BTreeMap::from([((0, 100), vec![7, 8]), ((1, 100), vec![3])]))
+        );
+        assert_eq!(
+            lighting.route_channels(&[1, 4, 2], 100),
+            Err(LightRejection::UnmappedChannel(4))
+        );
+    }
 
-            let file = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open(Path::new(&format!(
-                    "{}/config/components/crop_bed/actuating/lighting/crop_bed_lighting.yaml",
-                    env!("CARGO_MANIFEST_DIR")
-                )))
-                .expect("Faile to open file");
-            serde_yaml::to_writer(file, &config).expect("Failed to write yaml");
+    #[rstest]
+    #[case::uncapped(1, 90, 90)]
+    #[case::below_cap(2, 60, 60)]
+    #[case::clamped(2, 90, 70)]
+    #[case::off(2, 0, 0)]
+    /// Levels above the cap of a channel are clamped to it, channels without
+    /// a cap are driven at the level asked for.
+    fn test_clamp_light_level(#[case] channel: u8, #[case] level: u8, #[case] expected: u8) {
+        let config = CropBedLightingConfig::new(0, String::from("can3"), 17653)
+            .map_light_channel(1, 0, 7)
+            .map_light_channel(2, 0, 8)
+            .with_max_light_level(2, 70);
+        let lighting = CropBedLighting::new(config).unwrap();
+        let routed = lighting.route_channels(&[channel], level).unwrap();
+        assert_eq!(routed.into_keys().collect::<Vec<_>>(), vec![(0, expected)]);
+    }
+
+    #[rstest]
+    #[case::accepted(r#"{"channels": [1], "cam_id": 0, "crop_bed_id": 0}"#, false, Ok(()))]
+    #[case::wrong_bed(r#"{"channels": [1], "cam_id": 0, "crop_bed_id": 2}"#, false, Err(LightRejection::WrongBed(CropBed::RightBoom)))]
+    #[case::any_bed(r#"{"channels": [1], "cam_id": 0, "crop_bed_id": 2}"#, true, Ok(()))]
+    #[case::unknown_camera(r#"{"channels": [1], "cam_id": 5, "crop_bed_id": 0}"#, false, Err(LightRejection::UnknownCamera(5)))]
+    #[case::unmapped_channel(r#"{"channels": [1, 3], "cam_id": 1, "crop_bed_id": 0}"#, false, Err(LightRejection::UnmappedChannel(3)))]
+    #[case::invalid_pdm_channel(r#"{"channels": [2], "cam_id": 1, "crop_bed_id": 0}"#, false, Err(LightRejection::InvalidPdmChannel(2)))]
+    /// Messages for another bed, from an unknown camera or with a channel
+    /// that does not map onto a PDM channel are rejected with the reason,
+    /// unless the bench rig accepts every bed.
+    fn test_validate_light_message(
+        #[case] fields: &str,
+        #[case] allow_any_bed: bool,
+        #[case] expected: Result<(), LightRejection>,
+    ) {
+        let mut config = CropBedLightingConfig::new(0, String::from("can3"), 17653)
+            .map_light_channel(1, 0, 7)
+            .map_light_channel(2, 0, 13)
+            .add_known_camera(0)
+            .add_known_camera(1);
+        if allow_any_bed {
+            config = config.with_any_bed();
+        }
+        let mut lighting = CropBedLighting::new(config).unwrap();
+        let mut message: serde_json::Value = serde_json::from_str(fields).unwrap();
+        message["is_on"] = serde_json::Value::Bool(true);
+        let message: LightMessage = serde_json::from_value(message).unwrap();
+        let validated = lighting
+            .check_source(&message)
+            .and_then(|()| lighting.route_channels(&message.channels, 100))
+            .map(|_| ());
+        assert_eq!(validated, expected);
+        if let Err(rejection) = validated {
+            lighting.rejections.record(rejection);
+            let rejected = lighting.status().rejected;
+            let counts = [
+                rejected.wrong_bed,
+                rejected.unknown_camera,
+                rejected.unmapped_channel,
+                rejected.invalid_pdm_channel,
+            ];
+            assert_eq!(counts.iter().sum::<u64>(), 1, "{rejected:?}");
         }
     }
 
     #[test]
+    /// The light channel map survives a round trip through yaml in channel
+    /// order, and configs written before it existed still load.
+    fn test_light_channel_map_yaml() {
+        let config = CropBedLightingConfig::new(0, String::from("can3"), 17653)
+            .map_light_channel(10, 0, 2)
+            .map_light_channel(9, 1, 4)
+            .with_max_light_level(10, 80);
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert!(yaml.contains("light_channel_map:\n  9:\n  - 1\n  - 4\n  10:\n"), "{yaml}");
+        assert_eq!(serde_yaml::from_str::<CropBedLightingConfig>(&yaml).unwrap(), config);
+
+        let legacy: CropBedLightingConfig =
+            serde_yaml::from_str("crop_bed_id: 0\ncanbus_id: can3\nport: 17653\npdm_config_files: {}\n").unwrap();
+        assert!(legacy.light_channel_map.is_empty());
+        assert!(!legacy.lights_on_at_boot);
+        assert!(!serde_yaml::to_string(&legacy).unwrap().contains("light_channel_map"));
+    }
+
+    #[tokio::test]
     #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// A started component turns the lights of the simulated PDM on through
+    /// the light channel map, with the command id of the PDM config, leaves
+    /// them for a message with an unmapped channel, and refuses connections
+    /// once it has been shut down.
+    async fn test_start_and_shutdown() {
+        use crate::devices::{hardware::pdm::PdmAddress, software::pdm::SimulatedPdm};
+        use tokio::io::AsyncWriteExt;
+
+        let port = 17669;
+        let config_dir = std::env::temp_dir().join(format!("onyx-lighting-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config = PdmConfig::new(PdmAddress::Pdm30, 0)
+            .with_response_timeout(std::time::Duration::from_millis(200))
+            .with_actuate_command_id(0x21);
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let config = CropBedLightingConfig::new(0, vcan_interface(), port)
+            .add_pdm_config_file(pdm_config_file, 0)
+            .map_light_channel(7, 0, 3);
+        let simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let handle = CropBedLightingController::start(CropBedLighting::new(config).unwrap())
+            .await
+            .unwrap();
+        assert!(!handle.component().lock().await.drifted);
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).await.unwrap();
+        stream
+            .write_all(b"{\"channels\": [7], \"is_on\": true, \"cam_id\": 0, \"crop_bed_id\": 0}\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert_eq!(simulated.output(3), Some(100.0));
+        assert_eq!(simulated.output(7), Some(0.0));
+        assert_eq!(simulated.actuations_of(3).last().and_then(|record| record.command_id), Some(0x21));
+
+        stream
+            .write_all(b"{\"channels\": [7, 9], \"is_on\": false, \"cam_id\": 0, \"crop_bed_id\": 0}\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert_eq!(simulated.output(3), Some(100.0), "Partly unmapped message was applied");
+
+        handle.shutdown().await;
+        assert!(
+            TcpStream::connect(format!("127.0.0.1:{port}")).await.is_err(),
+            "Component still accepting connections"
+        );
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    /// A status request is answered with the level each channel was last
+    /// set to under its cap, the strobed channels and the uptime.
+    async fn test_light_status_request() {
+        let config = CropBedLightingConfig::new(0, String::from("can3"), 17653)
+            .map_light_channel(1, 0, 7)
+            .map_light_channel(2, 0, 8)
+            .map_light_channel(3, 0, 9)
+            .with_max_light_level(2, 50)
+            .with_strobe(StrobeConfig::new(17680));
+        let lighting = Arc::new(Mutex::new(CropBedLighting::new(config).unwrap()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_lighting = lighting.clone();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            handle_connection(socket, server_lighting).await;
+        });
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        for message in [
+            r#"{"channels": [1, 2], "is_on": true, "level": 80, "cam_id": 0, "crop_bed_id": 0}"#,
+            r#"{"channels": [1], "is_on": false, "cam_id": 0, "crop_bed_id": 0}"#,
+            r#"{"channels": [3], "is_on": true, "strobe": true, "cam_id": 0, "crop_bed_id": 0}"#,
+            r#"{"channels": [4], "is_on": true, "cam_id": 0, "crop_bed_id": 0}"#,
+            r#"{"request": "status"}"#,
+        ] {
+            stream.write_all(format!("{message}\n").as_bytes()).await.unwrap();
+        }
+        let mut read_stream = BufReader::new(stream);
+        let timeout = std::time::Duration::from_secs(1);
+        for _ in 0..3 {
+            assert!(read_response(&mut read_stream, timeout).await.unwrap().is_accepted());
+        }
+        let response = read_response(&mut read_stream, timeout).await.unwrap();
+        assert_eq!(response.reason(), Some("channel 4 is not in the light channel map"));
+        assert_eq!(response.component, lighting.lock().await.uuid);
+        let mut response = String::new();
+        read_stream.read_line(&mut response).await.unwrap();
+        let status: LightStatusResponse = serde_json::from_str(&response).unwrap();
+        assert_eq!(status.crop_bed_id, CropBed::LeftBoom);
+        assert_eq!(status.channels, BTreeMap::from([(1, 0), (2, 50), (3, 0)]));
+        assert_eq!(status.strobed, BTreeMap::from([(3, 100)]));
+        assert!(status.pdms_initialised.is_empty());
+        assert!(!status.drifted);
+        assert!(status.uptime_s > 0.0);
+        assert_eq!(status, LightStatusResponse { uptime_s: status.uptime_s, ..lighting.lock().await.status() });
+    }
+
+    #[tokio::test]
+    /// Heartbeats of other components are accepted on the light message
+    /// socket and kept, without touching the lights.
+    async fn test_receive_heartbeat() {
+        let config = CropBedLightingConfig::new(0, String::from("can3"), 17653).map_light_channel(1, 0, 7);
+        let lighting = Arc::new(Mutex::new(CropBedLighting::new(config).unwrap()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_lighting = lighting.clone();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            handle_connection(socket, server_lighting).await;
+        });
+
+        let heartbeat =
+            Heartbeat::new(ComponentKind::Power, Uuid::new_v4(), CropBed::LeftBoom, 8.0).with_queue_depth(2);
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(format!("{}\n", serde_json::to_string(&heartbeat).unwrap()).as_bytes())
+            .await
+            .unwrap();
+        let mut read_stream = BufReader::new(stream);
+        let response = read_response(&mut read_stream, std::time::Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert!(response.is_accepted());
+
+        let gaurd = lighting.lock().await;
+        assert_eq!(
+            gaurd.peers().latest(heartbeat.component).map(|(_, latest)| latest),
+            Some(&heartbeat)
+        );
+        assert!(gaurd.levels.is_empty());
+        assert_eq!(gaurd.heartbeat().heartbeat, ComponentKind::Lighting);
+    }
+
+    #[rstest]
+    #[case::cbor(Encoding::Cbor)]
+    #[case::message_pack(Encoding::MessagePack)]
+    #[tokio::test]
+    /// Light messages and status requests framed in a binary encoding are
+    /// answered in it.
+    async fn test_binary_encoding(#[case] encoding: Encoding) {
+        let config = CropBedLightingConfig::new(0, String::from("can3"), 17653)
+            .map_light_channel(1, 0, 7)
+            .with_encoding(encoding);
+        let lighting = Arc::new(Mutex::new(CropBedLighting::new(config).unwrap()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_lighting = lighting.clone();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            handle_connection(socket, server_lighting).await;
+        });
+
+        let mut codec = FramedCodec::new(encoding.framing());
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let message = LightMessage::new(0, 0).channels([1]).on().level(40);
+        stream.write_all(&codec.frame(&encoding.encode(&message))).await.unwrap();
+        stream
+            .write_all(&codec.frame(&encoding.encode(&LightStatusRequest {
+                request: LightRequest::Status,
+            })))
+            .await
+            .unwrap();
+        let mut read_stream = BufReader::new(stream);
+        let timeout = std::time::Duration::from_secs(1);
+        assert!(read_encoded_response(&mut read_stream, &mut codec, encoding, timeout)
+            .await
+            .unwrap()
+            .is_accepted());
+        let mut frame = Vec::new();
+        assert_eq!(
+            codec.read_frame(&mut read_stream, &mut frame).await.unwrap(),
+            FrameRead::Frame
+        );
+        let status: LightStatusResponse = encoding.decode(&frame).unwrap();
+        assert_eq!(status.channels, BTreeMap::from([(1, 40)]));
+    }
+
+    #[tokio::test]
+    /// The handler returns once the peer disconnects, cleanly or mid-message,
+    /// so a client reconnecting is served again rather than leaving a task
+    /// spinning on the closed connection, and silent connections are closed.
+    async fn test_connection_ends_on_disconnect() {
+        use tokio::io::AsyncReadExt;
+
+        let config = CropBedLightingConfig::new(0, String::from("can3"), 17653)
+            .map_light_channel(1, 0, 7)
+            .with_connection_idle_timeout(1000);
+        let lighting = Arc::new(Mutex::new(CropBedLighting::new(config).unwrap()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        for message in [
+            "{\"channels\": [1], \"is_on\": true, \"cam_id\": 0, \"crop_bed_id\": 0}\n",
+            "{\"channels\": [1], \"is_on\": false, \"cam_id\": 0, \"crop_bed_id\": 0}",
+        ] {
+            let server_lighting = lighting.clone();
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            let (socket, _) = listener.accept().await.unwrap();
+            let server = tokio::spawn(handle_connection(socket, server_lighting));
+            stream.write_all(message.as_bytes()).await.unwrap();
+            drop(stream);
+            tokio::time::timeout(std::time::Duration::from_millis(200), server)
+                .await
+                .expect("Handler still running after the peer disconnected")
+                .unwrap();
+        }
+        // The line cut short was dropped, the level is that of the first.
+        assert_eq!(lighting.lock().await.status().channels, BTreeMap::from([(1, 100)]));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let server = tokio::spawn(handle_connection(socket, lighting));
+        tokio::time::timeout(std::time::Duration::from_millis(2000), server)
+            .await
+            .expect("Idle connection was not closed")
+            .unwrap();
+        let mut rest = Vec::new();
+        assert_eq!(stream.read_to_end(&mut rest).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// Strobed lights pulse the simulated PDM once for each trigger of the
+    /// crop bed, and not at all while strobe mode is off.
+    async fn test_strobe_on_camera_triggers() {
+        use crate::devices::{hardware::pdm::PdmAddress, software::pdm::SimulatedPdm};
+        use crate::messages::control::trigger::TriggerMessage;
+        use tokio::io::AsyncWriteExt;
+
+        let (port, trigger_port) = (17673, 17674);
+        let config_dir = std::env::temp_dir().join(format!("onyx-lighting-strobe-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config = PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let config = CropBedLightingConfig::new(0, vcan_interface(), port)
+            .add_pdm_config_file(pdm_config_file, 0)
+            .map_light_channel(7, 0, 3)
+            .with_strobe(StrobeConfig::new(trigger_port).with_pulse(50, 0));
+        let simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let handle = CropBedLightingController::start(CropBedLighting::new(config).unwrap())
+            .await
+            .unwrap();
+        let lighting = handle.component();
+        let camera = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let trigger = |crop_bed_id: u8| {
+            serde_json::to_vec(&TriggerMessage::new(chrono::Utc::now(), 0, CropBed::from(crop_bed_id))).unwrap()
+        };
+        let send_triggers = |count: usize, crop_bed_id: u8| {
+            let camera = &camera;
+            async move {
+                for _ in 0..count {
+                    camera.send_to(&trigger(crop_bed_id), ("127.0.0.1", trigger_port)).await.unwrap();
+                    tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+                }
+            }
+        };
+
+        send_triggers(2, 0).await;
+        assert_eq!(lighting.lock().await.strobe_pulses(), 0);
+        assert!(simulated.actuations_of(3).iter().all(|record| record.duty_percent <= 0.0));
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).await.unwrap();
+        stream
+            .write_all(b"{\"channels\": [7], \"is_on\": true, \"strobe\": true, \"cam_id\": 0, \"crop_bed_id\": 0}\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(lighting.lock().await.strobing());
+        assert_eq!(simulated.output(3), Some(0.0), "Strobed channel held on");
+        send_triggers(3, 0).await;
+        send_triggers(2, 1).await;
+        assert_eq!(lighting.lock().await.strobe_pulses(), 3);
+        let pulses = simulated
+            .actuations_of(3)
+            .iter()
+            .filter(|record| record.duty_percent > 0.0)
+            .count();
+        assert_eq!(pulses, 3, "{:?}", simulated.actuations_of(3));
+        assert_eq!(simulated.output(3), Some(0.0));
+
+        stream
+            .write_all(b"{\"channels\": [7], \"is_on\": false, \"cam_id\": 0, \"crop_bed_id\": 0}\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        send_triggers(2, 0).await;
+        assert!(!lighting.lock().await.strobing());
+        assert_eq!(lighting.lock().await.strobe_pulses(), 3);
+
+        handle.shutdown().await;
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// Camera triggers of the crop bed published on the bus pulse the
+    /// strobed lights, and a shutdown requested on it stops the component.
+    async fn test_strobe_on_bus_triggers() {
+        use crate::devices::{hardware::pdm::PdmAddress, software::pdm::SimulatedPdm};
+        use crate::messages::control::trigger::TriggerMessage;
+        use crate::utils::bus::BusEvent;
+
+        let config_dir = std::env::temp_dir().join(format!("onyx-lighting-bus-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config = PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let config = CropBedLightingConfig::new(0, vcan_interface(), 17680)
+            .add_pdm_config_file(pdm_config_file, 0)
+            .map_light_channel(7, 0, 3)
+            .with_strobe(StrobeConfig::new(17681).with_pulse(50, 0).add_channel(7));
+        let simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let bus = MessageBus::new();
+        let mut handle = CropBedLightingController::start(CropBedLighting::new(config).unwrap().with_bus(bus.clone()))
+            .await
+            .unwrap();
+        let lighting = handle.component();
+
+        for crop_bed_id in [0, 0, 1, 0] {
+            bus.publish(BusEvent::CameraTrigger(TriggerMessage::new(
+                chrono::Utc::now(),
+                0,
+                CropBed::from(crop_bed_id),
+            )));
+            tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        }
+        assert_eq!(lighting.lock().await.strobe_pulses(), 3);
+        let pulses = simulated
+            .actuations_of(3)
+            .iter()
+            .filter(|record| record.duty_percent > 0.0)
+            .count();
+        assert_eq!(pulses, 3, "{:?}", simulated.actuations_of(3));
+
+        bus.request_shutdown();
+        tokio::time::timeout(std::time::Duration::from_secs(1), handle.stopped())
+            .await
+            .expect("Shutdown on the bus did not stop the component")
+            .unwrap();
+        handle.shutdown().await;
+        assert_eq!(simulated.output(3), Some(0.0));
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// Mapped lights come on at boot when configured, and the last frames
+    /// sent on shutdown leave every mapped channel off, strobed or not.
+    async fn test_lights_off_on_shutdown() {
+        use crate::devices::{hardware::pdm::PdmAddress, software::pdm::SimulatedPdm};
+        use tokio::io::AsyncWriteExt;
+
+        let port = 17675;
+        let config_dir = std::env::temp_dir().join(format!("onyx-lighting-shutdown-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config = PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let config = CropBedLightingConfig::new(0, vcan_interface(), port)
+            .add_pdm_config_file(pdm_config_file, 0)
+            .map_light_channel(7, 0, 3)
+            .map_light_channel(8, 0, 4)
+            .map_light_channel(9, 0, 5)
+            .with_max_light_level(8, 40)
+            .with_strobe(StrobeConfig::new(17676).add_channel(9))
+            .with_lights_on_at_boot();
+        let simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let handle = CropBedLightingController::start(CropBedLighting::new(config).unwrap())
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(simulated.output(3), Some(100.0));
+        assert_eq!(simulated.output(4), Some(40.0));
+        assert_eq!(simulated.output(5), Some(0.0), "Strobed channel held on at boot");
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).await.unwrap();
+        stream
+            .write_all(b"{\"channels\": [7], \"is_on\": true, \"level\": 70, \"cam_id\": 0, \"crop_bed_id\": 0}\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(simulated.output(3), Some(70.0));
+
+        handle.shutdown().await;
+        for channel in [3, 4, 5] {
+            let last = simulated.actuations_of(channel).last().cloned();
+            assert_eq!(last.map(|record| record.duty_percent), Some(0.0), "Channel {channel} left on");
+        }
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    /// Write a PDM config to a new directory and start the simulated PDM
+    /// answering for it, returning the directory, the config file and the
+    /// simulated PDM.
+    fn start_simulated_pdm() -> (PathBuf, PathBuf, crate::devices::software::pdm::SimulatedPdmHandle) {
+        use crate::devices::{hardware::pdm::PdmAddress, software::pdm::SimulatedPdm};
+
+        let config_dir = std::env::temp_dir().join(format!("onyx-lighting-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config =
+            PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        (config_dir, pdm_config_file, simulated)
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// A connection left open over the shutdown is stopped with it, so a
+    /// message sent on it afterwards turns no light back on.
+    async fn test_connection_stopped_on_shutdown() {
+        let port = 17708;
+        let (config_dir, pdm_config_file, simulated) = start_simulated_pdm();
+        let config = CropBedLightingConfig::new(0, vcan_interface(), port)
+            .add_pdm_config_file(pdm_config_file, 0)
+            .map_light_channel(7, 0, 3);
+        let handle = CropBedLightingController::start(CropBedLighting::new(config).unwrap())
+            .await
+            .unwrap();
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).await.unwrap();
+        let on = b"{\"channels\": [7], \"is_on\": true, \"cam_id\": 0, \"crop_bed_id\": 0}\n";
+        stream.write_all(on).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(simulated.output(3), Some(100.0));
+
+        handle.shutdown().await;
+        // The connection is closed by now, the write may or may not fail.
+        let _ = stream.write_all(on).await;
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert_eq!(
+            simulated.output(3),
+            Some(0.0),
+            "Light turned back on after the shutdown"
+        );
+        let last = simulated.actuations_of(3).last().cloned();
+        assert_eq!(last.map(|record| record.duty_percent), Some(0.0));
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// A start refused for a port already in use leaves the lights off,
+    /// even when they are turned on at boot.
+    async fn test_failed_start_leaves_lights_off() {
+        let (port, trigger_port) = (17709, 17710);
+        let _taken = std::net::UdpSocket::bind(format!("0.0.0.0:{trigger_port}")).unwrap();
+        let (config_dir, pdm_config_file, simulated) = start_simulated_pdm();
+        let config = CropBedLightingConfig::new(0, vcan_interface(), port)
+            .add_pdm_config_file(pdm_config_file, 0)
+            .map_light_channel(7, 0, 3)
+            .with_strobe(StrobeConfig::new(trigger_port))
+            .with_lights_on_at_boot();
+        let error = CropBedLightingController::start(CropBedLighting::new(config).unwrap())
+            .await
+            .err()
+            .expect("Started on a trigger port in use");
+        assert!(error.to_string().contains("trigger port"), "{error}");
+        assert!(
+            simulated
+                .actuations_of(3)
+                .iter()
+                .all(|record| record.duty_percent.abs() < f32::EPSILON),
+            "Lights turned on by a refused start"
+        );
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// The component is ready once started against the simulated PDM and
+    /// stays so through the loss of CAN the idle lights bring, is not ready
+    /// while the PDM reports a tripped channel and is ready again once the
+    /// trip clears.
+    async fn test_readiness_follows_pdm_faults() {
+        use crate::devices::{
+            hardware::pdm::{frames::ChannelFaults, PdmAddress},
+            software::pdm::SimulatedPdm,
+        };
+
+        let config_dir = std::env::temp_dir().join(format!("onyx-lighting-readiness-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config =
+            PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let config = CropBedLightingConfig::new(0, vcan_interface(), 17697).add_pdm_config_file(pdm_config_file, 0);
+        let simulated = SimulatedPdm::new(pdm_config)
+            .with_loss_of_can_timeout(std::time::Duration::from_millis(100))
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let lighting = CropBedLighting::new(config).unwrap();
+        let health = lighting.health();
+        let handle = CropBedLightingController::start(lighting).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        assert!(simulated.status().loss_of_can, "The idle lights did not lose CAN");
+        assert!(health.report().is_ready(), "{:?}", health.report());
+
+        let mut tripped = simulated.status();
+        tripped.channels[2] = ChannelFaults {
+            over_current_trip: true,
+            ..ChannelFaults::default()
+        };
+        simulated.report_status(tripped).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(health.report().pdms.get(&0), Some(&false));
+        assert!(!health.report().is_ready());
+
+        let cleared = PdmStatus {
+            loss_of_can: true,
+            ..PdmStatus::default()
+        };
+        simulated.report_status(cleared).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(health.report().is_ready(), "{:?}", health.report());
+
+        handle.shutdown().await;
+        assert!(!health.report().is_ready());
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// The self-test turns each mapped light on and off again in channel
+    /// order before messages are served, and reports every channel in the
+    /// status. The simulated PDM sends no current feedback.
+    async fn test_self_test_at_start() {
+        use crate::devices::{hardware::pdm::PdmAddress, software::pdm::SimulatedPdm};
+
+        let port = 17677;
+        let config_dir = std::env::temp_dir().join(format!("onyx-lighting-self-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config = PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let config = CropBedLightingConfig::new(0, vcan_interface(), port)
+            .add_pdm_config_file(pdm_config_file, 0)
+            .map_light_channel(1, 0, 3)
+            .map_light_channel(2, 0, 1)
+            .map_light_channel(3, 0, 2)
+            .with_self_test(SelfTestConfig::default().with_on_ms(50));
+        let simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let handle = CropBedLightingController::start(CropBedLighting::new(config).unwrap())
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let actuations: Vec<(Vec<u8>, bool)> = simulated
+            .actuations()
+            .into_iter()
+            .map(|record| (record.channels, record.duty_percent > 0.0))
+            .collect();
+        assert_eq!(
+            actuations,
+            vec![
+                (vec![3], true),
+                (vec![3], false),
+                (vec![1], true),
+                (vec![1], false),
+                (vec![2], true),
+                (vec![2], false),
+            ]
+        );
+        let status = handle.component().lock().await.status();
+        assert_eq!(
+            status.self_test,
+            BTreeMap::from([
+                (1, SelfTestResult::NoFeedback),
+                (2, SelfTestResult::NoFeedback),
+                (3, SelfTestResult::NoFeedback),
+            ])
+        );
+        handle.shutdown().await;
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[test]
+    /// Configs built apart with the same light channels and caps write
+    /// byte-identical yaml, so the config files do not churn.
+    fn test_config_yaml_is_deterministic() {
+        let config = || {
+            (0..16).fold(
+                CropBedLightingConfig::new(0, String::from("can3"), 17653)
+                    .add_pdm_config_file("./config/devices/crop_bed/pdm_utilities.yaml", 0),
+                |config, channel| config.with_max_light_level(channel, 50 + channel),
+            )
+        };
+        let yaml = serde_yaml::to_string(&utilities_light_channels(config())).unwrap();
+        assert_eq!(serde_yaml::to_string(&utilities_light_channels(config())).unwrap(), yaml);
+        assert!(yaml.contains("max_light_levels:\n  0: 50\n  1: 51\n"), "{yaml}");
+    }
+
+    #[test]
+    /// The lighting config from the generator reads back and writes out as
+    /// it was generated.
+    fn test_write_component_config_to_file() {
+        use crate::utils::configgen::{canonical_configs, to_yaml};
+
+        let generated = canonical_configs()
+            .into_iter()
+            .find(|config| config.path.ends_with("crop_bed_lighting.yaml"))
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("onyx-lighting-config-{}.yaml", Uuid::new_v4()));
+        std::fs::write(&path, &generated.yaml).unwrap();
+        let read_config = CropBedLightingConfig::try_from_file(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(to_yaml(&read_config), generated.yaml);
+        assert_eq!(
+            read_config,
+            utilities_light_channels(
+                CropBedLightingConfig::new(0, String::from("can3"), 17653)
+                    .add_pdm_config_file("./config/devices/crop_bed/pdm_utilities.yaml", 0)
+                    .with_any_bed()
+            )
+        );
+    }
+
+    #[test]
     fn test_read_component_config_to_file() {
         let pdm_config_ids: Vec<(u8, &str, i32)> = vec![(0, "can3", 17653)];
 
         for (id, interface, port) in pdm_config_ids {
             let write_config = CropBedLightingConfig::new(id, String::from(interface), port)
-                .add_pdm_config_file("./config/devices/crop_bed/pdm_utilities.yaml", 0);
-
-            let file = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open(Path::new(&format!(
-                    "{}/config/components/crop_bed/actuating/lighting/crop_bed_lighting.yaml",
-                    env!("CARGO_MANIFEST_DIR")
-                )))
-                .expect("Faile to open file");
-            serde_yaml::to_writer(file, &write_config).expect("Failed to write yaml");
-            let read_config = CropBedLightingConfig::from_file(Path::new(&format!(
-                "{}/config/components/crop_bed/actuating/lighting/crop_bed_lighting.yaml",
-                env!("CARGO_MANIFEST_DIR")
-            )));
+                .add_pdm_config_file("./config/devices/crop_bed/pdm_utilities.yaml", 0)
+                .with_any_bed();
+            let write_config = utilities_light_channels(write_config);
+
+            let path = std::env::temp_dir().join(format!("onyx-lighting-config-{}.yaml", Uuid::new_v4()));
+            crate::utils::configgen::write_config(&path, &write_config).expect("Failed to write yaml");
+            let read_config = CropBedLightingConfig::try_from_file(&path).unwrap();
+            std::fs::remove_file(path).unwrap();
             assert_eq!(
                 write_config, read_config,
                 "Failed to read write array config"
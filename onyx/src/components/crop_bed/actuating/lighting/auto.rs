@@ -0,0 +1,259 @@
+use super::CropBedLighting;
+use crate::{
+    devices::hardware::ambient_light::{AmbientLightConfig, AmbientLightSensor},
+    messages::control::weed::FULL_INTENSITY,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{watch, Mutex};
+use tracing::{info, warn};
+
+/// Time in milliseconds a light message holds off the automatic lighting,
+/// when not set in the config.
+pub const DEFAULT_MANUAL_HOLD_MS: u64 = 600_000;
+
+/// Turn the lights on and off from the ambient light, so a dawn or dusk pass
+/// does not rely on the operator remembering to.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct AutoLightingConfig {
+    /// Where the ambient light is read from.
+    pub sensor: AmbientLightConfig,
+    /// The lights come on once the ambient light drops below this, in lux.
+    pub on_below_lux: u32,
+    /// The lights go off once the ambient light rises above this, in lux.
+    /// The gap to the on threshold keeps them from flickering at dusk.
+    pub off_above_lux: u32,
+    /// Time in milliseconds after a light message before the automatic
+    /// lighting drives the lights again.
+    #[serde(default = "default_manual_hold_ms")]
+    pub manual_hold_ms: u64,
+}
+
+/// Default manual hold for serde.
+fn default_manual_hold_ms() -> u64 {
+    DEFAULT_MANUAL_HOLD_MS
+}
+
+impl AutoLightingConfig {
+    /// Automatic lighting between two thresholds.
+    ///
+    /// * `sensor`: where the ambient light is read from.
+    /// * `on_below_lux`: the lights come on below this.
+    /// * `off_above_lux`: the lights go off above this.
+    pub fn new(sensor: AmbientLightConfig, on_below_lux: u32, off_above_lux: u32) -> Self {
+        Self {
+            sensor,
+            on_below_lux,
+            off_above_lux,
+            manual_hold_ms: DEFAULT_MANUAL_HOLD_MS,
+        }
+    }
+
+    /// Set how long a light message holds off the automatic lighting.
+    ///
+    /// * `manual_hold_ms`: time in milliseconds.
+    pub fn with_manual_hold(mut self, manual_hold_ms: u64) -> Self {
+        self.manual_hold_ms = manual_hold_ms;
+        self
+    }
+}
+
+/// Whether the lights should be on for the ambient light, switching only
+/// once a reading crosses the threshold on the far side of the band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoLightState {
+    /// Readings below this turn the lights on.
+    on_below_lux: u32,
+    /// Readings above this turn the lights off.
+    off_above_lux: u32,
+    /// Whether the lights should be on.
+    is_on: bool,
+}
+
+impl AutoLightState {
+    /// State between two thresholds, panics if the off threshold is not
+    /// above the on threshold as the lights would never settle.
+    ///
+    /// * `on_below_lux`: readings below this turn the lights on.
+    /// * `off_above_lux`: readings above this turn the lights off.
+    /// * `is_on`: whether the lights start on.
+    pub fn new(on_below_lux: u32, off_above_lux: u32, is_on: bool) -> Self {
+        assert!(
+            off_above_lux > on_below_lux,
+            "Automatic lighting turns off above {off_above_lux} lux, which must be above the {on_below_lux} lux it turns on below"
+        );
+        Self {
+            on_below_lux,
+            off_above_lux,
+            is_on,
+        }
+    }
+
+    /// Whether the lights should be on.
+    pub fn is_on(&self) -> bool {
+        self.is_on
+    }
+
+    /// Take a reading, returning whether the lights should now be on if that
+    /// has changed.
+    ///
+    /// * `lux`: illuminance in lux.
+    pub fn update(&mut self, lux: u32) -> Option<bool> {
+        let is_on = if self.is_on {
+            lux <= self.off_above_lux
+        } else {
+            lux < self.on_below_lux
+        };
+        (is_on != self.is_on).then(|| {
+            self.is_on = is_on;
+            is_on
+        })
+    }
+}
+
+impl CropBedLighting {
+    /// Hold off the automatic lighting after a light message, the operator
+    /// has taken over the lights.
+    pub(super) fn hold_auto(&mut self) {
+        let Some(auto) = &self.auto else {
+            return;
+        };
+        if !self.in_manual_hold() {
+            info!("Automatic lighting held for {}ms by a light message", auto.manual_hold_ms);
+        }
+        self.manual_until = Some(Instant::now() + Duration::from_millis(auto.manual_hold_ms));
+        self.auto_applied = None;
+    }
+
+    /// Whether a light message has held off the automatic lighting.
+    fn in_manual_hold(&self) -> bool {
+        self.manual_until.is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Follow a reading of the ambient light, driving every mapped light
+    /// channel when the lights should change or once a manual hold has ended
+    /// and they do not match it.
+    ///
+    /// * `lux`: illuminance in lux.
+    pub(super) async fn apply_lux(&mut self, lux: u32) {
+        let Some(state) = self.auto_state.as_mut() else {
+            return;
+        };
+        if let Some(is_on) = state.update(lux) {
+            let action = if is_on { "on" } else { "off" };
+            info!("Ambient light is {lux} lux, automatic lighting turning the lights {action}");
+        }
+        let is_on = state.is_on();
+        if self.drifted || self.in_manual_hold() || self.auto_applied == Some(is_on) {
+            return;
+        }
+        self.actuate_all(if is_on { FULL_INTENSITY } else { 0 }).await;
+        self.auto_applied = Some(is_on);
+    }
+}
+
+/// Drive the lights from every ambient light reading until the component is
+/// stopped or the sensor stops reading.
+///
+/// * `sensor`: started ambient light sensor.
+/// * `lighting`: component
+/// * `stop_rx`: stop signal from the component handle.
+pub(super) async fn follow_ambient_light(
+    sensor: AmbientLightSensor,
+    lighting: Arc<Mutex<CropBedLighting>>,
+    mut stop_rx: watch::Receiver<bool>,
+) {
+    let mut lux_rx = sensor.subscribe();
+    while !*stop_rx.borrow() {
+        tokio::select! {
+            changed = lux_rx.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+            },
+            _ = stop_rx.changed() => continue,
+        }
+        let lux = *lux_rx.borrow_and_update();
+        match lux {
+            Some(lux) => lighting.lock().await.apply_lux(lux).await,
+            None => warn!("Ambient light sensor stopped, automatic lighting leaves the lights as they are"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        components::crop_bed::actuating::lighting::CropBedLightingConfig,
+        devices::hardware::ambient_light::DEFAULT_AMBIENT_LIGHT_PGN,
+    };
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::dusk(false, &[500, 300, 150, 99, 120, 180], &[false, false, false, true, true, true])]
+    #[case::dawn(true, &[20, 150, 200, 201, 190, 120], &[true, true, true, false, false, false])]
+    #[case::flicker_at_dusk(false, &[99, 101, 99, 150, 201, 199, 99], &[true, true, true, true, false, false, true])]
+    #[case::band_keeps_start(true, &[150, 100, 200], &[true, true, true])]
+    /// The lights switch only once a reading crosses the far threshold,
+    /// so readings wandering inside the band leave them as they are.
+    fn test_auto_light_hysteresis(#[case] is_on: bool, #[case] readings: &[u32], #[case] expected: &[bool]) {
+        let mut state = AutoLightState::new(100, 200, is_on);
+        let followed: Vec<bool> = readings
+            .iter()
+            .map(|lux| {
+                state.update(*lux);
+                state.is_on()
+            })
+            .collect();
+        assert_eq!(followed, expected);
+    }
+
+    #[test]
+    /// A change is only reported on the reading that causes it.
+    fn test_auto_light_reports_changes() {
+        let mut state = AutoLightState::new(100, 200, false);
+        assert_eq!(state.update(50), Some(true));
+        assert_eq!(state.update(40), None);
+        assert_eq!(state.update(250), Some(false));
+        assert_eq!(state.update(250), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be above")]
+    fn test_auto_light_thresholds_overlap() {
+        AutoLightState::new(200, 200, false);
+    }
+
+    #[tokio::test]
+    /// Dusk turns the mapped lights on, a light message holds them for its
+    /// hold period and they follow the ambient light again after it.
+    async fn test_manual_hold_overrides_auto() {
+        let sensor = AmbientLightConfig::Can {
+            canbus_id: String::from("can3"),
+            pgn: DEFAULT_AMBIENT_LIGHT_PGN,
+            source_address: None,
+        };
+        let config = CropBedLightingConfig::new(0, String::from("can3"), 17653)
+            .map_light_channel(1, 0, 7)
+            .map_light_channel(2, 0, 8)
+            .with_max_light_level(2, 60)
+            .with_auto_lighting(AutoLightingConfig::new(sensor, 100, 200).with_manual_hold(50));
+        let mut lighting = CropBedLighting::new(config).unwrap();
+
+        lighting.apply_lux(80).await;
+        assert_eq!(lighting.status().channels, [(1, 100), (2, 60)].into());
+
+        lighting.hold_auto();
+        lighting.record_levels(&[1], 30);
+        lighting.apply_lux(500).await;
+        assert_eq!(lighting.status().channels, [(1, 30), (2, 60)].into(), "Auto lighting ignored the hold");
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        lighting.apply_lux(500).await;
+        assert_eq!(lighting.status().channels, [(1, 0), (2, 0)].into());
+    }
+}
@@ -0,0 +1,147 @@
+use super::CropBedLighting;
+use crate::{
+    devices::hardware::pdm::ChannelFeedback,
+    messages::control::{light::SelfTestResult, weed::FULL_INTENSITY},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Time in milliseconds each light is on for during the self-test, when not
+/// set in the config.
+pub const DEFAULT_SELF_TEST_ON_MS: u64 = 1000;
+
+/// Current in amps below which a light is taken to be drawing nothing.
+const ZERO_CURRENT_AMPS: f32 = 0.05;
+
+/// Turn each mapped light on in turn at start up and check its PDM saw it
+/// draw current, so a dead bar is found before a night run.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct SelfTestConfig {
+    /// Time in milliseconds each light is on for.
+    #[serde(default = "default_on_ms")]
+    pub on_ms: u64,
+}
+
+/// Default on time for serde.
+fn default_on_ms() -> u64 {
+    DEFAULT_SELF_TEST_ON_MS
+}
+
+impl Default for SelfTestConfig {
+    fn default() -> Self {
+        Self {
+            on_ms: DEFAULT_SELF_TEST_ON_MS,
+        }
+    }
+}
+
+impl SelfTestConfig {
+    /// Set how long each light is on for.
+    ///
+    /// * `on_ms`: time in milliseconds.
+    pub fn with_on_ms(mut self, on_ms: u64) -> Self {
+        self.on_ms = on_ms;
+        self
+    }
+}
+
+/// Result for a light from the feedback of its channel while it was on.
+///
+/// * `feedback`: last feedback received once the light had settled.
+fn classify(feedback: Option<ChannelFeedback>) -> SelfTestResult {
+    match feedback {
+        None => SelfTestResult::NoFeedback,
+        Some(feedback) if feedback.has_fault() => SelfTestResult::Fault {
+            status_flags: feedback.status_flags,
+        },
+        Some(feedback) if feedback.amps < ZERO_CURRENT_AMPS => SelfTestResult::NoLoad { amps: feedback.amps },
+        Some(feedback) => SelfTestResult::Pass { amps: feedback.amps },
+    }
+}
+
+impl CropBedLighting {
+    /// Outcome of the start up self-test for each light channel, empty when
+    /// it has not been run.
+    pub fn self_test(&self) -> &BTreeMap<u8, SelfTestResult> {
+        &self.self_test
+    }
+
+    /// Cycle every mapped light channel on at full intensity one at a time,
+    /// in channel order, and log whether each drew current. Only feedback
+    /// from the second half of the on time is used, once the light has
+    /// settled. Every light is left off.
+    ///
+    /// * `config`: time each light is on for.
+    pub(super) async fn run_self_test(&mut self, config: SelfTestConfig) {
+        let on = std::time::Duration::from_millis(config.on_ms);
+        let mut channels: Vec<u8> = self.light_channel_map.keys().copied().collect();
+        channels.sort_unstable();
+        info!("Self-testing {} light channels on {}", channels.len(), self.canbus_id);
+        for channel in channels {
+            let (pdm_id, pdm_channel) = match self.route_channels(&[channel], FULL_INTENSITY) {
+                Ok(routed) => match routed.into_iter().next() {
+                    Some(((pdm_id, _), pdm_channels)) => (pdm_id, pdm_channels[0]),
+                    None => continue,
+                },
+                Err(rejection) => {
+                    warn!("Light channel {channel} skipped in the self-test, {rejection}");
+                    continue;
+                }
+            };
+            let Some(pdm) = self.pdms.get(&pdm_id) else {
+                warn!("Light channel {channel} skipped in the self-test, no PDM at bed position {pdm_id}");
+                continue;
+            };
+            let level = self.capped_level(channel, FULL_INTENSITY);
+            let on_at = Instant::now();
+            pdm.actuate_channels(vec![pdm_channel], f32::from(level)).await;
+            tokio::time::sleep(on).await;
+            let result = classify(pdm.channel_feedback_since(pdm_channel, on_at + on / 2));
+            pdm.actuate_channels(vec![pdm_channel], 0.0).await;
+            info!(
+                "Light channel {channel}, channel {pdm_channel} on PDM {}: {result}",
+                pdm.address()
+            );
+            self.self_test.insert(channel, result);
+        }
+        let passed = self.self_test.values().filter(|result| result.passed()).count();
+        info!("Self-test passed {passed} of {} light channels", self.self_test.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Lights pass on current, fail on none or a fault, and are unknown
+    /// without feedback.
+    fn test_classify_self_test() {
+        let feedback = |amps, status_flags| ChannelFeedback {
+            channel: 3,
+            amps,
+            status_flags,
+        };
+        assert_eq!(classify(None), SelfTestResult::NoFeedback);
+        assert_eq!(classify(Some(feedback(1.5, 0))), SelfTestResult::Pass { amps: 1.5 });
+        assert_eq!(classify(Some(feedback(0.01, 0))), SelfTestResult::NoLoad { amps: 0.01 });
+        assert_eq!(
+            classify(Some(feedback(1.5, ChannelFeedback::FEEDBACK_SHORT_CIRCUIT))),
+            SelfTestResult::Fault {
+                status_flags: ChannelFeedback::FEEDBACK_SHORT_CIRCUIT
+            }
+        );
+        assert!(!classify(None).passed());
+        assert!(classify(Some(feedback(1.5, 0))).passed());
+    }
+
+    #[test]
+    /// The on time falls back to its default.
+    fn test_self_test_config_defaults() {
+        let config: SelfTestConfig = serde_yaml::from_str("{}").unwrap();
+        assert_eq!(config, SelfTestConfig::default());
+        assert_eq!(config.with_on_ms(200).on_ms, 200);
+    }
+}
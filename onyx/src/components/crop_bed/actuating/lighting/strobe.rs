@@ -0,0 +1,276 @@
+use super::CropBedLighting;
+use crate::{
+    messages::control::{light::LightRejection, trigger::TriggerMessage},
+    utils::bus::{BusEvent, BusSubscriber},
+};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::{
+    net::UdpSocket,
+    sync::{watch, Mutex},
+};
+use tracing::{debug, warn};
+
+/// Time in milliseconds a strobed light is on for each trigger, when not set
+/// in the config.
+pub const DEFAULT_STROBE_PULSE_WIDTH_MS: u64 = 10;
+
+/// Pulse the lights for each camera trigger rather than holding them on,
+/// saving power and heat and giving sharper images.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct StrobeConfig {
+    /// UDP port the trigger events of the camera array are received on.
+    pub trigger_port: u16,
+    /// Time in milliseconds the lights are on for each trigger.
+    #[serde(default = "default_pulse_width_ms")]
+    pub pulse_width_ms: u64,
+    /// Time in milliseconds from the trigger to turning the lights on,
+    /// negative to turn them on before it.
+    #[serde(default)]
+    pub phase_offset_ms: i64,
+    /// Light channels strobed from start up, more are strobed and stopped
+    /// by light messages.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<u8>,
+}
+
+/// Default pulse width for serde.
+fn default_pulse_width_ms() -> u64 {
+    DEFAULT_STROBE_PULSE_WIDTH_MS
+}
+
+impl StrobeConfig {
+    /// Strobe on the trigger events received on a port.
+    ///
+    /// * `trigger_port`: UDP port of the trigger events.
+    pub fn new(trigger_port: u16) -> Self {
+        Self {
+            trigger_port,
+            pulse_width_ms: DEFAULT_STROBE_PULSE_WIDTH_MS,
+            phase_offset_ms: 0,
+            channels: Vec::new(),
+        }
+    }
+
+    /// Set how long and when the lights are pulsed around each trigger.
+    ///
+    /// * `pulse_width_ms`: time the lights are on.
+    /// * `phase_offset_ms`: time from the trigger to turning them on.
+    pub fn with_pulse(mut self, pulse_width_ms: u64, phase_offset_ms: i64) -> Self {
+        self.pulse_width_ms = pulse_width_ms;
+        self.phase_offset_ms = phase_offset_ms;
+        self
+    }
+
+    /// Strobe a light channel from start up.
+    ///
+    /// * `channel`: channel in the light messages.
+    pub fn add_channel(mut self, channel: u8) -> Self {
+        self.channels.push(channel);
+        self
+    }
+}
+
+impl CropBedLighting {
+    /// Whether the lights are strobed on a trigger, false when no channel
+    /// is in strobe mode.
+    pub fn strobing(&self) -> bool {
+        !self.strobed.is_empty()
+    }
+
+    /// Count of pulses started since the component was created.
+    pub fn strobe_pulses(&self) -> u64 {
+        self.strobe_pulses
+    }
+
+    /// Channels on each PDM turned on for a pulse, grouped by their level
+    /// as for a light message.
+    fn pulse_routes(&self) -> BTreeMap<(u8, u8), Vec<u8>> {
+        let mut routed: BTreeMap<(u8, u8), Vec<u8>> = BTreeMap::new();
+        for (channel, level) in &self.strobed {
+            // Strobed channels are only added once they have been routed.
+            if let Ok(channels) = self.route_channels(&[*channel], *level) {
+                for (key, pdm_channels) in channels {
+                    routed.entry(key).or_default().extend(pdm_channels);
+                }
+            }
+        }
+        routed
+    }
+
+    /// Put light channels into strobe mode at a level, they are turned off
+    /// and only come on with a trigger. Returns why the first invalid
+    /// channel is invalid, leaving strobe mode as it was.
+    ///
+    /// * `channels`: channels of the light message.
+    /// * `level`: duty cycle in percent of the pulses.
+    pub(super) fn start_strobe(&mut self, channels: &[u8], level: u8) -> Result<(), LightRejection> {
+        self.route_channels(channels, level)?;
+        for channel in channels {
+            self.strobed.insert(*channel, level);
+        }
+        Ok(())
+    }
+
+    /// Take light channels out of strobe mode, returning whether any were
+    /// strobed.
+    ///
+    /// * `channels`: channels of the light message.
+    pub(super) fn stop_strobe(&mut self, channels: &[u8]) -> bool {
+        channels
+            .iter()
+            .fold(false, |stopped, channel| self.strobed.remove(channel).is_some() || stopped)
+    }
+}
+
+/// Pulse the strobed lights once for a trigger, at the phase offset from it
+/// or straight away when that has already passed. Triggers are ignored
+/// while no channel is strobed or the PDM configuration has drifted.
+///
+/// * `lighting`: component
+/// * `trigger`: trigger event of a camera.
+async fn pulse(lighting: Arc<Mutex<CropBedLighting>>, trigger: TriggerMessage) {
+    let (on, width) = {
+        let mut gaurd = lighting.lock().await;
+        let Some((phase_offset_ms, pulse_width_ms)) = gaurd
+            .strobe
+            .as_ref()
+            .map(|strobe| (strobe.phase_offset_ms, strobe.pulse_width_ms))
+        else {
+            return;
+        };
+        if gaurd.drifted || !gaurd.strobing() {
+            return;
+        }
+        let on_at = trigger.triggered_at + Duration::milliseconds(phase_offset_ms);
+        let width = std::time::Duration::from_millis(pulse_width_ms);
+        gaurd.strobe_pulses += 1;
+        ((on_at - Utc::now()).to_std().unwrap_or_default(), width)
+    };
+    tokio::time::sleep(on).await;
+    let routed = {
+        let gaurd = lighting.lock().await;
+        let routed = gaurd.pulse_routes();
+        for ((pdm_id, level), pdm_channels) in &routed {
+            if let Some(pdm) = gaurd.pdms.get(pdm_id) {
+                pdm.actuate_channels(pdm_channels.clone(), f32::from(*level)).await;
+            }
+        }
+        routed
+    };
+    tokio::time::sleep(width).await;
+    let gaurd = lighting.lock().await;
+    for ((pdm_id, _), pdm_channels) in routed {
+        if let Some(pdm) = gaurd.pdms.get(&pdm_id) {
+            pdm.actuate_channels(pdm_channels, 0.0).await;
+        }
+    }
+}
+
+/// Pulse the lights for every trigger event of the crop bed until the
+/// component is stopped, each pulse runs on its own so a long pulse does not
+/// hold up the next trigger.
+///
+/// * `socket`: bound socket, the trigger port of the strobe config.
+/// * `lighting`: component
+/// * `stop_rx`: stop signal from the component handle.
+pub(super) async fn serve_triggers(
+    socket: UdpSocket,
+    lighting: Arc<Mutex<CropBedLighting>>,
+    mut stop_rx: watch::Receiver<bool>,
+) {
+    let crop_bed_id = lighting.lock().await.crop_bed_id;
+    let mut data = [0; 512];
+    while !*stop_rx.borrow() {
+        let length = tokio::select! {
+            received = socket.recv(&mut data) => match received {
+                Ok(length) => length,
+                Err(e) => {
+                    warn!("Failed to receive a camera trigger: {e}");
+                    continue;
+                }
+            },
+            _ = stop_rx.changed() => continue,
+        };
+        match serde_json::from_slice::<TriggerMessage>(&data[..length]) {
+            Ok(trigger) if trigger.crop_bed_id == crop_bed_id => {
+                tokio::spawn(pulse(lighting.clone(), trigger));
+            }
+            Ok(trigger) => debug!("Trigger ignored, camera {} is on crop bed {}", trigger.cam_id, trigger.crop_bed_id),
+            Err(e) => warn!("Received a malformed trigger {e:?}, data: {:?}", &data[..length]),
+        }
+    }
+}
+
+/// Pulse the lights for every camera trigger of the crop bed published on
+/// the bus, as for those received by [`serve_triggers`], until the
+/// component is stopped or the bus has gone.
+///
+/// * `triggers`: subscriber to the camera triggers of the bus.
+/// * `lighting`: component
+/// * `stop_rx`: stop signal from the component handle.
+pub(super) async fn follow_bus_triggers(
+    mut triggers: BusSubscriber,
+    lighting: Arc<Mutex<CropBedLighting>>,
+    mut stop_rx: watch::Receiver<bool>,
+) {
+    let crop_bed_id = lighting.lock().await.crop_bed_id;
+    while !*stop_rx.borrow() {
+        let event = tokio::select! {
+            event = triggers.recv() => event,
+            _ = stop_rx.changed() => continue,
+        };
+        match event {
+            Some(BusEvent::CameraTrigger(trigger)) if trigger.crop_bed_id == crop_bed_id => {
+                tokio::spawn(pulse(lighting.clone(), trigger));
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::crop_bed::actuating::lighting::CropBedLightingConfig;
+
+    #[test]
+    /// Channels strobed from the config and by messages are pulsed at their
+    /// level, unmapped channels are refused and stopping leaves the rest.
+    fn test_strobe_channels() {
+        let config = CropBedLightingConfig::new(0, String::from("can3"), 17653)
+            .map_light_channel(1, 0, 7)
+            .map_light_channel(2, 0, 8)
+            .map_light_channel(3, 1, 2)
+            .with_strobe(StrobeConfig::new(17680).add_channel(1));
+        let mut lighting = CropBedLighting::new(config).unwrap();
+        assert!(lighting.strobing());
+        assert_eq!(lighting.pulse_routes(), BTreeMap::from([((0, 100), vec![7])]));
+
+        assert_eq!(lighting.start_strobe(&[2, 4], 60), Err(LightRejection::UnmappedChannel(4)));
+        lighting.start_strobe(&[2, 3], 60).unwrap();
+        assert_eq!(
+            lighting.pulse_routes(),
+            BTreeMap::from([((0, 100), vec![7]), ((0, 60), vec![8]), ((1, 60), vec![2])])
+        );
+
+        assert!(lighting.stop_strobe(&[1, 3]));
+        assert!(!lighting.stop_strobe(&[1]));
+        assert_eq!(lighting.pulse_routes(), BTreeMap::from([((0, 60), vec![8])]));
+        lighting.stop_strobe(&[2]);
+        assert!(!lighting.strobing());
+    }
+
+    #[test]
+    /// Only the trigger port is needed, the pulse falls back to its default.
+    fn test_strobe_config_defaults() {
+        let config: StrobeConfig = serde_yaml::from_str("trigger_port: 17680\n").unwrap();
+        assert_eq!(config, StrobeConfig::new(17680));
+        assert_eq!(config.pulse_width_ms, DEFAULT_STROBE_PULSE_WIDTH_MS);
+        let config = config.with_pulse(4, -2).add_channel(5);
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert_eq!(serde_yaml::from_str::<StrobeConfig>(&yaml).unwrap(), config);
+    }
+}
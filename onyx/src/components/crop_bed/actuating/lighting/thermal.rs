@@ -0,0 +1,415 @@
+use super::CropBedLighting;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// Time in milliseconds a light may be driven above its safe level before
+/// it is protected, when not set in the config.
+pub const DEFAULT_MAX_ON_MS: u64 = 600_000;
+
+/// Time in milliseconds a protected light is held at its safe level, when
+/// not set in the config.
+pub const DEFAULT_COOL_DOWN_MS: u64 = 120_000;
+
+/// Time between checks of how long the lights have been on.
+const THERMAL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Protect the LED bars from overheating when held on for too long, for
+/// every channel with overrides for those that differ. Every field falls
+/// back to its default when missing.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone)]
+#[serde(default)]
+pub struct ThermalProtectionConfig {
+    /// Time in milliseconds a light may be driven above its safe level
+    /// without a break.
+    pub max_on_ms: u64,
+    /// Time in milliseconds a protected light is held at its safe level.
+    pub cool_down_ms: u64,
+    /// Level in percent a protected light is derated to, turned off when
+    /// not set. Lights at or below it are never protected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub derate_level: Option<u8>,
+    /// Policy of the channels that differ, keyed by channel of the light
+    /// messages.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub channels: BTreeMap<u8, ChannelThermalPolicy>,
+}
+
+impl Default for ThermalProtectionConfig {
+    fn default() -> Self {
+        Self {
+            max_on_ms: DEFAULT_MAX_ON_MS,
+            cool_down_ms: DEFAULT_COOL_DOWN_MS,
+            derate_level: None,
+            channels: BTreeMap::new(),
+        }
+    }
+}
+
+/// Policy of one channel, each field falls back to that of every channel
+/// when not set.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct ChannelThermalPolicy {
+    /// Time in milliseconds the light may be driven above its safe level.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_on_ms: Option<u64>,
+    /// Time in milliseconds the light is held at its safe level.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cool_down_ms: Option<u64>,
+    /// Level in percent the light is derated to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derate_level: Option<u8>,
+}
+
+impl ThermalProtectionConfig {
+    /// Set how long every light may be on and then has to cool down for.
+    ///
+    /// * `max_on_ms`: time on above the safe level.
+    /// * `cool_down_ms`: time held at the safe level.
+    pub fn with_limits(mut self, max_on_ms: u64, cool_down_ms: u64) -> Self {
+        self.max_on_ms = max_on_ms;
+        self.cool_down_ms = cool_down_ms;
+        self
+    }
+
+    /// Derate protected lights rather than turning them off.
+    ///
+    /// * `derate_level`: duty cycle in percent.
+    pub fn with_derate(mut self, derate_level: u8) -> Self {
+        self.derate_level = Some(derate_level);
+        self
+    }
+
+    /// Set the policy of a channel that differs.
+    ///
+    /// * `channel`: channel in the light messages.
+    /// * `policy`: fields that differ from every channel.
+    pub fn with_channel(mut self, channel: u8, policy: ChannelThermalPolicy) -> Self {
+        self.channels.insert(channel, policy);
+        self
+    }
+
+    /// Time on, cool down and safe level of a channel.
+    ///
+    /// * `channel`: channel in the light messages.
+    fn of(&self, channel: u8) -> (Duration, Duration, u8) {
+        let policy = self.channels.get(&channel).copied().unwrap_or_default();
+        (
+            Duration::from_millis(policy.max_on_ms.unwrap_or(self.max_on_ms)),
+            Duration::from_millis(policy.cool_down_ms.unwrap_or(self.cool_down_ms)),
+            policy.derate_level.or(self.derate_level).unwrap_or(0),
+        )
+    }
+}
+
+/// Change the protection made to a light, to be sent to its PDM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThermalEvent {
+    /// The light was on for too long and is held at its safe level.
+    Protected {
+        /// Channel in the light messages.
+        channel: u8,
+        /// Level in percent the light is now driven at.
+        level: u8,
+        /// Time the light was on for.
+        on_for: Duration,
+        /// Time the light is held for.
+        cool_down: Duration,
+    },
+    /// The light has cooled down and is back at the level asked for.
+    Restored {
+        /// Channel in the light messages.
+        channel: u8,
+        /// Level in percent the light is now driven at.
+        level: u8,
+    },
+}
+
+impl std::fmt::Display for ThermalEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Protected {
+                channel,
+                level,
+                on_for,
+                cool_down,
+            } => write!(
+                f,
+                "Warning, light channel {channel} was on for {}s, held at {level}% for {}s to cool down",
+                on_for.as_secs(),
+                cool_down.as_secs()
+            ),
+            Self::Restored { channel, level } => {
+                write!(f, "Light channel {channel} has cooled down, back at {level}%")
+            }
+        }
+    }
+}
+
+/// How long a light has been on and whether it is cooling down.
+#[derive(Debug, Clone, Copy, Default)]
+struct ChannelThermal {
+    /// Level in percent last asked for.
+    requested: u8,
+    /// When the light was last driven above its safe level, while it is.
+    hot_since: Option<Instant>,
+    /// When a protected light may be driven above its safe level again.
+    cooling_until: Option<Instant>,
+    /// Time driven above the safe level before `hot_since`.
+    on_time: Duration,
+}
+
+/// Time each light has been on, protecting those on for too long. Every
+/// call takes the time so the policy can be followed on a simulated clock.
+#[derive(Debug, Clone)]
+pub struct ThermalGuard {
+    /// Policy of every channel.
+    config: ThermalProtectionConfig,
+    /// State of each channel asked for since the guard was created.
+    channels: BTreeMap<u8, ChannelThermal>,
+}
+
+impl ThermalGuard {
+    /// Guard following a policy.
+    ///
+    /// * `config`: policy of every channel.
+    pub fn new(config: ThermalProtectionConfig) -> Self {
+        Self {
+            config,
+            channels: BTreeMap::new(),
+        }
+    }
+
+    /// Note the level a light was asked for.
+    ///
+    /// * `channel`: channel in the light messages.
+    /// * `level`: duty cycle in percent asked for.
+    /// * `now`: time of the request.
+    pub fn request(&mut self, channel: u8, level: u8, now: Instant) {
+        let (_, _, safe_level) = self.config.of(channel);
+        let state = self.channels.entry(channel).or_default();
+        state.requested = level;
+        let hot = level > safe_level && state.cooling_until.is_none();
+        match (hot, state.hot_since) {
+            (true, None) => state.hot_since = Some(now),
+            (false, Some(hot_since)) => {
+                state.on_time += now.saturating_duration_since(hot_since);
+                state.hot_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Highest level a light may be driven at, `None` unless it is cooling
+    /// down.
+    ///
+    /// * `channel`: channel in the light messages.
+    pub fn limit(&self, channel: u8) -> Option<u8> {
+        let state = self.channels.get(&channel)?;
+        state.cooling_until.map(|_| self.config.of(channel).2)
+    }
+
+    /// Channels cooling down.
+    pub fn cooling(&self) -> impl Iterator<Item = u8> + '_ {
+        self.channels
+            .iter()
+            .filter(|(_, state)| state.cooling_until.is_some())
+            .map(|(channel, _)| *channel)
+    }
+
+    /// Total time a light has been driven above its safe level.
+    ///
+    /// * `channel`: channel in the light messages.
+    /// * `now`: time to count up to.
+    pub fn on_time(&self, channel: u8, now: Instant) -> Duration {
+        self.channels.get(&channel).map_or(Duration::ZERO, |state| {
+            state.on_time + state.hot_since.map_or(Duration::ZERO, |since| now.saturating_duration_since(since))
+        })
+    }
+
+    /// Protect the lights on for too long and restore those that have
+    /// cooled down, returning the changes to send.
+    ///
+    /// * `now`: time of the check.
+    pub fn check(&mut self, now: Instant) -> Vec<ThermalEvent> {
+        let mut events = Vec::new();
+        for (channel, state) in &mut self.channels {
+            let (max_on, cool_down, safe_level) = self.config.of(*channel);
+            if let Some(cooling_until) = state.cooling_until {
+                if now >= cooling_until {
+                    state.cooling_until = None;
+                    if state.requested > safe_level {
+                        state.hot_since = Some(now);
+                        events.push(ThermalEvent::Restored {
+                            channel: *channel,
+                            level: state.requested,
+                        });
+                    }
+                }
+            } else if let Some(hot_since) = state.hot_since {
+                let on_for = now.saturating_duration_since(hot_since);
+                if on_for >= max_on {
+                    state.on_time += on_for;
+                    state.hot_since = None;
+                    state.cooling_until = Some(now + cool_down);
+                    events.push(ThermalEvent::Protected {
+                        channel: *channel,
+                        level: safe_level,
+                        on_for,
+                        cool_down,
+                    });
+                }
+            }
+        }
+        events
+    }
+}
+
+impl CropBedLighting {
+    /// Check the thermal guard and drive the lights it has protected or
+    /// restored, logging a warning for each one protected.
+    ///
+    /// * `now`: time of the check.
+    pub(super) async fn check_thermal(&mut self, now: Instant) {
+        let Some(thermal) = self.thermal.as_mut() else {
+            return;
+        };
+        for event in thermal.check(now) {
+            warn!("{event}");
+            let (ThermalEvent::Protected { channel, level, .. } | ThermalEvent::Restored { channel, level }) = event;
+            if self.strobed.contains_key(&channel) {
+                continue;
+            }
+            self.levels.insert(channel, self.capped_level(channel, level));
+            if let Ok(routed) = self.route_channels(&[channel], level) {
+                for ((pdm_id, level), pdm_channels) in routed {
+                    if let Some(pdm) = self.pdms.get(&pdm_id) {
+                        pdm.actuate_channels(pdm_channels, f32::from(level)).await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Check how long the lights have been on every second until aborted.
+///
+/// * `lighting`: component
+pub(super) async fn watch_thermal(lighting: Arc<Mutex<CropBedLighting>>) {
+    let mut ticker = tokio::time::interval(THERMAL_CHECK_INTERVAL);
+    loop {
+        ticker.tick().await;
+        lighting.lock().await.check_thermal(Instant::now()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::crop_bed::actuating::lighting::CropBedLightingConfig;
+
+    /// Time a number of seconds after a start.
+    ///
+    /// * `start`: start of the simulated clock.
+    /// * `s`: seconds after it.
+    fn at(start: Instant, s: u64) -> Instant {
+        start + Duration::from_secs(s)
+    }
+
+    #[test]
+    /// A light held on is turned off once on for too long, kept off while
+    /// it cools down and turned back on after.
+    fn test_thermal_forced_off() {
+        let start = Instant::now();
+        let mut guard = ThermalGuard::new(ThermalProtectionConfig::default().with_limits(600_000, 120_000));
+        guard.request(3, 100, start);
+        assert!(guard.check(at(start, 599)).is_empty());
+        assert_eq!(guard.limit(3), None);
+
+        assert_eq!(
+            guard.check(at(start, 600)),
+            vec![ThermalEvent::Protected {
+                channel: 3,
+                level: 0,
+                on_for: Duration::from_secs(600),
+                cool_down: Duration::from_secs(120),
+            }]
+        );
+        assert_eq!(guard.limit(3), Some(0));
+        // Asking again while it cools down does not turn it back on.
+        guard.request(3, 100, at(start, 650));
+        assert_eq!(guard.limit(3), Some(0));
+        assert!(guard.check(at(start, 719)).is_empty());
+        assert_eq!(guard.cooling().collect::<Vec<_>>(), vec![3]);
+
+        assert_eq!(
+            guard.check(at(start, 720)),
+            vec![ThermalEvent::Restored { channel: 3, level: 100 }]
+        );
+        assert_eq!(guard.limit(3), None);
+        assert_eq!(guard.on_time(3, at(start, 730)), Duration::from_secs(610));
+    }
+
+    #[test]
+    /// Lights are derated rather than turned off when set, lights at the
+    /// derate level are never protected and a break resets the time on.
+    fn test_thermal_derated() {
+        let start = Instant::now();
+        let config = ThermalProtectionConfig::default().with_limits(60_000, 30_000).with_derate(40);
+        let mut guard = ThermalGuard::new(config);
+        guard.request(1, 30, start);
+        guard.request(2, 100, start);
+        guard.request(2, 0, at(start, 50));
+        guard.request(2, 100, at(start, 55));
+        assert!(guard.check(at(start, 100)).is_empty(), "The break did not reset the time on");
+
+        let events = guard.check(at(start, 115));
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], ThermalEvent::Protected { channel: 2, level: 40, .. }));
+        assert_eq!(guard.limit(2), Some(40));
+        assert_eq!(guard.limit(1), None);
+
+        // Turned down while cooling so nothing is restored.
+        guard.request(2, 20, at(start, 120));
+        assert!(guard.check(at(start, 145)).is_empty());
+        assert_eq!(guard.limit(2), None);
+    }
+
+    #[test]
+    /// Channels fall back to the policy of every channel field by field.
+    fn test_channel_thermal_policy() {
+        let config: ThermalProtectionConfig =
+            serde_yaml::from_str("derate_level: 30\nchannels:\n  4:\n    max_on_ms: 1000\n").unwrap();
+        assert_eq!(
+            config.of(1),
+            (Duration::from_millis(DEFAULT_MAX_ON_MS), Duration::from_millis(DEFAULT_COOL_DOWN_MS), 30)
+        );
+        assert_eq!(config.of(4), (Duration::from_secs(1), Duration::from_millis(DEFAULT_COOL_DOWN_MS), 30));
+    }
+
+    #[tokio::test]
+    /// A stuck on light message is capped once the channel is protected,
+    /// and the level asked for comes back after the cool down.
+    async fn test_thermal_protects_lighting() {
+        let config = CropBedLightingConfig::new(0, String::from("can3"), 17653)
+            .map_light_channel(1, 0, 7)
+            .with_thermal_protection(ThermalProtectionConfig::default().with_limits(1000, 1000).with_derate(25));
+        let mut lighting = CropBedLighting::new(config).unwrap();
+        let start = Instant::now();
+        lighting.record_levels(&[1], 90);
+
+        lighting.check_thermal(start + Duration::from_secs(2)).await;
+        assert_eq!(lighting.status().channels.get(&1), Some(&25));
+        assert_eq!(lighting.capped_level(1, 100), 25);
+        assert!(lighting.status().cooling_down.contains(&1));
+
+        lighting.check_thermal(start + Duration::from_secs(4)).await;
+        assert_eq!(lighting.status().channels.get(&1), Some(&90));
+        assert!(lighting.status().cooling_down.is_empty());
+    }
+}
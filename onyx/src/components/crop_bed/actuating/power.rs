@@ -1,61 +1,522 @@
-use crate::devices::hardware::pdm::{Pdm, PdmConfig};
-use crate::messages::control::weed::WeedMessage;
+use crate::components::traits::{ComponentConfig, ComponentController, ComponentError, ComponentHandle};
+use crate::devices::hardware::pdm::{
+    check_unique_addresses, frames::CHANNEL_COUNT, open_interface, validate_pdm_config_files, ChannelUsage, Pdm,
+    PdmConfig, PdmStatus, PdmVerification,
+};
+use crate::devices::hardware::wheel_speed::{GroundSpeed, WheelSpeedConfig, WheelSpeedSensor};
+use crate::messages::control::{
+    heartbeat::{ComponentKind, Heartbeat},
+    manual::ManualSprayMessage,
+    pdm::PdmControlMessage,
+    weed::{
+        MessageConstraints, ValidationCounts, WeedMessage, WeedMessageResponse, WeedMessageStatus, FULL_INTENSITY,
+    },
+};
+use crate::messages::{
+    encoding::Encoding,
+    envelope::Incoming,
+    logging::{EventCode, LogLevel},
+    telemetry::{PdmStateTelemetry, PdmTelemetry, SprayTelemetry, TelemetryHeader},
+};
+use crate::utils::{
+    bus::{BusEvent, MessageBus},
+    client::ComponentClient,
+    config::{load_yaml, ConfigError, ConfigFile, Validate, ValidationReport},
+    health::Health,
+    heartbeat::{emit_heartbeats, HeartbeatEmitterConfig, HeartbeatPeers},
+    location::{CropBed, GeoPosition},
+    logging::{LogConfig, LogEmitter},
+    metrics,
+    net::{FrameRead, FramedCodec, Framing},
+    serde::{ordered_map, ordered_optional_map},
+    tasks::{first_finished, NamedTask},
+    telemetry::{TelemetryEmitter, TelemetryEmitterConfig},
+    time::{MonotonicClock, DEFAULT_CLOCK_STEP_THRESHOLD_MS},
+    watchdog::Progress,
+};
 use chrono::{DateTime, Duration, Utc};
 use priority_queue::DoublePriorityQueue;
 use serde::{Deserialize, Serialize};
-use socketcan::tokio::CanSocket as AsyncCanSocket;
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
     ffi::OsStr,
+    fmt::Display,
+    io,
     path::{Path, PathBuf},
     sync::Arc,
 };
 use tokio::{
-    io::{AsyncBufReadExt, BufReader},
-    net::{TcpListener, TcpStream},
-    sync::Mutex,
+    io::BufReader,
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::{watch, Mutex, Notify},
+    task::{JoinError, JoinHandle},
     time::Instant,
 };
+use tracing::Instrument;
 use uuid::Uuid;
 
-/// Spray bound is a time constant in microseconds that determines
-/// if a spray message is close enough to the current UTC time to
-/// be sent to the PDM to be sprayed. There is some fluctuation
-/// here because the tokio based thread sleep may introduce some
-/// drift, although this has not been seen in tests.
-// TODO: move this to yaml config.
-const SPRAY_BOUND: i64 = 5;
+/// Merging of overlapping spray intervals for each channel.
+pub mod schedule;
+
+/// Journal of the lines received on the weed message socket, for replay.
+pub mod journal;
+
+/// Wiring of the crop bed channels to the PDMs.
+pub mod layout;
+
+/// Reloading the config of a running component.
+pub mod reload;
+
+/// Latency from a weed message being received to its sprays firing.
+pub mod latency;
+
+/// Recognising weed messages the AI system sent again.
+pub mod dedup;
+
+/// Weed messages received as UDP datagrams.
+pub mod udp;
+
+/// Firing ahead of the spray times by the delay of the solenoids.
+pub mod solenoid;
+
+/// Test pattern chasing every channel in turn, for commissioning.
+pub mod chase;
+
+/// Draining the queue or stopping at once when the component shuts down.
+pub mod shutdown;
+
+/// HTTP status server for the component.
+#[cfg(feature = "http")]
+pub mod http;
+
+use dedup::{DedupConfig, MessageKey, RecentMessages};
+use journal::{Journal, JournalEntry, DEFAULT_JOURNAL_MAX_BYTES};
+use latency::{LatencyRecord, LatencySummary, LatencyWindow};
+use layout::ChannelLayout;
+use schedule::SpraySchedule;
+use shutdown::{ShutdownConfig, ShutdownMode};
+use solenoid::SolenoidLatencyConfig;
+use udp::{Transport, DEFAULT_MAX_DATAGRAM_BYTES};
+
+/// Timing of the spray messages sent to the PDMs. Every field falls
+/// back to its default when missing, so configs written before the
+/// timing was configurable still load.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct PowerTiming {
+    /// Spray bound in microseconds determines if a spray message is
+    /// close enough to the current UTC time to be sent to the PDM to
+    /// be sprayed. There is some fluctuation here because the tokio
+    /// based thread sleep may introduce some drift, although this has
+    /// not been seen in tests.
+    pub spray_bound_us: u64,
+    /// Interval in milliseconds that sprays longer than the PDM
+    /// keepalive are re-fired at.
+    pub refire_interval_ms: u64,
+    /// Time in milliseconds the PDM keeps a channel on without hearing
+    /// from the controller before its loss of can feature turns it off.
+    pub pdm_keepalive_ms: u64,
+    /// Time in milliseconds without a spray after which a heartbeat is
+    /// sent, well inside the PDM keepalive.
+    pub heartbeat_interval_ms: u64,
+    /// Time in milliseconds a connection from the analysis system is kept
+    /// open without receiving a message.
+    pub connection_idle_timeout_ms: u64,
+    /// Time in milliseconds a spray can be behind before it is discarded.
+    /// Queued messages this late still fire, and weed messages starting
+    /// this late are accepted starting now, as over spraying is preferred
+    /// to under spraying.
+    pub late_grace_ms: u64,
+    /// Time in milliseconds an off is held back for when its channel
+    /// sprays again within it, so chattering detections keep the solenoid
+    /// open rather than flicking it shut. Not debounced when zero.
+    pub debounce_ms: u64,
+}
+
+impl Default for PowerTiming {
+    fn default() -> Self {
+        Self {
+            spray_bound_us: 5,
+            refire_interval_ms: 100,
+            pdm_keepalive_ms: 1000,
+            heartbeat_interval_ms: 500,
+            connection_idle_timeout_ms: 30_000,
+            late_grace_ms: 20,
+            debounce_ms: 0,
+        }
+    }
+}
+
+impl PowerTiming {
+    /// Whether every field is the default, in which case the timing is
+    /// left out of written configs.
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Spray bound before a message is fired.
+    pub fn spray_bound(&self) -> Duration {
+        Duration::microseconds(i64::try_from(self.spray_bound_us).unwrap_or(i64::MAX))
+    }
+
+    /// Interval that long sprays are re-fired at.
+    pub fn refire_interval(&self) -> Duration {
+        Duration::milliseconds(i64::try_from(self.refire_interval_ms).unwrap_or(i64::MAX))
+    }
+
+    /// Time the PDM keeps a channel on without hearing from the controller.
+    pub fn pdm_keepalive(&self) -> Duration {
+        Duration::milliseconds(i64::try_from(self.pdm_keepalive_ms).unwrap_or(i64::MAX))
+    }
+
+    /// Time without a spray after which a heartbeat is sent.
+    pub fn heartbeat_interval(&self) -> tokio::time::Duration {
+        tokio::time::Duration::from_millis(self.heartbeat_interval_ms)
+    }
+
+    /// Time a spray can be behind before it is discarded.
+    pub fn late_grace(&self) -> Duration {
+        Duration::milliseconds(i64::try_from(self.late_grace_ms).unwrap_or(i64::MAX))
+    }
+
+    /// Window after an off in which a channel spraying again is kept on.
+    pub fn debounce(&self) -> Duration {
+        Duration::milliseconds(i64::try_from(self.debounce_ms).unwrap_or(i64::MAX))
+    }
+
+    /// Time a quiet connection from the analysis system is kept open.
+    pub fn connection_idle_timeout(&self) -> tokio::time::Duration {
+        tokio::time::Duration::from_millis(self.connection_idle_timeout_ms)
+    }
+}
+
+/// Time after a channel is turned on before its current feedback is
+/// checked, allowing the solenoid to pull in.
+const FEEDBACK_SETTLE: tokio::time::Duration = tokio::time::Duration::from_millis(100);
+
+/// Current below which an actuated channel is treated as not drawing any,
+/// e.g. a broken solenoid coil or a disconnected harness.
+const ZERO_CURRENT_AMPS: f32 = 0.05;
+
+/// Tokio timers run on a millisecond wheel and can fire a millisecond or
+/// more late, so the firing task wakes this long before a message is due
+/// and yields through the rest rather than sleeping past the spray bound.
+const TIMER_SLACK: tokio::time::Duration = tokio::time::Duration::from_millis(2);
+
+/// Ground speeds below this are not followed, the machine is treated as
+/// stopped or the sensor as stalled and queued times are kept.
+const MIN_TRACKED_SPEED_MPS: f64 = 0.1;
+
+/// Messages kept in the queue when no limit is set in the config, far more
+/// than the sprays a crop bed can have ahead of it.
+const DEFAULT_MAX_QUEUE_LEN: usize = 4096;
+
+/// Time between the component status being logged.
+const STATUS_LOG_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(300);
+
+/// Longest spray of a weed message when no limit is set in the config, far
+/// longer than a single weed takes to pass a nozzle.
+const DEFAULT_MAX_SPRAY_DURATION_MS: u64 = 10_000;
+
+/// Furthest ahead a weed message may start spraying when no limit is set
+/// in the config, well past the view of the cameras at crawling speed.
+const DEFAULT_MAX_MESSAGE_LEAD_MS: u64 = 60_000;
+
+/// Longest a manual spray may keep its channels open, so a mistyped
+/// duration cannot leave a channel spraying.
+const MAX_MANUAL_SPRAY_MS: u64 = 10_000;
+
+/// Time between a manual spray being received and it starting, so it is
+/// queued ahead of when it is due.
+const MANUAL_SPRAY_LEAD_MS: i64 = 5;
+
+/// Status of a crop bed power component, logged periodically.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CropBedPowerStatus {
+    /// Canbus interface of the component.
+    pub canbus_id: String,
+    /// Usage of each channel keyed by the bed position of the PDM, then the
+    /// channel on the PDM.
+    pub channel_stats: BTreeMap<u8, BTreeMap<u8, ChannelUsage>>,
+    /// Messages waiting in the queue.
+    pub queue_depth: usize,
+    /// Messages dropped because the queue was full.
+    pub queue_dropped: u64,
+    /// Tasks that panicked as the component shut down, zero while it is
+    /// running.
+    pub tasks_failed: usize,
+}
+
+impl Display for CropBedPowerStatus {
+    #[allow(clippy::cast_precision_loss)]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Crop bed power on {}: {} queued, {} dropped",
+            self.canbus_id, self.queue_depth, self.queue_dropped
+        )?;
+        if self.tasks_failed > 0 {
+            write!(f, ", {} tasks failed", self.tasks_failed)?;
+        }
+        for (bed_position, channels) in &self.channel_stats {
+            for (channel, usage) in channels {
+                write!(
+                    f,
+                    "\n  PDM {} channel {}: {} actuations, {:.1}s on",
+                    bed_position,
+                    channel,
+                    usage.on_transitions,
+                    usage.on_time_ms as f64 / 1000.0
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What is done with weed messages asking for a spray longer than the
+/// maximum spray duration.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverlongSpray {
+    /// Spray for the maximum duration from the start of the message.
+    #[default]
+    Clamp,
+    /// Refuse the message and spray nothing.
+    Reject,
+}
+
+/// Channels the heartbeat keeping the PDMs from their loss of CAN timeout
+/// is sent to. The ix3212 has no command that only keeps it online, so the
+/// heartbeat is an output command at 0%.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HeartbeatStrategy {
+    /// Channels that were not commanded on, so a channel held on past the
+    /// heartbeat interval stays on. Nothing is sent to a PDM with every
+    /// channel on, the sprays re-firing them keep it online.
+    #[default]
+    IdleChannels,
+    /// Every channel, as the heartbeat used to be. A channel held on past
+    /// the heartbeat interval is turned off.
+    AllChannels,
+}
+
+/// Messages received on the weed message socket by how they were handled.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageCounts {
+    /// Weed messages and manual sprays queued.
+    pub accepted: u64,
+    /// Accepted weed messages that arrived within the late grace of their
+    /// spray and had their start moved to when they arrived.
+    pub clamped: u64,
+    /// Weed messages that arrived after their spray was due, past the late
+    /// grace, and were discarded.
+    pub late: u64,
+    /// Data that could not be parsed.
+    pub malformed: u64,
+    /// Manual sprays refused by the interlock and weed messages failing
+    /// validation.
+    pub rejected: u64,
+    /// Weed messages queued with their spray cut to the maximum duration.
+    pub truncated: u64,
+    /// Weed messages dropped as a retry of one already received.
+    pub duplicate: u64,
+    /// Weed messages failing validation by reason, counted as rejected too.
+    #[serde(default)]
+    pub invalid: ValidationCounts,
+}
+
+impl MessageCounts {
+    /// Count a message.
+    ///
+    /// * `status`: how the message was handled.
+    fn record(&mut self, status: WeedMessageStatus) {
+        metrics::weed_message(status.into());
+        match status {
+            WeedMessageStatus::Accepted => self.accepted += 1,
+            WeedMessageStatus::Late => self.late += 1,
+            WeedMessageStatus::Malformed => self.malformed += 1,
+            WeedMessageStatus::Rejected => self.rejected += 1,
+            WeedMessageStatus::Truncated => self.truncated += 1,
+            WeedMessageStatus::Duplicate => self.duplicate += 1,
+        }
+    }
+}
+
+/// State of one PDM of a component.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PdmSnapshot {
+    /// Source address of the PDM.
+    pub address: u8,
+    /// Whether the PDM accepted its configuration.
+    pub initialised: bool,
+    /// Latest fault state reported by the PDM.
+    pub status: PdmStatus,
+}
+
+/// What a crop bed power component is doing, served on its status port.
+/// Taken under the component lock and serialised after it is released.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CropBedPowerSnapshot {
+    /// Canbus interface of the component.
+    pub canbus_id: String,
+    /// Messages waiting in the queue.
+    pub queue_depth: usize,
+    /// Messages dropped because the queue was full.
+    pub queue_dropped: u64,
+    /// When the next queued message is due.
+    pub next_fire_time: Option<DateTime<Utc>>,
+    /// When a message was last sent to a PDM, heartbeats aside.
+    pub last_fire_time: Option<DateTime<Utc>>,
+    /// State of each PDM keyed by bed position.
+    pub pdms: BTreeMap<u8, PdmSnapshot>,
+    /// Messages received on the weed message socket.
+    pub messages: MessageCounts,
+    /// Queued messages fired behind their time, within the late grace.
+    pub late_fired: u64,
+    /// Queued messages discarded for being further behind than the late
+    /// grace.
+    pub late_discarded: u64,
+    /// Offs held back because their channel sprays again within the
+    /// debounce window, each an off and an on the solenoid did not make.
+    pub debounced: u64,
+    /// Fire times the solenoid latency could not be fully compensated for,
+    /// as firing that far ahead would have been in the past.
+    pub compensation_clamped: u64,
+    /// Channel map from the config, as the solenoid channel to the PDM
+    /// channel and PDM.
+    pub channel_map: Option<BTreeMap<u8, (u8, u8)>>,
+    /// Latency of the messages last sent to the PDMs.
+    pub latency: LatencySummary,
+}
 
 /// Set the configuration for a crop bed power component.
 /// This is created by grouping multiple PDMs with different
 /// addresses on a canbus trunk line which are wired to
 /// actuated solenoids.
-#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone)]
 pub struct CropBedPowerConfig {
     /// ID of the crop be the component is attached to.
-    crop_bed_id: u8,
+    crop_bed_id: CropBed,
     /// The addressable canbus interface ID.
     canbus_id: String,
     /// The internal linux socket that the component listens to for incoming messages.
     port: i32,
     /// Map of the config files used to generate the PDMs, as per the technical specification.
+    #[serde(serialize_with = "ordered_map")]
     pdm_config_files: HashMap<u8, PathBuf>,
     /// Due to the way electrical wanted to wire the harnesses channel
     /// numbers do not always match with the expected solenoid actuator.
     /// This map translates these wiring IDs.
     // NOTE: Remember this when implementing logging and telemetry as it
     // will likely lead to confusion.
+    #[serde(serialize_with = "ordered_optional_map")]
     channel_map: Option<HashMap<u8, (u8, u8)>>,
+    /// When the PDM configuration is read back and verified, only once at
+    /// start up when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pdm_verification: Option<PdmVerification>,
+    /// Timing of the spray messages, defaults when not set.
+    #[serde(default, skip_serializing_if = "PowerTiming::is_default")]
+    timing: PowerTiming,
+    /// Wheel speed sensor the spray times follow, times are used as sent
+    /// when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wheel_speed: Option<WheelSpeedConfig>,
+    /// Messages kept in the queue before the sprays furthest in the future
+    /// are dropped, see [`DEFAULT_MAX_QUEUE_LEN`] when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_queue_len: Option<usize>,
+    /// Port the status server listens on, not served when not set or when
+    /// built without the `http` feature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status_port: Option<i32>,
+    /// File every line received on the weed message socket is appended to,
+    /// with when it arrived and what was done with it. Not journalled when
+    /// not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    journal_path: Option<PathBuf>,
+    /// Size in bytes the journal is rotated at, see
+    /// [`DEFAULT_JOURNAL_MAX_BYTES`] when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    journal_max_bytes: Option<u64>,
+    /// Which PDM each crop bed channel is wired to, see
+    /// [`ChannelLayout::default`] when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channel_layout: Option<ChannelLayout>,
+    /// Interlock for manual sprays from the operator, refused unless set.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    allow_manual_spray: bool,
+    /// Longest spray in milliseconds a weed message may ask for, see
+    /// [`DEFAULT_MAX_SPRAY_DURATION_MS`] when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_spray_duration_ms: Option<u64>,
+    /// What is done with longer sprays, clamped when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    overlong_spray: Option<OverlongSpray>,
+    /// Furthest ahead in milliseconds a weed message may start spraying, see
+    /// [`DEFAULT_MAX_MESSAGE_LEAD_MS`] when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_message_lead_ms: Option<u64>,
+    /// Print the latency of every message sent to the PDMs as a json
+    /// line, the percentiles are on the status port either way.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    log_latency: bool,
+    /// Transports the weed messages are received over on the port, TCP
+    /// when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transport: Option<Transport>,
+    /// Encoding of the messages and responses on TCP connections, json
+    /// when not set. Datagrams are always json.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding: Option<Encoding>,
+    /// Framing of the TCP connections, lines for json and length prefixed
+    /// for binary encodings when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    framing: Option<Framing>,
+    /// Size in bytes of the largest datagram handled, see
+    /// [`DEFAULT_MAX_DATAGRAM_BYTES`] when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_datagram_bytes: Option<usize>,
+    /// Channels the heartbeat is sent to, idle channels when not set. The
+    /// interval is in the timing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    heartbeat: Option<HeartbeatStrategy>,
+    /// Recent weed messages remembered to drop retries, see
+    /// [`DedupConfig::default`] when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dedup: Option<DedupConfig>,
+    /// Delay of the solenoids the sprays are fired ahead by, not
+    /// compensated when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    solenoid_latency: Option<SolenoidLatencyConfig>,
+    /// Whether the queue is drained or dropped on a shutdown, dropped when
+    /// not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shutdown: Option<ShutdownConfig>,
+    /// Where the heartbeats of the component are sent, none are sent when
+    /// not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    heartbeat_emitter: Option<HeartbeatEmitterConfig>,
+    /// Where the structured log of the component is written, events are
+    /// only printed when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    log: Option<LogConfig>,
+    /// Where the spray queue and PDM telemetry of the component is shipped,
+    /// none is shipped when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    telemetry: Option<TelemetryEmitterConfig>,
 }
 
 /// Convert received weed messages into a type that suits a
 /// priority queue. The original weed message sends information
 /// about starting and stopping the weed message, where as the
 /// queue saves messages for both on and off.
-#[derive(Hash, PartialEq, Eq, Debug)]
+#[derive(Hash, PartialEq, Eq, Debug, Clone)]
 pub struct WeedQueueMessage {
-    /// Channels to actuate.
-    pub channels: Vec<u8>,
+    /// Channels to actuate, as the bed position of the PDM each is wired to
+    /// and the crop bed channel.
+    pub channels: Vec<(u8, u8)>,
     /// UTC time when the action should take place.
     pub time_to_fire: DateTime<Utc>,
     /// Power is turned on, i.e. PWM 100.
@@ -64,381 +525,5091 @@ pub struct WeedQueueMessage {
     pub original_spray_starts: DateTime<Utc>,
     /// Spray ending is used in  loop to prune overlapping messages.
     pub original_spray_ending: DateTime<Utc>,
+    /// Duty cycle in percent for each channel when turning on, for variable
+    /// rate application. Channels not in the map, or every channel when not
+    /// set, are turned fully on.
+    pub pwm: Option<BTreeMap<u8, u8>>,
+    /// Ground speed the times were computed for, they are shifted when the
+    /// measured speed changes. Times are kept as they are when not set.
+    pub timed_for: Option<GroundSpeed>,
+    /// Fired by hand from the operator, marked in the PDM audit log.
+    pub manual: bool,
+    /// When the message it came from was received, for its latency.
+    pub received_at: DateTime<Utc>,
+    /// Where the machine was when the weed was captured, as sent by the AI
+    /// system.
+    pub position: Option<GeoPosition>,
+}
+
+impl WeedQueueMessage {
+    /// Whether two messages come from the same weed message, the on, any
+    /// re-fires and the off.
+    ///
+    /// * `other`: message to compare with.
+    fn same_spray(&self, other: &WeedQueueMessage) -> bool {
+        self.channels == other.channels
+            && self.original_spray_starts == other.original_spray_starts
+            && self.original_spray_ending == other.original_spray_ending
+    }
+
+    /// Crop bed channels of the message, without the PDM they are on.
+    fn crop_bed_channels(&self) -> Vec<u8> {
+        self.channels.iter().map(|(_, channel)| *channel).collect()
+    }
+
+    /// Channel numbers on a PDM with the duty cycle each should be set to.
+    ///
+    /// * `pdm_channels`: channels on the PDM.
+    /// * `offset`: added to a PDM channel to give the channel in the message.
+    fn duties(&self, pdm_channels: &[u8], offset: u8) -> Vec<(u8, f32)> {
+        pdm_channels
+            .iter()
+            .map(|channel| {
+                let duty = if self.is_on {
+                    self.pwm
+                        .as_ref()
+                        .and_then(|pwm| pwm.get(&(channel + offset)))
+                        .map_or(100.0, |duty| f32::from(*duty))
+                } else {
+                    0.0
+                };
+                (*channel, duty)
+            })
+            .collect()
+    }
+
+    /// Send the message to a PDM. Messages without per channel duty cycles
+    /// go out as a single call at 100 or 0.
+    ///
+    /// * `pdm`: PDM the channels are on.
+    /// * `pdm_channels`: channels on the PDM.
+    /// * `offset`: added to a PDM channel to give the channel in the message.
+    /// * `position`: where the machine was, recorded in the PDM audit log.
+    async fn actuate(&self, pdm: &Pdm, pdm_channels: Vec<u8>, offset: u8, position: Option<GeoPosition>) {
+        if self.manual {
+            pdm.actuate_channels_manual(self.duties(&pdm_channels, offset), position)
+                .await;
+        } else if self.pwm.is_some() && self.is_on {
+            pdm.actuate_channels_individual(self.duties(&pdm_channels, offset), position)
+                .await;
+        } else {
+            let pwm = if self.is_on { 100.0 } else { 0.0 };
+            pdm.actuate_channels_at(pdm_channels, pwm, position).await;
+        }
+    }
 }
 
 impl CropBedPowerConfig {
     /// Crop bed power configuration.
     ///
-    /// * `crop_bed_id`: module ids from [0 - 2]
+    /// * `crop_bed_id`: crop bed module, legacy ids from [0 - 2] convert.
     /// * `canbus_id`: String for the bus i.e., can0.
     pub fn new(
-        crop_bed_id: u8,
+        crop_bed_id: impl Into<CropBed>,
         canbus_id: String,
         port: i32,
         channel_map: Option<HashMap<u8, (u8, u8)>>,
     ) -> Self {
         Self {
             port,
-            crop_bed_id,
+            crop_bed_id: crop_bed_id.into(),
             canbus_id,
             pdm_config_files: HashMap::new(),
             channel_map,
+            pdm_verification: None,
+            timing: PowerTiming::default(),
+            wheel_speed: None,
+            max_queue_len: None,
+            status_port: None,
+            journal_path: None,
+            journal_max_bytes: None,
+            channel_layout: None,
+            allow_manual_spray: false,
+            max_spray_duration_ms: None,
+            overlong_spray: None,
+            max_message_lead_ms: None,
+            log_latency: false,
+            transport: None,
+            encoding: None,
+            framing: None,
+            max_datagram_bytes: None,
+            heartbeat: None,
+            dedup: None,
+            solenoid_latency: None,
+            shutdown: None,
+            heartbeat_emitter: None,
+            log: None,
+            telemetry: None,
+        }
+    }
+
+    /// Set which PDM each crop bed channel is wired to.
+    ///
+    /// * `channel_layout`: ranges of crop bed channels on each PDM.
+    pub fn with_channel_layout(mut self, channel_layout: ChannelLayout) -> Self {
+        self.channel_layout = Some(channel_layout);
+        self
+    }
+
+    /// Allow manual sprays from the operator, e.g. while commissioning.
+    ///
+    /// * `allow_manual_spray`: whether manual sprays are fired.
+    pub fn with_manual_spray(mut self, allow_manual_spray: bool) -> Self {
+        self.allow_manual_spray = allow_manual_spray;
+        self
+    }
+
+    /// Limit how long a weed message may spray for.
+    ///
+    /// * `max_spray_duration_ms`: longest spray in milliseconds.
+    /// * `overlong_spray`: what is done with longer sprays.
+    pub fn with_max_spray_duration(mut self, max_spray_duration_ms: u64, overlong_spray: OverlongSpray) -> Self {
+        self.max_spray_duration_ms = Some(max_spray_duration_ms);
+        self.overlong_spray = Some(overlong_spray);
+        self
+    }
+
+    /// Limit how far ahead a weed message may start spraying.
+    ///
+    /// * `max_message_lead_ms`: furthest ahead in milliseconds.
+    pub fn with_max_message_lead(mut self, max_message_lead_ms: u64) -> Self {
+        self.max_message_lead_ms = Some(max_message_lead_ms);
+        self
+    }
+
+    /// Print the latency of every message sent to the PDMs.
+    ///
+    /// * `log_latency`: whether each latency is printed.
+    pub fn with_latency_log(mut self, log_latency: bool) -> Self {
+        self.log_latency = log_latency;
+        self
+    }
+
+    /// Set the transports the weed messages are received over.
+    ///
+    /// * `transport`: TCP, UDP or both.
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Set the encoding of the TCP connections.
+    ///
+    /// * `encoding`: json, CBOR or MessagePack.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Set the framing of the TCP connections.
+    ///
+    /// * `framing`: lines, length prefixed or detected on each connection.
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = Some(framing);
+        self
+    }
+
+    /// Set the largest datagram handled, larger ones are rejected.
+    ///
+    /// * `max_datagram_bytes`: size in bytes.
+    pub fn with_max_datagram_bytes(mut self, max_datagram_bytes: usize) -> Self {
+        self.max_datagram_bytes = Some(max_datagram_bytes);
+        self
+    }
+
+    /// Set the channels the heartbeat is sent to.
+    ///
+    /// * `heartbeat`: idle or every channel.
+    pub fn with_heartbeat(mut self, heartbeat: HeartbeatStrategy) -> Self {
+        self.heartbeat = Some(heartbeat);
+        self
+    }
+
+    /// Set how many recent weed messages are remembered to drop retries.
+    ///
+    /// * `dedup`: capacity and time to live.
+    pub fn with_dedup(mut self, dedup: DedupConfig) -> Self {
+        self.dedup = Some(dedup);
+        self
+    }
+
+    /// Fire the sprays ahead of their times by the delay of the solenoids.
+    ///
+    /// * `solenoid_latency`: open and close latency, with overrides for
+    ///   some channels.
+    pub fn with_solenoid_latency(mut self, solenoid_latency: SolenoidLatencyConfig) -> Self {
+        self.solenoid_latency = Some(solenoid_latency);
+        self
+    }
+
+    /// Set whether the queue is drained or dropped on a shutdown.
+    ///
+    /// * `shutdown`: mode and drain horizon.
+    pub fn with_shutdown(mut self, shutdown: ShutdownConfig) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// Send the heartbeats of the component.
+    ///
+    /// * `heartbeat_emitter`: where and how often they go.
+    pub fn with_heartbeat_emitter(mut self, heartbeat_emitter: HeartbeatEmitterConfig) -> Self {
+        self.heartbeat_emitter = Some(heartbeat_emitter);
+        self
+    }
+
+    /// Write the structured log of the component.
+    ///
+    /// * `log`: file or collector the events go to.
+    pub fn with_log(mut self, log: LogConfig) -> Self {
+        self.log = Some(log);
+        self
+    }
+
+    /// Ship the spray queue and PDM telemetry of the component.
+    ///
+    /// * `telemetry`: where and how often it goes.
+    pub fn with_telemetry(mut self, telemetry: TelemetryEmitterConfig) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
+
+    /// Journal the lines received on the weed message socket.
+    ///
+    /// * `journal_path`: file the lines are appended to.
+    pub fn with_journal<P: Into<PathBuf>>(mut self, journal_path: P) -> Self {
+        self.journal_path = Some(journal_path.into());
+        self
+    }
+
+    /// Set the size the journal is rotated at.
+    ///
+    /// * `journal_max_bytes`: size in bytes.
+    pub fn with_journal_max_bytes(mut self, journal_max_bytes: u64) -> Self {
+        self.journal_max_bytes = Some(journal_max_bytes);
+        self
+    }
+
+    /// Serve the component status on a port.
+    ///
+    /// * `status_port`: port the status server listens on.
+    pub fn with_status_port(mut self, status_port: i32) -> Self {
+        self.status_port = Some(status_port);
+        self
+    }
+
+    /// Set the messages kept in the queue before the sprays furthest in the
+    /// future are dropped.
+    ///
+    /// * `max_queue_len`: largest number of queued messages.
+    pub fn with_max_queue_len(mut self, max_queue_len: usize) -> Self {
+        self.max_queue_len = Some(max_queue_len);
+        self
+    }
+
+    /// Set the wheel speed sensor the spray times follow.
+    ///
+    /// * `wheel_speed`: canbus or pulse counter the speed is read from.
+    pub fn with_wheel_speed(mut self, wheel_speed: WheelSpeedConfig) -> Self {
+        self.wheel_speed = Some(wheel_speed);
+        self
+    }
+
+    /// Set the timing of the spray messages.
+    ///
+    /// * `timing`: spray bound, re-fire, keepalive and heartbeat timing.
+    pub fn with_timing(mut self, timing: PowerTiming) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Set when the PDM configuration is read back and verified.
+    ///
+    /// * `pdm_verification`: check interval and drift policy.
+    pub fn with_pdm_verification(mut self, pdm_verification: PdmVerification) -> Self {
+        self.pdm_verification = Some(pdm_verification);
+        self
+    }
+
+    /// Add a PDM to the component with a config file.
+    ///
+    /// * `filepath`: path to config
+    /// * `pdm_id`: bed position of the PDM, the address is set in its config.
+    pub fn add_pdm_config_file<F>(mut self, filepath: F, pdm_id: u8) -> Self
+    where
+        F: AsRef<OsStr>,
+    {
+        self.pdm_config_files.insert(pdm_id, (&filepath).into());
+        self
+    }
+
+    /// Read the configuration from a file, for reloading a running
+    /// component where a bad file should not bring it down. The error
+    /// names the field that is wrong and where it is.
+    ///
+    /// * `filepath`: filepath to the stored parameters.
+    pub fn try_from_file<F: AsRef<OsStr>>(filepath: F) -> Result<Self, ConfigError> {
+        load_yaml(Path::new(&filepath))
+    }
+
+    /// Longest spray a weed message may ask for.
+    fn max_spray_duration(&self) -> Duration {
+        Duration::milliseconds(
+            i64::try_from(self.max_spray_duration_ms.unwrap_or(DEFAULT_MAX_SPRAY_DURATION_MS)).unwrap_or(i64::MAX),
+        )
+    }
+
+    /// Limits the weed messages are validated against, the channels wired
+    /// through the channel map or layout, the maximum spray duration when
+    /// longer sprays are refused and the furthest lead.
+    pub fn message_constraints(&self) -> MessageConstraints {
+        // Weed messages carry zero based channels.
+        let channels = match &self.channel_map {
+            Some(channel_map) => channel_map.keys().filter_map(|channel| channel.checked_sub(1)).collect(),
+            None => self
+                .channel_layout
+                .clone()
+                .unwrap_or_default()
+                .channels()
+                .into_iter()
+                .filter_map(|channel| channel.checked_sub(1))
+                .collect(),
+        };
+        MessageConstraints {
+            channels,
+            max_spray_duration: (self.overlong_spray.unwrap_or_default() == OverlongSpray::Reject)
+                .then(|| self.max_spray_duration()),
+            max_lead: Duration::milliseconds(
+                i64::try_from(self.max_message_lead_ms.unwrap_or(DEFAULT_MAX_MESSAGE_LEAD_MS)).unwrap_or(i64::MAX),
+            ),
+        }
+    }
+
+    /// Canbus interface the PDMs are on.
+    pub fn canbus_id(&self) -> &str {
+        &self.canbus_id
+    }
+
+    /// Read the config of each PDM from its file, keyed by bed position.
+    pub fn pdm_configs(&self) -> Result<HashMap<u8, PdmConfig>, ConfigError> {
+        self.pdm_config_files
+            .iter()
+            .map(|(bed_position, pdm_config_file)| Ok((*bed_position, PdmConfig::try_from_file(pdm_config_file)?)))
+            .collect()
+    }
+
+    /// Connect to the weed message socket of a component running the
+    /// config on this machine, over its transport and encoding as the AI
+    /// system does. UDP is used only when it is the sole transport.
+    pub async fn connect_client(&self) -> io::Result<ComponentClient> {
+        let address = format!("127.0.0.1:{}", self.port);
+        match self.transport.unwrap_or_default() {
+            Transport::Udp => ComponentClient::connect_udp(address).await,
+            _ => ComponentClient::connect_tcp(address, self.encoding.unwrap_or_default(), self.framing).await,
+        }
+    }
+}
+
+impl Validate for CropBedPowerConfig {
+    fn validate(&self, report: &mut ValidationReport) {
+        report.check_port("port", self.port);
+        if let Some(status_port) = self.status_port {
+            report.check_port("status_port", status_port);
+            if status_port == self.port {
+                report.push("status_port", "the weed messages are received on the same port");
+            }
+        }
+        validate_pdm_config_files(report, &self.pdm_config_files);
+        let channel_layout = self.channel_layout.clone().unwrap_or_default();
+        if let Err(e) = channel_layout.validate() {
+            report.push("channel_layout", e);
+        }
+        // The default layout is left to wire a second PDM a bench rig may
+        // not have, an explicit one is expected to match the PDMs.
+        if self.channel_layout.is_some() {
+            for pdm_id in channel_layout.pdm_ids() {
+                if !self.pdm_config_files.contains_key(&pdm_id) {
+                    report.push("channel_layout", format!("PDM {pdm_id} is not in pdm_config_files"));
+                }
+            }
+        }
+        let mut channels: Vec<(&u8, &(u8, u8))> = self.channel_map.iter().flatten().collect();
+        channels.sort_unstable();
+        for (channel, (converted, pdm_id)) in channels {
+            let field = format!("channel_map.{channel}");
+            if !self.pdm_config_files.contains_key(pdm_id) {
+                report.push(field, format!("PDM {pdm_id} is not in pdm_config_files"));
+            } else if !channel_layout.wires(*pdm_id, *converted) {
+                report.push(
+                    field,
+                    format!("crop bed channel {converted} is not wired to PDM {pdm_id} in the channel layout"),
+                );
+            }
+        }
+        if let Some(journal_path) = &self.journal_path {
+            report.check_parent_dir("journal_path", journal_path);
+        }
+        if self.max_queue_len == Some(0) {
+            report.push("max_queue_len", "every message would be dropped from a queue of 0");
+        }
+        if self.max_spray_duration_ms == Some(0) {
+            report.push("max_spray_duration_ms", "no spray is shorter than 0ms");
         }
+        if let Some(problem) = self.shutdown.as_ref().and_then(ShutdownConfig::check_drain_horizon) {
+            report.push("shutdown.drain_horizon_ms", problem);
+        }
+    }
+}
+
+impl ComponentConfig for CropBedPowerConfig {
+    fn crop_bed(&self) -> CropBed {
+        self.crop_bed_id
+    }
+}
+
+/// Component for managing the crop bed power in one module.
+/// Currently this consists of two PDMs, but could be increased
+/// to as many as allowed on the canbus network (pending addressing
+/// clashing)
+#[allow(dead_code)]
+pub struct CropBedPower {
+    /// Unique identifier for the component.
+    uuid: Uuid,
+    /// ID of the specific crop bed module.
+    crop_bed_id: CropBed,
+    /// Canbus interface name.
+    canbus_id: String,
+    /// Map of the Pdm drivers.
+    pdms: HashMap<u8, Pdm>,
+    /// Internal linux port the component will be commanded on
+    port: i32,
+    /// Message queue that stores upcoming actions, keyed by when they fire
+    /// on the clock of the component.
+    message_queue: DoublePriorityQueue<WeedQueueMessage, DateTime<Utc>>,
+    /// Largest number of messages kept in the queue.
+    max_queue_len: usize,
+    /// Messages dropped because the queue was full.
+    queue_dropped: u64,
+    /// Messages received on the weed message socket.
+    message_counts: MessageCounts,
+    /// Queued messages fired behind their time, within the late grace.
+    late_fired: u64,
+    /// Queued messages discarded for being past the late grace.
+    late_discarded: u64,
+    /// Offs held back by the debounce window.
+    debounced: u64,
+    /// Fire times the solenoid latency could not be fully compensated for.
+    compensation_clamped: u64,
+    /// When a message was last sent to a PDM.
+    last_fired_at: Option<DateTime<Utc>>,
+    /// Where the machine was when a message was last sent to a PDM.
+    last_fired_position: Option<GeoPosition>,
+    /// Latest fix of a GPS on the machine, attached to the sprays of weed
+    /// messages sent without a position.
+    gps_fix: Option<GeoPosition>,
+    /// Port the status server listens on.
+    status_port: Option<i32>,
+    /// File the received lines are journalled to.
+    journal_path: Option<PathBuf>,
+    /// Size the journal is rotated at.
+    journal_max_bytes: u64,
+    /// Journal the received lines are handed to, once started.
+    journal: Option<Journal>,
+    /// Which PDM each crop bed channel is wired to.
+    channel_layout: ChannelLayout,
+    /// Whether manual sprays from the operator are fired.
+    allow_manual_spray: bool,
+    /// Whether sprays are queued and timed as usual without turning any
+    /// channel on, toggled by the operator.
+    dry_run: bool,
+    /// Longest spray that is queued, longer sprays are clamped to it unless
+    /// refused by validation.
+    max_spray_duration: Duration,
+    /// Limits weed messages are validated against before they are queued.
+    message_constraints: MessageConstraints,
+    /// Channel maps for the PDMs when the wiring harness does
+    /// not logically map to the solenoid numbers.
+    channel_map: Option<HashMap<u8, (u8, u8)>>,
+    /// When the PDM configuration is read back and verified.
+    pdm_verification: PdmVerification,
+    /// Timing of the spray messages.
+    timing: PowerTiming,
+    /// Wheel speed sensor the spray times follow.
+    wheel_speed: Option<WheelSpeedConfig>,
+    /// Latest ground speed followed, queued messages with a speed are timed
+    /// for it once it is known.
+    ground_speed: Option<GroundSpeed>,
+    /// Spray intervals accepted for each channel, used to drop offs that
+    /// would cut short an overlapping spray.
+    spray_schedule: SpraySchedule,
+    /// Wakes the firing task when a message arrives that is due before the
+    /// one it is sleeping on.
+    queue_changed: Arc<Notify>,
+    /// Channels turned on whose current feedback is still to be checked,
+    /// as the PDM key, channel and time fired.
+    feedback_checks: Vec<(u8, u8, Instant)>,
+    /// Clock the receive, fire and actuation times are read from.
+    clock: MonotonicClock,
+    /// Latency of the messages last sent to the PDMs.
+    latency: LatencyWindow,
+    /// Whether each latency is printed.
+    log_latency: bool,
+    /// Transports the weed messages are received over.
+    transport: Transport,
+    /// Encoding of the TCP connections.
+    encoding: Encoding,
+    /// Framing of the TCP connections.
+    framing: Framing,
+    /// Size of the largest datagram handled.
+    max_datagram_bytes: usize,
+    /// Channels the heartbeat is sent to.
+    heartbeat: HeartbeatStrategy,
+    /// Weed messages recently received, to drop retries.
+    recent_messages: RecentMessages,
+    /// Delay of the solenoids the sprays are fired ahead by.
+    solenoid_latency: SolenoidLatencyConfig,
+    /// Config the component is running, compared against on a reload.
+    config: CropBedPowerConfig,
+    /// File the config was read from, reloaded on SIGHUP.
+    config_file: Option<PathBuf>,
+    /// When the component was created, for the uptime in its heartbeats.
+    started_at: Instant,
+    /// Where the heartbeats of the component are sent.
+    heartbeat_emitter: Option<HeartbeatEmitterConfig>,
+    /// Latest heartbeats received from other components.
+    peers: HeartbeatPeers,
+    /// Events of the component, printed and written to the structured log.
+    log: LogEmitter,
+    /// Where the telemetry of the component is shipped.
+    telemetry: Option<TelemetryEmitterConfig>,
+    /// Liveness and readiness probed by the orchestrator.
+    health: Health,
+    /// Passes of the firing task, followed by the watchdog.
+    firing_progress: Progress,
+    /// Whether the queue is drained or dropped on a shutdown.
+    shutdown: ShutdownConfig,
+    /// Set once an emergency stop has turned every channel off, the
+    /// component then stops without draining.
+    emergency_stop: watch::Sender<bool>,
+    /// Bus the ground speed and PDM faults are published on and a shutdown
+    /// is requested on, when run in one binary with other components.
+    bus: Option<MessageBus>,
+    /// Sensor followed in place of the wheel speed of the config, until the
+    /// component is started.
+    speed_sensor: Option<WheelSpeedSensor>,
+}
+
+impl CropBedPower {
+    /// Create a new component from a config struct, refusing a channel
+    /// layout or PDM configs it cannot run.
+    ///
+    /// * `config`: Struct containing the parameters for configuration.
+    pub fn new(config: CropBedPowerConfig) -> Result<Self, ComponentError> {
+        let uuid = Uuid::new_v4();
+        Ok(Self {
+            uuid,
+            port: config.port,
+            crop_bed_id: config.crop_bed_id,
+            canbus_id: config.canbus_id.clone(),
+            channel_map: config.channel_map.clone(),
+            pdm_verification: config.pdm_verification.unwrap_or_default(),
+            timing: config.timing,
+            wheel_speed: config.wheel_speed.clone(),
+            ground_speed: None,
+            message_queue: DoublePriorityQueue::new(),
+            max_queue_len: config.max_queue_len.unwrap_or(DEFAULT_MAX_QUEUE_LEN),
+            queue_dropped: 0,
+            message_counts: MessageCounts::default(),
+            late_fired: 0,
+            late_discarded: 0,
+            debounced: 0,
+            compensation_clamped: 0,
+            last_fired_at: None,
+            last_fired_position: None,
+            gps_fix: None,
+            status_port: config.status_port,
+            journal_path: config.journal_path.clone(),
+            journal_max_bytes: config.journal_max_bytes.unwrap_or(DEFAULT_JOURNAL_MAX_BYTES),
+            journal: None,
+            channel_layout: Self::build_channel_layout(&config)?,
+            allow_manual_spray: config.allow_manual_spray,
+            dry_run: false,
+            max_spray_duration: config.max_spray_duration(),
+            message_constraints: config.message_constraints(),
+            queue_changed: Arc::new(Notify::new()),
+            spray_schedule: SpraySchedule::default(),
+            feedback_checks: Vec::new(),
+            clock: MonotonicClock::new(),
+            latency: LatencyWindow::default(),
+            log_latency: config.log_latency,
+            transport: config.transport.unwrap_or_default(),
+            encoding: config.encoding.unwrap_or_default(),
+            framing: config.encoding.unwrap_or_default().framing_or(config.framing),
+            max_datagram_bytes: config.max_datagram_bytes.unwrap_or(DEFAULT_MAX_DATAGRAM_BYTES),
+            heartbeat: config.heartbeat.unwrap_or_default(),
+            recent_messages: RecentMessages::new(config.dedup.unwrap_or_default()),
+            solenoid_latency: config.solenoid_latency.clone().unwrap_or_default(),
+            started_at: Instant::now(),
+            heartbeat_emitter: config.heartbeat_emitter.clone(),
+            peers: HeartbeatPeers::default(),
+            log: LogEmitter::new(ComponentKind::Power, uuid, config.crop_bed_id).with_config(config.log.as_ref()),
+            telemetry: config.telemetry.clone(),
+            health: Health::new(),
+            firing_progress: Progress::new(),
+            shutdown: config.shutdown.unwrap_or_default(),
+            emergency_stop: watch::channel(false).0,
+            bus: None,
+            speed_sensor: None,
+            config: config.clone(),
+            config_file: None,
+            pdms: Self::build_from_config(config)?,
+        })
     }
 
-    /// Add a PDM to the component with a config file.
-    ///
-    /// * `filepath`: path to config
-    /// * `pdm_id`: Address of the PDM.
-    pub fn add_pdm_config_file<F>(mut self, filepath: F, pdm_id: u8) -> Self
-    where
-        F: AsRef<OsStr>,
-    {
-        self.pdm_config_files.insert(pdm_id, (&filepath).into());
-        self
+    /// Create a new component by reading the config parameters from a file.
+    ///
+    /// * `filepath`: path to config file.
+    pub fn from_config_file<F: AsRef<OsStr>>(filepath: F) -> Result<Self, ComponentError> {
+        let config = CropBedPowerConfig::try_from_file(&filepath)?;
+        let mut component = Self::new(config)?;
+        component.config_file = Some(PathBuf::from(filepath.as_ref()));
+        Ok(component)
+    }
+
+    /// Channel layout of the config, refusing ones that wire a crop bed
+    /// channel twice.
+    ///
+    /// * `config`: struct with configuration parameters.
+    fn build_channel_layout(config: &CropBedPowerConfig) -> Result<ChannelLayout, ComponentError> {
+        let channel_layout = config.channel_layout.clone().unwrap_or_default();
+        if let Err(e) = channel_layout.validate() {
+            return Err(ComponentError::Invalid(format!(
+                "Invalid channel layout for {}: {e}",
+                config.canbus_id
+            )));
+        }
+        Ok(channel_layout)
+    }
+
+    /// Helper function used to build the resulting component.
+    ///
+    /// * `config`: struct with configuration parameters.
+    fn build_from_config(config: CropBedPowerConfig) -> Result<HashMap<u8, Pdm>, ComponentError> {
+        let pdm_configs = config.pdm_configs()?;
+        if let Err(e) = check_unique_addresses(&pdm_configs) {
+            return Err(ComponentError::Invalid(format!(
+                "Invalid PDM configs for {}: {e}",
+                config.canbus_id
+            )));
+        }
+        Ok(pdm_configs
+            .into_iter()
+            .map(|(bed_position, pdm_config)| (bed_position, Pdm::new(pdm_config)))
+            .collect())
+    }
+
+    /// Current status of the component.
+    pub fn status(&self) -> CropBedPowerStatus {
+        CropBedPowerStatus {
+            canbus_id: self.canbus_id.clone(),
+            channel_stats: self
+                .pdms
+                .iter()
+                .map(|(bed_position, pdm)| (*bed_position, pdm.channel_stats()))
+                .collect(),
+            queue_depth: self.message_queue.len(),
+            queue_dropped: self.queue_dropped,
+            tasks_failed: 0,
+        }
+    }
+
+    /// Heartbeat telling the other components this one is alive.
+    pub fn heartbeat(&self) -> Heartbeat {
+        Heartbeat::new(
+            ComponentKind::Power,
+            self.uuid,
+            self.crop_bed_id,
+            self.started_at.elapsed().as_secs_f64(),
+        )
+        .with_queue_depth(self.message_queue.len())
+    }
+
+    /// Latest heartbeats received from other components.
+    pub fn peers(&self) -> &HeartbeatPeers {
+        &self.peers
+    }
+
+    /// Whether sprays are run without turning any channel on.
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Health of the component, updated once it is started.
+    pub fn health(&self) -> Health {
+        self.health.clone()
+    }
+
+    /// Passes of the firing task, which goes round at least once a
+    /// heartbeat interval while the component is firing.
+    pub fn firing_progress(&self) -> Progress {
+        self.firing_progress.clone()
+    }
+
+    /// Publish the ground speed followed and the PDM faults on a bus, and
+    /// stop once a shutdown is requested on it.
+    ///
+    /// * `bus`: bus shared with the other components of the binary.
+    pub fn with_bus(mut self, bus: MessageBus) -> Self {
+        self.bus = Some(bus);
+        self
+    }
+
+    /// Follow the ground speed of a sensor rather than the wheel speed of
+    /// the config, i.e. one started on a simulated source.
+    ///
+    /// * `speed_sensor`: sensor the spray times follow.
+    pub fn with_speed_sensor(mut self, speed_sensor: WheelSpeedSensor) -> Self {
+        self.speed_sensor = Some(speed_sensor);
+        self
+    }
+
+    /// Keep the latest fix of a GPS on the machine, attached to sprays
+    /// from weed messages the AI system sent without a position.
+    ///
+    /// * `fix`: position reported by the GPS.
+    pub fn record_gps_fix(&mut self, fix: GeoPosition) {
+        self.gps_fix = Some(fix);
+    }
+
+    /// Snapshot of what the component is doing, for the status server.
+    pub fn snapshot(&self) -> CropBedPowerSnapshot {
+        CropBedPowerSnapshot {
+            canbus_id: self.canbus_id.clone(),
+            queue_depth: self.message_queue.len(),
+            queue_dropped: self.queue_dropped,
+            next_fire_time: self.message_queue.peek_min().map(|(_, priority)| *priority),
+            last_fire_time: self.last_fired_at,
+            pdms: self
+                .pdms
+                .iter()
+                .map(|(bed_position, pdm)| {
+                    (
+                        *bed_position,
+                        PdmSnapshot {
+                            address: pdm.address().raw(),
+                            initialised: pdm.is_initialised(),
+                            status: pdm.status(),
+                        },
+                    )
+                })
+                .collect(),
+            messages: self.message_counts,
+            late_fired: self.late_fired,
+            late_discarded: self.late_discarded,
+            debounced: self.debounced,
+            compensation_clamped: self.compensation_clamped,
+            channel_map: self
+                .channel_map
+                .as_ref()
+                .map(|channel_map| channel_map.iter().map(|(channel, mapped)| (*channel, *mapped)).collect()),
+            latency: self.latency.summary(),
+        }
+    }
+
+    /// Telemetry of the spray queue, taken under the component lock and
+    /// serialised by the emitter after it is released.
+    pub fn spray_telemetry(&self) -> SprayTelemetry {
+        SprayTelemetry {
+            header: TelemetryHeader::new(self.uuid, self.crop_bed_id),
+            queue_depth: self.message_queue.len(),
+            accepted: self.message_counts.accepted,
+            late: self.message_counts.late,
+            late_discarded: self.late_discarded,
+            last_fired_at: self.last_fired_at,
+            last_fired_position: self.last_fired_position,
+        }
+    }
+
+    /// Telemetry of the fault state and wear of each PDM, taken under the
+    /// component lock and serialised by the emitter after it is released.
+    pub fn pdm_telemetry(&self) -> PdmTelemetry {
+        PdmTelemetry {
+            header: TelemetryHeader::new(self.uuid, self.crop_bed_id),
+            pdms: self
+                .pdms
+                .iter()
+                .map(|(bed_position, pdm)| {
+                    let status = pdm.status();
+                    (
+                        *bed_position,
+                        PdmStateTelemetry {
+                            address: pdm.address().raw(),
+                            loss_of_can: status.loss_of_can,
+                            over_temperature: status.module_over_temperature,
+                            tripped_channels: status.tripped_channels(),
+                            hot_channels: status.hot_channels(),
+                            channel_on_time_ms: pdm
+                                .channel_stats()
+                                .into_iter()
+                                .map(|(channel, usage)| (channel, usage.on_time_ms))
+                                .collect(),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Read back the configuration of every PDM, returning false if one has
+    /// drifted and the policy is to stop driving it.
+    async fn verify_pdms(&self) -> bool {
+        let mut keep_running = true;
+        for (bed_position, pdm) in &self.pdms {
+            let verified = pdm.check_drift(self.pdm_verification.on_drift).await;
+            self.health.set_pdm_ready(*bed_position, verified);
+            keep_running &= verified;
+        }
+        keep_running
+    }
+
+    /// Configure a PDM again and read the configuration back, logging how
+    /// long the PDM was out of action. Returns whether it recovered.
+    ///
+    /// * `bed_position`: key of the PDM in the component.
+    /// * `reason`: why the PDM is being reinitialised, for the log.
+    async fn reinitialise_pdm(&mut self, bed_position: u8, reason: &str) -> bool {
+        let Some(pdm) = self.pdms.get_mut(&bed_position) else {
+            self.log.warn(
+                EventCode::Unrouted,
+                format!("No PDM at bed position {bed_position} to reinitialise on {}", self.canbus_id),
+            );
+            return false;
+        };
+        let started = Instant::now();
+        self.log.emit(
+            self.log
+                .event(
+                    LogLevel::Warn,
+                    EventCode::PdmReinitialising,
+                    format!(
+                        "Reinitialising PDM {} at bed position {} after {reason}",
+                        pdm.address(),
+                        bed_position
+                    ),
+                )
+                .with_field("pdm", pdm.address())
+                .with_field("bed_position", bed_position),
+        );
+        match pdm.reinitialise().await {
+            Ok(mismatches) => {
+                for mismatch in &mismatches {
+                    self.log.warn(
+                        EventCode::PdmMismatch,
+                        format!("PDM {} did not read back {mismatch}", pdm.address()),
+                    );
+                }
+                // The bus carried the configuration both ways, so it is
+                // working again whatever took it down.
+                self.health.set_canbus_open(true);
+                self.health.set_pdm_ready(bed_position, mismatches.is_empty());
+                self.log.emit(
+                    self.log
+                        .event(
+                            LogLevel::Info,
+                            EventCode::PdmRecovered,
+                            format!(
+                                "Recovered PDM {} at bed position {} in {:?}",
+                                pdm.address(),
+                                bed_position,
+                                started.elapsed()
+                            ),
+                        )
+                        .with_field("pdm", pdm.address())
+                        .with_field("bed_position", bed_position),
+                );
+                true
+            }
+            Err(e) => {
+                self.health.set_pdm_ready(bed_position, false);
+                self.log.emit(
+                    self.log
+                        .event(
+                            LogLevel::Error,
+                            EventCode::PdmFailed,
+                            format!(
+                                "ALARM: failed to reinitialise PDM {} after {:?}: {e}",
+                                pdm.address(),
+                                started.elapsed()
+                            ),
+                        )
+                        .with_field("pdm", pdm.address())
+                        .with_field("bed_position", bed_position),
+                );
+                false
+            }
+        }
+    }
+
+    /// Discard every queued message and turn every channel of every PDM
+    /// off, the last thing sent before the component exits.
+    async fn turn_all_off(&mut self) {
+        self.message_queue.clear();
+        self.spray_schedule = SpraySchedule::default();
+        self.feedback_checks.clear();
+        let mut bed_positions: Vec<u8> = self.pdms.keys().copied().collect();
+        bed_positions.sort_unstable();
+        for bed_position in bed_positions {
+            let pdm = &self.pdms[&bed_position];
+            pdm.actuate_channels((1..=CHANNEL_COUNT).collect(), 0.0).await;
+            self.log.info(
+                EventCode::ChannelsOff,
+                format!(
+                    "Turned every channel off on PDM {} at bed position {}",
+                    pdm.address(),
+                    bed_position
+                ),
+            );
+        }
+    }
+
+    /// Stop spraying at once: drop the queue, turn every channel off and
+    /// stop the component, which then shuts down without draining. Nothing
+    /// more is fired once it returns.
+    pub async fn emergency_stop(&mut self) {
+        let dropped = self.message_queue.len();
+        self.turn_all_off().await;
+        self.log.error(
+            EventCode::EmergencyStop,
+            format!(
+                "Emergency stop on {}, {dropped} queued messages dropped",
+                self.canbus_id
+            ),
+        );
+        self.emergency_stop.send_replace(true);
+    }
+
+    /// Whether an emergency stop has turned every channel off.
+    pub fn emergency_stopped(&self) -> bool {
+        *self.emergency_stop.borrow()
+    }
+
+    /// Fire the queued messages due within a horizon of now, then return
+    /// the number dropped for being after it. The off of a spray starting
+    /// within the horizon and ending after it is brought forward to the end
+    /// of the horizon, so no spray is left open. The component is held
+    /// locked throughout, so no message is queued meanwhile.
+    ///
+    /// * `horizon`: time ahead of now the messages are fired.
+    async fn drain(&mut self, horizon: std::time::Duration) -> usize {
+        let now = self.clock.now();
+        let cutoff = now + Duration::from_std(horizon).unwrap_or_else(|_| Duration::zero());
+        let mut after_cutoff = Vec::new();
+        while self
+            .message_queue
+            .peek_max()
+            .is_some_and(|(_, priority)| *priority > cutoff)
+        {
+            after_cutoff.extend(self.message_queue.pop_max());
+        }
+        let mut dropped = 0;
+        for (mut message, _) in after_cutoff {
+            if !message.is_on && message.original_spray_starts <= cutoff {
+                message.time_to_fire = cutoff;
+                message.original_spray_ending = cutoff;
+                self.message_queue.push(message, cutoff);
+            } else {
+                dropped += 1;
+            }
+        }
+        // The schedule would still hold the cut sprays open to their old end
+        // and suppress their offs.
+        self.rebuild_spray_schedule(now);
+        let mut last_fire = Instant::now();
+        while !self.message_queue.is_empty() {
+            last_fire = self.process_message_queue(last_fire).await;
+            let wake = self.next_wake(last_fire);
+            if wake <= Instant::now() {
+                tokio::task::yield_now().await;
+            } else {
+                tokio::time::sleep_until(wake).await;
+            }
+        }
+        dropped
+    }
+
+    /// Log channels that were turned on but report no current once the
+    /// solenoid has had time to pull in. Normal current with no flow points
+    /// at a blocked nozzle, no current at a broken coil or harness.
+    fn check_feedback(&mut self) {
+        let (pdms, log) = (&self.pdms, &self.log);
+        self.feedback_checks.retain(|(pdm_key, channel, fired_at)| {
+            if fired_at.elapsed() < FEEDBACK_SETTLE {
+                return true;
+            }
+            let feedback = pdms
+                .get(pdm_key)
+                .and_then(|pdm| pdm.channel_feedback_since(*channel, *fired_at + FEEDBACK_SETTLE / 2));
+            if let Some(feedback) = feedback {
+                if feedback.amps < ZERO_CURRENT_AMPS || feedback.has_fault() {
+                    log.emit(
+                        log.event(
+                            LogLevel::Warn,
+                            EventCode::SolenoidFault,
+                            format!(
+                                "Channel {} on PDM {} drew {:.2}A after actuation, status flags {:#04b}, check the solenoid coil",
+                                channel,
+                                pdms[pdm_key].address(),
+                                feedback.amps,
+                                feedback.status_flags
+                            ),
+                        )
+                        .with_field("channel", channel)
+                        .with_field("pdm", pdms[pdm_key].address())
+                        .with_field("amps", feedback.amps),
+                    );
+                }
+            }
+            false
+        });
+    }
+
+    /// Add weed message to queue once parsed from the AI system.
+    /// NOTE: There is an edge case where a channel will send a
+    /// message that may turn off another channel pre-emptively
+    /// this message was stripped out in a previous implementation
+    /// of this function however there still appeared to be noise.
+    /// On further investigation it became clear that there was
+    /// some additional messages being sent by the AI system both
+    /// factually (i.e. weeds moving in the wind), and *Potentially*
+    /// erroneously (array indexing) which may have been contributing
+    /// to the noise or in the very least making it hard to trouble
+    /// shoot. See git commit history for those naive implementations
+    /// once the AI message generation has been confirmed.
+    ///
+    /// * `message`: message parsed from AI container.
+    fn add_to_message_queue(&mut self, message: WeedQueueMessage) {
+        let priority = message.time_to_fire;
+        let new_minimum = match self.message_queue.peek_min() {
+            Some((_, next)) => priority < *next,
+            None => true,
+        };
+        self.message_queue.push(message, priority);
+        if self.message_queue.len() > self.max_queue_len {
+            self.evict_furthest_sprays(self.clock.now());
+        }
+        if new_minimum {
+            self.queue_changed.notify_one();
+        }
+        metrics::queue_depth(&self.canbus_id, self.message_queue.len());
+    }
+
+    /// Drop the sprays furthest in the future until the queue is back
+    /// within its limit, counting the messages dropped. A misbehaving AI
+    /// system flooding the socket then only costs the sprays it is least
+    /// sure about. Sprays are dropped whole so an on never loses its off,
+    /// and sprays that have started are kept so their channels still turn
+    /// off, which can leave the queue over the limit until they end.
+    ///
+    /// * `now`: sprays starting before this are kept.
+    fn evict_furthest_sprays(&mut self, now: DateTime<Utc>) {
+        let mut evicted = false;
+        while self.message_queue.len() > self.max_queue_len {
+            let Some(furthest) = self
+                .message_queue
+                .iter()
+                .filter(|(message, _)| message.original_spray_starts > now)
+                .max_by_key(|(_, priority)| **priority)
+                .map(|(message, _)| message.clone())
+            else {
+                break;
+            };
+            let spray: Vec<WeedQueueMessage> = self
+                .message_queue
+                .iter()
+                .filter(|(message, _)| message.same_spray(&furthest))
+                .map(|(message, _)| message.clone())
+                .collect();
+            for message in &spray {
+                self.message_queue.remove(message);
+                self.queue_dropped += 1;
+            }
+            evicted = true;
+        }
+        if evicted {
+            self.rebuild_spray_schedule(now);
+        }
+    }
+
+    /// Drop the ons of the manual sprays left in the queue as a dry run
+    /// ends, returning the number dropped. The interlock is lifted for a
+    /// dry run, so sprays it let through must not go on for real once it
+    /// is over. Their offs are kept, turning a channel off is always safe.
+    fn drop_manual_ons(&mut self) -> usize {
+        let manual_ons: Vec<WeedQueueMessage> = self
+            .message_queue
+            .iter()
+            .filter(|(message, _)| message.manual && message.is_on)
+            .map(|(message, _)| message.clone())
+            .collect();
+        for message in &manual_ons {
+            self.message_queue.remove(message);
+        }
+        if !manual_ons.is_empty() {
+            self.rebuild_spray_schedule(self.clock.now());
+            metrics::queue_depth(&self.canbus_id, self.message_queue.len());
+        }
+        manual_ons.len()
+    }
+
+    /// Rebuild the spray intervals from the queue after messages have been
+    /// moved or dropped. Sprays whose off has fired are over, so the queue
+    /// holds every interval that can still suppress an off.
+    ///
+    /// * `now`: intervals ending before this are left out.
+    fn rebuild_spray_schedule(&mut self, now: DateTime<Utc>) {
+        let mut spray_schedule = SpraySchedule::default();
+        for (message, _) in &self.message_queue {
+            spray_schedule.insert(
+                &message.crop_bed_channels(),
+                message.original_spray_starts,
+                message.original_spray_ending,
+                now,
+            );
+        }
+        self.spray_schedule = spray_schedule;
+    }
+
+    /// Spray times of a weed message for the ground speed being followed,
+    /// and the speed they are then timed for. Messages without an assumed
+    /// speed keep their times.
+    ///
+    /// * `message`: message from the AI system.
+    /// * `now`: time the spray is shifted from.
+    fn timed_spray(&self, message: &WeedMessage, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>, Option<GroundSpeed>) {
+        let start = message.start_spray_time;
+        let end = message.end_spray_time;
+        match (message.assumed_speed_mps.filter(|assumed| *assumed > 0.0), self.ground_speed) {
+            (Some(assumed), Some(ground_speed)) => {
+                let ratio = assumed / ground_speed.mps();
+                (retime(start, now, ratio), retime(end, now, ratio), Some(ground_speed))
+            }
+            (Some(assumed), None) => (start, end, Some(GroundSpeed::from_mps(assumed))),
+            (None, _) => (start, end, None),
+        }
+    }
+
+    /// Route crop bed channels to the PDM each is wired to, dropping
+    /// channels that are not wired to one.
+    ///
+    /// * `channels`: crop bed channels, numbered from 1.
+    fn route_channels(&self, channels: impl IntoIterator<Item = u8>) -> Vec<(u8, u8)> {
+        let mut routed = Vec::new();
+        for channel in channels {
+            // The electrical team needed to wire the PDMs in a specific way to make
+            // it easier for physical manufacturing. This means that on some crop beds
+            // that the channel numbers do not coincide with the channel numbers of the
+            // PDM. This mapping can be very confusing to trouble shoot.
+            let wired = match &self.channel_map {
+                Some(channel_map) => channel_map.get(&channel).map(|(converted, pdm)| (*pdm, *converted)),
+                None => self
+                    .channel_layout
+                    .locate(channel)
+                    .map(|(pdm, _pdm_channel)| (pdm, channel)),
+            };
+            if let Some(wired) = wired {
+                routed.push(wired);
+            } else {
+                self.log.warn(
+                    EventCode::Unrouted,
+                    format!("Channel {channel} is not wired to a PDM, not spraying it"),
+                );
+            }
+        }
+        routed
+    }
+
+    /// Queue the on, any re-fires and the off of a spray, returning the
+    /// number of actions queued. Channels are fired ahead of the spray
+    /// times by the latency of their solenoids, channels with different
+    /// latencies in messages of their own.
+    ///
+    /// * `channels`: bed position of the PDM and crop bed channel to spray.
+    /// * `start_spray_time`: when the liquid starts at the nozzles.
+    /// * `end_spray_time`: when the liquid stops at the nozzles.
+    /// * `intensity`: duty cycle in percent.
+    /// * `timed_for`: ground speed the times were computed for.
+    /// * `manual`: fired by hand from the operator.
+    /// * `received_at`: when the message asking for the spray was received.
+    /// * `position`: where the machine was when the weed was captured.
+    #[allow(clippy::too_many_arguments)]
+    fn queue_spray(
+        &mut self,
+        channels: Vec<(u8, u8)>,
+        start_spray_time: DateTime<Utc>,
+        end_spray_time: DateTime<Utc>,
+        intensity: u8,
+        timed_for: Option<GroundSpeed>,
+        manual: bool,
+        received_at: DateTime<Utc>,
+        position: Option<GeoPosition>,
+    ) -> usize {
+        let mut queued_actions = 0;
+        // Bound the re-fires however the spray got here, a spray held open
+        // for hours would fill the queue and never let the channel close.
+        let end_spray_time = if end_spray_time - start_spray_time > self.max_spray_duration {
+            self.log.warn(
+                EventCode::Clamped,
+                format!(
+                    "Spray of {} is longer than the maximum {}, cutting it short",
+                    end_spray_time - start_spray_time,
+                    self.max_spray_duration
+                ),
+            );
+            start_spray_time + self.max_spray_duration
+        } else {
+            end_spray_time
+        };
+        let crop_bed_channels: Vec<u8> = channels.iter().map(|(_, channel)| *channel).collect();
+        // Per channel duty cycles only when dosing below full, so full
+        // sprays still go out as one call per PDM.
+        let pwm = (intensity < FULL_INTENSITY).then(|| {
+            crop_bed_channels
+                .iter()
+                .map(|channel| (*channel, intensity))
+                .collect::<BTreeMap<u8, u8>>()
+        });
+        self.spray_schedule
+            .insert(&crop_bed_channels, start_spray_time, end_spray_time, self.clock.now());
+        let timing = self.timing;
+        for (latency, channels) in self.solenoid_latency.group(channels) {
+            // Fire ahead of the spray times by the delay of the solenoids, so
+            // the liquid rather than the signal follows them.
+            let (on_time, on_clamped) = solenoid::compensate(start_spray_time, latency.open, self.clock.now());
+            let (off_time, off_clamped) = solenoid::compensate(end_spray_time, latency.close, on_time);
+            self.compensation_clamped += u64::from(on_clamped) + u64::from(off_clamped);
+            let mut delta = off_time - on_time;
+            // PDM will cut off after the keepalive, so longer durations require
+            // to have the message queue to be padded out.
+            if delta > timing.pdm_keepalive() {
+                let mut time_to_fire = on_time;
+                while delta > timing.refire_interval() {
+                    let power_ons = WeedQueueMessage {
+                        channels: channels.clone(),
+                        time_to_fire: time_to_fire + timing.refire_interval(),
+                        is_on: true,
+                        original_spray_starts: start_spray_time,
+                        original_spray_ending: end_spray_time,
+                        pwm: pwm.clone(),
+                        timed_for,
+                        manual,
+                        received_at,
+                        position,
+                    };
+                    self.add_to_message_queue(power_ons);
+                    queued_actions += 1;
+                    time_to_fire += timing.refire_interval();
+                    delta = delta - timing.refire_interval();
+                }
+                let power_off = WeedQueueMessage {
+                    channels,
+                    time_to_fire: off_time,
+                    is_on: false,
+                    original_spray_starts: start_spray_time,
+                    original_spray_ending: end_spray_time,
+                    pwm: pwm.clone(),
+                    timed_for,
+                    manual,
+                    received_at,
+                    position,
+                };
+                self.add_to_message_queue(power_off);
+                queued_actions += 1;
+            } else {
+                let power_ons = WeedQueueMessage {
+                    channels: channels.clone(),
+                    time_to_fire: on_time,
+                    is_on: true,
+                    original_spray_starts: start_spray_time,
+                    original_spray_ending: end_spray_time,
+                    pwm: pwm.clone(),
+                    timed_for,
+                    manual,
+                    received_at,
+                    position,
+                };
+
+                let power_off = WeedQueueMessage {
+                    channels,
+                    time_to_fire: off_time,
+                    is_on: false,
+                    original_spray_starts: start_spray_time,
+                    original_spray_ending: end_spray_time,
+                    pwm: pwm.clone(),
+                    timed_for,
+                    manual,
+                    received_at,
+                    position,
+                };
+                self.add_to_message_queue(power_ons);
+                queued_actions += 1;
+                self.add_to_message_queue(power_off);
+                queued_actions += 1;
+            }
+        }
+        queued_actions
+    }
+
+    /// Follow a new ground speed, shifting every queued message timed for a
+    /// different speed so it fires over the same ground. The on and off of
+    /// a spray are shifted together under the same lock and from the same
+    /// time, so the distance sprayed is kept. Speeds that are unknown or
+    /// too low to follow leave the queue as it is.
+    ///
+    /// * `speed`: latest ground speed.
+    /// * `now`: time the messages are shifted from.
+    pub fn update_ground_speed(&mut self, speed: Option<GroundSpeed>, now: DateTime<Utc>) {
+        let Some(speed) = speed.filter(|speed| speed.mps() >= MIN_TRACKED_SPEED_MPS) else {
+            self.ground_speed = None;
+            return;
+        };
+        self.ground_speed = Some(speed);
+        let needs_retime = self
+            .message_queue
+            .iter()
+            .any(|(message, _)| message.timed_for.is_some_and(|timed_for| timed_for != speed));
+        if !needs_retime {
+            return;
+        }
+        let queue = std::mem::replace(&mut self.message_queue, DoublePriorityQueue::new());
+        for (mut message, _) in queue {
+            if let Some(timed_for) = message.timed_for.filter(|timed_for| *timed_for != speed) {
+                let ratio = timed_for.mps() / speed.mps();
+                message.time_to_fire = retime(message.time_to_fire, now, ratio);
+                message.original_spray_starts = retime(message.original_spray_starts, now, ratio);
+                message.original_spray_ending = retime(message.original_spray_ending, now, ratio);
+                message.timed_for = Some(speed);
+            }
+            let priority = message.time_to_fire;
+            self.message_queue.push(message, priority);
+        }
+        self.rebuild_spray_schedule(now);
+        self.queue_changed.notify_one();
+    }
+
+    /// When the firing task next has something to do: the next message
+    /// coming within the spray bound less the timer slack, the next
+    /// heartbeat, or the next current feedback check.
+    ///
+    /// * `last_fire`: when a message or heartbeat was last sent.
+    fn next_wake(&self, last_fire: Instant) -> Instant {
+        let now = Instant::now();
+        let mut wake = last_fire + self.timing.heartbeat_interval();
+        if let Some((_, priority)) = self.message_queue.peek_min() {
+            // Messages already due convert to zero and wake at once.
+            let until_due = (*priority - self.clock.now() - self.timing.spray_bound())
+                .to_std()
+                .unwrap_or_default();
+            wake = wake.min(now + until_due.saturating_sub(TIMER_SLACK));
+        }
+        if let Some(check_at) = self
+            .feedback_checks
+            .iter()
+            .map(|(_, _, fired_at)| *fired_at + FEEDBACK_SETTLE)
+            .min()
+        {
+            wake = wake.min(check_at);
+        }
+        wake
+    }
+
+    /// I dislike this implementation, will need to work on the image messages being
+    /// sent through to the control system, or some kind of state machine which can
+    // be polled by futures. Ultimately it will change with the inclusion of a wheel
+    // speed sensor anyway.
+    // INFO: The channels of a message are routed to the PDM they were queued for, the
+    //       channel layout gives the channel on that PDM.
+    async fn process_message_queue(&mut self, mut last_fire: Instant) -> Instant {
+        // Channels turned on this call, checked for current once settled.
+        let mut fired_on: Vec<(u8, Vec<u8>)> = Vec::new();
+        // Queued sprays are timed off the monotonic clock, a step of the
+        // system clock leaves them in place but is worth telling about.
+        if let Some(step) = self
+            .clock
+            .detect_step(Utc::now(), Duration::milliseconds(DEFAULT_CLOCK_STEP_THRESHOLD_MS))
+        {
+            self.log.warn(
+                EventCode::ClockStepped,
+                format!("System clock stepped by {step}, queued sprays keep their monotonic deadlines"),
+            );
+        }
+        if let Some((message, priority)) = self.message_queue.peek_min() {
+            let utc_now = self.clock.now();
+            // The thread sleep can miss by a few microseconds, and over spraying is preferred
+            // to under spraying, so messages only just behind still fire.
+            match queue_action(*priority, utc_now, &self.timing) {
+                QueueAction::Wait => {}
+                QueueAction::Discard => {
+                    self.log.warn(
+                        EventCode::MessageLate,
+                        format!("Discarding a message {} behind its fire time", utc_now - *priority),
+                    );
+                    self.late_discarded += 1;
+                    self.message_queue.pop_min();
+                }
+                QueueAction::Fire => {
+                    if *priority < utc_now {
+                        self.late_fired += 1;
+                    }
+                    let (channels, debounced) =
+                        channels_to_fire(message, *priority, &self.spray_schedule, self.timing.debounce());
+                    self.debounced += debounced;
+                    let mut actuated = false;
+                    let position = message.position.or(self.gps_fix);
+                    for ((pdm_key, offset), pdm_channels) in self.channel_layout.group(&channels) {
+                        // Turning channels off is always safe, so a dry run
+                        // started part way through a spray still ends it.
+                        if message.is_on && self.dry_run {
+                            continue;
+                        }
+                        if let Some(pdm) = self.pdms.get(&pdm_key) {
+                            if message.is_on {
+                                fired_on.push((pdm_key, pdm_channels.clone()));
+                            }
+                            message.actuate(pdm, pdm_channels, offset, position).await;
+                            metrics::actuation(pdm_key);
+                            actuated = true;
+                        }
+                    }
+                    if actuated {
+                        let latency = LatencyRecord::new(message.received_at, *priority, self.clock.now())
+                            .with_position(position);
+                        if self.log_latency {
+                            self.log.emit(
+                                self.log
+                                    .event(
+                                        LogLevel::Info,
+                                        EventCode::Latency,
+                                        serde_json::to_string(&latency)
+                                            .unwrap_or_else(|e| format!("Failed to serialise latency: {e}")),
+                                    )
+                                    .with_field("latency", &latency),
+                            );
+                        }
+                        self.latency.record(latency);
+                    }
+                    // No need for heartbeat message as we just sent the above.
+                    last_fire = Instant::now();
+                    self.last_fired_at = Some(Utc::now());
+                    if actuated {
+                        self.last_fired_position = position;
+                    }
+                    self.message_queue.pop_min();
+                }
+            }
+        }
+
+        let fired_at = Instant::now();
+        for (pdm_key, channels) in fired_on {
+            self.feedback_checks
+                .extend(channels.into_iter().map(|channel| (pdm_key, channel, fired_at)));
+        }
+        self.check_feedback();
+
+        // The PDM loss of can feature will come online when a signal has not
+        // been received within the keepalive. This last fire signal helps
+        // keep the PDM online by sending a heartbeat.
+        if last_fire.elapsed() > self.timing.heartbeat_interval() {
+            // TODO: Potentially wrap a config handshake in here to ensure the
+            // PDM has not drifted to another state.
+            for (bed_position, pdm) in &self.pdms {
+                let channels = match self.heartbeat {
+                    HeartbeatStrategy::IdleChannels => pdm.idle_channels(),
+                    HeartbeatStrategy::AllChannels => (1..=CHANNEL_COUNT).collect(),
+                };
+                if !channels.is_empty() {
+                    pdm.actuate_channels(channels, 0.0).await;
+                    metrics::heartbeat(*bed_position);
+                }
+            }
+            last_fire = Instant::now();
+        }
+        metrics::queue_depth(&self.canbus_id, self.message_queue.len());
+        last_fire
+    }
+}
+
+/// Channels a message due at a time is sent to, and the number of channels
+/// held on by the debounce. An on goes to every channel. An off only goes
+/// to channels no other accepted spray still covers, and not to those
+/// spraying again within the debounce window, which stay on through the
+/// gap and are turned off by the later spray.
+///
+/// * `message`: message due.
+/// * `time`: time the message is due.
+/// * `schedule`: accepted sprays.
+/// * `debounce`: window after an off in which spraying again keeps the
+///   channel on.
+fn channels_to_fire(
+    message: &WeedQueueMessage,
+    time: DateTime<Utc>,
+    schedule: &SpraySchedule,
+    debounce: Duration,
+) -> (Vec<(u8, u8)>, u64) {
+    if message.is_on {
+        return (message.channels.clone(), 0);
+    }
+    let mut debounced = 0;
+    let channels = message
+        .channels
+        .iter()
+        .copied()
+        .filter(|(_, channel)| !schedule.covers(*channel, time))
+        .filter(|(_, channel)| {
+            let resumes = schedule.resumes_within(*channel, time, debounce);
+            debounced += u64::from(resumes);
+            !resumes
+        })
+        .collect();
+    (channels, debounced)
+}
+
+/// What the firing task does with the message at the front of the queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QueueAction {
+    /// The message is not due yet.
+    Wait,
+    /// The message is within the spray bound of its time, or behind it by
+    /// less than the late grace.
+    Fire,
+    /// The message is further behind its time than the late grace.
+    Discard,
+}
+
+/// Decide what to do with the message at the front of the queue.
+///
+/// * `time_to_fire`: when the message is due.
+/// * `now`: current time.
+/// * `timing`: spray bound and late grace.
+fn queue_action(time_to_fire: DateTime<Utc>, now: DateTime<Utc>, timing: &PowerTiming) -> QueueAction {
+    if time_to_fire < now - timing.late_grace() {
+        QueueAction::Discard
+    } else if time_to_fire - now < timing.spray_bound() {
+        QueueAction::Fire
+    } else {
+        QueueAction::Wait
+    }
+}
+
+/// Spray times of a weed message if it is to be accepted. Sprays starting
+/// behind now by less than the late grace start now instead, keeping their
+/// duration, those further behind are too late.
+///
+/// * `start`: when the spray was due to start.
+/// * `end`: when the spray was due to end.
+/// * `now`: time the message was received.
+/// * `late_grace`: time a spray can be behind and still be accepted.
+fn clamp_to_grace(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    now: DateTime<Utc>,
+    late_grace: Duration,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    if start > now {
+        Some((start, end))
+    } else if start > now - late_grace {
+        Some((now, end + (now - start)))
+    } else {
+        None
+    }
+}
+
+/// Time a distance ahead of now takes to reach at a different ground speed,
+/// times already passed are kept.
+///
+/// * `time`: time computed for the old speed.
+/// * `now`: time the distance is measured from.
+/// * `ratio`: old speed over the new speed.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn retime(time: DateTime<Utc>, now: DateTime<Utc>, ratio: f64) -> DateTime<Utc> {
+    match (time - now).num_nanoseconds() {
+        Some(ahead) if ahead > 0 => now + Duration::nanoseconds((ahead as f64 * ratio) as i64),
+        _ => time,
+    }
+}
+
+/// Whether the component has been asked to stop, which includes the
+/// handle having been dropped.
+///
+/// * `stop_rx`: stop signal from the handle.
+fn stopping(stop_rx: &watch::Receiver<bool>) -> bool {
+    *stop_rx.borrow() || stop_rx.has_changed().is_err()
+}
+
+/// Running crop bed power component, used to shut it down in order.
+/// Dropping the handle stops the tasks without turning the channels off,
+/// leaving them to the PDM loss of CAN cutoff, so prefer
+/// [`CropBedPowerHandle::shutdown`].
+pub struct CropBedPowerHandle {
+    /// Component shared with the tasks.
+    power: Arc<Mutex<CropBedPower>>,
+    /// Set to stop accepting messages and firing, shared with the task
+    /// stopping the component on a shutdown requested on the bus.
+    stop_tx: Arc<watch::Sender<bool>>,
+    /// Tasks accepting connections and datagrams from the AI system and
+    /// firing the message queue, until they stop.
+    tasks: Vec<NamedTask>,
+    /// Tasks following the PDM status and the ground speed.
+    monitors: Vec<JoinHandle<()>>,
+    /// Task writing the journal, if journalled.
+    journal_writer: Option<JoinHandle<()>>,
+    /// Set once an emergency stop has turned every channel off.
+    emergency_rx: watch::Receiver<bool>,
+}
+
+impl CropBedPowerHandle {
+    /// Ask the component to stop, without waiting for it.
+    pub fn request_stop(&self) {
+        self.stop_tx.send_replace(true);
+    }
+
+    /// Component shared with the tasks, for status queries.
+    pub fn component(&self) -> Arc<Mutex<CropBedPower>> {
+        self.power.clone()
+    }
+
+    /// Snapshot of the running component.
+    pub async fn snapshot(&self) -> CropBedPowerSnapshot {
+        self.power.lock().await.snapshot()
+    }
+
+    /// Wait for a listener or the firing task to stop without being asked
+    /// to, i.e. it panicked or the PDM configuration drifted, returning
+    /// its name or how it panicked. Call [`CropBedPowerHandle::shutdown`]
+    /// afterwards to stop the rest.
+    pub async fn stopped(&mut self) -> Result<&'static str, JoinError> {
+        let (name, result) = first_finished(&mut self.tasks).await;
+        result.map(|()| name)
+    }
+
+    /// Wait for an emergency stop, which has turned every channel off and
+    /// stopped the tasks by the time this returns. Never returns otherwise.
+    pub async fn emergency_stopped(&mut self) {
+        while !*self.emergency_rx.borrow_and_update() {
+            if self.emergency_rx.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Stop the component in the mode of its config, immediately after an
+    /// emergency stop, see [`CropBedPowerHandle::shutdown_with`].
+    pub async fn shutdown(self) -> CropBedPowerStatus {
+        let mode = {
+            let gaurd = self.power.lock().await;
+            if gaurd.emergency_stopped() {
+                ShutdownMode::Immediate
+            } else {
+                gaurd.shutdown.mode
+            }
+        };
+        self.shutdown_with(mode).await
+    }
+
+    /// Stop the component in order: stop accepting connections, let a
+    /// message being sent finish, drain or discard the rest of the queue
+    /// and turn every channel on every PDM off before returning the final
+    /// status, counting the tasks that panicked on the way. Draining fires
+    /// the sprays due within the horizon of the config first, for weeds
+    /// already passed under the cameras.
+    ///
+    /// * `mode`: whether the queue is drained or discarded.
+    pub async fn shutdown_with(self, mode: ShutdownMode) -> CropBedPowerStatus {
+        self.request_stop();
+        let (log, health) = {
+            let gaurd = self.power.lock().await;
+            (gaurd.log.clone(), gaurd.health.clone())
+        };
+        health.set_listening(false);
+        let mut tasks_failed = 0;
+        for (name, task) in self.tasks {
+            if let Err(e) = task.await {
+                log.error(EventCode::TaskFailed, format!("Crop bed power {name} did not stop cleanly: {e}"));
+                tasks_failed += 1;
+            }
+        }
+        if mode == ShutdownMode::Drain {
+            let mut gaurd = self.power.lock().await;
+            let horizon = gaurd.shutdown.drain_horizon();
+            let queued = gaurd.message_queue.len();
+            let dropped = gaurd.drain(horizon).await;
+            log.info(
+                EventCode::ShutDown,
+                format!(
+                    "Drained {} queued messages due within {}ms, dropped {dropped} after it",
+                    queued - dropped,
+                    horizon.as_millis()
+                ),
+            );
+        }
+        for monitor in &self.monitors {
+            monitor.abort();
+        }
+        // Dropping the journal lets the writer finish the entries still
+        // queued before the component is reported as shut down.
+        drop(self.power.lock().await.journal.take());
+        if let Some(journal_writer) = self.journal_writer {
+            if let Err(e) = journal_writer.await {
+                log.error(EventCode::TaskFailed, format!("Crop bed power journal did not stop cleanly: {e}"));
+                tasks_failed += 1;
+            }
+        }
+        let mut gaurd = self.power.lock().await;
+        gaurd.turn_all_off().await;
+        log.info(EventCode::ShutDown, format!("Crop bed power on {} shut down", gaurd.canbus_id));
+        CropBedPowerStatus {
+            tasks_failed,
+            ..gaurd.status()
+        }
+    }
+
+    /// Stop the component as [`CropBedPowerHandle::shutdown_with`] does,
+    /// in the mode of its config when not given, giving up on it after a
+    /// timeout. A shutdown given up on stops every task and still turns
+    /// every channel off before returning `None`, so no solenoid is left
+    /// open while the binary exits.
+    ///
+    /// * `mode`: whether the queue is drained or discarded.
+    /// * `timeout`: time the shutdown is waited for.
+    pub async fn shutdown_within(
+        self,
+        mode: Option<ShutdownMode>,
+        timeout: std::time::Duration,
+    ) -> Option<CropBedPowerStatus> {
+        let power = self.power.clone();
+        let tasks: Vec<_> = self
+            .tasks
+            .iter()
+            .map(|(_, task)| task.abort_handle())
+            .chain(self.monitors.iter().map(JoinHandle::abort_handle))
+            .collect();
+        let shutdown = async move {
+            match mode {
+                Some(mode) => self.shutdown_with(mode).await,
+                None => self.shutdown().await,
+            }
+        };
+        if let Ok(status) = tokio::time::timeout(timeout, shutdown).await {
+            return Some(status);
+        }
+        // Dropping the shutdown released the lock if it was draining, the
+        // tasks are stopped so none takes it back to fire.
+        for task in tasks {
+            task.abort();
+        }
+        let mut gaurd = power.lock().await;
+        gaurd.message_queue.clear();
+        gaurd.turn_all_off().await;
+        gaurd.log.error(
+            EventCode::ShutDown,
+            format!(
+                "Crop bed power on {} did not shut down within {}ms, turned every channel off",
+                gaurd.canbus_id,
+                timeout.as_millis()
+            ),
+        );
+        None
+    }
+}
+
+/// Unit struct for adding controlling behaviour to the crop bed power.
+pub struct CropBedPowerController;
+
+impl CropBedPowerController {
+    /// Start the crop bed power component, returning once it is listening
+    /// with a handle used to shut it down. Refuses to start when the canbus
+    /// or a PDM cannot be reached, or a port cannot be bound.
+    ///
+    /// * `crop_bed_power`: component
+    pub async fn start(mut crop_bed_power: CropBedPower) -> Result<CropBedPowerHandle, ComponentError> {
+        let interface = open_interface(&crop_bed_power.canbus_id).map_err(|source| ComponentError::Canbus {
+            canbus_id: crop_bed_power.canbus_id.clone(),
+            source,
+        })?;
+        crop_bed_power.health.set_canbus_open(true);
+
+        // An unconfigured PDM only shows up as outputs not firing in the
+        // field, so refuse to start rather than run without one.
+        for (bed_position, pdm) in &mut crop_bed_power.pdms {
+            if let Err(source) = pdm.initialise(interface.clone()).await {
+                return Err(ComponentError::Pdm {
+                    address: pdm.address(),
+                    bed_position: *bed_position,
+                    canbus_id: crop_bed_power.canbus_id.clone(),
+                    source,
+                });
+            }
+        }
+        if !crop_bed_power.verify_pdms().await {
+            return Err(ComponentError::PdmMismatch {
+                canbus_id: crop_bed_power.canbus_id.clone(),
+            });
+        }
+        // Feedback and status are used for diagnostics and recovery, so
+        // spraying carries on without them.
+        let mut status_receivers = Vec::new();
+        for (bed_position, pdm) in &mut crop_bed_power.pdms {
+            let log = &crop_bed_power.log;
+            if let Err(e) = pdm.subscribe_feedback(&crop_bed_power.canbus_id) {
+                log.warn(
+                    EventCode::Unavailable,
+                    format!("No current feedback from PDM {}: {e}", pdm.address()),
+                );
+            }
+            match pdm.watch_status(&crop_bed_power.canbus_id) {
+                Ok(status_rx) => status_receivers.push((*bed_position, status_rx)),
+                Err(e) => log.warn(EventCode::Unavailable, format!("No fault status from PDM {}: {e}", pdm.address())),
+            }
+        }
+        // Bind on the loop back port from within the container
+        let listener = if crop_bed_power.transport.tcp() {
+            Some(
+                TcpListener::bind(format!("0.0.0.0:{}", crop_bed_power.port))
+                    .await
+                    .map_err(ComponentError::io(format!(
+                        "Failed to bind port {}",
+                        crop_bed_power.port
+                    )))?,
+            )
+        } else {
+            None
+        };
+        let datagram_socket = if crop_bed_power.transport.udp() {
+            Some(
+                UdpSocket::bind(format!("0.0.0.0:{}", crop_bed_power.port))
+                    .await
+                    .map_err(ComponentError::io(format!(
+                        "Failed to bind datagram port {}",
+                        crop_bed_power.port
+                    )))?,
+            )
+        } else {
+            None
+        };
+
+        let journal_writer = crop_bed_power.journal_path.clone().map(|journal_path| {
+            let (journal, journal_writer) = Journal::start(journal_path, crop_bed_power.journal_max_bytes);
+            crop_bed_power.journal = Some(journal);
+            journal_writer
+        });
+        let wheel_speed = crop_bed_power.wheel_speed.clone();
+        let speed_sensor = crop_bed_power.speed_sensor.take();
+        let status_port = crop_bed_power.status_port;
+        let reloadable = crop_bed_power.config_file.is_some();
+        let heartbeat_emitter = crop_bed_power.heartbeat_emitter.clone();
+        let telemetry = crop_bed_power.telemetry.clone();
+        let log = crop_bed_power.log.clone();
+        let health = crop_bed_power.health.clone();
+        health.set_listening(true);
+        // Tasks do not inherit the span they are spawned in, each is given
+        // the span of the component.
+        let span = log.span();
+        let bus = crop_bed_power.bus.clone();
+        let emergency_rx = crop_bed_power.emergency_stop.subscribe();
+        let thread_safe_crop_bed_power = Arc::new(Mutex::new(crop_bed_power));
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let stop_tx = Arc::new(stop_tx);
+        let mut monitors = Vec::new();
+
+        // An emergency stop has already turned every channel off, the
+        // tasks are stopped so nothing more is accepted.
+        let emergency_stop = stop_tx.clone();
+        let mut emergency_stop_rx = emergency_rx.clone();
+        monitors.push(tokio::spawn(async move {
+            if emergency_stop_rx.wait_for(|stopped| *stopped).await.is_ok() {
+                emergency_stop.send_replace(true);
+            }
+        }));
+
+        if let Some(bus) = bus {
+            let bus_stop = stop_tx.clone();
+            monitors.push(bus.on_shutdown("crop bed power", move || {
+                bus_stop.send_replace(true);
+            }));
+        }
+
+        if let Some(status_port) = status_port {
+            monitors.extend(spawn_status_server(
+                status_port,
+                thread_safe_crop_bed_power.clone(),
+                stop_rx.clone(),
+                log.clone(),
+            ));
+        }
+
+        // Without a sensor the component sprays at the times the AI sent,
+        // which assume a constant ground speed.
+        let speed_sensor = match (speed_sensor, wheel_speed) {
+            (Some(speed_sensor), _) => Some(speed_sensor),
+            (None, Some(wheel_speed)) => match wheel_speed.open() {
+                Ok(source) => Some(WheelSpeedSensor::start(source)),
+                Err(e) => {
+                    log.warn(
+                        EventCode::GroundSpeed,
+                        format!("No wheel speed sensor, spraying at the assumed speed: {e}"),
+                    );
+                    None
+                }
+            },
+            (None, None) => None,
+        };
+        if let Some(speed_sensor) = speed_sensor {
+            let power_speed = thread_safe_crop_bed_power.clone();
+            monitors.push(tokio::spawn(
+                async move {
+                    follow_ground_speed(speed_sensor, power_speed).await;
+                }
+                .instrument(span.clone()),
+            ));
+        }
+
+        if reloadable {
+            monitors.push(tokio::spawn(
+                reload::reload_on_hangup(thread_safe_crop_bed_power.clone()).instrument(span.clone()),
+            ));
+        }
+
+        if let Some(heartbeat_emitter) = heartbeat_emitter {
+            let power_heartbeat = thread_safe_crop_bed_power.clone();
+            monitors.push(tokio::spawn(
+                emit_heartbeats(heartbeat_emitter, move || {
+                    let power_heartbeat = power_heartbeat.clone();
+                    async move { Some(power_heartbeat.lock().await.heartbeat()) }
+                })
+                .instrument(span.clone()),
+            ));
+        }
+
+        // Snapshots are taken with the component locked and handed to the
+        // emitter, which serialises and ships them with it unlocked.
+        if let Some(telemetry) = telemetry {
+            let emitter = TelemetryEmitter::new(telemetry);
+            let mut interval = tokio::time::interval(emitter.config().interval());
+            let power_telemetry = thread_safe_crop_bed_power.clone();
+            let feeder = emitter.clone();
+            monitors.push(tokio::spawn(async move {
+                loop {
+                    interval.tick().await;
+                    let (spray, pdm) = {
+                        let gaurd = power_telemetry.lock().await;
+                        (gaurd.spray_telemetry(), gaurd.pdm_telemetry())
+                    };
+                    feeder.feed(spray);
+                    feeder.feed(pdm);
+                }
+            }));
+            monitors.push(tokio::spawn(emitter.ship().instrument(span.clone())));
+        }
+
+        for (bed_position, status_rx) in status_receivers {
+            let power_status = thread_safe_crop_bed_power.clone();
+            monitors.push(tokio::spawn(
+                health
+                    .clone()
+                    .supervise("PDM status", async move {
+                        handle_pdm_status(bed_position, status_rx, power_status).await;
+                    })
+                    .instrument(span.clone()),
+            ));
+        }
+
+        let power_processing = thread_safe_crop_bed_power.clone();
+
+        // PDM message firing task. When a PDM drifts and the policy is to
+        // refuse, the task stops which also stops the heartbeat, so the PDM
+        // loss of can feature turns every output off. Between messages the
+        // task sleeps with the component unlocked, a new message due sooner
+        // wakes it early, and a stop ends it between messages.
+        let mut firing_stop = stop_rx.clone();
+        let firing = tokio::spawn(
+            health
+                .clone()
+                .supervise("firing task", async move {
+                    let mut last_fire = Instant::now();
+                    let mut last_verified = Instant::now();
+                    let mut last_status = Instant::now();
+                    while !stopping(&firing_stop) {
+                        let mut gaurd = power_processing.lock().await;
+                        if gaurd.emergency_stopped() {
+                            break;
+                        }
+                        last_fire = gaurd.process_message_queue(last_fire).await;
+                        gaurd.firing_progress.tick();
+                        if last_status.elapsed() > STATUS_LOG_INTERVAL {
+                            let status = gaurd.status();
+                            gaurd.log.emit(
+                                gaurd
+                                    .log
+                                    .event(LogLevel::Info, EventCode::Status, status.to_string())
+                                    .with_field("status", &status),
+                            );
+                            last_status = Instant::now();
+                        }
+                        let mut wake = gaurd.next_wake(last_fire).min(last_status + STATUS_LOG_INTERVAL);
+                        if let Some(interval) = gaurd.pdm_verification.interval() {
+                            if last_verified.elapsed() > interval {
+                                if !gaurd.verify_pdms().await {
+                                    gaurd.log.error(
+                                        EventCode::PdmDrifted,
+                                        format!(
+                                            "PDM configuration has drifted, no longer firing on {}",
+                                            gaurd.canbus_id
+                                        ),
+                                    );
+                                    break;
+                                }
+                                last_verified = Instant::now();
+                            }
+                            wake = wake.min(last_verified + interval);
+                        }
+                        let queue_changed = gaurd.queue_changed.clone();
+                        drop(gaurd);
+
+                        if wake <= Instant::now() {
+                            // Inside the timer slack of a message, let the connection
+                            // tasks in and go round again.
+                            tokio::task::yield_now().await;
+                        } else {
+                            tokio::select! {
+                                () = tokio::time::sleep_until(wake) => {}
+                                () = queue_changed.notified() => {}
+                                _ = firing_stop.changed() => {}
+                            }
+                        }
+                    }
+                })
+                .instrument(span.clone()),
+        );
+        let mut tasks = Vec::new();
+        // Looping message parsing task, the listener is closed once it stops
+        // so new connections are refused.
+        if let Some(listener) = listener {
+            let mut listener_stop = stop_rx.clone();
+            let listener_power = thread_safe_crop_bed_power.clone();
+            tasks.push((
+                "listener",
+                tokio::spawn(
+                    health
+                        .clone()
+                        .supervise("listener", async move {
+                            while !stopping(&listener_stop) {
+                                tokio::select! {
+                                    accepted = listener.accept() => {
+                                        if let Ok((socket, _)) = accepted {
+                                            let power_connection = listener_power.clone();
+                                            tokio::spawn(
+                                                async move {
+                                                    handle_connection(socket, power_connection).await;
+                                                }
+                                                .in_current_span(),
+                                            );
+                                        }
+                                    }
+                                    _ = listener_stop.changed() => {}
+                                }
+                            }
+                        })
+                        .instrument(span.clone()),
+                ),
+            ));
+        }
+        if let Some(socket) = datagram_socket {
+            tasks.push((
+                "datagram listener",
+                tokio::spawn(
+                    health
+                        .supervise(
+                            "datagram listener",
+                            udp::serve_datagrams(socket, thread_safe_crop_bed_power.clone(), stop_rx),
+                        )
+                        .instrument(span),
+                ),
+            ));
+        }
+        tasks.push(("firing task", firing));
+
+        Ok(CropBedPowerHandle {
+            power: thread_safe_crop_bed_power,
+            stop_tx,
+            tasks,
+            monitors,
+            journal_writer,
+            emergency_rx,
+        })
+    }
+}
+
+impl ComponentHandle for CropBedPowerHandle {
+    type Status = CropBedPowerStatus;
+
+    fn request_stop(&self) {
+        CropBedPowerHandle::request_stop(self);
+    }
+
+    async fn shutdown(self) -> CropBedPowerStatus {
+        CropBedPowerHandle::shutdown(self).await
+    }
+}
+
+impl ComponentController for CropBedPowerController {
+    type Config = CropBedPowerConfig;
+    type Component = CropBedPower;
+    type Handle = CropBedPowerHandle;
+
+    fn build(config: CropBedPowerConfig) -> Result<CropBedPower, ComponentError> {
+        CropBedPower::new(config)
+    }
+
+    // The file is kept so the PDM configs can be reloaded from it.
+    fn from_file(path: &Path) -> Result<CropBedPower, ComponentError> {
+        let mut component = CropBedPower::new(CropBedPowerConfig::load_validated(path)?)?;
+        component.config_file = Some(path.to_path_buf());
+        Ok(component)
+    }
+
+    async fn start(component: CropBedPower) -> Result<CropBedPowerHandle, ComponentError> {
+        CropBedPowerController::start(component).await
+    }
+}
+
+/// React to faults reported by a PDM until it stops being monitored. A
+/// tripped channel raises an alarm, loss of CAN means the PDM has turned its
+/// outputs off and may have reset, so the configuration is sent again. The
+/// PDM is not ready while it reports a fault, and the canbus is taken to
+/// have failed once the monitor stops reading it.
+///
+/// * `bed_position`: key of the PDM in the component.
+/// * `status_rx`: fault state of the PDM.
+/// * `power`: component
+async fn handle_pdm_status(
+    bed_position: u8,
+    mut status_rx: watch::Receiver<PdmStatus>,
+    power: Arc<Mutex<CropBedPower>>,
+) {
+    while status_rx.changed().await.is_ok() {
+        let status = status_rx.borrow_and_update().clone();
+        let mut gaurd = power.lock().await;
+        let Some(pdm) = gaurd.pdms.get(&bed_position) else {
+            break;
+        };
+        gaurd.health.set_pdm_ready(bed_position, !status.has_fault());
+        for channel in status.tripped_channels() {
+            metrics::pdm_fault(bed_position, "tripped_channel");
+            gaurd.log.emit(
+                gaurd
+                    .log
+                    .event(
+                        LogLevel::Error,
+                        EventCode::PdmFault,
+                        format!(
+                            "ALARM: channel {} on PDM {} at bed position {} has tripped",
+                            channel,
+                            pdm.address(),
+                            bed_position
+                        ),
+                    )
+                    .with_field("channel", channel)
+                    .with_field("pdm", pdm.address()),
+            );
+        }
+        if status.module_over_temperature {
+            metrics::pdm_fault(bed_position, "over_temperature");
+            gaurd.log.emit(
+                gaurd
+                    .log
+                    .event(
+                        LogLevel::Error,
+                        EventCode::PdmFault,
+                        format!(
+                            "ALARM: PDM {} at bed position {} is over temperature",
+                            pdm.address(),
+                            bed_position
+                        ),
+                    )
+                    .with_field("pdm", pdm.address()),
+            );
+        }
+        if let Some(bus) = gaurd.bus.as_ref().filter(|_| status.has_fault()) {
+            bus.publish(BusEvent::PdmFault {
+                crop_bed_id: gaurd.crop_bed_id,
+                address: pdm.address().raw(),
+                status: status.clone(),
+            });
+        }
+        if status.loss_of_can {
+            metrics::pdm_fault(bed_position, "loss_of_can");
+            gaurd.reinitialise_pdm(bed_position, "loss of CAN").await;
+        }
+    }
+    let gaurd = power.lock().await;
+    gaurd.health.set_canbus_open(false);
+    gaurd.log.error(
+        EventCode::Unavailable,
+        format!(
+            "No longer hearing the PDM at bed position {bed_position} on {}",
+            gaurd.canbus_id
+        ),
+    );
+}
+
+/// Serve the component status until it stops.
+///
+/// * `status_port`: port the status server listens on.
+/// * `power`: component
+/// * `stop_rx`: stop signal from the handle.
+/// * `log`: events of the component.
+#[cfg(feature = "http")]
+fn spawn_status_server(
+    status_port: i32,
+    power: Arc<Mutex<CropBedPower>>,
+    stop_rx: watch::Receiver<bool>,
+    log: LogEmitter,
+) -> Option<JoinHandle<()>> {
+    // Status is for diagnostics, so spraying carries on without it.
+    let listener = match std::net::TcpListener::bind(format!("0.0.0.0:{status_port}")) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log.warn(EventCode::Unavailable, format!("No status server on port {status_port}: {e}"));
+            return None;
+        }
+    };
+    Some(tokio::spawn(async move {
+        if let Err(e) = http::serve(listener, power, stop_rx).await {
+            log.error(EventCode::TaskFailed, format!("Status server on port {status_port} stopped: {e}"));
+        }
+    }))
+}
+
+/// Serve the component status until it stops, not available without the
+/// `http` feature.
+///
+/// * `status_port`: port the status server would listen on.
+/// * `_power`: component
+/// * `_stop_rx`: stop signal from the handle.
+/// * `log`: events of the component.
+#[cfg(not(feature = "http"))]
+fn spawn_status_server(
+    status_port: i32,
+    _power: Arc<Mutex<CropBedPower>>,
+    _stop_rx: watch::Receiver<bool>,
+    log: LogEmitter,
+) -> Option<JoinHandle<()>> {
+    log.warn(
+        EventCode::Unavailable,
+        format!("Status port {status_port} is set but onyx was built without the http feature"),
+    );
+    None
+}
+
+/// Shift the queued messages each time the ground speed changes, until
+/// the sensor stops.
+///
+/// * `sensor`: wheel speed sensor.
+/// * `power`: component
+async fn follow_ground_speed(sensor: WheelSpeedSensor, power: Arc<Mutex<CropBedPower>>) {
+    let mut speed_rx = sensor.subscribe();
+    while speed_rx.changed().await.is_ok() {
+        let speed = *speed_rx.borrow_and_update();
+        let mut gaurd = power.lock().await;
+        let now = gaurd.clock.now();
+        gaurd.update_ground_speed(speed, now);
+        if let Some(bus) = &gaurd.bus {
+            bus.publish(BusEvent::GroundSpeed(speed));
+        }
+        if speed.is_none() {
+            gaurd
+                .log
+                .warn(EventCode::GroundSpeed, "Lost the wheel speed, spraying at the last speed followed");
+            break;
+        }
+    }
+}
+
+/// Handle connection from the AI container, answering each message it sends
+/// in the encoding of the component until it closes the connection or goes
+/// quiet for the idle timeout.
+///
+/// * `socket`: `TcpStream`
+/// * `power`: component
+// NOTE: This interface was the issue that wasted ~ 2 weeks during testing, the previous
+//       implementation relied on a long standing connection from another container and
+//       taking messages off the wire at '\b', however the starmap from the AI system
+//       created a new connection every time it sent a message, this lead to an
+//       enormous amount of useless tokio tasks that would be looped and polled. Both
+//       styles are served now, the idle timeout keeps dead connections from leaking tasks.
+async fn handle_connection(mut socket: TcpStream, power: Arc<Mutex<CropBedPower>>) {
+    let (timing, clock, uuid, encoding, framing, log) = {
+        let gaurd = power.lock().await;
+        (
+            gaurd.timing,
+            gaurd.clock,
+            gaurd.uuid,
+            gaurd.encoding,
+            gaurd.framing,
+            gaurd.log.clone(),
+        )
+    };
+    let mut codec = FramedCodec::new(framing);
+    let idle_timeout = timing.connection_idle_timeout();
+    let (read_stream, mut write_stream) = socket.split();
+    let mut read_stream = BufReader::new(read_stream);
+    let mut data = Vec::new();
+
+    loop {
+        data.clear();
+        match tokio::time::timeout(idle_timeout, codec.read_frame(&mut read_stream, &mut data)).await {
+            Ok(Ok(FrameRead::Closed)) => break,
+            Ok(Ok(FrameRead::CutShort)) if codec.framing() != Framing::Lines => {
+                log.warn(
+                    EventCode::ConnectionClosed,
+                    format!(
+                        "Analysis system closed the connection mid-frame, dropping {} bytes",
+                        data.len()
+                    ),
+                );
+                break;
+            }
+            // A last line without its new line is still handled.
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => {
+                log.warn(EventCode::ConnectionFailed, format!("Failed to read from the analysis system: {e}"));
+                break;
+            }
+            Err(_) => {
+                log.info(EventCode::ConnectionClosed, "Closing idle connection from the analysis system");
+                break;
+            }
+        }
+        if encoding.is_blank(&data) {
+            continue;
+        }
+        // Binary frames are handled as the json line they carry, one that is
+        // not a document is passed on as is to be answered as malformed.
+        let line = encoding.to_json(&data).unwrap_or_else(|e| {
+            log.warn(EventCode::MessageMalformed, format!("Received a malformed frame, {e}"));
+            Cow::Borrowed(&data[..])
+        });
+        let response = handle_line(&line, clock.now(), &timing, &power).await;
+        // The sender may not wait for the response, so failing to write it
+        // only matters for the log.
+        if let Err(e) = codec
+            .write_frame(&mut write_stream, &encoding.encode(&response.to_control(uuid)))
+            .await
+        {
+            log.warn(EventCode::ConnectionFailed, format!("Failed to respond to the analysis system: {e}"));
+            break;
+        }
+    }
+}
+
+/// Queue or act on a single line from the AI container and build the
+/// response to it.
+///
+/// * `data`: line received, a weed or control message or the heartbeat of
+///   another component.
+/// * `received_at`: when the line was received.
+/// * `timing`: timing of the component, for the late grace.
+/// * `power`: component
+async fn handle_line(
+    data: &[u8],
+    received_at: DateTime<Utc>,
+    timing: &PowerTiming,
+    power: &Arc<Mutex<CropBedPower>>,
+) -> WeedMessageResponse {
+    let (log, clock) = {
+        let gaurd = power.lock().await;
+        (gaurd.log.clone(), gaurd.clock)
+    };
+    // Heartbeats and control messages are journaled but not counted, the
+    // counts are of the weed messages and manual sprays.
+    let mut counted = true;
+    let response = match serde_json::from_slice::<Incoming<WeedMessage>>(data) {
+        Ok(incoming) => {
+            incoming.warn_clock_skew(received_at);
+            // The envelope id stands in for a message id, so retries are
+            // dropped and the response can be matched to the message.
+            let envelope_id = incoming.id();
+            let mut message = incoming.into_payload();
+            if message.message_id.is_none() {
+                message.message_id = envelope_id.map(|id| id.to_string());
+            }
+            let message_id = message.message_id.clone();
+            let validated = message.validate(&power.lock().await.message_constraints);
+            let key = MessageKey::of(&message);
+            if let Err(rejection) = validated {
+                log.emit(
+                    log.event(
+                        LogLevel::Warn,
+                        EventCode::MessageRejected,
+                        format!("Message rejected, {rejection}"),
+                    )
+                    .with_field("message_id", &message_id)
+                    .with_field("rejection", rejection),
+                );
+                power.lock().await.message_counts.invalid.record(rejection);
+                WeedMessageResponse::invalid(message_id, rejection)
+            } else if power.lock().await.recent_messages.seen(key.clone(), received_at) {
+                log.info(
+                    EventCode::MessageDuplicate,
+                    format!("Message dropped, a retry of {key:?} already received"),
+                );
+                WeedMessageResponse::new(WeedMessageStatus::Duplicate, message_id, 0)
+            } else {
+                // The spray times are read off the clock of the AI system, they
+                // are brought onto the monotonic clock once here.
+                let wall_now = Utc::now();
+                message.start_spray_time = clock.to_clock(message.start_spray_time, wall_now);
+                message.end_spray_time = clock.to_clock(message.end_spray_time, wall_now);
+                let accepted = clamp_to_grace(
+                    message.start_spray_time,
+                    message.end_spray_time,
+                    clock.now(),
+                    timing.late_grace(),
+                );
+                if let Some((start_spray_time, end_spray_time)) = accepted {
+                    let mut gaurd = power.lock().await;
+                    if start_spray_time != message.start_spray_time {
+                        log.warn(
+                            EventCode::Clamped,
+                            format!(
+                                "Message recieved {} after its spray was due, spraying from now",
+                                start_spray_time - message.start_spray_time
+                            ),
+                        );
+                        gaurd.message_counts.clamped += 1;
+                        message.start_spray_time = start_spray_time;
+                        message.end_spray_time = end_spray_time;
+                    }
+                    // Sprays refused for being too long have failed validation,
+                    // so any longer spray left is clamped.
+                    let duration = message.end_spray_time - message.start_spray_time;
+                    let max_spray_duration = gaurd.max_spray_duration;
+                    let status = if duration > max_spray_duration {
+                        log.warn(
+                            EventCode::Clamped,
+                            format!("Spray of {duration} is longer than the maximum {max_spray_duration}, clamping it"),
+                        );
+                        message.end_spray_time = message.start_spray_time + max_spray_duration;
+                        WeedMessageStatus::Truncated
+                    } else {
+                        WeedMessageStatus::Accepted
+                    };
+                    let (start_spray_time, end_spray_time, timed_for) = gaurd.timed_spray(&message, clock.now());
+                    let channels =
+                        gaurd.route_channels(message.channels_to_open.iter().map(|channel| channel + 1));
+                    let queued_actions = gaurd.queue_spray(
+                        channels,
+                        start_spray_time,
+                        end_spray_time,
+                        message.intensity,
+                        timed_for,
+                        false,
+                        received_at,
+                        message.position(),
+                    );
+                    // Make sure to drop the guard strait after using in the loop.
+                    drop(gaurd);
+                    WeedMessageResponse::new(status, message_id, queued_actions)
+                } else {
+                    log.warn(EventCode::MessageLate, "Message Ignored, recieved to late from analysis system");
+                    WeedMessageResponse::new(WeedMessageStatus::Late, message_id, 0)
+                }
+            }
+        }
+        Err(e) => {
+            if let Ok(heartbeat) = serde_json::from_slice::<Incoming<Heartbeat>>(data) {
+                power.lock().await.peers.record(heartbeat.into_payload(), received_at);
+                counted = false;
+                WeedMessageResponse::new(WeedMessageStatus::Accepted, None, 0)
+            } else if let Ok(manual) = serde_json::from_slice::<Incoming<ManualSprayMessage>>(data) {
+                manual.warn_clock_skew(received_at);
+                handle_manual_spray(manual.into_payload(), received_at, power).await
+            } else if let Ok(control) = serde_json::from_slice::<Incoming<PdmControlMessage>>(data) {
+                control.warn_clock_skew(received_at);
+                counted = false;
+                handle_control_message(control.into_payload(), received_at, power).await
+            } else {
+                log.warn(
+                    EventCode::MessageMalformed,
+                    format!("Received a malformed request {:?}, data: {:?}", e, data),
+                );
+                WeedMessageResponse::malformed(data)
+            }
+        }
+    };
+    let mut gaurd = power.lock().await;
+    if counted {
+        gaurd.message_counts.record(response.status);
+    }
+    if let Some(journal) = &mut gaurd.journal {
+        journal.record(JournalEntry::new(received_at, response.status, data));
+    }
+    response
+}
+
+/// Queue a manual spray from the operator to start straight away, unless
+/// the interlock forbids manual sprays outside a dry run, it opens a
+/// channel not wired to a PDM or it is too long.
+///
+/// * `message`: parsed manual spray.
+/// * `received_at`: when the message was received.
+/// * `power`: component
+async fn handle_manual_spray(
+    message: ManualSprayMessage,
+    received_at: DateTime<Utc>,
+    power: &Mutex<CropBedPower>,
+) -> WeedMessageResponse {
+    let mut gaurd = power.lock().await;
+    // A dry run fires nothing, so commissioning can check a manual spray is
+    // routed without lifting the interlock.
+    if !gaurd.allow_manual_spray && !gaurd.dry_run {
+        gaurd.log.warn(
+            EventCode::MessageRejected,
+            format!("Manual spray of channels {:?} refused, manual sprays are not allowed", message.channels),
+        );
+        return WeedMessageResponse::refused(message.message_id, "manual sprays are not allowed");
+    }
+    // Manual sprays are numbered from 1, the constraints from 0 as in the
+    // weed messages.
+    let refusal = if message.channels.is_empty() {
+        Some(String::from("no channels to open"))
+    } else {
+        message
+            .channels
+            .iter()
+            .find(|channel| {
+                !channel
+                    .checked_sub(1)
+                    .is_some_and(|channel| gaurd.message_constraints.channels.contains(&channel))
+            })
+            .map(|channel| format!("channel {channel} is not wired to a PDM"))
+    };
+    if let Some(reason) = refusal {
+        gaurd.log.warn(
+            EventCode::MessageRejected,
+            format!("Manual spray of channels {:?} refused, {reason}", message.channels),
+        );
+        return WeedMessageResponse::refused(message.message_id, reason);
+    }
+    if message.duration_ms > MAX_MANUAL_SPRAY_MS {
+        gaurd.log.warn(
+            EventCode::MessageRejected,
+            format!(
+                "Manual spray of channels {:?} refused, {}ms is longer than {MAX_MANUAL_SPRAY_MS}ms",
+                message.channels, message.duration_ms
+            ),
+        );
+        let reason = format!("{}ms is longer than {MAX_MANUAL_SPRAY_MS}ms", message.duration_ms);
+        return WeedMessageResponse::refused(message.message_id, reason);
+    }
+    gaurd.log.info(
+        EventCode::MessageReceived,
+        format!(
+            "Manual spray of channels {:?} for {}ms at {}%",
+            message.channels, message.duration_ms, message.pwm
+        ),
+    );
+    let start_spray_time = gaurd.clock.now() + Duration::milliseconds(MANUAL_SPRAY_LEAD_MS);
+    #[allow(clippy::cast_possible_wrap)]
+    let end_spray_time = start_spray_time + Duration::milliseconds(message.duration_ms as i64);
+    let channels = gaurd.route_channels(message.channels.iter().copied());
+    let queued_actions = gaurd.queue_spray(
+        channels,
+        start_spray_time,
+        end_spray_time,
+        message.pwm,
+        None,
+        true,
+        received_at,
+        None,
+    );
+    WeedMessageResponse::new(WeedMessageStatus::Accepted, message.message_id, queued_actions)
+}
+
+/// Act on a control message from the operator.
+///
+/// * `message`: parsed control message.
+/// * `received_at`: when the message was received.
+/// * `power`: component
+async fn handle_control_message(
+    message: PdmControlMessage,
+    received_at: DateTime<Utc>,
+    power: &Mutex<CropBedPower>,
+) -> WeedMessageResponse {
+    match message {
+        PdmControlMessage::Reinitialise { bed_position } => {
+            let mut gaurd = power.lock().await;
+            let bed_positions: Vec<u8> = match bed_position {
+                Some(bed_position) => vec![bed_position],
+                None => gaurd.pdms.keys().copied().collect(),
+            };
+            for bed_position in bed_positions {
+                gaurd.reinitialise_pdm(bed_position, "a reinitialise request").await;
+            }
+            WeedMessageResponse::new(WeedMessageStatus::Accepted, None, 0)
+        }
+        PdmControlMessage::TestPattern(pattern) => {
+            let mut gaurd = power.lock().await;
+            match gaurd.queue_test_pattern(&pattern, received_at) {
+                Ok(queued_actions) => {
+                    gaurd
+                        .log
+                        .info(EventCode::MessageReceived, format!("Test pattern {pattern:?} queued"));
+                    WeedMessageResponse::new(WeedMessageStatus::Accepted, None, queued_actions)
+                }
+                Err(e) => {
+                    gaurd.log.warn(EventCode::MessageRejected, format!("Test pattern refused, {e}"));
+                    WeedMessageResponse::refused(None, e.to_string())
+                }
+            }
+        }
+        PdmControlMessage::DryRun { enabled } => {
+            let mut gaurd = power.lock().await;
+            if gaurd.dry_run && !enabled {
+                let dropped = gaurd.drop_manual_ons();
+                if dropped > 0 {
+                    gaurd.log.warn(
+                        EventCode::MessageRejected,
+                        format!("Dropped {dropped} manual sprays queued during the dry run"),
+                    );
+                }
+            }
+            gaurd.dry_run = enabled;
+            let state = if enabled {
+                "started, sprays are timed without firing"
+            } else {
+                "ended, sprays fire again"
+            };
+            gaurd.log.info(EventCode::MessageReceived, format!("Dry run {state} on {}", gaurd.canbus_id));
+            WeedMessageResponse::new(WeedMessageStatus::Accepted, None, 0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{
+        control::{
+            response::{ControlResponse, ResponseStatus, MALFORMED_REASON},
+            weed::ValidationError,
+        },
+        envelope::Envelope,
+        logging::LogEvent,
+    };
+    use crate::utils::{
+        configgen::{canonical_configs, to_yaml},
+        logging::capture,
+        responses::{read_encoded_response, read_response},
+    };
+    use rstest::rstest;
+    use serial_test::serial;
+    use std::collections::BTreeSet;
+    use tokio::io::AsyncWriteExt;
+
+    #[test]
+    /// A layout wiring a crop bed channel to two PDMs is rejected when the
+    /// component is loaded.
+    fn test_reject_overlapping_channel_layout() {
+        use layout::ChannelRange;
+
+        let channel_layout = ChannelLayout::new(vec![ChannelRange::new(1, 12, 0, 0), ChannelRange::new(12, 23, 1, 11)]);
+        let Err(error) = CropBedPower::new(
+            CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None)
+                .with_channel_layout(channel_layout),
+        ) else {
+            panic!("Overlapping channel layout was accepted");
+        };
+        assert!(error.is_config());
+        assert!(
+            error.to_string().contains("Channel ranges 1-12 and 12-23 overlap"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    /// Two PDM configs strapped to the same address are rejected when the
+    /// component is loaded.
+    fn test_reject_duplicate_pdm_addresses() {
+        use crate::devices::hardware::pdm::PdmAddress;
+
+        let config_dir = std::env::temp_dir().join(format!("onyx-pdm-address-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let mut config = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None);
+        for bed_position in [0, 1] {
+            let pdm_config_file = config_dir.join(format!("pdm_{bed_position}.yaml"));
+            let file = std::fs::File::create(&pdm_config_file).unwrap();
+            serde_yaml::to_writer(file, &PdmConfig::new(PdmAddress::Pdm31, bed_position)).unwrap();
+            config = config.add_pdm_config_file(pdm_config_file, bed_position);
+        }
+        let result = CropBedPower::new(config);
+        std::fs::remove_dir_all(config_dir).unwrap();
+        let Err(error) = result else {
+            panic!("Duplicate PDM addresses were accepted");
+        };
+        assert!(error.to_string().contains("Duplicate PDM address 31"), "{error}");
+    }
+
+    #[test]
+    /// The power configs in the repository are valid, and a config with
+    /// clashing ports, channels wired to no PDM, an empty queue and a drain
+    /// outlasting the shutdown is not.
+    fn test_validate_power_config() {
+        use crate::utils::{config::validate_file, paths::repo_relative};
+
+        for name in ["0", "0_no_map", "1", "1_no_map", "2", "2_no_map"] {
+            let path = repo_relative(format!("config/components/crop_bed/actuating/power/crop_bed_power_{name}.yaml"));
+            let report = validate_file::<CropBedPowerConfig>(&path.unwrap());
+            assert!(report.is_valid(), "{report}");
+        }
+
+        let pdm_config_file = repo_relative("config/devices/crop_bed/pdm_0.yaml").unwrap();
+        let channel_map = HashMap::from([(1, (1, 0)), (2, (13, 0)), (3, (14, 1))]);
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 0, Some(channel_map))
+            .add_pdm_config_file(pdm_config_file, 0)
+            .with_status_port(0)
+            .with_journal("/missing/journal.jsonl")
+            .with_max_queue_len(0)
+            .with_shutdown(ShutdownConfig::drain(9500));
+        let mut report = ValidationReport::new("power.yaml");
+        config.validate(&mut report);
+        assert_eq!(
+            report.fields(),
+            [
+                "port",
+                "status_port",
+                "status_port",
+                "channel_map.2",
+                "channel_map.3",
+                "journal_path",
+                "max_queue_len",
+                "shutdown.drain_horizon_ms"
+            ],
+            "{report}"
+        );
+    }
+
+    /// Virtual canbus interface used by the `vcan_test` tests.
+    fn vcan_interface() -> String {
+        std::env::var("ONYX_VCAN").unwrap_or_else(|_| String::from("vcan0"))
+    }
+
+    /// Weed message as the AI system sends it.
+    ///
+    /// * `channels_to_open`: zero based channels.
+    /// * `start_spray_time`: time to start spraying.
+    /// * `end_spray_time`: time to stop spraying.
+    fn weed_message_json(
+        channels_to_open: &[u8],
+        start_spray_time: DateTime<Utc>,
+        end_spray_time: DateTime<Utc>,
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "channels_to_open": channels_to_open,
+            "start_spray_time": start_spray_time,
+            "end_spray_time": end_spray_time,
+            "message_created_at": Utc::now(),
+            "capture_time": Utc::now(),
+            "time_diff_capture_to_start_spray_no_offset": 0.0,
+            "distance_to_solenoid_mm": 0.0,
+            "cam_id": 0,
+            "crop_bed_id": 0,
+        })
+    }
+
+    /// Send a weed message to the component as the AI system does, with a
+    /// new connection per message.
+    ///
+    /// * `port`: port the component listens on.
+    /// * `channels_to_open`: zero based channels.
+    /// * `start_spray_time`: time to start spraying.
+    /// * `end_spray_time`: time to stop spraying.
+    async fn send_weed_message(
+        port: i32,
+        channels_to_open: &[u8],
+        start_spray_time: DateTime<Utc>,
+        end_spray_time: DateTime<Utc>,
+    ) {
+        let message = weed_message_json(channels_to_open, start_spray_time, end_spray_time);
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{port}"))
+            .await
+            .expect("Failed to connect to the component");
+        stream
+            .write_all(format!("{message}\n").as_bytes())
+            .await
+            .expect("Failed to send weed message");
+    }
+
+    /// Send a line to a connection handler over TCP and read its response.
+    ///
+    /// * `power`: component handling the connection.
+    /// * `line`: data sent, without the trailing new line.
+    async fn exchange(power: Arc<Mutex<CropBedPower>>, line: &str) -> ControlResponse {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            handle_connection(socket, power).await;
+        });
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(format!("{line}\n").as_bytes()).await.unwrap();
+        let response = read_response(&mut BufReader::new(stream), std::time::Duration::from_secs(1))
+            .await
+            .unwrap();
+        server.await.unwrap();
+        response
+    }
+
+    /// Response the component writes back for an outcome.
+    ///
+    /// * `power`: component responding.
+    /// * `response`: outcome of the message.
+    async fn responds(power: &Mutex<CropBedPower>, response: &WeedMessageResponse) -> ControlResponse {
+        response.to_control(power.lock().await.uuid)
+    }
+
+    /// Component without PDMs, enough to queue messages.
+    fn queue_only_power() -> Arc<Mutex<CropBedPower>> {
+        Arc::new(Mutex::new(
+            CropBedPower::new(CropBedPowerConfig::new(
+                CropBed::LeftBoom,
+                String::from("can0"),
+                17650,
+                None,
+            ))
+            .unwrap(),
+        ))
+    }
+
+    /// Channel map of crop bed 2, wired in reverse so logical channel 1 is
+    /// channel 24 on the second PDM.
+    fn bed_two_channel_map() -> HashMap<u8, (u8, u8)> {
+        (1..=24).map(|channel| (channel, (25 - channel, u8::from(channel <= 12)))).collect()
+    }
+
+    #[tokio::test]
+    /// Telemetry of the queue follows the messages accepted, and is taken
+    /// for the component it came from, with no PDMs when none are set.
+    async fn test_telemetry_snapshots() {
+        let power = queue_only_power();
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let message = weed_message_json(&[3], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        assert_eq!(exchange(power.clone(), &message.to_string()).await.status, ResponseStatus::Accepted);
+
+        let gaurd = power.lock().await;
+        let (spray, pdm) = (gaurd.spray_telemetry(), gaurd.pdm_telemetry());
+        assert_eq!((spray.queue_depth, spray.accepted, spray.late), (1, 1, 0));
+        assert_eq!(spray.last_fired_at, None);
+        assert_eq!((spray.header.component, spray.header.crop_bed_id), (gaurd.uuid, CropBed::LeftBoom));
+        assert!(pdm.pdms.is_empty());
+    }
+
+    #[tokio::test]
+    /// A dry run is started and ended by a control message, and weed
+    /// messages are still queued while it runs.
+    async fn test_toggle_dry_run() {
+        let power = queue_only_power();
+        assert!(!power.lock().await.dry_run());
+        let response = exchange(power.clone(), r#"{"dry_run": {"enabled": true}}"#).await;
+        assert_eq!(response.status, ResponseStatus::Accepted);
+        assert!(power.lock().await.dry_run());
+
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let message = weed_message_json(&[3], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        assert_eq!(exchange(power.clone(), &message.to_string()).await.status, ResponseStatus::Accepted);
+        assert_eq!(power.lock().await.message_queue.len(), 2);
+
+        exchange(power.clone(), r#"{"dry_run": {"enabled": false}}"#).await;
+        assert!(!power.lock().await.dry_run());
+    }
+
+    #[tokio::test]
+    /// Mapped channels are queued for the PDM the channel map gives, rather
+    /// than one worked out from the channel number.
+    async fn test_queue_mapped_channels_with_pdm() {
+        let power = Arc::new(Mutex::new(
+            CropBedPower::new(CropBedPowerConfig::new(
+                CropBed::RightBoom,
+                String::from("can2"),
+                17652,
+                Some(bed_two_channel_map()),
+            ))
+            .unwrap(),
+        ));
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let message = weed_message_json(&[0, 12], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        assert_eq!(exchange(power.clone(), &message.to_string()).await.status, ResponseStatus::Accepted);
+        for (message, _) in &power.lock().await.message_queue {
+            assert_eq!(message.channels, vec![(1, 24), (0, 12)]);
+        }
+    }
+
+    #[tokio::test]
+    /// Accepted messages echo their id and count the actions queued, long
+    /// sprays included, and messages without an id are still accepted.
+    async fn test_respond_accepted() {
+        let power = queue_only_power();
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let mut message = weed_message_json(&[0, 1], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        message["message_id"] = serde_json::json!("cam0-1");
+        let response = exchange(power.clone(), &message.to_string()).await;
+        let expected = WeedMessageResponse::new(WeedMessageStatus::Accepted, Some(String::from("cam0-1")), 2);
+        assert_eq!(response, responds(&power, &expected).await);
+
+        let long_start = start_spray_time + Duration::seconds(1);
+        let long = weed_message_json(&[2], long_start, long_start + Duration::milliseconds(1500));
+        let response = exchange(power.clone(), &long.to_string()).await;
+        let queued_actions = response.detail::<usize>("queued_actions").unwrap();
+        assert_eq!(response.status, ResponseStatus::Accepted);
+        assert_eq!(response.correlation_id, None);
+        assert_eq!(queued_actions + 2, power.lock().await.message_queue.len());
+        assert!(queued_actions > 2);
+    }
+
+    #[tokio::test]
+    /// Messages arriving after the spray was due to start are reported as
+    /// late and nothing is queued.
+    async fn test_respond_late() {
+        let power = queue_only_power();
+        let start_spray_time = Utc::now() - Duration::seconds(1);
+        let mut message = weed_message_json(&[0], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        message["message_id"] = serde_json::json!("cam0-2");
+        let response = exchange(power.clone(), &message.to_string()).await;
+        let expected = WeedMessageResponse::new(WeedMessageStatus::Late, Some(String::from("cam0-2")), 0);
+        assert_eq!(response, responds(&power, &expected).await);
+        assert!(power.lock().await.message_queue.is_empty());
+    }
+
+    #[tokio::test]
+    /// A retry of a message, by its id or by its channels and times when it
+    /// has none, is answered as a duplicate and queues nothing more.
+    async fn test_drop_duplicate_messages() {
+        let power = queue_only_power();
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let mut message = weed_message_json(&[0], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        message["message_id"] = serde_json::json!("cam0-1");
+        assert_eq!(exchange(power.clone(), &message.to_string()).await.status, ResponseStatus::Accepted);
+        message["end_spray_time"] = serde_json::json!(start_spray_time + Duration::milliseconds(200));
+        let response = exchange(power.clone(), &message.to_string()).await;
+        let expected = WeedMessageResponse::new(WeedMessageStatus::Duplicate, Some(String::from("cam0-1")), 0);
+        assert_eq!(response, responds(&power, &expected).await);
+
+        let start_spray_time = start_spray_time + Duration::seconds(1);
+        let message = weed_message_json(&[1], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        assert_eq!(exchange(power.clone(), &message.to_string()).await.status, ResponseStatus::Accepted);
+        assert_eq!(exchange(power.clone(), &message.to_string()).await.status, ResponseStatus::Duplicate);
+
+        let gaurd = power.lock().await;
+        assert_eq!(gaurd.message_queue.len(), 4);
+        assert_eq!((gaurd.message_counts.accepted, gaurd.message_counts.duplicate), (2, 2));
+    }
+
+    #[tokio::test]
+    /// Messages that slipped just behind their start in transit are
+    /// accepted starting now, keeping their duration, and counted apart
+    /// from those accepted on time.
+    async fn test_respond_clamped() {
+        let timing = PowerTiming {
+            late_grace_ms: 1000,
+            ..PowerTiming::default()
+        };
+        let power = Arc::new(Mutex::new(
+            CropBedPower::new(
+                CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None).with_timing(timing),
+            )
+            .unwrap(),
+        ));
+        let start_spray_time = Utc::now() - Duration::milliseconds(100);
+        let message = weed_message_json(&[0], start_spray_time, start_spray_time + Duration::milliseconds(150));
+        let sent_at = Utc::now();
+        let response = exchange(power.clone(), &message.to_string()).await;
+        assert_eq!(response.status, ResponseStatus::Accepted);
+
+        let gaurd = power.lock().await;
+        assert_eq!((gaurd.message_counts.accepted, gaurd.message_counts.clamped), (1, 1));
+        let times = fire_times(&gaurd, 1);
+        assert!(times[0] >= sent_at, "Spray still starts in the past");
+        assert_eq!(times[1] - times[0], Duration::milliseconds(150));
+    }
+
+    #[rstest]
+    #[case::ahead(1000, false, Some((1000, 1050)))]
+    #[case::now(0, false, Some((0, 50)))]
+    #[case::just_behind(-5, false, Some((0, 55)))]
+    #[case::inside_grace(-19, false, Some((0, 69)))]
+    #[case::at_grace(-20, false, None)]
+    #[case::past_grace(-21, false, None)]
+    #[case::no_grace_now(0, true, None)]
+    /// Weed messages are accepted up to the late grace behind, starting now,
+    /// and without a grace only when they start after now.
+    fn test_clamp_to_grace(#[case] start_ms: i64, #[case] no_grace: bool, #[case] expected: Option<(i64, i64)>) {
+        let now = Utc::now();
+        let late_grace = if no_grace { Duration::zero() } else { Duration::milliseconds(20) };
+        let start = now + Duration::milliseconds(start_ms);
+        let at = |(start, end): (i64, i64)| (now + Duration::milliseconds(start), now + Duration::milliseconds(end));
+        assert_eq!(
+            clamp_to_grace(start, start + Duration::milliseconds(50), now, late_grace),
+            expected.map(at)
+        );
+    }
+
+    #[rstest]
+    #[case::not_due(1000, 20, QueueAction::Wait)]
+    #[case::within_bound(3, 20, QueueAction::Fire)]
+    #[case::now(0, 20, QueueAction::Fire)]
+    #[case::just_behind(-1, 20, QueueAction::Fire)]
+    #[case::at_grace(-20_000, 20, QueueAction::Fire)]
+    #[case::past_grace(-20_001, 20, QueueAction::Discard)]
+    #[case::no_grace(-1, 0, QueueAction::Discard)]
+    /// Queued messages fire up to the late grace behind their time and are
+    /// discarded past it.
+    fn test_queue_action(#[case] offset_us: i64, #[case] late_grace_ms: u64, #[case] expected: QueueAction) {
+        let timing = PowerTiming {
+            late_grace_ms,
+            ..PowerTiming::default()
+        };
+        let now = Utc::now();
+        assert_eq!(queue_action(now + Duration::microseconds(offset_us), now, &timing), expected);
+    }
+
+    #[tokio::test]
+    /// The intensity of a message is queued as the duty cycle of each of its
+    /// channels, messages at full intensity keep the single call per PDM and
+    /// intensities over 100 are rejected.
+    async fn test_queue_intensity() {
+        let power = queue_only_power();
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let mut message = weed_message_json(&[0, 13], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        message["intensity"] = serde_json::json!(40);
+        assert_eq!(exchange(power.clone(), &message.to_string()).await.status, ResponseStatus::Accepted);
+        for (queued, _) in &power.lock().await.message_queue {
+            assert_eq!(queued.pwm, Some(BTreeMap::from([(1, 40), (14, 40)])));
+        }
+
+        message["intensity"] = serde_json::json!(150);
+        assert_eq!(exchange(power.clone(), &message.to_string()).await.reason(), Some("malformed message"));
+        assert_eq!(power.lock().await.message_queue.len(), 2);
+
+        let power = queue_only_power();
+        let message = weed_message_json(&[0], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        exchange(power.clone(), &message.to_string()).await;
+        assert!(power.lock().await.message_queue.iter().all(|(queued, _)| queued.pwm.is_none()));
+    }
+
+    #[rstest]
+    #[case::clamp(OverlongSpray::Clamp, WeedMessageStatus::Truncated, 2)]
+    #[case::reject(OverlongSpray::Reject, WeedMessageStatus::Rejected, 0)]
+    #[tokio::test]
+    /// Sprays longer than the maximum are cut to it or refused depending on
+    /// the config, and counted either way. Sprays within it are untouched.
+    async fn test_max_spray_duration(
+        #[case] overlong_spray: OverlongSpray,
+        #[case] status: WeedMessageStatus,
+        #[case] queued_actions: usize,
+    ) {
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None)
+            .with_max_spray_duration(500, overlong_spray);
+        let power = Arc::new(Mutex::new(CropBedPower::new(config).unwrap()));
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let message = weed_message_json(&[0], start_spray_time, start_spray_time + Duration::milliseconds(800));
+        let response = exchange(power.clone(), &message.to_string()).await;
+        let truncated = status == WeedMessageStatus::Truncated;
+        assert_eq!((response.is_accepted(), response.detail::<bool>("truncated")), (truncated, truncated.then_some(true)));
+        assert_eq!(response.detail::<usize>("queued_actions"), Some(queued_actions));
+        {
+            let gaurd = power.lock().await;
+            for (queued, _) in &gaurd.message_queue {
+                assert_eq!(queued.original_spray_ending, start_spray_time + Duration::milliseconds(500));
+            }
+            assert_eq!(gaurd.message_counts.truncated + gaurd.message_counts.rejected, 1);
+        }
+
+        let message = weed_message_json(&[0], start_spray_time, start_spray_time + Duration::milliseconds(500));
+        let response = exchange(power.clone(), &message.to_string()).await;
+        assert_eq!(response.status, ResponseStatus::Accepted);
+    }
+
+    #[tokio::test]
+    /// Messages failing validation are refused before they are queued or
+    /// remembered, with the reason in the response, counted and traced.
+    async fn test_reject_invalid_weed_message() {
+        capture::capture_events();
+        let power = queue_only_power();
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let message = weed_message_json(&[0, 24], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        let response = exchange(power.clone(), &message.to_string()).await;
+        assert_eq!(response.reason(), Some("channel 24 is not wired to a PDM"));
+        assert_eq!(
+            response.detail::<ValidationError>("rejection"),
+            Some(ValidationError::ChannelOutOfRange { channel: 24 })
+        );
+
+        let message = weed_message_json(&[0], start_spray_time, start_spray_time - Duration::milliseconds(100));
+        let response = exchange(power.clone(), &message.to_string()).await;
+        assert_eq!(response.detail::<ValidationError>("rejection"), Some(ValidationError::TimeOrder));
+
+        let far_ahead = Utc::now() + Duration::minutes(10);
+        let message = weed_message_json(&[0], far_ahead, far_ahead + Duration::milliseconds(100));
+        let response = exchange(power.clone(), &message.to_string()).await;
+        assert!(matches!(
+            response.detail::<ValidationError>("rejection"),
+            Some(ValidationError::TooFarInFuture { .. })
+        ));
+        {
+            let gaurd = power.lock().await;
+            assert!(gaurd.message_queue.is_empty());
+            let invalid = gaurd.message_counts.invalid;
+            assert_eq!((invalid.channel_out_of_range, invalid.time_order, invalid.too_far_in_future), (1, 1, 1));
+            assert_eq!(gaurd.message_counts.rejected, 3);
+        }
+        let component = power.lock().await.uuid.to_string();
+        let rejected: Vec<_> = capture::captured_with("component", &component)
+            .into_iter()
+            .filter(|event| event.field("code") == Some("message_rejected"))
+            .collect();
+        assert_eq!(rejected.len(), 3, "{rejected:?}");
+        assert!(rejected.iter().all(|event| event.level == tracing::Level::WARN));
+        assert_eq!(
+            rejected[0].field("crop_bed_id"),
+            Some(CropBed::LeftBoom.to_string().as_str())
+        );
+        let fields: serde_json::Value = serde_json::from_str(rejected[0].field("fields").unwrap()).unwrap();
+        assert_eq!(
+            fields,
+            serde_json::json!({
+                "message_id": null,
+                "rejection": {"reason": "channel_out_of_range", "channel": 24},
+            })
+        );
+
+        let message = weed_message_json(&[0, 23], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        let response = exchange(power.clone(), &message.to_string()).await;
+        assert!(response.is_accepted());
+        assert_eq!(response.detail::<ValidationError>("rejection"), None);
+    }
+
+    #[tokio::test]
+    /// Enveloped messages are queued like bare ones, acknowledged with the
+    /// envelope id and their retries dropped by it.
+    async fn test_enveloped_weed_message() {
+        let power = queue_only_power();
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let message = weed_message_json(&[0], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        let envelope = Envelope::new(message.clone());
+        let line = serde_json::to_string(&envelope).unwrap();
+        let response = exchange(power.clone(), &line).await;
+        assert_eq!(response.status, ResponseStatus::Accepted);
+        assert_eq!(response.correlation_id, Some(envelope.id.to_string()));
+
+        let response = exchange(power.clone(), &line).await;
+        assert_eq!(response.status, ResponseStatus::Duplicate);
+
+        let response = exchange(power.clone(), &message.to_string()).await;
+        assert_eq!((response.status, response.correlation_id), (ResponseStatus::Accepted, None));
+        let counts = power.lock().await.message_counts;
+        assert_eq!((counts.accepted, counts.duplicate), (2, 1));
+    }
+
+    #[tokio::test]
+    /// Heartbeats of other components are accepted on the weed message
+    /// socket and the latest of each kept, queueing nothing and counted
+    /// apart from the weed messages.
+    async fn test_receive_heartbeats() {
+        let power = queue_only_power();
+        let heartbeat = Heartbeat::new(ComponentKind::Lighting, Uuid::new_v4(), CropBed::Centre, 4.0);
+        for _ in 0..2 {
+            let response = exchange(power.clone(), &serde_json::to_string(&heartbeat).unwrap()).await;
+            assert!(response.is_accepted());
+        }
+        let gaurd = power.lock().await;
+        assert!(gaurd.message_queue.is_empty());
+        assert_eq!(gaurd.peers().components().collect::<Vec<_>>(), vec![&heartbeat]);
+        assert_eq!(gaurd.message_counts, MessageCounts::default());
+    }
+
+    #[tokio::test]
+    /// Control messages are acted on without counting as weed messages,
+    /// only the weed message sent alongside them is counted as accepted.
+    async fn test_control_messages_not_counted() {
+        let power = queue_only_power();
+        for message in [
+            r#"{"dry_run": {"enabled": true}}"#,
+            r#"{"dry_run": {"enabled": false}}"#,
+        ] {
+            assert!(exchange(power.clone(), message).await.is_accepted());
+        }
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let message = weed_message_json(&[3], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        exchange(power.clone(), &message.to_string()).await;
+        let counts = power.lock().await.message_counts;
+        assert_eq!(
+            counts,
+            MessageCounts {
+                accepted: 1,
+                ..MessageCounts::default()
+            }
+        );
+    }
+
+    #[rstest]
+    #[case::cbor(Encoding::Cbor)]
+    #[case::message_pack(Encoding::MessagePack)]
+    #[tokio::test]
+    /// Weed messages framed in a binary encoding are queued and answered in
+    /// it, a frame that is not a document is answered as malformed and the
+    /// connection is still served.
+    async fn test_binary_encoding(#[case] encoding: Encoding) {
+        let power = queue_only_power();
+        {
+            let mut gaurd = power.lock().await;
+            gaurd.encoding = encoding;
+            gaurd.framing = encoding.framing();
+        }
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_power = power.clone();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            handle_connection(socket, server_power).await;
+        });
+
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let mut message = weed_message_json(&[0], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        message["message_id"] = serde_json::json!("cam0-1");
+        let mut codec = FramedCodec::new(encoding.framing());
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(&codec.frame(&encoding.encode(&message))).await.unwrap();
+        stream.write_all(&codec.frame(&[0xc1, 0xff])).await.unwrap();
+        let mut read_stream = BufReader::new(stream);
+        let timeout = std::time::Duration::from_secs(1);
+        let response = read_encoded_response(&mut read_stream, &mut codec, encoding, timeout)
+            .await
+            .unwrap();
+        assert_eq!(response.status, ResponseStatus::Accepted);
+        assert_eq!(response.correlation_id.as_deref(), Some("cam0-1"));
+        let response = read_encoded_response(&mut read_stream, &mut codec, encoding, timeout)
+            .await
+            .unwrap();
+        assert_eq!(response.reason(), Some(MALFORMED_REASON));
+        let counts = power.lock().await.message_counts;
+        assert_eq!((counts.accepted, counts.malformed), (1, 1));
+    }
+
+    #[tokio::test]
+    /// With detected framing a pretty printed weed message is queued whole
+    /// once length prefixed, and a legacy sender of lines is still served
+    /// on its own connection.
+    async fn test_detect_framing() {
+        let power = queue_only_power();
+        power.lock().await.framing = Framing::Detect;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_power = power.clone();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(handle_connection(socket, server_power.clone()));
+            }
+        });
+
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let message = weed_message_json(&[0], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        let timeout = std::time::Duration::from_secs(1);
+        let mut codec = FramedCodec::new(Framing::LengthPrefixed);
+        let mut stream = BufReader::new(TcpStream::connect(address).await.unwrap());
+        let pretty = serde_json::to_vec_pretty(&message).unwrap();
+        stream.write_all(&codec.frame(&pretty)).await.unwrap();
+        let response = read_encoded_response(&mut stream, &mut codec, Encoding::Json, timeout)
+            .await
+            .unwrap();
+        assert!(response.is_accepted(), "{response:?}");
+
+        let mut stream = BufReader::new(TcpStream::connect(address).await.unwrap());
+        stream.write_all(format!("{message}\n").as_bytes()).await.unwrap();
+        assert!(read_response(&mut stream, timeout).await.unwrap().is_accepted());
+        let counts = power.lock().await.message_counts;
+        assert_eq!((counts.accepted, counts.malformed), (2, 0));
+    }
+
+    #[tokio::test]
+    /// Messages the component cannot read are written to the structured
+    /// log as typed events of the component.
+    async fn test_structured_log() {
+        let path = std::env::temp_dir().join(format!("onyx-power-log-{}.jsonl", Uuid::new_v4()));
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None)
+            .with_log(LogConfig::file(&path));
+        let power = Arc::new(Mutex::new(CropBedPower::new(config).unwrap()));
+        let uuid = power.lock().await.uuid;
+        let response = exchange(power, r#"{"channels": [3]"#).await;
+        assert_eq!(response.reason(), Some(MALFORMED_REASON));
+
+        // The writer runs on its own thread, so wait for the line.
+        let mut events: Vec<LogEvent> = Vec::new();
+        for _ in 0..100 {
+            events = std::fs::read_to_string(&path)
+                .unwrap_or_default()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            if !events.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let event = events
+            .iter()
+            .find(|event| event.code == EventCode::MessageMalformed)
+            .expect("No malformed message event");
+        assert_eq!((event.kind, event.component, event.level), (ComponentKind::Power, uuid, LogLevel::Warn));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// A running component sends its heartbeat at the configured interval.
+    async fn test_emit_heartbeats_while_running() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let emitter = HeartbeatEmitterConfig::new(receiver.local_addr().unwrap().to_string()).with_interval(50);
+        let config =
+            CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), 17678, None).with_heartbeat_emitter(emitter);
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
+            .await
+            .unwrap();
+
+        let mut data = vec![0; 1024];
+        let mut heartbeats = Vec::new();
+        for _ in 0..2 {
+            let length = tokio::time::timeout(std::time::Duration::from_secs(1), receiver.recv(&mut data))
+                .await
+                .expect("No heartbeat from the component")
+                .unwrap();
+            heartbeats.push(serde_json::from_slice::<Heartbeat>(&data[..length]).unwrap());
+        }
+        component.shutdown().await;
+
+        for heartbeat in &heartbeats {
+            assert_eq!(heartbeat.heartbeat, ComponentKind::Power);
+            assert_eq!((heartbeat.crop_bed_id, heartbeat.queue_depth), (CropBed::LeftBoom, Some(0)));
+        }
+        assert_eq!(heartbeats[0].component, heartbeats[1].component);
+        assert!(heartbeats[1].uptime_s > heartbeats[0].uptime_s);
+    }
+
+    #[test]
+    /// Only the channels wired through the channel map are valid when set.
+    fn test_message_constraints_from_channel_map() {
+        let channel_map = HashMap::from([(1, (3, 0)), (2, (4, 0)), (13, (1, 1))]);
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, Some(channel_map))
+            .with_max_message_lead(2000)
+            .with_max_spray_duration(500, OverlongSpray::Reject);
+        let constraints = config.message_constraints();
+        assert_eq!(constraints.channels, BTreeSet::from([0, 1, 12]));
+        assert_eq!(constraints.max_spray_duration, Some(Duration::milliseconds(500)));
+        assert_eq!(constraints.max_lead, Duration::seconds(2));
+
+        let constraints =
+            CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None).message_constraints();
+        assert_eq!(constraints.channels, (0..24).collect());
+        assert_eq!(constraints.max_spray_duration, None);
+    }
+
+    #[tokio::test]
+    /// A message spraying for hours queues no more than the maximum spray
+    /// duration of re-fires, and the channel is still turned off.
+    async fn test_multi_hour_spray_is_bounded() {
+        let power = queue_only_power();
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let message = weed_message_json(&[0], start_spray_time, start_spray_time + Duration::hours(3));
+        let response = exchange(power.clone(), &message.to_string()).await;
+        assert_eq!(response.detail::<bool>("truncated"), Some(true));
+        #[allow(clippy::cast_possible_truncation)]
+        let refires = (DEFAULT_MAX_SPRAY_DURATION_MS / PowerTiming::default().refire_interval_ms) as usize;
+        let queued_actions = response.detail::<usize>("queued_actions").unwrap();
+        assert!(queued_actions <= refires + 1, "Queued {queued_actions}");
+
+        // The re-fire padding is bounded even when it is asked for directly.
+        let mut gaurd = power.lock().await;
+        gaurd.message_queue.clear();
+        let queued_actions =
+            gaurd.queue_spray(
+                vec![(0, 2)],
+                start_spray_time,
+                start_spray_time + Duration::hours(3),
+                100,
+                None,
+                false,
+                Utc::now(),
+                None,
+            );
+        assert!(queued_actions <= refires + 1, "Queued {queued_actions}");
+        let off = gaurd
+            .message_queue
+            .iter()
+            .find(|(queued, _)| !queued.is_on)
+            .map(|(queued, _)| queued.time_to_fire);
+        let max_spray_duration = Duration::milliseconds(i64::try_from(DEFAULT_MAX_SPRAY_DURATION_MS).unwrap());
+        assert_eq!(off, Some(start_spray_time + max_spray_duration));
+    }
+
+    #[tokio::test]
+    /// Manual sprays are refused while the interlock is set, and counted.
+    async fn test_reject_interlocked_manual_spray() {
+        let power = queue_only_power();
+        let response = exchange(
+            power.clone(),
+            r#"{"manual": true, "channels": [7], "duration_ms": 500, "message_id": "hmi-1"}"#,
+        )
+        .await;
+        let expected = WeedMessageResponse::refused(Some(String::from("hmi-1")), "manual sprays are not allowed");
+        assert_eq!(response, responds(&power, &expected).await);
+        let gaurd = power.lock().await;
+        assert!(gaurd.message_queue.is_empty());
+        assert_eq!(gaurd.message_counts.rejected, 1);
+    }
+
+    #[tokio::test]
+    /// Allowed manual sprays are queued to start straight away and marked
+    /// manual, sprays longer than the limit are refused.
+    async fn test_queue_manual_spray() {
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None).with_manual_spray(true);
+        let power = Arc::new(Mutex::new(CropBedPower::new(config).unwrap()));
+        let sent_at = Utc::now();
+        let response = exchange(power.clone(), r#"{"manual": true, "channels": [7, 14], "duration_ms": 500, "pwm": 60}"#).await;
+        let expected = WeedMessageResponse::new(WeedMessageStatus::Accepted, None, 2);
+        assert_eq!(response, responds(&power, &expected).await);
+        {
+            let gaurd = power.lock().await;
+            for (queued, _) in &gaurd.message_queue {
+                assert!(queued.manual);
+                assert_eq!(queued.channels, vec![(0, 7), (1, 14)]);
+                assert_eq!(queued.pwm, Some(BTreeMap::from([(7, 60), (14, 60)])));
+                assert_eq!(queued.original_spray_ending - queued.original_spray_starts, Duration::milliseconds(500));
+                assert!(queued.original_spray_starts - sent_at < Duration::milliseconds(100));
+            }
+        }
+
+        let response = exchange(power.clone(), r#"{"manual": true, "channels": [7], "duration_ms": 60000}"#).await;
+        assert_eq!(response.reason(), Some("60000ms is longer than 10000ms"));
+        assert_eq!(power.lock().await.message_queue.len(), 2);
+    }
+
+    #[tokio::test]
+    /// Manual sprays opening no channels, or a channel missing from the
+    /// channel map or layout, are refused without queuing anything.
+    async fn test_reject_unwired_manual_spray() {
+        let channel_map = HashMap::from([(1, (12, 0)), (2, (11, 0))]);
+        let mapped = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, Some(channel_map))
+            .with_manual_spray(true);
+        let laid_out =
+            CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None).with_manual_spray(true);
+        for (config, channels, reason) in [
+            (mapped.clone(), "[2, 3]", "channel 3 is not wired to a PDM"),
+            (mapped, "[]", "no channels to open"),
+            (laid_out.clone(), "[25]", "channel 25 is not wired to a PDM"),
+            (laid_out, "[0]", "channel 0 is not wired to a PDM"),
+        ] {
+            let power = Arc::new(Mutex::new(CropBedPower::new(config).unwrap()));
+            let line = format!(r#"{{"manual": true, "channels": {channels}, "duration_ms": 500}}"#);
+            let response = exchange(power.clone(), &line).await;
+            assert_eq!(response.reason(), Some(reason), "{channels}");
+            assert!(power.lock().await.message_queue.is_empty(), "{channels}");
+        }
+    }
+
+    #[tokio::test]
+    /// A dry run lifts the interlock, the manual spray queued but fired
+    /// without touching the PDMs, and only its offs are left once the dry
+    /// run ends.
+    async fn test_dry_run_bypasses_manual_interlock() {
+        let power = queue_only_power();
+        exchange(power.clone(), r#"{"dry_run": {"enabled": true}}"#).await;
+        let response = exchange(
+            power.clone(),
+            r#"{"manual": true, "channels": [7], "duration_ms": 500}"#,
+        )
+        .await;
+        let expected = WeedMessageResponse::new(WeedMessageStatus::Accepted, None, 2);
+        assert_eq!(response, responds(&power, &expected).await);
+        assert_eq!(power.lock().await.message_queue.len(), 2);
+
+        exchange(power.clone(), r#"{"dry_run": {"enabled": false}}"#).await;
+        let gaurd = power.lock().await;
+        assert_eq!(gaurd.message_queue.len(), 1);
+        assert!(gaurd.message_queue.iter().all(|(message, _)| !message.is_on));
+    }
+
+    #[tokio::test]
+    /// Data that is not a message is reported as malformed, with the id
+    /// when one can be read.
+    async fn test_respond_malformed() {
+        let power = queue_only_power();
+        let response = exchange(power.clone(), r#"{"message_id": "cam0-3", "channels_to_open": "seven"}"#).await;
+        let expected = WeedMessageResponse::new(WeedMessageStatus::Malformed, Some(String::from("cam0-3")), 0);
+        assert_eq!(response, responds(&power, &expected).await);
+        let response = exchange(power.clone(), "not json").await;
+        let expected = WeedMessageResponse::new(WeedMessageStatus::Malformed, None, 0);
+        assert_eq!(response, responds(&power, &expected).await);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    /// Weed messages sent to the component are counted by how they were
+    /// handled on the metrics endpoint, alongside the queue depth.
+    async fn test_scrape_weed_message_metrics() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/metrics", listener.local_addr().unwrap());
+        metrics::spawn(listener);
+        let count = |text: &str, status: &str| {
+            let prefix = format!("onyx_weed_messages_total{{status=\"{status}\"}} ");
+            text.lines()
+                .find_map(|line| line.strip_prefix(&prefix)?.parse::<u64>().ok())
+                .unwrap_or(0)
+        };
+        let before = reqwest::get(&url).await.unwrap().text().await.unwrap();
+
+        let power = queue_only_power();
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let message = weed_message_json(&[0], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        exchange(power.clone(), &message.to_string()).await;
+        exchange(power, "not json").await;
+
+        // Other tests count messages into the same registry as they run.
+        let after = reqwest::get(&url).await.unwrap().text().await.unwrap();
+        assert!(count(&after, "accepted") > count(&before, "accepted"), "{after}");
+        assert!(count(&after, "malformed") > count(&before, "malformed"), "{after}");
+        assert!(after.contains("# TYPE onyx_spray_queue_depth gauge"), "{after}");
+    }
+
+    #[tokio::test]
+    /// A client streaming several messages over one connection gets a
+    /// response to each, in order, and the handler ends when it closes.
+    async fn test_respond_streamed_messages() {
+        let power = queue_only_power();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let handler_power = power.clone();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            handle_connection(socket, handler_power).await;
+        });
+
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let mut accepted = weed_message_json(&[0], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        accepted["message_id"] = serde_json::json!("cam1-1");
+        let mut late = weed_message_json(&[1], Utc::now() - Duration::seconds(1), Utc::now());
+        late["message_id"] = serde_json::json!("cam1-2");
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let (read_stream, mut write_stream) = stream.split();
+        let mut read_stream = BufReader::new(read_stream);
+        let mut responses = Vec::new();
+        for line in [accepted.to_string(), late.to_string(), String::from("not json")] {
+            write_stream.write_all(format!("{line}\n").as_bytes()).await.unwrap();
+            responses.push(
+                read_response(&mut read_stream, std::time::Duration::from_secs(1))
+                    .await
+                    .unwrap(),
+            );
+        }
+        drop(stream);
+        tokio::time::timeout(std::time::Duration::from_millis(500), server)
+            .await
+            .expect("Handler did not end when the client closed")
+            .unwrap();
+
+        let uuid = power.lock().await.uuid;
+        assert_eq!(
+            responses,
+            vec![
+                WeedMessageResponse::new(WeedMessageStatus::Accepted, Some(String::from("cam1-1")), 2).to_control(uuid),
+                WeedMessageResponse::new(WeedMessageStatus::Late, Some(String::from("cam1-2")), 0).to_control(uuid),
+                WeedMessageResponse::malformed(b"not json").to_control(uuid),
+            ]
+        );
+        let counts = power.lock().await.message_counts;
+        assert_eq!((counts.accepted, counts.late, counts.malformed), (1, 1, 1));
+    }
+
+    #[tokio::test]
+    /// Every line received is journalled with its verdict, in the order
+    /// received.
+    async fn test_journal_received_lines() {
+        let path = std::env::temp_dir().join(format!("onyx-power-journal-{}.jsonl", Uuid::new_v4()));
+        let power = queue_only_power();
+        let (journal, journal_writer) = journal::Journal::start(path.clone(), DEFAULT_JOURNAL_MAX_BYTES);
+        power.lock().await.journal = Some(journal);
+
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let accepted = weed_message_json(&[0], start_spray_time, start_spray_time + Duration::milliseconds(100));
+        let late = weed_message_json(&[0], Utc::now() - Duration::seconds(1), Utc::now());
+        let lines = [accepted.to_string(), late.to_string(), String::from("not json")];
+        let sent_at = Utc::now();
+        for line in &lines {
+            exchange(power.clone(), line).await;
+        }
+        drop(power.lock().await.journal.take());
+        journal_writer.await.unwrap();
+
+        let entries = journal::read_journal(&path).unwrap();
+        assert_eq!(
+            entries.iter().map(|entry| entry.status).collect::<Vec<_>>(),
+            vec![WeedMessageStatus::Accepted, WeedMessageStatus::Late, WeedMessageStatus::Malformed]
+        );
+        assert_eq!(entries.iter().map(|entry| entry.line.clone()).collect::<Vec<_>>(), lines);
+        assert!(entries.iter().all(|entry| entry.received_at >= sent_at));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    /// A connection that stays open without sending anything is closed after
+    /// the idle timeout rather than holding its task forever.
+    async fn test_close_idle_connection() {
+        use tokio::io::AsyncReadExt;
+
+        let timing = PowerTiming {
+            connection_idle_timeout_ms: 100,
+            ..PowerTiming::default()
+        };
+        let power = Arc::new(Mutex::new(
+            CropBedPower::new(
+                CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None).with_timing(timing),
+            )
+            .unwrap(),
+        ));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            handle_connection(socket, power).await;
+        });
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        tokio::time::timeout(std::time::Duration::from_millis(500), server)
+            .await
+            .expect("Idle connection was not closed")
+            .unwrap();
+        let mut rest = Vec::new();
+        assert_eq!(stream.read_to_end(&mut rest).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// A message sprayed at reduced intensity turns the simulated PDM
+    /// channel on at that duty cycle, and off again at the end, sent with
+    /// the command id of the PDM config.
+    async fn test_intensity_reaches_simulated_pdm() {
+        use crate::devices::{
+            hardware::pdm::PdmAddress,
+            software::pdm::{ActuationCause, SimulatedPdm},
+        };
+
+        let port = 17666;
+        let config_dir = std::env::temp_dir().join(format!("onyx-intensity-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config = PdmConfig::new(PdmAddress::Pdm30, 0)
+            .with_response_timeout(std::time::Duration::from_millis(200))
+            .with_actuate_command_id(0x21);
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), port, None)
+            .add_pdm_config_file(pdm_config_file, 0);
+        let simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
+            .await
+            .unwrap();
+
+        let start_spray_time = Utc::now() + Duration::milliseconds(300);
+        let mut message = weed_message_json(&[1], start_spray_time, start_spray_time + Duration::milliseconds(200));
+        message["intensity"] = serde_json::json!(40);
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).await.unwrap();
+        stream.write_all(format!("{message}\n").as_bytes()).await.unwrap();
+        drop(stream);
+        tokio::time::sleep(tokio::time::Duration::from_millis(800)).await;
+        component.shutdown().await;
+
+        let commands: Vec<_> = simulated
+            .actuations_of(2)
+            .into_iter()
+            .filter(|record| record.cause == ActuationCause::Command && record.channels.len() == 1)
+            .collect();
+        assert_eq!(commands.len(), 2, "PDM saw {:?}", commands);
+        assert!((commands[0].duty_percent - 40.0).abs() < f32::EPSILON);
+        assert!(commands[1].duty_percent.abs() < f32::EPSILON);
+        assert!(commands.iter().all(|record| record.command_id == Some(0x21)), "PDM saw {:?}", commands);
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    /// Wait for the component to become ready or stop being ready, failing
+    /// after a second.
+    ///
+    /// * `health`: health of the component.
+    /// * `ready`: readiness waited for.
+    async fn wait_for_readiness(health: &Health, ready: bool) {
+        tokio::time::timeout(tokio::time::Duration::from_secs(1), async {
+            while health.report().is_ready() != ready {
+                tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("Readiness did not become {ready}, {:?}", health.report()));
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// The component is ready once started against the simulated PDM, with
+    /// the firing task going round while idle, not ready while the PDM
+    /// reports a fault, ready again once the PDM has been reinitialised after
+    /// a loss of CAN, and not ready once shut down.
+    async fn test_readiness_follows_pdm_faults() {
+        use crate::devices::{hardware::pdm::PdmAddress, software::pdm::SimulatedPdm};
+
+        let config_dir = std::env::temp_dir().join(format!("onyx-readiness-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config =
+            PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), 17694, None)
+            .add_pdm_config_file(pdm_config_file, 0);
+        let simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let power = CropBedPower::new(config).unwrap();
+        let health = power.health();
+        let firing = power.firing_progress();
+        assert!(!health.report().is_ready());
+        let component = CropBedPowerController::start(power).await.unwrap();
+        assert!(health.report().is_ready(), "{:?}", health.report());
+        let passes = firing.count();
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        assert!(firing.count() > passes, "Firing task made no progress while idle");
+
+        let over_temperature = PdmStatus {
+            module_over_temperature: true,
+            ..PdmStatus::default()
+        };
+        simulated.report_status(over_temperature).await.unwrap();
+        wait_for_readiness(&health, false).await;
+        assert_eq!(health.report().pdms.get(&0), Some(&false));
+
+        let configured = simulated.configurations_received();
+        let loss_of_can = PdmStatus {
+            loss_of_can: true,
+            ..PdmStatus::default()
+        };
+        simulated.report_status(loss_of_can).await.unwrap();
+        wait_for_readiness(&health, true).await;
+        assert!(
+            simulated.configurations_received() > configured,
+            "PDM was not reinitialised"
+        );
+
+        component.shutdown().await;
+        let report = health.report();
+        assert_eq!((report.is_live(), report.is_ready()), (true, false));
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// Sprays are audited at the position the AI system sent, and at the
+    /// latest GPS fix when it sent none.
+    async fn test_actuation_audit_position() {
+        use crate::devices::{hardware::pdm::PdmAddress, software::pdm::SimulatedPdm};
+
+        let port = 17679;
+        let config_dir = std::env::temp_dir().join(format!("onyx-position-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config =
+            PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), port, None)
+            .add_pdm_config_file(pdm_config_file, 0);
+        let _simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
+            .await
+            .unwrap();
+        let fix = GeoPosition::new(-27.47, 153.02);
+        component.power.lock().await.record_gps_fix(fix);
+
+        let start_spray_time = Utc::now() + Duration::milliseconds(300);
+        let end_spray_time = start_spray_time + Duration::milliseconds(100);
+        let tagged = GeoPosition::new(-27.4698, 153.0251).with_heading(92.5);
+        let mut positioned = weed_message_json(&[1], start_spray_time, end_spray_time);
+        positioned["latitude"] = serde_json::json!(tagged.latitude);
+        positioned["longitude"] = serde_json::json!(tagged.longitude);
+        positioned["heading_deg"] = serde_json::json!(92.5);
+        let unpositioned = weed_message_json(&[4], start_spray_time, end_spray_time);
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).await.unwrap();
+        stream.write_all(format!("{positioned}\n{unpositioned}\n").as_bytes()).await.unwrap();
+        drop(stream);
+        tokio::time::sleep(tokio::time::Duration::from_millis(700)).await;
+        let audited = component.power.lock().await.pdms[&0].recent_actuations(16);
+        let telemetry = component.power.lock().await.spray_telemetry();
+        component.shutdown().await;
+
+        let positions_of = |channel| -> Vec<_> {
+            audited
+                .iter()
+                .filter(|record| record.channels == vec![channel])
+                .map(|record| record.position)
+                .collect()
+        };
+        assert_eq!(positions_of(2), vec![Some(tagged); 2], "Audited {audited:?}");
+        assert_eq!(positions_of(5), vec![Some(fix); 2], "Audited {audited:?}");
+        assert!(telemetry.last_fired_position.is_some());
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// A manual spray opens the simulated PDM channel for the requested
+    /// time and is marked manual in the audit log.
+    async fn test_manual_spray_reaches_simulated_pdm() {
+        use crate::devices::{
+            hardware::pdm::PdmAddress,
+            software::pdm::{ActuationCause, SimulatedPdm},
+        };
+
+        let port = 17667;
+        let config_dir = std::env::temp_dir().join(format!("onyx-manual-spray-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config =
+            PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), port, None)
+            .add_pdm_config_file(pdm_config_file, 0)
+            .with_manual_spray(true);
+        let simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
+            .await
+            .unwrap();
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).await.unwrap();
+        stream
+            .write_all(b"{\"manual\": true, \"channels\": [7], \"duration_ms\": 200}\n")
+            .await
+            .unwrap();
+        drop(stream);
+        tokio::time::sleep(tokio::time::Duration::from_millis(600)).await;
+        let audited: Vec<_> = component.power.lock().await.pdms[&0]
+            .recent_actuations(16)
+            .into_iter()
+            .filter(|record| record.channels == vec![7])
+            .collect();
+        component.shutdown().await;
+
+        let commands: Vec<_> = simulated
+            .actuations_of(7)
+            .into_iter()
+            .filter(|record| record.cause == ActuationCause::Command && record.channels.len() == 1)
+            .collect();
+        assert_eq!(commands.len(), 2, "PDM saw {:?}", commands);
+        assert!((commands[0].duty_percent - 100.0).abs() < f32::EPSILON);
+        assert!(commands[1].duty_percent.abs() < f32::EPSILON);
+        assert_eq!(audited.len(), 2, "Audited {:?}", audited);
+        assert!(audited.iter().all(|record| record.manual));
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// A manual spray let past the interlock by a dry run never turns the
+    /// simulated PDM channel on, even when the dry run ends part way
+    /// through it.
+    async fn test_dry_run_end_drops_manual_spray() {
+        use crate::devices::{
+            hardware::pdm::PdmAddress,
+            software::pdm::{ActuationCause, SimulatedPdm},
+        };
+
+        let port = 17711;
+        let config_dir = std::env::temp_dir().join(format!("onyx-dry-run-manual-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config =
+            PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), port, None)
+            .add_pdm_config_file(pdm_config_file, 0);
+        let simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
+            .await
+            .unwrap();
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).await.unwrap();
+        for (line, pause_ms) in [
+            ("{\"dry_run\": {\"enabled\": true}}\n", 50),
+            ("{\"manual\": true, \"channels\": [7], \"duration_ms\": 1000}\n", 300),
+            ("{\"dry_run\": {\"enabled\": false}}\n", 1200),
+        ] {
+            stream.write_all(line.as_bytes()).await.unwrap();
+            tokio::time::sleep(tokio::time::Duration::from_millis(pause_ms)).await;
+        }
+        drop(stream);
+        component.shutdown().await;
+
+        let ons: Vec<_> = simulated
+            .actuations_of(7)
+            .into_iter()
+            .filter(|record| record.cause == ActuationCause::Command && record.duty_percent > 0.0)
+            .collect();
+        assert!(ons.is_empty(), "PDM turned on {:?}", ons);
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// A test pattern turns the simulated PDM channels on one after another
+    /// with the gap between them, and leaves every channel off.
+    async fn test_test_pattern_reaches_simulated_pdm() {
+        use crate::devices::{
+            hardware::pdm::PdmAddress,
+            software::pdm::{ActuationCause, SimulatedPdm},
+        };
+        use layout::ChannelRange;
+
+        let port = 17672;
+        let config_dir = std::env::temp_dir().join(format!("onyx-test-pattern-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config =
+            PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), port, None)
+            .add_pdm_config_file(pdm_config_file, 0)
+            .with_channel_layout(ChannelLayout::new(vec![ChannelRange::new(1, 3, 0, 0)]))
+            .with_manual_spray(true);
+        let simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
+            .await
+            .unwrap();
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).await.unwrap();
+        stream
+            .write_all(b"{\"test_pattern\": {\"on_ms\": 100, \"gap_ms\": 100}}\n")
+            .await
+            .unwrap();
+        let response = read_response(&mut BufReader::new(stream), std::time::Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert!(response.is_accepted());
+        assert_eq!(response.detail::<usize>("queued_actions"), Some(7));
+        tokio::time::sleep(tokio::time::Duration::from_millis(900)).await;
+        let outputs: Vec<_> = (1..=3).map(|channel| simulated.output(channel)).collect();
+        component.shutdown().await;
+
+        let ons: Vec<_> = simulated
+            .actuations()
+            .into_iter()
+            .filter(|record| record.cause == ActuationCause::Command && record.duty_percent > 0.0)
+            .collect();
+        assert_eq!(
+            ons.iter().map(|record| record.channels.clone()).collect::<Vec<_>>(),
+            vec![vec![1], vec![2], vec![3]],
+            "PDM saw {ons:?}"
+        );
+        for pair in ons.windows(2) {
+            let step = (pair[1].at - pair[0].at).num_milliseconds();
+            assert!((160..=240).contains(&step), "Channels {step}ms apart");
+        }
+        assert_eq!(outputs, vec![Some(0.0); 3]);
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// Sprays to the simulated PDM are actuated within the spray bound of
+    /// when they were due, and their latencies are summarised.
+    async fn test_latency_within_spray_bound() {
+        use crate::devices::{hardware::pdm::PdmAddress, software::pdm::SimulatedPdm};
+
+        let port = 17668;
+        let config_dir = std::env::temp_dir().join(format!("onyx-latency-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config =
+            PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let timing = PowerTiming {
+            spray_bound_us: 5_000,
+            ..PowerTiming::default()
+        };
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), port, None)
+            .add_pdm_config_file(pdm_config_file, 0)
+            .with_timing(timing);
+        let _simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
+            .await
+            .unwrap();
+
+        for (index, channel) in [1, 2, 3, 4].into_iter().enumerate() {
+            let start_spray_time = Utc::now() + Duration::milliseconds(200 + 50 * i64::try_from(index).unwrap());
+            send_weed_message(port, &[channel], start_spray_time, start_spray_time + Duration::milliseconds(100)).await;
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(800)).await;
+        let latency = component.snapshot().await.latency;
+        component.shutdown().await;
+
+        assert_eq!(latency.samples, 8, "{latency:?}");
+        let spray_bound_us = i64::try_from(timing.spray_bound_us).unwrap();
+        assert!(latency.intended_to_actual.max_us < spray_bound_us, "{latency:?}");
+        assert!(latency.intended_to_actual.p50_us > -spray_bound_us, "{latency:?}");
+        assert!(latency.receive_to_intended.p50_us > 0, "{latency:?}");
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// With the crop bed 2 channel map, logical channel 0 sprays channel 24
+    /// of the crop bed, the last channel of the second PDM, and nothing is
+    /// sent to the first PDM.
+    async fn test_channel_map_routes_to_mapped_pdm() {
+        use crate::devices::{
+            hardware::pdm::PdmAddress,
+            software::pdm::{ActuationCause, SimulatedPdm},
+        };
+
+        let port = 17665;
+        let config_dir = std::env::temp_dir().join(format!("onyx-channel-map-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let mut config = CropBedPowerConfig::new(CropBed::RightBoom, vcan_interface(), port, Some(bed_two_channel_map()));
+        let mut simulated = Vec::new();
+        for (bed_position, address) in [(0, PdmAddress::Pdm30), (1, PdmAddress::Pdm31)] {
+            let pdm_config = PdmConfig::new(address, bed_position)
+                .with_response_timeout(std::time::Duration::from_millis(200));
+            let pdm_config_file = config_dir.join(format!("pdm_{bed_position}.yaml"));
+            serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+            config = config.add_pdm_config_file(pdm_config_file, bed_position);
+            simulated.push(
+                SimulatedPdm::new(pdm_config)
+                    .start(&vcan_interface())
+                    .expect("Failed to start simulated PDM"),
+            );
+        }
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
+            .await
+            .unwrap();
+
+        let start_spray_time = Utc::now() + Duration::milliseconds(300);
+        send_weed_message(port, &[0], start_spray_time, start_spray_time + Duration::milliseconds(200)).await;
+        tokio::time::sleep(tokio::time::Duration::from_millis(800)).await;
+        component.shutdown().await;
+
+        let commands: Vec<_> = simulated[1]
+            .actuations_of(12)
+            .into_iter()
+            .filter(|record| record.cause == ActuationCause::Command && record.channels.len() == 1)
+            .collect();
+        assert_eq!(commands.len(), 2, "PDM 1 saw {:?}", commands);
+        assert!((commands[0].duty_percent - 100.0).abs() < f32::EPSILON);
+        assert!(commands[1].duty_percent.abs() < f32::EPSILON);
+        assert!(
+            !simulated[0]
+                .actuations()
+                .iter()
+                .any(|record| record.cause == ActuationCause::Command && record.channels.len() == 1),
+            "PDM 0 was sent a spray for a channel on PDM 1"
+        );
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// Weed messages sent to the component reach the channels of both
+    /// simulated PDMs at the requested times, split across the PDMs.
+    async fn test_crop_bed_power_against_simulated_pdms() {
+        use crate::devices::{
+            hardware::pdm::PdmAddress,
+            software::pdm::{ActuationCause, SimulatedPdm},
+        };
+
+        let port = 17660;
+        let config_dir = std::env::temp_dir().join(format!("onyx-simulated-pdm-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let mut config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), port, None);
+        let mut simulated = Vec::new();
+        for (bed_position, address) in [(0, PdmAddress::Pdm30), (1, PdmAddress::Pdm31)] {
+            let pdm_config = PdmConfig::new(address, bed_position)
+                .with_response_timeout(std::time::Duration::from_millis(200));
+            let pdm_config_file = config_dir.join(format!("pdm_{bed_position}.yaml"));
+            serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+            config = config.add_pdm_config_file(pdm_config_file, bed_position);
+            simulated.push(
+                SimulatedPdm::new(pdm_config)
+                    .start(&vcan_interface())
+                    .expect("Failed to start simulated PDM"),
+            );
+        }
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
+            .await
+            .unwrap();
+
+        // Channel 1 is channel 2 on the first PDM, channel 14 is channel 3
+        // on the second.
+        let start_spray_time = Utc::now() + Duration::milliseconds(300);
+        let end_spray_time = start_spray_time + Duration::milliseconds(200);
+        send_weed_message(port, &[1, 14], start_spray_time, end_spray_time).await;
+        tokio::time::sleep(tokio::time::Duration::from_millis(800)).await;
+        component.shutdown().await;
+
+        for (pdm, channel) in [(&simulated[0], 2), (&simulated[1], 3)] {
+            let commands: Vec<_> = pdm
+                .actuations_of(channel)
+                .into_iter()
+                .filter(|record| record.cause == ActuationCause::Command && record.channels.len() == 1)
+                .collect();
+            assert_eq!(commands.len(), 2, "PDM {} saw {:?}", pdm.address(), commands);
+            assert!((commands[0].duty_percent - 100.0).abs() < f32::EPSILON);
+            assert!(commands[1].duty_percent.abs() < f32::EPSILON);
+            for (record, expected) in commands.iter().zip([start_spray_time, end_spray_time]) {
+                let error = (record.at - expected).num_milliseconds().abs();
+                assert!(error < 50, "PDM {} actuated {error}ms from the requested time", pdm.address());
+            }
+            assert!(
+                pdm.actuations().iter().all(|record| record.cause != ActuationCause::LossOfCan),
+                "PDM {} lost CAN while the component was running",
+                pdm.address()
+            );
+        }
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[test]
+    /// Per channel duty cycles are looked up by the channel in the message,
+    /// channels missing from the map are fully on and off overrides them.
+    fn test_weed_queue_message_duties() {
+        let mut message = WeedQueueMessage {
+            channels: vec![(0, 2), (1, 14), (1, 15)],
+            time_to_fire: Utc::now(),
+            is_on: true,
+            original_spray_starts: Utc::now(),
+            original_spray_ending: Utc::now(),
+            pwm: Some(BTreeMap::from([(2, 40), (14, 70)])),
+            timed_for: None,
+            manual: false,
+            received_at: Utc::now(),
+            position: None,
+        };
+        assert_eq!(message.duties(&[2], 0), vec![(2, 40.0)]);
+        assert_eq!(message.duties(&[2, 3], 12), vec![(2, 70.0), (3, 100.0)]);
+        message.is_on = false;
+        assert_eq!(message.duties(&[2, 3], 12), vec![(2, 0.0), (3, 0.0)]);
+        message.pwm = None;
+        message.is_on = true;
+        assert_eq!(message.duties(&[2], 0), vec![(2, 100.0)]);
+    }
+
+    #[test]
+    /// Channel usage is reported for each PDM of the component, keyed by
+    /// bed position.
+    fn test_status_reports_channel_usage() {
+        let power = CropBedPower::new(CropBedPowerConfig::new(
+            CropBed::LeftBoom,
+            String::from("can0"),
+            17650,
+            None,
+        ))
+        .unwrap();
+        let status = power.status();
+        assert!(status.channel_stats.is_empty());
+        assert_eq!(status.to_string(), "Crop bed power on can0: 0 queued, 0 dropped");
+
+        let status = CropBedPowerStatus {
+            canbus_id: String::from("can1"),
+            channel_stats: BTreeMap::from([(
+                1,
+                BTreeMap::from([(
+                    3,
+                    ChannelUsage {
+                        on_transitions: 12,
+                        on_time_ms: 1500,
+                    },
+                )]),
+            )]),
+            queue_depth: 6,
+            queue_dropped: 2,
+            tasks_failed: 0,
+        };
+        assert_eq!(
+            status.to_string(),
+            "Crop bed power on can1: 6 queued, 2 dropped\n  PDM 1 channel 3: 12 actuations, 1.5s on"
+        );
+        let failed = CropBedPowerStatus {
+            tasks_failed: 1,
+            ..status
+        };
+        assert!(failed.to_string().starts_with("Crop bed power on can1: 6 queued, 2 dropped, 1 tasks failed\n"));
     }
 
-    /// Create a new `PdmConfig` by reading parameters stored in a file.
+    /// Queue message turning channel 1 on at a time.
     ///
-    /// * `filepath`: filepath to the stored parameters.
-    pub fn from_file<F: AsRef<OsStr>>(filepath: F) -> Self {
-        let file = Path::new(&filepath);
-        if file.is_file() {
-            let config_file = config::Config::builder()
-                .add_source(config::File::new(
-                    &file.to_string_lossy(),
-                    config::FileFormat::Yaml,
-                ))
-                .build()
-                .expect("Failed read config");
-
-            config_file
-                .try_deserialize::<CropBedPowerConfig>()
-                .expect("Failed to parse config file into struct")
-        } else {
-            panic!("Could not locate the config file {:?}", file);
+    /// * `time_to_fire`: time the message is due.
+    fn queue_message(time_to_fire: DateTime<Utc>) -> WeedQueueMessage {
+        WeedQueueMessage {
+            channels: vec![(0, 1)],
+            time_to_fire,
+            is_on: true,
+            original_spray_starts: time_to_fire,
+            original_spray_ending: time_to_fire,
+            pwm: None,
+            timed_for: None,
+            manual: false,
+            received_at: time_to_fire,
+            position: None,
         }
     }
-}
 
-/// Component for managing the crop bed power in one module.
-/// Currently this consists of two PDMs, but could be increased
-/// to as many as allowed on the canbus network (pending addressing
-/// clashing)
-#[allow(dead_code)]
-pub struct CropBedPower {
-    /// Unique identifier for the component.
-    uuid: Uuid,
-    /// ID of the specific crop bed module.
-    crop_bed_id: u8,
-    /// Canbus interface name.
-    canbus_id: String,
-    /// Map of the Pdm drivers.
-    pdms: HashMap<u8, Pdm>,
-    /// Internal linux port the component will be commanded on
-    port: i32,
-    /// Message queue that stores upcoming actions.
-    message_queue: DoublePriorityQueue<WeedQueueMessage, DateTime<Utc>>,
-    /// Channel maps for the PDMs when the wiring harness does
-    /// not logically map to the solenoid numbers.
-    channel_map: Option<HashMap<u8, (u8, u8)>>,
-}
+    #[tokio::test]
+    /// The firing task sleeps until the heartbeat when the queue is empty,
+    /// until just before the next message otherwise, and is notified when
+    /// a message due sooner arrives.
+    async fn test_next_wake() {
+        let mut power = CropBedPower::new(CropBedPowerConfig::new(
+            CropBed::LeftBoom,
+            String::from("can0"),
+            17650,
+            None,
+        ))
+        .unwrap();
+        let last_fire = Instant::now();
+        assert_eq!(power.next_wake(last_fire), last_fire + power.timing.heartbeat_interval());
 
-impl CropBedPower {
-    /// Create a new component from a config struct.
-    ///
-    /// * `config`: Struct containing the parameters for configuration.
-    pub fn new(config: CropBedPowerConfig) -> Self {
-        Self {
-            uuid: Uuid::new_v4(),
-            port: config.port,
-            crop_bed_id: config.crop_bed_id,
-            canbus_id: config.canbus_id.clone(),
-            channel_map: config.channel_map.clone(),
-            pdms: Self::build_from_config(config),
-            message_queue: DoublePriorityQueue::new(),
-        }
+        power.add_to_message_queue(queue_message(Utc::now() + Duration::milliseconds(200)));
+        let wake = power.next_wake(last_fire);
+        let until_wake = wake - Instant::now();
+        assert!(
+            until_wake > tokio::time::Duration::from_millis(180) && until_wake < tokio::time::Duration::from_millis(200),
+            "Waking in {until_wake:?}"
+        );
+        // Consume the notification from the first message.
+        tokio::time::timeout(tokio::time::Duration::from_millis(10), power.queue_changed.notified())
+            .await
+            .expect("First message did not notify");
+
+        power.add_to_message_queue(queue_message(Utc::now() + Duration::milliseconds(300)));
+        assert!(
+            tokio::time::timeout(tokio::time::Duration::from_millis(10), power.queue_changed.notified())
+                .await
+                .is_err(),
+            "A later message woke the firing task"
+        );
+        power.add_to_message_queue(queue_message(Utc::now() + Duration::milliseconds(50)));
+        tokio::time::timeout(tokio::time::Duration::from_millis(10), power.queue_changed.notified())
+            .await
+            .expect("Sooner message did not notify");
+        assert!(power.next_wake(last_fire) < wake);
+
+        power.add_to_message_queue(queue_message(Utc::now() - Duration::milliseconds(5)));
+        assert!(power.next_wake(last_fire) <= Instant::now());
     }
 
-    /// Create a new component by reading the config parameters from a file.
-    ///
-    /// * `filepath`: path to config file.
-    pub fn from_config_file<F: AsRef<OsStr>>(filepath: F) -> Self {
-        let config = CropBedPowerConfig::from_file(filepath);
-        Self::new(config)
+    #[test]
+    /// Times ahead are scaled from now, times passed are kept.
+    fn test_retime() {
+        let now = Utc::now();
+        assert_eq!(retime(now + Duration::milliseconds(400), now, 0.5), now + Duration::milliseconds(200));
+        assert_eq!(retime(now + Duration::milliseconds(400), now, 1.5), now + Duration::milliseconds(600));
+        assert_eq!(retime(now - Duration::milliseconds(400), now, 0.5), now - Duration::milliseconds(400));
     }
 
-    /// Helper function used to build the resulting component.
+    /// Queue the on and off of a spray on a channel timed for a speed.
     ///
-    /// * `config`: struct with configuration parameters.
-    fn build_from_config(config: CropBedPowerConfig) -> HashMap<u8, Pdm> {
-        let mut pdms = HashMap::new();
-        for (bed_position, pdm_config_file) in config.pdm_config_files {
-            let pdm = Pdm::new(PdmConfig::from_file(pdm_config_file));
-            pdms.insert(bed_position, pdm);
+    /// * `power`: component
+    /// * `channel`: channel to spray.
+    /// * `start`: time to start spraying.
+    /// * `end`: time to stop spraying.
+    /// * `timed_for`: ground speed in metres per second the times are for.
+    fn queue_spray(
+        power: &mut CropBedPower,
+        channel: u8,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        timed_for: Option<f64>,
+    ) {
+        for (time_to_fire, is_on) in [(start, true), (end, false)] {
+            power.add_to_message_queue(WeedQueueMessage {
+                channels: vec![(0, channel)],
+                time_to_fire,
+                is_on,
+                original_spray_starts: start,
+                original_spray_ending: end,
+                pwm: None,
+                timed_for: timed_for.map(GroundSpeed::from_mps),
+                manual: false,
+                received_at: start,
+                position: None,
+            });
         }
-        pdms
     }
 
-    /// Add weed message to queue once parsed from the AI system.
-    /// NOTE: There is an edge case where a channel will send a
-    /// message that may turn off another channel pre-emptively
-    /// this message was stripped out in a previous implementation
-    /// of this function however there still appeared to be noise.
-    /// On further investigation it became clear that there was
-    /// some additional messages being sent by the AI system both
-    /// factually (i.e. weeds moving in the wind), and *Potentially*
-    /// erroneously (array indexing) which may have been contributing
-    /// to the noise or in the very least making it hard to trouble
-    /// shoot. See git commit history for those naive implementations
-    /// once the AI message generation has been confirmed.
+    /// Fire times of the queued messages for a channel, the on then the off.
     ///
-    /// * `message`: message parsed from AI container.
-    fn add_to_message_queue(&mut self, message: WeedQueueMessage) {
-        let priority = message.time_to_fire;
-        self.message_queue.push(message, priority);
+    /// * `power`: component
+    /// * `channel`: channel of the spray.
+    fn fire_times(power: &CropBedPower, channel: u8) -> Vec<DateTime<Utc>> {
+        let mut fire_times: Vec<(bool, DateTime<Utc>)> = power
+            .message_queue
+            .iter()
+            .filter(|(message, _)| message.crop_bed_channels() == [channel])
+            .map(|(message, priority)| (!message.is_on, *priority))
+            .collect();
+        fire_times.sort();
+        fire_times.into_iter().map(|(_, priority)| priority).collect()
     }
 
-    /// I dislike this implementation, will need to work on the image messages being
-    /// sent through to the control system, or some kind of state machine which can
-    // be polled by futures. Ultimately it will change with the inclusion of a wheel
-    // speed sensor anyway.
-    // INFO: The PDMs actuate channels based in blocks 1-12, 13-24 and need to be
-    //       split up accordingly.
-    async fn process_message_queue(&mut self, mut last_fire: Instant) -> Instant {
-        if let Some((message, priority)) = self.message_queue.peek_min() {
-            let utc_now = Utc::now();
-            // TODO: this bound could be adjusted as the thread sleep can miss by 1-2 microseconds which
-            //       means that messages could be discarded by being 1 microsecond behind which for this
-            //      system seems unreasonable as it is more beneficial to over spray than under spray.
-            if *priority < utc_now {
-                self.message_queue.pop_min();
-            } else if let Some(delta_t) = (*priority - utc_now).num_microseconds() {
-                // check if the delta is within SPRAY_BOUND microseconds (positive)
-                if delta_t < SPRAY_BOUND {
-                    // The first iteration of the messages coming from AI needed to check for this
-                    // condition however the AI messages have changed several times as well as the
-                    // partitioning of the channels so this section can most likely be removed. The
-                    // implementation for the vector of required channels is much cleaner using the
-                    // partition.
-                    // TODO: Add test to confirm and then remove.
-                    if message.channels.len() == 1 {
-                        if message.channels[0] <= 12 {
-                            if let Some(pdm) = self.pdms.get(&0) {
-                                let pwm = if message.is_on { 100.0 } else { 0.0 };
-                                let channels = vec![message.channels[0]];
-                                pdm.driver.actuate_channels(17, channels, pwm).await;
-                            }
-                        } else if let Some(pdm) = self.pdms.get(&1) {
-                            let pwm = if message.is_on { 100.0 } else { 0.0 };
-                            let channels = vec![message.channels[0] - 12];
-                            pdm.driver.actuate_channels(17, channels, pwm).await;
-                        }
-                    } else {
-                        // TODO: remove in line 12, and move to const module, or PDM config.
-                        let (pdm_0, pdm_1): (_, Vec<_>) = message
-                            .channels
-                            .clone()
-                            .into_iter()
-                            .partition(|x| (*x <= 12));
-                        if !pdm_0.is_empty() {
-                            if let Some(pdm) = self.pdms.get(&0) {
-                                let pwm = if message.is_on { 100.0 } else { 0.0 };
-                                pdm.driver.actuate_channels(17, pdm_0, pwm).await;
-                            }
-                        }
-                        if !pdm_1.is_empty() {
-                            if let Some(pdm) = self.pdms.get(&1) {
-                                let pwm = if message.is_on { 100.0 } else { 0.0 };
-                                let channels = pdm_1.clone().iter().map(|x| x - 12).collect();
-                                pdm.driver.actuate_channels(17, channels, pwm).await;
-                            }
-                        }
-                    }
-                    // No need for heartbeat message as we just sent the above.
-                    last_fire = Instant::now();
-                    self.message_queue.pop_min();
-                }
+    #[test]
+    /// Doubling the speed halves the time to both the on and off of a
+    /// spray, slowing again stretches them from the time of the change, and
+    /// messages without a speed keep their times.
+    fn test_ground_speed_shifts_queue() {
+        use schedule::SprayInterval;
+
+        let mut power = CropBedPower::new(CropBedPowerConfig::new(
+            CropBed::LeftBoom,
+            String::from("can0"),
+            17650,
+            None,
+        ))
+        .unwrap();
+        let now = Utc::now();
+        let at = |ms| now + Duration::milliseconds(ms);
+        queue_spray(&mut power, 1, at(1000), at(1200), Some(1.0));
+        queue_spray(&mut power, 2, at(1000), at(1200), None);
+
+        power.update_ground_speed(Some(GroundSpeed::from_mps(2.0)), now);
+        assert_eq!(fire_times(&power, 1), vec![at(500), at(600)]);
+        assert_eq!(fire_times(&power, 2), vec![at(1000), at(1200)]);
+        assert_eq!(
+            power.spray_schedule.intervals(1),
+            [SprayInterval {
+                start: at(500),
+                end: at(600),
+            }]
+        );
+
+        // Too slow to follow, the queue is left alone.
+        power.update_ground_speed(Some(GroundSpeed::from_mps(0.01)), at(100));
+        assert_eq!(fire_times(&power, 1), vec![at(500), at(600)]);
+
+        power.update_ground_speed(Some(GroundSpeed::from_mps(1.0)), at(100));
+        assert_eq!(fire_times(&power, 1), vec![at(900), at(1100)]);
+        assert_eq!(fire_times(&power, 2), vec![at(1000), at(1200)]);
+    }
+
+    #[test]
+    /// Ons and offs are fired ahead by the latency of each channel's
+    /// solenoid, channels that differ split out, and an on that would have
+    /// to fire in the past is clamped to now and counted.
+    fn test_solenoid_latency_compensation() {
+        use solenoid::ChannelLatency;
+
+        let solenoid_latency = SolenoidLatencyConfig {
+            open_latency_ms: 10,
+            close_latency_ms: 15,
+            channels: BTreeMap::from([(
+                2,
+                ChannelLatency {
+                    open_latency_ms: Some(20),
+                    close_latency_ms: None,
+                },
+            )]),
+        };
+        let mut power = CropBedPower::new(
+            CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None)
+                .with_solenoid_latency(solenoid_latency),
+        )
+        .unwrap();
+        let now = Utc::now();
+        let at = |ms| now + Duration::milliseconds(ms);
+        let queued_actions =
+            power.queue_spray(vec![(0, 1), (0, 2), (0, 3)], at(1000), at(1100), 100, None, false, now, None);
+        assert_eq!(queued_actions, 4);
+        assert_eq!(fire_times(&power, 2), vec![at(980), at(1085)]);
+        let grouped: Vec<_> = power
+            .message_queue
+            .iter()
+            .filter(|(message, _)| message.crop_bed_channels() == [1, 3])
+            .map(|(_, priority)| *priority)
+            .collect();
+        assert_eq!(grouped.len(), 2);
+        assert!(grouped.contains(&at(990)) && grouped.contains(&at(1085)));
+        assert_eq!(power.compensation_clamped, 0);
+
+        power.message_queue.clear();
+        let before = Utc::now();
+        power.queue_spray(
+            vec![(0, 2)],
+            before + Duration::milliseconds(5),
+            before + Duration::milliseconds(100),
+            100,
+            None,
+            false,
+            before,
+            None,
+        );
+        let fire_times = fire_times(&power, 2);
+        assert!(fire_times[0] >= before, "Fired before now at {}", fire_times[0]);
+        assert_eq!(fire_times[1], before + Duration::milliseconds(85));
+        assert_eq!(power.compensation_clamped, 1);
+    }
+
+    #[test]
+    /// A full queue drops the sprays furthest in the future, whole, and the
+    /// drops show in the status.
+    fn test_queue_drops_furthest_sprays() {
+        let mut power = CropBedPower::new(
+            CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None).with_max_queue_len(4),
+        )
+        .unwrap();
+        let now = Utc::now();
+        let at = |ms| now + Duration::milliseconds(ms);
+        queue_spray(&mut power, 1, at(1000), at(1100), None);
+        queue_spray(&mut power, 2, at(2000), at(2100), None);
+        // Furthest out, dropped as soon as it arrives.
+        queue_spray(&mut power, 3, at(3000), at(3100), None);
+        assert!(fire_times(&power, 3).is_empty());
+        assert_eq!(power.queue_dropped, 2);
+
+        // Sooner than the rest, so the spray on channel 2 makes room.
+        queue_spray(&mut power, 4, at(500), at(600), None);
+        assert_eq!(fire_times(&power, 4), vec![at(500), at(600)]);
+        assert_eq!(fire_times(&power, 1), vec![at(1000), at(1100)]);
+        assert!(fire_times(&power, 2).is_empty());
+        let status = power.status();
+        assert_eq!((status.queue_depth, status.queue_dropped), (4, 4));
+    }
+
+    #[test]
+    /// A spray that has started keeps its off even when it is the furthest
+    /// message in a full queue.
+    fn test_queue_keeps_started_sprays() {
+        let mut power = CropBedPower::new(
+            CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None).with_max_queue_len(2),
+        )
+        .unwrap();
+        let now = Utc::now();
+        let at = |ms| now + Duration::milliseconds(ms);
+        queue_spray(&mut power, 1, at(-100), at(5000), None);
+        queue_spray(&mut power, 2, at(1000), at(1100), None);
+        assert_eq!(fire_times(&power, 1), vec![at(-100), at(5000)]);
+        assert!(fire_times(&power, 2).is_empty());
+        assert_eq!(power.queue_dropped, 2);
+        assert_eq!(
+            power.spray_schedule.intervals(1).len(),
+            1,
+            "Spray intervals not rebuilt from the queue"
+        );
+    }
+
+    #[tokio::test]
+    /// Speeds reported by a simulated wheel speed sensor reach the queue,
+    /// shifting the pair of a spray together.
+    async fn test_simulated_speed_shifts_fire_times() {
+        use crate::devices::software::wheel_speed::SimulatedSpeedSource;
+
+        let mut power = CropBedPower::new(CropBedPowerConfig::new(
+            CropBed::LeftBoom,
+            String::from("can0"),
+            17650,
+            None,
+        ))
+        .unwrap();
+        let start = Utc::now() + Duration::seconds(10);
+        let end = start + Duration::milliseconds(400);
+        queue_spray(&mut power, 1, start, end, Some(1.0));
+        let power = Arc::new(Mutex::new(power));
+
+        let (source, handle) = SimulatedSpeedSource::new();
+        let follower = tokio::spawn(follow_ground_speed(WheelSpeedSensor::start(Box::new(source)), power.clone()));
+        handle.set_speed(4.0);
+        let mut shifted = Vec::new();
+        for _ in 0..50 {
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+            shifted = fire_times(&*power.lock().await, 1);
+            if shifted[0] < start {
+                break;
             }
         }
+        // The spray is a quarter of the time away and a quarter as long.
+        let until_start = shifted[0] - Utc::now();
+        assert!(
+            until_start > Duration::milliseconds(2300) && until_start < Duration::milliseconds(2600),
+            "Starting in {until_start}"
+        );
+        let duration = shifted[1] - shifted[0];
+        assert!(
+            (duration - Duration::milliseconds(100)).num_microseconds().is_some_and(|error| error.abs() < 1),
+            "Spraying for {duration}"
+        );
 
-        // The PDM loss of can feature will come online when a signal has not
-        // been received every second. This last fire signal helps keep the
-        // PDM online by sending a heartbeat.
-        if last_fire.elapsed() > tokio::time::Duration::from_millis(500) {
-            // TODO: Potentially wrap a config handshake in here to ensure the
-            // PDM has not drifted to another state.
-            if let Some(pdm) = self.pdms.get(&0) {
-                pdm.driver
-                    .actuate_channels(17, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12], 0.0)
-                    .await;
-            }
-            if let Some(pdm) = self.pdms.get(&1) {
-                pdm.driver
-                    .actuate_channels(17, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12], 0.0)
-                    .await;
-            }
-            last_fire = Instant::now();
+        drop(handle);
+        follower.await.unwrap();
+        assert_eq!(power.lock().await.ground_speed, None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// A run of weed messages fires within a few milliseconds of the
+    /// requested times with the firing task sleeping between them.
+    async fn test_fire_times_against_simulated_pdm() {
+        use crate::devices::{
+            hardware::pdm::PdmAddress,
+            software::pdm::{ActuationCause, SimulatedPdm},
+        };
+
+        let port = 17661;
+        let config_dir = std::env::temp_dir().join(format!("onyx-fire-times-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config = PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), port, None)
+            .add_pdm_config_file(pdm_config_file, 0);
+        let simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
+            .await
+            .unwrap();
+
+        // Spread out so each message waits on its own sleep, sent out of
+        // order so later arrivals have to wake the task early.
+        let first = Utc::now() + Duration::milliseconds(400);
+        let requested: Vec<(u8, DateTime<Utc>)> = (0..5)
+            .map(|index| (index, first + Duration::milliseconds(150 * i64::from(index))))
+            .collect();
+        for (channel, start_spray_time) in requested.iter().rev() {
+            send_weed_message(port, &[*channel], *start_spray_time, *start_spray_time + Duration::milliseconds(50)).await;
         }
-        last_fire
+        tokio::time::sleep(tokio::time::Duration::from_millis(1400)).await;
+        component.shutdown().await;
+
+        for (channel, start_spray_time) in &requested {
+            let fired: Vec<_> = simulated
+                .actuations_of(channel + 1)
+                .into_iter()
+                .filter(|record| record.cause == ActuationCause::Command && record.channels.len() == 1)
+                .collect();
+            assert_eq!(fired.len(), 2, "Channel {} saw {:?}", channel + 1, fired);
+            let error = (fired[0].at - *start_spray_time).num_microseconds().unwrap().abs();
+            assert!(error < 3000, "Channel {} fired {error}us from the requested time", channel + 1);
+        }
+        std::fs::remove_dir_all(config_dir).unwrap();
     }
-}
 
-/// Unit struct for adding controlling behaviour to the crop bed power.
-pub struct CropBedPowerController;
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// Shutting down in the middle of a spray refuses new connections and
+    /// ends with an explicit off for every channel of every PDM, before
+    /// the spray was due to end and without waiting on the loss of CAN
+    /// cutoff.
+    async fn test_shutdown_turns_every_channel_off() {
+        use crate::devices::{
+            hardware::pdm::PdmAddress,
+            software::pdm::{ActuationCause, SimulatedPdm},
+        };
 
-impl CropBedPowerController {
-    /// Start the crop bed power component.
+        let port = 17662;
+        let config_dir = std::env::temp_dir().join(format!("onyx-shutdown-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let mut config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), port, None);
+        let mut simulated = Vec::new();
+        for (bed_position, address) in [(0, PdmAddress::Pdm30), (1, PdmAddress::Pdm31)] {
+            let pdm_config = PdmConfig::new(address, bed_position)
+                .with_response_timeout(std::time::Duration::from_millis(200));
+            let pdm_config_file = config_dir.join(format!("pdm_{bed_position}.yaml"));
+            serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+            config = config.add_pdm_config_file(pdm_config_file, bed_position);
+            simulated.push(
+                SimulatedPdm::new(pdm_config)
+                    .start(&vcan_interface())
+                    .expect("Failed to start simulated PDM"),
+            );
+        }
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
+            .await
+            .unwrap();
+
+        let start_spray_time = Utc::now() + Duration::milliseconds(100);
+        let end_spray_time = start_spray_time + Duration::seconds(3);
+        send_weed_message(port, &[1, 14], start_spray_time, end_spray_time).await;
+        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+        assert_eq!(simulated[0].output(2), Some(100.0), "Spray did not start");
+
+        component.shutdown().await;
+        assert!(Utc::now() < end_spray_time);
+        assert!(
+            TcpStream::connect(format!("127.0.0.1:{port}")).await.is_err(),
+            "Component still accepting connections"
+        );
+        for pdm in &simulated {
+            let last = pdm.actuations().pop().expect("PDM saw no actuations");
+            assert_eq!(last.cause, ActuationCause::Command, "PDM {} was not turned off", pdm.address());
+            assert!(last.duty_percent.abs() < f32::EPSILON);
+            assert_eq!(last.channels, (1..=CHANNEL_COUNT).collect::<Vec<_>>());
+            for channel in 1..=CHANNEL_COUNT {
+                assert_eq!(pdm.output(channel), Some(0.0), "PDM {} channel {channel} left on", pdm.address());
+            }
+        }
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    /// Start the component against one simulated PDM on the virtual canbus.
     ///
-    /// * `crop_bed_power`: component
-    pub async fn start(mut crop_bed_power: CropBedPower) {
-        let interface = Arc::new(Mutex::new(
-            AsyncCanSocket::open(&crop_bed_power.canbus_id)
-                .expect("Failed to create canbus socket"),
-        ));
+    /// * `port`: port the component listens on.
+    /// * `config_dir`: directory the PDM config is written to.
+    /// * `shutdown`: shutdown mode of the component, immediate when not set.
+    async fn start_with_simulated_pdm(
+        port: i32,
+        config_dir: &Path,
+        shutdown: Option<ShutdownConfig>,
+    ) -> (CropBedPowerHandle, crate::devices::software::pdm::SimulatedPdmHandle) {
+        use crate::devices::{hardware::pdm::PdmAddress, software::pdm::SimulatedPdm};
 
-        for pdm in crop_bed_power.pdms.values_mut() {
-            pdm.initialise(interface.clone()).await;
+        std::fs::create_dir_all(config_dir).unwrap();
+        let pdm_config =
+            PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let mut config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), port, None)
+            .add_pdm_config_file(pdm_config_file, 0);
+        if let Some(shutdown) = shutdown {
+            config = config.with_shutdown(shutdown);
         }
-        // Bind on the loop back port from within the container
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", crop_bed_power.port))
+        let simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
             .await
-            .expect("Failed to bind port");
+            .unwrap();
+        (component, simulated)
+    }
 
-        let thread_safe_crop_bed_power = Arc::new(Mutex::new(crop_bed_power));
+    /// Duty cycles a channel was commanded to on its own, leaving out the
+    /// heartbeats and the final all off.
+    ///
+    /// * `pdm`: simulated PDM.
+    /// * `channel`: one based channel on the PDM.
+    fn commanded(
+        pdm: &crate::devices::software::pdm::SimulatedPdmHandle,
+        channel: u8,
+    ) -> Vec<crate::devices::software::pdm::ActuationRecord> {
+        use crate::devices::software::pdm::ActuationCause;
 
-        let power_processing = thread_safe_crop_bed_power.clone();
+        pdm.actuations_of(channel)
+            .into_iter()
+            .filter(|record| record.cause == ActuationCause::Command && record.channels.len() == 1)
+            .collect()
+    }
 
-        // PDM message firing task.
-        tokio::spawn(async move {
-            let mut last_fire = Instant::now();
-            loop {
-                let mut gaurd = power_processing.lock().await;
-                last_fire = gaurd.process_message_queue(last_fire).await;
-                drop(gaurd);
-            }
-        });
-        // Looping message parsing task.
-
-        // TODO: Remove the continue, picked up with more strict clippy linting.
-        //       very straight forward. Good first issue.
-        #[allow(clippy::needless_continue)]
-        loop {
-            if let Ok((socket, _)) = listener.accept().await {
-                let power_connection = thread_safe_crop_bed_power.clone();
-                tokio::spawn(async move {
-                    handle_connection(socket, power_connection).await;
-                });
-            } else {
-                continue;
-            }
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// Draining fires a spray within the horizon in full, cuts a spray
+    /// running past the horizon at its end and never starts a spray after
+    /// it, before turning every channel off.
+    async fn test_shutdown_drains_within_horizon() {
+        let port = 17698;
+        let config_dir = std::env::temp_dir().join(format!("onyx-drain-{}", Uuid::new_v4()));
+        let (component, simulated) =
+            start_with_simulated_pdm(port, &config_dir, Some(ShutdownConfig::drain(500))).await;
+
+        let now = Utc::now();
+        let at = |ms| now + Duration::milliseconds(ms);
+        send_weed_message(port, &[1], at(200), at(400)).await;
+        send_weed_message(port, &[3], at(300), at(2000)).await;
+        send_weed_message(port, &[5], at(1000), at(1200)).await;
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        let stopped_at = Utc::now();
+        let status = component.shutdown().await;
+        assert_eq!(status.tasks_failed, 0);
+        assert!(Utc::now() < at(1000), "Drained past the horizon");
+
+        let in_horizon = commanded(&simulated, 2);
+        assert_eq!(in_horizon.len(), 2, "PDM saw {in_horizon:?}");
+        assert!((in_horizon[0].duty_percent - 100.0).abs() < f32::EPSILON);
+        assert!(in_horizon[1].duty_percent.abs() < f32::EPSILON);
+        assert!(in_horizon[1].at >= at(350), "Spray ended early");
+
+        let straddling = commanded(&simulated, 4);
+        assert_eq!(straddling.len(), 2, "PDM saw {straddling:?}");
+        assert!(straddling[1].duty_percent.abs() < f32::EPSILON);
+        let cut_after = straddling[1].at - stopped_at;
+        assert!(
+            cut_after >= Duration::milliseconds(400) && cut_after < Duration::milliseconds(800),
+            "Spray cut {cut_after} after the stop"
+        );
+
+        assert!(commanded(&simulated, 6).is_empty(), "Spray after the horizon fired");
+        assert_eq!(simulated.output(6), Some(0.0));
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// A drain outlasting the shutdown timeout is given up on with every
+    /// channel turned off, the spray it was holding open cut and nothing
+    /// fired after.
+    async fn test_timed_out_shutdown_turns_all_off() {
+        let port = 17707;
+        let config_dir = std::env::temp_dir().join(format!("onyx-shutdown-timeout-{}", Uuid::new_v4()));
+        let (component, simulated) =
+            start_with_simulated_pdm(port, &config_dir, Some(ShutdownConfig::drain(2000))).await;
+
+        let now = Utc::now();
+        let at = |ms| now + Duration::milliseconds(ms);
+        send_weed_message(port, &[1], at(100), at(1800)).await;
+        send_weed_message(port, &[3], at(1000), at(1200)).await;
+        tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
+        assert_eq!(simulated.output(2), Some(100.0), "Spray did not start");
+
+        let status = component
+            .shutdown_within(None, std::time::Duration::from_millis(300))
+            .await;
+        assert!(status.is_none(), "Drain finished within the timeout");
+        assert!(Utc::now() < at(1000), "Shutdown waited past the timeout");
+        for channel in 1..=CHANNEL_COUNT {
+            assert_eq!(simulated.output(channel), Some(0.0), "Channel {channel} left on");
         }
+        tokio::time::sleep(tokio::time::Duration::from_millis(1200)).await;
+        assert!(commanded(&simulated, 4).is_empty(), "Spray fired after the timeout");
+        assert_eq!(simulated.output(2), Some(0.0));
+        std::fs::remove_dir_all(config_dir).unwrap();
     }
-}
 
-/// Handle connection from the AI container when it sends a message.
-///
-/// * `socket`: `TcpStream`
-/// * `power`: component
-// NOTE: This interface was the issue that wasted ~ 2 weeks during testing, the previous
-//       implementation relied on a long standing connection from another container and
-//       taking messages off the wire at '\b', however the starmap from the AI system
-//       created a new connection every time it sent a message, this lead to an
-//       enormous amount of useless tokio tasks that would be looped and polled.
-// TODO: Review starmap and connection function between two systems.
-async fn handle_connection(mut socket: TcpStream, power: Arc<Mutex<CropBedPower>>) {
-    let (read_stream, _) = socket.split();
-    let mut read_stream = BufReader::new(read_stream);
-    let mut data = Vec::new();
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// Stopping immediately, the default, fires nothing left in the queue.
+    async fn test_shutdown_immediate_drops_queue() {
+        let port = 17699;
+        let config_dir = std::env::temp_dir().join(format!("onyx-immediate-{}", Uuid::new_v4()));
+        let (component, simulated) = start_with_simulated_pdm(port, &config_dir, None).await;
 
-    data.clear();
-    let _bytes_read = read_stream
-        .read_until(b'\n', &mut data)
-        .await
-        .expect("Failed to read buffer");
-    match serde_json::from_slice::<WeedMessage>(&data) {
-        Ok(message) => {
-            if message.start_spray_time > Utc::now() {
-                let mut delta = message.end_spray_time - message.start_spray_time;
-
-                let mut channels = Vec::new();
-                let mut gaurd = power.lock().await;
-
-                for channel in message.channels_to_open {
-                    // The electrical team needed to wire the PDMs in a specific way to make
-                    // it easier for physical manufacturing. This means that on some crop beds
-                    // that the channel numbers do not coincide with the channel numbers of the
-                    // PDM. This mapping can be very confusing to trouble shoot.
-                    if let Some(ref channel_map) = gaurd.channel_map {
-                        let (converted, _pdm) =
-                            channel_map.get(&(channel + 1)).expect("No channel map");
-                        channels.push(*converted);
-                    } else {
-                        channels.push(channel + 1);
-                    }
-                }
-                // PDM will cut off after 1 second, so longer durations require to have
-                // the message queue to be padded out.
-                // TODO: pull out 100 to a constant in utils.
-                if delta > Duration::seconds(1) {
-                    let mut time_to_fire = message.start_spray_time;
-                    while delta > Duration::milliseconds(100) {
-                        let power_ons = WeedQueueMessage {
-                            channels: channels.clone(),
-                            time_to_fire: time_to_fire + Duration::milliseconds(100),
-                            is_on: true,
-                            original_spray_starts: message.start_spray_time,
-                            original_spray_ending: message.end_spray_time,
-                        };
-                        gaurd.add_to_message_queue(power_ons);
-                        time_to_fire += Duration::milliseconds(100);
-                        delta = delta - Duration::milliseconds(100);
-                    }
-                    let power_off = WeedQueueMessage {
-                        channels: channels.clone(),
-                        time_to_fire: message.end_spray_time,
-                        is_on: false,
-                        original_spray_starts: message.start_spray_time,
-                        original_spray_ending: message.end_spray_time,
-                    };
-                    gaurd.add_to_message_queue(power_off);
-                } else {
-                    let power_ons = WeedQueueMessage {
-                        channels: channels.clone(),
-                        time_to_fire: message.start_spray_time,
-                        is_on: true,
-                        original_spray_starts: message.start_spray_time,
-                        original_spray_ending: message.end_spray_time,
-                    };
+        let now = Utc::now();
+        let at = |ms| now + Duration::milliseconds(ms);
+        send_weed_message(port, &[1], at(300), at(500)).await;
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        component.shutdown().await;
+        tokio::time::sleep(tokio::time::Duration::from_millis(600)).await;
 
-                    let power_off = WeedQueueMessage {
-                        channels,
-                        time_to_fire: message.end_spray_time,
-                        is_on: false,
-                        original_spray_starts: message.start_spray_time,
-                        original_spray_ending: message.end_spray_time,
-                    };
-                    gaurd.add_to_message_queue(power_ons);
-                    gaurd.add_to_message_queue(power_off);
-                }
-                // Make sure to drop the guard strait after using in the loop.
-                drop(gaurd);
-            } else {
-                println!("Message Ignored, recieved to late from analysis system");
+        assert!(commanded(&simulated, 2).is_empty(), "Queued spray fired");
+        assert_eq!(simulated.output(2), Some(0.0));
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// An emergency stop cuts a spray at once and stops the component,
+    /// which then shuts down without draining even when configured to.
+    async fn test_emergency_stop_skips_drain() {
+        let port = 17700;
+        let config_dir = std::env::temp_dir().join(format!("onyx-estop-{}", Uuid::new_v4()));
+        let (mut component, simulated) =
+            start_with_simulated_pdm(port, &config_dir, Some(ShutdownConfig::drain(2000))).await;
+
+        let now = Utc::now();
+        let at = |ms| now + Duration::milliseconds(ms);
+        send_weed_message(port, &[1], at(100), at(3000)).await;
+        send_weed_message(port, &[3], at(800), at(1000)).await;
+        tokio::time::sleep(tokio::time::Duration::from_millis(400)).await;
+        assert_eq!(simulated.output(2), Some(100.0), "Spray did not start");
+
+        component.component().lock().await.emergency_stop().await;
+        assert_eq!(simulated.output(2), Some(0.0), "Spray not cut");
+        tokio::time::timeout(tokio::time::Duration::from_secs(1), component.emergency_stopped())
+            .await
+            .expect("Emergency stop not reported");
+        tokio::time::timeout(tokio::time::Duration::from_secs(1), component.stopped())
+            .await
+            .expect("Tasks still running")
+            .unwrap();
+        component.shutdown().await;
+        tokio::time::sleep(tokio::time::Duration::from_millis(800)).await;
+
+        assert!(commanded(&simulated, 4).is_empty(), "Queued spray fired after the stop");
+        assert_eq!(simulated.output(2), Some(0.0));
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[rstest]
+    #[case::idle_channels(HeartbeatStrategy::IdleChannels, 100.0)]
+    #[case::all_channels(HeartbeatStrategy::AllChannels, 0.0)]
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// A channel held on past several heartbeats is never zeroed by the idle
+    /// channel heartbeat, which still reaches the simulated PDM for the
+    /// other channels, while the heartbeat to every channel turns it off.
+    async fn test_heartbeat_keeps_held_channel_on(#[case] heartbeat: HeartbeatStrategy, #[case] held_pwm: f32) {
+        use crate::devices::{
+            hardware::pdm::PdmAddress,
+            software::pdm::{ActuationCause, SimulatedPdm},
+        };
+
+        let port = 17671;
+        let config_dir = std::env::temp_dir().join(format!("onyx-heartbeat-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let pdm_config =
+            PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+        let pdm_config_file = config_dir.join("pdm_0.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let timing = PowerTiming {
+            heartbeat_interval_ms: 100,
+            ..PowerTiming::default()
+        };
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), port, None)
+            .add_pdm_config_file(pdm_config_file, 0)
+            .with_timing(timing)
+            .with_heartbeat(heartbeat);
+        let simulated = SimulatedPdm::new(pdm_config)
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
+            .await
+            .unwrap();
+
+        component.power.lock().await.pdms[&0].actuate_channels(vec![3], 100.0).await;
+        tokio::time::sleep(tokio::time::Duration::from_millis(600)).await;
+        let held = simulated.output(3);
+        let heartbeats = simulated
+            .actuations()
+            .into_iter()
+            .filter(|record| record.cause == ActuationCause::Command && record.duty_percent.abs() < f32::EPSILON)
+            .count();
+        component.shutdown().await;
+
+        assert_eq!(held, Some(held_pwm));
+        assert!(heartbeats >= 3, "PDM saw {heartbeats} heartbeats");
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[tokio::test]
+    /// A task panicking is handed back by the handle, which still shuts the
+    /// rest of the component down cleanly.
+    async fn test_handle_reports_panicked_task() {
+        let power = queue_only_power();
+        let (stop_tx, mut stop_rx) = watch::channel(false);
+        let mut handle = CropBedPowerHandle {
+            power: power.clone(),
+            stop_tx: Arc::new(stop_tx),
+            tasks: vec![
+                (
+                    "listener",
+                    tokio::spawn(async move {
+                        while !stopping(&stop_rx) {
+                            let _ = stop_rx.changed().await;
+                        }
+                    }),
+                ),
+                ("firing task", tokio::spawn(async { panic!("Firing failed") })),
+            ],
+            monitors: Vec::new(),
+            journal_writer: None,
+            emergency_rx: watch::channel(false).1,
+        };
+        assert!(handle.stopped().await.unwrap_err().is_panic());
+        assert_eq!(handle.snapshot().await.queue_depth, 0);
+        assert!(Arc::ptr_eq(&handle.component(), &power));
+        handle.shutdown().await;
+    }
+
+    /// Channels each queued message is sent to, firing the queue in order,
+    /// as whether it is an on and the channels.
+    ///
+    /// * `power`: component
+    fn emitted(power: &mut CropBedPower) -> Vec<(bool, Vec<u8>)> {
+        let mut emitted = Vec::new();
+        while let Some((message, priority)) = power.message_queue.pop_min() {
+            let (channels, debounced) =
+                channels_to_fire(&message, priority, &power.spray_schedule, power.timing.debounce());
+            power.debounced += debounced;
+            if !channels.is_empty() {
+                emitted.push((message.is_on, channels.into_iter().map(|(_, channel)| channel).collect()));
             }
         }
-        Err(e) => {
-            println!("Received a malformed request {:?}, data: {:?}", e, &data);
+        emitted
+    }
+
+    #[rstest]
+    #[case::debounced(30, vec![(true, vec![2]), (true, vec![2]), (true, vec![2]), (false, vec![2])], 2)]
+    #[case::not_debounced(
+        0,
+        vec![(true, vec![2]), (false, vec![2]), (true, vec![2]), (false, vec![2]), (true, vec![2]), (false, vec![2])],
+        0
+    )]
+    #[tokio::test]
+    /// Detections chattering a few milliseconds apart keep the channel on
+    /// until the last of them ends when debounced, and every off is sent
+    /// without a debounce window.
+    async fn test_debounce_chatter(
+        #[case] debounce_ms: u64,
+        #[case] expected: Vec<(bool, Vec<u8>)>,
+        #[case] debounced: u64,
+    ) {
+        let timing = PowerTiming {
+            debounce_ms,
+            ..PowerTiming::default()
+        };
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None).with_timing(timing);
+        let power = Arc::new(Mutex::new(CropBedPower::new(config).unwrap()));
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        for offset_ms in [0, 60, 120] {
+            let start = start_spray_time + Duration::milliseconds(offset_ms);
+            let message = weed_message_json(&[1], start, start + Duration::milliseconds(50));
+            let response = handle_line(message.to_string().as_bytes(), Utc::now(), &timing, &power).await;
+            assert_eq!(response.status, WeedMessageStatus::Accepted);
         }
-    };
-}
+        let mut gaurd = power.lock().await;
+        assert_eq!(emitted(&mut gaurd), expected);
+        assert_eq!(gaurd.debounced, debounced);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rstest::rstest;
-    use serial_test::serial;
-    use std::fs::OpenOptions;
+    #[test]
+    /// An off is still sent once the next spray of its channel starts past
+    /// the debounce window, and other channels of a held off go off.
+    fn test_debounce_window_edge() {
+        let timing = PowerTiming {
+            debounce_ms: 30,
+            ..PowerTiming::default()
+        };
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None).with_timing(timing);
+        let mut power = CropBedPower::new(config).unwrap();
+        let start = Utc::now() + Duration::seconds(5);
+        power.queue_spray(
+            vec![(0, 1), (0, 2)],
+            start,
+            start + Duration::milliseconds(50),
+            100,
+            None,
+            false,
+            start,
+            None,
+        );
+        let next = start + Duration::milliseconds(70);
+        power.queue_spray(
+            vec![(0, 1)],
+            next,
+            next + Duration::milliseconds(50),
+            100,
+            None,
+            false,
+            next,
+            None,
+        );
+        let later = start + Duration::milliseconds(100);
+        power.queue_spray(
+            vec![(0, 2)],
+            later,
+            later + Duration::milliseconds(50),
+            100,
+            None,
+            false,
+            later,
+            None,
+        );
+        assert_eq!(
+            emitted(&mut power),
+            vec![
+                (true, vec![1, 2]),
+                (false, vec![2]),
+                (true, vec![1]),
+                (true, vec![2]),
+                (false, vec![1]),
+                (false, vec![2])
+            ]
+        );
+        assert_eq!(power.debounced, 1);
+    }
+
+    #[tokio::test]
+    /// Asking for a PDM the component does not have is logged and reported
+    /// as not recovered rather than panicking.
+    async fn test_reinitialise_unknown_pdm() {
+        let mut power = CropBedPower::new(CropBedPowerConfig::new(
+            CropBed::LeftBoom,
+            String::from("can0"),
+            17650,
+            None,
+        ))
+        .unwrap();
+        assert!(!power.reinitialise_pdm(3, "a reinitialise request").await);
+    }
+
+    #[test]
+    /// Configs written before the crop beds were named use plain integers
+    /// and must keep parsing.
+    fn test_read_legacy_integer_crop_bed_id() {
+        let legacy = "crop_bed_id: 2\ncanbus_id: can2\nport: 17652\npdm_config_files: {}\nchannel_map: null\n";
+        let config: CropBedPowerConfig = serde_yaml::from_str(legacy).unwrap();
+        assert_eq!(
+            config,
+            CropBedPowerConfig::new(CropBed::RightBoom, String::from("can2"), 17652, None)
+        );
+    }
+
+    #[test]
+    /// Timing left out of a config, or only partly set, falls back to the
+    /// defaults, and default timing is not written out.
+    fn test_read_timing_section() {
+        let legacy = "crop_bed_id: 2\ncanbus_id: can2\nport: 17652\npdm_config_files: {}\nchannel_map: null\n";
+        let config: CropBedPowerConfig = serde_yaml::from_str(legacy).unwrap();
+        assert_eq!(config.timing, PowerTiming::default());
+        assert!(!serde_yaml::to_string(&config).unwrap().contains("timing"));
+
+        let partial = format!("{legacy}timing:\n  spray_bound_us: 250\n  refire_interval_ms: 80\n");
+        let config: CropBedPowerConfig = serde_yaml::from_str(&partial).unwrap();
+        assert_eq!(
+            config.timing,
+            PowerTiming {
+                spray_bound_us: 250,
+                refire_interval_ms: 80,
+                ..PowerTiming::default()
+            }
+        );
+        assert_eq!(config.timing.refire_interval(), Duration::milliseconds(80));
+
+        let written = serde_yaml::to_string(&config).unwrap();
+        assert!(written.contains("spray_bound_us: 250"), "{written}");
+        assert_eq!(serde_yaml::from_str::<CropBedPowerConfig>(&written).unwrap(), config);
+    }
 
     #[rstest]
     /// Test partitioning functions.
@@ -452,7 +5623,6 @@ mod tests {
     }
 
     #[rstest]
-    #[serial]
     #[rustfmt::skip]
     #[case((vec![
             (1,  (11, 0)),
@@ -496,9 +5666,8 @@ mod tests {
             (23, (2,  0)),
             (24, (1,  0)),
         ], 2, "can2", 17652))]
-    /// Assert that the channel maps are serialised correctly to ensure 
-    /// that the wiring harness is mapped to the software so messages 
-    /// still get sent to the expected output.
+    /// Assert that the channel maps the generator writes match the wiring
+    /// harness, so messages still get sent to the expected output.
     ///
     /// * `params`: Vector of channel maps.
     fn test_write_component_config_to_file_with_channel_maps(
@@ -523,38 +5692,72 @@ mod tests {
             .add_pdm_config_file("./config/devices/crop_bed/pdm_0.yaml", 0)
             .add_pdm_config_file("./config/devices/crop_bed/pdm_1.yaml", 1);
 
-            let file = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open(Path::new(&format!("{}/config/components/crop_bed/actuating/power/crop_bed_power_{}.yaml", env!("CARGO_MANIFEST_DIR"), params.1)))
-                .expect("Faile to open file");
-            serde_yaml::to_writer(file, &config).expect("Failed to write yaml");
+        let generated = canonical_configs()
+            .into_iter()
+            .find(|generated| generated.path.ends_with(format!("crop_bed_power_{}.yaml", params.1)))
+            .expect("No generated config for the crop bed");
+        let path = std::env::temp_dir().join(format!("onyx-power-config-{}.yaml", Uuid::new_v4()));
+        std::fs::write(&path, &generated.yaml).expect("Failed to write yaml");
+        let read_config = CropBedPowerConfig::try_from_file(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(read_config, config, "Generated channel map differs from the harness");
+        assert_eq!(to_yaml(&read_config), generated.yaml);
     }
 
     #[test]
-    #[serial]
-    fn test_write_component_config_to_file() {
-        let pdm_config_ids: Vec<(u8, &str, i32)> = vec![(0, "can0", 17650)];
+    /// Configs built apart with the same PDMs and channel map write
+    /// byte-identical yaml, so the config files do not churn.
+    fn test_config_yaml_is_deterministic() {
+        let config = || {
+            let channel_map = (1..=24).map(|channel| (channel, (channel + 10, channel % 2))).collect();
+            (0..8).fold(
+                CropBedPowerConfig::new(1, String::from("can1"), 17651, Some(channel_map)),
+                |config, pdm_id| {
+                    config.add_pdm_config_file(format!("./config/devices/crop_bed/pdm_{pdm_id}.yaml"), pdm_id)
+                },
+            )
+        };
+        let yaml = serde_yaml::to_string(&config()).unwrap();
+        assert_eq!(serde_yaml::to_string(&config()).unwrap(), yaml);
+        assert!(yaml.contains("channel_map:\n  1:\n  - 11\n  - 1\n  2:\n"), "{yaml}");
+    }
 
-        for (id, interface, port) in pdm_config_ids {
-            let config = CropBedPowerConfig::new(id, String::from(interface), port, None)
-                .add_pdm_config_file("./config/devices/crop_bed/pdm_0.yaml", 0)
-                .add_pdm_config_file("./config/devices/crop_bed/pdm_1.yaml", 1);
+    #[test]
+    /// A config file with a bad field is refused naming the field and its
+    /// line, rather than panicking.
+    fn test_try_from_file_names_bad_field() {
+        let yaml = serde_yaml::to_string(&CropBedPowerConfig::new(1, String::from("can1"), 17651, None)).unwrap();
+        assert!(yaml.contains("port: 17651\n"), "{yaml}");
+        let path = std::env::temp_dir().join(format!("onyx-power-config-{}.yaml", Uuid::new_v4()));
+        std::fs::write(&path, yaml.replace("port: 17651", "port: seventeen")).unwrap();
+        let error = CropBedPowerConfig::try_from_file(&path).unwrap_err();
+        assert_eq!(error.field(), Some("port"), "{error}");
+        assert_eq!(error.location().map(|(line, _)| line), Some(3), "{error}");
+        std::fs::remove_file(path).unwrap();
+    }
 
-            let file = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open(Path::new(&format!(
-                    "{}/config/components/crop_bed/actuating/power/crop_bed_power_{id}.yaml",
-                    env!("CARGO_MANIFEST_DIR")
-                )))
-                .expect("Faile to open file");
-            serde_yaml::to_writer(file, &config).expect("Failed to write yaml");
+    #[test]
+    /// The configs from the generator without a channel map read back and
+    /// write out as they were generated.
+    fn test_write_component_config_to_file() {
+        let dir = std::env::temp_dir().join(format!("onyx-power-configs-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let generated: Vec<_> = canonical_configs()
+            .into_iter()
+            .filter(|config| config.path.to_string_lossy().ends_with("_no_map.yaml"))
+            .collect();
+        assert_eq!(generated.len(), 3);
+        for config in generated {
+            let path = dir.join(config.path.file_name().unwrap());
+            std::fs::write(&path, &config.yaml).unwrap();
+            let read_config = CropBedPowerConfig::try_from_file(&path).unwrap();
+            assert!(read_config.channel_map.is_none());
+            assert_eq!(to_yaml(&read_config), config.yaml, "{}", config.path.display());
         }
+        std::fs::remove_dir_all(dir).unwrap();
     }
 
     #[test]
-    #[serial]
     fn test_read_component_config_to_file() {
         let pdm_config_ids: Vec<(u8, &str, i32)> =
             vec![(0, "can0", 17650), (1, "can1", 17651), (2, "can2", 17652)];
@@ -564,21 +5767,10 @@ mod tests {
                 .add_pdm_config_file("./config/devices/crop_bed/pdm_0.yaml", 0)
                 .add_pdm_config_file("./config/devices/crop_bed/pdm_1.yaml", 1);
 
-            let file = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open(Path::new(&format!(
-                    "{}/config/components/crop_bed/actuating/power/crop_bed_power_{id}_no_map.yaml",
-                    env!("CARGO_MANIFEST_DIR")
-                )))
-                .expect("Faile to open file");
-
-            serde_yaml::to_writer(file, &write_config).expect("Failed to write yaml");
-
-            let read_config = CropBedPowerConfig::from_file(Path::new(&format!(
-                "{}/config/components/crop_bed/actuating/power/crop_bed_power_{id}_no_map.yaml",
-                env!("CARGO_MANIFEST_DIR")
-            )));
+            let path = std::env::temp_dir().join(format!("onyx-power-config-{}.yaml", Uuid::new_v4()));
+            crate::utils::configgen::write_config(&path, &write_config).expect("Failed to write yaml");
+            let read_config = CropBedPowerConfig::try_from_file(&path).unwrap();
+            std::fs::remove_file(path).unwrap();
 
             assert_eq!(
                 write_config, read_config,
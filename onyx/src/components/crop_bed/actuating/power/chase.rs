@@ -0,0 +1,224 @@
+use super::{CropBedPower, WeedQueueMessage, MANUAL_SPRAY_LEAD_MS, MAX_MANUAL_SPRAY_MS};
+use crate::messages::control::{pdm::TestPattern, weed::FULL_INTENSITY};
+use chrono::{DateTime, Duration, Utc};
+
+/// Most times a test pattern may run through every channel, so a pattern
+/// cannot hold the boom for longer than a walk along it.
+pub const MAX_TEST_PATTERN_LOOPS: u32 = 10;
+
+impl CropBedPower {
+    /// Crop bed channels of the component in the order a test pattern fires
+    /// them, that of the channel map when set and of the layout otherwise.
+    pub fn chase_channels(&self) -> Vec<u8> {
+        match &self.channel_map {
+            Some(channel_map) => {
+                let mut channels: Vec<u8> = channel_map.keys().copied().collect();
+                channels.sort_unstable();
+                channels
+            }
+            None => self.channel_layout.channels(),
+        }
+    }
+
+    /// Queue a test pattern as manual sprays, each channel on in turn and
+    /// every channel off at the end, returning the number of actions
+    /// queued. The pattern is refused while manual sprays are not allowed,
+    /// and while weed messages are queued unless it is forced. Shutting the
+    /// component down stops the pattern with the rest of the queue.
+    ///
+    /// * `pattern`: times and loops of the chase.
+    /// * `received_at`: when the command was received.
+    pub fn queue_test_pattern(&mut self, pattern: &TestPattern, received_at: DateTime<Utc>) -> Result<usize, String> {
+        if !self.allow_manual_spray {
+            return Err(String::from("manual sprays are not allowed"));
+        }
+        if pattern.on_ms > MAX_MANUAL_SPRAY_MS {
+            return Err(format!("{}ms on is longer than {MAX_MANUAL_SPRAY_MS}ms", pattern.on_ms));
+        }
+        if pattern.loops == 0 || pattern.loops > MAX_TEST_PATTERN_LOOPS {
+            return Err(format!("{} loops is not between 1 and {MAX_TEST_PATTERN_LOOPS}", pattern.loops));
+        }
+        if !pattern.force && self.message_queue.iter().any(|(message, _)| !message.manual) {
+            return Err(String::from("weed messages are being sprayed, force it to run anyway"));
+        }
+        let channels = self.chase_channels();
+        #[allow(clippy::cast_possible_wrap)]
+        let (on, step) = (
+            Duration::milliseconds(pattern.on_ms as i64),
+            Duration::milliseconds((pattern.on_ms + pattern.gap_ms) as i64),
+        );
+        let mut start_spray_time = self.clock.now() + Duration::milliseconds(MANUAL_SPRAY_LEAD_MS);
+        let mut queued_actions = 0;
+        for _ in 0..pattern.loops {
+            for channel in &channels {
+                let routed = self.route_channels([*channel]);
+                queued_actions += self.queue_spray(
+                    routed,
+                    start_spray_time,
+                    start_spray_time + on,
+                    FULL_INTENSITY,
+                    None,
+                    true,
+                    received_at,
+                    None,
+                );
+                start_spray_time += step;
+            }
+        }
+        // Every channel off once the pattern is through, whatever the
+        // channels were left at.
+        let channels = self.route_channels(channels);
+        self.add_to_message_queue(WeedQueueMessage {
+            channels,
+            time_to_fire: start_spray_time,
+            is_on: false,
+            original_spray_starts: start_spray_time,
+            original_spray_ending: start_spray_time,
+            pwm: None,
+            timed_for: None,
+            manual: true,
+            received_at,
+            position: None,
+        });
+        Ok(queued_actions + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::crop_bed::actuating::power::{
+        layout::{ChannelLayout, ChannelRange},
+        CropBedPowerConfig,
+    };
+    use crate::utils::{location::CropBed, time::MonotonicClock};
+    use std::{collections::HashMap, time::Instant};
+
+    /// Component allowing manual sprays on channels 1 to 3.
+    ///
+    /// * `channel_map`: channel map of the component.
+    fn three_channel_power(channel_map: Option<HashMap<u8, (u8, u8)>>) -> CropBedPower {
+        CropBedPower::new(
+            CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, channel_map)
+                .with_channel_layout(ChannelLayout::new(vec![ChannelRange::new(1, 3, 0, 0)]))
+                .with_manual_spray(true),
+        )
+        .unwrap()
+    }
+
+    /// Queued messages in the order they fire, as whether each is an on,
+    /// its crop bed channels and its time from the first.
+    ///
+    /// * `power`: component
+    fn fired(power: &CropBedPower) -> Vec<(bool, Vec<u8>, i64)> {
+        let mut queued: Vec<_> = power
+            .message_queue
+            .iter()
+            .map(|(message, priority)| (*priority, message))
+            .collect();
+        queued.sort_by_key(|(priority, message)| (*priority, message.is_on));
+        let first = queued[0].0;
+        queued
+            .into_iter()
+            .map(|(priority, message)| {
+                (
+                    message.is_on,
+                    message.crop_bed_channels(),
+                    (priority - first).num_milliseconds(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    /// Each channel is on for its time with the gap before the next, loop
+    /// after loop, then every channel is turned off.
+    fn test_chase_order_and_timing() {
+        let mut power = three_channel_power(None);
+        let pattern = TestPattern {
+            on_ms: 100,
+            gap_ms: 50,
+            loops: 2,
+            force: false,
+        };
+        assert_eq!(power.queue_test_pattern(&pattern, Utc::now()), Ok(13));
+        let mut expected = Vec::new();
+        for index in 0..6 {
+            let channel = [1, 2, 3][index % 3];
+            #[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
+            let start = 150 * index as i64;
+            expected.push((true, vec![channel], start));
+            expected.push((false, vec![channel], start + 100));
+        }
+        expected.push((false, vec![1, 2, 3], 900));
+        assert_eq!(fired(&power), expected);
+        assert!(power.message_queue.iter().all(|(message, _)| message.manual));
+    }
+
+    #[test]
+    /// The pattern starts on the clock of the component, so a wall clock
+    /// stepped since it started does not move the chase.
+    fn test_chase_follows_component_clock() {
+        let mut power = three_channel_power(None);
+        power.clock = MonotonicClock::with_anchor(Instant::now(), Utc::now() - Duration::hours(1));
+        let before = power.clock.now();
+        power.queue_test_pattern(&TestPattern::default(), Utc::now()).unwrap();
+        let first = power.message_queue.peek_min().map(|(_, priority)| *priority).unwrap();
+        assert!(first >= before, "Chase starts at {first}, before {before}");
+        assert!(
+            first <= power.clock.now() + Duration::milliseconds(MANUAL_SPRAY_LEAD_MS),
+            "Chase starts at {first} on the wall clock"
+        );
+    }
+
+    #[test]
+    /// Channels are chased in channel map order rather than by the PDM
+    /// channel they are wired to.
+    fn test_chase_channel_map_order() {
+        let channel_map = HashMap::from([(3, (1, 0)), (1, (3, 0)), (2, (2, 0))]);
+        let mut power = three_channel_power(Some(channel_map));
+        assert_eq!(power.chase_channels(), vec![1, 2, 3]);
+        power.queue_test_pattern(&TestPattern::default(), Utc::now()).unwrap();
+        let ons: Vec<_> = fired(&power)
+            .into_iter()
+            .filter(|(is_on, _, _)| *is_on)
+            .map(|(_, channels, _)| channels)
+            .collect();
+        assert_eq!(ons, vec![vec![3], vec![2], vec![1]]);
+    }
+
+    #[test]
+    /// Patterns are refused while weed messages are queued unless forced,
+    /// without the interlock, and when too long.
+    fn test_refuse_test_pattern() {
+        let mut power = three_channel_power(None);
+        let start = power.clock.now() + Duration::seconds(5);
+        let end = start + Duration::milliseconds(100);
+        power.queue_spray(vec![(0, 1)], start, end, 100, None, false, power.clock.now(), None);
+        let error = power.queue_test_pattern(&TestPattern::default(), Utc::now()).unwrap_err();
+        assert!(error.contains("weed messages"), "{error}");
+        assert_eq!(power.message_queue.len(), 2);
+        let forced = TestPattern {
+            force: true,
+            ..TestPattern::default()
+        };
+        assert!(power.queue_test_pattern(&forced, Utc::now()).is_ok());
+
+        let mut power = three_channel_power(None);
+        for pattern in [
+            TestPattern {
+                loops: 0,
+                ..TestPattern::default()
+            },
+            TestPattern {
+                on_ms: MAX_MANUAL_SPRAY_MS + 1,
+                ..TestPattern::default()
+            },
+        ] {
+            assert!(power.queue_test_pattern(&pattern, Utc::now()).is_err());
+        }
+        power.allow_manual_spray = false;
+        assert!(power.queue_test_pattern(&TestPattern::default(), Utc::now()).is_err());
+        assert!(power.message_queue.is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+use crate::messages::control::weed::WeedMessage;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+};
+
+/// How many recent weed messages are remembered to drop the retries of the
+/// AI system. Every field falls back to its default when missing.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct DedupConfig {
+    /// Messages remembered before the least recently seen is forgotten,
+    /// nothing is dropped as a duplicate when zero.
+    pub capacity: usize,
+    /// Time in milliseconds a message is remembered for.
+    pub ttl_ms: u64,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            ttl_ms: 5000,
+        }
+    }
+}
+
+impl DedupConfig {
+    /// Time a message is remembered for.
+    pub fn ttl(&self) -> Duration {
+        Duration::milliseconds(i64::try_from(self.ttl_ms).unwrap_or(i64::MAX))
+    }
+}
+
+/// What a weed message is recognised by when it is sent again.
+#[derive(Hash, PartialEq, Eq, Debug, Clone)]
+pub enum MessageKey {
+    /// Identifier the AI system sent with the message.
+    Id(String),
+    /// Hash of the channels and spray times of a message sent without an
+    /// identifier.
+    Content(u64),
+}
+
+impl MessageKey {
+    /// Key of a weed message, its identifier when it has one.
+    ///
+    /// * `message`: message as received.
+    pub fn of(message: &WeedMessage) -> Self {
+        match &message.message_id {
+            Some(message_id) => Self::Id(message_id.clone()),
+            None => {
+                let mut hasher = DefaultHasher::new();
+                (&message.channels_to_open, message.start_spray_time, message.end_spray_time).hash(&mut hasher);
+                Self::Content(hasher.finish())
+            }
+        }
+    }
+}
+
+/// Weed messages seen within the time to live, least recently seen first,
+/// bounded by the capacity.
+#[derive(Debug, Default)]
+pub struct RecentMessages {
+    /// Capacity and time to live.
+    config: DedupConfig,
+    /// When each remembered message was last seen.
+    seen_at: HashMap<MessageKey, DateTime<Utc>>,
+    /// Remembered messages in the order they were last seen, a message
+    /// seen again is in here twice and only its latest entry counts.
+    order: VecDeque<(MessageKey, DateTime<Utc>)>,
+}
+
+impl RecentMessages {
+    /// Empty set of messages.
+    ///
+    /// * `config`: capacity and time to live.
+    pub fn new(config: DedupConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Change the capacity and time to live, forgetting the messages that
+    /// no longer fit.
+    ///
+    /// * `config`: capacity and time to live.
+    /// * `now`: current time.
+    pub fn configure(&mut self, config: DedupConfig, now: DateTime<Utc>) {
+        self.config = config;
+        self.forget(now);
+    }
+
+    /// Remember a message, returning whether it had already been seen
+    /// within the time to live. A duplicate is remembered afresh, so an AI
+    /// system retrying for longer than the time to live is still caught.
+    ///
+    /// * `key`: key of the message.
+    /// * `now`: time the message was received.
+    pub fn seen(&mut self, key: MessageKey, now: DateTime<Utc>) -> bool {
+        if self.config.capacity == 0 {
+            return false;
+        }
+        self.forget(now);
+        let duplicate = self.seen_at.insert(key.clone(), now).is_some();
+        self.order.push_back((key, now));
+        self.forget(now);
+        duplicate
+    }
+
+    /// Messages remembered.
+    pub fn len(&self) -> usize {
+        self.seen_at.len()
+    }
+
+    /// Whether no message is remembered.
+    pub fn is_empty(&self) -> bool {
+        self.seen_at.is_empty()
+    }
+
+    /// Forget the messages last seen before the time to live and the
+    /// least recently seen past the capacity.
+    ///
+    /// * `now`: current time.
+    fn forget(&mut self, now: DateTime<Utc>) {
+        let oldest = now - self.config.ttl();
+        while let Some((key, seen_at)) = self.order.front() {
+            let latest = self.seen_at.get(key) == Some(seen_at);
+            if latest && *seen_at >= oldest && self.seen_at.len() <= self.config.capacity {
+                break;
+            }
+            if latest {
+                self.seen_at.remove(key);
+            }
+            self.order.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// Weed message spraying channel 0 from a time.
+    ///
+    /// * `message_id`: identifier of the message.
+    /// * `start_spray_time`: time to start spraying.
+    fn weed_message(message_id: Option<&str>, start_spray_time: DateTime<Utc>) -> WeedMessage {
+        let message = WeedMessage::new(0, 0, start_spray_time, start_spray_time + Duration::milliseconds(100)).channels([0]);
+        match message_id {
+            Some(message_id) => message.message_id(message_id),
+            None => message,
+        }
+    }
+
+    #[rstest]
+    #[case::same_id(Some("cam0-1"), Some("cam0-1"), 0, true)]
+    #[case::same_id_other_times(Some("cam0-1"), Some("cam0-1"), 50, true)]
+    #[case::other_id(Some("cam0-1"), Some("cam0-2"), 0, false)]
+    #[case::same_content(None, None, 0, true)]
+    #[case::other_content(None, None, 50, false)]
+    #[case::id_and_content(Some("cam0-1"), None, 0, false)]
+    /// Messages are keyed by their identifier, and by their channels and
+    /// times when sent without one.
+    fn test_message_keys(
+        #[case] first_id: Option<&str>,
+        #[case] second_id: Option<&str>,
+        #[case] offset_ms: i64,
+        #[case] duplicate: bool,
+    ) {
+        let start_spray_time = Utc::now();
+        let first = MessageKey::of(&weed_message(first_id, start_spray_time));
+        let second = MessageKey::of(&weed_message(
+            second_id,
+            start_spray_time + Duration::milliseconds(offset_ms),
+        ));
+        assert_eq!(first == second, duplicate);
+    }
+
+    #[test]
+    /// Messages are forgotten past the time to live and the capacity, least
+    /// recently seen first.
+    fn test_forget_past_ttl_and_capacity() {
+        let mut recent = RecentMessages::new(DedupConfig {
+            capacity: 2,
+            ttl_ms: 1000,
+        });
+        let now = Utc::now();
+        let key = |id: &str| MessageKey::Id(String::from(id));
+        assert!(!recent.seen(key("a"), now));
+        assert!(recent.seen(key("a"), now + Duration::milliseconds(900)));
+        // Seen again, so remembered from the retry rather than the first.
+        assert!(recent.seen(key("a"), now + Duration::milliseconds(1500)));
+        assert!(!recent.seen(key("a"), now + Duration::milliseconds(2600)));
+
+        assert!(!recent.seen(key("b"), now + Duration::milliseconds(2700)));
+        assert!(!recent.seen(key("c"), now + Duration::milliseconds(2800)));
+        assert_eq!(recent.len(), 2);
+        assert!(!recent.seen(key("a"), now + Duration::milliseconds(2900)), "Capacity exceeded");
+
+        recent.configure(DedupConfig { capacity: 0, ttl_ms: 1000 }, now);
+        assert!(!recent.seen(key("c"), now + Duration::milliseconds(2900)));
+        assert!(recent.is_empty());
+    }
+}
@@ -0,0 +1,308 @@
+use super::{reload::reload_config_file, stopping, CropBedPower, CropBedPowerSnapshot};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use std::{io, net::TcpListener, sync::Arc};
+use tokio::sync::{watch, Mutex};
+
+/// Build the routes served to the HMI, the status, a config reload and an
+/// emergency stop.
+///
+/// * `power`: running component.
+pub fn router(power: Arc<Mutex<CropBedPower>>) -> Router {
+    Router::new()
+        .route("/status", get(status))
+        .route("/reload", post(reload))
+        .route("/estop", post(estop))
+        .with_state(power)
+}
+
+/// `GET /status`, snapshot of the component. The lock is only held to copy
+/// the state out, the firing task is not held up while it is serialised.
+async fn status(State(power): State<Arc<Mutex<CropBedPower>>>) -> Json<CropBedPowerSnapshot> {
+    let snapshot = power.lock().await.snapshot();
+    Json(snapshot)
+}
+
+/// `POST /reload`, re-read the config file and apply the items safe to
+/// change while running. Answers with the items changed, or a conflict
+/// with the reason when the config was refused.
+async fn reload(
+    State(power): State<Arc<Mutex<CropBedPower>>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    match reload_config_file(&power).await {
+        Ok(changed) => Ok(Json(serde_json::json!({ "changed": changed }))),
+        Err(e) => Err((
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({ "error": e.to_string() })),
+        )),
+    }
+}
+
+/// `POST /estop`, turn every channel off at once and stop the component,
+/// which shuts down without draining. Answers once the channels are off.
+async fn estop(State(power): State<Arc<Mutex<CropBedPower>>>) -> StatusCode {
+    power.lock().await.emergency_stop().await;
+    StatusCode::OK
+}
+
+/// Serve the status routes until the component is stopped.
+///
+/// * `listener`: bound listener, use port 0 in tests.
+/// * `power`: running component.
+/// * `stop_rx`: stop signal from the component handle.
+pub async fn serve(
+    listener: TcpListener,
+    power: Arc<Mutex<CropBedPower>>,
+    mut stop_rx: watch::Receiver<bool>,
+) -> io::Result<()> {
+    listener.set_nonblocking(true)?;
+    axum::Server::from_tcp(listener)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        .serve(router(power).into_make_service())
+        .with_graceful_shutdown(async move {
+            while !stopping(&stop_rx) {
+                if stop_rx.changed().await.is_err() {
+                    break;
+                }
+            }
+        })
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::crop_bed::actuating::power::{
+        CropBedPowerConfig, CropBedPowerController, WeedQueueMessage,
+    };
+    use crate::messages::control::weed::WeedMessageStatus;
+    use crate::utils::{location::CropBed, paths::repo_relative};
+    use chrono::{Duration, Utc};
+    use serial_test::serial;
+
+    /// Virtual canbus interface used by the `vcan_test` tests.
+    fn vcan_interface() -> String {
+        std::env::var("ONYX_VCAN").unwrap_or_else(|_| String::from("vcan0"))
+    }
+
+    #[tokio::test]
+    /// The status is served as json with every field the HMI reads.
+    async fn test_status_json_shape() {
+        let mut channel_map = std::collections::HashMap::new();
+        channel_map.insert(1, (3, 0));
+        let power = Arc::new(Mutex::new(
+            CropBedPower::new(CropBedPowerConfig::new(
+                CropBed::LeftBoom,
+                String::from("can0"),
+                17650,
+                Some(channel_map),
+            ))
+            .unwrap(),
+        ));
+        let time_to_fire = Utc::now() + Duration::seconds(10);
+        {
+            let mut gaurd = power.lock().await;
+            gaurd.add_to_message_queue(WeedQueueMessage {
+                channels: vec![(0, 3)],
+                time_to_fire,
+                is_on: true,
+                original_spray_starts: time_to_fire,
+                original_spray_ending: time_to_fire,
+                pwm: None,
+                timed_for: None,
+                manual: false,
+                received_at: Utc::now(),
+                position: None,
+            });
+            gaurd.message_counts.record(WeedMessageStatus::Accepted);
+            gaurd.message_counts.record(WeedMessageStatus::Malformed);
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/status", listener.local_addr().unwrap());
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let server = tokio::spawn(serve(listener, power, stop_rx));
+
+        let status: serde_json::Value = reqwest::get(url).await.unwrap().json().await.unwrap();
+        assert_eq!(status["canbus_id"], "can0");
+        assert_eq!(status["queue_depth"], 1);
+        assert_eq!(status["queue_dropped"], 0);
+        assert_eq!(status["next_fire_time"], serde_json::json!(time_to_fire));
+        assert!(status["last_fire_time"].is_null());
+        assert_eq!(status["pdms"], serde_json::json!({}));
+        assert_eq!(
+            status["messages"],
+            serde_json::json!({
+                "accepted": 1, "clamped": 0, "late": 0, "malformed": 1, "rejected": 0, "truncated": 0, "duplicate": 0,
+                "invalid": {
+                    "time_order": 0, "empty_channels": 0, "channel_out_of_range": 0, "duration_too_long": 0,
+                    "too_far_in_future": 0
+                }
+            })
+        );
+        assert_eq!((status["late_fired"].as_u64(), status["late_discarded"].as_u64()), (Some(0), Some(0)));
+        assert_eq!(status["debounced"], 0);
+        assert_eq!(status["compensation_clamped"], 0);
+        assert_eq!(status["channel_map"], serde_json::json!({"1": [3, 0]}));
+        assert_eq!(status["latency"]["samples"], 0);
+        assert_eq!(
+            status["latency"]["intended_to_actual"],
+            serde_json::json!({"p50_us": 0, "p95_us": 0, "max_us": 0})
+        );
+
+        stop_tx.send_replace(true);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    /// A reload is answered with what changed, and refused with the reason
+    /// when the config needs a restart.
+    async fn test_reload_route() {
+        let config_file = std::env::temp_dir().join(format!("onyx-http-reload-{}.yaml", uuid::Uuid::new_v4()));
+        let pdm_config_file = repo_relative("config/devices/crop_bed/pdm_0.yaml").unwrap();
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None)
+            .add_pdm_config_file(pdm_config_file, 0);
+        serde_yaml::to_writer(std::fs::File::create(&config_file).unwrap(), &config).unwrap();
+        let power = Arc::new(Mutex::new(CropBedPower::from_config_file(&config_file).unwrap()));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/reload", listener.local_addr().unwrap());
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let server = tokio::spawn(serve(listener, power, stop_rx));
+        let client = reqwest::Client::new();
+
+        let config = config.with_max_queue_len(64);
+        serde_yaml::to_writer(std::fs::File::create(&config_file).unwrap(), &config).unwrap();
+        let response = client.post(&url).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body, serde_json::json!({"changed": ["max_queue_len"]}));
+
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can1"), 17650, None);
+        serde_yaml::to_writer(std::fs::File::create(&config_file).unwrap(), &config).unwrap();
+        let response = client.post(&url).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert!(body["error"].as_str().unwrap().contains("canbus_id"), "{body}");
+
+        stop_tx.send_replace(true);
+        server.await.unwrap().unwrap();
+        std::fs::remove_file(config_file).unwrap();
+    }
+
+    #[tokio::test]
+    /// An emergency stop drops the queue and is answered once done.
+    async fn test_estop_route() {
+        let power = Arc::new(Mutex::new(
+            CropBedPower::new(CropBedPowerConfig::new(
+                CropBed::LeftBoom,
+                String::from("can0"),
+                17650,
+                None,
+            ))
+            .unwrap(),
+        ));
+        let time_to_fire = Utc::now() + Duration::seconds(10);
+        power.lock().await.add_to_message_queue(WeedQueueMessage {
+            channels: vec![(0, 3)],
+            time_to_fire,
+            is_on: true,
+            original_spray_starts: time_to_fire,
+            original_spray_ending: time_to_fire,
+            pwm: None,
+            timed_for: None,
+            manual: false,
+            received_at: Utc::now(),
+            position: None,
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/estop", listener.local_addr().unwrap());
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let server = tokio::spawn(serve(listener, power.clone(), stop_rx));
+
+        let response = reqwest::Client::new().post(&url).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let gaurd = power.lock().await;
+        assert!(gaurd.emergency_stopped());
+        assert_eq!(gaurd.snapshot().queue_depth, 0);
+        drop(gaurd);
+
+        stop_tx.send_replace(true);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// A running controller reports its simulated PDMs as initialised and
+    /// counts the spray it has fired.
+    async fn test_status_server_against_simulated_pdms() {
+        use crate::devices::{
+            hardware::pdm::{PdmAddress, PdmConfig},
+            software::pdm::SimulatedPdm,
+        };
+        use tokio::io::AsyncWriteExt;
+
+        let port = 17663;
+        let status_port = 17664;
+        let config_dir = std::env::temp_dir().join(format!("onyx-power-status-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let mut config =
+            CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), port, None).with_status_port(status_port);
+        let mut simulated = Vec::new();
+        for (bed_position, address) in [(0, PdmAddress::Pdm30), (1, PdmAddress::Pdm31)] {
+            let pdm_config = PdmConfig::new(address, bed_position)
+                .with_response_timeout(std::time::Duration::from_millis(200));
+            let pdm_config_file = config_dir.join(format!("pdm_{bed_position}.yaml"));
+            serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+            config = config.add_pdm_config_file(pdm_config_file, bed_position);
+            simulated.push(
+                SimulatedPdm::new(pdm_config)
+                    .start(&vcan_interface())
+                    .expect("Failed to start simulated PDM"),
+            );
+        }
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
+            .await
+            .unwrap();
+
+        let start_spray_time = Utc::now() + Duration::milliseconds(200);
+        let message = serde_json::json!({
+            "channels_to_open": [1],
+            "start_spray_time": start_spray_time,
+            "end_spray_time": start_spray_time + Duration::milliseconds(100),
+            "message_created_at": Utc::now(),
+            "capture_time": Utc::now(),
+            "time_diff_capture_to_start_spray_no_offset": 0.0,
+            "distance_to_solenoid_mm": 0.0,
+            "cam_id": 0,
+            "crop_bed_id": 0,
+        });
+        let mut stream = tokio::net::TcpStream::connect(format!("127.0.0.1:{port}")).await.unwrap();
+        stream.write_all(format!("{message}\n").as_bytes()).await.unwrap();
+        drop(stream);
+        tokio::time::sleep(std::time::Duration::from_millis(600)).await;
+
+        let status: serde_json::Value = reqwest::get(format!("http://127.0.0.1:{status_port}/status"))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        for (bed_position, pdm) in ["0", "1"].iter().zip(&simulated) {
+            assert_eq!(status["pdms"][bed_position]["address"], pdm.address().raw());
+            assert_eq!(status["pdms"][bed_position]["initialised"], true);
+            assert_eq!(status["pdms"][bed_position]["status"]["loss_of_can"], false);
+        }
+        assert_eq!(status["messages"]["accepted"], 1);
+        assert_eq!(status["queue_depth"], 0);
+        assert!(status["last_fire_time"].is_string(), "Nothing fired {status}");
+
+        component.shutdown().await;
+        std::fs::remove_dir_all(config_dir).unwrap();
+    }
+}
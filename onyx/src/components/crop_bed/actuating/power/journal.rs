@@ -0,0 +1,427 @@
+use crate::messages::{
+    control::{response::ControlResponse, weed::WeedMessageStatus},
+    encoding::Encoding,
+};
+use crate::utils::client::ComponentClient;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use tokio::{io::AsyncWriteExt, sync::mpsc, task::JoinHandle};
+use tracing::warn;
+
+/// Size in bytes the journal grows to before it is rotated, when not set in
+/// the config.
+pub const DEFAULT_JOURNAL_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Rotated journals kept next to the current one, `journal.1` being the
+/// most recent and the oldest being deleted on rotation.
+const JOURNAL_ROTATIONS: usize = 4;
+
+/// Entries waiting to be written before new ones are dropped, so a slow
+/// disk never holds up message handling.
+const JOURNAL_CHANNEL_LEN: usize = 1024;
+
+/// Times in a weed message that are shifted when it is replayed.
+const REPLAYED_TIMES: [&str; 4] = [
+    "start_spray_time",
+    "end_spray_time",
+    "message_created_at",
+    "capture_time",
+];
+
+/// One line received on the weed message socket, as written to the journal.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// When the line was received.
+    pub received_at: DateTime<Utc>,
+    /// What was done with the line.
+    pub status: WeedMessageStatus,
+    /// Line as sent by the AI system, without the newline.
+    pub line: String,
+}
+
+impl JournalEntry {
+    /// Entry for a line received on the socket.
+    ///
+    /// * `received_at`: when the line was received.
+    /// * `status`: what was done with the line.
+    /// * `data`: raw line, a trailing newline is dropped.
+    pub fn new(received_at: DateTime<Utc>, status: WeedMessageStatus, data: &[u8]) -> Self {
+        Self {
+            received_at,
+            status,
+            line: String::from_utf8_lossy(data).trim_end().to_string(),
+        }
+    }
+}
+
+/// Appends the lines received to a JSONL file. Entries are handed to a
+/// writer task over a bounded channel and dropped when it falls behind.
+#[derive(Debug)]
+pub struct Journal {
+    /// Entries sent to the writer task.
+    entry_tx: mpsc::Sender<JournalEntry>,
+    /// Entries dropped because the writer task had fallen behind.
+    dropped: u64,
+}
+
+impl Journal {
+    /// Start the writer task appending to a journal. The task ends once
+    /// the journal is dropped and every entry handed to it is written.
+    ///
+    /// * `path`: journal file, created when missing.
+    /// * `max_bytes`: size the journal is rotated at.
+    pub fn start(path: PathBuf, max_bytes: u64) -> (Self, JoinHandle<()>) {
+        let (entry_tx, entry_rx) = mpsc::channel(JOURNAL_CHANNEL_LEN);
+        let writer = tokio::spawn(async move {
+            if let Err(e) = write_entries(entry_rx, &path, max_bytes).await {
+                warn!("Stopped writing the weed message journal {:?}: {e}", path);
+            }
+        });
+        (Self { entry_tx, dropped: 0 }, writer)
+    }
+
+    /// Hand an entry to the writer task, without waiting for it.
+    ///
+    /// * `entry`: line received.
+    pub fn record(&mut self, entry: JournalEntry) {
+        if self.entry_tx.try_send(entry).is_err() {
+            self.dropped += 1;
+            warn!("Weed message journal is behind, {} entries dropped", self.dropped);
+        }
+    }
+
+    /// Entries dropped because the writer task had fallen behind or stopped.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// Path of a rotated journal.
+///
+/// * `path`: current journal.
+/// * `rotation`: how many rotations ago it was current.
+fn rotated_path(path: &Path, rotation: usize) -> PathBuf {
+    let mut rotated = OsString::from(path.as_os_str());
+    rotated.push(format!(".{rotation}"));
+    PathBuf::from(rotated)
+}
+
+/// Move the current journal to `journal.1`, shifting older rotations along
+/// and deleting the oldest.
+///
+/// * `path`: current journal.
+async fn rotate(path: &Path) -> io::Result<()> {
+    for rotation in (1..JOURNAL_ROTATIONS).rev() {
+        let older = rotated_path(path, rotation);
+        if tokio::fs::metadata(&older).await.is_ok() {
+            tokio::fs::rename(&older, rotated_path(path, rotation + 1)).await?;
+        }
+    }
+    tokio::fs::rename(path, rotated_path(path, 1)).await
+}
+
+/// Open a journal for appending, creating it when missing.
+///
+/// * `path`: journal file.
+async fn open_journal(path: &Path) -> io::Result<tokio::fs::File> {
+    tokio::fs::OpenOptions::new().create(true).append(true).open(path).await
+}
+
+/// Writer task, appends each entry as a line of json and rotates the
+/// journal before it grows past its size.
+///
+/// * `entry_rx`: entries to write.
+/// * `path`: journal file.
+/// * `max_bytes`: size the journal is rotated at.
+async fn write_entries(mut entry_rx: mpsc::Receiver<JournalEntry>, path: &Path, max_bytes: u64) -> io::Result<()> {
+    let mut file = open_journal(path).await?;
+    let mut size = file.metadata().await?.len();
+    while let Some(entry) = entry_rx.recv().await {
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        let line_len = line.len() as u64;
+        if size > 0 && size + line_len > max_bytes {
+            rotate(path).await?;
+            file = open_journal(path).await?;
+            size = 0;
+        }
+        file.write_all(&line).await?;
+        // Entries are flushed one by one so a power cut loses at most the
+        // line being written.
+        file.flush().await?;
+        size += line_len;
+    }
+    Ok(())
+}
+
+/// Read the entries of a journal, skipping lines that cannot be parsed such
+/// as one cut short by a power cut.
+///
+/// * `path`: journal file, current or rotated.
+pub fn read_journal<P: AsRef<Path>>(path: P) -> io::Result<Vec<JournalEntry>> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)?;
+    Ok(contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                warn!("Skipping unreadable journal line in {:?}: {e}", path);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Shift one time of a message, left as it is when it cannot be read.
+///
+/// * `message`: json object holding the time.
+/// * `key`: field of the time.
+/// * `offset`: time it is shifted by.
+fn shift_time(message: &mut serde_json::Value, key: &str, offset: Duration) {
+    let shifted = message
+        .get(key)
+        .and_then(serde_json::Value::as_str)
+        .and_then(|time| time.parse::<DateTime<Utc>>().ok())
+        .map(|time| time + offset);
+    if let Some(shifted) = shifted {
+        message[key] = serde_json::json!(shifted);
+    }
+}
+
+/// Shift the times of a weed message line, so a replayed message is due as
+/// far after it is sent as it was originally. Messages in an envelope have
+/// the times of their payload and when they were sent shifted. Lines that
+/// are not json, or times that cannot be read, are left as they are.
+///
+/// * `line`: journalled line.
+/// * `offset`: time between the original receive and the replay.
+pub fn shift_times(line: &str, offset: Duration) -> String {
+    let Ok(mut message) = serde_json::from_str::<serde_json::Value>(line) else {
+        return line.to_string();
+    };
+    for key in REPLAYED_TIMES {
+        shift_time(&mut message, key, offset);
+    }
+    if message.get("id").is_some() {
+        shift_time(&mut message, "sent_at", offset);
+        if let Some(payload) = message.get_mut("payload") {
+            for key in REPLAYED_TIMES {
+                shift_time(payload, key, offset);
+            }
+        }
+    }
+    message.to_string()
+}
+
+/// Send journalled lines to a running component over one connection, with
+/// the time between them they were originally received with, and the
+/// times in each message shifted to match. Used to reproduce field
+/// behaviour on the bench.
+///
+/// * `entries`: journal entries in the order received.
+/// * `address`: address of the weed message socket, e.g. `127.0.0.1:17652`.
+pub async fn replay(entries: &[JournalEntry], address: &str) -> io::Result<Vec<ControlResponse>> {
+    if entries.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut client = ComponentClient::connect_tcp(address, Encoding::Json, None).await?;
+    replay_with(entries, &mut client, |_, _| {}).await
+}
+
+/// Send journalled lines to a component as [`replay`] does, over a client
+/// already connected in whichever transport and encoding the component
+/// takes, handing each response over as it arrives.
+///
+/// * `entries`: journal entries in the order received.
+/// * `client`: client connected to the component.
+/// * `on_response`: called with each entry and the response to it.
+pub async fn replay_with<F>(
+    entries: &[JournalEntry],
+    client: &mut ComponentClient,
+    mut on_response: F,
+) -> io::Result<Vec<ControlResponse>>
+where
+    F: FnMut(&JournalEntry, &ControlResponse),
+{
+    let mut responses = Vec::new();
+    let Some(first) = entries.first() else {
+        return Ok(responses);
+    };
+    let replay_start = Utc::now();
+    let offset = replay_start - first.received_at;
+
+    for entry in entries {
+        let due = entry.received_at + offset;
+        if let Ok(wait) = (due - Utc::now()).to_std() {
+            tokio::time::sleep(wait).await;
+        }
+        let response = client.send_json_line(&shift_times(&entry.line, offset)).await?;
+        on_response(entry, &response);
+        responses.push(response);
+    }
+    Ok(responses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::control::weed::WeedMessageResponse;
+    use chrono::TimeZone;
+    use tokio::{
+        io::{AsyncBufReadExt, BufReader},
+        net::TcpListener,
+    };
+    use uuid::Uuid;
+
+    /// Entry for a weed message spraying a second after it was received.
+    ///
+    /// * `received_at`: when the message was received.
+    /// * `status`: what was done with it.
+    fn weed_entry(received_at: DateTime<Utc>, status: WeedMessageStatus) -> JournalEntry {
+        let start_spray_time = received_at + Duration::seconds(1);
+        let line = serde_json::json!({
+            "channels_to_open": [1],
+            "start_spray_time": start_spray_time,
+            "end_spray_time": start_spray_time + Duration::milliseconds(100),
+            "message_created_at": received_at,
+            "capture_time": received_at,
+            "time_diff_capture_to_start_spray_no_offset": 0.0,
+            "distance_to_solenoid_mm": 0.0,
+            "cam_id": 0,
+            "crop_bed_id": 0,
+        });
+        JournalEntry::new(received_at, status, format!("{line}\n").as_bytes())
+    }
+
+    #[tokio::test]
+    /// Entries recorded are all on disk once the writer task has finished,
+    /// in the order recorded.
+    async fn test_journal_round_trip() {
+        let path = std::env::temp_dir().join(format!("onyx-journal-{}.jsonl", Uuid::new_v4()));
+        let (mut journal, writer) = Journal::start(path.clone(), DEFAULT_JOURNAL_MAX_BYTES);
+        let now = Utc::now();
+        let entries = vec![
+            weed_entry(now, WeedMessageStatus::Accepted),
+            weed_entry(now + Duration::milliseconds(20), WeedMessageStatus::Late),
+            JournalEntry::new(now + Duration::milliseconds(40), WeedMessageStatus::Malformed, b"not json\n"),
+        ];
+        for entry in &entries {
+            journal.record(entry.clone());
+        }
+        drop(journal);
+        writer.await.unwrap();
+
+        assert_eq!(read_journal(&path).unwrap(), entries);
+        assert_eq!(entries[2].line, "not json");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    /// The journal is rotated before it grows past its size, keeping the
+    /// most recent rotations with every entry in one of them.
+    async fn test_journal_rotates_by_size() {
+        let dir = std::env::temp_dir().join(format!("onyx-journal-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("weed.jsonl");
+        // A fixed time keeps every entry the same length.
+        let now = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let entries: Vec<JournalEntry> = (0..6)
+            .map(|i| weed_entry(now + Duration::milliseconds(i), WeedMessageStatus::Accepted))
+            .collect();
+        let entry_len = serde_json::to_vec(&entries[0]).unwrap().len() as u64 + 1;
+        let (mut journal, writer) = Journal::start(path.clone(), entry_len * 2);
+        for entry in &entries {
+            journal.record(entry.clone());
+        }
+        drop(journal);
+        writer.await.unwrap();
+
+        assert!(!rotated_path(&path, 3).exists());
+        let mut read = Vec::new();
+        for journal_file in [rotated_path(&path, 2), rotated_path(&path, 1), path.clone()] {
+            assert!(fs::metadata(&journal_file).unwrap().len() <= entry_len * 2);
+            read.extend(read_journal(&journal_file).unwrap());
+        }
+        assert_eq!(read, entries);
+
+        for _ in 0..JOURNAL_ROTATIONS + 1 {
+            rotate(&path).await.ok();
+            fs::write(&path, "{}\n").unwrap();
+        }
+        assert!(rotated_path(&path, JOURNAL_ROTATIONS).exists());
+        assert!(!rotated_path(&path, JOURNAL_ROTATIONS + 1).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Replayed messages keep the time between receiving them and spraying,
+    /// other lines are sent as they were.
+    fn test_shift_times() {
+        let now = Utc::now();
+        let entry = weed_entry(now, WeedMessageStatus::Accepted);
+        let offset = Duration::hours(3);
+        let shifted: serde_json::Value = serde_json::from_str(&shift_times(&entry.line, offset)).unwrap();
+        let original: serde_json::Value = serde_json::from_str(&entry.line).unwrap();
+        for key in REPLAYED_TIMES {
+            let time = |message: &serde_json::Value| message[key].as_str().unwrap().parse::<DateTime<Utc>>().unwrap();
+            assert_eq!(time(&shifted) - time(&original), offset, "{key}");
+        }
+        assert_eq!(shifted["channels_to_open"], original["channels_to_open"]);
+        assert_eq!(shift_times("not json", offset), "not json");
+
+        let enveloped = serde_json::json!({"id": Uuid::nil(), "version": 1, "sent_at": now, "payload": original});
+        let shifted: serde_json::Value =
+            serde_json::from_str(&shift_times(&enveloped.to_string(), offset)).unwrap();
+        let time = |time: &serde_json::Value| time.as_str().unwrap().parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(time(&shifted["sent_at"]) - now, offset);
+        assert_eq!(time(&shifted["payload"]["start_spray_time"]) - time(&original["start_spray_time"]), offset);
+    }
+
+    #[tokio::test]
+    /// Replayed lines arrive with the gaps they were received with, and a
+    /// response is collected for each.
+    async fn test_replay_keeps_relative_timing() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let component = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let (read_stream, mut write_stream) = socket.split();
+            let mut read_stream = BufReader::new(read_stream);
+            let mut arrivals = Vec::new();
+            let mut line = String::new();
+            while read_stream.read_line(&mut line).await.unwrap() > 0 {
+                arrivals.push((Utc::now(), line.clone()));
+                let response = WeedMessageResponse::new(WeedMessageStatus::Accepted, None, 2).to_control(Uuid::nil());
+                let response = format!("{}\n", serde_json::to_string(&response).unwrap());
+                write_stream.write_all(response.as_bytes()).await.unwrap();
+                line.clear();
+            }
+            arrivals
+        });
+
+        let received_at = Utc::now() - Duration::days(1);
+        let entries: Vec<JournalEntry> = [0, 150, 300]
+            .into_iter()
+            .map(|ms| weed_entry(received_at + Duration::milliseconds(ms), WeedMessageStatus::Accepted))
+            .collect();
+        let responses = replay(&entries, &address).await.unwrap();
+        assert_eq!(responses.len(), entries.len());
+        assert!(responses.iter().all(ControlResponse::is_accepted));
+        let arrivals = component.await.unwrap();
+
+        for ((arrived, line), entry) in arrivals.iter().zip(&entries) {
+            let expected = arrivals[0].0 + (entry.received_at - entries[0].received_at);
+            assert!((*arrived - expected).num_milliseconds().abs() < 50, "Replayed at {arrived}, expected {expected}");
+            let message: serde_json::Value = serde_json::from_str(line).unwrap();
+            let start = message["start_spray_time"].as_str().unwrap().parse::<DateTime<Utc>>().unwrap();
+            assert!(start > *arrived, "Replayed message is already late");
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use crate::utils::location::GeoPosition;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// Fired messages the latency percentiles are taken over.
+pub const LATENCY_WINDOW: usize = 1024;
+
+/// Latency of one message sent to the PDMs.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct LatencyRecord {
+    /// When the weed message it came from was received.
+    pub received_at: DateTime<Utc>,
+    /// When it was due to fire.
+    pub time_to_fire: DateTime<Utc>,
+    /// When it had been sent to the PDMs.
+    pub actuated_at: DateTime<Utc>,
+    /// Microseconds from being received to being due, the lead the AI
+    /// system gave.
+    pub receive_to_intended_us: i64,
+    /// Microseconds the actuation was behind when it was due, negative
+    /// when fired early inside the spray bound.
+    pub intended_to_actual_us: i64,
+    /// Microseconds from being received to being actuated.
+    pub receive_to_actual_us: i64,
+    /// Where the machine was when it was actuated, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<GeoPosition>,
+}
+
+impl LatencyRecord {
+    /// Latency of a message from its three times.
+    ///
+    /// * `received_at`: when the weed message was received.
+    /// * `time_to_fire`: when it was due to fire.
+    /// * `actuated_at`: when it had been sent to the PDMs.
+    pub fn new(received_at: DateTime<Utc>, time_to_fire: DateTime<Utc>, actuated_at: DateTime<Utc>) -> Self {
+        Self {
+            received_at,
+            time_to_fire,
+            actuated_at,
+            receive_to_intended_us: microseconds(time_to_fire - received_at),
+            intended_to_actual_us: microseconds(actuated_at - time_to_fire),
+            receive_to_actual_us: microseconds(actuated_at - received_at),
+            position: None,
+        }
+    }
+
+    /// Set where the machine was when the message was actuated.
+    ///
+    /// * `position`: position of the machine, if known.
+    pub fn with_position(mut self, position: Option<GeoPosition>) -> Self {
+        self.position = position;
+        self
+    }
+}
+
+/// Whole microseconds of a duration, saturating.
+///
+/// * `duration`: duration to convert.
+fn microseconds(duration: Duration) -> i64 {
+    duration
+        .num_microseconds()
+        .unwrap_or(if duration < Duration::zero() { i64::MIN } else { i64::MAX })
+}
+
+/// Percentiles of one of the latencies, in microseconds.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyPercentiles {
+    /// Median.
+    pub p50_us: i64,
+    /// 95th percentile.
+    pub p95_us: i64,
+    /// Largest.
+    pub max_us: i64,
+}
+
+impl LatencyPercentiles {
+    /// Nearest rank percentiles of some latencies, all zero when there are
+    /// none.
+    ///
+    /// * `latencies`: latencies in microseconds.
+    fn of(mut latencies: Vec<i64>) -> Self {
+        if latencies.is_empty() {
+            return Self::default();
+        }
+        latencies.sort_unstable();
+        let rank = |percent: usize| latencies[(latencies.len() * percent + 99) / 100 - 1];
+        Self {
+            p50_us: rank(50),
+            p95_us: rank(95),
+            max_us: latencies[latencies.len() - 1],
+        }
+    }
+}
+
+/// Latencies of the messages in the window, served on the status port.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencySummary {
+    /// Messages in the window.
+    pub samples: usize,
+    /// From being received to being due.
+    pub receive_to_intended: LatencyPercentiles,
+    /// From being due to being actuated.
+    pub intended_to_actual: LatencyPercentiles,
+    /// From being received to being actuated.
+    pub receive_to_actual: LatencyPercentiles,
+}
+
+/// Latencies of the last [`LATENCY_WINDOW`] messages sent to the PDMs.
+#[derive(Debug, Default)]
+pub struct LatencyWindow {
+    /// Latencies, oldest first.
+    records: VecDeque<LatencyRecord>,
+}
+
+impl LatencyWindow {
+    /// Add the latency of a message, dropping the oldest once the window
+    /// is full.
+    ///
+    /// * `record`: latency of the message.
+    pub fn record(&mut self, record: LatencyRecord) {
+        if self.records.len() == LATENCY_WINDOW {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    /// Percentiles of each latency over the window.
+    pub fn summary(&self) -> LatencySummary {
+        let of = |latency: fn(&LatencyRecord) -> i64| LatencyPercentiles::of(self.records.iter().map(latency).collect());
+        LatencySummary {
+            samples: self.records.len(),
+            receive_to_intended: of(|record| record.receive_to_intended_us),
+            intended_to_actual: of(|record| record.intended_to_actual_us),
+            receive_to_actual: of(|record| record.receive_to_actual_us),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// The three latencies are the differences of the times recorded.
+    fn test_latency_record() {
+        let received_at = Utc::now();
+        let record = LatencyRecord::new(
+            received_at,
+            received_at + Duration::milliseconds(300),
+            received_at + Duration::microseconds(300_250),
+        );
+        assert_eq!(
+            (record.receive_to_intended_us, record.intended_to_actual_us, record.receive_to_actual_us),
+            (300_000, 250, 300_250)
+        );
+        assert!(!serde_json::to_string(&record).unwrap().contains("position"));
+        let positioned = record.with_position(Some(GeoPosition::new(-27.47, 153.02)));
+        let json = serde_json::to_value(positioned).unwrap();
+        assert_eq!(json["position"]["longitude"], 153.02);
+    }
+
+    #[test]
+    /// Percentiles are taken over the last messages of the window only.
+    fn test_window_percentiles() {
+        let mut window = LatencyWindow::default();
+        assert_eq!(window.summary(), LatencySummary::default());
+
+        let received_at = Utc::now();
+        let time_to_fire = received_at + Duration::milliseconds(100);
+        // An outlier pushed out of the window by the messages after it.
+        window.record(LatencyRecord::new(received_at, time_to_fire, time_to_fire + Duration::seconds(1)));
+        for index in 0..LATENCY_WINDOW {
+            // Spread evenly from 1 to 100 microseconds late.
+            let late_us = i64::try_from(index * 100 / LATENCY_WINDOW).unwrap() + 1;
+            window.record(LatencyRecord::new(
+                received_at,
+                time_to_fire,
+                time_to_fire + Duration::microseconds(late_us),
+            ));
+        }
+        let summary = window.summary();
+        assert_eq!(summary.samples, LATENCY_WINDOW);
+        assert_eq!(
+            summary.intended_to_actual,
+            LatencyPercentiles {
+                p50_us: 50,
+                p95_us: 95,
+                max_us: 100
+            }
+        );
+        assert_eq!(summary.receive_to_intended.max_us, 100_000);
+    }
+}
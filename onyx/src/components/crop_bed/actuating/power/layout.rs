@@ -0,0 +1,255 @@
+use crate::devices::hardware::pdm::frames::CHANNEL_COUNT;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use tracing::warn;
+
+/// Range of crop bed channels wired to one PDM.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelRange {
+    /// First crop bed channel on the PDM, inclusive.
+    pub first: u8,
+    /// Last crop bed channel on the PDM, inclusive.
+    pub last: u8,
+    /// Bed position of the PDM the channels are wired to.
+    pub pdm_id: u8,
+    /// Subtracted from a crop bed channel to give the channel on the PDM.
+    pub local_channel_offset: u8,
+}
+
+impl ChannelRange {
+    /// Range of crop bed channels wired to a PDM.
+    ///
+    /// * `first`: first crop bed channel, inclusive.
+    /// * `last`: last crop bed channel, inclusive.
+    /// * `pdm_id`: bed position of the PDM.
+    /// * `local_channel_offset`: subtracted to give the channel on the PDM.
+    pub fn new(first: u8, last: u8, pdm_id: u8, local_channel_offset: u8) -> Self {
+        Self {
+            first,
+            last,
+            pdm_id,
+            local_channel_offset,
+        }
+    }
+
+    /// Whether a crop bed channel is in the range.
+    ///
+    /// * `channel`: crop bed channel.
+    pub fn contains(&self, channel: u8) -> bool {
+        self.first <= channel && channel <= self.last
+    }
+}
+
+/// Which PDM, and which channel on it, each crop bed channel is wired to.
+/// Defaults to the two PDM layout, channels 1-12 on bed position 0 and
+/// 13-24 on bed position 1.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct ChannelLayout {
+    /// Ranges of crop bed channels, in the order configured.
+    ranges: Vec<ChannelRange>,
+}
+
+impl Default for ChannelLayout {
+    fn default() -> Self {
+        Self::split(2)
+    }
+}
+
+impl ChannelLayout {
+    /// Layout from ranges of channels, see [`ChannelLayout::validate`].
+    ///
+    /// * `ranges`: ranges of crop bed channels wired to each PDM.
+    pub fn new(ranges: Vec<ChannelRange>) -> Self {
+        Self { ranges }
+    }
+
+    /// Layout with consecutive blocks of channels on each PDM in turn, the
+    /// way the harnesses are usually wired.
+    ///
+    /// * `pdm_count`: PDMs on the crop bed.
+    pub fn split(pdm_count: u8) -> Self {
+        Self::new(
+            (0..pdm_count)
+                .map(|pdm_id| {
+                    let offset = pdm_id * CHANNEL_COUNT;
+                    ChannelRange::new(offset + 1, offset + CHANNEL_COUNT, pdm_id, offset)
+                })
+                .collect(),
+        )
+    }
+
+    /// Check every range maps onto channels the PDM has and no crop bed
+    /// channel is in two ranges.
+    pub fn validate(&self) -> Result<(), String> {
+        for range in &self.ranges {
+            if range.first > range.last {
+                return Err(format!(
+                    "Channel range {}-{} for PDM {} is empty",
+                    range.first, range.last, range.pdm_id
+                ));
+            }
+            if range.first <= range.local_channel_offset
+                || range.last - range.local_channel_offset > CHANNEL_COUNT
+            {
+                return Err(format!(
+                    "Channel range {}-{} with offset {} is outside the {} channels of PDM {}",
+                    range.first, range.last, range.local_channel_offset, CHANNEL_COUNT, range.pdm_id
+                ));
+            }
+        }
+        let mut sorted: Vec<&ChannelRange> = self.ranges.iter().collect();
+        sorted.sort_by_key(|range| range.first);
+        for pair in sorted.windows(2) {
+            if pair[1].first <= pair[0].last {
+                return Err(format!(
+                    "Channel ranges {}-{} and {}-{} overlap",
+                    pair[0].first, pair[0].last, pair[1].first, pair[1].last
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Every crop bed channel wired to a PDM, in the order of the ranges.
+    pub fn channels(&self) -> Vec<u8> {
+        self.ranges.iter().flat_map(|range| range.first..=range.last).collect()
+    }
+
+    /// Bed positions of the PDMs channels are wired to.
+    pub fn pdm_ids(&self) -> BTreeSet<u8> {
+        self.ranges.iter().map(|range| range.pdm_id).collect()
+    }
+
+    /// Whether a crop bed channel is wired to a PDM, so a message routed
+    /// there through the channel map is not dropped.
+    ///
+    /// * `pdm_id`: bed position of the PDM.
+    /// * `channel`: crop bed channel.
+    pub fn wires(&self, pdm_id: u8, channel: u8) -> bool {
+        self.ranges
+            .iter()
+            .any(|range| range.pdm_id == pdm_id && range.contains(channel))
+    }
+
+    /// PDM and channel on it a crop bed channel is wired to.
+    ///
+    /// * `channel`: crop bed channel.
+    pub fn locate(&self, channel: u8) -> Option<(u8, u8)> {
+        self.ranges
+            .iter()
+            .find(|range| range.contains(channel))
+            .map(|range| (range.pdm_id, channel - range.local_channel_offset))
+    }
+
+    /// Group channels by the PDM they were routed to, in one pass, giving
+    /// the channel on the PDM from the range of that PDM holding the crop
+    /// bed channel. Groups are keyed by bed position and offset, so a
+    /// message goes out as one call per PDM and the duty cycles can be
+    /// looked up by crop bed channel. Channels with no such range are
+    /// dropped.
+    ///
+    /// * `channels`: bed position of the PDM and crop bed channel.
+    pub fn group(&self, channels: &[(u8, u8)]) -> BTreeMap<(u8, u8), Vec<u8>> {
+        let mut groups: BTreeMap<(u8, u8), Vec<u8>> = BTreeMap::new();
+        for (pdm_id, channel) in channels {
+            match self
+                .ranges
+                .iter()
+                .find(|range| range.pdm_id == *pdm_id && range.contains(*channel))
+            {
+                Some(range) => groups
+                    .entry((range.pdm_id, range.local_channel_offset))
+                    .or_default()
+                    .push(channel - range.local_channel_offset),
+                None => warn!("Channel {channel} is not wired to PDM {pdm_id} in the channel layout"),
+            }
+        }
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::first(1, Some((0, 1)))]
+    #[case::last_of_first_pdm(12, Some((0, 12)))]
+    #[case::first_of_second_pdm(13, Some((1, 1)))]
+    #[case::last(24, Some((1, 12)))]
+    #[case::outside(25, None)]
+    #[case::zero(0, None)]
+    /// The default layout keeps the split the crop beds were wired with.
+    fn test_two_pdm_layout(#[case] channel: u8, #[case] expected: Option<(u8, u8)>) {
+        let layout = ChannelLayout::default();
+        layout.validate().unwrap();
+        assert_eq!(layout.locate(channel), expected);
+    }
+
+    #[rstest]
+    #[case::last_of_first_pdm(12, Some((0, 12)))]
+    #[case::first_of_second_pdm(13, Some((1, 1)))]
+    #[case::last_of_second_pdm(24, Some((1, 12)))]
+    #[case::first_of_third_pdm(25, Some((2, 1)))]
+    #[case::last(36, Some((2, 12)))]
+    #[case::outside(37, None)]
+    /// A third PDM only needs another range.
+    fn test_three_pdm_layout(#[case] channel: u8, #[case] expected: Option<(u8, u8)>) {
+        let layout = ChannelLayout::split(3);
+        layout.validate().unwrap();
+        assert_eq!(layout.locate(channel), expected);
+    }
+
+    #[test]
+    /// Channels of a message are grouped per PDM in the order sent, with
+    /// channels that are not wired to their PDM dropped.
+    fn test_group_channels() {
+        let layout = ChannelLayout::split(3);
+        let groups = layout.group(&[(1, 13), (0, 1), (0, 12), (2, 25), (2, 40), (1, 24), (0, 13)]);
+        assert_eq!(
+            groups.into_iter().collect::<Vec<_>>(),
+            vec![((0, 0), vec![1, 12]), ((1, 12), vec![1, 12]), ((2, 24), vec![1])]
+        );
+
+        // Odd wiring, the first six crop bed channels on the second PDM.
+        let layout = ChannelLayout::new(vec![
+            ChannelRange::new(1, 6, 1, 0),
+            ChannelRange::new(7, 12, 0, 6),
+            ChannelRange::new(13, 18, 0, 6),
+        ]);
+        layout.validate().unwrap();
+        assert_eq!(
+            layout.group(&[(1, 1), (0, 7), (0, 13)]).into_iter().collect::<Vec<_>>(),
+            vec![((0, 6), vec![1, 7]), ((1, 0), vec![1])]
+        );
+        assert_eq!(layout.pdm_ids(), BTreeSet::from([0, 1]));
+        assert!(layout.wires(1, 6));
+        assert!(!layout.wires(0, 6));
+        assert!(layout.wires(0, 18));
+        assert!(!layout.wires(0, 19));
+    }
+
+    #[rstest]
+    #[case::overlapping(vec![ChannelRange::new(1, 12, 0, 0), ChannelRange::new(12, 23, 1, 11)], "overlap")]
+    #[case::contained(vec![ChannelRange::new(13, 24, 1, 12), ChannelRange::new(15, 16, 0, 14)], "overlap")]
+    #[case::empty(vec![ChannelRange::new(5, 4, 0, 0)], "empty")]
+    #[case::past_last_channel(vec![ChannelRange::new(1, 13, 0, 0)], "outside")]
+    #[case::channel_zero(vec![ChannelRange::new(12, 24, 1, 12)], "outside")]
+    /// Layouts wiring a crop bed channel twice, or to a channel the PDM does
+    /// not have, are rejected.
+    fn test_reject_invalid_layout(#[case] ranges: Vec<ChannelRange>, #[case] reason: &str) {
+        let error = ChannelLayout::new(ranges).validate().unwrap_err();
+        assert!(error.contains(reason), "{error}");
+    }
+
+    #[test]
+    /// Layouts are written as a list of ranges in the component config.
+    fn test_layout_yaml() {
+        let yaml = "- first: 1\n  last: 12\n  pdm_id: 0\n  local_channel_offset: 0\n";
+        let layout: ChannelLayout = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(layout, ChannelLayout::split(1));
+        assert_eq!(serde_yaml::to_string(&layout).unwrap(), yaml);
+    }
+}
@@ -0,0 +1,286 @@
+use super::{CropBedPower, CropBedPowerConfig, DEFAULT_MAX_QUEUE_LEN};
+use crate::{
+    components::traits::ComponentError,
+    utils::config::{Validate, ValidationReport},
+};
+use std::sync::Arc;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::Mutex,
+};
+use tracing::{info, warn};
+
+impl CropBedPower {
+    /// Apply the items of a config that are safe to change while running,
+    /// the channel map and layout, timing, queue bounds, spray and message
+    /// limits, latency logging, the heartbeat, deduplication, solenoid
+    /// latency and the shutdown mode, returning the names of those that
+    /// changed. A config changing items that need the PDMs re-initialised
+    /// or the sockets bound again is refused whole, so the component never
+    /// runs a mix of two configs, as is one that does not validate.
+    /// Queued messages are kept as they were routed, the new items apply
+    /// to messages received after the reload.
+    ///
+    /// * `config`: config read from the file.
+    pub fn reload(&mut self, config: CropBedPowerConfig) -> Result<Vec<&'static str>, ComponentError> {
+        let running = &self.config;
+        let needs_restart = changed_items(&[
+            ("crop_bed_id", running.crop_bed_id != config.crop_bed_id),
+            ("canbus_id", running.canbus_id != config.canbus_id),
+            ("port", running.port != config.port),
+            ("pdm_config_files", running.pdm_config_files != config.pdm_config_files),
+            ("pdm_verification", running.pdm_verification != config.pdm_verification),
+            ("wheel_speed", running.wheel_speed != config.wheel_speed),
+            ("status_port", running.status_port != config.status_port),
+            ("journal_path", running.journal_path != config.journal_path),
+            ("journal_max_bytes", running.journal_max_bytes != config.journal_max_bytes),
+            ("transport", running.transport != config.transport),
+            ("max_datagram_bytes", running.max_datagram_bytes != config.max_datagram_bytes),
+            ("encoding", running.encoding != config.encoding),
+            ("framing", running.framing != config.framing),
+            ("heartbeat_emitter", running.heartbeat_emitter != config.heartbeat_emitter),
+            ("log", running.log != config.log),
+            ("telemetry", running.telemetry != config.telemetry),
+        ]);
+        if !needs_restart.is_empty() {
+            return Err(ComponentError::Invalid(format!(
+                "Changing {} needs the component restarting, nothing was reloaded",
+                needs_restart.join(", ")
+            )));
+        }
+        let mut report = ValidationReport::new(self.config_file.clone().unwrap_or_default());
+        config.validate(&mut report);
+        if !report.is_valid() {
+            return Err(ComponentError::Config(report));
+        }
+        let changed = changed_items(&[
+            ("channel_map", running.channel_map != config.channel_map),
+            ("channel_layout", running.channel_layout != config.channel_layout),
+            ("timing", running.timing != config.timing),
+            ("max_queue_len", running.max_queue_len != config.max_queue_len),
+            ("allow_manual_spray", running.allow_manual_spray != config.allow_manual_spray),
+            ("max_spray_duration_ms", running.max_spray_duration_ms != config.max_spray_duration_ms),
+            ("overlong_spray", running.overlong_spray != config.overlong_spray),
+            ("max_message_lead_ms", running.max_message_lead_ms != config.max_message_lead_ms),
+            ("log_latency", running.log_latency != config.log_latency),
+            ("heartbeat", running.heartbeat != config.heartbeat),
+            ("dedup", running.dedup != config.dedup),
+            ("solenoid_latency", running.solenoid_latency != config.solenoid_latency),
+            ("shutdown", running.shutdown != config.shutdown),
+        ]);
+
+        self.channel_map = config.channel_map.clone();
+        self.channel_layout = config.channel_layout.clone().unwrap_or_default();
+        self.timing = config.timing;
+        self.max_queue_len = config.max_queue_len.unwrap_or(DEFAULT_MAX_QUEUE_LEN);
+        self.allow_manual_spray = config.allow_manual_spray;
+        self.max_spray_duration = config.max_spray_duration();
+        self.message_constraints = config.message_constraints();
+        self.log_latency = config.log_latency;
+        self.heartbeat = config.heartbeat.unwrap_or_default();
+        self.recent_messages
+            .configure(config.dedup.unwrap_or_default(), self.clock.now());
+        self.solenoid_latency = config.solenoid_latency.clone().unwrap_or_default();
+        self.shutdown = config.shutdown.unwrap_or_default();
+        self.config = config;
+        if self.message_queue.len() > self.max_queue_len {
+            self.evict_furthest_sprays(self.clock.now());
+        }
+        // The firing task may be sleeping on a heartbeat from the old timing.
+        self.queue_changed.notify_one();
+        Ok(changed)
+    }
+}
+
+/// Names of the items that changed.
+///
+/// * `items`: name of each item and whether it changed.
+fn changed_items(items: &[(&'static str, bool)]) -> Vec<&'static str> {
+    items
+        .iter()
+        .filter_map(|(name, changed)| changed.then_some(*name))
+        .collect()
+}
+
+/// Read the config file of the component again and apply it, see
+/// [`CropBedPower::reload`]. The file is read with the component unlocked
+/// so firing is not held up by the disk.
+///
+/// * `power`: component
+pub async fn reload_config_file(power: &Mutex<CropBedPower>) -> Result<Vec<&'static str>, ComponentError> {
+    let Some(config_file) = power.lock().await.config_file.clone() else {
+        return Err(ComponentError::Invalid(String::from(
+            "The component was not started from a config file",
+        )));
+    };
+    let reloaded = match CropBedPowerConfig::try_from_file(&config_file) {
+        Ok(config) => power.lock().await.reload(config),
+        Err(e) => Err(ComponentError::Load(e)),
+    };
+    match &reloaded {
+        Ok(changed) => info!("Reloaded {:?}, changed {:?}", config_file, changed),
+        Err(e) => warn!("Reload of {:?} refused: {e}", config_file),
+    }
+    reloaded
+}
+
+/// Reload the config file each time the process receives SIGHUP, until the
+/// task is aborted.
+///
+/// * `power`: component
+pub(super) async fn reload_on_hangup(power: Arc<Mutex<CropBedPower>>) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            warn!("No config reload on SIGHUP: {e}");
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        // Refusals are logged, the component keeps its running config.
+        let _ = reload_config_file(&power).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::crop_bed::actuating::power::{handle_line, PowerTiming};
+    use crate::messages::control::weed::WeedMessageStatus;
+    use crate::utils::{location::CropBed, paths::repo_relative};
+    use chrono::{DateTime, Duration, Utc};
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    /// Weed message line spraying logical channel 0.
+    ///
+    /// * `start_spray_time`: time to start spraying.
+    fn weed_message_line(start_spray_time: DateTime<Utc>) -> Vec<u8> {
+        serde_json::json!({
+            "channels_to_open": [0],
+            "start_spray_time": start_spray_time,
+            "end_spray_time": start_spray_time + Duration::milliseconds(100),
+            "message_created_at": Utc::now(),
+            "capture_time": Utc::now(),
+            "time_diff_capture_to_start_spray_no_offset": 0.0,
+            "distance_to_solenoid_mm": 0.0,
+            "cam_id": 0,
+            "crop_bed_id": 0,
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    /// Config of the left boom with both PDMs, valid to reload.
+    ///
+    /// * `channel_map`: channel map, logical channels to crop bed channels and
+    ///   PDM.
+    fn power_config(channel_map: Option<HashMap<u8, (u8, u8)>>) -> CropBedPowerConfig {
+        CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, channel_map)
+            .add_pdm_config_file(repo_relative("config/devices/crop_bed/pdm_0.yaml").unwrap(), 0)
+            .add_pdm_config_file(repo_relative("config/devices/crop_bed/pdm_1.yaml").unwrap(), 1)
+    }
+
+    /// Write a config to a file.
+    ///
+    /// * `config_file`: file to write.
+    /// * `config`: config to write.
+    fn write_config(config_file: &std::path::Path, config: &CropBedPowerConfig) {
+        serde_yaml::to_writer(std::fs::File::create(config_file).unwrap(), config).unwrap();
+    }
+
+    #[tokio::test]
+    /// A changed channel map routes the messages received after the reload,
+    /// while the messages already queued are kept as they were routed.
+    async fn test_reload_channel_map_keeps_queue() {
+        let config_file = std::env::temp_dir().join(format!("onyx-reload-{}.yaml", Uuid::new_v4()));
+        let config = power_config(None);
+        write_config(&config_file, &config);
+        let power = Arc::new(Mutex::new(CropBedPower::from_config_file(&config_file).unwrap()));
+        let timing = PowerTiming::default();
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+        let response = handle_line(&weed_message_line(start_spray_time), Utc::now(), &timing, &power).await;
+        assert_eq!(response.status, WeedMessageStatus::Accepted);
+
+        let channel_map: HashMap<u8, (u8, u8)> = (1..=24)
+            .map(|channel| (channel, (25 - channel, u8::from(channel <= 12))))
+            .collect();
+        let config = power_config(Some(channel_map)).with_max_queue_len(64);
+        write_config(&config_file, &config);
+        assert_eq!(
+            reload_config_file(&power).await.unwrap(),
+            vec!["channel_map", "max_queue_len"]
+        );
+
+        handle_line(
+            &weed_message_line(start_spray_time + Duration::seconds(1)),
+            Utc::now(),
+            &timing,
+            &power,
+        )
+        .await;
+        let gaurd = power.lock().await;
+        assert_eq!(gaurd.message_queue.len(), 4, "Queue was dropped on reload");
+        for (queued, _) in &gaurd.message_queue {
+            let expected = if queued.original_spray_starts == start_spray_time {
+                vec![(0, 1)]
+            } else {
+                vec![(1, 24)]
+            };
+            assert_eq!(queued.channels, expected);
+        }
+        assert_eq!(gaurd.max_queue_len, 64);
+        drop(gaurd);
+        std::fs::remove_file(config_file).unwrap();
+    }
+
+    #[tokio::test]
+    /// Configs changing what the PDMs or sockets were set up with are
+    /// refused whole, naming what needs a restart.
+    async fn test_reload_refuses_restart_changes() {
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None);
+        let mut power = CropBedPower::new(config).unwrap();
+        let mut channel_map = HashMap::new();
+        channel_map.insert(1, (3, 0));
+        let changed = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can1"), 17651, Some(channel_map));
+        let error = power.reload(changed).unwrap_err().to_string();
+        assert!(error.contains("canbus_id, port"), "{error}");
+        assert!(power.channel_map.is_none(), "Safe items were applied from a refused config");
+    }
+
+    #[tokio::test]
+    /// A config that does not validate is refused whole with the problems
+    /// found, the channel layout and the other items alike.
+    async fn test_reload_refuses_invalid_config() {
+        let mut power = CropBedPower::new(power_config(None)).unwrap();
+        let mut channel_map = HashMap::new();
+        channel_map.insert(1, (3, 2));
+        let invalid = power_config(Some(channel_map)).with_max_queue_len(0);
+        let Err(ComponentError::Config(report)) = power.reload(invalid) else {
+            panic!("Invalid config was reloaded");
+        };
+        assert_eq!(report.fields(), vec!["channel_map.1", "max_queue_len"]);
+        assert!(
+            power.channel_map.is_none(),
+            "Safe items were applied from a refused config"
+        );
+        assert_eq!(power.max_queue_len, DEFAULT_MAX_QUEUE_LEN);
+    }
+
+    #[tokio::test]
+    /// A config file that cannot be read leaves the component running its
+    /// config.
+    async fn test_reload_unreadable_file() {
+        let config_file = std::env::temp_dir().join(format!("onyx-reload-{}.yaml", Uuid::new_v4()));
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None);
+        write_config(&config_file, &config);
+        let power = Mutex::new(CropBedPower::from_config_file(&config_file).unwrap());
+        std::fs::write(&config_file, "canbus_id: [").unwrap();
+        assert!(matches!(reload_config_file(&power).await, Err(ComponentError::Load(_))));
+        assert_eq!(power.lock().await.config, config);
+        std::fs::remove_file(config_file).unwrap();
+
+        let power = Mutex::new(CropBedPower::new(config).unwrap());
+        assert!(reload_config_file(&power).await.is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+
+/// Time a channel has been asked to spray for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SprayInterval {
+    /// When spraying starts.
+    pub start: DateTime<Utc>,
+    /// When spraying ends.
+    pub end: DateTime<Utc>,
+}
+
+impl SprayInterval {
+    /// Whether the channel should be spraying at a time. The end is
+    /// exclusive so the off at the end of an interval is not suppressed by
+    /// the interval itself.
+    ///
+    /// * `time`: time to check.
+    pub fn covers(&self, time: DateTime<Utc>) -> bool {
+        self.start <= time && time < self.end
+    }
+}
+
+/// Spray intervals accepted for each channel, merged so a channel has at
+/// most one interval covering any time. Detections of the same weed, or of
+/// weeds close together, overlap; turning the channel off at the end of one
+/// would cut short another, so offs are only sent once no accepted interval
+/// still covers the channel.
+#[derive(Debug, Default)]
+pub struct SpraySchedule {
+    /// Merged intervals for each channel, in start order and disjoint.
+    intervals: BTreeMap<u8, Vec<SprayInterval>>,
+}
+
+impl SpraySchedule {
+    /// Accept a spray for channels, merging it with any interval it
+    /// overlaps or touches and forgetting intervals that ended before now.
+    ///
+    /// * `channels`: channels to spray.
+    /// * `start`: when spraying starts.
+    /// * `end`: when spraying ends.
+    /// * `now`: current time, intervals ending before it are dropped.
+    pub fn insert(&mut self, channels: &[u8], start: DateTime<Utc>, end: DateTime<Utc>, now: DateTime<Utc>) {
+        for channel in channels {
+            let intervals = self.intervals.entry(*channel).or_default();
+            intervals.retain(|interval| interval.end >= now);
+
+            let mut merged = SprayInterval { start, end };
+            intervals.retain(|interval| {
+                let touches = interval.start <= merged.end && merged.start <= interval.end;
+                if touches {
+                    merged.start = merged.start.min(interval.start);
+                    merged.end = merged.end.max(interval.end);
+                }
+                !touches
+            });
+            let position = intervals.partition_point(|interval| interval.start < merged.start);
+            intervals.insert(position, merged);
+        }
+        self.intervals.retain(|_, intervals| !intervals.is_empty());
+    }
+
+    /// Whether a channel should still be spraying at a time.
+    ///
+    /// * `channel`: channel to check.
+    /// * `time`: time to check.
+    pub fn covers(&self, channel: u8, time: DateTime<Utc>) -> bool {
+        self.intervals
+            .get(&channel)
+            .is_some_and(|intervals| intervals.iter().any(|interval| interval.covers(time)))
+    }
+
+    /// Channels of an off that can be turned off at a time, dropping those
+    /// another interval still covers.
+    ///
+    /// * `channels`: channels the off was for.
+    /// * `time`: time of the off.
+    pub fn channels_to_turn_off(&self, channels: &[u8], time: DateTime<Utc>) -> Vec<u8> {
+        channels
+            .iter()
+            .copied()
+            .filter(|channel| !self.covers(*channel, time))
+            .collect()
+    }
+
+    /// Whether a channel starts spraying again within a window after a
+    /// time, so turning it off at the time would only chatter the solenoid.
+    ///
+    /// * `channel`: channel to check.
+    /// * `time`: time of the off.
+    /// * `window`: debounce window after the off.
+    pub fn resumes_within(&self, channel: u8, time: DateTime<Utc>, window: Duration) -> bool {
+        self.intervals.get(&channel).is_some_and(|intervals| {
+            intervals
+                .iter()
+                .any(|interval| time < interval.start && interval.start <= time + window)
+        })
+    }
+
+    /// Merged intervals of a channel, in start order.
+    ///
+    /// * `channel`: channel to look up.
+    pub fn intervals(&self, channel: u8) -> &[SprayInterval] {
+        self.intervals.get(&channel).map_or(&[], Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use proptest::prelude::*;
+    use rstest::rstest;
+
+    /// Time a number of milliseconds after a fixed origin.
+    ///
+    /// * `ms`: milliseconds after the origin.
+    fn at(ms: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_000, 0).unwrap() + Duration::milliseconds(ms)
+    }
+
+    #[rstest]
+    #[case::fully_contained((0, 100), (20, 60), vec![(0, 100)])]
+    #[case::containing((20, 60), (0, 100), vec![(0, 100)])]
+    #[case::partial_overlap((0, 100), (50, 150), vec![(0, 150)])]
+    #[case::back_to_back((0, 100), (100, 200), vec![(0, 200)])]
+    #[case::disjoint((0, 100), (150, 200), vec![(0, 100), (150, 200)])]
+    #[case::disjoint_before((150, 200), (0, 100), vec![(0, 100), (150, 200)])]
+    /// Intervals that overlap or touch merge into one, others are kept
+    /// apart in start order.
+    fn test_merge_intervals(
+        #[case] first: (i64, i64),
+        #[case] second: (i64, i64),
+        #[case] expected: Vec<(i64, i64)>,
+    ) {
+        let mut schedule = SpraySchedule::default();
+        schedule.insert(&[3], at(first.0), at(first.1), at(0));
+        schedule.insert(&[3], at(second.0), at(second.1), at(0));
+        let expected: Vec<SprayInterval> = expected
+            .into_iter()
+            .map(|(start, end)| SprayInterval {
+                start: at(start),
+                end: at(end),
+            })
+            .collect();
+        assert_eq!(schedule.intervals(3), expected.as_slice());
+    }
+
+    #[test]
+    /// The off of a shorter detection is dropped while a longer one still
+    /// covers the channel, other channels of the off still go off.
+    fn test_suppress_conflicting_off() {
+        let mut schedule = SpraySchedule::default();
+        schedule.insert(&[1, 2], at(0), at(100), at(0));
+        schedule.insert(&[1], at(50), at(200), at(0));
+        assert_eq!(schedule.channels_to_turn_off(&[1, 2], at(100)), vec![2]);
+        assert_eq!(schedule.channels_to_turn_off(&[1], at(200)), vec![1]);
+    }
+
+    #[test]
+    /// Intervals that have ended are forgotten on the next insert.
+    fn test_prune_ended_intervals() {
+        let mut schedule = SpraySchedule::default();
+        schedule.insert(&[1, 2], at(0), at(100), at(0));
+        schedule.insert(&[1], at(500), at(600), at(400));
+        assert_eq!(schedule.intervals(1).len(), 1);
+        assert_eq!(schedule.intervals(2).len(), 1);
+        schedule.insert(&[2], at(500), at(600), at(400));
+        assert_eq!(schedule.intervals(2)[0].start, at(500));
+    }
+
+    #[rstest]
+    #[case::inside_window(120, 30, true)]
+    #[case::edge_of_window(130, 30, true)]
+    #[case::past_window(140, 30, false)]
+    #[case::no_window(110, 0, false)]
+    /// A channel resumes within the window only for sprays starting after
+    /// the off and no later than the window.
+    fn test_resumes_within(#[case] next_start: i64, #[case] window_ms: i64, #[case] expected: bool) {
+        let mut schedule = SpraySchedule::default();
+        schedule.insert(&[4], at(0), at(100), at(0));
+        schedule.insert(&[4], at(next_start), at(next_start + 100), at(0));
+        assert_eq!(schedule.resumes_within(4, at(100), Duration::milliseconds(window_ms)), expected);
+        assert!(!schedule.resumes_within(5, at(100), Duration::milliseconds(window_ms)));
+    }
+
+    proptest! {
+        #[test]
+        /// Whatever order sprays arrive in, an off that gets through is never
+        /// inside an accepted interval, and every time inside an accepted
+        /// interval stays covered.
+        fn test_never_off_while_covered(
+            sprays in prop::collection::vec((0i64..1000, 1i64..300), 1..20),
+            probes in prop::collection::vec(0i64..1300, 1..50),
+        ) {
+            let mut schedule = SpraySchedule::default();
+            for (start, length) in &sprays {
+                schedule.insert(&[1], at(*start), at(start + length), at(0));
+            }
+            for probe in probes {
+                let covered = sprays
+                    .iter()
+                    .any(|(start, length)| *start <= probe && probe < start + length);
+                prop_assert_eq!(schedule.covers(1, at(probe)), covered);
+                if covered {
+                    prop_assert!(schedule.channels_to_turn_off(&[1], at(probe)).is_empty());
+                }
+            }
+            for end in sprays.iter().map(|(start, length)| start + length) {
+                let still_covered = sprays.iter().any(|(start, length)| *start <= end && end < start + length);
+                prop_assert_eq!(schedule.channels_to_turn_off(&[1], at(end)).is_empty(), still_covered);
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::utils::tasks::DEFAULT_SHUTDOWN_TIMEOUT;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Time in milliseconds ahead of the stop the queued sprays are still fired
+/// when draining, when not set in the config. Weeds already passed under
+/// the cameras are sprayed within it at working speeds.
+pub const DEFAULT_DRAIN_HORIZON_MS: u64 = 2000;
+
+/// Longest drain horizon in milliseconds a config may set, half the time a
+/// binary waits for the shutdown. Draining longer would see the shutdown
+/// given up on before every channel is turned off.
+pub const MAX_DRAIN_HORIZON_MS: u64 = DEFAULT_SHUTDOWN_TIMEOUT.as_secs() * 1000 / 2;
+
+/// How the component stops when asked to shut down, an emergency stop is
+/// always immediate.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ShutdownMode {
+    /// Stop accepting messages, fire the queued sprays due within the
+    /// horizon, cutting those still open at its end, then turn every
+    /// channel off.
+    Drain,
+    /// Drop the queue and turn every channel off now.
+    #[default]
+    Immediate,
+}
+
+/// How the component stops when asked to shut down. Every field falls back
+/// to its default when missing.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    /// Whether the queue is drained or dropped.
+    pub mode: ShutdownMode,
+    /// Time in milliseconds ahead of the stop the queued sprays are fired
+    /// when draining.
+    pub drain_horizon_ms: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            mode: ShutdownMode::default(),
+            drain_horizon_ms: DEFAULT_DRAIN_HORIZON_MS,
+        }
+    }
+}
+
+impl ShutdownConfig {
+    /// Drain the queue on a shutdown, firing the sprays due within a
+    /// horizon.
+    ///
+    /// * `drain_horizon_ms`: time in milliseconds ahead of the stop.
+    pub fn drain(drain_horizon_ms: u64) -> Self {
+        Self {
+            mode: ShutdownMode::Drain,
+            drain_horizon_ms,
+        }
+    }
+
+    /// Time ahead of the stop the queued sprays are fired when draining.
+    pub fn drain_horizon(&self) -> Duration {
+        Duration::from_millis(self.drain_horizon_ms)
+    }
+
+    /// Why the drain horizon would outlast the shutdown, `None` when it is
+    /// well within it.
+    pub fn check_drain_horizon(&self) -> Option<String> {
+        (self.drain_horizon_ms > MAX_DRAIN_HORIZON_MS).then(|| {
+            format!(
+                "{}ms is over the {MAX_DRAIN_HORIZON_MS}ms allowed within the {}s shutdown timeout",
+                self.drain_horizon_ms,
+                DEFAULT_SHUTDOWN_TIMEOUT.as_secs()
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// A config without the section, or with only the mode, falls back to
+    /// the defaults.
+    fn test_shutdown_config_defaults() {
+        assert_eq!(ShutdownConfig::default().mode, ShutdownMode::Immediate);
+        let config: ShutdownConfig = serde_yaml::from_str("mode: drain").unwrap();
+        assert_eq!(config, ShutdownConfig::drain(DEFAULT_DRAIN_HORIZON_MS));
+        let config: ShutdownConfig = serde_yaml::from_str("drain_horizon_ms: 500").unwrap();
+        assert_eq!(
+            (config.mode, config.drain_horizon()),
+            (ShutdownMode::Immediate, Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    /// A horizon up to half the shutdown timeout is allowed, one near the
+    /// timeout is refused.
+    fn test_drain_horizon_within_shutdown_timeout() {
+        assert_eq!(ShutdownConfig::default().check_drain_horizon(), None);
+        assert_eq!(ShutdownConfig::drain(MAX_DRAIN_HORIZON_MS).check_drain_horizon(), None);
+        let problem = ShutdownConfig::drain(9500)
+            .check_drain_horizon()
+            .expect("Horizon near the timeout allowed");
+        assert!(problem.contains("10s shutdown timeout"), "{problem}");
+    }
+}
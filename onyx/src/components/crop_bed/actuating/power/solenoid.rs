@@ -0,0 +1,150 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Delay of the solenoids between the PDM switching a channel and the
+/// liquid starting or stopping at the nozzle, for every channel with
+/// overrides for those that differ. Every field falls back to its default
+/// when missing, so no channel is compensated unless set.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SolenoidLatencyConfig {
+    /// Time in milliseconds from a channel being energised to the liquid
+    /// leaving the nozzle.
+    pub open_latency_ms: u64,
+    /// Time in milliseconds from a channel being de-energised to the liquid
+    /// stopping.
+    pub close_latency_ms: u64,
+    /// Latency of the channels that differ, keyed by crop bed channel as in
+    /// the channel map.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub channels: BTreeMap<u8, ChannelLatency>,
+}
+
+/// Latency of one channel, each field falls back to that of every channel
+/// when not set.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct ChannelLatency {
+    /// Time in milliseconds from the channel being energised to the liquid
+    /// leaving the nozzle.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_latency_ms: Option<u64>,
+    /// Time in milliseconds from the channel being de-energised to the
+    /// liquid stopping.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub close_latency_ms: Option<u64>,
+}
+
+/// Latency the on and off of a channel are fired ahead by.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Default)]
+pub struct SolenoidLatency {
+    /// Delay from energising the channel to the liquid leaving the nozzle.
+    pub open: Duration,
+    /// Delay from de-energising the channel to the liquid stopping.
+    pub close: Duration,
+}
+
+impl SolenoidLatencyConfig {
+    /// Latency of a channel.
+    ///
+    /// * `channel`: crop bed channel.
+    pub fn of(&self, channel: u8) -> SolenoidLatency {
+        let latency = self.channels.get(&channel).copied().unwrap_or_default();
+        SolenoidLatency {
+            open: milliseconds(latency.open_latency_ms.unwrap_or(self.open_latency_ms)),
+            close: milliseconds(latency.close_latency_ms.unwrap_or(self.close_latency_ms)),
+        }
+    }
+
+    /// Channels of a spray grouped by their latency, each group keeping
+    /// the order of the channels. Channels with the same latency fire
+    /// together, so a spray is only split when they differ.
+    ///
+    /// * `channels`: bed position of the PDM and crop bed channel.
+    pub fn group(&self, channels: Vec<(u8, u8)>) -> BTreeMap<SolenoidLatency, Vec<(u8, u8)>> {
+        let mut groups: BTreeMap<SolenoidLatency, Vec<(u8, u8)>> = BTreeMap::new();
+        for channel in channels {
+            groups.entry(self.of(channel.1)).or_default().push(channel);
+        }
+        groups
+    }
+}
+
+/// Time to fire a channel so the liquid follows at a time, returning
+/// whether it was clamped. A fire time is never moved before the floor,
+/// or later than the time itself when that is already past the floor.
+///
+/// * `target`: when the liquid should start or stop.
+/// * `latency`: delay of the solenoid.
+/// * `floor`: earliest time to fire, now for an on and the on for an off.
+pub fn compensate(target: DateTime<Utc>, latency: Duration, floor: DateTime<Utc>) -> (DateTime<Utc>, bool) {
+    let compensated = target - latency;
+    let floor = floor.min(target);
+    if compensated < floor {
+        (floor, true)
+    } else {
+        (compensated, false)
+    }
+}
+
+/// Duration of a number of milliseconds from the config.
+///
+/// * `ms`: milliseconds.
+fn milliseconds(ms: u64) -> Duration {
+    Duration::milliseconds(i64::try_from(ms).unwrap_or(i64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::ahead(1000, 12, 0, 988, false)]
+    #[case::not_configured(1000, 0, 0, 1000, false)]
+    #[case::onto_floor(1000, 12, 988, 988, false)]
+    #[case::clamped(1000, 12, 995, 995, true)]
+    #[case::already_past(1000, 12, 1500, 1000, true)]
+    /// Fire times are moved ahead by the latency, clamped to the floor
+    /// without moving past the time asked for.
+    fn test_compensate(
+        #[case] target_ms: i64,
+        #[case] latency_ms: i64,
+        #[case] floor_ms: i64,
+        #[case] expected_ms: i64,
+        #[case] clamped: bool,
+    ) {
+        let now = Utc::now();
+        let at = |ms| now + Duration::milliseconds(ms);
+        assert_eq!(
+            compensate(at(target_ms), Duration::milliseconds(latency_ms), at(floor_ms)),
+            (at(expected_ms), clamped)
+        );
+    }
+
+    #[test]
+    /// Channels fall back to the latency of every channel field by field,
+    /// and are grouped by the latency they end up with.
+    fn test_channel_latency() {
+        let config: SolenoidLatencyConfig = serde_yaml::from_str(
+            "open_latency_ms: 10\nclose_latency_ms: 12\nchannels:\n  3:\n    open_latency_ms: 15\n  4:\n    open_latency_ms: 10\n",
+        )
+        .unwrap();
+        let default = SolenoidLatency {
+            open: Duration::milliseconds(10),
+            close: Duration::milliseconds(12),
+        };
+        assert_eq!(config.of(1), default);
+        assert_eq!(config.of(4), default);
+        let slow = SolenoidLatency {
+            open: Duration::milliseconds(15),
+            close: Duration::milliseconds(12),
+        };
+        assert_eq!(config.of(3), slow);
+        assert_eq!(
+            config.group(vec![(0, 4), (0, 3), (0, 1)]),
+            BTreeMap::from([(default, vec![(0, 4), (0, 1)]), (slow, vec![(0, 3)])])
+        );
+        assert_eq!(SolenoidLatencyConfig::default().of(1), SolenoidLatency::default());
+    }
+}
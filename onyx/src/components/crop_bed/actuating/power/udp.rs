@@ -0,0 +1,221 @@
+use super::{handle_line, stopping, CropBedPower};
+use crate::messages::control::weed::{WeedMessageResponse, WeedMessageStatus};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::{
+    net::UdpSocket,
+    sync::{watch, Mutex},
+};
+use tracing::warn;
+
+/// Size in bytes of the largest datagram handled, when not set in the
+/// config. Weed messages are a few hundred bytes.
+pub const DEFAULT_MAX_DATAGRAM_BYTES: usize = 4096;
+
+/// Transports the weed messages are received over, both on the port of the
+/// component.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// Lines over TCP connections, each answered on its connection.
+    #[default]
+    Tcp,
+    /// One message per UDP datagram, each answered to its sender.
+    Udp,
+    /// TCP and UDP together.
+    Both,
+}
+
+impl Transport {
+    /// Whether weed messages are received over TCP.
+    pub fn tcp(self) -> bool {
+        matches!(self, Self::Tcp | Self::Both)
+    }
+
+    /// Whether weed messages are received over UDP.
+    pub fn udp(self) -> bool {
+        matches!(self, Self::Udp | Self::Both)
+    }
+}
+
+/// Handle datagrams from the AI container until the component is stopped.
+/// Each datagram is one message, handled as a line received over TCP is,
+/// and the response is sent back to the sender, which need not wait for
+/// it. Datagrams larger than the maximum are rejected without being read.
+///
+/// * `socket`: bound socket, use port 0 in tests.
+/// * `power`: component
+/// * `stop_rx`: stop signal from the component handle.
+pub(super) async fn serve_datagrams(
+    socket: UdpSocket,
+    power: Arc<Mutex<CropBedPower>>,
+    mut stop_rx: watch::Receiver<bool>,
+) {
+    let (clock, max_datagram_bytes, uuid) = {
+        let gaurd = power.lock().await;
+        (gaurd.clock, gaurd.max_datagram_bytes, gaurd.uuid)
+    };
+    // One byte spare, a datagram filling the buffer was cut short by the
+    // socket and is larger than the maximum.
+    let mut data = vec![0; max_datagram_bytes + 1];
+    while !stopping(&stop_rx) {
+        let (length, sender) = tokio::select! {
+            received = socket.recv_from(&mut data) => match received {
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("Failed to receive from the analysis system: {e}");
+                    continue;
+                }
+            },
+            _ = stop_rx.changed() => continue,
+        };
+        let received_at = clock.now();
+        let datagram = &data[..length];
+        let response = if length > max_datagram_bytes {
+            warn!("Datagram from {sender} rejected, larger than {max_datagram_bytes} bytes");
+            power.lock().await.message_counts.record(WeedMessageStatus::Rejected);
+            WeedMessageResponse::refused(None, format!("datagram larger than {max_datagram_bytes} bytes"))
+        } else if datagram.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        } else {
+            let timing = power.lock().await.timing;
+            handle_line(datagram, received_at, &timing, &power).await
+        };
+        let response = serde_json::to_vec(&response.to_control(uuid)).expect("Failed to serialise response");
+        if let Err(e) = socket.send_to(&response, sender).await {
+            warn!("Failed to respond to {sender}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::crop_bed::actuating::power::{CropBedPowerConfig, CropBedPowerController};
+    use crate::messages::control::response::ControlResponse;
+    use crate::utils::{
+        location::CropBed,
+        responses::{parse_response, read_response},
+    };
+    use chrono::{DateTime, Duration, Utc};
+    use rstest::rstest;
+    use serial_test::serial;
+    use tokio::{
+        io::{AsyncWriteExt, BufReader},
+        net::TcpStream,
+    };
+
+    /// Virtual canbus interface used by the `vcan_test` tests.
+    fn vcan_interface() -> String {
+        std::env::var("ONYX_VCAN").unwrap_or_else(|_| String::from("vcan0"))
+    }
+
+    /// Weed message line spraying logical channel 0.
+    ///
+    /// * `start_spray_time`: time to start spraying.
+    fn weed_message_line(start_spray_time: DateTime<Utc>) -> String {
+        serde_json::json!({
+            "channels_to_open": [0],
+            "start_spray_time": start_spray_time,
+            "end_spray_time": start_spray_time + Duration::milliseconds(100),
+            "message_created_at": Utc::now(),
+            "capture_time": Utc::now(),
+            "time_diff_capture_to_start_spray_no_offset": 0.0,
+            "distance_to_solenoid_mm": 0.0,
+            "cam_id": 0,
+            "crop_bed_id": 0,
+        })
+        .to_string()
+    }
+
+    /// Send a datagram to a port on the loop back and read the response.
+    ///
+    /// * `port`: port the component listens on.
+    /// * `datagram`: data sent.
+    async fn exchange_datagram(port: u16, datagram: &[u8]) -> ControlResponse {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.send_to(datagram, format!("127.0.0.1:{port}")).await.unwrap();
+        let mut response = vec![0; 1024];
+        let length = tokio::time::timeout(std::time::Duration::from_secs(1), socket.recv(&mut response))
+            .await
+            .expect("No response to the datagram")
+            .unwrap();
+        parse_response(&response[..length]).expect("Response is not a control response")
+    }
+
+    #[rstest]
+    #[case::tcp("tcp", Transport::Tcp, true, false)]
+    #[case::udp("udp", Transport::Udp, false, true)]
+    #[case::both("both", Transport::Both, true, true)]
+    /// Transports are written in lower case in the component config.
+    fn test_transport_yaml(#[case] yaml: &str, #[case] expected: Transport, #[case] tcp: bool, #[case] udp: bool) {
+        let transport: Transport = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(transport, expected);
+        assert_eq!((transport.tcp(), transport.udp()), (tcp, udp));
+    }
+
+    #[tokio::test]
+    /// A datagram is queued as a weed message, and one larger than the
+    /// maximum is rejected and counted without being parsed.
+    async fn test_datagrams_queued_and_bounded() {
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17650, None)
+            .with_max_datagram_bytes(512);
+        let power = Arc::new(Mutex::new(CropBedPower::new(config).unwrap()));
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let server = tokio::spawn(serve_datagrams(socket, power.clone(), stop_rx));
+
+        let line = weed_message_line(Utc::now() + Duration::seconds(5));
+        let response = exchange_datagram(port, line.as_bytes()).await;
+        assert!(response.is_accepted());
+        let oversized = format!("{line}{}", " ".repeat(512));
+        let response = exchange_datagram(port, oversized.as_bytes()).await;
+        assert_eq!(response.reason(), Some("datagram larger than 512 bytes"));
+        let response = exchange_datagram(port, b"{\"channels_to_open\": [0]").await;
+        assert_eq!(response.reason(), Some("malformed message"));
+
+        stop_tx.send_replace(true);
+        server.await.unwrap();
+        let gaurd = power.lock().await;
+        assert_eq!(gaurd.message_queue.len(), 2);
+        assert_eq!(
+            (gaurd.message_counts.accepted, gaurd.message_counts.rejected, gaurd.message_counts.malformed),
+            (1, 1, 1)
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// A component receiving both transports queues the messages from each
+    /// into the one queue.
+    async fn test_both_transports_into_one_component() {
+        let port: u16 = 17670;
+        let config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), i32::from(port), None)
+            .with_transport(Transport::Both);
+        let component = CropBedPowerController::start(CropBedPower::new(config).unwrap())
+            .await
+            .unwrap();
+        let start_spray_time = Utc::now() + Duration::seconds(5);
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).await.unwrap();
+        stream
+            .write_all(format!("{}\n", weed_message_line(start_spray_time)).as_bytes())
+            .await
+            .unwrap();
+        let response = read_response(&mut BufReader::new(stream), std::time::Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert!(response.is_accepted());
+
+        let line = weed_message_line(start_spray_time + Duration::seconds(1));
+        let response = exchange_datagram(port, line.as_bytes()).await;
+        assert!(response.is_accepted());
+
+        let snapshot = component.snapshot().await;
+        assert_eq!(snapshot.messages.accepted, 2);
+        assert_eq!(snapshot.queue_depth, 4);
+        component.shutdown().await;
+    }
+}
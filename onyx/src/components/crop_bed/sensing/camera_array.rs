@@ -1,45 +1,364 @@
-use crate::devices::hardware::camera::{
-    CameraController, DevicePayload, OnyxCamera, OnyxCameraConfig,
+use crate::{
+    components::traits::{ComponentConfig, ComponentController, ComponentError, ComponentHandle},
+    devices::{
+        hardware::camera::{
+            CameraCommand, CameraController, CameraStats, CameraStatsSnapshot, DevicePayload, OnyxCameraConfig,
+            StartGate,
+        },
+        software::camera::{SimulatedCamera, SimulatedCameraConfig},
+    },
+    messages::{
+        control::heartbeat::{ComponentKind, Heartbeat},
+        logging::{EventCode, LogLevel},
+        telemetry::{CameraFrameTelemetry, CameraTelemetry, TelemetryHeader},
+    },
+    utils::{
+        bus::{BusEvent, MessageBus, Topic},
+        heartbeat::{emit_heartbeats, HeartbeatEmitterConfig},
+        image::{debayer, BayerPattern, Roi},
+        location::CropBed,
+        logging::{spawn_in_current_span, LogConfig, LogEmitter},
+        config::{load_yaml, ConfigError, Validate, ValidationReport},
+        metrics,
+        serde::{ordered_map, ordered_optional_map},
+        shm::{ShmImageWriter, ShmSinkConfig},
+        telemetry::{TelemetryEmitter, TelemetryEmitterConfig},
+    },
 };
-use ringbuffer::{AllocRingBuffer, RingBuffer};
+use image::DynamicImage;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     ffi::OsStr,
     fmt::Display,
-    fs::create_dir_all,
+    fs::{self, create_dir_all},
     path::{Path, PathBuf},
-    sync::{atomic::AtomicBool, mpsc, Arc, Barrier},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, PoisonError,
+    },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
+/// Retention of the images saved to the capture directory.
+pub mod retention;
+
+/// Image writer running on a tokio runtime.
+pub mod async_writer;
+
+/// Latest downscaled frame of each camera for the live preview.
+pub mod preview;
+
+/// Skipping frames that look the same as the last one saved.
+pub mod dedupe;
+
+/// Trigger events of the cameras for the lighting to strobe on.
+pub mod trigger;
+
+/// Compact line of the array statistics logged at an interval.
+pub mod report;
+
+/// HTTP status server for the HMI.
+#[cfg(feature = "http")]
+pub mod http;
+
+use async_writer::AsyncWriterConfig;
+use dedupe::{DedupeConfig, FrameDedupe};
+use preview::{ChannelZone, PreviewConfig, PreviewFrames};
+use retention::{RetentionPolicy, PARTIAL_SUFFIX};
+use trigger::TriggerPublisherConfig;
+
+/// Longest time a camera waits at the start gate for the rest of the array.
+const START_GATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the supervisor checks on the camera threads.
+const SUPERVISOR_POLL: Duration = Duration::from_millis(50);
+
+/// Image writer workers used when the config does not set a count.
+const DEFAULT_WRITER_THREADS: usize = 4;
+
 /// Camera handle is generated when starting a device from
 /// within a component. Allows threads to be stopped in a
-/// consistent manner.
+/// consistent manner, and rebuilt by the supervisor if they exit.
 #[allow(dead_code)]
 pub struct CameraHandle {
+    /// Retained config the device is rebuilt from.
+    blueprint: CameraBlueprint,
     /// The spawned thread handle that needs to be cleaned up.
     join_handle: Option<JoinHandle<()>>,
     /// Thread safe signal to gracefully shutdown a separate thread.
     stop_signal: Option<Arc<AtomicBool>>,
+    /// Counters updated by the camera thread.
+    stats: Arc<CameraStats>,
+    /// Times the camera has been rebuilt.
+    restarts: u32,
+    /// Back off to apply before the next rebuild.
+    backoff: Duration,
+    /// When the next rebuild is due, if one is scheduled.
+    restart_at: Option<Instant>,
+    /// Sends commands to the running capture loop.
+    commands: Sender<CameraCommand>,
+    /// How far the watchdog has gone to recover a stalled camera.
+    stall: StallRecovery,
+    /// The operator asked for the device to be rebuilt, which does not
+    /// count against its restarts.
+    restart_requested: bool,
+}
+
+/// Recovery tiers the watchdog steps through for a stalled camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StallRecovery {
+    /// Frames are arriving.
+    Healthy,
+    /// The stream was restarted at this time.
+    StreamRestarted(Instant),
+    /// The capture loop was asked to exit so the device is rebuilt.
+    Rebuilding,
+}
+
+/// Config a camera device is built from, kept so the device can be
+/// rebuilt when its thread exits.
+#[derive(Clone)]
+enum CameraBlueprint {
+    /// A genicam camera on the network.
+    Hardware(OnyxCameraConfig),
+    /// A camera generating synthetic frames.
+    Simulated(SimulatedCameraConfig),
+}
+
+impl CameraBlueprint {
+    /// Build the device and run it in a new thread. The device is built
+    /// inside the thread so a camera missing from the network is handled
+    /// the same way as a camera that stops or panics mid stream.
+    ///
+    /// * `bed_position`: position in line with bill of materials.
+    /// * `stop_signal`: Will halt the camera streaming.
+    /// * `start_gate`: Initial sync with the rest of the array.
+    /// * `image_channel`: MPSC channel for sharing payloads.
+    /// * `stats`: Counters shared with the component.
+    fn spawn(
+        &self,
+        bed_position: u8,
+        stop_signal: Arc<AtomicBool>,
+        start_gate: Option<Arc<StartGate>>,
+        image_channel: Sender<DevicePayload>,
+        stats: Arc<CameraStats>,
+    ) -> (JoinHandle<()>, Sender<CameraCommand>) {
+        let blueprint = self.clone();
+        let (commands_tx, commands) = mpsc::channel();
+        let join_handle = spawn_in_current_span(move || {
+            let started = match blueprint {
+                #[cfg(feature = "camera-hw")]
+                CameraBlueprint::Hardware(config) => OnyxCamera::new(config).and_then(|mut camera| {
+                    camera.set_location_id(bed_position);
+                    CameraController::start(camera, stop_signal, start_gate, image_channel, stats, commands)
+                }),
+                #[cfg(not(feature = "camera-hw"))]
+                CameraBlueprint::Hardware(_) => unreachable!("Network cameras are refused without camera-hw"),
+                CameraBlueprint::Simulated(config) => {
+                    let mut camera = SimulatedCamera::new(config);
+                    camera.set_location_id(bed_position);
+                    CameraController::start(camera, stop_signal, start_gate, image_channel, stats, commands)
+                }
+            };
+            // The supervisor rebuilds a camera whose thread exited early, as
+            // it does one that panicked.
+            if let Err(e) = started {
+                error!("Camera at bed position {bed_position} stopped: {e}");
+            }
+        });
+        (join_handle, commands_tx)
+    }
+
+    /// Time between frames at the configured frame rate.
+    fn frame_interval(&self) -> Duration {
+        let fps = match self {
+            CameraBlueprint::Hardware(config) => config.fps(),
+            CameraBlueprint::Simulated(config) => config.fps(),
+        };
+        Duration::from_secs_f64(1.0 / f64::from(fps.max(1)))
+    }
+
+    /// Width and height of the frames the device will produce.
+    fn dimensions(&self) -> (i32, i32) {
+        match self {
+            CameraBlueprint::Hardware(config) => {
+                let roi = config
+                    .roi()
+                    .expect("Shared memory sink requires every camera to set a roi");
+                (roi.w, roi.h)
+            }
+            #[allow(clippy::cast_possible_wrap)]
+            CameraBlueprint::Simulated(config) => {
+                let (w, h) = config.dimensions();
+                (w as i32, h as i32)
+            }
+        }
+    }
+}
+
+/// How long a camera may go without producing a buffer before the
+/// supervisor steps in, first restarting the stream and then rebuilding
+/// the device if the stream restart did not help.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogPolicy {
+    /// Number of frame intervals without a buffer that count as a stall.
+    pub stall_factor: u32,
+}
+
+impl Default for WatchdogPolicy {
+    fn default() -> Self {
+        Self { stall_factor: 10 }
+    }
+}
+
+/// How the supervisor rebuilds a camera whose thread has exited.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// Rebuilds allowed per camera before it is left stopped.
+    pub max_restarts: u32,
+    /// Back off before the first rebuild, doubled after each one.
+    pub initial_backoff_ms: u64,
+    /// Upper limit of the back off.
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 30_000,
+        }
+    }
 }
 
 /// Type safe device position, helpful if devices are added to different parts 
 /// of the machine, but perform different functions.
 #[derive(Eq, PartialEq, Hash, Copy, Clone, Deserialize, Debug, PartialOrd, Ord)]
 pub enum DevicePosition {
-    /// As per the bill of materials.
-    // TODO: Ensure common naming convention is reached between all 
-    //       engineering disciplines. This did not happen in the 
-    //       previous iteration unfortunately. 
-    BedPosition(u8),
+    /// Position within a crop bed module as per the bill of materials.
+    BedPosition(CropBed, u8),
 }
 
 impl Display for DevicePosition {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            DevicePosition::BedPosition(idx) => write!(f, "bed_location_{}", idx),
+            DevicePosition::BedPosition(crop_bed, idx) => {
+                write!(f, "{}_bed_location_{}", crop_bed, idx)
+            }
+        }
+    }
+}
+
+/// Where the config for a camera in the array comes from. Untagged so a
+/// plain path and an inline camera config can both be written under
+/// `camera_config_files`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum CameraSource {
+    /// Path to a camera config file, relative paths are resolved against
+    /// the directory of the array config.
+    File(PathBuf),
+    /// Camera config written inside the array config.
+    Inline(OnyxCameraConfig),
+}
+
+impl CameraSource {
+    /// Resolve a relative file path against `base_dir`. Configs written
+    /// before paths were resolved this way are relative to the working
+    /// directory, so that location is still used if nothing exists
+    /// relative to `base_dir`.
+    ///
+    /// * `base_dir`: directory of the array config file.
+    fn relative_to(self, base_dir: &Path) -> Self {
+        match self {
+            CameraSource::File(path) if path.is_relative() => {
+                let resolved = base_dir.join(&path);
+                if !resolved.is_file() && path.is_file() {
+                    warn!(
+                        "Camera config {:?} is relative to the working directory, \
+                         paths are now resolved against {:?}",
+                        path, base_dir
+                    );
+                    CameraSource::File(path)
+                } else {
+                    CameraSource::File(resolved)
+                }
+            }
+            source => source,
+        }
+    }
+
+    /// Read or take the camera config.
+    fn into_config(self) -> Result<OnyxCameraConfig, ConfigError> {
+        match self {
+            CameraSource::File(path) => OnyxCameraConfig::try_from_file(path),
+            CameraSource::Inline(config) => Ok(config),
+        }
+    }
+}
+
+/// Entry for a camera in the array config. A plain source keeps existing
+/// configs unchanged, the detailed form allows a camera to be disabled
+/// while it is off the machine without removing it from the config.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum CameraEntry {
+    /// Source with additional settings.
+    Detailed {
+        /// Where the camera config comes from.
+        source: CameraSource,
+        /// Disabled cameras are not started.
+        #[serde(default = "enabled_by_default")]
+        enabled: bool,
+    },
+    /// Source for a camera that is always enabled.
+    Plain(CameraSource),
+}
+
+/// Cameras are enabled unless the config says otherwise.
+fn enabled_by_default() -> bool {
+    true
+}
+
+impl CameraEntry {
+    /// Whether the camera should be started.
+    pub fn enabled(&self) -> bool {
+        match self {
+            CameraEntry::Detailed { enabled, .. } => *enabled,
+            CameraEntry::Plain(_) => true,
+        }
+    }
+
+    /// Where the camera config comes from.
+    pub fn source(&self) -> &CameraSource {
+        match self {
+            CameraEntry::Detailed { source, .. } | CameraEntry::Plain(source) => source,
+        }
+    }
+
+    /// Consume the entry and return the camera config source.
+    fn into_source(self) -> CameraSource {
+        match self {
+            CameraEntry::Detailed { source, .. } | CameraEntry::Plain(source) => source,
+        }
+    }
+
+    /// Apply `f` to the source, keeping the rest of the entry.
+    ///
+    /// * `f`: transform for the source.
+    fn map_source(self, f: impl FnOnce(CameraSource) -> CameraSource) -> Self {
+        match self {
+            CameraEntry::Detailed { source, enabled } => CameraEntry::Detailed {
+                source: f(source),
+                enabled,
+            },
+            CameraEntry::Plain(source) => CameraEntry::Plain(f(source)),
         }
     }
 }
@@ -50,29 +369,248 @@ impl Display for DevicePosition {
 #[derive(Deserialize, Debug, Clone, Serialize, PartialEq)]
 pub struct CameraArrayConfig {
     /// Determine which crop bed the component is connected to.
-    crop_bed_id: u8,
+    crop_bed_id: CropBed,
     /// Where to store images on disk.
     image_path: String,
-    /// Map of config files used to generate the cameras in the array.
-    camera_config_files: HashMap<u8, PathBuf>,
+    /// Map of config files, or inline configs, used to generate the
+    /// cameras in the array.
+    #[serde(serialize_with = "ordered_map")]
+    camera_config_files: HashMap<u8, CameraEntry>,
+    /// Hand images to the AI container through shared memory rather
+    /// than writing them to `image_path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shm_sink: Option<ShmSinkConfig>,
+    /// Simulated cameras to run alongside, or instead of, the hardware.
+    #[serde(default, serialize_with = "ordered_map", skip_serializing_if = "HashMap::is_empty")]
+    simulated_cameras: HashMap<u8, SimulatedCameraConfig>,
+    /// How cameras are rebuilt after their thread exits, the default
+    /// policy is used when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    restart_policy: Option<RestartPolicy>,
+    /// Detection of cameras that stop producing frames, the default policy
+    /// is used when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    watchdog: Option<WatchdogPolicy>,
+    /// Number of workers saving images to `image_path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    writer_threads: Option<usize>,
+    /// Limits on the images kept in `image_path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retention: Option<RetentionPolicy>,
+    /// Port the HMI status server listens on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status_port: Option<u16>,
+    /// Write a json file of the capture metadata next to each image.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    write_sidecar: bool,
+    /// Write images from a tokio runtime rather than the pool of writer
+    /// threads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    async_writer: Option<AsyncWriterConfig>,
+    /// Keep the latest frame of each camera for the live preview.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preview: Option<PreviewConfig>,
+    /// Columns of the frames of each camera, keyed by bed position, that
+    /// each spray channel covers. Drawn over the preview frames when set,
+    /// for lining the sprays up with what the cameras see.
+    #[serde(
+        default,
+        serialize_with = "ordered_optional_map",
+        skip_serializing_if = "Option::is_none"
+    )]
+    channel_zones: Option<HashMap<u8, Vec<ChannelZone>>>,
+    /// Skip saving frames that look the same as the last one saved for
+    /// their camera, every frame is saved when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dedupe: Option<DedupeConfig>,
+    /// Debayer raw mosaic frames from the cameras in software before they
+    /// are previewed, saved or streamed, left raw when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    debayer: Option<BayerPattern>,
+    /// Publish a trigger event for every frame, for the lights to strobe on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trigger_publisher: Option<TriggerPublisherConfig>,
+    /// Where the heartbeats of the array are sent, none are sent when not
+    /// set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    heartbeat_emitter: Option<HeartbeatEmitterConfig>,
+    /// Where the structured log of the array is written, events are only
+    /// printed when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    log: Option<LogConfig>,
+    /// Where the frame rate and loss telemetry of the array is shipped,
+    /// none is shipped when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    telemetry: Option<TelemetryEmitterConfig>,
 }
 
 impl CameraArrayConfig {
     /// Create a new empty camera config.
     ///
     /// * `image_path`: path to config file.
-    /// * `crop_bed_id`: id of the crop bed module etc. (0, 1, 2)
-    // TODO: Create a set of enums for module numbering and naming
-    //       and set it in line with mechanical, electrical such
-    //       as Module::Left, Module::Centre, Module::Right.
-    pub fn new(image_path: String, crop_bed_id: u8) -> Self {
+    /// * `crop_bed_id`: crop bed module, legacy ids (0, 1, 2) convert.
+    pub fn new(image_path: String, crop_bed_id: impl Into<CropBed>) -> Self {
         Self {
             image_path,
-            crop_bed_id,
+            crop_bed_id: crop_bed_id.into(),
             camera_config_files: HashMap::new(),
+            shm_sink: None,
+            simulated_cameras: HashMap::new(),
+            restart_policy: None,
+            watchdog: None,
+            writer_threads: None,
+            retention: None,
+            status_port: None,
+            write_sidecar: false,
+            async_writer: None,
+            preview: None,
+            channel_zones: None,
+            dedupe: None,
+            debayer: None,
+            trigger_publisher: None,
+            heartbeat_emitter: None,
+            log: None,
+            telemetry: None,
         }
     }
 
+    /// Send the heartbeats of the array.
+    ///
+    /// * `heartbeat_emitter`: where and how often they go.
+    pub fn with_heartbeat_emitter(mut self, heartbeat_emitter: HeartbeatEmitterConfig) -> Self {
+        self.heartbeat_emitter = Some(heartbeat_emitter);
+        self
+    }
+
+    /// Write the structured log of the array.
+    ///
+    /// * `log`: file or collector the events go to.
+    pub fn with_log(mut self, log: LogConfig) -> Self {
+        self.log = Some(log);
+        self
+    }
+
+    /// Ship the frame rate and loss telemetry of the array.
+    ///
+    /// * `telemetry`: where and how often it goes.
+    pub fn with_telemetry(mut self, telemetry: TelemetryEmitterConfig) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
+
+    /// Publish a trigger event for every frame captured.
+    ///
+    /// * `trigger_publisher`: where the trigger events go.
+    pub fn with_trigger_publisher(mut self, trigger_publisher: TriggerPublisherConfig) -> Self {
+        self.trigger_publisher = Some(trigger_publisher);
+        self
+    }
+
+    /// Keep the latest frame of each camera for the live preview.
+    ///
+    /// * `preview`: preview rate and size.
+    pub fn with_preview(mut self, preview: PreviewConfig) -> Self {
+        self.preview = Some(preview);
+        self
+    }
+
+    /// Draw the columns each spray channel covers over the preview frames.
+    ///
+    /// * `channel_zones`: zones of each camera keyed by bed position.
+    pub fn with_channel_zones(mut self, channel_zones: HashMap<u8, Vec<ChannelZone>>) -> Self {
+        self.channel_zones = Some(channel_zones);
+        self
+    }
+
+    /// Skip saving frames that look the same as the last one saved for
+    /// their camera.
+    ///
+    /// * `dedupe`: how close a frame must be to be skipped.
+    pub fn with_dedupe(mut self, dedupe: DedupeConfig) -> Self {
+        self.dedupe = Some(dedupe);
+        self
+    }
+
+    /// Debayer raw mosaic frames in software before they are previewed,
+    /// saved or streamed.
+    ///
+    /// * `pattern`: order of the colour filters on the sensors.
+    pub fn with_debayer(mut self, pattern: BayerPattern) -> Self {
+        self.debayer = Some(pattern);
+        self
+    }
+
+    /// Write images from a tokio runtime, `writer_threads` is ignored.
+    ///
+    /// * `async_writer`: queue depth and write concurrency.
+    pub fn with_async_writer(mut self, async_writer: AsyncWriterConfig) -> Self {
+        self.async_writer = Some(async_writer);
+        self
+    }
+
+    /// Write a json file of the capture metadata next to each saved image.
+    pub fn with_sidecar(mut self) -> Self {
+        self.write_sidecar = true;
+        self
+    }
+
+    /// Serve the array status to the HMI on a port.
+    ///
+    /// * `status_port`: port to listen on.
+    pub fn with_status_port(mut self, status_port: u16) -> Self {
+        self.status_port = Some(status_port);
+        self
+    }
+
+    /// Limit the images kept on disk.
+    ///
+    /// * `retention`: size, age and free space limits.
+    pub fn with_retention(mut self, retention: RetentionPolicy) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    /// Set the number of workers saving images to disk.
+    ///
+    /// * `writer_threads`: worker count, at least one is always started.
+    pub fn with_writer_threads(mut self, writer_threads: usize) -> Self {
+        self.writer_threads = Some(writer_threads);
+        self
+    }
+
+    /// Override the default stall detection.
+    ///
+    /// * `watchdog`: frame intervals allowed without a buffer.
+    pub fn with_watchdog(mut self, watchdog: WatchdogPolicy) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    /// Override the default policy for rebuilding cameras.
+    ///
+    /// * `policy`: restart count and back off.
+    pub fn with_restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.restart_policy = Some(policy);
+        self
+    }
+
+    /// Add a simulated camera to the component.
+    ///
+    /// * `config`: simulated camera config.
+    /// * `bed_position_idx`: position in line with bill of materials.
+    pub fn add_simulated_camera(mut self, config: SimulatedCameraConfig, bed_position_idx: u8) -> Self {
+        self.simulated_cameras.insert(bed_position_idx, config);
+        self
+    }
+
+    /// Send images to a shared memory ring instead of to disk.
+    ///
+    /// * `sink`: name and slot count of the ring.
+    pub fn with_shm_sink(mut self, sink: ShmSinkConfig) -> Self {
+        self.shm_sink = Some(sink);
+        self
+    }
+
     /// Dynamically add additional camera config to the component that
     /// can be initiated during the component build.
     ///
@@ -83,30 +621,131 @@ impl CameraArrayConfig {
         F: AsRef<OsStr>,
     {
         self.camera_config_files
-            .insert(bed_position_idx, (&filepath).into());
+            .insert(bed_position_idx, CameraEntry::Plain(CameraSource::File((&filepath).into())));
         self
     }
 
-    /// Create a camera array component from a config file.
-    ///
-    /// * `filepath`: path to camera array config config.
-    pub fn from_file<F: AsRef<OsStr>>(filepath: F) -> Self {
-        let file = Path::new(&filepath);
-        let camera_array_config = if file.is_file() {
-            let config_file = config::Config::builder()
-                .add_source(config::File::new(
-                    &file.to_string_lossy(),
-                    config::FileFormat::Yaml,
-                ))
-                .build()
-                .expect("Failed read config");
-            config_file
-                .try_deserialize::<CameraArrayConfig>()
-                .expect("Failed to parse config file into struct")
-        } else {
-            panic!("Could not locate the config file {:?}", file);
+    /// Add a camera config written inline rather than in its own file.
+    ///
+    /// * `config`: camera config.
+    /// * `bed_position_idx`: position in line with bill of materials.
+    pub fn add_camera_config(mut self, config: OnyxCameraConfig, bed_position_idx: u8) -> Self {
+        self.camera_config_files
+            .insert(bed_position_idx, CameraEntry::Plain(CameraSource::Inline(config)));
+        self
+    }
+
+    /// Keep a camera in the config but do not start it, i.e. while it is
+    /// off the machine for repair.
+    ///
+    /// * `bed_position_idx`: position in line with bill of materials.
+    pub fn disable_camera(mut self, bed_position_idx: u8) -> Self {
+        if let Some(entry) = self.camera_config_files.remove(&bed_position_idx) {
+            self.camera_config_files.insert(
+                bed_position_idx,
+                CameraEntry::Detailed {
+                    source: entry.into_source(),
+                    enabled: false,
+                },
+            );
+        }
+        self
+    }
+
+    /// Resolve relative camera config paths against a directory, used so
+    /// the binaries do not depend on the working directory they are
+    /// started in.
+    ///
+    /// * `base_dir`: directory of the array config file.
+    pub fn relative_to(mut self, base_dir: &Path) -> Self {
+        self.camera_config_files = self
+            .camera_config_files
+            .into_iter()
+            .map(|(bed_position, entry)| {
+                (bed_position, entry.map_source(|source| source.relative_to(base_dir)))
+            })
+            .collect();
+        self
+    }
+
+    /// Load the config from a yaml file, the error naming the field that
+    /// is wrong and where it is.
+    ///
+    /// * `filepath`: path to camera array config.
+    pub fn try_from_file<F: AsRef<OsStr>>(filepath: F) -> Result<Self, ConfigError> {
+        load_yaml(Path::new(&filepath))
+    }
+}
+
+impl Validate for CameraArrayConfig {
+    fn validate(&self, report: &mut ValidationReport) {
+        let mut bed_positions: Vec<&u8> = self.camera_config_files.keys().collect();
+        bed_positions.sort_unstable();
+        for bed_position in bed_positions {
+            let entry = &self.camera_config_files[bed_position];
+            // Disabled cameras are off the machine, their configs are not
+            // read until they are enabled again.
+            if !entry.enabled() {
+                continue;
+            }
+            let field = format!("camera_config_files.{bed_position}");
+            match entry.source() {
+                CameraSource::File(path) => {
+                    report.validate_file::<OnyxCameraConfig>(&field, path);
+                }
+                CameraSource::Inline(config) => {
+                    let mut nested = ValidationReport::new(report.path());
+                    config.validate(&mut nested);
+                    report.nest(&field, nested);
+                }
+            }
+            if self.simulated_cameras.contains_key(bed_position) {
+                report.push(field, "a simulated camera is at the same bed position and is used in its place");
+            }
+        }
+        let mut simulated: Vec<(&u8, &SimulatedCameraConfig)> = self.simulated_cameras.iter().collect();
+        simulated.sort_unstable_by_key(|(bed_position, _)| **bed_position);
+        for (bed_position, config) in simulated {
+            let mut nested = ValidationReport::new(report.path());
+            config.validate(&mut nested);
+            report.nest(format!("simulated_cameras.{bed_position}"), nested);
+        }
+        let has_camera = |bed_position: &u8| {
+            self.simulated_cameras.contains_key(bed_position)
+                || self
+                    .camera_config_files
+                    .get(bed_position)
+                    .is_some_and(CameraEntry::enabled)
         };
-        camera_array_config
+        if !self.camera_config_files.keys().chain(self.simulated_cameras.keys()).any(has_camera) {
+            report.push("camera_config_files", "no cameras are enabled");
+        }
+        if self.shm_sink.is_none() {
+            report.check_parent_dir("image_path", Path::new(&self.image_path));
+        }
+        let mut channel_zones: Vec<(&u8, &Vec<ChannelZone>)> = self.channel_zones.iter().flatten().collect();
+        channel_zones.sort_unstable_by_key(|(bed_position, _)| **bed_position);
+        for (bed_position, zones) in channel_zones {
+            if !has_camera(bed_position) {
+                report.push(format!("channel_zones.{bed_position}"), "no camera is enabled at the bed position");
+            }
+            for (index, zone) in zones.iter().enumerate().filter(|(_, zone)| zone.x0 >= zone.x1) {
+                report.push(
+                    format!("channel_zones.{bed_position}.{index}"),
+                    format!("columns {} to {} of channel {} cover nothing", zone.x0, zone.x1, zone.channel),
+                );
+            }
+        }
+    }
+
+    fn loaded_from(self, path: &Path) -> Self {
+        self.relative_to(path.parent().unwrap_or_else(|| Path::new("")))
+    }
+}
+
+impl ComponentConfig for CameraArrayConfig {
+    fn crop_bed(&self) -> CropBed {
+        self.crop_bed_id
     }
 }
 
@@ -119,15 +758,54 @@ impl CameraArrayConfig {
 pub struct CameraArray {
     /// Unique id of the camera array.
     uuid: Uuid,
-    /// Map of the thread handles and start stop atomics's.
-    camera_handles: HashMap<Uuid, CameraHandle>,
-    /// Map of the camera devices.
-    cameras: HashMap<u8, OnyxCamera>,
+    /// Map of the configs the camera devices are built from.
+    cameras: HashMap<u8, CameraBlueprint>,
     /// Parent save directory for the images.
     // TODO: Remove once port streaming is implemented.
     pub image_path: String,
     /// Crop bed id from the machine as per the bill of materials.
-    crop_bed_id: u8,
+    crop_bed_id: CropBed,
+    /// Optional shared memory ring used in place of writing to disk.
+    shm_sink: Option<ShmSinkConfig>,
+    /// How cameras are rebuilt after their thread exits.
+    restart_policy: RestartPolicy,
+    /// Detection of cameras that stop producing frames.
+    watchdog: WatchdogPolicy,
+    /// Number of workers saving images to disk.
+    writer_threads: usize,
+    /// Limits on the images kept on disk.
+    retention: Option<RetentionPolicy>,
+    /// Port the HMI status server listens on.
+    status_port: Option<u16>,
+    /// Write a json metadata sidecar next to each image.
+    write_sidecar: bool,
+    /// Tokio image writer used in place of the writer threads.
+    async_writer: Option<AsyncWriterConfig>,
+    /// Rate and size of the live preview.
+    preview: Option<PreviewConfig>,
+    /// Columns each spray channel covers, drawn over the preview frames.
+    channel_zones: HashMap<u8, Vec<ChannelZone>>,
+    /// How close a frame must be to the last one saved to be skipped.
+    dedupe: Option<DedupeConfig>,
+    /// Colour filters of the raw frames debayered in software.
+    debayer: Option<BayerPattern>,
+    /// Where the trigger events of the cameras are published.
+    trigger_publisher: Option<TriggerPublisherConfig>,
+    /// Where the heartbeats of the array are sent.
+    heartbeat_emitter: Option<HeartbeatEmitterConfig>,
+    /// Where the telemetry of the array is shipped.
+    telemetry: Option<TelemetryEmitterConfig>,
+    /// Bus the trigger events are published on and a shutdown is requested
+    /// on, when run in one binary with other components.
+    bus: Option<MessageBus>,
+    /// When the array was created, for the uptime in its heartbeats.
+    started_at: Instant,
+    /// Bed positions of cameras in the config that are disabled.
+    disabled_cameras: Vec<u8>,
+    /// Config the array was built from, for the HMI to show.
+    config: Arc<CameraArrayConfig>,
+    /// Events of the array, printed and written to the structured log.
+    log: LogEmitter,
 }
 
 impl CameraArray {
@@ -136,193 +814,1240 @@ impl CameraArray {
         self.uuid
     }
 
+    /// Port the HMI status server should listen on, if configured.
+    pub fn status_port(&self) -> Option<u16> {
+        self.status_port
+    }
+
     /// Create camera array by consuming a config.
     ///
     /// * `config`: Specified camera array config
-    pub fn new(config: CameraArrayConfig) -> Self {
-        Self {
-            uuid: Uuid::new_v4(),
+    pub fn new(config: CameraArrayConfig) -> Result<Self, ComponentError> {
+        let uuid = Uuid::new_v4();
+        Ok(Self {
+            uuid,
             image_path: config.image_path.clone(),
             crop_bed_id: config.crop_bed_id,
-            camera_handles: HashMap::new(),
-            cameras: Self::build_from_config(config),
-        }
+            shm_sink: config.shm_sink.clone(),
+            restart_policy: config.restart_policy.unwrap_or_default(),
+            watchdog: config.watchdog.unwrap_or_default(),
+            writer_threads: config
+                .writer_threads
+                .unwrap_or(DEFAULT_WRITER_THREADS)
+                .max(1),
+            retention: config.retention,
+            status_port: config.status_port,
+            write_sidecar: config.write_sidecar,
+            async_writer: config.async_writer,
+            preview: config.preview,
+            channel_zones: config.channel_zones.clone().unwrap_or_default(),
+            dedupe: config.dedupe,
+            debayer: config.debayer,
+            trigger_publisher: config.trigger_publisher,
+            heartbeat_emitter: config.heartbeat_emitter.clone(),
+            telemetry: config.telemetry.clone(),
+            bus: None,
+            started_at: Instant::now(),
+            disabled_cameras: Self::disabled_from_config(&config),
+            config: Arc::new(config.clone()),
+            log: LogEmitter::new(ComponentKind::CameraArray, uuid, config.crop_bed_id).with_config(config.log.as_ref()),
+            cameras: Self::build_from_config(config)?,
+        })
+    }
+
+    /// Publish the trigger events of the cameras on a bus and stop once a
+    /// shutdown is requested on it.
+    ///
+    /// * `bus`: bus shared with the other components of the binary.
+    pub fn with_bus(mut self, bus: MessageBus) -> Self {
+        self.bus = Some(bus);
+        self
     }
 
     /// Create a camera array component by ingesting a config file.
     ///
     /// * `filepath`: filepath to the config.
-    pub fn from_config_file<F: AsRef<OsStr>>(filepath: F) -> Self {
-        let config = CameraArrayConfig::from_file(filepath);
+    pub fn from_config_file<F: AsRef<OsStr>>(filepath: F) -> Result<Self, ComponentError> {
+        let base_dir = Path::new(&filepath)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let config = CameraArrayConfig::try_from_file(filepath)?.relative_to(&base_dir);
         Self::new(config)
     }
 
     /// Build the devices linked to the component, in this case the individual
-    /// cameras within the `CameraArray`. This is a helper function.
+    /// cameras within the `CameraArray`. This is a helper function. Network
+    /// cameras are refused in a build without the `camera-hw` feature.
     ///
     /// * `config`: `CameraArrayConfig`
-    fn build_from_config(config: CameraArrayConfig) -> HashMap<u8, OnyxCamera> {
+    fn build_from_config(config: CameraArrayConfig) -> Result<HashMap<u8, CameraBlueprint>, ComponentError> {
         let mut cameras = HashMap::new();
 
-        for (bed_position, camera_config_file) in config.camera_config_files {
-            let camera = OnyxCamera::new(OnyxCameraConfig::from_file(camera_config_file));
-            cameras.insert(bed_position, camera);
+        for (bed_position, camera_entry) in config.camera_config_files {
+            if !camera_entry.enabled() {
+                continue;
+            }
+            let camera_config = camera_entry.into_source().into_config()?;
+            if cfg!(not(feature = "camera-hw")) {
+                return Err(ComponentError::Invalid(format!(
+                    "Camera at bed position {bed_position} is a network camera, built without the camera-hw feature"
+                )));
+            }
+            cameras.insert(bed_position, CameraBlueprint::Hardware(camera_config));
+        }
+        for (bed_position, simulated_config) in config.simulated_cameras {
+            cameras.insert(bed_position, CameraBlueprint::Simulated(simulated_config));
         }
-        cameras
+        Ok(cameras)
     }
-}
-
-/// Unit struct to link component controller behaviour, all components will
-/// need some type of behaviour and it is easier to detach this behaviour
-/// from requiring owned state. Rather pass it to functions that do the work.
-pub struct CameraArrayController;
 
-impl CameraArrayController {
-    /// Start the cameras in their own threads.
+    /// Sorted bed positions of the cameras disabled in the config.
     ///
-    /// * `camera_array`: Component containing initialised cameras.
-    // TODO: Using separate threads for networks cameras is an interesting choice considering
-    //       much of the time the device will be in a hold state, so the thread will be context
-    //       switching. The obvious alternative is to change this to async, however at the time
-    //       the underlying aravis library did not implement any futures capability, and there
-    //       was not enough time to write and contribute an async version.
-    pub fn start(
-        mut camera_array: CameraArray,
-    ) -> (JoinHandle<AllocRingBuffer<JoinHandle<()>>>, Arc<AtomicBool>) {
-        let nthread = camera_array.cameras.len();
-        let barrier = Arc::new(Barrier::new(nthread));
-        let stop_signal = Arc::new(AtomicBool::new(false));
-        let (device_channel_tx, device_channel_rx) = mpsc::channel::<DevicePayload>();
-
-        let path = PathBuf::from(format!(
-            "{}/{}",
-            camera_array.image_path, camera_array.crop_bed_id
-        ));
-        create_dir_all(&path).expect("Failed to create filepath");
-
-
-        // TODO: Should be as simple as dropping the into_iter however this update 
-        //       was explicitly no code changes due to upcoming tests on farms.
-        //       Good first issue.
-        #[allow(clippy::explicit_into_iter_loop)]
-        for (bed_position, mut camera) in camera_array.cameras.into_iter() {
-            create_dir_all(&path.join(bed_position.to_string()))
-                .expect("Failed to create bed position path");
-            let camera_uuid = camera.get_uuid();
-            // Set up the requirements for the threads to operate.
-            // lots of clones as new thread will take ownership.
-            let thread_barrier = barrier.clone();
-            let thread_stop_signal = stop_signal.clone();
-            let caller_stop_signal = stop_signal.clone();
-            let thread_device_sender_tx = device_channel_tx.clone();
+    /// * `config`: `CameraArrayConfig`
+    fn disabled_from_config(config: &CameraArrayConfig) -> Vec<u8> {
+        let mut disabled: Vec<u8> = config
+            .camera_config_files
+            .iter()
+            .filter(|(_, entry)| !entry.enabled())
+            .map(|(bed_position, _)| *bed_position)
+            .collect();
+        disabled.sort_unstable();
+        disabled
+    }
 
-            camera.set_location_id(bed_position);
+    /// Bed positions of cameras in the config that are disabled.
+    pub fn disabled_cameras(&self) -> &[u8] {
+        &self.disabled_cameras
+    }
 
-            let device_handle = thread::spawn(move || {
-                CameraController::start(
-                    camera,
-                    thread_stop_signal,
-                    thread_barrier,
-                    thread_device_sender_tx,
-                );
-            });
+    /// The smallest region that covers every camera in the array, used to
+    /// size the slots of a shared memory sink.
+    fn largest_roi(&self) -> Roi {
+        self.cameras
+            .values()
+            .map(CameraBlueprint::dimensions)
+            .fold(Roi { x: 0, y: 0, w: 0, h: 0 }, |roi, (w, h)| Roi {
+                w: roi.w.max(w),
+                h: roi.h.max(h),
+                ..roi
+            })
+    }
+}
 
-            camera_array.camera_handles.insert(
-                camera_uuid,
-                CameraHandle {
-                    join_handle: Some(device_handle),
-                    stop_signal: Some(caller_stop_signal),
-                },
-            );
-        }
+/// Counters kept by the image writer, shared between the writer threads.
+#[derive(Default, Debug)]
+struct WriterStats {
+    /// Images successfully handed to the sink.
+    images_written: AtomicU64,
+    /// Images that failed to be written.
+    write_failures: AtomicU64,
+    /// Images dropped because the writer queue was full.
+    images_dropped: AtomicU64,
+    /// Frames skipped for looking the same as the last one saved.
+    frames_skipped: AtomicU64,
+    /// Images removed by the retention policy.
+    files_pruned: AtomicU64,
+    /// Images of each camera that failed to be written, keyed by bed
+    /// position.
+    camera_write_failures: Mutex<BTreeMap<u8, u64>>,
+}
 
-        // TODO: write out the device signals to either another object or to the struct.
-        // Issue here is that the vector can grow infinitely so we need to get rid of
-        // some of the successful thread join handles. Currently using a ring buffer
-        // to discard join handles that leak  over the total length of the thread handle
-        // storage. An alternate way try to implement this is to add another MPSC and use
-        // the try_recv function to test if there are any threads that should be closed.
-        // A very naive way would also be to just chuck these image writer join handles
-        // away. Ultimately due to schedule / resourcing unable to spend time on this.
-
-        let handles = thread::spawn(|| {
-            let thread_path = Arc::new(path);
-
-            let mut image_writer_handles_buffer = AllocRingBuffer::new(128);
-            for payload in device_channel_rx {
-                let image_path = thread_path.clone();
-                let image_writer_handle = thread::spawn(move || {
-                    let filename = image_path.join(payload.filename());
-                    if let Err(e) = payload.image.save(&filename) {
-                        println!("Failed to save image to path {:?} {e}", filename);
-                    }
-                });
-                image_writer_handles_buffer.push(image_writer_handle);
+impl WriterStats {
+    /// Record the outcome of writing a single image.
+    ///
+    /// * `location_id`: bed position of the camera that took the image.
+    /// * `success`: whether the image made it to the sink.
+    fn record(&self, location_id: Option<u8>, success: bool) {
+        if success {
+            self.images_written.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.write_failures.fetch_add(1, Ordering::Relaxed);
+            if let Some(location_id) = location_id {
+                *self
+                    .camera_write_failures
+                    .lock()
+                    .expect("Write failures poisoned")
+                    .entry(location_id)
+                    .or_default() += 1;
             }
-            image_writer_handles_buffer
-        });
-        (handles, stop_signal)
+            metrics::image_write_failure();
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-
-    use super::*;
-    use serial_test::serial;
-    use std::{fs::OpenOptions, time::Duration};
+/// Aggregate statistics for a camera array, per camera and for the
+/// writer that saves the images.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CameraArrayStats {
+    /// Crop bed the array is attached to.
+    pub crop_bed: CropBed,
+    /// Counters for each camera keyed by bed position.
+    pub cameras: BTreeMap<u8, CameraStatsSnapshot>,
+    /// Bed positions of cameras disabled in the config.
+    pub disabled_cameras: Vec<u8>,
+    /// True when cameras are disabled, so the HMI can show the bed as
+    /// degraded rather than healthy.
+    pub degraded: bool,
+    /// Images successfully handed to the sink.
+    pub images_written: u64,
+    /// Images that failed to be written.
+    pub write_failures: u64,
+    /// Images dropped because the writer queue was full.
+    pub images_dropped: u64,
+    /// Frames skipped for looking the same as the last one saved for
+    /// their camera.
+    #[serde(default)]
+    pub frames_skipped: u64,
+    /// Images removed by the retention policy.
+    pub files_pruned: u64,
+    /// Images of each camera that failed to be written, keyed by bed
+    /// position, cameras without a failure are left out.
+    #[serde(default)]
+    pub camera_write_failures: BTreeMap<u8, u64>,
+    /// Threads of the array that panicked, only counted once the array
+    /// has been joined.
+    #[serde(default)]
+    pub threads_panicked: u64,
+}
 
-    #[test]
-    #[serial]
-    /// Test writing component configurations to a yaml file.
-    fn test_write_component_config_to_file() {
-        let camera_array_configs: Vec<(u8, [u8; 2])> = vec![(0, [0, 1]), (1, [2, 3]), (2, [4, 5])];
-
-        for (crop_bed_id, camera_ids) in camera_array_configs {
-            let config = CameraArrayConfig::new(String::from("./images"), crop_bed_id)
-                .add_camera_config_file(
-                    format!("./config/devices/crop_bed/camera_{}.yaml", camera_ids[0]),
-                    0,
-                )
-                .add_camera_config_file(
-                    format!("./config/devices/crop_bed/camera_{}.yaml", camera_ids[1]),
-                    1,
-                );
+impl CameraArrayStats {
+    /// Total frames captured across every camera in the array.
+    pub fn frames_captured(&self) -> u64 {
+        self.cameras.values().map(|c| c.frames_captured).sum()
+    }
 
-            let file = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open(Path::new(&format!("{}/config/components/crop_bed/sensing/camera_array/crop_bed_camera_array_{crop_bed_id}.yaml", env!("CARGO_MANIFEST_DIR"))))
-                .expect("Faile to open file");
+    /// Frame rate of a camera since an earlier snapshot, zero without one
+    /// or for a camera not in the array.
+    ///
+    /// * `bed_position`: position in line with bill of materials.
+    /// * `previous`: earlier snapshot and the time since it was taken.
+    pub fn fps(&self, bed_position: u8, previous: Option<(&CameraArrayStats, Duration)>) -> f64 {
+        let Some((previous, elapsed)) = previous.filter(|(_, elapsed)| !elapsed.is_zero()) else {
+            return 0.0;
+        };
+        let frames_captured = self.cameras.get(&bed_position).map_or(0, |c| c.frames_captured);
+        let before = previous.cameras.get(&bed_position).map_or(0, |c| c.frames_captured);
+        let frames = u32::try_from(frames_captured.saturating_sub(before)).unwrap_or(u32::MAX);
+        f64::from(frames) / elapsed.as_secs_f64()
+    }
 
-            serde_yaml::to_writer(file, &config).expect("Failed to write yaml");
+    /// Telemetry of the array, with the frame rate of each camera since an
+    /// earlier snapshot, zero without one.
+    ///
+    /// * `component`: unique id of the array.
+    /// * `previous`: earlier snapshot and the time since it was taken.
+    pub fn telemetry(&self, component: Uuid, previous: Option<(&CameraArrayStats, Duration)>) -> CameraTelemetry {
+        CameraTelemetry {
+            header: TelemetryHeader::new(component, self.crop_bed),
+            cameras: self
+                .cameras
+                .iter()
+                .map(|(bed_position, stats)| {
+                    (
+                        *bed_position,
+                        CameraFrameTelemetry {
+                            fps: self.fps(*bed_position, previous),
+                            frames_captured: stats.frames_captured,
+                            dropped_frames: stats.frames_late,
+                        },
+                    )
+                })
+                .collect(),
+            write_failures: self.write_failures,
+            images_dropped: self.images_dropped,
         }
     }
+}
 
-    #[test]
-    #[serial]
-    /// Test writing component configurations to a yaml file, and reading
+impl Display for CameraArrayStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (bed_position, stats) in &self.cameras {
+            writeln!(
+                f,
+                "{}: captured {}, late {}, stream restarts {}, restarts {}, backoff {}ms, stalls {}",
+                DevicePosition::BedPosition(self.crop_bed, *bed_position),
+                stats.frames_captured,
+                stats.frames_late,
+                stats.stream_restarts,
+                stats.restarts,
+                stats.backoff_ms,
+                stats.stalls
+            )?;
+        }
+        for bed_position in &self.disabled_cameras {
+            writeln!(
+                f,
+                "{}: disabled",
+                DevicePosition::BedPosition(self.crop_bed, *bed_position)
+            )?;
+        }
+        write!(
+            f,
+            "images written {}, write failures {}, images dropped {}, frames skipped {}, files pruned {}, \
+             threads panicked {}",
+            self.images_written,
+            self.write_failures,
+            self.images_dropped,
+            self.frames_skipped,
+            self.files_pruned,
+            self.threads_panicked
+        )
+    }
+}
+
+/// Returned by [`CameraArrayController::start`], owns every thread the
+/// array spawned so they can be shut down and joined in order.
+pub struct CameraArrayHandle {
+    /// Shared view of the array used to report on and stop it.
+    monitor: CameraArrayMonitor,
+    /// Thread watching and rebuilding the cameras.
+    supervisor_handle: JoinHandle<()>,
+    /// Workers that pull payloads off the channel and write them out.
+    writer_handles: Vec<JoinHandle<()>>,
+    /// Thread enforcing the retention policy, if one is set.
+    retention_handle: Option<JoinHandle<()>>,
+    /// Thread sending the heartbeats of the array, if they are sent.
+    heartbeat_handle: Option<JoinHandle<()>>,
+    /// Thread shipping the telemetry of the array, if it is shipped.
+    telemetry_handle: Option<JoinHandle<()>>,
+    /// Thread stopping the array on a shutdown requested on the bus, if
+    /// the array is on one.
+    shutdown_handle: Option<JoinHandle<()>>,
+}
+
+/// Cheap to clone view of a running array, handed to anything that needs
+/// to report on or stop the array without owning its threads.
+#[derive(Clone)]
+pub struct CameraArrayMonitor {
+    /// Crop bed the array is attached to.
+    crop_bed: CropBed,
+    /// Bed positions of cameras disabled in the config.
+    disabled_cameras: Vec<u8>,
+    /// Signal shared with every camera thread.
+    stop_signal: Arc<AtomicBool>,
+    /// Counters for each camera keyed by bed position.
+    camera_stats: HashMap<u8, Arc<CameraStats>>,
+    /// Counters kept by the image writer.
+    writer_stats: Arc<WriterStats>,
+    /// Latest frame of each camera, when the preview is enabled.
+    preview: Option<Arc<PreviewFrames>>,
+    /// Last frame saved for each camera, when frames are deduplicated.
+    dedupe: Option<Arc<FrameDedupe>>,
+    /// Bed positions of the cameras asked to be rebuilt, taken by the
+    /// supervisor on its next pass.
+    restart_requests: Arc<Mutex<Vec<u8>>>,
+    /// Config the array was built from.
+    config: Arc<CameraArrayConfig>,
+    /// Events of the array.
+    log: LogEmitter,
+}
+
+impl CameraArrayMonitor {
+    /// Latest frames for the live preview, if it is enabled.
+    pub fn preview(&self) -> Option<Arc<PreviewFrames>> {
+        self.preview.clone()
+    }
+
+    /// Last frame saved for each camera, if frames are deduplicated, e.g.
+    /// to reset once the machine moves off.
+    pub fn dedupe(&self) -> Option<Arc<FrameDedupe>> {
+        self.dedupe.clone()
+    }
+
+    /// Whether a camera is running at a bed position.
+    ///
+    /// * `bed_position`: position in line with bill of materials.
+    pub fn has_camera(&self, bed_position: u8) -> bool {
+        self.camera_stats.contains_key(&bed_position)
+    }
+
+    /// Ask for the camera at a bed position to be rebuilt, without counting
+    /// against its restarts. False when there is no camera there.
+    ///
+    /// * `bed_position`: position in line with bill of materials.
+    pub fn request_restart(&self, bed_position: u8) -> bool {
+        if !self.has_camera(bed_position) {
+            return false;
+        }
+        self.restart_requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(bed_position);
+        true
+    }
+
+    /// Config the array was built from.
+    pub fn config(&self) -> &CameraArrayConfig {
+        &self.config
+    }
+
+    /// Take a snapshot of the array statistics while it is running.
+    pub fn stats(&self) -> CameraArrayStats {
+        CameraArrayStats {
+            crop_bed: self.crop_bed,
+            cameras: self
+                .camera_stats
+                .iter()
+                .map(|(bed_position, stats)| (*bed_position, stats.snapshot()))
+                .collect(),
+            disabled_cameras: self.disabled_cameras.clone(),
+            degraded: !self.disabled_cameras.is_empty(),
+            images_written: self.writer_stats.images_written.load(Ordering::Relaxed),
+            write_failures: self.writer_stats.write_failures.load(Ordering::Relaxed),
+            images_dropped: self.writer_stats.images_dropped.load(Ordering::Relaxed),
+            frames_skipped: self.writer_stats.frames_skipped.load(Ordering::Relaxed),
+            files_pruned: self.writer_stats.files_pruned.load(Ordering::Relaxed),
+            camera_write_failures: self
+                .writer_stats
+                .camera_write_failures
+                .lock()
+                .expect("Write failures poisoned")
+                .clone(),
+            threads_panicked: 0,
+        }
+    }
+
+    /// Ask every camera to stop, the owner of the [`CameraArrayHandle`]
+    /// is left to join the threads.
+    pub fn request_stop(&self) {
+        self.stop_signal.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the array has been asked to stop.
+    pub fn is_stopping(&self) -> bool {
+        self.stop_signal.load(Ordering::Relaxed)
+    }
+}
+
+impl CameraArrayHandle {
+    /// Take a snapshot of the array statistics while it is running.
+    pub fn stats(&self) -> CameraArrayStats {
+        self.monitor.stats()
+    }
+
+    /// Shared view of the array for reporting and stopping it from
+    /// another thread.
+    pub fn monitor(&self) -> CameraArrayMonitor {
+        self.monitor.clone()
+    }
+
+    /// Stop every camera, let the writer finish what is left in the
+    /// channel and return the final statistics.
+    pub fn stop(self) -> CameraArrayStats {
+        self.monitor.request_stop();
+        self.wait()
+    }
+
+    /// Block until the supervisor has exited, then join the writers.
+    /// Without a call to [`CameraArrayHandle::stop`] this only returns
+    /// once every camera has used up its restarts.
+    pub fn wait(mut self) -> CameraArrayStats {
+        let log = self.monitor.log.clone();
+        let mut threads_panicked = 0;
+        let mut panicked = |message: &str| {
+            log.error(EventCode::TaskFailed, message);
+            threads_panicked += 1;
+        };
+        if self.supervisor_handle.join().is_err() {
+            panicked("Camera supervisor thread panicked");
+        }
+
+        // No cameras are left running, so stop the retention scans too.
+        self.monitor.request_stop();
+        if let Some(retention_handle) = self.retention_handle.take() {
+            if retention_handle.join().is_err() {
+                panicked("Retention thread panicked");
+            }
+        }
+        if let Some(heartbeat_handle) = self.heartbeat_handle.take() {
+            if heartbeat_handle.join().is_err() {
+                panicked("Heartbeat thread panicked");
+            }
+        }
+        if let Some(telemetry_handle) = self.telemetry_handle.take() {
+            if telemetry_handle.join().is_err() {
+                panicked("Telemetry thread panicked");
+            }
+        }
+        if let Some(shutdown_handle) = self.shutdown_handle.take() {
+            if shutdown_handle.join().is_err() {
+                panicked("Bus shutdown thread panicked");
+            }
+        }
+
+        // Every sender has now been dropped with the camera threads and
+        // the supervisor, so the workers drain the channel and return.
+        for image_writer in self.writer_handles.drain(..) {
+            if image_writer.join().is_err() {
+                panicked("Image writer thread panicked");
+            }
+        }
+        CameraArrayStats {
+            threads_panicked,
+            ..self.stats()
+        }
+    }
+}
+
+/// Unit struct to link component controller behaviour, all components will
+/// need some type of behaviour and it is easier to detach this behaviour
+/// from requiring owned state. Rather pass it to functions that do the work.
+pub struct CameraArrayController;
+
+impl CameraArrayController {
+    /// Start the cameras in their own threads and return a handle that
+    /// is used to stop them. Fails without starting a thread when the
+    /// capture directory or the shared memory sink cannot be set up.
+    ///
+    /// * `camera_array`: Component containing initialised cameras.
+    // TODO: Using separate threads for networks cameras is an interesting choice considering
+    //       much of the time the device will be in a hold state, so the thread will be context
+    //       switching. The obvious alternative is to change this to async, however at the time
+    //       the underlying aravis library did not implement any futures capability, and there
+    //       was not enough time to write and contribute an async version.
+    pub fn start(camera_array: CameraArray) -> Result<CameraArrayHandle, ComponentError> {
+        // Every thread of the array starts in its span, the cameras rebuilt
+        // by the supervisor included.
+        let _span = camera_array.log.span().entered();
+        let start_gate = Arc::new(StartGate::new(
+            camera_array.cameras.len(),
+            START_GATE_TIMEOUT,
+        ));
+        let stop_signal = Arc::new(AtomicBool::new(false));
+        let (device_channel_tx, device_channel_rx) = mpsc::channel::<DevicePayload>();
+        let writer_stats = Arc::new(WriterStats::default());
+
+        let path = PathBuf::from(format!(
+            "{}/{}",
+            camera_array.image_path,
+            camera_array.crop_bed_id.id()
+        ));
+        create_dir_all(&path).map_err(ComponentError::io("Failed to create filepath"))?;
+        if let Some(retention) = &camera_array.retention {
+            retention
+                .check_free_space(&path)
+                .map_err(ComponentError::io("Refusing to start camera array"))?;
+        }
+        // Every directory is created before a camera is spawned, so an
+        // array refused here leaves no thread behind.
+        for bed_position in camera_array.cameras.keys() {
+            create_dir_all(path.join(bed_position.to_string()))
+                .map_err(ComponentError::io("Failed to create bed position path"))?;
+        }
+
+        let shm_writer = camera_array
+            .shm_sink
+            .as_ref()
+            .map(|sink| ShmImageWriter::create(sink, &camera_array.largest_roi()))
+            .transpose()
+            .map_err(ComponentError::io("Failed to create shared memory sink"))?;
+        // The trigger socket and the runtimes of the heartbeats and telemetry
+        // are set up before a camera is spawned too.
+        let trigger_publisher = camera_array
+            .trigger_publisher
+            .as_ref()
+            .map(TriggerPublisherConfig::bind)
+            .transpose()
+            .map_err(ComponentError::io("Failed to bind trigger publisher"))?;
+        let heartbeat = camera_array
+            .heartbeat_emitter
+            .map(|emitter| thread_runtime().map(|runtime| (emitter, runtime)))
+            .transpose()
+            .map_err(ComponentError::io("Failed to build heartbeat runtime"))?;
+        let telemetry = camera_array
+            .telemetry
+            .map(|telemetry| thread_runtime().map(|runtime| (telemetry, runtime)))
+            .transpose()
+            .map_err(ComponentError::io("Failed to build telemetry runtime"))?;
+
+        let restart_policy = camera_array.restart_policy;
+        let watchdog = camera_array.watchdog;
+        let mut camera_handles = HashMap::new();
+        for (bed_position, blueprint) in camera_array.cameras {
+            let stats = Arc::new(CameraStats::default());
+
+            // Set up the requirements for the threads to operate.
+            // lots of clones as new thread will take ownership.
+            let (device_handle, commands) = blueprint.spawn(
+                bed_position,
+                stop_signal.clone(),
+                Some(start_gate.clone()),
+                device_channel_tx.clone(),
+                stats.clone(),
+            );
+
+            camera_handles.insert(
+                bed_position,
+                CameraHandle {
+                    blueprint,
+                    join_handle: Some(device_handle),
+                    stop_signal: Some(stop_signal.clone()),
+                    stats,
+                    restarts: 0,
+                    backoff: Duration::from_millis(restart_policy.initial_backoff_ms),
+                    restart_at: None,
+                    commands,
+                    stall: StallRecovery::Healthy,
+                    restart_requested: false,
+                },
+            );
+        }
+        let camera_stats = camera_handles
+            .iter()
+            .map(|(bed_position, handle)| (*bed_position, handle.stats.clone()))
+            .collect();
+
+        // The supervisor holds the last sender outside the camera threads so
+        // rebuilt cameras can be handed one, it is dropped once the supervisor
+        // exits so the writer will see the channel close.
+        let supervisor_stop_signal = stop_signal.clone();
+        let supervisor_log = camera_array.log.clone();
+        let restart_requests = Arc::new(Mutex::new(Vec::new()));
+        let supervisor_restart_requests = restart_requests.clone();
+        let supervisor_handle = spawn_in_current_span(move || {
+            supervise_cameras(
+                camera_handles,
+                &restart_policy,
+                &watchdog,
+                &supervisor_stop_signal,
+                &supervisor_restart_requests,
+                &device_channel_tx,
+                &supervisor_log,
+            );
+        });
+
+        // The shared memory ring is written in place by a single worker,
+        // otherwise the tokio writer or a fixed pool of workers share the
+        // channel and save the images to disk. Workers only exit once the
+        // channel has closed and been drained.
+        let retention_handle = camera_array.retention.map(|retention| {
+            let thread_path = path.clone();
+            let thread_stop_signal = stop_signal.clone();
+            let thread_writer_stats = writer_stats.clone();
+            let thread_log = camera_array.log.clone();
+            spawn_in_current_span(move || {
+                enforce_retention(
+                    &retention,
+                    &thread_path,
+                    &thread_stop_signal,
+                    &thread_writer_stats,
+                    &thread_log,
+                );
+            })
+        });
+
+        // Trigger events are published straight off the cameras, so the
+        // lights are not held up behind the preview.
+        let mut tap_handles = Vec::new();
+        let device_channel_rx = if trigger_publisher.is_some() || camera_array.bus.is_some() {
+            let (sink_tx, sink_rx) = mpsc::channel::<DevicePayload>();
+            let crop_bed = camera_array.crop_bed_id;
+            let bus = camera_array.bus.clone();
+            tap_handles.push(spawn_in_current_span(move || {
+                trigger::tap_payloads(device_channel_rx, &sink_tx, trigger_publisher, bus.as_ref(), crop_bed);
+            }));
+            sink_rx
+        } else {
+            device_channel_rx
+        };
+
+        // Raw frames are debayered once here, so the preview and the writers
+        // all see the colour image.
+        let device_channel_rx = if let Some(pattern) = camera_array.debayer {
+            let (sink_tx, sink_rx) = mpsc::channel::<DevicePayload>();
+            tap_handles.push(spawn_in_current_span(move || {
+                debayer_payloads(device_channel_rx, &sink_tx, pattern);
+            }));
+            sink_rx
+        } else {
+            device_channel_rx
+        };
+
+        // The preview taps the channel ahead of the writers, only keeping a
+        // downscaled copy of a frame once per preview interval per camera.
+        let preview = camera_array.preview.map(|config| {
+            Arc::new(PreviewFrames::new(config).with_channel_zones(camera_array.channel_zones.clone()))
+        });
+        let device_channel_rx = if let Some(preview) = &preview {
+            let (sink_tx, sink_rx) = mpsc::channel::<DevicePayload>();
+            let thread_preview = preview.clone();
+            tap_handles.push(spawn_in_current_span(move || {
+                preview::tap_payloads(device_channel_rx, &sink_tx, &thread_preview);
+            }));
+            sink_rx
+        } else {
+            device_channel_rx
+        };
+
+        // Frames are deduplicated after the preview, so it still shows the
+        // cameras while the machine is stopped.
+        let dedupe = camera_array.dedupe.map(|config| Arc::new(FrameDedupe::new(config)));
+        let device_channel_rx = if let Some(dedupe) = &dedupe {
+            let (sink_tx, sink_rx) = mpsc::channel::<DevicePayload>();
+            let thread_dedupe = dedupe.clone();
+            let thread_writer_stats = writer_stats.clone();
+            tap_handles.push(spawn_in_current_span(move || {
+                dedupe::tap_payloads(device_channel_rx, &sink_tx, &thread_dedupe, &thread_writer_stats);
+            }));
+            sink_rx
+        } else {
+            device_channel_rx
+        };
+
+        let mut writer_handles: Vec<JoinHandle<()>> = if let Some(shm_writer) = shm_writer {
+            let thread_writer_stats = writer_stats.clone();
+            let thread_log = camera_array.log.clone();
+            vec![spawn_in_current_span(move || {
+                write_images_to_shm(shm_writer, device_channel_rx, &thread_writer_stats, &thread_log);
+            })]
+        } else if let Some(async_writer) = camera_array.async_writer {
+            let thread_writer_stats = writer_stats.clone();
+            let write_sidecar = camera_array.write_sidecar;
+            vec![spawn_in_current_span(move || {
+                async_writer::write_images(
+                    device_channel_rx,
+                    path,
+                    write_sidecar,
+                    async_writer,
+                    thread_writer_stats,
+                );
+            })]
+        } else {
+            let path = Arc::new(path);
+            let receiver = Arc::new(Mutex::new(device_channel_rx));
+            let write_sidecar = camera_array.write_sidecar;
+            (0..camera_array.writer_threads)
+                .map(|_| {
+                    let thread_path = path.clone();
+                    let thread_receiver = receiver.clone();
+                    let thread_writer_stats = writer_stats.clone();
+                    let thread_log = camera_array.log.clone();
+                    spawn_in_current_span(move || {
+                        write_images_to_disk(
+                            &thread_receiver,
+                            &thread_path,
+                            write_sidecar,
+                            &thread_writer_stats,
+                            &thread_log,
+                        );
+                    })
+                })
+                .collect()
+        };
+        writer_handles.extend(tap_handles);
+
+        let monitor = CameraArrayMonitor {
+            crop_bed: camera_array.crop_bed_id,
+            disabled_cameras: camera_array.disabled_cameras,
+            stop_signal,
+            camera_stats,
+            writer_stats,
+            preview,
+            dedupe,
+            restart_requests,
+            config: camera_array.config.clone(),
+            log: camera_array.log,
+        };
+
+        // The cameras run in threads, so the heartbeats are sent from a
+        // runtime of their own until the array is asked to stop.
+        let heartbeat_handle = heartbeat.map(|(heartbeat_emitter, runtime)| {
+            let thread_monitor = monitor.clone();
+            let uuid = camera_array.uuid;
+            let started_at = camera_array.started_at;
+            spawn_in_current_span(move || {
+                runtime.block_on(emit_heartbeats(heartbeat_emitter, || {
+                    let heartbeat = (!thread_monitor.is_stopping()).then(|| {
+                        Heartbeat::new(
+                            ComponentKind::CameraArray,
+                            uuid,
+                            thread_monitor.crop_bed,
+                            started_at.elapsed().as_secs_f64(),
+                        )
+                        .with_frame_count(thread_monitor.stats().frames_captured())
+                    });
+                    std::future::ready(heartbeat)
+                }));
+            })
+        });
+
+        // Telemetry is fed from the shared counters, which need no lock,
+        // and the last snapshot is shipped once the array is stopped.
+        let telemetry_handle = telemetry.map(|(telemetry, runtime)| {
+            let thread_monitor = monitor.clone();
+            let uuid = camera_array.uuid;
+            spawn_in_current_span(move || {
+                let emitter = TelemetryEmitter::new(telemetry);
+                let shipping = emitter.clone().ship();
+                let feeding = async move {
+                    let mut interval = tokio::time::interval(emitter.config().interval());
+                    let mut previous: Option<(Instant, CameraArrayStats)> = None;
+                    while !thread_monitor.is_stopping() {
+                        interval.tick().await;
+                        let stats = thread_monitor.stats();
+                        let taken_at = Instant::now();
+                        let since = previous.as_ref().map(|(at, before)| (before, taken_at - *at));
+                        emitter.feed(stats.telemetry(uuid, since));
+                        previous = Some((taken_at, stats));
+                    }
+                };
+                runtime.block_on(async { tokio::join!(shipping, feeding) });
+            })
+        });
+
+        // The cameras run in threads, so the bus is polled for a shutdown
+        // until the array is stopped one way or the other.
+        let shutdown_handle = camera_array.bus.map(|bus| {
+            let mut subscriber = bus.subscribe(Topic::ShutdownRequested, "camera array");
+            let thread_monitor = monitor.clone();
+            spawn_in_current_span(move || {
+                while !thread_monitor.is_stopping() {
+                    if let Some(BusEvent::ShutdownRequested) = subscriber.try_recv() {
+                        info!("Shutdown requested on the bus, stopping the camera array");
+                        thread_monitor.request_stop();
+                    }
+                    thread::sleep(SUPERVISOR_POLL);
+                }
+            })
+        });
+
+        Ok(CameraArrayHandle {
+            monitor,
+            supervisor_handle,
+            writer_handles,
+            retention_handle,
+            heartbeat_handle,
+            telemetry_handle,
+            shutdown_handle,
+        })
+    }
+}
+
+impl ComponentHandle for CameraArrayHandle {
+    type Status = CameraArrayStats;
+
+    fn request_stop(&self) {
+        self.monitor.request_stop();
+    }
+
+    // Joining the camera threads blocks, so it is kept off the runtime.
+    // Should the join itself panic the running counters are returned.
+    async fn shutdown(self) -> CameraArrayStats {
+        let monitor = self.monitor.clone();
+        match tokio::task::spawn_blocking(move || self.stop()).await {
+            Ok(stats) => stats,
+            Err(e) => {
+                monitor
+                    .log
+                    .error(EventCode::TaskFailed, format!("Failed to join the camera array: {e}"));
+                CameraArrayStats {
+                    threads_panicked: 1,
+                    ..monitor.stats()
+                }
+            }
+        }
+    }
+}
+
+impl ComponentController for CameraArrayController {
+    type Config = CameraArrayConfig;
+    type Component = CameraArray;
+    type Handle = CameraArrayHandle;
+
+    fn build(config: CameraArrayConfig) -> Result<CameraArray, ComponentError> {
+        CameraArray::new(config)
+    }
+
+    async fn start(component: CameraArray) -> Result<CameraArrayHandle, ComponentError> {
+        CameraArrayController::start(component)
+    }
+}
+
+/// Build the single threaded runtime an array thread drives its async
+/// work on.
+fn thread_runtime() -> std::io::Result<tokio::runtime::Runtime> {
+    tokio::runtime::Builder::new_current_thread().enable_all().build()
+}
+
+/// Image writer worker that saves payloads to disk until every sender has
+/// been dropped.
+///
+/// * `receiver`: channel shared between the workers.
+/// * `path`: parent directory for the images.
+/// * `write_sidecar`: also save the payload metadata as json.
+/// * `stats`: counters shared between the workers.
+/// * `log`: events of the array.
+fn write_images_to_disk(
+    receiver: &Mutex<Receiver<DevicePayload>>,
+    path: &Path,
+    write_sidecar: bool,
+    stats: &WriterStats,
+    log: &LogEmitter,
+) {
+    loop {
+        // The lock is only held while waiting for the next payload, so the
+        // workers take turns receiving and save in parallel.
+        let next_payload = receiver.lock().expect("Image queue poisoned").recv();
+        let Ok(payload) = next_payload else {
+            break;
+        };
+        // Save under a partial name and rename once complete, the rename is
+        // atomic so the retention scan only ever sees finished images.
+        let filename = path.join(payload.filename());
+        let partial = PathBuf::from(format!("{}{PARTIAL_SUFFIX}", filename.display()));
+        let mut result = payload
+            .image
+            .save_with_format(&partial, image::ImageFormat::Png)
+            .map_err(|e| e.to_string())
+            .and_then(|()| fs::rename(&partial, &filename).map_err(|e| e.to_string()));
+        // The sidecar follows the image so a reader that finds the json can
+        // always open the png next to it.
+        if write_sidecar && result.is_ok() {
+            result = write_metadata_sidecar(&payload, &filename.with_extension("json"));
+        }
+        if let Err(ref e) = result {
+            log.warn(
+                EventCode::ImageWriteFailed,
+                format!("Failed to save image to path {:?} {e}", filename),
+            );
+        }
+        stats.record(payload.location_id(), result.is_ok());
+    }
+}
+
+/// Save the payload metadata as json, through a partial file and a rename
+/// like the image itself.
+///
+/// * `payload`: payload that was just saved.
+/// * `filename`: destination of the sidecar.
+fn write_metadata_sidecar(payload: &DevicePayload, filename: &Path) -> Result<(), String> {
+    let partial = PathBuf::from(format!("{}{PARTIAL_SUFFIX}", filename.display()));
+    let json = serde_json::to_vec_pretty(&payload.metadata()).map_err(|e| e.to_string())?;
+    fs::write(&partial, json)
+        .and_then(|()| fs::rename(&partial, filename))
+        .map_err(|e| e.to_string())
+}
+
+/// Periodically scan the capture directory and remove images beyond the
+/// retention limits until the stop signal is set.
+///
+/// * `retention`: size and age limits.
+/// * `path`: capture directory.
+/// * `stop_signal`: shared with the rest of the array.
+/// * `stats`: counters for the writer.
+/// * `log`: events of the array.
+fn enforce_retention(
+    retention: &RetentionPolicy,
+    path: &Path,
+    stop_signal: &AtomicBool,
+    stats: &WriterStats,
+    log: &LogEmitter,
+) {
+    let mut last_scan: Option<Instant> = None;
+    while !stop_signal.load(Ordering::Relaxed) {
+        if last_scan.map_or(true, |at| at.elapsed() >= retention.scan_interval()) {
+            match retention.prune(path) {
+                Ok(report) => {
+                    stats.files_pruned.fetch_add(report.files, Ordering::Relaxed);
+                }
+                Err(e) => log.warn(
+                    EventCode::RetentionFailed,
+                    format!("Failed to apply retention to {:?} {e}", path),
+                ),
+            }
+            last_scan = Some(Instant::now());
+        }
+        thread::sleep(SUPERVISOR_POLL);
+    }
+}
+
+/// Colour image of a raw mosaic frame, `None` for frames already in
+/// colour.
+///
+/// * `image`: frame from a camera.
+/// * `pattern`: order of the colour filters on the sensor.
+fn debayer_frame(image: &DynamicImage, pattern: BayerPattern) -> Option<DynamicImage> {
+    match image {
+        DynamicImage::ImageLuma8(raw) => Some(debayer(raw.as_raw(), raw.width(), raw.height(), pattern)),
+        _ => None,
+    }
+}
+
+/// Forward every payload from the cameras to the primary sink, debayering
+/// the raw frames on the way. Returns once every camera sender has been
+/// dropped or the sink has gone.
+///
+/// * `receiver`: channel the cameras send payloads on.
+/// * `sink`: channel read by the preview and the image writers.
+/// * `pattern`: order of the colour filters on the sensors.
+fn debayer_payloads(receiver: Receiver<DevicePayload>, sink: &Sender<DevicePayload>, pattern: BayerPattern) {
+    for mut payload in receiver {
+        if let Some(image) = debayer_frame(&payload.image, pattern) {
+            payload.image = image;
+        }
+        if sink.send(payload).is_err() {
+            break;
+        }
+    }
+}
+
+/// Image writer worker that copies payloads into a shared memory ring
+/// until every sender has been dropped.
+///
+/// * `shm_writer`: writer for the ring.
+/// * `receiver`: channel the cameras send payloads on.
+/// * `stats`: counters for the writer.
+/// * `log`: events of the array.
+fn write_images_to_shm(
+    mut shm_writer: ShmImageWriter,
+    receiver: Receiver<DevicePayload>,
+    stats: &WriterStats,
+    log: &LogEmitter,
+) {
+    for payload in receiver {
+        let image = &payload.image;
+        let result = shm_writer.write(
+            payload.captured_at(),
+            payload.location_id(),
+            (image.width(), image.height()),
+            image.as_bytes(),
+        );
+        if let Err(ref e) = result {
+            log.warn(EventCode::ImageWriteFailed, format!("Failed to write image to shared memory {e}"));
+        }
+        stats.record(payload.location_id(), result.is_ok());
+    }
+}
+
+/// Check a running camera for a stall and step through the recovery
+/// tiers, restarting the stream first and rebuilding the device if the
+/// camera is still stalled one stall period later.
+///
+/// * `bed_position`: position in line with bill of materials.
+/// * `handle`: handle for the camera thread.
+/// * `watchdog`: frame intervals allowed without a buffer.
+/// * `log`: events of the array.
+fn watch_for_stall(bed_position: u8, handle: &mut CameraHandle, watchdog: &WatchdogPolicy, log: &LogEmitter) {
+    if handle.join_handle.is_none() {
+        return;
+    }
+    let stall_limit = handle.blueprint.frame_interval() * watchdog.stall_factor.max(1);
+    let Some(since_progress) = handle.stats.since_progress() else {
+        return;
+    };
+    if since_progress < stall_limit {
+        handle.stall = StallRecovery::Healthy;
+        return;
+    }
+    match handle.stall {
+        StallRecovery::Healthy => {
+            log.warn(
+                EventCode::CameraStalled,
+                format!("Camera at bed position {bed_position} stalled, restarting stream"),
+            );
+            handle.stats.stalls.fetch_add(1, Ordering::Relaxed);
+            // A send only fails if the thread has exited, which the
+            // supervisor will pick up on its next pass.
+            let _ = handle.commands.send(CameraCommand::RestartStream);
+            handle.stall = StallRecovery::StreamRestarted(Instant::now());
+        }
+        StallRecovery::StreamRestarted(at) if at.elapsed() >= stall_limit => {
+            log.warn(
+                EventCode::CameraStalled,
+                format!("Camera at bed position {bed_position} still stalled, rebuilding device"),
+            );
+            let _ = handle.commands.send(CameraCommand::Rebuild);
+            handle.stall = StallRecovery::Rebuilding;
+        }
+        StallRecovery::StreamRestarted(_) | StallRecovery::Rebuilding => {}
+    }
+}
+
+/// Watch the camera threads and rebuild any that exit before the stop
+/// signal is set, backing off exponentially between rebuilds. Running
+/// cameras are checked by the watchdog. Returns once stopped or once
+/// every camera has used up its restarts.
+///
+/// * `camera_handles`: Handles for the camera threads keyed by bed position.
+/// * `policy`: Restart count and back off.
+/// * `watchdog`: Stall detection for running cameras.
+/// * `stop_signal`: Signal shared with every camera thread.
+/// * `restart_requests`: Bed positions of the cameras the operator asked
+///   to be rebuilt.
+/// * `image_channel`: Sender cloned into rebuilt cameras.
+/// * `log`: Events of the array.
+fn supervise_cameras(
+    mut camera_handles: HashMap<u8, CameraHandle>,
+    policy: &RestartPolicy,
+    watchdog: &WatchdogPolicy,
+    stop_signal: &Arc<AtomicBool>,
+    restart_requests: &Mutex<Vec<u8>>,
+    image_channel: &Sender<DevicePayload>,
+    log: &LogEmitter,
+) {
+    let max_backoff = Duration::from_millis(policy.max_backoff_ms);
+
+    while !stop_signal.load(Ordering::Relaxed) {
+        let requested = std::mem::take(&mut *restart_requests.lock().unwrap_or_else(PoisonError::into_inner));
+        for bed_position in requested {
+            let Some(handle) = camera_handles.get_mut(&bed_position) else {
+                continue;
+            };
+            log.info(
+                EventCode::CameraRestarted,
+                format!("Restart of the camera at bed position {bed_position} requested"),
+            );
+            handle.restart_requested = true;
+            if handle.join_handle.is_some() {
+                let _ = handle.commands.send(CameraCommand::Rebuild);
+                handle.stall = StallRecovery::Rebuilding;
+            } else {
+                // Waiting out a back off or out of restarts, rebuild it now.
+                handle.restart_at = Some(Instant::now());
+            }
+        }
+
+        for (bed_position, handle) in &mut camera_handles {
+            if handle
+                .join_handle
+                .as_ref()
+                .is_some_and(JoinHandle::is_finished)
+            {
+                let join_handle = handle.join_handle.take().expect("Checked above");
+                if join_handle.join().is_err() {
+                    log.error(
+                        EventCode::CameraRestarted,
+                        format!("Camera thread at bed position {bed_position} panicked"),
+                    );
+                } else if !handle.restart_requested {
+                    log.warn(
+                        EventCode::CameraRestarted,
+                        format!("Camera thread at bed position {bed_position} exited early"),
+                    );
+                }
+
+                if handle.restart_requested {
+                    handle.restart_at = Some(Instant::now());
+                } else if handle.restarts < policy.max_restarts {
+                    #[allow(clippy::cast_possible_truncation)]
+                    handle
+                        .stats
+                        .backoff_ms
+                        .store(handle.backoff.as_millis() as u64, Ordering::Relaxed);
+                    handle.restart_at = Some(Instant::now() + handle.backoff);
+                    handle.backoff = (handle.backoff * 2).min(max_backoff);
+                } else {
+                    log.error(
+                        EventCode::CameraRestarted,
+                        format!(
+                            "Camera at bed position {bed_position} used all {} restarts",
+                            policy.max_restarts
+                        ),
+                    );
+                }
+            }
+
+            if handle.restart_at.is_some_and(|at| Instant::now() >= at) {
+                handle.restart_at = None;
+                if !std::mem::take(&mut handle.restart_requested) {
+                    handle.restarts += 1;
+                }
+                handle.stats.restarts.fetch_add(1, Ordering::Relaxed);
+                handle.stats.reset_progress();
+                log.emit(
+                    log.event(
+                        LogLevel::Info,
+                        EventCode::CameraRestarted,
+                        format!("Camera at bed position {bed_position} restarted"),
+                    )
+                    .with_field("bed_position", bed_position)
+                    .with_field("restarts", handle.restarts),
+                );
+                let (join_handle, commands) = handle.blueprint.spawn(
+                    *bed_position,
+                    stop_signal.clone(),
+                    None,
+                    image_channel.clone(),
+                    handle.stats.clone(),
+                );
+                handle.join_handle = Some(join_handle);
+                handle.commands = commands;
+                handle.stall = StallRecovery::Healthy;
+            }
+
+            watch_for_stall(*bed_position, handle, watchdog, log);
+        }
+
+        let exhausted = camera_handles
+            .values()
+            .all(|handle| handle.join_handle.is_none() && handle.restart_at.is_none());
+        if exhausted {
+            break;
+        }
+        thread::sleep(SUPERVISOR_POLL);
+    }
+
+    for (bed_position, handle) in camera_handles {
+        if let Some(join_handle) = handle.join_handle {
+            if join_handle.join().is_err() {
+                log.error(
+                    EventCode::TaskFailed,
+                    format!("Camera thread at bed position {bed_position} panicked"),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        devices::hardware::camera::PayloadMetadata,
+        messages::{control::trigger::TriggerMessage, telemetry::Telemetry},
+        utils::{
+            configgen::{canonical_configs, to_yaml},
+            logging::capture,
+            paths::repo_relative,
+        },
+    };
+    use serial_test::serial;
+    use tracing::Level;
+
+    #[test]
+    /// The array configs from the generator read back and write out as
+    /// they were generated.
+    fn test_write_component_config_to_file() {
+        let dir = std::env::temp_dir().join(format!("onyx-array-configs-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let generated: Vec<_> = canonical_configs()
+            .into_iter()
+            .filter(|config| config.path.starts_with("config/components/crop_bed/sensing/camera_array"))
+            .collect();
+        assert_eq!(generated.len(), 3);
+        for config in generated {
+            let path = dir.join(config.path.file_name().unwrap());
+            std::fs::write(&path, &config.yaml).unwrap();
+            let read_config = CameraArrayConfig::try_from_file(&path).unwrap();
+            assert_eq!(to_yaml(&read_config), config.yaml, "{}", config.path.display());
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test writing component configurations to a yaml file, and reading
     /// back to a type safe structure.
     fn test_read_write_component_config_to_file() {
         let write_config = CameraArrayConfig::new(String::from("./images"), 0)
             .add_camera_config_file(format!("./config/devices/crop_bed/camera_{}.yaml", 0), 0)
             .add_camera_config_file(format!("./config/devices/crop_bed/camera_{}.yaml", 1), 1);
 
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(Path::new(&format!(
-                "{}/config/components/crop_bed/sensing/camera_array/crop_bed_camera_array_0.yaml",
-                env!("CARGO_MANIFEST_DIR")
-            )))
-            .expect("Failed to open file");
-
-        serde_yaml::to_writer(file, &write_config).expect("Failed to write yaml");
-
-        let read_config = CameraArrayConfig::from_file(Path::new(&format!(
-            "{}/config/components/crop_bed/sensing/camera_array/crop_bed_camera_array_0.yaml",
-            env!("CARGO_MANIFEST_DIR")
-        )));
+        let path = std::env::temp_dir().join(format!("onyx-array-config-{}.yaml", Uuid::new_v4()));
+        crate::utils::configgen::write_config(&path, &write_config).expect("Failed to write yaml");
+        let read_config = CameraArrayConfig::try_from_file(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
 
         assert_eq!(
             write_config, read_config,
@@ -330,6 +2055,606 @@ mod tests {
         );
     }
 
+    #[test]
+    /// The shared memory sink is optional and should survive a round trip
+    /// through yaml without touching configs that do not use it.
+    fn test_shm_sink_config_round_trip() {
+        let config = CameraArrayConfig::new(String::from("./images"), 0)
+            .add_camera_config_file("./config/devices/crop_bed/camera_0.yaml", 0);
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert!(!yaml.contains("shm_sink"), "Unused sink should not be written");
+
+        let config = config.with_shm_sink(ShmSinkConfig {
+            name: String::from("onyx-bed-0"),
+            slots: 8,
+        });
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        let read_config: CameraArrayConfig = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(config, read_config);
+    }
+
+    #[test]
+    /// The array configs in the repository are valid. Missing and broken
+    /// camera configs, cameras shadowed by simulated ones, an array without
+    /// cameras and zones of missing cameras or no width are reported.
+    fn test_validate_camera_array_config() {
+        for crop_bed in 0..=2 {
+            let path = repo_relative(format!(
+                "config/components/crop_bed/sensing/camera_array/crop_bed_camera_array_{crop_bed}.yaml"
+            ))
+            .unwrap();
+            let report = crate::utils::config::validate_file::<CameraArrayConfig>(&path);
+            assert!(report.is_valid(), "{report}");
+        }
+
+        let dir = std::env::temp_dir().join(format!("onyx-validate-array-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("camera_1.yaml"), "fps: 0\nip_address: 169.254.8.11\n").unwrap();
+        let broken = CameraArrayConfig::new(dir.join("missing/images").to_string_lossy().into_owned(), 0)
+            .add_camera_config_file("camera_0.yaml", 0)
+            .add_camera_config_file("camera_1.yaml", 1)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 1)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 0, 8), 2)
+            .with_channel_zones(HashMap::from([
+                (2, vec![ChannelZone { channel: 1, x0: 4, x1: 4 }]),
+                (7, vec![ChannelZone { channel: 2, x0: 0, x1: 4 }]),
+            ]));
+        let path = dir.join("camera_array.yaml");
+        serde_yaml::to_writer(fs::File::create(&path).unwrap(), &broken).unwrap();
+        let report = crate::utils::config::validate_file::<CameraArrayConfig>(&path);
+        assert_eq!(
+            report.fields(),
+            [
+                "camera_config_files.0",
+                "camera_config_files.1.fps",
+                "camera_config_files.1",
+                "simulated_cameras.2.width",
+                "image_path",
+                "channel_zones.2.0",
+                "channel_zones.7",
+            ],
+            "{report}"
+        );
+
+        let mut report = ValidationReport::new("camera_array.yaml");
+        CameraArrayConfig::new(String::from("./images"), 0)
+            .add_camera_config_file("missing.yaml", 0)
+            .disable_camera(0)
+            .validate(&mut report);
+        assert_eq!(report.fields(), ["camera_config_files"], "{report}");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Configs built apart with the same cameras write byte-identical yaml,
+    /// the cameras in bed position order.
+    fn test_config_yaml_is_deterministic() {
+        let config = || {
+            (0..16).fold(CameraArrayConfig::new(String::from("./images"), 0), |config, position| {
+                config
+                    .add_camera_config_file(format!("./config/devices/crop_bed/camera_{position}.yaml"), position)
+                    .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), position + 16)
+            })
+        };
+        let yaml = serde_yaml::to_string(&config()).unwrap();
+        assert_eq!(serde_yaml::to_string(&config()).unwrap(), yaml);
+        let camera_0 = yaml.find("camera_0.yaml").unwrap();
+        assert!(yaml.find("camera_15.yaml").unwrap() > camera_0, "{yaml}");
+    }
+
+    #[test]
+    /// Raw mosaic frames are debayered with the pattern configured, and
+    /// frames already in colour are passed on as they are.
+    fn test_debayer_frames() {
+        let config = CameraArrayConfig::new(String::from("./images"), 0).with_debayer(BayerPattern::Grbg);
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert!(yaml.contains("debayer: grbg"), "{yaml}");
+        assert_eq!(serde_yaml::from_str::<CameraArrayConfig>(&yaml).unwrap(), config);
+
+        let raw = image::GrayImage::from_raw(2, 2, vec![10, 20, 30, 40]).unwrap();
+        let rgb = debayer_frame(&DynamicImage::ImageLuma8(raw), BayerPattern::Grbg).unwrap();
+        assert_eq!(rgb.to_rgb8().get_pixel(1, 0).0, [20, 25, 30]);
+        assert!(debayer_frame(&DynamicImage::new_rgb8(2, 2), BayerPattern::Grbg).is_none());
+    }
+
+    #[test]
+    #[serial]
+    /// A simulated camera that panics after a few frames should be rebuilt
+    /// until its restarts are used up, without stalling the rest of the array,
+    /// each panic and restart traced with its bed position.
+    fn test_camera_array_recovers_from_camera_panic() {
+        capture::capture_events();
+        let frames_per_run = 5;
+        let max_restarts = 2;
+        let config = CameraArrayConfig::new(
+            format!("{}/test-outputs/component-tests/camera_array_restart", env!("CARGO_MANIFEST_DIR")),
+            0,
+        )
+        .add_simulated_camera(
+            SimulatedCameraConfig::new(None, 50, 8, 8).with_panic_after_frames(frames_per_run),
+            0,
+        )
+        .add_simulated_camera(SimulatedCameraConfig::new(None, 50, 8, 8), 1)
+        .with_restart_policy(RestartPolicy {
+            max_restarts,
+            initial_backoff_ms: 10,
+            max_backoff_ms: 20,
+        });
+
+        let camera_array = CameraArray::new(config).unwrap();
+        let component = camera_array.get_uuid().to_string();
+        let handle = CameraArrayController::start(camera_array).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while handle.stats().cameras[&0].restarts < u64::from(max_restarts) {
+            assert!(Instant::now() < deadline, "Camera was not restarted {}", handle.stats());
+            thread::sleep(Duration::from_millis(10));
+        }
+        // Give the last run time to reach its panic.
+        thread::sleep(Duration::from_millis(500));
+        let stats = handle.stop();
+
+        let failing = stats.cameras[&0];
+        assert_eq!(failing.restarts, u64::from(max_restarts));
+        assert_eq!(
+            failing.frames_captured + failing.frames_late,
+            frames_per_run * (u64::from(max_restarts) + 1)
+        );
+        assert!(
+            stats.cameras[&1].frames_captured > failing.frames_captured,
+            "Healthy camera stalled {stats}"
+        );
+        assert_eq!(stats.cameras[&1].restarts, 0);
+        assert_eq!(stats.write_failures, 0, "Failed to write images {stats}");
+
+        let restarts: Vec<_> = capture::captured_with("component", &component)
+            .into_iter()
+            .filter(|event| event.field("code") == Some("camera_restarted"))
+            .collect();
+        let levels: Vec<_> = restarts.iter().map(|event| event.level).collect();
+        assert_eq!(
+            levels,
+            [
+                Level::ERROR,
+                Level::INFO,
+                Level::ERROR,
+                Level::INFO,
+                Level::ERROR,
+                Level::ERROR
+            ],
+            "{restarts:?}"
+        );
+        assert_eq!(restarts[1].field("fields"), Some(r#"{"bed_position":0,"restarts":1}"#));
+        assert_eq!(restarts[3].field("fields"), Some(r#"{"bed_position":0,"restarts":2}"#));
+        assert_eq!(restarts[3].field("kind"), Some("camera array"));
+    }
+
+    #[test]
+    #[serial]
+    /// The retention scan should remove images while the writers keep
+    /// saving, without ever removing an image that is in flight.
+    fn test_camera_array_retention_while_writing() {
+        let image_path = std::env::temp_dir().join(format!("onyx-camera-array-{}", Uuid::new_v4()));
+        let config = CameraArrayConfig::new(image_path.to_string_lossy().into_owned(), 0)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 50, 16, 16), 0)
+            .with_retention(RetentionPolicy {
+                max_bytes: Some(1),
+                scan_interval_secs: 1,
+                ..RetentionPolicy::default()
+            });
+
+        let handle = CameraArrayController::start(CameraArray::new(config).unwrap()).unwrap();
+        thread::sleep(Duration::from_millis(1500));
+        let stats = handle.stop();
+
+        assert_eq!(stats.write_failures, 0, "Retention raced a write {stats}");
+        assert!(stats.files_pruned > 0, "Nothing was pruned {stats}");
+        fs::remove_dir_all(image_path).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    /// A camera whose stall clears on a stream restart is recovered by the
+    /// first tier, one that keeps stalling is rebuilt by the second.
+    fn test_watchdog_recovers_stalled_cameras() {
+        let image_path = std::env::temp_dir().join(format!("onyx-watchdog-{}", Uuid::new_v4()));
+        let frames_before_stall = 5;
+        let config = CameraArrayConfig::new(image_path.to_string_lossy().into_owned(), 0)
+            .add_simulated_camera(
+                SimulatedCameraConfig::new(None, 50, 8, 8)
+                    .with_stall_after_frames(frames_before_stall, false),
+                0,
+            )
+            .add_simulated_camera(
+                SimulatedCameraConfig::new(None, 50, 8, 8)
+                    .with_stall_after_frames(frames_before_stall, true),
+                1,
+            )
+            .with_watchdog(WatchdogPolicy { stall_factor: 5 })
+            .with_restart_policy(RestartPolicy {
+                max_restarts: 10,
+                initial_backoff_ms: 10,
+                max_backoff_ms: 10,
+            });
+
+        let handle = CameraArrayController::start(CameraArray::new(config).unwrap()).unwrap();
+        thread::sleep(Duration::from_millis(1500));
+        let stats = handle.stop();
+
+        let stream_tier = stats.cameras[&0];
+        assert!(stream_tier.stalls >= 1, "Stall was not detected {stats}");
+        assert!(stream_tier.stream_restarts >= 1, "Stream was not restarted {stats}");
+        assert_eq!(stream_tier.restarts, 0, "Stream restart should have been enough {stats}");
+        assert!(stream_tier.frames_captured > frames_before_stall, "Camera did not recover {stats}");
+
+        let rebuild_tier = stats.cameras[&1];
+        assert!(rebuild_tier.stalls >= 1, "Stall was not detected {stats}");
+        assert!(rebuild_tier.stream_restarts >= 1, "Stream was not restarted first {stats}");
+        assert!(rebuild_tier.restarts >= 1, "Device was not rebuilt {stats}");
+        assert!(rebuild_tier.frames_captured > frames_before_stall, "Camera did not recover {stats}");
+        fs::remove_dir_all(image_path).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    /// Every image should get a sidecar that reads back to the metadata of
+    /// the payload it was saved from.
+    fn test_metadata_sidecar() {
+        let image_path = std::env::temp_dir().join(format!("onyx-sidecar-{}", Uuid::new_v4()));
+        let config = CameraArrayConfig::new(image_path.to_string_lossy().into_owned(), 0)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 3)
+            .with_sidecar();
+        let handle = CameraArrayController::start(CameraArray::new(config).unwrap()).unwrap();
+        thread::sleep(Duration::from_millis(500));
+        let stats = handle.stop();
+        assert_eq!(stats.write_failures, 0, "{stats}");
+
+        let mut sequences = Vec::new();
+        for entry in fs::read_dir(image_path.join("0/3")).unwrap() {
+            let sidecar = entry.unwrap().path();
+            if sidecar.extension().and_then(OsStr::to_str) != Some("json") {
+                continue;
+            }
+            let metadata: PayloadMetadata =
+                serde_json::from_slice(&fs::read(&sidecar).unwrap()).unwrap();
+            assert!(sidecar.with_extension("png").exists(), "No image for {:?}", sidecar);
+            assert_eq!(metadata.location_id, Some(3));
+            assert_eq!((metadata.width, metadata.height), (8, 8));
+            assert_eq!(metadata.roi, Some(Roi { x: 0, y: 0, w: 8, h: 8 }));
+            sequences.push(metadata.sequence);
+        }
+        assert_eq!(sequences.len() as u64, stats.images_written, "{stats}");
+        sequences.sort_unstable();
+        sequences.dedup();
+        assert_eq!(sequences.len() as u64, stats.images_written, "Repeated sequence numbers");
+        fs::remove_dir_all(image_path).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    /// Every frame saved is published as a trigger event of its camera.
+    fn test_trigger_publisher() {
+        let listener = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        let image_path = std::env::temp_dir().join(format!("onyx-trigger-{}", Uuid::new_v4()));
+        let config = CameraArrayConfig::new(image_path.to_string_lossy().into_owned(), 1)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 3)
+            .with_trigger_publisher(TriggerPublisherConfig {
+                address: listener.local_addr().unwrap(),
+            });
+        let handle = CameraArrayController::start(CameraArray::new(config).unwrap()).unwrap();
+        thread::sleep(Duration::from_millis(500));
+        let stats = handle.stop();
+
+        let mut data = [0; 512];
+        let mut triggers = Vec::new();
+        while let Ok(length) = listener.recv(&mut data) {
+            triggers.push(serde_json::from_slice::<TriggerMessage>(&data[..length]).unwrap());
+        }
+        assert_eq!(triggers.len() as u64, stats.images_written, "{stats}");
+        assert!(triggers.iter().all(|t| (t.cam_id, t.crop_bed_id) == (3, CropBed::Centre)));
+        assert!(triggers.windows(2).all(|t| t[0].triggered_at < t[1].triggered_at));
+        fs::remove_dir_all(image_path).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    /// With every frame close enough, only the first frame of each camera
+    /// is saved and the rest are counted as skipped, until it is reset.
+    fn test_dedupe_skips_frames() {
+        let image_path = std::env::temp_dir().join(format!("onyx-dedupe-{}", Uuid::new_v4()));
+        let config = CameraArrayConfig::new(image_path.to_string_lossy().into_owned(), 0)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 50, 8, 8), 0)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 50, 8, 8), 1)
+            .with_dedupe(DedupeConfig { max_distance: 64 });
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert!(yaml.contains("max_distance: 64"), "{yaml}");
+        let handle = CameraArrayController::start(CameraArray::new(config).unwrap()).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        let dedupe = handle.monitor().dedupe().expect("Frames are not deduplicated");
+        assert_eq!(handle.stats().images_written, 2);
+        dedupe.reset(0);
+        thread::sleep(Duration::from_millis(200));
+        let stats = handle.stop();
+
+        assert_eq!(stats.images_written, 3, "{stats}");
+        assert!(stats.frames_skipped > 0, "{stats}");
+        assert!(stats.images_written + stats.frames_skipped <= stats.frames_captured(), "{stats}");
+        fs::remove_dir_all(image_path).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    /// On a bus every frame saved is published as a trigger event, and a
+    /// shutdown requested on the bus stops the array without a stop.
+    fn test_bus_triggers_and_shutdown() {
+        let image_path = std::env::temp_dir().join(format!("onyx-bus-{}", Uuid::new_v4()));
+        let config = CameraArrayConfig::new(image_path.to_string_lossy().into_owned(), 0)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 2);
+        let bus = MessageBus::new();
+        let mut triggers = bus.subscribe(Topic::CameraTrigger, "test");
+        let handle = CameraArrayController::start(CameraArray::new(config).unwrap().with_bus(bus.clone())).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(bus.request_shutdown(), 1);
+        let stats = handle.wait();
+
+        let mut published = Vec::new();
+        while let Some(BusEvent::CameraTrigger(trigger)) = triggers.try_recv() {
+            published.push(trigger);
+        }
+        assert!(!published.is_empty());
+        assert_eq!(published.len() as u64, stats.images_written, "{stats}");
+        assert!(published.iter().all(|t| (t.cam_id, t.crop_bed_id) == (2, CropBed::LeftBoom)));
+        assert_eq!(triggers.dropped(), 0);
+        fs::remove_dir_all(image_path).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    /// A running array sends its heartbeat with the frames captured so far,
+    /// and stops sending once it is stopped.
+    fn test_heartbeats_while_running() {
+        let listener = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+        let image_path = std::env::temp_dir().join(format!("onyx-heartbeat-{}", Uuid::new_v4()));
+        let config = CameraArrayConfig::new(image_path.to_string_lossy().into_owned(), 2)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 0)
+            .with_heartbeat_emitter(
+                HeartbeatEmitterConfig::new(listener.local_addr().unwrap().to_string()).with_interval(50),
+            );
+        let camera_array = CameraArray::new(config).unwrap();
+        let uuid = camera_array.get_uuid();
+        let handle = CameraArrayController::start(camera_array).unwrap();
+
+        let mut data = [0; 512];
+        let mut heartbeats = Vec::new();
+        for _ in 0..3 {
+            let length = listener.recv(&mut data).expect("No heartbeat from the array");
+            heartbeats.push(serde_json::from_slice::<Heartbeat>(&data[..length]).unwrap());
+        }
+        let stats = handle.stop();
+        assert!(heartbeats.iter().all(
+            |h| (h.heartbeat, h.component, h.crop_bed_id) == (ComponentKind::CameraArray, uuid, CropBed::RightBoom)
+        ));
+        assert!(heartbeats.windows(2).all(|h| h[0].uptime_s < h[1].uptime_s));
+        assert!(heartbeats.iter().all(|h| h.frame_count <= Some(stats.frames_captured())));
+
+        // Drain what was sent before the stop, nothing follows it.
+        listener.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        while listener.recv(&mut data).is_ok() {}
+        assert!(listener.recv(&mut data).is_err(), "Heartbeats sent after the stop");
+        fs::remove_dir_all(image_path).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    /// A running array ships the frame rate of each camera, taken from the
+    /// frames captured between snapshots, and a last snapshot once stopped.
+    fn test_telemetry_while_running() {
+        let listener = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+        let image_path = std::env::temp_dir().join(format!("onyx-telemetry-{}", Uuid::new_v4()));
+        let config = CameraArrayConfig::new(image_path.to_string_lossy().into_owned(), 1)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 0)
+            .with_telemetry(TelemetryEmitterConfig::new(listener.local_addr().unwrap().to_string()).with_interval(100));
+        let camera_array = CameraArray::new(config).unwrap();
+        let uuid = camera_array.get_uuid();
+        let handle = CameraArrayController::start(camera_array).unwrap();
+
+        let mut data = [0; 1024];
+        let mut snapshots = Vec::new();
+        for _ in 0..3 {
+            let length = listener.recv(&mut data).expect("No telemetry from the array");
+            match serde_json::from_slice(&data[..length]).unwrap() {
+                Telemetry::Camera(camera) => snapshots.push(camera),
+                telemetry => panic!("Shipped {} telemetry from the array", telemetry.kind()),
+            }
+        }
+        let stats = handle.stop();
+        assert!(snapshots
+            .iter()
+            .all(|t| (t.header.component, t.header.crop_bed_id) == (uuid, CropBed::Centre)));
+        assert!(snapshots.iter().skip(1).any(|t| t.cameras[&0].fps > 0.0));
+        assert!(snapshots.iter().all(|t| t.cameras[&0].frames_captured <= stats.frames_captured()));
+        fs::remove_dir_all(image_path).unwrap();
+    }
+
+    #[test]
+    /// The frame rate is the frames captured since the previous snapshot
+    /// over the time between them, zero for the first snapshot.
+    fn test_camera_telemetry() {
+        let stats = |frames_captured, frames_late| CameraArrayStats {
+            crop_bed: CropBed::Centre,
+            cameras: BTreeMap::from([(
+                2,
+                CameraStatsSnapshot {
+                    frames_captured,
+                    frames_late,
+                    ..CameraStatsSnapshot::default()
+                },
+            )]),
+            disabled_cameras: Vec::new(),
+            degraded: false,
+            images_written: frames_captured,
+            write_failures: 3,
+            images_dropped: 1,
+            frames_skipped: 0,
+            files_pruned: 0,
+            camera_write_failures: BTreeMap::from([(2, 3)]),
+            threads_panicked: 0,
+        };
+        let uuid = Uuid::new_v4();
+        let (before, after) = (stats(100, 0), stats(130, 2));
+        let first = before.telemetry(uuid, None);
+        assert!(first.cameras[&2].fps.abs() < f64::EPSILON);
+        let telemetry = after.telemetry(uuid, Some((&before, Duration::from_secs(2))));
+        let camera = telemetry.cameras[&2];
+        assert!((camera.fps - 15.0).abs() < f64::EPSILON);
+        assert_eq!((camera.frames_captured, camera.dropped_frames), (130, 2));
+        assert_eq!((telemetry.write_failures, telemetry.images_dropped), (3, 1));
+    }
+
+    #[test]
+    /// Simulated cameras, the restart policy and writer count are optional
+    /// and should survive a round trip through yaml.
+    fn test_restart_policy_config_round_trip() {
+        let config = CameraArrayConfig::new(String::from("./images"), 0)
+            .add_simulated_camera(SimulatedCameraConfig::new(Some(0), 10, 64, 48), 0)
+            .with_restart_policy(RestartPolicy::default())
+            .with_writer_threads(2)
+            .with_async_writer(AsyncWriterConfig::default());
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        let read_config: CameraArrayConfig = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(config, read_config);
+    }
+
+    #[test]
+    /// File and inline camera sources can be mixed and should survive a
+    /// round trip through yaml.
+    fn test_camera_source_round_trip() {
+        let inline = OnyxCameraConfig::new([192, 168, 0, 10], 10);
+        let config = CameraArrayConfig::new(String::from("./images"), 0)
+            .add_camera_config_file("./config/devices/crop_bed/camera_0.yaml", 0)
+            .add_camera_config(inline.clone(), 1);
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        let read_config: CameraArrayConfig = serde_yaml::from_str(&yaml).unwrap();
+
+        assert_eq!(config, read_config);
+        assert_eq!(
+            read_config.camera_config_files[&0].source(),
+            &CameraSource::File(PathBuf::from("./config/devices/crop_bed/camera_0.yaml"))
+        );
+        assert_eq!(
+            read_config.camera_config_files[&1].source(),
+            &CameraSource::Inline(inline)
+        );
+    }
+
+    #[test]
+    /// Relative paths resolve against the array config directory, while
+    /// absolute paths, inline configs and legacy working directory paths
+    /// are left as they are.
+    fn test_camera_source_resolution() {
+        let base_dir = std::env::temp_dir().join(format!("onyx-sources-{}", Uuid::new_v4()));
+        fs::create_dir_all(base_dir.join("cameras")).unwrap();
+        fs::write(base_dir.join("cameras/camera_0.yaml"), "").unwrap();
+        let inline = OnyxCameraConfig::new([192, 168, 0, 10], 10);
+
+        let config = CameraArrayConfig::new(String::from("./images"), 0)
+            .add_camera_config_file("cameras/camera_0.yaml", 0)
+            .add_camera_config_file("/etc/onyx/camera_1.yaml", 1)
+            .add_camera_config(inline.clone(), 2)
+            .add_camera_config_file("Cargo.toml", 3)
+            .relative_to(&base_dir);
+
+        assert_eq!(
+            config.camera_config_files[&0].source(),
+            &CameraSource::File(base_dir.join("cameras/camera_0.yaml"))
+        );
+        assert_eq!(
+            config.camera_config_files[&1].source(),
+            &CameraSource::File(PathBuf::from("/etc/onyx/camera_1.yaml"))
+        );
+        assert_eq!(
+            config.camera_config_files[&2].source(),
+            &CameraSource::Inline(inline)
+        );
+        assert_eq!(
+            config.camera_config_files[&3].source(),
+            &CameraSource::File(PathBuf::from("Cargo.toml"))
+        );
+        fs::remove_dir_all(base_dir).unwrap();
+    }
+
+    #[test]
+    /// Disabled cameras keep their source through a round trip, and plain
+    /// entries are still written without the detailed form.
+    fn test_camera_entry_round_trip() {
+        let config = CameraArrayConfig::new(String::from("./images"), 0)
+            .add_camera_config_file("./config/devices/crop_bed/camera_0.yaml", 0)
+            .add_camera_config_file("./config/devices/crop_bed/camera_1.yaml", 1)
+            .disable_camera(1);
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert_eq!(yaml.matches("enabled: false").count(), 1);
+        assert_eq!(yaml.matches("source:").count(), 1);
+
+        let read_config: CameraArrayConfig = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(config, read_config);
+        assert!(read_config.camera_config_files[&0].enabled());
+        assert!(!read_config.camera_config_files[&1].enabled());
+    }
+
+    #[test]
+    #[serial]
+    /// An array with a disabled camera starts the remaining cameras and
+    /// reports the gap as degraded.
+    fn test_camera_array_partial_start_up() {
+        let image_path = std::env::temp_dir().join(format!("onyx-partial-{}", Uuid::new_v4()));
+        // The disabled camera is never read, so its file does not need to exist.
+        let config = CameraArrayConfig::new(image_path.to_string_lossy().into_owned(), 0)
+            .add_camera_config_file("./config/devices/crop_bed/missing_camera.yaml", 0)
+            .disable_camera(0)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 1)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 2);
+        let camera_array = CameraArray::new(config).unwrap();
+        assert_eq!(camera_array.cameras.len(), 2);
+        assert_eq!(camera_array.disabled_cameras(), &[0]);
+
+        let handle = CameraArrayController::start(camera_array).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        let stats = handle.stop();
+
+        assert_eq!(stats.cameras.keys().copied().collect::<Vec<u8>>(), vec![1, 2]);
+        assert_eq!(stats.disabled_cameras, vec![0]);
+        assert!(stats.degraded);
+        assert!(stats.to_string().contains("left_boom_bed_location_0: disabled"));
+        fs::remove_dir_all(image_path).unwrap();
+    }
+
+    #[test]
+    /// A camera whose config file is missing refuses the array as a config
+    /// error, and a capture directory that cannot be created refuses to
+    /// start it.
+    fn test_camera_array_errors() {
+        let config = CameraArrayConfig::new(String::from("images"), 0)
+            .add_camera_config_file("./config/devices/crop_bed/missing_camera.yaml", 0);
+        let error = CameraArray::new(config)
+            .err()
+            .expect("Missing camera config was accepted");
+        assert!(error.is_config(), "{error}");
+        assert!(error.to_string().contains("missing_camera.yaml"), "{error}");
+
+        let blocking_file = std::env::temp_dir().join(format!("onyx-blocked-{}", Uuid::new_v4()));
+        fs::write(&blocking_file, b"").unwrap();
+        let config = CameraArrayConfig::new(blocking_file.join("images").to_string_lossy().into_owned(), 0)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 0);
+        let error = CameraArrayController::start(CameraArray::new(config).unwrap())
+            .err()
+            .expect("Capture directory under a file was accepted");
+        assert!(!error.is_config(), "{error}");
+        assert!(error.to_string().starts_with("Failed to create filepath: "), "{error}");
+        fs::remove_file(blocking_file).unwrap();
+    }
+
     #[test]
     /// Review how hash maps are serialised to yaml with serde.
     fn test_serde_hashmap_camera_configs() {
@@ -357,7 +2682,7 @@ mod tests {
             "{}/config/components/crop_bed/sensing/camera_array/crop_bed_array_test.yaml",
             env!("CARGO_MANIFEST_DIR")
         );
-        let camera_array = CameraArray::from_config_file(config_file);
+        let camera_array = CameraArray::from_config_file(config_file).unwrap();
         assert!(camera_array.cameras.len() == 1);
     }
 
@@ -371,25 +2696,14 @@ mod tests {
             "{}/config/components/crop_bed/sensing/camera_array/crop_bed_array_test.yaml",
             env!("CARGO_MANIFEST_DIR")
         );
-        let mut camera_array = CameraArray::from_config_file(config_file);
+        let mut camera_array = CameraArray::from_config_file(config_file).unwrap();
         camera_array.image_path = String::from("./test-outputs/component-tests/camera_array");
 
-        let (handles, stop_signal) = CameraArrayController::start(camera_array);
+        let handle = CameraArrayController::start(camera_array).unwrap();
         thread::sleep(Duration::from_secs(5));
 
-        stop_signal.store(true, std::sync::atomic::Ordering::Relaxed);
-
-        let mut image_writers = handles
-            .join()
-            .expect("Unable to return image writer thread");
-
-        for image_writer in image_writers.drain() {
-            image_writer
-                .join()
-                .expect("Failed to shut down image writer.");
-        }
-
-        thread::sleep(Duration::from_secs(1));
+        let stats = handle.stop();
+        assert_eq!(stats.write_failures, 0, "Failed to write images {stats}");
 
         let total_images = std::fs::read_dir(format!(
             "{}/test-outputs/component-tests/camera_array/0/0",
@@ -399,7 +2713,7 @@ mod tests {
         .count();
 
         assert!(
-            (50_usize.abs_diff(total_images)) < 5,
+            (50_usize.abs_diff(total_images)) < 2,
             "Failed to generate the correct number of images @ 10 FPS"
         );
     }
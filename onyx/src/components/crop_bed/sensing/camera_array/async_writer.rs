@@ -0,0 +1,221 @@
+use super::{retention::PARTIAL_SUFFIX, WriterStats};
+use crate::{devices::hardware::camera::DevicePayload, utils::metrics};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, mpsc::Receiver, Arc},
+    thread,
+};
+use tokio::{
+    fs,
+    io::{AsyncWriteExt, BufWriter},
+    sync::{
+        mpsc::{self, error::TrySendError},
+        Semaphore,
+    },
+};
+use tracing::warn;
+
+/// Settings for the tokio image writer, used in place of the pool of
+/// writer threads when set.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AsyncWriterConfig {
+    /// Images that may wait to be written before new frames are dropped,
+    /// this bounds the memory held by the writer.
+    pub queue_depth: usize,
+    /// Images encoded and written at the same time.
+    pub max_concurrent_writes: u32,
+}
+
+impl Default for AsyncWriterConfig {
+    fn default() -> Self {
+        Self {
+            queue_depth: 64,
+            max_concurrent_writes: 8,
+        }
+    }
+}
+
+/// Save payloads to disk on a tokio runtime until every camera has dropped
+/// its sender. The capture threads stay synchronous, a bridge thread moves
+/// payloads onto a bounded tokio channel and counts the frames dropped when
+/// the writer falls behind.
+///
+/// * `receiver`: channel the cameras send payloads on.
+/// * `path`: parent directory for the images.
+/// * `write_sidecar`: also save the payload metadata as json.
+/// * `config`: queue depth and write concurrency.
+/// * `stats`: counters for the writer.
+pub(super) fn write_images(
+    receiver: Receiver<DevicePayload>,
+    path: PathBuf,
+    write_sidecar: bool,
+    config: AsyncWriterConfig,
+    stats: Arc<WriterStats>,
+) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to build image writer runtime");
+    let (payload_tx, payload_rx) = mpsc::channel(config.queue_depth.max(1));
+
+    let bridge_stats = stats.clone();
+    let bridge = thread::spawn(move || {
+        for payload in receiver {
+            match payload_tx.try_send(payload) {
+                Ok(()) => {}
+                Err(TrySendError::Full(payload)) => {
+                    bridge_stats.images_dropped.fetch_add(1, Ordering::Relaxed);
+                    metrics::frame_dropped(&payload.camera_label(), "queue_full");
+                }
+                Err(TrySendError::Closed(_)) => break,
+            }
+        }
+    });
+
+    runtime.block_on(drain(
+        payload_rx,
+        Arc::new(path),
+        write_sidecar,
+        config.max_concurrent_writes.max(1),
+        stats,
+    ));
+    bridge.join().expect("Image writer bridge panicked");
+}
+
+/// Write payloads as they arrive with at most `max_concurrent_writes` in
+/// flight, returning once the channel has closed and every write finished.
+///
+/// * `payload_rx`: bounded channel fed by the bridge.
+/// * `path`: parent directory for the images.
+/// * `write_sidecar`: also save the payload metadata as json.
+/// * `max_concurrent_writes`: writes allowed in flight.
+/// * `stats`: counters for the writer.
+async fn drain(
+    mut payload_rx: mpsc::Receiver<DevicePayload>,
+    path: Arc<PathBuf>,
+    write_sidecar: bool,
+    max_concurrent_writes: u32,
+    stats: Arc<WriterStats>,
+) {
+    let permits = Arc::new(Semaphore::new(max_concurrent_writes as usize));
+    while let Some(payload) = payload_rx.recv().await {
+        let permit = permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Image writer semaphore closed");
+        let path = path.clone();
+        let stats = stats.clone();
+        tokio::spawn(async move {
+            let filename = path.join(payload.filename());
+            let location_id = payload.location_id();
+            let result = save_payload(payload, &filename, write_sidecar).await;
+            if let Err(ref e) = result {
+                warn!("Failed to save image to path {:?} {e}", filename);
+            }
+            stats.record(location_id, result.is_ok());
+            drop(permit);
+        });
+    }
+    // Every permit is back once the last write has finished.
+    let _all_written = permits
+        .acquire_many(max_concurrent_writes)
+        .await
+        .expect("Image writer semaphore closed");
+}
+
+/// Encode a payload as png and save it, followed by its sidecar.
+///
+/// * `payload`: image to save.
+/// * `filename`: destination of the image.
+/// * `write_sidecar`: also save the payload metadata as json.
+async fn save_payload(
+    payload: DevicePayload,
+    filename: &Path,
+    write_sidecar: bool,
+) -> Result<(), String> {
+    let metadata = write_sidecar.then(|| payload.metadata());
+    // Encoding is CPU bound, keep it off the runtime workers.
+    let png = tokio::task::spawn_blocking(move || {
+        let mut png = Cursor::new(Vec::new());
+        payload
+            .image
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .map(|()| png.into_inner())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())?;
+    write_atomically(filename, &png).await?;
+
+    if let Some(metadata) = metadata {
+        let json = serde_json::to_vec_pretty(&metadata).map_err(|e| e.to_string())?;
+        write_atomically(&filename.with_extension("json"), &json).await?;
+    }
+    Ok(())
+}
+
+/// Write a file under its partial name and rename once complete, so the
+/// retention scan only ever sees finished files.
+///
+/// * `filename`: final destination.
+/// * `bytes`: file contents.
+async fn write_atomically(filename: &Path, bytes: &[u8]) -> Result<(), String> {
+    let partial = PathBuf::from(format!("{}{PARTIAL_SUFFIX}", filename.display()));
+    let file = fs::File::create(&partial).await.map_err(|e| e.to_string())?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(bytes).await.map_err(|e| e.to_string())?;
+    writer.flush().await.map_err(|e| e.to_string())?;
+    fs::rename(&partial, filename)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        components::crop_bed::sensing::camera_array::{
+            CameraArray, CameraArrayConfig, CameraArrayController,
+        },
+        devices::software::camera::SimulatedCameraConfig,
+    };
+    use serial_test::serial;
+    use std::time::{Duration, Instant};
+    use uuid::Uuid;
+
+    #[test]
+    #[serial]
+    /// Six cameras at 10 FPS should be written in full by the async writer
+    /// without the bounded queue overflowing.
+    #[allow(clippy::cast_precision_loss)]
+    fn test_async_writer_sustains_six_cameras() {
+        let image_path = std::env::temp_dir().join(format!("onyx-async-writer-{}", Uuid::new_v4()));
+        let mut config = CameraArrayConfig::new(image_path.to_string_lossy().into_owned(), 0)
+            .with_async_writer(AsyncWriterConfig::default());
+        for bed_position in 0..6 {
+            config = config
+                .add_simulated_camera(SimulatedCameraConfig::new(None, 10, 320, 240), bed_position);
+        }
+        let run_time = Duration::from_secs(3);
+        let start = Instant::now();
+        let handle = CameraArrayController::start(CameraArray::new(config).unwrap()).unwrap();
+        std::thread::sleep(run_time);
+        let stats = handle.stop();
+        let elapsed = start.elapsed();
+
+        println!(
+            "{} images in {:?}, {:.1} images/s",
+            stats.images_written,
+            elapsed,
+            stats.images_written as f64 / elapsed.as_secs_f64()
+        );
+        assert_eq!(stats.write_failures, 0, "{stats}");
+        assert_eq!(stats.images_dropped, 0, "Writer fell behind {stats}");
+        assert_eq!(stats.images_written, stats.frames_captured(), "{stats}");
+        assert!(stats.images_written >= 6 * 10 * 2, "Throughput too low {stats}");
+        std::fs::remove_dir_all(image_path).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+use super::WriterStats;
+use crate::{
+    devices::hardware::camera::DevicePayload,
+    utils::image::{dhash, hamming_distance},
+};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::Ordering,
+        mpsc::{Receiver, Sender},
+        Mutex,
+    },
+};
+
+/// Bits a frame may differ from the last saved frame of its camera by and
+/// still be skipped, when not set.
+pub const DEFAULT_DEDUPE_DISTANCE: u32 = 4;
+
+/// Skip saving frames that look the same as the last frame saved for their
+/// camera, e.g. while the machine is stopped at a headland.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DedupeConfig {
+    /// Bits the difference hash of a frame may differ from the last saved
+    /// frame of its camera by and still be skipped, 0 only skipping frames
+    /// that hash the same.
+    pub max_distance: u32,
+}
+
+impl Default for DedupeConfig {
+    fn default() -> Self {
+        Self {
+            max_distance: DEFAULT_DEDUPE_DISTANCE,
+        }
+    }
+}
+
+/// Hash of the last frame saved for each camera, shared between the tap on
+/// the payload channel and whatever resets it.
+#[derive(Debug)]
+pub struct FrameDedupe {
+    /// How close a frame must be to be skipped.
+    config: DedupeConfig,
+    /// Hash of the last frame saved keyed by bed position.
+    last_saved: Mutex<HashMap<u8, u64>>,
+}
+
+impl FrameDedupe {
+    /// Filter that has not seen a frame yet.
+    ///
+    /// * `config`: how close a frame must be to be skipped.
+    pub fn new(config: DedupeConfig) -> Self {
+        Self {
+            config,
+            last_saved: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// How close a frame must be to be skipped.
+    pub fn config(&self) -> DedupeConfig {
+        self.config
+    }
+
+    /// Whether a frame is close enough to the last frame saved for its
+    /// camera to be skipped. A frame that is not becomes the one later
+    /// frames of the camera are compared with.
+    ///
+    /// * `bed_position`: position of the camera that took the image.
+    /// * `image`: full size capture.
+    pub fn is_duplicate(&self, bed_position: u8, image: &DynamicImage) -> bool {
+        // Hash outside of the lock so the cameras are not held up on each
+        // other.
+        let hash = dhash(image);
+        let mut last_saved = self.last_saved.lock().expect("Frame dedupe poisoned");
+        match last_saved.get(&bed_position) {
+            Some(last) if hamming_distance(*last, hash) <= self.config.max_distance => true,
+            _ => {
+                last_saved.insert(bed_position, hash);
+                false
+            }
+        }
+    }
+
+    /// Forget the last frame saved for a camera, so its next frame is saved.
+    ///
+    /// * `bed_position`: position of the camera.
+    pub fn reset(&self, bed_position: u8) {
+        self.last_saved
+            .lock()
+            .expect("Frame dedupe poisoned")
+            .remove(&bed_position);
+    }
+
+    /// Forget the last frame saved for every camera.
+    pub fn reset_all(&self) {
+        self.last_saved.lock().expect("Frame dedupe poisoned").clear();
+    }
+}
+
+/// Forward the payloads from the cameras to the image writers, counting
+/// and dropping those that look the same as the last saved frame of their
+/// camera. Returns once every camera sender has been dropped or the sink
+/// has gone.
+///
+/// * `receiver`: channel the cameras send payloads on.
+/// * `sink`: channel read by the image writers.
+/// * `dedupe`: hash of the last frame saved for each camera.
+/// * `stats`: counters of the image writer.
+pub(super) fn tap_payloads(
+    receiver: Receiver<DevicePayload>,
+    sink: &Sender<DevicePayload>,
+    dedupe: &FrameDedupe,
+    stats: &WriterStats,
+) {
+    for payload in receiver {
+        if let Some(bed_position) = payload.location_id() {
+            if dedupe.is_duplicate(bed_position, &payload.image) {
+                stats.frames_skipped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+        }
+        if sink.send(payload).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GrayImage;
+
+    /// Horizontal gradient, rising left to right or falling.
+    ///
+    /// * `rising`: brighter to the right.
+    fn gradient(rising: bool) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(64, 32, |x, _| {
+            let value = u8::try_from(x * 4).unwrap();
+            image::Luma([if rising { value } else { 255 - value }])
+        }))
+    }
+
+    #[test]
+    /// Frames are compared with the last frame saved for their own camera,
+    /// until the camera is reset.
+    fn test_frame_dedupe_per_camera() {
+        let dedupe = FrameDedupe::new(DedupeConfig::default());
+        assert!(!dedupe.is_duplicate(0, &gradient(true)));
+        assert!(dedupe.is_duplicate(0, &gradient(true)));
+        assert!(!dedupe.is_duplicate(1, &gradient(true)), "Compared across cameras");
+        assert!(!dedupe.is_duplicate(0, &gradient(false)));
+        assert!(dedupe.is_duplicate(0, &gradient(false)));
+
+        dedupe.reset(0);
+        assert!(!dedupe.is_duplicate(0, &gradient(false)));
+        assert!(dedupe.is_duplicate(1, &gradient(true)));
+        dedupe.reset_all();
+        assert!(!dedupe.is_duplicate(1, &gradient(true)));
+    }
+
+    #[test]
+    /// Only frames that hash the same are skipped without a distance.
+    fn test_frame_dedupe_distance() {
+        let exact = FrameDedupe::new(DedupeConfig { max_distance: 0 });
+        let anything = FrameDedupe::new(DedupeConfig { max_distance: 64 });
+        for dedupe in [&exact, &anything] {
+            assert!(!dedupe.is_duplicate(0, &gradient(true)));
+        }
+        assert!(!exact.is_duplicate(0, &gradient(false)));
+        assert!(anything.is_duplicate(0, &gradient(false)));
+    }
+}
@@ -0,0 +1,324 @@
+use super::{preview::PreviewFrames, CameraArrayConfig, CameraArrayMonitor, CameraArrayStats};
+use axum::{
+    body::StreamBody,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use std::sync::Arc;
+use std::{
+    io,
+    net::TcpListener,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use tracing::warn;
+
+/// How often the server checks whether the array has been stopped.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
+/// Build the routes served to the HMI.
+///
+/// * `monitor`: view of the running array.
+pub fn router(monitor: CameraArrayMonitor) -> Router {
+    Router::new()
+        .route("/status", get(status))
+        .route("/healthz", get(healthz))
+        .route("/config", get(config))
+        .route("/stop", post(stop))
+        .route("/shutdown", post(stop))
+        .route("/cameras/:bed_position/restart", post(restart_camera))
+        .route("/preview/:bed_position", get(preview))
+        .with_state(monitor)
+}
+
+/// `GET /status`, statistics for every camera and the writer.
+async fn status(State(monitor): State<CameraArrayMonitor>) -> Json<CameraArrayStats> {
+    Json(monitor.stats())
+}
+
+/// `GET /healthz`, container liveness. Reports unavailable once the array
+/// is shutting down so the orchestrator does not route to it.
+async fn healthz(State(monitor): State<CameraArrayMonitor>) -> StatusCode {
+    if monitor.is_stopping() {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    }
+}
+
+/// `GET /config`, the config the array was built from.
+async fn config(State(monitor): State<CameraArrayMonitor>) -> Json<CameraArrayConfig> {
+    Json(monitor.config().clone())
+}
+
+/// `POST /shutdown` or `POST /stop`, start a graceful shutdown of the
+/// array.
+async fn stop(State(monitor): State<CameraArrayMonitor>) -> StatusCode {
+    monitor.request_stop();
+    StatusCode::ACCEPTED
+}
+
+/// `POST /cameras/{bed_position}/restart`, rebuild a camera on the next
+/// pass of the supervisor. Not found when there is no camera at the bed
+/// position.
+async fn restart_camera(State(monitor): State<CameraArrayMonitor>, Path(bed_position): Path<u8>) -> StatusCode {
+    if monitor.request_restart(bed_position) {
+        StatusCode::ACCEPTED
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Boundary between the jpeg parts of the preview stream.
+const PREVIEW_BOUNDARY: &str = "frame";
+
+/// `GET /preview/{bed_position}`, MJPEG stream of the latest frames from a
+/// camera at the preview rate. Not found when the preview is disabled or
+/// there is no camera at the bed position.
+async fn preview(
+    State(monitor): State<CameraArrayMonitor>,
+    Path(bed_position): Path<u8>,
+) -> Response {
+    let Some(frames) = monitor.preview() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !monitor.has_camera(bed_position) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let parts = futures::stream::unfold(
+        (monitor, frames, None),
+        move |(monitor, frames, last_sent)| next_preview_part(bed_position, monitor, frames, last_sent),
+    );
+    (
+        [(
+            header::CONTENT_TYPE,
+            format!("multipart/x-mixed-replace; boundary={PREVIEW_BOUNDARY}"),
+        )],
+        StreamBody::new(parts),
+    )
+        .into_response()
+}
+
+/// Wait for a preview frame that has not been sent yet and encode it as a
+/// part of the MJPEG stream. Ends the stream once the array is stopping.
+///
+/// * `bed_position`: position of the camera being streamed.
+/// * `monitor`: view of the running array.
+/// * `frames`: latest frame of each camera.
+/// * `last_sent`: sequence of the last frame sent on this stream.
+#[allow(clippy::type_complexity)]
+async fn next_preview_part(
+    bed_position: u8,
+    monitor: CameraArrayMonitor,
+    frames: Arc<PreviewFrames>,
+    mut last_sent: Option<u64>,
+) -> Option<(io::Result<Vec<u8>>, (CameraArrayMonitor, Arc<PreviewFrames>, Option<u64>))> {
+    let config = frames.config();
+    while !monitor.is_stopping() {
+        match frames.latest(bed_position) {
+            Some(frame) if Some(frame.sequence) != last_sent => {
+                last_sent = Some(frame.sequence);
+                // Encoding is CPU bound, keep it off the runtime.
+                match tokio::task::spawn_blocking(move || frame.to_jpeg(config.jpeg_quality)).await {
+                    Ok(Ok(jpeg)) => {
+                        let mut part = format!(
+                            "--{PREVIEW_BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                            jpeg.len()
+                        )
+                        .into_bytes();
+                        part.extend_from_slice(&jpeg);
+                        part.extend_from_slice(b"\r\n");
+                        return Some((Ok(part), (monitor, frames, last_sent)));
+                    }
+                    Ok(Err(e)) => warn!("Failed to encode preview frame {e}"),
+                    Err(e) => warn!("Preview encoder panicked {e}"),
+                }
+            }
+            _ => tokio::time::sleep(config.interval()).await,
+        }
+    }
+    None
+}
+
+/// Serve the status routes until the array is stopped, either through
+/// `POST /shutdown` or by the owner of the array handle.
+///
+/// * `listener`: bound listener, use port 0 in tests.
+/// * `monitor`: view of the running array.
+pub async fn serve(listener: TcpListener, monitor: CameraArrayMonitor) -> io::Result<()> {
+    listener.set_nonblocking(true)?;
+    let shutdown_monitor = monitor.clone();
+    axum::Server::from_tcp(listener)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        .serve(router(monitor).into_make_service())
+        .with_graceful_shutdown(async move {
+            while !shutdown_monitor.is_stopping() {
+                tokio::time::sleep(SHUTDOWN_POLL).await;
+            }
+        })
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+/// Run [`serve`] on its own runtime in a new thread, so synchronous
+/// binaries can serve the status alongside the camera threads.
+///
+/// * `listener`: bound listener.
+/// * `monitor`: view of the running array.
+pub fn spawn(listener: TcpListener, monitor: CameraArrayMonitor) -> JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(serve(listener, monitor))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        components::crop_bed::sensing::camera_array::{
+            preview::PreviewConfig, CameraArray, CameraArrayConfig, CameraArrayController,
+        },
+        devices::software::camera::SimulatedCameraConfig,
+    };
+    use serial_test::serial;
+    use uuid::Uuid;
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[serial]
+    /// Drive the status routes against a simulated array and stop it
+    /// through the HMI endpoint.
+    async fn test_status_server_against_simulated_array() {
+        let image_path = std::env::temp_dir().join(format!("onyx-http-{}", Uuid::new_v4()));
+        let config = CameraArrayConfig::new(image_path.to_string_lossy().into_owned(), 0)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 0);
+        let handle = CameraArrayController::start(CameraArray::new(config).unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = spawn(listener, handle.monitor());
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let client = reqwest::Client::new();
+        let health = client.get(format!("{url}/healthz")).send().await.unwrap();
+        assert_eq!(health.status(), reqwest::StatusCode::OK);
+
+        let stats: CameraArrayStats = client
+            .get(format!("{url}/status"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(stats.cameras[&0].frames_captured > 0, "No frames reported {stats}");
+
+        let stopped = client.post(format!("{url}/stop")).send().await.unwrap();
+        assert_eq!(stopped.status(), reqwest::StatusCode::ACCEPTED);
+
+        let stats = tokio::task::spawn_blocking(move || handle.wait()).await.unwrap();
+        assert_eq!(stats.write_failures, 0);
+        server.join().unwrap().unwrap();
+        std::fs::remove_dir_all(image_path).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[serial]
+    /// The preview should stream jpeg parts for a running camera and not
+    /// found for a bed position without one.
+    async fn test_preview_stream_against_simulated_array() {
+        let image_path = std::env::temp_dir().join(format!("onyx-preview-{}", Uuid::new_v4()));
+        let config = CameraArrayConfig::new(image_path.to_string_lossy().into_owned(), 0)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 64, 48), 0)
+            .with_preview(PreviewConfig::default());
+        let handle = CameraArrayController::start(CameraArray::new(config).unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = spawn(listener, handle.monitor());
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let client = reqwest::Client::new();
+        let missing = client.get(format!("{url}/preview/5")).send().await.unwrap();
+        assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let mut stream = client.get(format!("{url}/preview/0")).send().await.unwrap();
+        assert_eq!(stream.status(), reqwest::StatusCode::OK);
+        let content_type = stream.headers()[reqwest::header::CONTENT_TYPE].to_str().unwrap();
+        assert!(content_type.starts_with("multipart/x-mixed-replace"), "{content_type}");
+        let mut body = Vec::new();
+        let jpeg_start = [0xFF, 0xD8];
+        while !body.windows(2).any(|window| window == jpeg_start) {
+            body.extend_from_slice(&stream.chunk().await.unwrap().expect("Stream ended early"));
+        }
+        assert!(body.starts_with(format!("--{PREVIEW_BOUNDARY}").as_bytes()));
+        drop(stream);
+
+        let stats = tokio::task::spawn_blocking(move || handle.stop()).await.unwrap();
+        assert_eq!(stats.write_failures, 0);
+        server.join().unwrap().unwrap();
+        std::fs::remove_dir_all(image_path).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[serial]
+    /// Hit every HMI route against a simulated array: read its config and
+    /// status, restart a camera and shut the array down.
+    async fn test_hmi_routes_against_simulated_array() {
+        let image_path = std::env::temp_dir().join(format!("onyx-hmi-{}", Uuid::new_v4()));
+        let config = CameraArrayConfig::new(image_path.to_string_lossy().into_owned(), 0)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 0)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 1);
+        let handle = CameraArrayController::start(CameraArray::new(config.clone()).unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = spawn(listener, handle.monitor());
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let client = reqwest::Client::new();
+        let served: CameraArrayConfig = client
+            .get(format!("{url}/config"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(served, config);
+
+        let missing = client.post(format!("{url}/cameras/7/restart")).send().await.unwrap();
+        assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+        let restarted = client.post(format!("{url}/cameras/1/restart")).send().await.unwrap();
+        assert_eq!(restarted.status(), reqwest::StatusCode::ACCEPTED);
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        let stats = loop {
+            let stats: CameraArrayStats = client
+                .get(format!("{url}/status"))
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            if stats.cameras[&1].restarts == 1 {
+                break stats;
+            }
+            assert!(std::time::Instant::now() < deadline, "Camera was not restarted {stats}");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        };
+        assert_eq!(stats.cameras[&0].restarts, 0);
+
+        let shutdown = client.post(format!("{url}/shutdown")).send().await.unwrap();
+        assert_eq!(shutdown.status(), reqwest::StatusCode::ACCEPTED);
+        let stats = tokio::task::spawn_blocking(move || handle.wait()).await.unwrap();
+        assert!(stats.cameras[&1].frames_captured > 0, "{stats}");
+        server.join().unwrap().unwrap();
+        std::fs::remove_dir_all(image_path).unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+use crate::{
+    devices::hardware::camera::DevicePayload,
+    utils::image::{annotate, downscale, Annotation},
+};
+use image::{DynamicImage, ImageResult};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    sync::{
+        mpsc::{Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Live preview of each camera, served to a browser on the farm.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreviewConfig {
+    /// Frames per second kept and streamed for each camera.
+    pub fps: u32,
+    /// Width preview frames are downscaled to.
+    pub max_width: u32,
+    /// Quality of the streamed jpeg frames, 1 to 100.
+    pub jpeg_quality: u8,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            fps: 2,
+            max_width: 640,
+            jpeg_quality: 70,
+        }
+    }
+}
+
+impl PreviewConfig {
+    /// Time between preview frames.
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(1000 / u64::from(self.fps.max(1)))
+    }
+}
+
+/// Columns of the frames of a camera a spray channel covers, drawn over
+/// its preview frames.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelZone {
+    /// Spray channel on the crop bed.
+    pub channel: u8,
+    /// First column covered, in pixels of the full size frame.
+    pub x0: u32,
+    /// Column one past the last covered.
+    pub x1: u32,
+}
+
+/// Most recent downscaled frame of a camera.
+#[derive(Debug, Clone)]
+pub struct PreviewFrame {
+    /// Count of preview frames kept for the camera, changes with every new
+    /// frame so streams can skip frames they have already sent.
+    pub sequence: u64,
+    /// Downscaled image.
+    pub image: Arc<DynamicImage>,
+    /// When the frame was kept.
+    updated_at: Instant,
+}
+
+impl PreviewFrame {
+    /// Encode the frame for streaming.
+    ///
+    /// * `quality`: jpeg quality, 1 to 100.
+    pub fn to_jpeg(&self, quality: u8) -> ImageResult<Vec<u8>> {
+        let mut jpeg = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(self.image.to_rgb8())
+            .write_to(&mut jpeg, image::ImageOutputFormat::Jpeg(quality))?;
+        Ok(jpeg.into_inner())
+    }
+}
+
+/// Latest preview frame for each bed position, shared between the tap on
+/// the payload channel and the preview server.
+#[derive(Debug)]
+pub struct PreviewFrames {
+    /// Rate and size of the preview.
+    config: PreviewConfig,
+    /// Latest frame keyed by bed position.
+    frames: Mutex<HashMap<u8, PreviewFrame>>,
+    /// Channel zones drawn over the frames of each bed position.
+    channel_zones: HashMap<u8, Vec<ChannelZone>>,
+}
+
+impl PreviewFrames {
+    /// Create an empty set of preview frames.
+    ///
+    /// * `config`: rate and size of the preview.
+    pub fn new(config: PreviewConfig) -> Self {
+        Self {
+            config,
+            frames: Mutex::new(HashMap::new()),
+            channel_zones: HashMap::new(),
+        }
+    }
+
+    /// Draw the columns each spray channel covers over the frames kept.
+    ///
+    /// * `channel_zones`: zones of each camera keyed by bed position.
+    pub fn with_channel_zones(mut self, channel_zones: HashMap<u8, Vec<ChannelZone>>) -> Self {
+        self.channel_zones = channel_zones;
+        self
+    }
+
+    /// Draw the channel zones of a camera over a downscaled frame, scaled
+    /// from the width of the full size capture.
+    ///
+    /// * `bed_position`: position of the camera that took the image.
+    /// * `full_width`: width of the full size capture.
+    /// * `frame`: downscaled frame.
+    fn overlay_zones(&self, bed_position: u8, full_width: u32, frame: DynamicImage) -> DynamicImage {
+        let Some(zones) = self.channel_zones.get(&bed_position).filter(|zones| !zones.is_empty()) else {
+            return frame;
+        };
+        let scale = |x: u32| {
+            u32::try_from(u64::from(x) * u64::from(frame.width()) / u64::from(full_width.max(1))).unwrap_or(u32::MAX)
+        };
+        let bands: Vec<Annotation> = zones
+            .iter()
+            .map(|zone| Annotation::VerticalBand(scale(zone.x0), scale(zone.x1), zone.channel.to_string()))
+            .collect();
+        annotate(&frame, &bands)
+    }
+
+    /// Rate and size of the preview.
+    pub fn config(&self) -> PreviewConfig {
+        self.config
+    }
+
+    /// Offer a captured image. It is only downscaled and kept if the
+    /// previous frame for the bed position is older than the preview
+    /// interval, otherwise it is ignored without being copied.
+    ///
+    /// * `bed_position`: position of the camera that took the image.
+    /// * `image`: full size capture.
+    pub fn offer(&self, bed_position: u8, image: &DynamicImage) {
+        let sequence = {
+            let frames = self.frames.lock().expect("Preview frames poisoned");
+            match frames.get(&bed_position) {
+                Some(frame) if frame.updated_at.elapsed() < self.config.interval() => return,
+                Some(frame) => frame.sequence + 1,
+                None => 0,
+            }
+        };
+        // Downscale outside of the lock so the server is never held up.
+        let frame = PreviewFrame {
+            sequence,
+            image: Arc::new(self.overlay_zones(bed_position, image.width(), downscale(image, self.config.max_width))),
+            updated_at: Instant::now(),
+        };
+        self.frames
+            .lock()
+            .expect("Preview frames poisoned")
+            .insert(bed_position, frame);
+    }
+
+    /// Latest frame for a bed position, if one has been captured.
+    ///
+    /// * `bed_position`: position of the camera.
+    pub fn latest(&self, bed_position: u8) -> Option<PreviewFrame> {
+        self.frames
+            .lock()
+            .expect("Preview frames poisoned")
+            .get(&bed_position)
+            .cloned()
+    }
+}
+
+/// Forward every payload from the cameras to the primary sink, offering
+/// each one to the preview on the way. Returns once every camera sender
+/// has been dropped or the sink has gone.
+///
+/// * `receiver`: channel the cameras send payloads on.
+/// * `sink`: channel read by the image writers.
+/// * `preview`: latest frame for each bed position.
+pub(super) fn tap_payloads(
+    receiver: Receiver<DevicePayload>,
+    sink: &Sender<DevicePayload>,
+    preview: &PreviewFrames,
+) {
+    for payload in receiver {
+        if let Some(bed_position) = payload.location_id() {
+            preview.offer(bed_position, &payload.image);
+        }
+        if sink.send(payload).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::image::BAND_COLOUR;
+    use image::{Rgb, RgbImage};
+    use std::thread;
+
+    #[test]
+    /// Preview frames are downscaled and only kept once per interval.
+    fn test_preview_frames_rate_limit_and_downscale() {
+        let preview = PreviewFrames::new(PreviewConfig {
+            fps: 10,
+            max_width: 32,
+            jpeg_quality: 70,
+        });
+        let image = DynamicImage::ImageRgb8(RgbImage::new(128, 64));
+        assert!(preview.latest(0).is_none());
+
+        preview.offer(0, &image);
+        preview.offer(0, &image);
+        let frame = preview.latest(0).expect("No preview frame kept");
+        assert_eq!(frame.sequence, 0, "Intermediate frame was kept");
+        assert_eq!((frame.image.width(), frame.image.height()), (32, 16));
+
+        thread::sleep(preview.config().interval());
+        preview.offer(0, &image);
+        assert_eq!(preview.latest(0).unwrap().sequence, 1);
+        assert!(preview.latest(1).is_none());
+
+        let jpeg = frame.to_jpeg(70).unwrap();
+        assert_eq!(&jpeg[..2], &[0xFF, 0xD8], "Not a jpeg");
+    }
+
+    #[test]
+    /// Channel zones are scaled to the preview and drawn over the frames of
+    /// their camera only.
+    fn test_preview_channel_zones() {
+        let preview = PreviewFrames::new(PreviewConfig {
+            fps: 10,
+            max_width: 32,
+            jpeg_quality: 70,
+        })
+        .with_channel_zones(HashMap::from([(
+            0,
+            vec![
+                ChannelZone { channel: 1, x0: 0, x1: 64 },
+                ChannelZone { channel: 2, x0: 64, x1: 128 },
+            ],
+        )]));
+        let image = DynamicImage::ImageRgb8(RgbImage::new(128, 64));
+        preview.offer(0, &image);
+        preview.offer(1, &image);
+
+        let zoned = preview.latest(0).unwrap().image.to_rgb8();
+        assert_eq!((zoned.width(), zoned.height()), (32, 16));
+        for x in [0, 15, 16, 31] {
+            assert_eq!(*zoned.get_pixel(x, 12), BAND_COLOUR, "No band edge at {x}");
+        }
+        assert_eq!(*zoned.get_pixel(8, 12), Rgb([0, 0, 0]));
+        let plain = preview.latest(1).unwrap().image.to_rgb8();
+        assert!(plain.pixels().all(|pixel| *pixel == Rgb([0, 0, 0])));
+    }
+}
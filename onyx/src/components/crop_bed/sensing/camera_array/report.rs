@@ -0,0 +1,148 @@
+use super::{CameraArrayMonitor, CameraArrayStats, DevicePosition};
+use std::{
+    path::PathBuf,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+/// How often the reporter checks whether the array has been asked to stop
+/// while waiting for the next report.
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+/// Free space in gibibytes to one decimal place.
+///
+/// * `bytes`: free space in bytes.
+fn gibibytes(bytes: u64) -> String {
+    let mebibytes = u32::try_from(bytes >> 20).unwrap_or(u32::MAX);
+    format!("{:.1}GiB", f64::from(mebibytes) / 1024.0)
+}
+
+/// One line summary of the array: frames captured, frame rate, late
+/// frames and write failures of each camera, then the totals of the array
+/// with the images dropped and the free space on the capture directory.
+///
+/// * `stats`: snapshot of the array.
+/// * `previous`: earlier snapshot and the time since it was taken, the
+///   frame rates are zero without one.
+/// * `free_bytes`: free space on the capture directory, `None` when it
+///   could not be read.
+pub fn stats_line(
+    stats: &CameraArrayStats,
+    previous: Option<(&CameraArrayStats, Duration)>,
+    free_bytes: Option<u64>,
+) -> String {
+    let mut parts = Vec::new();
+    let mut total_fps = 0.0;
+    let mut total_late = 0;
+    for (bed_position, camera) in &stats.cameras {
+        let fps = stats.fps(*bed_position, previous);
+        total_fps += fps;
+        total_late += camera.frames_late;
+        parts.push(format!(
+            "{} captured {} at {fps:.1}fps, late {}, write failures {}",
+            DevicePosition::BedPosition(stats.crop_bed, *bed_position),
+            camera.frames_captured,
+            camera.frames_late,
+            stats
+                .camera_write_failures
+                .get(bed_position)
+                .copied()
+                .unwrap_or_default()
+        ));
+    }
+    parts.push(format!(
+        "total captured {} at {total_fps:.1}fps, late {total_late}, write failures {}, dropped {}, disk free {}",
+        stats.frames_captured(),
+        stats.write_failures,
+        stats.images_dropped,
+        free_bytes.map_or_else(|| String::from("unknown"), gibibytes)
+    ));
+    parts.join(" | ")
+}
+
+/// Log the statistics of the array at an interval until it is asked to
+/// stop, the frame rates taken over each interval. The counters are read
+/// from the array rather than from the capture directory.
+///
+/// * `monitor`: view of the running array.
+/// * `interval`: time between the reports.
+pub fn spawn(monitor: CameraArrayMonitor, interval: Duration) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let image_path = PathBuf::from(&monitor.config().image_path);
+        let mut previous: Option<(Instant, CameraArrayStats)> = None;
+        let mut next_report = Instant::now() + interval;
+        while !monitor.is_stopping() {
+            let now = Instant::now();
+            if now < next_report {
+                thread::sleep(STOP_POLL.min(next_report - now));
+                continue;
+            }
+            next_report = now + interval;
+            let stats = monitor.stats();
+            let since = previous.as_ref().map(|(at, before)| (before, now - *at));
+            let free_bytes = fs2::available_space(&image_path).ok();
+            info!("Camera array stats {}", stats_line(&stats, since, free_bytes));
+            previous = Some((now, stats));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{devices::hardware::camera::CameraStatsSnapshot, utils::location::CropBed};
+    use std::collections::BTreeMap;
+
+    /// Snapshot of an array with two cameras.
+    ///
+    /// * `frames_captured`: frames captured by each camera.
+    fn snapshot(frames_captured: u64) -> CameraArrayStats {
+        let camera = CameraStatsSnapshot {
+            frames_captured,
+            frames_late: 1,
+            ..CameraStatsSnapshot::default()
+        };
+        CameraArrayStats {
+            crop_bed: CropBed::Centre,
+            cameras: BTreeMap::from([(0, camera.clone()), (1, camera)]),
+            disabled_cameras: Vec::new(),
+            degraded: false,
+            images_written: 2 * frames_captured,
+            write_failures: 2,
+            images_dropped: 3,
+            frames_skipped: 0,
+            files_pruned: 0,
+            camera_write_failures: BTreeMap::from([(1, 2)]),
+            threads_panicked: 0,
+        }
+    }
+
+    #[test]
+    /// Each camera and the totals fit on one line, the frame rates taken
+    /// over the time since the previous snapshot.
+    fn test_stats_line() {
+        let (before, after) = (snapshot(10), snapshot(30));
+        let line = stats_line(&after, Some((&before, Duration::from_secs(2))), Some(3 << 29));
+        assert!(!line.contains('\n'));
+        let parts: Vec<_> = line.split(" | ").collect();
+        assert_eq!(parts.len(), 3, "{line}");
+        assert!(
+            parts[0].ends_with("captured 30 at 10.0fps, late 1, write failures 0"),
+            "{line}"
+        );
+        assert!(
+            parts[1].ends_with("captured 30 at 10.0fps, late 1, write failures 2"),
+            "{line}"
+        );
+        assert_eq!(
+            parts[2],
+            "total captured 60 at 20.0fps, late 2, write failures 2, dropped 3, disk free 1.5GiB"
+        );
+        let first = stats_line(&before, None, None);
+        assert!(
+            first.ends_with("at 0.0fps, late 2, write failures 2, dropped 3, disk free unknown"),
+            "{first}"
+        );
+    }
+}
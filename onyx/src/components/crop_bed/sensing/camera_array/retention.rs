@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// Suffix of images that are still being written. Writers save to this
+/// name and rename once complete, so the retention scan never removes a
+/// file that is in flight.
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Limits applied to the capture directory so a full day of imaging does
+/// not fill the disk and leave every save failing.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Total size of images kept on disk before the oldest are removed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+    /// Age in seconds after which an image is removed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+    /// Free space required on the filesystem before the array will start.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_free_bytes: Option<u64>,
+    /// Time in seconds between scans of the capture directory.
+    pub scan_interval_secs: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_bytes: None,
+            max_age_secs: None,
+            min_free_bytes: None,
+            scan_interval_secs: 60,
+        }
+    }
+}
+
+/// Totals removed by a single retention scan.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PruneReport {
+    /// Number of images removed.
+    pub files: u64,
+    /// Bytes freed by removing them.
+    pub bytes: u64,
+}
+
+/// An image found during a scan.
+struct StoredImage {
+    /// Location of the image on disk.
+    path: PathBuf,
+    /// Last modification time, used as the capture time.
+    modified: SystemTime,
+    /// Size of the image in bytes.
+    len: u64,
+}
+
+impl RetentionPolicy {
+    /// Time between scans of the capture directory.
+    pub fn scan_interval(&self) -> Duration {
+        Duration::from_secs(self.scan_interval_secs.max(1))
+    }
+
+    /// Check the filesystem holding `path` has enough free space for the
+    /// array to start.
+    ///
+    /// * `path`: capture directory, must already exist.
+    pub fn check_free_space(&self, path: &Path) -> io::Result<()> {
+        let Some(min_free_bytes) = self.min_free_bytes else {
+            return Ok(());
+        };
+        let available = fs2::available_space(path)?;
+        if available < min_free_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "{} bytes free on {:?}, {} required",
+                    available, path, min_free_bytes
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Remove images older than `max_age_secs`, then the oldest images
+    /// until the total is within `max_bytes`. Images are stored flat in a
+    /// directory per bed position, so only files are removed and the
+    /// directories the writers expect are left in place.
+    ///
+    /// * `path`: capture directory.
+    pub fn prune(&self, path: &Path) -> io::Result<PruneReport> {
+        let mut images = Vec::new();
+        collect_images(path, &mut images)?;
+        images.sort_by_key(|image| image.modified);
+
+        let mut report = PruneReport::default();
+        let mut total_bytes: u64 = images.iter().map(|image| image.len).sum();
+        let now = SystemTime::now();
+
+        for image in images {
+            let expired = self.max_age_secs.is_some_and(|max_age_secs| {
+                now.duration_since(image.modified)
+                    .is_ok_and(|age| age > Duration::from_secs(max_age_secs))
+            });
+            let over_budget = self
+                .max_bytes
+                .is_some_and(|max_bytes| total_bytes > max_bytes);
+            // Sorted oldest first, so once an image is neither expired nor
+            // needed to get under budget none of the newer ones will be.
+            if !expired && !over_budget {
+                break;
+            }
+            match fs::remove_file(&image.path) {
+                Ok(()) => {
+                    report.files += 1;
+                    report.bytes += image.len;
+                    total_bytes -= image.len;
+                }
+                // Already gone, another process may be tidying up too.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    total_bytes -= image.len;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Recursively gather every complete image below `path`.
+///
+/// * `path`: directory to scan.
+/// * `images`: images found so far.
+fn collect_images(path: &Path, images: &mut Vec<StoredImage>) -> io::Result<()> {
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let entry_path = entry.path();
+        if metadata.is_dir() {
+            collect_images(&entry_path, images)?;
+        } else if metadata.is_file()
+            && !entry_path.to_string_lossy().ends_with(PARTIAL_SUFFIX)
+        {
+            images.push(StoredImage {
+                path: entry_path,
+                modified: metadata.modified()?,
+                len: metadata.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use uuid::Uuid;
+
+    /// Create a file of `len` bytes last modified `age` ago.
+    fn write_image(path: &Path, len: usize, age: Duration) {
+        fs::write(path, vec![0_u8; len]).expect("Failed to write image");
+        File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(SystemTime::now() - age))
+            .expect("Failed to set modified time");
+    }
+
+    /// Temporary capture directory with a bed position sub directory.
+    fn capture_dir() -> PathBuf {
+        let path = std::env::temp_dir().join(format!("onyx-retention-{}", Uuid::new_v4()));
+        fs::create_dir_all(path.join("0")).expect("Failed to create capture dir");
+        path
+    }
+
+    #[test]
+    /// The oldest images go first until the directory is within budget.
+    fn test_prune_oldest_beyond_max_bytes() {
+        let path = capture_dir();
+        for (idx, age) in [30, 20, 10].into_iter().enumerate() {
+            write_image(&path.join(format!("0/{idx}.png")), 100, Duration::from_secs(age));
+        }
+        let policy = RetentionPolicy {
+            max_bytes: Some(150),
+            ..RetentionPolicy::default()
+        };
+        let report = policy.prune(&path).unwrap();
+
+        assert_eq!(report, PruneReport { files: 2, bytes: 200 });
+        assert!(path.join("0/2.png").exists(), "Newest image was removed");
+        assert!(path.join("0").is_dir(), "Bed position directory was removed");
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    /// Images past their age are removed and in flight writes are left alone.
+    fn test_prune_expired_and_skip_partial() {
+        let path = capture_dir();
+        write_image(&path.join("0/old.png"), 10, Duration::from_secs(600));
+        write_image(&path.join("0/new.png"), 10, Duration::from_secs(0));
+        write_image(
+            &path.join(format!("0/writing.png{PARTIAL_SUFFIX}")),
+            10,
+            Duration::from_secs(600),
+        );
+        let policy = RetentionPolicy {
+            max_age_secs: Some(60),
+            max_bytes: Some(0),
+            ..RetentionPolicy::default()
+        };
+        let report = policy.prune(&path).unwrap();
+
+        assert_eq!(report.files, 2);
+        assert!(path.join(format!("0/writing.png{PARTIAL_SUFFIX}")).exists());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    /// Refuse to start when the free space threshold cannot be met.
+    fn test_free_space_threshold() {
+        let path = capture_dir();
+        let policy = RetentionPolicy {
+            min_free_bytes: Some(u64::MAX),
+            ..RetentionPolicy::default()
+        };
+        assert!(policy.check_free_space(&path).is_err());
+        assert!(RetentionPolicy::default().check_free_space(&path).is_ok());
+        fs::remove_dir_all(path).unwrap();
+    }
+}
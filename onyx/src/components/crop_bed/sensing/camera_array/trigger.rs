@@ -0,0 +1,71 @@
+use crate::{
+    devices::hardware::camera::DevicePayload,
+    messages::control::trigger::TriggerMessage,
+    utils::{
+        bus::{BusEvent, MessageBus},
+        location::CropBed,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    sync::mpsc::{Receiver, Sender},
+};
+use tracing::warn;
+
+/// Where the trigger events of the cameras are published, for the lighting
+/// to strobe on.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriggerPublisherConfig {
+    /// Address the lighting component listens for trigger events on.
+    pub address: SocketAddr,
+}
+
+impl TriggerPublisherConfig {
+    /// Bind the socket the trigger events are sent from, paired with the
+    /// address they are sent to.
+    pub fn bind(&self) -> io::Result<(UdpSocket, SocketAddr)> {
+        Ok((UdpSocket::bind("0.0.0.0:0")?, self.address))
+    }
+}
+
+/// Forward every payload from the cameras to the primary sink, publishing
+/// a trigger event for each one on the way, over UDP and on the bus. A
+/// failed send is logged and the payload is still forwarded, the images
+/// matter more than the strobe. Returns once every camera sender has been
+/// dropped or the sink has gone.
+///
+/// * `receiver`: channel the cameras send payloads on.
+/// * `sink`: channel read by the image writers.
+/// * `publisher`: socket and address the trigger events go to over UDP, if
+///   they do.
+/// * `bus`: bus the trigger events are published on, if any.
+/// * `crop_bed`: crop bed of the camera array.
+pub(super) fn tap_payloads(
+    receiver: Receiver<DevicePayload>,
+    sink: &Sender<DevicePayload>,
+    publisher: Option<(UdpSocket, SocketAddr)>,
+    bus: Option<&MessageBus>,
+    crop_bed: CropBed,
+) {
+    for payload in receiver {
+        if let Some(bed_position) = payload.location_id() {
+            let message = TriggerMessage::new(payload.captured_at(), bed_position, crop_bed);
+            if let Some((socket, address)) = &publisher {
+                let sent = serde_json::to_vec(&message)
+                    .map_err(io::Error::from)
+                    .and_then(|datagram| socket.send_to(&datagram, address));
+                if let Err(e) = sent {
+                    warn!("Failed to publish trigger of camera {bed_position} to {address}: {e}");
+                }
+            }
+            if let Some(bus) = bus {
+                bus.publish(BusEvent::CameraTrigger(message));
+            }
+        }
+        if sink.send(payload).is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use crate::devices::hardware::pdm::{PdmAddress, PdmError};
+use crate::utils::{
+    config::{ConfigError, ConfigFile, ValidationReport},
+    location::CropBed,
+};
+use std::{fmt::Display, future::Future, io, path::Path};
+
+/// Config a component is built from, loaded from its file through the
+/// shared loader and validated before the component is built.
+pub trait ComponentConfig: ConfigFile {
+    /// Crop bed module the component runs on.
+    fn crop_bed(&self) -> CropBed;
+}
+
+/// Why a component could not be built or started. The components refuse
+/// to run without their devices, so failing to reach one is an error.
+#[derive(Debug, thiserror::Error)]
+pub enum ComponentError {
+    /// The config file does not load, or is not one the component can be
+    /// started from.
+    #[error(transparent)]
+    Config(#[from] ValidationReport),
+    /// The config file, or one it refers to, could not be loaded.
+    #[error(transparent)]
+    Load(#[from] ConfigError),
+    /// The config asks for something the component cannot do, i.e. a
+    /// channel wired twice.
+    #[error("{0}")]
+    Invalid(String),
+    /// The canbus interface could not be opened.
+    #[error("Failed to create canbus socket on {canbus_id}: {source}")]
+    Canbus {
+        /// Canbus interface name.
+        canbus_id: String,
+        /// Error opening it.
+        source: PdmError,
+    },
+    /// A PDM on the bus could not be initialised.
+    #[error("Failed to initialise PDM {address} at bed position {bed_position} on {canbus_id}: {source}")]
+    Pdm {
+        /// Address of the PDM.
+        address: PdmAddress,
+        /// Key of the PDM in the component.
+        bed_position: u8,
+        /// Canbus interface name.
+        canbus_id: String,
+        /// Error initialising it.
+        source: PdmError,
+    },
+    /// The PDMs answered with a configuration other than the config.
+    #[error("PDM configuration on {canbus_id} does not match the config, refusing to start")]
+    PdmMismatch {
+        /// Canbus interface name.
+        canbus_id: String,
+    },
+    /// A port, directory or device the component needs could not be set
+    /// up.
+    #[error("{context}: {source}")]
+    Io {
+        /// What was being set up, i.e. `Failed to bind port 8080`.
+        context: String,
+        /// Error setting it up.
+        source: io::Error,
+    },
+}
+
+impl ComponentError {
+    /// Error for a port, directory or device that could not be set up.
+    ///
+    /// * `context`: what was being set up.
+    pub fn io(context: impl Into<String>) -> impl FnOnce(io::Error) -> Self {
+        let context = context.into();
+        move |source| Self::Io { context, source }
+    }
+
+    /// Whether the component was refused for its config rather than for
+    /// what it found when starting.
+    pub fn is_config(&self) -> bool {
+        matches!(self, Self::Config(_) | Self::Load(_) | Self::Invalid(_))
+    }
+}
+
+/// Running component, owning its tasks or threads until it is shut down.
+pub trait ComponentHandle: Sized {
+    /// Final state of the component, returned once it has shut down.
+    type Status: Display;
+
+    /// Ask the component to stop, without waiting for it.
+    fn request_stop(&self);
+
+    /// Stop the component and wait for every task or thread it started,
+    /// leaving its devices off.
+    fn shutdown(self) -> impl Future<Output = Self::Status>;
+}
+
+/// Starts a component, the unit struct the behaviour of each component
+/// hangs off. Lets a binary or supervisor run any of the components the
+/// same way.
+pub trait ComponentController {
+    /// Config the component is built from.
+    type Config: ComponentConfig;
+    /// Component built from the config.
+    type Component;
+    /// Handle of the running component.
+    type Handle: ComponentHandle;
+
+    /// Build the component by consuming its config, as its `new` does.
+    ///
+    /// * `config`: config of the component.
+    fn build(config: Self::Config) -> Result<Self::Component, ComponentError>;
+
+    /// Build the component from a config file, refusing a file that does
+    /// not load or is invalid.
+    ///
+    /// * `path`: path of the config file.
+    fn from_file(path: &Path) -> Result<Self::Component, ComponentError> {
+        Self::build(Self::Config::load_validated(path)?)
+    }
+
+    /// Start the component, returning once it is running with the handle
+    /// used to stop it.
+    ///
+    /// * `component`: component consumed by its tasks.
+    fn start(component: Self::Component) -> impl Future<Output = Result<Self::Handle, ComponentError>>;
+
+    /// Build the component from a config file and start it.
+    ///
+    /// * `path`: path of the config file.
+    fn start_from_file(path: &Path) -> impl Future<Output = Result<Self::Handle, ComponentError>> {
+        let component = Self::from_file(path);
+        async move { Self::start(component?).await }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        components::prelude::*,
+        devices::{software::camera::SimulatedCameraConfig, traits::DeviceConfig},
+    };
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    /// Start a component from its config file, let it run and shut it
+    /// down, as a binary or supervisor would.
+    ///
+    /// * `path`: path of the config file.
+    /// * `running`: time the component is left running.
+    async fn run_from_file<C: ComponentController>(
+        path: &Path,
+        running: Duration,
+    ) -> Result<<C::Handle as ComponentHandle>::Status, ComponentError> {
+        let handle = C::start_from_file(path).await?;
+        tokio::time::sleep(running).await;
+        handle.request_stop();
+        Ok(handle.shutdown().await)
+    }
+
+    /// Write a camera array config with simulated cameras to a new
+    /// directory, returning the directory and the config file.
+    ///
+    /// * `cameras`: config of the camera at each bed position.
+    fn write_camera_array(cameras: Vec<(u8, SimulatedCameraConfig)>) -> (std::path::PathBuf, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("onyx-component-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = cameras.into_iter().fold(
+            CameraArrayConfig::new(String::from("images"), CropBed::Centre),
+            |config, (bed_position, camera)| config.add_simulated_camera(camera, bed_position),
+        );
+        let config_file = dir.join("camera_array.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&config_file).unwrap(), &config).unwrap();
+        (dir, config_file)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    /// A camera array of simulated cameras started through the traits
+    /// captures from every camera, its images saved relative to the
+    /// config file, and stops on a shutdown.
+    async fn test_camera_array_through_traits() {
+        let cameras = vec![
+            (0, SimulatedCameraConfig::new(Some(0), 20, 8, 8)),
+            (1, SimulatedCameraConfig::new(Some(1), 20, 8, 8)),
+        ];
+        assert_eq!(cameras[1].1.location(), Some(1));
+        let (dir, config_file) = write_camera_array(cameras);
+        let config = CameraArrayConfig::load_validated(&config_file).unwrap();
+        assert_eq!(config.crop_bed(), CropBed::Centre);
+
+        let stats = run_from_file::<CameraArrayController>(&config_file, Duration::from_millis(500))
+            .await
+            .unwrap();
+        assert_eq!(stats.cameras.len(), 2);
+        assert!(
+            stats.cameras.values().all(|camera| camera.frames_captured > 0),
+            "{stats}"
+        );
+        assert!(dir.join("images").is_dir());
+        assert_eq!(stats.threads_panicked, 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    /// A config with a camera the array would refuse is not started, the
+    /// error naming the camera.
+    async fn test_invalid_component_config_refused() {
+        let (dir, config_file) = write_camera_array(vec![(2, SimulatedCameraConfig::new(Some(2), 0, 8, 8))]);
+        let error = run_from_file::<CameraArrayController>(&config_file, Duration::ZERO)
+            .await
+            .unwrap_err();
+        let ComponentError::Config(report) = &error else {
+            panic!("{error}");
+        };
+        assert!(report.to_string().contains("simulated_cameras.2.fps"), "{error}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,11 +2,31 @@
 /// into components. Their core responsibilities do not change 
 /// based on location, name etc.
 pub mod hardware {
+    /// Ambient light level from a sensor on the utilities bus.
+    pub mod ambient_light;
     /// Device interface for the network cameras.
     pub mod camera;
     /// Device interface for the pdm.
     pub mod pdm;
+    /// Ground speed from a wheel speed sensor.
+    pub mod wheel_speed;
 }
 
-/// TODO: Not utilised as yet.
-pub mod software {}
+/// Devices that stand in for hardware when it is not on the network.
+pub mod software {
+    /// Simulated ambient light sensor reporting set readings.
+    pub mod ambient_light;
+    /// Simulated camera producing synthetic frames.
+    pub mod camera;
+    /// Simulated PDM answering on a virtual canbus.
+    #[cfg(feature = "canbus")]
+    pub mod pdm;
+    /// Scenarios running the crop bed components against simulated devices.
+    #[cfg(feature = "canbus")]
+    pub mod scenario;
+    /// Simulated wheel speed sensor reporting set speeds.
+    pub mod wheel_speed;
+}
+
+/// Traits shared by the devices and the configs they are built from.
+pub mod traits;
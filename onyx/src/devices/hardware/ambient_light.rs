@@ -0,0 +1,218 @@
+use crate::devices::hardware::pdm::frames::J1939Frame;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "canbus")]
+use socketcan::{tokio::CanSocket as AsyncCanSocket, EmbeddedFrame, Frame};
+use std::io;
+use tokio::{sync::watch, task::JoinHandle};
+#[cfg(feature = "canbus")]
+use tracing::warn;
+
+/// Proprietary B parameter group the ambient light module on the utilities
+/// bus sends its reading in, when none is set in the config.
+pub const DEFAULT_AMBIENT_LIGHT_PGN: u32 = 0xFF60;
+
+/// Raw lux values from this up are error or not available, as for any four
+/// byte J1939 parameter.
+const AMBIENT_LIGHT_NOT_AVAILABLE: u32 = 0xFB00_0000;
+
+/// Illuminance in lux carried in an ambient light frame, bytes 1 to 4 at one
+/// lux per bit. `None` for other frames or when the sender reports the
+/// reading as unavailable.
+///
+/// * `frame`: frame read from the bus.
+/// * `pgn`: parameter group the reading is sent in.
+pub fn ambient_lux(frame: &J1939Frame, pgn: u32) -> Option<u32> {
+    if frame.pgn != pgn || frame.data.len() < 4 {
+        return None;
+    }
+    let raw = u32::from_le_bytes([frame.data[0], frame.data[1], frame.data[2], frame.data[3]]);
+    (raw < AMBIENT_LIGHT_NOT_AVAILABLE).then_some(raw)
+}
+
+/// Where ambient light readings come from, a sensor on the machine or a
+/// stand in for one.
+pub trait LuxSource: Send + 'static {
+    /// Wait for the next reading in lux, `None` once the source has stopped.
+    fn next_lux(&mut self) -> BoxFuture<'_, Option<u32>>;
+}
+
+/// Ambient light module converting its analog sensor onto the canbus.
+#[cfg(feature = "canbus")]
+pub struct CanLuxSource {
+    /// Socket listening on the bus.
+    socket: AsyncCanSocket,
+    /// Parameter group the reading is sent in.
+    pgn: u32,
+    /// Only take the reading from this node, any node when not set.
+    source_address: Option<u8>,
+}
+
+#[cfg(feature = "canbus")]
+impl CanLuxSource {
+    /// Listen for ambient light readings on a canbus.
+    ///
+    /// * `canbus_id`: String for the bus i.e., can0.
+    /// * `pgn`: parameter group the reading is sent in.
+    /// * `source_address`: node sending the reading, any node when not set.
+    pub fn open(canbus_id: &str, pgn: u32, source_address: Option<u8>) -> io::Result<Self> {
+        Ok(Self {
+            socket: AsyncCanSocket::open(canbus_id)?,
+            pgn,
+            source_address,
+        })
+    }
+}
+
+#[cfg(feature = "canbus")]
+impl LuxSource for CanLuxSource {
+    fn next_lux(&mut self) -> BoxFuture<'_, Option<u32>> {
+        Box::pin(async move {
+            loop {
+                let frame = match self.socket.read_frame().await {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        warn!("Stopped reading ambient light: {e}");
+                        return None;
+                    }
+                };
+                if !frame.is_extended() {
+                    continue;
+                }
+                let frame = J1939Frame::from_id(frame.raw_id(), frame.data());
+                if self.source_address.is_some_and(|address| address != frame.source) {
+                    continue;
+                }
+                if let Some(lux) = ambient_lux(&frame, self.pgn) {
+                    return Some(lux);
+                }
+            }
+        })
+    }
+}
+
+/// Default parameter group of the ambient light reading.
+fn default_pgn() -> u32 {
+    DEFAULT_AMBIENT_LIGHT_PGN
+}
+
+/// Where a component reads the ambient light from.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AmbientLightConfig {
+    /// Reading broadcast on a canbus by an ambient light module.
+    Can {
+        /// The addressable canbus interface ID.
+        canbus_id: String,
+        /// Parameter group the reading is sent in.
+        #[serde(default = "default_pgn")]
+        pgn: u32,
+        /// Node sending the reading, any node when not set.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source_address: Option<u8>,
+    },
+}
+
+impl AmbientLightConfig {
+    /// Open the lux source described by the config.
+    pub fn open(&self) -> io::Result<Box<dyn LuxSource>> {
+        match self {
+            #[cfg(feature = "canbus")]
+            AmbientLightConfig::Can {
+                canbus_id,
+                pgn,
+                source_address,
+            } => Ok(Box::new(CanLuxSource::open(canbus_id, *pgn, *source_address)?)),
+            #[cfg(not(feature = "canbus"))]
+            AmbientLightConfig::Can { .. } => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Ambient light on the canbus needs a build with the canbus feature",
+            )),
+        }
+    }
+}
+
+/// Device publishing the ambient light read from a lux source. The reading
+/// is `None` until the first one arrives and again once the source stops.
+pub struct AmbientLightSensor {
+    /// Latest illuminance in lux.
+    lux_rx: watch::Receiver<Option<u32>>,
+    /// Task reading the source.
+    task: JoinHandle<()>,
+}
+
+impl Drop for AmbientLightSensor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl AmbientLightSensor {
+    /// Start reading a lux source, must be called from within the runtime.
+    ///
+    /// * `source`: where the readings come from.
+    pub fn start(mut source: Box<dyn LuxSource>) -> Self {
+        let (lux_tx, lux_rx) = watch::channel(None);
+        let task = tokio::spawn(async move {
+            while let Some(lux) = source.next_lux().await {
+                lux_tx.send_replace(Some(lux));
+            }
+            lux_tx.send_replace(None);
+        });
+        Self { lux_rx, task }
+    }
+
+    /// Receiver notified on every new reading.
+    pub fn subscribe(&self) -> watch::Receiver<Option<u32>> {
+        self.lux_rx.clone()
+    }
+
+    /// Latest illuminance in lux, `None` when there is no reading.
+    pub fn lux(&self) -> Option<u32> {
+        *self.lux_rx.borrow()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::dark(0, Some(0))]
+    #[case::dusk(120, Some(120))]
+    #[case::daylight(80_000, Some(80_000))]
+    #[case::error(0xFE00_0000, None)]
+    #[case::not_available(0xFFFF_FFFF, None)]
+    fn test_ambient_lux_frame(#[case] raw: u32, #[case] expected: Option<u32>) {
+        let frame = J1939Frame {
+            priority: 6,
+            pgn: DEFAULT_AMBIENT_LIGHT_PGN,
+            source: 0x80,
+            destination: None,
+            data: raw.to_le_bytes().to_vec(),
+        };
+        assert_eq!(ambient_lux(&frame, DEFAULT_AMBIENT_LIGHT_PGN), expected);
+        assert_eq!(ambient_lux(&frame, DEFAULT_AMBIENT_LIGHT_PGN + 1), None);
+    }
+
+    #[test]
+    fn test_parse_ambient_light_config() {
+        let can: AmbientLightConfig = serde_yaml::from_str(
+            r#"
+            can:
+              canbus_id: can3
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            can,
+            AmbientLightConfig::Can {
+                canbus_id: String::from("can3"),
+                pgn: DEFAULT_AMBIENT_LIGHT_PGN,
+                source_address: None,
+            }
+        );
+    }
+}
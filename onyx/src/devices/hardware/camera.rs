@@ -1,22 +1,35 @@
-use crate::utils::image::{CameraPixelFormat, Roi};
-use aravis::{AcquisitionMode, Camera, CameraExt, CameraExtManual, StreamExt};
+#[cfg(feature = "camera-hw")]
+use crate::devices::traits::{Device, DeviceConfig};
+use crate::utils::{
+    config::{load_yaml, ConfigError, Validate, ValidationReport},
+    image::{CameraPixelFormat, Roi},
+    metrics,
+};
+#[cfg(feature = "camera-hw")]
+use aravis::{AcquisitionMode, Camera, CameraExt, CameraExtManual, PixelFormat, StreamExt};
 use chrono::{DateTime, Utc};
 use image::DynamicImage;
-use serde::{de::Visitor, Deserialize, Serialize};
+use serde::{Deserialize, Serialize};
 use std::{
     ffi::OsStr,
     net::Ipv4Addr,
     path::Path,
     sync::{
-        atomic::{AtomicBool, Ordering},
-        mpsc::Sender,
-        Arc, Barrier,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{Receiver, Sender, TryRecvError},
+        Arc, Condvar, Mutex,
     },
     time::{Duration, Instant},
 };
 use strum_macros::{EnumString, IntoStaticStr};
+use tracing::{debug, info, trace, warn, Level};
 use uuid::Uuid;
 
+/// Checking the cameras on the network against their configs, for
+/// bringing up a bed.
+#[cfg(feature = "camera-hw")]
+pub mod diagnostics;
+
 /// You can trigger the device in several ways as per the
 /// genicam standard, however for the onyx use case only
 /// the software trigger was implemented.
@@ -26,73 +39,95 @@ pub enum DeviceTrigger {
     Software,
 }
 
-/// Due to rusts orphan rule at times we need to provide wrapper types for struct's
-/// that come from other crates. The convention used in this software is to lead with
-/// `WrapperNameOfType`. This is seen a lot with the serde crate.
-#[derive(Copy, Clone, PartialEq, Eq)]
-pub struct WrapperAcquisitionMode(AcquisitionMode);
-
-impl Serialize for WrapperAcquisitionMode {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        match self.0 {
-            AcquisitionMode::Continuous => {
-                serializer.serialize_unit_variant("AcquisitionMode", 0, "Continuous")
-            }
-            AcquisitionMode::SingleFrame => {
-                serializer.serialize_unit_variant("AcquisitionMode", 1, "SingleFrame")
-            }
-            AcquisitionMode::MultiFrame => {
-                serializer.serialize_unit_variant("AcquisitionMode", 2, "MultiFrame")
-            }
-            _ => panic!("Unknown acquisition mode"),
-        }
-    }
+/// Why a network camera could not be built or streamed from. The reasons
+/// from aravis are kept as they were logged before.
+#[derive(Debug, thiserror::Error)]
+pub enum CameraError {
+    /// The config file could not be loaded.
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    /// No camera answered at the address, or it could not be opened.
+    #[error("Failed to create camera {0}")]
+    Create(String),
+    /// The camera cannot run as configured, i.e. at a frame rate or
+    /// region outside its range.
+    #[error("{0}")]
+    Unsupported(String),
+    /// The camera refused a setting, or kept another one.
+    #[error("Failed to set {setting} {reason}")]
+    Setting {
+        /// Setting that was refused.
+        setting: &'static str,
+        /// Why it was refused.
+        reason: String,
+    },
+    /// A property needed to stream could not be read from the camera.
+    #[error("Failed to get {property} {reason}")]
+    Property {
+        /// Property that could not be read.
+        property: &'static str,
+        /// Why it could not be read.
+        reason: String,
+    },
+    /// The stream could not be created or acquisition started.
+    #[error("Unable to {action} {reason}")]
+    Stream {
+        /// What was being done with the stream.
+        action: &'static str,
+        /// Why it failed.
+        reason: String,
+    },
 }
 
-impl<'de> Deserialize<'de> for WrapperAcquisitionMode {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        deserializer.deserialize_str(CameraAcquisitionModeVisitor {})
+#[cfg(feature = "camera-hw")]
+impl CameraError {
+    /// Error for a setting the camera refused.
+    ///
+    /// * `setting`: setting that was refused.
+    /// * `reason`: error from aravis, as it was logged.
+    fn setting(setting: &'static str, reason: String) -> Self {
+        Self::Setting { setting, reason }
     }
 }
 
-/// When implementing serde types we need to provide a `Visitor` type which is used
-/// for the implementation of the Visitor trait. See the [serde] crate for more
-/// information.
-struct CameraAcquisitionModeVisitor {}
-
-impl<'de> Visitor<'de> for CameraAcquisitionModeVisitor {
-    type Value = WrapperAcquisitionMode;
+/// How the camera captures once acquisition starts, as in the GenICam
+/// standard. Kept apart from the aravis modes so the configs load in a
+/// build without the `camera-hw` feature.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub enum CameraAcquisitionMode {
+    /// Capture at every trigger until acquisition is stopped.
+    Continuous,
+    /// Capture a single frame, then stop.
+    SingleFrame,
+    /// Capture a set number of frames, then stop.
+    MultiFrame,
+}
 
-    // TODO: Update function to elided lifetime below. Good first issue.
-    #[allow(unused_attributes)]
-    #[allow(elided_lifetimes_in_paths)]
-    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("Could not deserialise CameraAcquisitionMode")
+#[cfg(feature = "camera-hw")]
+impl From<CameraPixelFormat> for PixelFormat {
+    fn from(pixel_format: CameraPixelFormat) -> Self {
+        match pixel_format {
+            CameraPixelFormat::BayerRg8 => PixelFormat::BAYER_RG_8,
+            CameraPixelFormat::Rgb8Packed => PixelFormat::RGB_8_PACKED,
+            CameraPixelFormat::Rgb8Planar => PixelFormat::RGB_8_PLANAR,
+        }
     }
+}
 
-    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
-    where
-        E: serde::de::Error,
-    {
-        match v {
-            "Continuous" => Ok(WrapperAcquisitionMode(AcquisitionMode::Continuous)),
-            "SingleFrame" => Ok(WrapperAcquisitionMode(AcquisitionMode::SingleFrame)),
-            _ => Err(serde::de::Error::custom(
-                "Unknown acquisition mode format {v:?}",
-            )),
+#[cfg(feature = "camera-hw")]
+impl From<CameraAcquisitionMode> for AcquisitionMode {
+    fn from(acquisition_mode: CameraAcquisitionMode) -> Self {
+        match acquisition_mode {
+            CameraAcquisitionMode::Continuous => AcquisitionMode::Continuous,
+            CameraAcquisitionMode::SingleFrame => AcquisitionMode::SingleFrame,
+            CameraAcquisitionMode::MultiFrame => AcquisitionMode::MultiFrame,
         }
     }
 }
 
 /// Camera configuration struct contains all of the above specified parameters
 /// that interface with the genicam standard, and the aravis camera driver.
-#[derive(Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
 pub struct OnyxCameraConfig {
     /// Location of the device on the crop bed as per bill of materials.
     bed_location_id: Option<u8>,
@@ -107,7 +142,7 @@ pub struct OnyxCameraConfig {
     /// The type of trigger to set for the camera to capture an image.
     trigger: Option<DeviceTrigger>,
     /// Acquisition mode determines how the images are captured such as continuous or single frame.
-    acquisition_mode: Option<WrapperAcquisitionMode>,
+    acquisition_mode: Option<CameraAcquisitionMode>,
     /// A cameras ability to send data over a network is impacted by the MTU size, this setting automatically
     /// determines the maximum MTU that the camera can apply.
     auto_packet_size: Option<bool>,
@@ -146,26 +181,96 @@ impl OnyxCameraConfig {
         }
     }
 
-    /// Generates a new camera config from a file.
+    /// Camera config with the settings the crop bed cameras run, the full
+    /// 1280x1024 sensor in Bayer RG8, software triggered, with automatic
+    /// gain, brightness and exposure between 100us and 30ms.
+    ///
+    /// * `ip_address`: IP address of networked camera.
+    /// * `fps`: desired frame per second for capture.
+    /// * `bed_location_id`: location of the camera on the crop bed.
+    pub fn crop_bed(ip_address: impl Into<Ipv4Addr>, fps: u32, bed_location_id: u8) -> Self {
+        Self {
+            bed_location_id: Some(bed_location_id),
+            roi: Some(Roi {
+                x: 0,
+                y: 0,
+                w: 1280,
+                h: 1024,
+            }),
+            pixel_format: Some(CameraPixelFormat::BayerRg8),
+            trigger: Some(DeviceTrigger::Software),
+            acquisition_mode: Some(CameraAcquisitionMode::Continuous),
+            auto_packet_size: Some(true),
+            auto_gain: Some(true),
+            auto_brightness: Some(true),
+            auto_exposure: Some(true),
+            exposure_min: Some(100),
+            exposure_max: Some(30000),
+            ..Self::new(ip_address, fps)
+        }
+    }
+
+    /// Frames per second specified in Hz.
+    pub fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    /// Region of interest the camera will be cropped to.
+    pub fn roi(&self) -> Option<Roi> {
+        self.roi
+    }
+
+    /// Network address of the camera.
+    pub fn ip_address(&self) -> Ipv4Addr {
+        self.ip_address
+    }
+
+    /// Load the config from a yaml file, the error naming the field that
+    /// is wrong and where it is.
     ///
     /// * `filepath`: path to config file.
-    pub fn from_file<F: AsRef<OsStr>>(filepath: F) -> Self {
-        let file = Path::new(&filepath);
-        let camera_config = if file.is_file() {
-            let config_file = config::Config::builder()
-                .add_source(config::File::new(
-                    &file.to_string_lossy(),
-                    config::FileFormat::Yaml,
-                ))
-                .build()
-                .expect("Failed read config");
-            config_file
-                .try_deserialize::<OnyxCameraConfig>()
-                .expect("Failed to parse config file into struct")
-        } else {
-            panic!("Could not locate the config file {:?}", file);
-        };
-        camera_config
+    pub fn try_from_file<F: AsRef<OsStr>>(filepath: F) -> Result<Self, ConfigError> {
+        load_yaml(Path::new(&filepath))
+    }
+}
+
+impl Validate for OnyxCameraConfig {
+    fn validate(&self, report: &mut ValidationReport) {
+        if self.fps == 0 {
+            report.push("fps", "no frames are captured at 0 fps");
+        }
+        if self.ip_address.is_unspecified() || self.ip_address.is_broadcast() {
+            report.push("ip_address", format!("{} is not the address of a camera", self.ip_address));
+        }
+        // The size of the sensor is only known once the camera is opened,
+        // the rest of the region is checked there.
+        if let Some(Err(e)) = self.roi.map(|roi| roi.validate(i32::MAX, i32::MAX, 1, 1)) {
+            report.push("roi", e.to_string());
+        }
+        for (field, exposure) in [("exposure_min", self.exposure_min), ("exposure_max", self.exposure_max)] {
+            if exposure.is_some_and(|exposure| exposure <= 0) {
+                report.push(field, "exposures are a positive number of microseconds");
+            }
+        }
+        if let (Some(min), Some(max)) = (self.exposure_min, self.exposure_max) {
+            if min > max {
+                report.push("exposure_min", format!("{min}us is above exposure_max {max}us"));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "camera-hw")]
+impl DeviceConfig for OnyxCameraConfig {
+    type Device = OnyxCamera;
+    type Error = CameraError;
+
+    fn location(&self) -> Option<u8> {
+        self.bed_location_id
+    }
+
+    fn build(self) -> Result<OnyxCamera, CameraError> {
+        OnyxCamera::new(self)
     }
 }
 
@@ -174,6 +279,7 @@ impl OnyxCameraConfig {
 /// and allow a public interface to an underlying driver. This driver is either
 /// implemented by flux, such as the IX3212 PDM, or relies on an open source
 /// or manufacture provided driver, such as aravis (open source).
+#[cfg(feature = "camera-hw")]
 pub struct OnyxCamera {
     /// Access to the aravis driver for camera functionality.
     pub driver: Camera,
@@ -186,6 +292,7 @@ pub struct OnyxCamera {
 // TODO: extract out common functionality to traits. Didn't get time to do a
 // refactor and pull these out due to delivery constraints. In addition async
 // functions in traits where still fuzzy.
+#[cfg(feature = "camera-hw")]
 impl OnyxCamera {
 
     /// Return the unique identifier of the camera.
@@ -202,15 +309,16 @@ impl OnyxCamera {
         self.bed_location_id = Some(location_id);
     }
 
-    /// Create a new Onyx Camera by consuming a camera config.
+    /// Create a new Onyx Camera by consuming a camera config, failing when
+    /// the camera is not on the network or refuses the config.
     ///
     /// * `config`: Set of parameters that configure a network camera.
-    pub fn new(config: OnyxCameraConfig) -> Self {
-        Self {
+    pub fn new(config: OnyxCameraConfig) -> Result<Self, CameraError> {
+        Ok(Self {
             uuid: Uuid::new_v4(),
             bed_location_id: config.bed_location_id,
-            driver: Self::build_from_config(config),
-        }
+            driver: Self::build_from_config(config)?,
+        })
     }
 
     /// Create a new Onyx Camera by reading a file at a location 
@@ -218,8 +326,8 @@ impl OnyxCamera {
     /// config as per the builder patter.
     ///
     /// * `filepath`: path to the parameter file.
-    pub fn from_config_file<F: AsRef<OsStr>>(filepath: F) -> Self {
-        Self::new(OnyxCameraConfig::from_file(filepath))
+    pub fn from_config_file<F: AsRef<OsStr>>(filepath: F) -> Result<Self, CameraError> {
+        Self::new(OnyxCameraConfig::try_from_file(filepath)?)
     }
 
     /// Create an aravis camera handle for the `OnyxCamera` driver. Due to the way
@@ -230,7 +338,7 @@ impl OnyxCamera {
     /// the recommendation is to write additional unit tests below.
     ///
     /// * `config`: `OnyxCamera` config struct
-    fn build_from_config(config: OnyxCameraConfig) -> Camera {
+    fn build_from_config(config: OnyxCameraConfig) -> Result<Camera, CameraError> {
         // TODO:
         // As more camera tuning was required when getting the unit onto the customers farm
         // additional parameters were implemented and patched on, the result of this
@@ -240,78 +348,41 @@ impl OnyxCamera {
         // sense to look at this in tandem with that activity.
         let camera: Camera = match Camera::new(Some(&config.ip_address.to_string())) {
             Ok(c) => c,
-            Err(e) => panic!("Failed to create camera {e:?}"),
+            Err(e) => return Err(CameraError::Create(format!("{e:?}"))),
         };
 
-        // Some cameras will fail silently if you try to put a higher FPS in
-        // that can be tolerated by the device. I don't believe genicam (xml)
-        // will stop you putting an erroneous value in. TODO: review the
-        // aravis repo and check the wrapper functions in there.
-        match camera.frame_rate_bounds() {
-            Ok((min, max)) => {
-                assert!(
-                    (min..max).contains(&config.fps.into()),
-                    "Cannot set FPS as device range does not allow it"
-                );
-            }
-            Err(e) => panic!("Cannot determine frame rate bounds; {e}"),
-        }
+        frame_rate_in_bounds(&camera, config.fps).map_err(CameraError::Unsupported)?;
 
         //TODO: refactor this into above match statement.
         if let Err(e) = camera.set_frame_rate(config.fps.into()) {
-            panic!("Failed to set frame rate {e:?}")
+            return Err(CameraError::setting("frame rate", format!("{e:?}")));
         }
 
-        // Setting the region of interest requires some effort depending on if
-        // the sensor is utilising binning. See camera data sheet or Gig E vision
-        // specification to learn more.
         if let Some(roi) = config.roi {
-            if let Ok(binning_available) = camera.is_binning_available() {
-                if binning_available {
-                    if let Ok((min_y, max_y)) = camera.y_binning_bounds() {
-                        for y in (2..=max_y).step_by(2) {
-                            assert!(
-                                roi.h % y == 0,
-                                "ROI is not a muliple of the binning bounds in the Y direction bounds {:?}, y: {}", (min_y, max_y), y
-                            );
-                        }
-                    } else {
-                        panic!("Cannot automatically determine X direction binning bounds for the camera")
-                    }
-
-                    if let Ok((min_x, max_x)) = camera.x_binning_bounds() {
-                        for x in (2..=max_x).step_by(2) {
-                            assert!(
-                                roi.x % x == 0,
-                                "ROI is not a muliple of the binning bounds in the X direction bounds {:?}, x: {}", (min_x, max_x), x
-                            );
-                        }
-                    } else {
-                        panic!("Cannot automatically determine Y direction binning bounds for the camera")
-                    }
-                }
-            }
-            if let Err(e) = camera.set_region(roi.x, roi.y, roi.w, roi.h) {
-                panic!("Failed to set acquisition roi {e:?}")
+            let region = device_region(&camera, roi).map_err(CameraError::Unsupported)?;
+            if let Err(e) = camera.set_region(region.x, region.y, region.w, region.h) {
+                return Err(CameraError::setting("acquisition roi", format!("{e:?}")));
             }
 
             if let Ok((x, y, w, h)) = camera.region() {
-                assert!(x == roi.x, "Failed initialisation assert to set offset x");
-                assert!(y == roi.y, "Failed initialisation assert to set offset y");
-                assert!(w == roi.w, "Failed initialisation assert to set width  w");
-                assert!(h == roi.h, "Failed initialisation assert to set height h");
+                if (x, y, w, h) != (region.x, region.y, region.w, region.h) {
+                    return Err(CameraError::Unsupported(format!(
+                        "Failed initialisation assert to set the roi {region:?}, the camera is at {:?}",
+                        Roi { x, y, w, h }
+                    )));
+                }
             }
         }
 
         if let Some(pixel_format) = config.pixel_format {
-            if let Err(e) = camera.set_pixel_format(pixel_format.0) {
-                panic!("Failed to set pixel format {e:?}")
+            if let Err(e) = camera.set_pixel_format(pixel_format.into()) {
+                return Err(CameraError::setting("pixel format", format!("{e:?}")));
             }
         }
 
         if let Some(acquisition_mode) = config.acquisition_mode {
-            if let Err(e) = camera.set_acquisition_mode(acquisition_mode.0) {
-                panic!("Failed to set acquisition mode {e:?}")
+            if let Err(e) = camera.set_acquisition_mode(acquisition_mode.into()) {
+                return Err(CameraError::setting("acquisition mode", format!("{e:?}")));
             }
         }
 
@@ -320,11 +391,11 @@ impl OnyxCamera {
                 if available {
                     if auto_exposure {
                         if let Err(e) = camera.set_exposure_time_auto(aravis::Auto::Continuous) {
-                            panic!("Failed to set exposure time auto {e}");
+                            return Err(CameraError::setting("exposure time auto", e.to_string()));
                         }
                     }
                 } else {
-                    println!("Auto Exposure is not available");
+                    warn!("Auto Exposure is not available");
                 }
             }
         }
@@ -332,20 +403,20 @@ impl OnyxCamera {
         if let Some(auto_brightness) = config.auto_brightness {
             if auto_brightness {
                 if let Err(e) = camera.set_string("autoBrightnessMode", "Active") {
-                    panic!("Failed to set auto auto brightness {e}")
+                    return Err(CameraError::setting("auto auto brightness", e.to_string()));
                 }
             }
         }
 
         if let Some(exposure_min) = config.exposure_min {
             if let Err(e) = camera.set_float("exposureAutoMinValue", exposure_min as f64) {
-                panic!("Failed to set auto min time {e}");
+                return Err(CameraError::setting("auto min time", e.to_string()));
             }
         }
         // TODO: Set logging to tell when exposure max goes above 10,000
         if let Some(exposure_max) = config.exposure_max {
             if let Err(e) = camera.set_float("exposureAutoMaxValue", exposure_max as f64) {
-                panic!("Failed to set auto min time {e}");
+                return Err(CameraError::setting("auto min time", e.to_string()));
             }
         }
 
@@ -354,11 +425,11 @@ impl OnyxCamera {
                 if available {
                     if auto_gain {
                         if let Err(e) = camera.gain_auto() {
-                            panic!("Failed to set auto gain {e}");
+                            return Err(CameraError::setting("auto gain", e.to_string()));
                         }
                     }
                 } else {
-                    println!("Auto gane is not available");
+                    warn!("Auto gane is not available");
                 }
             }
         }
@@ -366,23 +437,101 @@ impl OnyxCamera {
         // TODO: Create some config enums for this. Good first issue.
         //       and refrain from having &str config without type safety.
         if let Err(e) = camera.set_string("BalanceWhiteAuto", "OnDemand") {
-            panic!("Failed to set on demand white balance {e}");
+            return Err(CameraError::setting("on demand white balance", e.to_string()));
         }
         // Need to set this last so we do not overwrite the configurations.
         if let Some(trigger) = config.trigger {
             if let Err(e) = camera.set_trigger(trigger.into()) {
-                panic!("Failed to set acquisition mode {e:?}")
+                return Err(CameraError::setting("acquisition mode", format!("{e:?}")));
             }
         }
 
         if let Some(auto_packet_size) = config.auto_packet_size {
             if auto_packet_size {
                 if let Err(e) = camera.gv_auto_packet_size() {
-                    panic!("Failed to set auto streaming packet size (MTU) {e:?}")
+                    return Err(CameraError::setting(
+                        "auto streaming packet size (MTU)",
+                        format!("{e:?}"),
+                    ));
                 }
             }
         }
-        camera
+        Ok(camera)
+    }
+}
+
+/// Check the device runs at a frame rate, returning the range it runs at.
+/// Some cameras will fail silently if you try to put a higher FPS in than
+/// can be tolerated by the device, and genicam (xml) does not stop you
+/// putting an erroneous value in.
+///
+/// * `camera`: aravis camera handle.
+/// * `fps`: frame rate in Hz.
+#[cfg(feature = "camera-hw")]
+fn frame_rate_in_bounds(camera: &Camera, fps: u32) -> Result<(f64, f64), String> {
+    let (min, max) = camera
+        .frame_rate_bounds()
+        .map_err(|e| format!("Cannot determine frame rate bounds; {e}"))?;
+    if (min..max).contains(&f64::from(fps)) {
+        Ok((min, max))
+    } else {
+        Err(format!(
+            "Cannot set FPS as device range {min} to {max} does not allow {fps}"
+        ))
+    }
+}
+
+/// Region the device is set to for a region of interest. Setting the region
+/// requires some effort depending on if the sensor is utilising binning, see
+/// camera data sheet or Gig E vision specification to learn more.
+///
+/// * `camera`: aravis camera handle.
+/// * `roi`: region of interest in the config.
+#[cfg(feature = "camera-hw")]
+fn device_region(camera: &Camera, roi: Roi) -> Result<Roi, String> {
+    let (sensor_w, sensor_h) = camera
+        .sensor_size()
+        .map_err(|e| format!("Cannot determine the sensor size; {e}"))?;
+    let (mut x_inc, mut y_inc) = match (camera.width_increment(), camera.height_increment()) {
+        (Ok(x_inc), Ok(y_inc)) => (x_inc, y_inc),
+        (Err(e), _) | (_, Err(e)) => return Err(format!("Cannot determine the ROI increments; {e}")),
+    };
+    // With binning the region has to divide by every binning factor the
+    // sensor can be switched to, offsets as well as sizes.
+    if camera.is_binning_available().unwrap_or(false) {
+        let (Ok((_, max_x)), Ok((_, max_y))) = (camera.x_binning_bounds(), camera.y_binning_bounds()) else {
+            return Err(String::from(
+                "Cannot automatically determine the binning bounds for the camera",
+            ));
+        };
+        x_inc = (2..=max_x).step_by(2).fold(x_inc, lcm);
+        y_inc = (2..=max_y).step_by(2).fold(y_inc, lcm);
+    }
+    let region = roi.aligned_to(x_inc, y_inc);
+    if region != roi {
+        warn!("ROI {roi:?} is not a multiple of the increments ({x_inc}, {y_inc}), using {region:?}");
+    }
+    region
+        .validate(sensor_w, sensor_h, x_inc, y_inc)
+        .map_err(|e| format!("Invalid ROI {region:?} for the {sensor_w}x{sensor_h} sensor, {e}"))?;
+    Ok(region)
+}
+
+/// Least common multiple of two increments, so a region stepping by it
+/// steps by both.
+///
+/// * `a`: first increment.
+/// * `b`: second increment.
+#[cfg(feature = "camera-hw")]
+fn lcm(a: i32, b: i32) -> i32 {
+    let (mut x, mut y) = (a.abs(), b.abs());
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    if x == 0 {
+        0
+    } else {
+        (a / x * b).abs()
     }
 }
 
@@ -390,12 +539,16 @@ impl OnyxCamera {
 /// it is triggered. We create a closure to allow us to wrap the generation
 /// process with the region of interest (ROI) specifications that are required
 /// in the onyx system.
-fn make_buffer_closure(camera: &OnyxCamera) -> impl Fn() -> aravis::Buffer {
-    let (_, _, w, h) = camera.driver.region().expect("Failed to get buffer area");
-    let pixel_format = camera
-        .driver
-        .pixel_format()
-        .expect("Failed to get pixel format");
+#[cfg(feature = "camera-hw")]
+fn make_buffer_closure(camera: &OnyxCamera) -> Result<impl Fn() -> aravis::Buffer, CameraError> {
+    let (_, _, w, h) = camera.driver.region().map_err(|e| CameraError::Property {
+        property: "buffer area",
+        reason: format!("{e:?}"),
+    })?;
+    let pixel_format = camera.driver.pixel_format().map_err(|e| CameraError::Property {
+        property: "pixel format",
+        reason: format!("{e:?}"),
+    })?;
 
     //TODO: Look at the use of the offsets and what they actually
     // pertain to from the genicam standards. I believe it is a 
@@ -405,7 +558,7 @@ fn make_buffer_closure(camera: &OnyxCamera) -> impl Fn() -> aravis::Buffer {
     #[allow(clippy::cast_sign_loss)]
     // SAFETY: w and h should not be negative numbers anyway, could look into
     // changing the data type for the serialisation format to a usize anyway.
-    move || aravis::Buffer::new_leaked_image(pixel_format, w as usize, h as usize)
+    Ok(move || aravis::Buffer::new_leaked_image(pixel_format, w as usize, h as usize))
 }
 
 /// Device payloads contain data and information that is passed from a
@@ -422,6 +575,43 @@ pub struct DevicePayload {
     datetime: DateTime<Utc>,
     /// Location of device that took the image.
     location_id: Option<u8>,
+    /// Frame number from the camera, kept across device rebuilds.
+    sequence: u64,
+    /// Exposure time in microseconds last read from the device.
+    exposure_us: Option<f64>,
+    /// Region of interest the device was cropped to.
+    roi: Option<Roi>,
+}
+
+/// Label of a camera in the metrics, its location or its uuid when it has
+/// not been placed.
+///
+/// * `location_id`: location of the camera.
+/// * `uuid`: unique identifier of the camera.
+fn camera_label(location_id: Option<u8>, uuid: Uuid) -> String {
+    location_id.map_or_else(|| uuid.to_string(), |location_id| location_id.to_string())
+}
+
+/// Serialisable description of a [`DevicePayload`] without the pixels,
+/// written as a sidecar next to saved images for offline analysis.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PayloadMetadata {
+    /// Unique identifier of the camera that took the image.
+    pub camera_uuid: Uuid,
+    /// Image capture time.
+    pub captured_at: DateTime<Utc>,
+    /// Location of device that took the image.
+    pub location_id: Option<u8>,
+    /// Frame number from the camera, kept across device rebuilds.
+    pub sequence: u64,
+    /// Exposure time in microseconds last read from the device.
+    pub exposure_us: Option<f64>,
+    /// Region of interest the device was cropped to.
+    pub roi: Option<Roi>,
+    /// Width of the image in pixels.
+    pub width: u32,
+    /// Height of the image in pixels.
+    pub height: u32,
 }
 
 impl DevicePayload {
@@ -437,6 +627,343 @@ impl DevicePayload {
             format!("{}.png", self.datetime)
         }
     }
+
+    /// Image capture time.
+    pub fn captured_at(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    /// Location of the device that took the image.
+    pub fn location_id(&self) -> Option<u8> {
+        self.location_id
+    }
+
+    /// Camera that took the image as labelled in the metrics.
+    pub fn camera_label(&self) -> String {
+        camera_label(self.location_id, self.uuid)
+    }
+
+    /// Describe the payload for a metadata sidecar.
+    pub fn metadata(&self) -> PayloadMetadata {
+        PayloadMetadata {
+            camera_uuid: self.uuid,
+            captured_at: self.datetime,
+            location_id: self.location_id,
+            sequence: self.sequence,
+            exposure_us: self.exposure_us,
+            roi: self.roi,
+            width: self.image.width(),
+            height: self.image.height(),
+        }
+    }
+}
+
+/// Counters updated by the camera capture loop so the parent
+/// component can report on a device without stopping it.
+#[derive(Default, Debug)]
+pub struct CameraStats {
+    /// Frames successfully captured and sent to the component.
+    frames_captured: AtomicU64,
+    /// Frames that took longer than the frame interval and were discarded.
+    frames_late: AtomicU64,
+    /// Times the stream had to be restarted after a failed buffer.
+    stream_restarts: AtomicU64,
+    /// Times the parent component rebuilt the device after its thread exited.
+    pub(crate) restarts: AtomicU64,
+    /// Current back off in milliseconds before the next rebuild.
+    pub(crate) backoff_ms: AtomicU64,
+    /// Times the watchdog found the camera had stopped producing buffers.
+    pub(crate) stalls: AtomicU64,
+    /// UTC time in milliseconds the capture loop started, zero until then.
+    started_at_ms: AtomicU64,
+    /// UTC time in milliseconds of the last buffer taken off the stream.
+    last_buffer_at_ms: AtomicU64,
+}
+
+/// Milliseconds since the unix epoch, used for the stats timestamps.
+fn utc_now_ms() -> u64 {
+    u64::try_from(Utc::now().timestamp_millis()).unwrap_or_default()
+}
+
+impl CameraStats {
+    /// Time of the latest progress made by the capture loop, either the
+    /// last buffer or the start of the loop. `None` until the loop starts.
+    pub fn last_progress_ms(&self) -> Option<u64> {
+        let started_at_ms = self.started_at_ms.load(Ordering::Relaxed);
+        (started_at_ms != 0)
+            .then(|| started_at_ms.max(self.last_buffer_at_ms.load(Ordering::Relaxed)))
+    }
+
+    /// Forget the progress of a previous capture loop before the device
+    /// is rebuilt, so the watchdog waits for the new loop to start.
+    pub(crate) fn reset_progress(&self) {
+        self.started_at_ms.store(0, Ordering::Relaxed);
+    }
+
+    /// Milliseconds since the capture loop last made progress.
+    pub fn since_progress(&self) -> Option<Duration> {
+        self.last_progress_ms()
+            .map(|at| Duration::from_millis(utc_now_ms().saturating_sub(at)))
+    }
+
+    /// Take a point in time copy of the counters.
+    pub fn snapshot(&self) -> CameraStatsSnapshot {
+        CameraStatsSnapshot {
+            frames_captured: self.frames_captured.load(Ordering::Relaxed),
+            frames_late: self.frames_late.load(Ordering::Relaxed),
+            stream_restarts: self.stream_restarts.load(Ordering::Relaxed),
+            restarts: self.restarts.load(Ordering::Relaxed),
+            backoff_ms: self.backoff_ms.load(Ordering::Relaxed),
+            stalls: self.stalls.load(Ordering::Relaxed),
+            last_buffer_at_ms: self.last_buffer_at_ms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Serialisable copy of the [`CameraStats`] counters.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CameraStatsSnapshot {
+    /// Frames successfully captured and sent to the component.
+    pub frames_captured: u64,
+    /// Frames that took longer than the frame interval and were discarded.
+    pub frames_late: u64,
+    /// Times the stream had to be restarted after a failed buffer.
+    pub stream_restarts: u64,
+    /// Times the parent component rebuilt the device after its thread exited.
+    pub restarts: u64,
+    /// Current back off in milliseconds before the next rebuild.
+    pub backoff_ms: u64,
+    /// Times the watchdog found the camera had stopped producing buffers.
+    pub stalls: u64,
+    /// UTC time in milliseconds of the last buffer, zero if none yet.
+    pub last_buffer_at_ms: u64,
+}
+
+/// Commands sent from the parent component to a running capture loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraCommand {
+    /// Stop and start the stream without rebuilding the device.
+    RestartStream,
+    /// Exit the capture loop so the parent can rebuild the device.
+    Rebuild,
+}
+
+/// Result of asking a device for a single frame.
+pub enum Capture {
+    /// A complete image was retrieved from the device.
+    Frame(DynamicImage),
+    /// The device has not filled a buffer yet, try again.
+    Pending,
+    /// The buffer could not be converted, the stream needs a restart.
+    Failed,
+}
+
+/// Behaviour the capture loop in [`CameraController`] needs from a device.
+/// Implemented by the aravis backed [`OnyxCamera`] and by the simulated
+/// camera in `devices::software` so components can be tested without
+/// hardware on the network.
+pub trait ImageDevice {
+    /// Stream state created inside the capture thread. It never crosses
+    /// threads so the driver types do not need to be `Send`.
+    type Stream;
+
+    /// Return the unique identifier of the device.
+    fn get_uuid(&self) -> Uuid;
+
+    /// Location of the device on the crop bed as per bill of materials.
+    fn location_id(&self) -> Option<u8>;
+
+    /// Time between frames at the configured frame rate.
+    fn frame_interval(&self) -> Duration;
+
+    /// Read the current exposure time in microseconds, if the device has
+    /// one. Called when the stream opens and with the periodic configuration
+    /// rather than per frame, as it is a round trip to the device.
+    fn exposure_us(&mut self) -> Option<f64> {
+        None
+    }
+
+    /// Region of interest the device is cropped to.
+    fn roi(&self) -> Option<Roi> {
+        None
+    }
+
+    /// Create the stream and start acquisition.
+    fn open_stream(&mut self) -> Result<Self::Stream, CameraError>;
+
+    /// Trigger the device and try to take an image off the stream.
+    ///
+    /// * `stream`: stream returned by [`ImageDevice::open_stream`].
+    fn capture(&mut self, stream: &mut Self::Stream) -> Capture;
+
+    /// Recover the stream after a failed capture.
+    ///
+    /// * `stream`: stream returned by [`ImageDevice::open_stream`].
+    fn restart_stream(&mut self, stream: &mut Self::Stream);
+
+    /// Take care of device properties that have no auto mode and need to
+    /// be refreshed every few seconds.
+    fn periodic_configuration(&mut self) {}
+}
+
+/// Stream state for an [`OnyxCamera`].
+#[cfg(feature = "camera-hw")]
+pub struct OnyxCameraStream {
+    /// The aravis stream buffers are pushed to and popped from.
+    stream: aravis::Stream,
+    /// Creates buffers sized to the region of interest.
+    build_buffer: Box<dyn Fn() -> aravis::Buffer>,
+}
+
+#[cfg(feature = "camera-hw")]
+impl Device for OnyxCamera {
+    fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    fn location(&self) -> Option<u8> {
+        self.bed_location_id
+    }
+}
+
+#[cfg(feature = "camera-hw")]
+impl ImageDevice for OnyxCamera {
+    type Stream = OnyxCameraStream;
+
+    fn get_uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    fn location_id(&self) -> Option<u8> {
+        self.bed_location_id
+    }
+
+    fn frame_interval(&self) -> Duration {
+        Duration::from_secs_f64(
+            1.0 / self
+                .driver
+                .frame_rate()
+                .expect("Failed to get frame rate"),
+        )
+    }
+
+    fn exposure_us(&mut self) -> Option<f64> {
+        self.driver.exposure_time().ok()
+    }
+
+    fn roi(&self) -> Option<Roi> {
+        self.driver
+            .region()
+            .ok()
+            .map(|(x, y, w, h)| Roi { x, y, w, h })
+    }
+
+    fn open_stream(&mut self) -> Result<Self::Stream, CameraError> {
+        let build_buffer = Box::new(make_buffer_closure(self)?);
+        let stream = self.driver.create_stream().map_err(|e| CameraError::Stream {
+            action: "create camera stream",
+            reason: format!("{e:?}"),
+        })?;
+
+        stream.push_buffer(&build_buffer());
+
+        self.driver.start_acquisition().map_err(|e| CameraError::Stream {
+            action: "start camera acquisition",
+            reason: format!("{e:?}"),
+        })?;
+
+        Ok(OnyxCameraStream { stream, build_buffer })
+    }
+
+    fn capture(&mut self, stream: &mut Self::Stream) -> Capture {
+        // Trigger the camera with the software trigger as per genicam.
+        self.driver
+            .software_trigger()
+            .expect("Failed to trigger camera with Software");
+
+        // Attempt to take off an image. Delta for image name generation
+        // and sending the payload was less than a couple microseconds.
+        let Some(buffer) = stream.stream.try_pop_buffer() else {
+            return Capture::Pending;
+        };
+
+        // SAFETY: This function assumes the buffer is backed by a leaked box
+        #[allow(unsafe_code)]
+        if let Ok(dynamic_image) = unsafe { buffer.into_image() } {
+            stream.stream.push_buffer(&(stream.build_buffer)());
+            Capture::Frame(dynamic_image)
+        } else {
+            Capture::Failed
+        }
+    }
+
+    fn restart_stream(&mut self, stream: &mut Self::Stream) {
+        // Have seen instances in testing where the camera stream fails, which
+        // can be due to light, network bandwidths etc.
+        // TODO: May only need to use camera_stream.stop_thread() here which is
+        //       a soft thread stop without rebuilding the buffers. The current
+        //       implementation may be overkill, however there was limited time
+        //       to test this.
+        stream.stream.stop_thread(true);
+        stream.stream.start_thread();
+        stream.stream.push_buffer(&(stream.build_buffer)());
+    }
+
+    fn periodic_configuration(&mut self) {
+        // TODO: There are several of this &str's in the
+        //       genicam spec, remove them to there own
+        //       crate or module.
+        if let Err(e) = self.driver.execute_command("balanceWhiteAutoOnDemandCmd") {
+            panic!("Failed to call white balance {e}")
+        }
+    }
+}
+
+/// Used to line up the first frame of every camera in an array. Unlike a
+/// [`std::sync::Barrier`] a camera that never arrives (because it failed to build)
+/// only delays the others by the timeout, and cameras restarted later
+/// skip the gate entirely.
+pub struct StartGate {
+    /// Number of cameras that have arrived at the gate.
+    arrived: Mutex<usize>,
+    /// Woken once every camera has arrived.
+    all_arrived: Condvar,
+    /// Number of cameras expected at the gate.
+    expected: usize,
+    /// Longest time a camera will wait for the others.
+    timeout: Duration,
+}
+
+impl StartGate {
+    /// Create a gate for a number of cameras.
+    ///
+    /// * `expected`: number of cameras that will arrive.
+    /// * `timeout`: longest time any camera waits for the rest.
+    pub fn new(expected: usize, timeout: Duration) -> Self {
+        Self {
+            arrived: Mutex::new(0),
+            all_arrived: Condvar::new(),
+            expected,
+            timeout,
+        }
+    }
+
+    /// Arrive at the gate and wait for the other cameras, returning false
+    /// if the timeout elapsed first.
+    pub fn wait(&self) -> bool {
+        let mut arrived = self.arrived.lock().expect("Start gate poisoned");
+        *arrived += 1;
+        if *arrived >= self.expected {
+            self.all_arrived.notify_all();
+            return true;
+        }
+        let (arrived, result) = self
+            .all_arrived
+            .wait_timeout_while(arrived, self.timeout, |arrived| *arrived < self.expected)
+            .expect("Start gate poisoned");
+        drop(arrived);
+        !result.timed_out()
+    }
 }
 
 /// A camera controller unit struct is used to group the 
@@ -446,40 +973,32 @@ pub struct CameraController;
 
 impl CameraController {
     /// Start streaming images from the camera and sending the payload
-    /// back up to the parent component. TODO: Look into soft restart
-    /// recovery from failure.
+    /// back up to the parent component. Returns when the stop signal is
+    /// set, or with the error when the stream cannot be opened; a panic in
+    /// the device is left for the parent to recover.
     ///
     /// * `camera`: an onyx camera device
     /// * `stop_signal`: Will halt the camera streaming.
-    /// * `barrier`: Linked thread barrier for other camera devices.
+    /// * `start_gate`: Linked start gate for other camera devices, `None`
+    ///   when the camera is restarted on its own.
     /// * `image_channel`: MPSC channel for sharing payloads.
-    pub fn start(
-        camera: OnyxCamera,
+    /// * `stats`: Counters shared with the parent component.
+    /// * `commands`: Commands from the parent, i.e. from a watchdog.
+    pub fn start<D: ImageDevice>(
+        mut camera: D,
         stop_signal: Arc<AtomicBool>,
-        barrier: Arc<Barrier>,
+        start_gate: Option<Arc<StartGate>>,
         image_channel: Sender<DevicePayload>,
-    ) {
-        let uuid = camera.uuid;
-        let build_buffer = make_buffer_closure(&camera);
-        let interval_ms = Duration::from_secs_f64(
-            1.0 / camera
-                .driver
-                .frame_rate()
-                .expect("Failed to get frame rate"),
-        )
-        .as_millis();
-
-        let camera_stream = camera
-            .driver
-            .create_stream()
-            .expect("Unable to create camera stream");
-
-        camera_stream.push_buffer(&build_buffer());
-
-        camera
-            .driver
-            .start_acquisition()
-            .expect("Unable to start camera acquisition");
+        stats: Arc<CameraStats>,
+        commands: Receiver<CameraCommand>,
+    ) -> Result<(), CameraError> {
+        let uuid = camera.get_uuid();
+        let label = camera_label(camera.location_id(), uuid);
+        let _span = tracing::info_span!("camera", uuid = %uuid, bed_position = camera.location_id()).entered();
+        let interval_ms = camera.frame_interval().as_millis();
+        let mut stream = camera.open_stream()?;
+        let roi = camera.roi();
+        let mut exposure_us = camera.exposure_us();
 
         // Some cameras don't have auto white balance, or auto gain etc.
         // so they have to be manually implemented during the camera capture
@@ -493,64 +1012,77 @@ impl CameraController {
         // TODO: Review sync primitives to asses drift between
         // cameras. May be more involved if you are also going 
         // to sync the light actuation system.
-        barrier.wait();
+        if let Some(start_gate) = start_gate {
+            if !start_gate.wait() {
+                info!("Camera {uuid} started without the rest of the array");
+            }
+        }
+        stats.started_at_ms.store(utc_now_ms(), Ordering::Relaxed);
         while !stop_signal.load(Ordering::Relaxed) {
             let tick = Instant::now();
 
+            match commands.try_recv() {
+                Ok(CameraCommand::RestartStream) => {
+                    camera.restart_stream(&mut stream);
+                    stats.stream_restarts.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(CameraCommand::Rebuild) => break,
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => {}
+            }
+
             // Take care of non auto based camera properties.
-            // TODO: There are several of this &str's in the
-            //       genicam spec, remove them to there own
-            //       crate or module.
             if config_tick.elapsed().as_secs() > config_limit {
-                if let Err(e) = camera.driver.execute_command("balanceWhiteAutoOnDemandCmd") {
-                    panic!("Failed to call white balance {e}")
-                }
+                camera.periodic_configuration();
+                exposure_us = camera.exposure_us();
                 // reset the ticker.
                 config_tick = Instant::now();
             }
 
-            // Trigger the camera with the software trigger as per genicam.
-            camera
-                .driver
-                .software_trigger()
-                .expect("Failed to trigger camera with Software");
-
-            // Attempt to take off an image. Delta for image name generation
-            // and sending the payload was less than a couple microseconds.
-            if let Some(buffer) = camera_stream.try_pop_buffer() {
-                let delta_ms = tick.elapsed().as_millis();
-
-                // SAFETY: This function assumes the buffer is backed by a leaked box
-                #[allow(unsafe_code)]
-                if let Ok(dynamic_image) = unsafe { buffer.into_image() } {
+            match camera.capture(&mut stream) {
+                Capture::Frame(dynamic_image) => {
+                    let delta_ms = tick.elapsed().as_millis();
                     let utc_time = Utc::now();
+                    stats.last_buffer_at_ms.store(utc_now_ms(), Ordering::Relaxed);
 
-                    camera_stream.push_buffer(&build_buffer());
                     if delta_ms < interval_ms {
                         let sleep_ms = interval_ms - delta_ms;
                         let payload = DevicePayload {
                             uuid,
                             image: dynamic_image,
                             datetime: utc_time,
-                            location_id: camera.bed_location_id,
+                            location_id: camera.location_id(),
+                            sequence: stats.frames_captured.fetch_add(1, Ordering::Relaxed),
+                            exposure_us,
+                            roi,
                         };
-                        image_channel.send(payload).unwrap();
+                        metrics::frame_captured(&label);
+                        // Checked before the fields are gathered, so a
+                        // disabled level costs a frame next to nothing.
+                        if tracing::enabled!(Level::TRACE) {
+                            trace!(sequence = payload.sequence, delta_ms = ?delta_ms, "Frame captured");
+                        }
+                        // The array has shut down if the receiver is gone.
+                        if image_channel.send(payload).is_err() {
+                            break;
+                        }
                         std::thread::sleep(Duration::from_millis(sleep_ms as u64));
+                    } else {
+                        stats.frames_late.fetch_add(1, Ordering::Relaxed);
+                        metrics::frame_dropped(&label, "late");
+                        if tracing::enabled!(Level::TRACE) {
+                            trace!(delta_ms = ?delta_ms, interval_ms = ?interval_ms, "Frame dropped, captured late");
+                        }
                     }
-                } else {
-                    // Have seen instances in testing where the camera stream fails, which
-                    // can be due to light, network bandwidths etc.
-                    // TODO: May only need to use camera_stream.stop_thread() here which is
-                    //       a soft thread stop without rebuilding the buffers. The current
-                    //       implementation may be overkill, however there was limited time
-                    //       to test this.
-                    camera_stream.stop_thread(true);
-                    camera_stream.start_thread();
-                    camera_stream.push_buffer(&build_buffer());
                 }
+                Capture::Failed => {
+                    debug!("Capture failed, restarting the stream");
+                    camera.restart_stream(&mut stream);
+                    stats.stream_restarts.fetch_add(1, Ordering::Relaxed);
+                }
+                Capture::Pending => {}
             }
         }
-        barrier.wait();
+        Ok(())
     }
 }
 
@@ -558,73 +1090,101 @@ impl CameraController {
 mod tests {
 
     use super::*;
-    use crate::test_file_path;
-    use aravis::PixelFormat;
+    use crate::utils::paths::repo_relative;
+    #[cfg(feature = "camera-hw")]
     use serial_test::serial;
+    #[cfg(feature = "camera-hw")]
     use std::{
         fs::{self, create_dir_all},
         path::PathBuf,
-        str::FromStr,
         sync::mpsc,
         thread,
     };
 
+    #[cfg(feature = "camera-hw")]
     #[test]
-    #[serial]
+    /// Binning factors fold into the sensor increment so a region stepping
+    /// by the result steps by every factor.
+    fn test_binning_increment() {
+        assert_eq!((2..=4).step_by(2).fold(8, lcm), 8);
+        assert_eq!((2..=8).step_by(2).fold(12, lcm), 24);
+        assert_eq!(lcm(1, 2), 2);
+        assert_eq!(lcm(0, 2), 0);
+    }
+
+    #[cfg(feature = "camera-hw")]
+    #[test]
+    /// A camera whose config file is missing is refused before aravis is
+    /// asked for it, and refused settings read as they were logged.
+    fn test_camera_errors() {
+        let path = std::env::temp_dir().join("onyx-missing-camera.yaml");
+        let error = OnyxCamera::from_config_file(&path)
+            .err()
+            .expect("Missing config was accepted");
+        assert!(
+            matches!(error, CameraError::Config(ConfigError::Read { .. })),
+            "{error}"
+        );
+        assert!(error.to_string().contains(&format!("{path:?}")), "{error}");
+        assert_eq!(
+            CameraError::setting("frame rate", String::from("out of range")).to_string(),
+            "Failed to set frame rate out of range"
+        );
+    }
+
+    #[test]
+    /// The camera configs in the repository are valid, one with no frame
+    /// rate, no address, an empty region and inverted exposure bounds has
+    /// each reported.
+    fn test_validate_camera_config() {
+        for position in 0..=5 {
+            let path = repo_relative(format!("config/devices/crop_bed/camera_{position}.yaml")).unwrap();
+            let report = crate::utils::config::validate_file::<OnyxCameraConfig>(&path);
+            assert!(report.is_valid(), "{report}");
+        }
+
+        let broken = OnyxCameraConfig {
+            roi: Some(Roi { x: -8, y: 0, w: 0, h: 1024 }),
+            exposure_min: Some(30_000),
+            exposure_max: Some(100),
+            ..OnyxCameraConfig::new(Ipv4Addr::UNSPECIFIED, 0)
+        };
+        let mut report = ValidationReport::new("camera.yaml");
+        broken.validate(&mut report);
+        assert_eq!(report.fields(), ["fps", "ip_address", "roi", "exposure_min"], "{report}");
+        assert!(report.problems()[2].message.contains("no area"), "{report}");
+
+        let negative = OnyxCameraConfig {
+            exposure_min: Some(-1),
+            ..OnyxCameraConfig::new(Ipv4Addr::new(169, 254, 8, 10), 3)
+        };
+        let mut report = ValidationReport::new("camera.yaml");
+        negative.validate(&mut report);
+        assert_eq!(report.fields(), ["exposure_min"], "{report}");
+    }
+
+    #[test]
+    /// Camera configs from the generator read back as they were written,
+    /// with every setting the crop bed cameras run.
     fn test_write_camera_configs() {
-        let ips: Vec<(u8, &str, u8)> = vec![
-            (0, "169.254.8.10", 0),
-            (1, "169.254.8.11", 1),
-            (2, "169.254.8.12", 0),
-            (3, "169.254.8.13", 1),
-            (4, "169.254.8.14", 0),
-            (5, "169.254.8.15", 1),
-        ];
-
-        for (id, ip, bed_id) in ips {
-            let mut config =
-                OnyxCameraConfig::new(Ipv4Addr::from_str(ip).expect("Failed to create address"), 3);
-            config.roi = Some(Roi {
-                x: 0,
-                y: 0,
-                h: 1024,
-                w: 1280,
-            });
-            config.pixel_format = Some(CameraPixelFormat(PixelFormat::BAYER_RG_8));
-            config.acquisition_mode = Some(WrapperAcquisitionMode(AcquisitionMode::Continuous));
-            config.auto_packet_size = Some(true);
-            config.trigger = Some(DeviceTrigger::Software);
-            config.bed_location_id = Some(bed_id);
-            config.auto_brightness = Some(true);
-            config.auto_gain = Some(true);
-            config.exposure_min = Some(100);
-            config.exposure_max = Some(30000);
-            config.auto_exposure = Some(true);
-
-            let f = std::fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open(format!(
-                    "{}/config/devices/crop_bed/camera_{id}.yaml",
-                    env!("CARGO_MANIFEST_DIR")
-                ))
-                .expect("Couldn't open file");
-
-            serde_yaml::to_writer(f, &config).unwrap();
-
-            let x = std::fs::File::open(format!(
-                "{}/config/devices/crop_bed/camera_{id}.yaml",
-                env!("CARGO_MANIFEST_DIR")
-            ))
-            .expect("Could not open file.");
-
-            let read_config: OnyxCameraConfig =
-                serde_yaml::from_reader(&x).expect("Could not read values.");
-
-            assert!(config == read_config, "Failed to be created equally");
+        use crate::utils::configgen::{write_config, DEFAULT_FPS};
+
+        let dir = std::env::temp_dir().join(format!("onyx-camera-configs-{}", Uuid::new_v4()));
+        for (id, bed_location_id) in [0, 1, 0, 1, 0, 1].into_iter().enumerate() {
+            let ip_address = Ipv4Addr::new(169, 254, 8, 10 + u8::try_from(id).unwrap());
+            let config = OnyxCameraConfig::crop_bed(ip_address, DEFAULT_FPS, bed_location_id);
+            let path = dir.join(format!("camera_{id}.yaml"));
+            write_config(&path, &config).unwrap();
+
+            let read_config = OnyxCameraConfig::try_from_file(&path).unwrap();
+            assert_eq!(config, read_config, "Failed to be created equally");
+            assert_eq!(read_config.bed_location_id, Some(bed_location_id));
+            assert_eq!(read_config.exposure_min.zip(read_config.exposure_max), Some((100, 30000)));
         }
+        std::fs::remove_dir_all(dir).unwrap();
     }
 
+    #[cfg(feature = "camera-hw")]
     #[cfg_attr(not(feature = "hardware_test"), ignore)]
     #[test]
     #[serial]
@@ -632,19 +1192,30 @@ mod tests {
     /// this type of development is helpful when trouble shooting new device 
     /// implementations.
     fn test_camera_run_without_component() {
-        let file = test_file_path!("/config/devices/crop_bed/camera_0.yaml");
-        let camera = OnyxCamera::from_config_file(file);
-        let config = OnyxCameraConfig::from_file(file);
+        let file = repo_relative("config/devices/crop_bed/camera_0.yaml").unwrap_or_else(|e| panic!("{e}"));
+        let camera = OnyxCamera::from_config_file(&file).unwrap_or_else(|e| panic!("{e}"));
+        let config = OnyxCameraConfig::try_from_file(&file).unwrap();
 
-        let barrier = Arc::new(Barrier::new(1));
+        let start_gate = Arc::new(StartGate::new(1, Duration::from_secs(1)));
         let stop_signal = Arc::new(AtomicBool::new(false));
         let (device_channel_tx, device_channel_rx) = mpsc::channel::<DevicePayload>();
 
         let controller_stop_signal = stop_signal.clone();
+        let stats = Arc::new(CameraStats::default());
+        let controller_stats = stats.clone();
+        let (_commands_tx, commands_rx) = mpsc::channel::<CameraCommand>();
 
         // Start the devices doing the work on separate threads.
         let controller_handle = thread::spawn(|| {
-            CameraController::start(camera, controller_stop_signal, barrier, device_channel_tx);
+            CameraController::start(
+                camera,
+                controller_stop_signal,
+                Some(start_gate),
+                device_channel_tx,
+                controller_stats,
+                commands_rx,
+            )
+            .unwrap_or_else(|e| panic!("{e}"));
         });
 
         // Start a writing thread that deals with sending the images to disk.
@@ -685,6 +1256,11 @@ mod tests {
             .count();
 
         let expected = 5 * config.fps;
+        assert_eq!(
+            stats.snapshot().frames_captured as usize,
+            images_count,
+            "Captured frame count does not match images on disk"
+        );
 
         assert!(
             images_count.abs_diff(expected as usize) < 5,
@@ -0,0 +1,401 @@
+use super::{device_region, frame_rate_in_bounds, CameraError, Capture, ImageDevice, OnyxCameraConfig};
+use aravis::{Camera, CameraExt};
+use image::DynamicImage;
+use serde::Serialize;
+use std::{
+    fmt::Display,
+    net::{Ipv4Addr, UdpSocket},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Protocol aravis reports for the GigE Vision devices.
+const GIGE_VISION_PROTOCOL: &str = "GigEVision";
+
+/// Port of the GigE Vision control channel, used to find the host address
+/// a camera is reached from.
+const GVCP_PORT: u16 = 3956;
+
+/// Time between the captures while waiting on a frame.
+const SNAP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A GigE Vision device answering on the network.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiscoveredCamera {
+    /// Network address of the device.
+    pub ip_address: String,
+    /// Serial number of the device.
+    pub serial: String,
+    /// Model name of the device.
+    pub model: String,
+    /// Firmware version, when the device could be opened to read it.
+    pub firmware: Option<String>,
+}
+
+/// Find the GigE Vision devices answering on the network, opening each to
+/// read its firmware version.
+pub fn discover() -> Vec<DiscoveredCamera> {
+    aravis::update_device_list();
+    (0..aravis::n_devices())
+        .filter(|index| {
+            aravis::device_protocol(*index).is_some_and(|protocol| protocol.as_str() == GIGE_VISION_PROTOCOL)
+        })
+        .map(|index| {
+            let firmware = aravis::device_id(index)
+                .and_then(|id| Camera::new(Some(id.as_str())).ok())
+                .and_then(|camera| camera.string("DeviceFirmwareVersion").ok())
+                .map(String::from);
+            DiscoveredCamera {
+                ip_address: aravis::device_address(index).map(String::from).unwrap_or_default(),
+                serial: aravis::device_serial_nbr(index).map(String::from).unwrap_or_default(),
+                model: aravis::device_model(index).map(String::from).unwrap_or_default(),
+                firmware,
+            }
+        })
+        .collect()
+}
+
+/// Table of the devices found, a header then a line per device with the
+/// columns padded to the widest cell.
+///
+/// * `cameras`: devices found.
+pub fn discovery_table(cameras: &[DiscoveredCamera]) -> String {
+    let mut rows = vec![["ip", "serial", "model", "firmware"].map(String::from)];
+    rows.extend(cameras.iter().map(|camera| {
+        [
+            camera.ip_address.clone(),
+            camera.serial.clone(),
+            camera.model.clone(),
+            camera.firmware.clone().unwrap_or_else(|| String::from("-")),
+        ]
+    }));
+    let widths: Vec<usize> = (0..4)
+        .map(|column| rows.iter().map(|row| row[column].len()).max().unwrap_or(0))
+        .collect();
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, &width)| format!("{cell:<width$}"))
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// How a check of a camera came out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckOutcome {
+    /// The device allows what the config asks for.
+    Pass(String),
+    /// The component would fail to build the camera from the config.
+    Fail(String),
+    /// Read from the device for the person bringing up the bed, not
+    /// checked.
+    Info(String),
+}
+
+/// One check of a camera against its config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CameraCheck {
+    /// What was checked, i.e. `fps`.
+    pub name: &'static str,
+    /// How it came out.
+    pub outcome: CheckOutcome,
+}
+
+/// Checks of a camera against its config, the bounds checked as the
+/// component checks them when it builds the camera.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CameraCheckReport {
+    /// Network address of the camera.
+    ip_address: Ipv4Addr,
+    /// Checks in the order they were made.
+    checks: Vec<CameraCheck>,
+}
+
+impl CameraCheckReport {
+    /// Report without checks.
+    ///
+    /// * `ip_address`: network address of the camera.
+    pub fn new(ip_address: Ipv4Addr) -> Self {
+        Self {
+            ip_address,
+            checks: Vec::new(),
+        }
+    }
+
+    /// Add a check.
+    ///
+    /// * `name`: what was checked.
+    /// * `outcome`: how it came out.
+    pub fn push(&mut self, name: &'static str, outcome: CheckOutcome) {
+        self.checks.push(CameraCheck { name, outcome });
+    }
+
+    /// Add a check that passed or failed.
+    ///
+    /// * `name`: what was checked.
+    /// * `result`: what passed, or what failed.
+    pub fn push_result(&mut self, name: &'static str, result: Result<String, String>) {
+        let outcome = match result {
+            Ok(message) => CheckOutcome::Pass(message),
+            Err(message) => CheckOutcome::Fail(message),
+        };
+        self.push(name, outcome);
+    }
+
+    /// Checks in the order they were made.
+    pub fn checks(&self) -> &[CameraCheck] {
+        &self.checks
+    }
+
+    /// Whether no check failed.
+    pub fn passed(&self) -> bool {
+        !self
+            .checks
+            .iter()
+            .any(|check| matches!(check.outcome, CheckOutcome::Fail(_)))
+    }
+}
+
+impl Display for CameraCheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let failed = self
+            .checks
+            .iter()
+            .filter(|check| matches!(check.outcome, CheckOutcome::Fail(_)))
+            .count();
+        if failed == 0 {
+            write!(f, "Camera {} passed its checks:", self.ip_address)?;
+        } else {
+            write!(f, "Camera {} failed {failed} checks:", self.ip_address)?;
+        }
+        for check in &self.checks {
+            let (mark, message) = match &check.outcome {
+                CheckOutcome::Pass(message) => ("ok", message),
+                CheckOutcome::Fail(message) => ("FAIL", message),
+                CheckOutcome::Info(message) => ("info", message),
+            };
+            write!(f, "\n  {mark:<4}  {}: {message}", check.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Host address the camera is reached from, the interface the route to it
+/// goes out of.
+///
+/// * `ip_address`: network address of the camera.
+fn host_address(ip_address: Ipv4Addr) -> Option<Ipv4Addr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((ip_address, GVCP_PORT)).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        std::net::IpAddr::V4(address) => Some(address),
+        std::net::IpAddr::V6(_) => None,
+    }
+}
+
+/// Connect to the camera of a config and check the device allows the frame
+/// rate, region and exposure bounds it asks for, reading the packet size
+/// and link speed. The packet size is negotiated first when the config
+/// asks for it, as the component does.
+///
+/// * `config`: config of the camera, validated.
+pub fn check_camera(config: &OnyxCameraConfig) -> CameraCheckReport {
+    let mut report = CameraCheckReport::new(config.ip_address);
+    let camera = match Camera::new(Some(&config.ip_address.to_string())) {
+        Ok(camera) => camera,
+        Err(e) => {
+            report.push("connect", CheckOutcome::Fail(format!("Failed to create camera {e:?}")));
+            return report;
+        }
+    };
+    let read = |feature: &str| {
+        camera
+            .string(feature)
+            .map_or_else(|_| String::from("unknown"), String::from)
+    };
+    report.push(
+        "connect",
+        CheckOutcome::Pass(format!(
+            "{} serial {}, firmware {}",
+            read("DeviceModelName"),
+            read("DeviceSerialNumber"),
+            read("DeviceFirmwareVersion")
+        )),
+    );
+    report.push_result(
+        "fps",
+        frame_rate_in_bounds(&camera, config.fps)
+            .map(|(min, max)| format!("{} fps within the device range {min} to {max}", config.fps)),
+    );
+    if let Some(roi) = config.roi {
+        report.push_result(
+            "roi",
+            device_region(&camera, roi).map(|region| {
+                if region == roi {
+                    format!("{roi:?} fits the sensor")
+                } else {
+                    format!("{roi:?} fits the sensor aligned to {region:?}")
+                }
+            }),
+        );
+    }
+    if config.exposure_min.is_some() || config.exposure_max.is_some() {
+        report.push_result(
+            "exposure",
+            camera
+                .exposure_time_bounds()
+                .map_err(|e| format!("Cannot determine exposure bounds; {e}"))
+                .and_then(|(min, max)| {
+                    let outside: Vec<String> = [
+                        ("exposure_min", config.exposure_min),
+                        ("exposure_max", config.exposure_max),
+                    ]
+                    .into_iter()
+                    .filter_map(|(field, exposure)| Some((field, exposure?)))
+                    .filter(|(_, exposure)| !(min..=max).contains(&f64::from(*exposure)))
+                    .map(|(field, exposure)| format!("{field} {exposure}us"))
+                    .collect();
+                    if outside.is_empty() {
+                        Ok(format!("within the device range {min}us to {max}us"))
+                    } else {
+                        Err(format!(
+                            "{} outside the device range {min}us to {max}us",
+                            outside.join(" and ")
+                        ))
+                    }
+                }),
+        );
+    }
+    if config.auto_packet_size == Some(true) {
+        if let Err(e) = camera.gv_auto_packet_size() {
+            report.push(
+                "mtu",
+                CheckOutcome::Fail(format!("Failed to set auto streaming packet size (MTU) {e:?}")),
+            );
+        }
+    }
+    report.push(
+        "mtu",
+        CheckOutcome::Info(camera.gv_packet_size().map_or_else(
+            |e| format!("packet size unknown, {e}"),
+            |size| format!("packet size {size} bytes"),
+        )),
+    );
+    report.push(
+        "link",
+        CheckOutcome::Info(
+            camera
+                .integer("GevLinkSpeed")
+                .map_or_else(|_| String::from("speed unknown"), |speed| format!("{speed} Mb/s")),
+        ),
+    );
+    report.push(
+        "route",
+        CheckOutcome::Info(host_address(config.ip_address).map_or_else(
+            || String::from("no route to the camera"),
+            |address| format!("reached from {address}"),
+        )),
+    );
+    report
+}
+
+/// Take one frame off a device, restarting the stream when a capture fails
+/// as the capture loop does. `None` when no frame arrives in time, an error
+/// when the stream cannot be opened.
+///
+/// * `device`: device built from its config.
+/// * `timeout`: longest time waited for the frame.
+pub fn snap<D: ImageDevice>(device: &mut D, timeout: Duration) -> Result<Option<DynamicImage>, CameraError> {
+    let mut stream = device.open_stream()?;
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        match device.capture(&mut stream) {
+            Capture::Frame(image) => return Ok(Some(image)),
+            Capture::Pending => thread::sleep(SNAP_POLL_INTERVAL),
+            Capture::Failed => device.restart_stream(&mut stream),
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::software::camera::{SimulatedCamera, SimulatedCameraConfig};
+
+    #[test]
+    /// The discovery table lines up its columns under the header, with a
+    /// dash for a firmware that could not be read.
+    fn test_discovery_table() {
+        let cameras = [
+            DiscoveredCamera {
+                ip_address: String::from("169.254.8.10"),
+                serial: String::from("21345678"),
+                model: String::from("Blackfly S BFS-PGE-16S2C"),
+                firmware: Some(String::from("1808.0.113.0")),
+            },
+            DiscoveredCamera {
+                ip_address: String::from("169.254.8.100"),
+                serial: String::from("7"),
+                model: String::from("acA1300"),
+                firmware: None,
+            },
+        ];
+        assert_eq!(
+            discovery_table(&cameras),
+            "ip             serial    model                     firmware\n\
+             169.254.8.10   21345678  Blackfly S BFS-PGE-16S2C  1808.0.113.0\n\
+             169.254.8.100  7         acA1300                   -"
+        );
+        assert_eq!(discovery_table(&[]), "ip  serial  model  firmware");
+    }
+
+    #[test]
+    /// A report passes unless a check failed, and prints a line per check
+    /// marked with how it came out.
+    fn test_check_report() {
+        let mut report = CameraCheckReport::new(Ipv4Addr::new(169, 254, 8, 10));
+        report.push_result("fps", Ok(String::from("15 fps within the device range 1 to 30")));
+        report.push("mtu", CheckOutcome::Info(String::from("packet size 8164 bytes")));
+        assert!(report.passed());
+        assert_eq!(
+            report.to_string(),
+            "Camera 169.254.8.10 passed its checks:\n  \
+             ok    fps: 15 fps within the device range 1 to 30\n  \
+             info  mtu: packet size 8164 bytes"
+        );
+
+        report.push_result("roi", Err(String::from("Invalid ROI for the 1280x1024 sensor")));
+        assert!(!report.passed());
+        assert_eq!(report.checks().len(), 3);
+        assert_eq!(
+            report.to_string().lines().collect::<Vec<_>>(),
+            [
+                "Camera 169.254.8.10 failed 1 checks:",
+                "  ok    fps: 15 fps within the device range 1 to 30",
+                "  info  mtu: packet size 8164 bytes",
+                "  FAIL  roi: Invalid ROI for the 1280x1024 sensor",
+            ]
+        );
+    }
+
+    #[test]
+    /// A snap returns the first frame of the device, and nothing from one
+    /// that has stopped filling buffers.
+    fn test_snap() {
+        let mut camera = SimulatedCamera::new(SimulatedCameraConfig::new(Some(0), 10, 64, 48));
+        let image = snap(&mut camera, Duration::from_secs(1))
+            .unwrap()
+            .expect("No frame captured");
+        assert_eq!((image.width(), image.height()), (64, 48));
+
+        let stalled = SimulatedCameraConfig::new(Some(0), 10, 64, 48).with_stall_after_frames(0, true);
+        let mut camera = SimulatedCamera::new(stalled);
+        assert!(snap(&mut camera, Duration::from_millis(100)).unwrap().is_none());
+    }
+}
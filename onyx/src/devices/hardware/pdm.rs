@@ -1,15 +1,277 @@
-use ix3212_pdm::{pdm::Pdm as PdmDriver, prelude::*};
-use serde::{Deserialize, Serialize, Serializer};
-use socketcan::tokio::CanSocket as AsyncCanSocket;
+#[cfg(feature = "canbus")]
+use crate::devices::traits::{Device, DeviceConfig};
+#[cfg(feature = "canbus")]
+use crate::utils::location::GeoPosition;
+use crate::utils::{
+    config::{load_yaml, ConfigError, Validate, ValidationReport},
+    serde::ordered_map,
+};
+#[cfg(feature = "canbus")]
+use ix3212_pdm::pdm::Pdm as PdmDriver;
+use ix3212_pdm::prelude::*;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "canbus")]
+use socketcan::{tokio::CanSocket as AsyncCanSocket, CanFrame, EmbeddedFrame, ExtendedId, Frame};
 use std::{
     collections::{BTreeMap, HashMap},
     ffi::OsStr,
-    path::Path,
-    sync::Arc,
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+#[cfg(feature = "canbus")]
+use std::{convert::Infallible, sync::Arc};
+#[cfg(feature = "canbus")]
+use tokio::{
+    sync::{broadcast, watch, Mutex},
+    task::JoinHandle,
+    time::Instant,
 };
-use tokio::sync::Mutex;
+#[cfg(feature = "canbus")]
+use tracing::{debug, warn, Instrument, Span};
+#[cfg(feature = "canbus")]
 use uuid::Uuid;
 
+/// J1939 framing used to confirm the PDM configuration.
+pub mod frames;
+
+/// Pin strapped source addresses of the PDMs.
+pub mod address;
+
+pub use address::PdmAddress;
+
+/// Record of the actuations sent to a PDM.
+pub mod audit;
+
+pub use audit::ActuationAuditRecord;
+
+/// Wear counters for each channel.
+pub mod usage;
+
+pub use usage::ChannelUsage;
+
+use frames::AckControl;
+pub use frames::{ChannelFaults, ChannelFeedback, PdmStatus};
+#[cfg(feature = "canbus")]
+use frames::{J1939Frame, ADDRESS_CLAIMED_PGN};
+pub use frames::{ChannelFaults, ChannelFeedback, PdmStatus};
+
+/// Time to wait for the PDM to answer on the bus when no timeout is set
+/// in its config.
+const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Command id the channels are actuated with when none is set in the
+/// config, that of the current ix-3212 firmware.
+pub const DEFAULT_ACTUATE_COMMAND_ID: u8 = 17;
+
+/// Time to listen for a rejection after configuring a single channel.
+#[cfg(feature = "canbus")]
+const CONFIGURATION_ACK_WINDOW: Duration = Duration::from_millis(50);
+
+/// Feedback frames buffered for each subscriber before the oldest are
+/// skipped.
+#[cfg(feature = "canbus")]
+const FEEDBACK_CAPACITY: usize = 256;
+
+/// Longest time between the steps of a ramp, well inside the one second
+/// after which the PDM applies its loss of communication outputs.
+const RAMP_STEP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Last feedback received for each channel and when it arrived.
+#[cfg(feature = "canbus")]
+type FeedbackSnapshot = Arc<std::sync::Mutex<HashMap<u8, (ChannelFeedback, Instant)>>>;
+
+/// Configuration message a PDM can reject.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdmConfiguration {
+    /// Output function of a channel.
+    OutputFunction,
+    /// Load control, feedback and current limit of a channel.
+    OutputChannel,
+}
+
+#[cfg(feature = "canbus")]
+impl PdmConfiguration {
+    /// Code identifying the configuration in read back messages.
+    fn code(self) -> u8 {
+        match self {
+            PdmConfiguration::OutputFunction => 0,
+            PdmConfiguration::OutputChannel => 1,
+        }
+    }
+}
+
+/// What a component does when a PDM no longer matches its config, for
+/// example after a brown out has reset it.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DriftPolicy {
+    /// Log the mismatched channels and keep running.
+    #[default]
+    Log,
+    /// Log the mismatched channels and stop driving the PDM.
+    Refuse,
+}
+
+/// When components read back and verify the PDM configuration.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PdmVerification {
+    /// Seconds between checks while running, only checked at start up
+    /// when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_secs: Option<u64>,
+    /// What to do when a PDM has drifted.
+    #[serde(default)]
+    pub on_drift: DriftPolicy,
+}
+
+impl PdmVerification {
+    /// Time between checks while running.
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval_secs
+            .map(|interval_secs| Duration::from_secs(interval_secs.max(1)))
+    }
+}
+
+/// A channel whose configuration on the PDM differs from its config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigurationMismatch {
+    /// Channel number.
+    pub channel: u8,
+    /// Configuration that differs.
+    pub configuration: PdmConfiguration,
+    /// Configuration from the config file.
+    pub expected: String,
+    /// Configuration reported by the PDM, `None` when it did not answer.
+    pub actual: Option<String>,
+}
+
+impl Display for ConfigurationMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "channel {} {:?} expected {} found {}",
+            self.channel,
+            self.configuration,
+            self.expected,
+            self.actual.as_deref().unwrap_or("no report")
+        )
+    }
+}
+
+/// Configuration that can be read back off the bus. The ix-3212 payloads
+/// are bitfields, so the bytes reported by the PDM are the bytes of the
+/// payload.
+#[cfg(feature = "canbus")]
+trait ReadBack: Clone + PartialEq + std::fmt::Debug + Sized {
+    /// Bytes of the configuration as they appear on the bus.
+    fn to_payload(&self) -> Vec<u8>;
+    /// Configuration from the bytes reported by the PDM.
+    ///
+    /// * `payload`: reported bytes.
+    fn from_payload(payload: &[u8]) -> Option<Self>;
+}
+
+#[cfg(feature = "canbus")]
+impl ReadBack for OutputFunctionConfigPayload {
+    fn to_payload(&self) -> Vec<u8> {
+        self.clone().into_bytes().to_vec()
+    }
+
+    fn from_payload(payload: &[u8]) -> Option<Self> {
+        payload.try_into().ok().map(Self::from_bytes)
+    }
+}
+
+#[cfg(feature = "canbus")]
+impl ReadBack for ChannelConfig {
+    fn to_payload(&self) -> Vec<u8> {
+        self.clone().into_bytes().to_vec()
+    }
+
+    fn from_payload(payload: &[u8]) -> Option<Self> {
+        payload.try_into().ok().map(Self::from_bytes)
+    }
+}
+
+/// Reasons a PDM could not be initialised.
+#[derive(Debug, thiserror::Error)]
+pub enum PdmError {
+    /// Reading or writing the canbus socket failed.
+    #[error("canbus socket error {0}")]
+    Socket(#[from] io::Error),
+    /// The PDM has not been given an interface by [`Pdm::initialise`].
+    #[error("PDM {address} has not been initialised on an interface")]
+    NotInitialised {
+        /// Source address of the PDM.
+        address: u8,
+    },
+    /// The PDM did not answer, it is likely unpowered or on another bus.
+    #[error("no response from PDM {address} after {waited:?}, check it is powered and on this bus")]
+    Timeout {
+        /// Source address of the PDM.
+        address: u8,
+        /// Time waited for the PDM.
+        waited: Duration,
+    },
+    /// The PDM rejected the configuration of a channel.
+    #[error("PDM {address} rejected the {configuration:?} configuration of channel {channel} ({control:?})")]
+    ChannelRejected {
+        /// Source address of the PDM.
+        address: u8,
+        /// Channel that was being configured.
+        channel: u8,
+        /// Configuration that was rejected.
+        configuration: PdmConfiguration,
+        /// Response from the PDM.
+        control: AckControl,
+    },
+}
+
+/// What the channels of a PDM drive, setting their current limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelLoad {
+    /// Spray solenoids of a crop bed, limited to 5A.
+    Solenoid,
+    /// Light bars of the utilities PDM, limited to 15A.
+    Light,
+}
+
+impl ChannelLoad {
+    /// Current limit of a channel driving the load.
+    fn current_limit(self) -> CurentLimit {
+        CurentLimit {
+            limit: match self {
+                ChannelLoad::Solenoid => 5.0,
+                ChannelLoad::Light => 15.0,
+            },
+            reserved: false,
+        }
+    }
+}
+
+impl Display for ChannelLoad {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelLoad::Solenoid => write!(f, "solenoid"),
+            ChannelLoad::Light => write!(f, "light"),
+        }
+    }
+}
+
+impl FromStr for ChannelLoad {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "solenoid" | "solenoids" | "spray" => Ok(ChannelLoad::Solenoid),
+            "light" | "lights" | "lighting" => Ok(ChannelLoad::Light),
+            other => Err(format!("Unknown channel load {other}, expected solenoid or light")),
+        }
+    }
+}
+
 /// Similar to the camera, a PDM (power delivery module) is created
 /// using the builder pattern that consumes a PDM configuration. A
 /// PDM config is used for one unit. Generally a crop bed will use
@@ -19,76 +281,271 @@ pub struct PdmConfig {
     /// Source address of the PDM on the canbus network. For the ix-3212
     /// the address can only be changed by physically altering the wire
     /// states on the PDM pin out (four total).
-    // TODO: Implement the four address states as a separate enum in the
-    // ix-3212 crate.
-    pub address: u8,
+    pub address: PdmAddress,
     /// Location in terms of bill of materials.
     bed_location_id: u8,
     /// PDM Function Config, see technical specification for ix-3212
-    #[serde(serialize_with = "ordered_u8_map")]
+    #[serde(serialize_with = "ordered_map")]
     output_function_config: HashMap<u8, OutputFunctionConfigPayload>,
     /// PDM Channel Config, see technical specification for ix-3212
-    #[serde(serialize_with = "ordered_u8_map")]
+    #[serde(serialize_with = "ordered_map")]
     output_channels_config: HashMap<u8, ChannelConfig>,
+    /// Time in milliseconds to wait for the PDM to answer on the bus.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    response_timeout_ms: Option<u64>,
+    /// Proprietary command id the channels are actuated with, it differs
+    /// between firmware revisions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    actuate_command_id: Option<u8>,
+    /// Json lines file every actuation is appended to, for analysis after
+    /// the season.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    actuation_log: Option<PathBuf>,
+    /// File the on transitions and on time of each channel are kept in, so
+    /// they survive restarts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    usage_state_file: Option<PathBuf>,
 }
-/// Orders the channel configuration in the yaml file.
-/// if this mapping is not used there is no guarantee
-/// that the config files will not be over written
-/// needlessly (i.e. same information in different order)
-///
-/// * `value`: `HashMap`
-/// * `serializer`: Serializer
-// TODO: extract this to utils
-pub fn ordered_u8_map<S, T>(value: &HashMap<u8, T>, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-    T: Serialize,
-{
-    let ordered: BTreeMap<_, _> = value.iter().collect();
-    ordered.serialize(serializer)
-}
-
 impl PdmConfig {
     /// Create a new PDM config without function, or channel
     /// configurations. For more information on configuration
     /// please review the ix-3212 crate, and the technical
     /// specification provided by the manufacturer.
     ///
-    /// * `address`: address of the PDM.
+    /// * `address`: pin strapped address of the PDM.
     /// * `bed_location_id`: identify unit in-line with bill of materials.
-    pub fn new(address: u8, bed_location_id: u8) -> Self {
+    pub fn new(address: PdmAddress, bed_location_id: u8) -> Self {
         Self {
             address,
             bed_location_id,
             output_function_config: HashMap::new(),
             output_channels_config: HashMap::new(),
+            response_timeout_ms: None,
+            actuate_command_id: None,
+            actuation_log: None,
+            usage_state_file: None,
+        }
+    }
+
+    /// Keep the usage of each channel in a state file.
+    ///
+    /// * `usage_state_file`: path to the file, created if missing.
+    pub fn with_usage_state_file<P: Into<PathBuf>>(mut self, usage_state_file: P) -> Self {
+        self.usage_state_file = Some(usage_state_file.into());
+        self
+    }
+
+    /// Append every actuation to a json lines file.
+    ///
+    /// * `actuation_log`: path to the file, created if missing.
+    pub fn with_actuation_log<P: Into<PathBuf>>(mut self, actuation_log: P) -> Self {
+        self.actuation_log = Some(actuation_log.into());
+        self
+    }
+
+    /// Override the time waited for the PDM to answer on the bus.
+    ///
+    /// * `response_timeout`: time to wait.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn with_response_timeout(mut self, response_timeout: Duration) -> Self {
+        self.response_timeout_ms = Some(response_timeout.as_millis() as u64);
+        self
+    }
+
+    /// Time waited for the PDM to answer on the bus.
+    pub fn response_timeout(&self) -> Duration {
+        self.response_timeout_ms
+            .map_or(DEFAULT_RESPONSE_TIMEOUT, Duration::from_millis)
+    }
+
+    /// Override the command id the channels are actuated with, for PDM
+    /// firmware expecting other than [`DEFAULT_ACTUATE_COMMAND_ID`].
+    ///
+    /// * `actuate_command_id`: proprietary command id.
+    pub fn with_actuate_command_id(mut self, actuate_command_id: u8) -> Self {
+        self.actuate_command_id = Some(actuate_command_id);
+        self
+    }
+
+    /// Command id the channels are actuated with.
+    pub fn actuate_command_id(&self) -> u8 {
+        self.actuate_command_id.unwrap_or(DEFAULT_ACTUATE_COMMAND_ID)
+    }
+
+    /// Channels with an output channel config, in ascending order.
+    pub fn channels(&self) -> Vec<u8> {
+        let mut channels: Vec<u8> = self.output_channels_config.keys().copied().collect();
+        channels.sort_unstable();
+        channels
+    }
+
+    /// Configure every channel the way the crop bed harnesses are wired, a
+    /// high side lamp profile that turns off on loss of communication,
+    /// current limited for the load.
+    ///
+    /// * `load`: what the channels drive.
+    pub fn with_channels(mut self, load: ChannelLoad) -> Self {
+        for channel_number in 1..=frames::CHANNEL_COUNT {
+            self.output_function_config.insert(
+                channel_number,
+                OutputFunctionConfigPayload::new()
+                    .with_channel(ChannelNumber::new(channel_number))
+                    .with_load_profile(LoadProfile::Lamp)
+                    .with_loss_of_communication(LossOfCommunication::CHZero)
+                    .with_soft_start_step_size(SoftStartStepSize::new(None, false))
+                    .with_local_source_control(
+                        LocalSourceControl::new()
+                            .with_calibration_time(LocalSourceCalibration::Unsupported)
+                            .with_input(DigitalInputChannel::new(None, false))
+                            .with_response(LocalSourceControlResponse::ActiveLowHigh),
+                    )
+                    .with_power_on_reset(
+                        PowerOnReset::new()
+                            .with_loss_of_can_feature_enabled(true)
+                            .with_enable(false)
+                            .with_motor_braking(MotorBraking::Disabled)
+                            .with_command(PowerOnResetCommand::new(0.00)),
+                    ),
+            );
+            self.output_channels_config.insert(
+                channel_number,
+                ChannelConfig::new()
+                    .with_channel_load_control(ChannelLoadControl::HighSide)
+                    .with_feeadback_type(FeedbackType::Current)
+                    .with_current_limit(load.current_limit())
+                    .with_automatic_reset(true),
+            );
         }
+        self
     }
 
-    /// Create a `PdmConfig` by reading data from a file.
+    /// Bytes of every channel configuration as the PDM reports them on the
+    /// bus, keyed by configuration code and channel.
+    #[cfg(feature = "canbus")]
+    pub(crate) fn configuration_payloads(&self) -> HashMap<(u8, u8), Vec<u8>> {
+        let output_functions = self.output_function_config.iter().map(|(channel, output_function)| {
+            (
+                (PdmConfiguration::OutputFunction.code(), *channel),
+                output_function.to_payload(),
+            )
+        });
+        let output_channels = self.output_channels_config.iter().map(|(channel, output_channel)| {
+            (
+                (PdmConfiguration::OutputChannel.code(), *channel),
+                output_channel.to_payload(),
+            )
+        });
+        output_functions.chain(output_channels).collect()
+    }
+
+    /// Load the config from a yaml file, the error naming the field that
+    /// is wrong and where it is.
     ///
     /// * `filepath`: Path to file with configuration parameters.
-    pub fn from_file<F: AsRef<OsStr>>(filepath: F) -> Self {
-        let file = Path::new(&filepath);
-        let pdm_config = if file.is_file() {
-            let config_file = config::Config::builder()
-                .add_source(config::File::new(
-                    &file.to_string_lossy(),
-                    config::FileFormat::Yaml,
-                ))
-                .build()
-                .expect("Failed read config");
-
-            config_file
-                .try_deserialize::<Self>()
-                .expect("Failed to parse config file into struct")
-        } else {
-            panic!("Could not locate the config file {:?}", file);
-        };
-        pdm_config
+    pub fn try_from_file<F: AsRef<OsStr>>(filepath: F) -> Result<Self, ConfigError> {
+        load_yaml(Path::new(&filepath))
+    }
+}
+
+impl Validate for PdmConfig {
+    fn validate(&self, report: &mut ValidationReport) {
+        let function_channels: Vec<u8> = self.output_function_config.keys().copied().collect();
+        let output_channels: Vec<u8> = self.output_channels_config.keys().copied().collect();
+        for (field, channels) in [
+            ("output_function_config", function_channels),
+            ("output_channels_config", output_channels),
+        ] {
+            for channel in channels.into_iter().filter(|channel| !(1..=frames::CHANNEL_COUNT).contains(channel)) {
+                report.push(
+                    format!("{field}.{channel}"),
+                    format!("the PDM has channels 1 to {}", frames::CHANNEL_COUNT),
+                );
+            }
+        }
+        if self.response_timeout_ms == Some(0) {
+            report.push("response_timeout_ms", "no answer from the PDM arrives within 0ms");
+        }
+        if let Some(actuation_log) = &self.actuation_log {
+            report.check_parent_dir("actuation_log", actuation_log);
+        }
+        if let Some(usage_state_file) = &self.usage_state_file {
+            report.check_parent_dir("usage_state_file", usage_state_file);
+        }
     }
 }
 
+#[cfg(feature = "canbus")]
+impl DeviceConfig for PdmConfig {
+    type Device = Pdm;
+    type Error = Infallible;
+
+    fn location(&self) -> Option<u8> {
+        Some(self.bed_location_id)
+    }
+
+    // Nothing is sent to the PDM until it is initialised on an interface.
+    fn build(self) -> Result<Pdm, Infallible> {
+        Ok(Pdm::new(self))
+    }
+}
+
+/// Check no two PDMs on one component share an address, as both would
+/// answer every message sent to either.
+///
+/// * `configs`: PDM configs keyed by bed position.
+pub fn check_unique_addresses<'a>(
+    configs: impl IntoIterator<Item = (&'a u8, &'a PdmConfig)>,
+) -> Result<(), String> {
+    let mut seen: BTreeMap<PdmAddress, u8> = BTreeMap::new();
+    for (bed_position, config) in configs {
+        if let Some(other) = seen.insert(config.address, *bed_position) {
+            return Err(format!(
+                "Duplicate PDM address {} at bed positions {} and {}",
+                config.address,
+                other.min(*bed_position),
+                other.max(*bed_position)
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Load and validate the PDM config files of a component, checking there
+/// is at least one and no two share an address.
+///
+/// * `report`: report of the component config.
+/// * `pdm_config_files`: paths of the PDM configs keyed by bed position.
+pub fn validate_pdm_config_files(report: &mut ValidationReport, pdm_config_files: &HashMap<u8, PathBuf>) {
+    if pdm_config_files.is_empty() {
+        report.push("pdm_config_files", "no PDMs are configured");
+    }
+    let mut bed_positions: Vec<&u8> = pdm_config_files.keys().collect();
+    bed_positions.sort_unstable();
+    let configs: BTreeMap<u8, PdmConfig> = bed_positions
+        .into_iter()
+        .filter_map(|bed_position| {
+            report
+                .validate_file::<PdmConfig>(
+                    format!("pdm_config_files.{bed_position}"),
+                    &pdm_config_files[bed_position],
+                )
+                .map(|config| (*bed_position, config))
+        })
+        .collect();
+    if let Err(e) = check_unique_addresses(&configs) {
+        report.push("pdm_config_files", e);
+    }
+}
+
+/// Open the canbus socket shared by the PDMs on a trunk line, as the
+/// components do before initialising them.
+///
+/// * `canbus_id`: interface the PDMs are on, i.e. can0.
+#[cfg(feature = "canbus")]
+pub fn open_interface(canbus_id: &str) -> Result<Arc<Mutex<AsyncCanSocket>>, PdmError> {
+    Ok(Arc::new(Mutex::new(AsyncCanSocket::open(canbus_id)?)))
+}
+
 /// Similar to the `OnyxCamera` provide a wrapper struct type
 /// that provides access to the underlying driver that can
 /// be configured by consuming a `PdmConfig` in the builder
@@ -96,6 +553,7 @@ impl PdmConfig {
 // TODO: Consistent naming, move this to OnyxPdm, and rename
 //       the PdmDriver import crate as PDM etc. Good first 
 //       issue.
+#[cfg(feature = "canbus")]
 #[allow(dead_code)]
 pub struct Pdm {
     /// Unique identifier for a Pdm in the system.
@@ -108,18 +566,90 @@ pub struct Pdm {
     /// Location in the bed for the Pdm.
     /// TODO: Change this to a location enum.
     bed_location_id: u8,
+    /// Canbus socket set by [`Pdm::initialise`], used to read back the
+    /// configuration.
+    interface: Option<Arc<Mutex<AsyncCanSocket>>>,
+    /// Last current feedback for each channel.
+    feedback: FeedbackSnapshot,
+    /// Task reading feedback and status off the bus, started by the first
+    /// subscriber.
+    monitor: Option<PdmMonitor>,
+    /// Duty cycle in percent each channel was last actuated to through the
+    /// wrapper, channels not yet actuated are off.
+    duty_cycles: std::sync::Mutex<HashMap<u8, f32>>,
+    /// Every actuation sent through the wrapper.
+    audit: audit::ActuationAudit,
+    /// Wear counters for each channel.
+    usage: std::sync::Mutex<usage::ChannelUsageTracker>,
+    /// Whether the last call to [`Pdm::initialise`] configured the PDM.
+    initialised: bool,
+    /// Span the events of the PDM are traced in, with its uuid, address and
+    /// bed location.
+    span: Span,
+}
+
+/// Task reading the frames the PDM broadcasts and where it publishes them.
+#[cfg(feature = "canbus")]
+struct PdmMonitor {
+    /// Publishes current feedback for each channel.
+    feedback_tx: broadcast::Sender<ChannelFeedback>,
+    /// Latest fault state, subscribers are woken when it changes.
+    status_tx: watch::Sender<PdmStatus>,
+    /// Task reading the bus.
+    task: JoinHandle<()>,
+}
+
+#[cfg(feature = "canbus")]
+impl Drop for Pdm {
+    fn drop(&mut self) {
+        if let Some(monitor) = self.monitor.take() {
+            monitor.task.abort();
+        }
+        if let Ok(usage) = self.usage.get_mut() {
+            if let Err(e) = usage.save(std::time::Instant::now()) {
+                warn!(parent: &self.span, "Failed to save channel usage of PDM {}: {e}", self.config.address);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "canbus")]
+impl Device for Pdm {
+    fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    fn location(&self) -> Option<u8> {
+        Some(self.bed_location_id)
+    }
 }
 
+#[cfg(feature = "canbus")]
 impl Pdm {
     /// Create a new onyx Pdm by consuming a `PdmConfig`.
     ///
     /// * `config`: Set of config parameters.
     pub fn new(config: PdmConfig) -> Self {
+        let uuid = uuid::Uuid::new_v4();
+        let span = tracing::info_span!(
+            "pdm",
+            uuid = %uuid,
+            address = %config.address,
+            bed_location_id = config.bed_location_id
+        );
         Self {
-            uuid: uuid::Uuid::new_v4(),
+            uuid,
             bed_location_id: config.bed_location_id,
-            driver: PdmDriver::new(config.address),
+            driver: PdmDriver::new(config.address.raw()),
+            audit: audit::ActuationAudit::new(config.actuation_log.clone()),
+            usage: std::sync::Mutex::new(usage::ChannelUsageTracker::load(config.usage_state_file.clone())),
             config,
+            interface: None,
+            feedback: FeedbackSnapshot::default(),
+            monitor: None,
+            duty_cycles: std::sync::Mutex::new(HashMap::new()),
+            initialised: false,
+            span,
         }
     }
 
@@ -129,19 +659,522 @@ impl Pdm {
     /// be connected to which canbus trunk line (interface), i.e.
     /// can0, can1. Note: Pdm's must be configured and confirmed
     /// to be in the right configuration prior to sending messages.
+    ///
+    /// The PDM is asked for its address claim first, so an unpowered PDM
+    /// or one on another bus fails with [`PdmError::Timeout`] rather than
+    /// the component starting with an unconfigured device. Channels are
+    /// then configured one at a time so a rejection names the channel.
     // TODO: Pass by reference not mutable.
-    // TODO: Pass by reference for configure output calls.
-    // TODO: Implement periodic configuration checks during runtime.
-    pub async fn initialise(&mut self, interface: Arc<Mutex<AsyncCanSocket>>) {
+    pub async fn initialise(&mut self, interface: Arc<Mutex<AsyncCanSocket>>) -> Result<(), PdmError> {
+        self.initialised = false;
+        self.attach(interface.clone());
+        let address = self.config.address.raw();
+
+        send_frame(&interface, &J1939Frame::request(address, ADDRESS_CLAIMED_PGN)).await?;
+        let timeout = self.config.response_timeout();
+        let deadline = Instant::now() + timeout;
+        loop {
+            match next_frame(&interface, deadline).await? {
+                // Any traffic from the PDM shows it is powered and on this bus.
+                Some(frame) if frame.source == address => break,
+                Some(_) => {}
+                None => return Err(PdmError::Timeout { address, waited: timeout }),
+            }
+        }
+
+        let output_functions: BTreeMap<_, _> = self.config.output_function_config.iter().collect();
+        for (channel, output_function) in output_functions {
+            self.driver
+                .configure_output_function(HashMap::from([(*channel, output_function.clone())]))
+                .await;
+            self.check_rejection(&interface, *channel, PdmConfiguration::OutputFunction)
+                .await?;
+        }
+        let output_channels: BTreeMap<_, _> = self.config.output_channels_config.iter().collect();
+        for (channel, output_channel) in output_channels {
+            self.driver
+                .configure_output_channels(HashMap::from([(*channel, output_channel.clone())]))
+                .await;
+            self.check_rejection(&interface, *channel, PdmConfiguration::OutputChannel)
+                .await?;
+        }
+        self.initialised = true;
+        Ok(())
+    }
+
+    /// Send commands on an interface without configuring the PDM, for one
+    /// left configured by an earlier run, e.g. from a bench tool.
+    ///
+    /// * `interface`: canbus socket shared with the other PDMs on the bus.
+    pub fn attach(&mut self, interface: Arc<Mutex<AsyncCanSocket>>) {
         // set the PDM to use the correct interface.
-        self.driver.set_interface(interface);
+        self.driver.set_interface(interface.clone());
+        self.interface = Some(interface);
+    }
+
+    /// Whether the PDM answered and accepted its configuration the last
+    /// time it was initialised.
+    pub fn is_initialised(&self) -> bool {
+        self.initialised
+    }
+
+    /// Listen briefly for the PDM rejecting the configuration just sent.
+    ///
+    /// * `interface`: canbus socket shared with the driver.
+    /// * `channel`: channel that was configured.
+    /// * `configuration`: configuration that was sent.
+    async fn check_rejection(
+        &self,
+        interface: &Mutex<AsyncCanSocket>,
+        channel: u8,
+        configuration: PdmConfiguration,
+    ) -> Result<(), PdmError> {
+        let address = self.config.address.raw();
+        let deadline = Instant::now() + CONFIGURATION_ACK_WINDOW;
+        while let Some(frame) = next_frame(interface, deadline).await? {
+            if frame.source != address {
+                continue;
+            }
+            match frame.acknowledgement() {
+                Some(ack) if ack.control != AckControl::Ack => {
+                    return Err(PdmError::ChannelRejected {
+                        address,
+                        channel,
+                        configuration,
+                        control: ack.control,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Actuate channels at a duty cycle, keeping track of it so ramps start
+    /// from the duty cycle the channel is at, and recording it in the
+    /// actuation audit. Sent with the command id of the config.
+    ///
+    /// * `channels`: channel numbers.
+    /// * `pwm`: duty cycle in percent.
+    pub async fn actuate_channels(&self, channels: Vec<u8>, pwm: f32) {
+        self.actuate(channels, pwm, false, None).await;
+    }
+
+    /// Actuate channels for a spray, recording where the machine was in
+    /// the actuation audit, see [`Pdm::actuate_channels`].
+    ///
+    /// * `channels`: channel numbers.
+    /// * `pwm`: duty cycle in percent.
+    /// * `position`: where the machine was, if known.
+    pub async fn actuate_channels_at(&self, channels: Vec<u8>, pwm: f32, position: Option<GeoPosition>) {
+        self.actuate(channels, pwm, false, position).await;
+    }
+
+    /// Actuate channels each at their own duty cycle for a manual spray by
+    /// the operator, marked as manual in the actuation audit.
+    ///
+    /// * `duties`: channel number and duty cycle in percent.
+    /// * `position`: where the machine was, if known.
+    pub async fn actuate_channels_manual(&self, duties: Vec<(u8, f32)>, position: Option<GeoPosition>) {
+        for command in frames::pack_output_commands(&duties) {
+            self.actuate(command.channels, command.duty_percent, true, position)
+                .await;
+        }
+    }
+
+    /// Actuate channels, see [`Pdm::actuate_channels`].
+    ///
+    /// * `channels`: channel numbers.
+    /// * `pwm`: duty cycle in percent.
+    /// * `manual`: whether the actuation is for a manual spray.
+    /// * `position`: where the machine was, if known.
+    async fn actuate(&self, channels: Vec<u8>, pwm: f32, manual: bool, position: Option<GeoPosition>) {
+        let command_id = self.config.actuate_command_id();
+        {
+            let mut duty_cycles = self.duty_cycles.lock().expect("Duty cycles poisoned");
+            for channel in &channels {
+                duty_cycles.insert(*channel, pwm);
+            }
+        }
+        if manual {
+            self.audit.record_manual(command_id, &channels, pwm, position);
+        } else {
+            self.audit.record(command_id, &channels, pwm, position);
+        }
+        self.usage
+            .lock()
+            .expect("Channel usage poisoned")
+            .record(&channels, pwm, std::time::Instant::now());
+        debug!(
+            parent: &self.span,
+            channels = ?channels,
+            duty_percent = pwm,
+            command_id,
+            manual,
+            "Actuation fired"
+        );
+        self.driver.actuate_channels(command_id, channels, pwm).await;
+    }
 
-        self.driver
-            .configure_output_function(self.config.output_function_config.clone())
-            .await;
-        self.driver
-            .configure_output_channels(self.config.output_channels_config.clone())
-            .await;
+    /// On transitions and on time of every channel that has been turned on,
+    /// including the usage loaded from the state file.
+    pub fn channel_stats(&self) -> BTreeMap<u8, ChannelUsage> {
+        self.usage
+            .lock()
+            .expect("Channel usage poisoned")
+            .snapshot(std::time::Instant::now())
+    }
+
+    /// The last `n` actuations sent to the PDM, oldest first.
+    ///
+    /// * `n`: number of actuations.
+    pub fn recent_actuations(&self, n: usize) -> Vec<ActuationAuditRecord> {
+        self.audit.recent(n)
+    }
+
+    /// Duty cycle in percent a channel was last actuated to.
+    ///
+    /// * `channel`: channel number.
+    pub fn duty_cycle(&self, channel: u8) -> f32 {
+        self.duty_cycles
+            .lock()
+            .expect("Duty cycles poisoned")
+            .get(&channel)
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// Channels last actuated to 0%, or not actuated yet, in ascending
+    /// order.
+    pub fn idle_channels(&self) -> Vec<u8> {
+        let duty_cycles = self.duty_cycles.lock().expect("Duty cycles poisoned");
+        (1..=frames::CHANNEL_COUNT)
+            .filter(|channel| !duty_cycles.get(channel).is_some_and(|pwm| *pwm > 0.0))
+            .collect()
+    }
+
+    /// Step a channel from its current duty cycle to a target over a
+    /// duration rather than switching it at once, to limit the inrush of
+    /// loads such as the pump. Returns once the target has been sent.
+    ///
+    /// * `channel`: channel number.
+    /// * `target_pwm`: duty cycle to finish at in percent.
+    /// * `duration`: time to ramp over.
+    pub async fn ramp_channel(&self, channel: u8, target_pwm: f32, duration: Duration) {
+        let start = Instant::now();
+        for (offset, pwm) in ramp_schedule(self.duty_cycle(channel), target_pwm, duration) {
+            tokio::time::sleep_until(start + offset).await;
+            self.actuate_channels(vec![channel], pwm).await;
+        }
+    }
+
+    /// Actuate channels each at their own duty cycle. The driver takes one
+    /// duty cycle per call, so channels sharing a duty cycle are sent
+    /// together and the calls go out back to back, one per distinct duty
+    /// cycle.
+    ///
+    /// * `duties`: channel number and duty cycle in percent.
+    /// * `position`: where the machine was, if known.
+    pub async fn actuate_channels_individual(&self, duties: Vec<(u8, f32)>, position: Option<GeoPosition>) {
+        for command in frames::pack_output_commands(&duties) {
+            self.actuate_channels_at(command.channels, command.duty_percent, position)
+                .await;
+        }
+    }
+
+    /// Source address of the PDM.
+    pub fn address(&self) -> PdmAddress {
+        self.config.address
+    }
+
+    /// Command id the channels are actuated with.
+    pub fn actuate_command_id(&self) -> u8 {
+        self.config.actuate_command_id()
+    }
+
+    /// Ask the PDM for the configuration of every channel in its config
+    /// and return the channels that differ, including those it did not
+    /// report on.
+    pub async fn verify_configuration(&self) -> Result<Vec<ConfigurationMismatch>, PdmError> {
+        let address = self.config.address.raw();
+        let interface = self
+            .interface
+            .as_ref()
+            .ok_or(PdmError::NotInitialised { address })?;
+        let mut mismatches = Vec::new();
+
+        let output_functions: BTreeMap<_, _> = self.config.output_function_config.iter().collect();
+        for (channel, expected) in output_functions {
+            mismatches.extend(
+                self.verify_channel(interface, *channel, PdmConfiguration::OutputFunction, expected)
+                    .await?,
+            );
+        }
+        let output_channels: BTreeMap<_, _> = self.config.output_channels_config.iter().collect();
+        for (channel, expected) in output_channels {
+            mismatches.extend(
+                self.verify_channel(interface, *channel, PdmConfiguration::OutputChannel, expected)
+                    .await?,
+            );
+        }
+        Ok(mismatches)
+    }
+
+    /// Read back the configuration of one channel and compare it.
+    ///
+    /// * `interface`: canbus socket shared with the driver.
+    /// * `channel`: channel number.
+    /// * `configuration`: configuration to read.
+    /// * `expected`: configuration from the config file.
+    async fn verify_channel<T: ReadBack>(
+        &self,
+        interface: &Mutex<AsyncCanSocket>,
+        channel: u8,
+        configuration: PdmConfiguration,
+        expected: &T,
+    ) -> Result<Option<ConfigurationMismatch>, PdmError> {
+        let address = self.config.address.raw();
+        let code = configuration.code();
+        send_frame(interface, &J1939Frame::configuration_query(address, code, channel)).await?;
+
+        let deadline = Instant::now() + self.config.response_timeout();
+        let reported = loop {
+            match next_frame(interface, deadline).await? {
+                Some(frame) if frame.source == address => match frame.as_configuration_report() {
+                    Some((reported_code, reported_channel, payload))
+                        if reported_code == code && reported_channel == channel =>
+                    {
+                        break Some(payload.to_vec());
+                    }
+                    _ => {}
+                },
+                Some(_) => {}
+                None => break None,
+            }
+        };
+
+        let decoded = reported.as_deref().and_then(T::from_payload);
+        if decoded.as_ref() == Some(expected) {
+            return Ok(None);
+        }
+        Ok(Some(ConfigurationMismatch {
+            channel,
+            configuration,
+            expected: format!("{expected:?}"),
+            actual: decoded
+                .map(|actual| format!("{actual:?}"))
+                .or_else(|| reported.map(|bytes| format!("{bytes:02X?}"))),
+        }))
+    }
+
+    /// Start the task that reads the feedback and status frames the PDM
+    /// broadcasts, if it is not already running.
+    ///
+    /// The task listens on its own socket bound to the interface, reads on
+    /// the shared socket would hold its lock and delay actuation.
+    ///
+    /// * `canbus_id`: interface the PDM is on, i.e. can0.
+    fn start_monitor(&mut self, canbus_id: &str) -> Result<&PdmMonitor, PdmError> {
+        if self.monitor.is_none() {
+            let socket = AsyncCanSocket::open(canbus_id)?;
+            let (feedback_tx, _) = broadcast::channel(FEEDBACK_CAPACITY);
+            let (status_tx, _) = watch::channel(PdmStatus::default());
+            let task_feedback_tx = feedback_tx.clone();
+            let task_status_tx = status_tx.clone();
+            let snapshot = self.feedback.clone();
+            let address = self.config.address.raw();
+
+            let task = tokio::spawn(
+                async move {
+                    loop {
+                        let frame = match socket.read_frame().await {
+                            Ok(frame) => frame,
+                            Err(e) => {
+                                warn!("Stopped monitoring PDM {address}: {e}");
+                                break;
+                            }
+                        };
+                        if !frame.is_extended() {
+                            continue;
+                        }
+                        let frame = J1939Frame::from_id(frame.raw_id(), frame.data());
+                        if frame.source != address {
+                            continue;
+                        }
+                        if let Some(feedback) = frame.as_channel_feedback() {
+                            snapshot
+                                .lock()
+                                .expect("Feedback snapshot poisoned")
+                                .insert(feedback.channel, (feedback, Instant::now()));
+                            // Having no subscribers is fine, the snapshot is kept.
+                            let _subscribers = task_feedback_tx.send(feedback);
+                        } else if let Some(status) = frame.as_status() {
+                            // The PDM repeats its status, only wake the owner
+                            // when something has changed.
+                            task_status_tx.send_if_modified(|current| {
+                                let modified = *current != status;
+                                *current = status;
+                                modified
+                            });
+                        }
+                    }
+                }
+                .instrument(self.span.clone()),
+            );
+            self.monitor = Some(PdmMonitor {
+                feedback_tx,
+                status_tx,
+                task,
+            });
+        }
+        self.monitor
+            .as_ref()
+            .ok_or(PdmError::NotInitialised { address: self.config.address.raw() })
+    }
+
+    /// Subscribe to the current feedback the PDM broadcasts for each
+    /// channel. The latest for each channel is also kept for
+    /// [`Pdm::channel_feedback`].
+    ///
+    /// * `canbus_id`: interface the PDM is on, i.e. can0.
+    pub fn subscribe_feedback(&mut self, canbus_id: &str) -> Result<broadcast::Receiver<ChannelFeedback>, PdmError> {
+        Ok(self.start_monitor(canbus_id)?.feedback_tx.subscribe())
+    }
+
+    /// Watch the fault state the PDM broadcasts, the receiver is woken
+    /// whenever a fault is raised or cleared.
+    ///
+    /// * `canbus_id`: interface the PDM is on, i.e. can0.
+    pub fn watch_status(&mut self, canbus_id: &str) -> Result<watch::Receiver<PdmStatus>, PdmError> {
+        Ok(self.start_monitor(canbus_id)?.status_tx.subscribe())
+    }
+
+    /// Latest fault state reported by the PDM, empty until the status is
+    /// being watched and the PDM has reported.
+    pub fn status(&self) -> PdmStatus {
+        self.monitor
+            .as_ref()
+            .map(|monitor| monitor.status_tx.borrow().clone())
+            .unwrap_or_default()
+    }
+
+    /// Send the configuration again on the interface given to
+    /// [`Pdm::initialise`] and read it back, e.g. after a loss of CAN or a
+    /// brown out reset the PDM. Returns the channels that did not read back
+    /// as configured.
+    pub async fn reinitialise(&mut self) -> Result<Vec<ConfigurationMismatch>, PdmError> {
+        let interface = self.interface.clone().ok_or(PdmError::NotInitialised {
+            address: self.config.address.raw(),
+        })?;
+        self.initialise(interface).await?;
+        self.verify_configuration().await
+    }
+
+    /// Last current feedback reported for a channel.
+    ///
+    /// * `channel`: channel number.
+    pub fn channel_feedback(&self, channel: u8) -> Option<ChannelFeedback> {
+        self.feedback
+            .lock()
+            .expect("Feedback snapshot poisoned")
+            .get(&channel)
+            .map(|(feedback, _)| *feedback)
+    }
+
+    /// Last current feedback for a channel, if it arrived after `since`.
+    ///
+    /// * `channel`: channel number.
+    /// * `since`: oldest feedback to accept.
+    pub fn channel_feedback_since(&self, channel: u8, since: Instant) -> Option<ChannelFeedback> {
+        self.feedback
+            .lock()
+            .expect("Feedback snapshot poisoned")
+            .get(&channel)
+            .filter(|(_, received_at)| *received_at >= since)
+            .map(|(feedback, _)| *feedback)
+    }
+
+    /// Verify the configuration and log any drift, returning false when
+    /// the policy says the component should stop driving the PDM.
+    ///
+    /// * `on_drift`: what to do when the PDM has drifted.
+    pub async fn check_drift(&self, on_drift: DriftPolicy) -> bool {
+        let address = self.config.address.raw();
+        let drifted = match self.verify_configuration().await {
+            Ok(mismatches) => {
+                for mismatch in &mismatches {
+                    warn!(parent: &self.span, "PDM {address} has drifted from its config, {mismatch}");
+                }
+                !mismatches.is_empty()
+            }
+            Err(e) => {
+                warn!(parent: &self.span, "Failed to verify the configuration of PDM {address}: {e}");
+                true
+            }
+        };
+        !(drifted && on_drift == DriftPolicy::Refuse)
+    }
+}
+
+/// Steps of a ramp from one duty cycle to another, as the time after the
+/// start of the ramp and the duty cycle to send. Steps are evenly spaced at
+/// most [`RAMP_STEP_INTERVAL`] apart so the PDM keeps hearing from the
+/// controller, and the last step is the target at the end of the ramp.
+///
+/// * `from_pwm`: duty cycle the channel is at in percent.
+/// * `target_pwm`: duty cycle to finish at in percent.
+/// * `duration`: time to ramp over.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+pub fn ramp_schedule(from_pwm: f32, target_pwm: f32, duration: Duration) -> Vec<(Duration, f32)> {
+    let steps = (duration.as_secs_f64() / RAMP_STEP_INTERVAL.as_secs_f64()).ceil().max(1.0) as u32;
+    (1..=steps)
+        .map(|step| {
+            let fraction = step as f32 / steps as f32;
+            (
+                duration.mul_f32(fraction),
+                from_pwm + (target_pwm - from_pwm) * fraction,
+            )
+        })
+        .collect()
+}
+
+/// Write a J1939 frame to the bus.
+///
+/// * `interface`: canbus socket shared with the driver.
+/// * `frame`: frame to send.
+#[cfg(feature = "canbus")]
+pub(crate) async fn send_frame(interface: &Mutex<AsyncCanSocket>, frame: &J1939Frame) -> Result<(), PdmError> {
+    let id = ExtendedId::new(frame.id())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Identifier out of range"))?;
+    let can_frame = CanFrame::new(id, &frame.data)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Payload too long"))?;
+    interface.lock().await.write_frame(can_frame).await?;
+    Ok(())
+}
+
+/// Read the next extended frame from the bus, `None` once the deadline
+/// has passed.
+///
+/// * `interface`: canbus socket shared with the driver.
+/// * `deadline`: latest time to wait until.
+#[cfg(feature = "canbus")]
+pub(crate) async fn next_frame(
+    interface: &Mutex<AsyncCanSocket>,
+    deadline: Instant,
+) -> Result<Option<J1939Frame>, PdmError> {
+    loop {
+        let socket = interface.lock().await;
+        let Ok(frame) = tokio::time::timeout_at(deadline, socket.read_frame()).await else {
+            return Ok(None);
+        };
+        let frame = frame?;
+        if frame.is_extended() {
+            return Ok(Some(J1939Frame::from_id(frame.raw_id(), frame.data())));
+        }
     }
 }
 
@@ -149,141 +1182,413 @@ impl Pdm {
 mod tests {
 
     use super::*;
+    #[cfg(feature = "canbus")]
+    use crate::utils::logging::capture;
     use rstest::rstest;
+    #[cfg(feature = "canbus")]
+    use serial_test::serial;
+    use uuid::Uuid;
 
-    #[rstest]
-    #[case(30, 0)]
-    #[case(31, 1)]
-    fn test_read_write_pdm_to_config_file(#[case] pdm_address: u8, #[case] bed_location_id: u8) {
-        let mut write_config = PdmConfig::new(pdm_address, bed_location_id);
+    /// Virtual canbus interface used by the `vcan_test` tests, set up with
+    /// `ip link add dev vcan0 type vcan && ip link set up vcan0`.
+    #[cfg(feature = "canbus")]
+    fn vcan_interface() -> String {
+        std::env::var("ONYX_VCAN").unwrap_or_else(|_| String::from("vcan0"))
+    }
 
-        for channel_number in 1u8..=12u8 {
-            write_config.output_function_config.insert(
-                channel_number,
-                OutputFunctionConfigPayload::new()
-                    .with_channel(ChannelNumber::new(channel_number))
-                    .with_load_profile(LoadProfile::Lamp)
-                    .with_loss_of_communication(LossOfCommunication::CHZero)
-                    .with_soft_start_step_size(SoftStartStepSize::new(None, false))
-                    .with_local_source_control(
-                        LocalSourceControl::new()
-                            .with_calibration_time(LocalSourceCalibration::Unsupported)
-                            .with_input(DigitalInputChannel::new(None, false))
-                            .with_response(LocalSourceControlResponse::ActiveLowHigh),
-                    )
-                    .with_power_on_reset(
-                        PowerOnReset::new()
-                            .with_loss_of_can_feature_enabled(true)
-                            .with_enable(false)
-                            .with_motor_braking(MotorBraking::Disabled)
-                            .with_command(PowerOnResetCommand::new(0.00)),
-                    ),
-            );
+    #[cfg(feature = "canbus")]
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// With nothing answering on the bus initialise should time out and
+    /// name the PDM rather than report success.
+    async fn test_initialise_times_out_without_pdm() {
+        let interface = Arc::new(Mutex::new(
+            AsyncCanSocket::open(&vcan_interface()).expect("Failed to open vcan interface"),
+        ));
+        let mut pdm = Pdm::new(PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(Duration::from_millis(200)));
+        match pdm.initialise(interface).await {
+            Err(PdmError::Timeout { address, waited }) => {
+                assert_eq!(address, PdmAddress::Pdm30.raw());
+                assert_eq!(waited, Duration::from_millis(200));
+            }
+            other => panic!("Expected a timeout, got {other:?}"),
         }
+    }
 
-        for channel_number in 1u8..=12u8 {
-            write_config.output_channels_config.insert(
-                channel_number,
-                ChannelConfig::new()
-                    .with_channel_load_control(ChannelLoadControl::HighSide)
-                    .with_feeadback_type(FeedbackType::Current)
-                    .with_current_limit(CurentLimit {
-                        limit: 5.0,
-                        reserved: false,
-                    })
-                    .with_automatic_reset(true),
-            );
+    /// Output function of a lamp on a channel, as in the config files.
+    ///
+    /// * `channel_number`: channel on the PDM.
+    #[cfg(feature = "canbus")]
+    fn lamp_output_function(channel_number: u8) -> OutputFunctionConfigPayload {
+        OutputFunctionConfigPayload::new()
+            .with_channel(ChannelNumber::new(channel_number))
+            .with_load_profile(LoadProfile::Lamp)
+            .with_loss_of_communication(LossOfCommunication::CHZero)
+            .with_soft_start_step_size(SoftStartStepSize::new(None, false))
+    }
+
+    /// Stand in PDM that answers the address claim request and reports
+    /// the scripted channel configurations.
+    ///
+    /// * `socket`: second socket on the virtual interface.
+    /// * `address`: address the stand in PDM answers on.
+    /// * `reports`: payload reported for each configuration code and channel.
+    #[cfg(feature = "canbus")]
+    async fn scripted_responder(socket: AsyncCanSocket, address: u8, reports: HashMap<(u8, u8), Vec<u8>>) {
+        let socket = Mutex::new(socket);
+        while let Ok(Some(frame)) = next_frame(&socket, Instant::now() + Duration::from_secs(5)).await {
+            if frame.destination != Some(address) {
+                continue;
+            }
+            let reply = if frame.pgn == frames::REQUEST_PGN {
+                Some(J1939Frame {
+                    priority: 6,
+                    pgn: ADDRESS_CLAIMED_PGN,
+                    source: address,
+                    destination: Some(frames::GLOBAL_ADDRESS),
+                    data: vec![0; 8],
+                })
+            } else {
+                frame.as_configuration_query().and_then(|(code, channel)| {
+                    reports
+                        .get(&(code, channel))
+                        .map(|payload| J1939Frame::configuration_report(address, code, channel, payload))
+                })
+            };
+            if let Some(reply) = reply {
+                send_frame(&socket, &reply).await.expect("Failed to reply");
+            }
+        }
+    }
+
+    #[cfg(feature = "canbus")]
+    #[rstest]
+    #[case(1, None)]
+    #[case(2, Some(2))]
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// The PDM reports channel 2 as either configured or as a copy of
+    /// channel 1, which should show up as a single mismatch.
+    async fn test_verify_configuration_against_scripted_pdm(
+        #[case] reported_channel_2: u8,
+        #[case] expected_mismatch: Option<u8>,
+    ) {
+        let mut config = PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(Duration::from_millis(200));
+        for channel in [1, 2] {
+            config.output_function_config.insert(channel, lamp_output_function(channel));
+        }
+        let reports = HashMap::from([
+            ((PdmConfiguration::OutputFunction.code(), 1), lamp_output_function(1).to_payload()),
+            (
+                (PdmConfiguration::OutputFunction.code(), 2),
+                lamp_output_function(reported_channel_2).to_payload(),
+            ),
+        ]);
+        let responder = tokio::spawn(scripted_responder(
+            AsyncCanSocket::open(&vcan_interface()).expect("Failed to open vcan interface"),
+            30,
+            reports,
+        ));
+
+        let interface = Arc::new(Mutex::new(
+            AsyncCanSocket::open(&vcan_interface()).expect("Failed to open vcan interface"),
+        ));
+        let mut pdm = Pdm::new(config);
+        pdm.initialise(interface).await.expect("Failed to initialise");
+        let mismatches = pdm.verify_configuration().await.expect("Failed to verify");
+        let mismatched_channels: Vec<u8> = mismatches.iter().map(|mismatch| mismatch.channel).collect();
+        assert_eq!(mismatched_channels, expected_mismatch.into_iter().collect::<Vec<_>>());
+        assert_eq!(
+            pdm.check_drift(DriftPolicy::Refuse).await,
+            expected_mismatch.is_none()
+        );
+        assert!(pdm.check_drift(DriftPolicy::Log).await);
+        responder.abort();
+    }
+
+    #[cfg(feature = "canbus")]
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// Feedback sent by the PDM is published to subscribers and kept for
+    /// the channel, feedback from other PDMs is ignored.
+    async fn test_subscribe_feedback() {
+        let mut pdm = Pdm::new(PdmConfig::new(PdmAddress::Pdm30, 0));
+        let mut feedback_rx = pdm.subscribe_feedback(&vcan_interface()).expect("Failed to subscribe");
+        let sender = Mutex::new(AsyncCanSocket::open(&vcan_interface()).expect("Failed to open vcan interface"));
+        let started = Instant::now();
+        for (source, data) in [(31, [2, 0xF4, 0x01, 0]), (30, [3, 0xF4, 0x01, 0])] {
+            let frame = J1939Frame {
+                priority: 6,
+                pgn: frames::CHANNEL_FEEDBACK_PGN,
+                source,
+                destination: None,
+                data: data.to_vec(),
+            };
+            send_frame(&sender, &frame).await.expect("Failed to send feedback");
         }
 
-        let write_file = std::fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(format!(
-                "{}/config/devices/crop_bed/pdm_{bed_location_id}.yaml",
-                env!("CARGO_MANIFEST_DIR")
-            ))
-            .expect("Couldn't open file");
+        let feedback = tokio::time::timeout(Duration::from_secs(1), feedback_rx.recv())
+            .await
+            .expect("No feedback published")
+            .unwrap();
+        assert_eq!(feedback.channel, 3);
+        assert!((feedback.amps - 5.0).abs() < 0.001);
+        assert_eq!(pdm.channel_feedback(3), Some(feedback));
+        assert!(pdm.channel_feedback_since(3, started).is_some());
+        assert!(pdm.channel_feedback_since(3, Instant::now()).is_none());
+        assert_eq!(pdm.channel_feedback(2), None);
+    }
 
-        serde_yaml::to_writer(write_file, &write_config).unwrap();
+    #[cfg(feature = "canbus")]
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// Each actuation sent is traced in the span of the PDM, with the
+    /// channels, duty cycle and command id it was sent with.
+    async fn test_actuation_traced() {
+        capture::capture_events();
+        let mut pdm = Pdm::new(PdmConfig::new(PdmAddress::Pdm30, 0));
+        pdm.attach(open_interface(&vcan_interface()).expect("Failed to open vcan interface"));
+        pdm.actuate_channels(vec![3, 4], 60.0).await;
+        pdm.actuate_channels_manual(vec![(5, 100.0)], None).await;
 
-        let test_file = std::fs::File::open(format!(
-            "{}/config/devices/crop_bed/pdm_{bed_location_id}.yaml",
-            env!("CARGO_MANIFEST_DIR")
-        ))
-        .expect("Could not open file.");
+        let fired: Vec<_> = capture::captured_with("uuid", &pdm.uuid.to_string())
+            .into_iter()
+            .filter(|event| event.field("message") == Some("Actuation fired"))
+            .collect();
+        assert_eq!(fired.len(), 2, "{fired:?}");
+        assert_eq!(fired[0].level, tracing::Level::DEBUG);
+        assert_eq!(fired[0].field("address"), Some(pdm.address().to_string().as_str()));
+        assert_eq!(fired[0].field("bed_location_id"), Some("0"));
+        assert_eq!(fired[0].field("channels"), Some("[3, 4]"));
+        assert_eq!(fired[0].field("duty_percent"), Some("60.0"));
+        assert_eq!(
+            fired[0].field("command_id"),
+            Some(pdm.actuate_command_id().to_string().as_str())
+        );
+        assert_eq!(
+            (fired[0].field("manual"), fired[1].field("manual")),
+            (Some("false"), Some("true"))
+        );
+    }
 
-        let read_config: PdmConfig =
-            serde_yaml::from_reader(&test_file).expect("Could not read values.");
+    #[cfg(feature = "canbus")]
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// A status frame from the PDM wakes the watcher and is kept, repeats
+    /// of the same status do not.
+    async fn test_watch_status() {
+        let mut pdm = Pdm::new(PdmConfig::new(PdmAddress::Pdm30, 0));
+        let mut status_rx = pdm.watch_status(&vcan_interface()).expect("Failed to watch status");
+        let sender = Mutex::new(AsyncCanSocket::open(&vcan_interface()).expect("Failed to open vcan interface"));
+        // Loss of CAN with channel 2 tripped.
+        let status_frame = J1939Frame {
+            priority: 6,
+            pgn: frames::STATUS_PGN,
+            source: PdmAddress::Pdm30.raw(),
+            destination: None,
+            data: vec![0b01, 0b10, 0, 0, 0, 0xFF, 0xFF, 0xFF],
+        };
+        for _ in 0..2 {
+            send_frame(&sender, &status_frame).await.expect("Failed to send status");
+        }
 
-        assert_eq!(write_config, read_config, "Failed to be created equally");
+        tokio::time::timeout(Duration::from_secs(1), status_rx.changed())
+            .await
+            .expect("Status was not published")
+            .unwrap();
+        let status = status_rx.borrow_and_update().clone();
+        assert!(status.loss_of_can);
+        assert_eq!(status.tripped_channels(), vec![2]);
+        assert_eq!(pdm.status(), status);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), status_rx.changed())
+                .await
+                .is_err(),
+            "Repeated status woke the watcher"
+        );
     }
 
     #[rstest]
-    #[case(30, 0)]
-    fn test_read_write_utilities_pdm_to_config_file(
-        #[case] pdm_address: u8,
-        #[case] bed_location_id: u8,
+    #[case(0.0, 100.0, Duration::from_secs(1), 10)]
+    #[case(100.0, 0.0, Duration::from_millis(250), 3)]
+    #[case(20.0, 80.0, Duration::from_secs(5), 50)]
+    #[case(0.0, 100.0, Duration::ZERO, 1)]
+    /// Ramps step evenly to the target, never leaving the PDM without a
+    /// frame for longer than the step interval.
+    fn test_ramp_schedule(
+        #[case] from_pwm: f32,
+        #[case] target_pwm: f32,
+        #[case] duration: Duration,
+        #[case] steps: usize,
     ) {
-        let mut write_config = PdmConfig::new(pdm_address, bed_location_id);
+        let schedule = ramp_schedule(from_pwm, target_pwm, duration);
+        assert_eq!(schedule.len(), steps);
+        let (last_offset, last_pwm) = *schedule.last().unwrap();
+        assert_eq!(last_offset, duration);
+        assert!((last_pwm - target_pwm).abs() < 0.001);
 
-        for channel_number in 1u8..=12u8 {
-            write_config.output_function_config.insert(
-                channel_number,
-                OutputFunctionConfigPayload::new()
-                    .with_channel(ChannelNumber::new(channel_number))
-                    .with_load_profile(LoadProfile::Lamp)
-                    .with_loss_of_communication(LossOfCommunication::CHZero)
-                    .with_soft_start_step_size(SoftStartStepSize::new(None, false))
-                    .with_local_source_control(
-                        LocalSourceControl::new()
-                            .with_calibration_time(LocalSourceCalibration::Unsupported)
-                            .with_input(DigitalInputChannel::new(None, false))
-                            .with_response(LocalSourceControlResponse::ActiveLowHigh),
-                    )
-                    .with_power_on_reset(
-                        PowerOnReset::new()
-                            .with_loss_of_can_feature_enabled(true)
-                            .with_enable(false)
-                            .with_motor_braking(MotorBraking::Disabled)
-                            .with_command(PowerOnResetCommand::new(0.00)),
-                    ),
+        let mut previous = (Duration::ZERO, from_pwm);
+        for (offset, pwm) in schedule {
+            assert!(offset - previous.0 <= RAMP_STEP_INTERVAL, "Gap before {offset:?}");
+            assert!(
+                (pwm - previous.1) * (target_pwm - from_pwm) >= 0.0,
+                "Ramp changed direction at {offset:?}"
             );
+            previous = (offset, pwm);
         }
+    }
 
-        for channel_number in 1u8..=12u8 {
-            write_config.output_channels_config.insert(
-                channel_number,
-                ChannelConfig::new()
-                    .with_channel_load_control(ChannelLoadControl::HighSide)
-                    .with_feeadback_type(FeedbackType::Current)
-                    .with_current_limit(CurentLimit {
-                        limit: 15.0,
-                        reserved: false,
-                    })
-                    .with_automatic_reset(true),
-            );
+    #[test]
+    /// Two PDMs strapped to the same address cannot be told apart.
+    fn test_check_unique_addresses() {
+        let pdm_0 = PdmConfig::new(PdmAddress::Pdm30, 0);
+        let pdm_1 = PdmConfig::new(PdmAddress::Pdm31, 1);
+        let duplicate = PdmConfig::new(PdmAddress::Pdm30, 2);
+        assert!(check_unique_addresses([(&0, &pdm_0), (&1, &pdm_1)]).is_ok());
+        let e = check_unique_addresses([(&0, &pdm_0), (&1, &pdm_1), (&2, &duplicate)]).unwrap_err();
+        assert_eq!(e, "Duplicate PDM address 30 at bed positions 0 and 2");
+    }
+
+    #[test]
+    /// Channels the PDM does not have, a timeout nothing answers within and
+    /// files written to missing directories are reported, the configs in
+    /// the repository are not.
+    fn test_validate_pdm_config() {
+        for file in ["pdm_0.yaml", "pdm_1.yaml", "pdm_utilities.yaml"] {
+            let path = crate::utils::paths::repo_relative(format!("config/devices/crop_bed/{file}")).unwrap();
+            let report = crate::utils::config::validate_file::<PdmConfig>(&path);
+            assert!(report.is_valid(), "{report}");
         }
 
-        let write_file = std::fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(format!(
-                "{}/config/devices/crop_bed/pdm_utilities.yaml",
-                env!("CARGO_MANIFEST_DIR")
-            ))
-            .expect("Couldn't open file");
+        let missing_dir = std::env::temp_dir().join(format!("onyx-pdm-{}", Uuid::new_v4()));
+        let mut broken = PdmConfig::new(PdmAddress::Pdm30, 0)
+            .with_response_timeout(Duration::ZERO)
+            .with_actuation_log(missing_dir.join("actuations.jsonl"))
+            .with_usage_state_file("usage.json");
+        broken.output_channels_config.insert(0, ChannelConfig::new());
+        broken.output_channels_config.insert(13, ChannelConfig::new());
+        broken.output_channels_config.insert(12, ChannelConfig::new());
+        let mut report = ValidationReport::new("pdm.yaml");
+        broken.validate(&mut report);
+        let mut fields = report.fields();
+        fields.sort_unstable();
+        assert_eq!(
+            fields,
+            [
+                "actuation_log",
+                "output_channels_config.0",
+                "output_channels_config.13",
+                "response_timeout_ms"
+            ],
+            "{report}"
+        );
+    }
+
+    #[test]
+    /// PDM files that are missing or share an address are reported under
+    /// the config referring to them, as is a component without any.
+    fn test_validate_pdm_config_files() {
+        let dir = std::env::temp_dir().join(format!("onyx-pdm-files-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut files = HashMap::new();
+        for bed_position in [0, 1] {
+            let path = dir.join(format!("pdm_{bed_position}.yaml"));
+            let config = PdmConfig::new(PdmAddress::Pdm30, bed_position);
+            serde_yaml::to_writer(std::fs::File::create(&path).unwrap(), &config).unwrap();
+            files.insert(bed_position, path);
+        }
+        files.insert(2, dir.join("pdm_2.yaml"));
+        let mut report = ValidationReport::new("power.yaml");
+        validate_pdm_config_files(&mut report, &files);
+        assert_eq!(report.fields(), ["pdm_config_files.2", "pdm_config_files"], "{report}");
+        assert!(report.problems()[1].message.contains("Duplicate PDM address 30"), "{report}");
+
+        let mut report = ValidationReport::new("power.yaml");
+        validate_pdm_config_files(&mut report, &HashMap::new());
+        assert_eq!(report.fields(), ["pdm_config_files"], "{report}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// A PDM not answering names itself and how long it was waited for.
+    fn test_pdm_error_messages() {
+        let error = PdmError::Timeout {
+            address: PdmAddress::Pdm31.raw(),
+            waited: Duration::from_millis(200),
+        };
+        assert_eq!(
+            error.to_string(),
+            "no response from PDM 31 after 200ms, check it is powered and on this bus"
+        );
+    }
+
+    #[cfg(feature = "canbus")]
+    #[test]
+    /// A socket error opening an interface keeps the reason from the kernel.
+    fn test_open_interface_error() {
+        let error = open_interface("nocan0").err().expect("Missing interface was opened");
+        assert!(matches!(error, PdmError::Socket(_)), "{error}");
+        assert!(error.to_string().starts_with("canbus socket error "), "{error}");
+    }
 
-        serde_yaml::to_writer(write_file, &write_config).unwrap();
+    #[test]
+    /// Configs written before the timeout was configurable still parse.
+    fn test_response_timeout_defaults() {
+        let config = PdmConfig::new(PdmAddress::Pdm30, 0);
+        assert_eq!(config.response_timeout(), DEFAULT_RESPONSE_TIMEOUT);
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert!(!yaml.contains("response_timeout_ms"));
+        let config = config.with_response_timeout(Duration::from_millis(250));
+        let read_config: PdmConfig = serde_yaml::from_str(&serde_yaml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(read_config.response_timeout(), Duration::from_millis(250));
+    }
 
-        let test_file = std::fs::File::open(format!(
-            "{}/config/devices/crop_bed/pdm_utilities.yaml",
-            env!("CARGO_MANIFEST_DIR")
-        ))
-        .expect("Could not open file.");
+    #[test]
+    /// Configs written before the command id was configurable actuate with
+    /// the id of the current firmware.
+    fn test_actuate_command_id_defaults() {
+        let config = PdmConfig::new(PdmAddress::Pdm30, 0);
+        assert_eq!(config.actuate_command_id(), DEFAULT_ACTUATE_COMMAND_ID);
+        assert!(!serde_yaml::to_string(&config).unwrap().contains("actuate_command_id"));
+        let config = config.with_actuate_command_id(0x21);
+        let read_config: PdmConfig = serde_yaml::from_str(&serde_yaml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(read_config.actuate_command_id(), 0x21);
+    }
 
-        let read_config: PdmConfig =
-            serde_yaml::from_reader(&test_file).expect("Could not read values.");
+    #[rstest]
+    #[case(PdmAddress::Pdm30, 0, ChannelLoad::Solenoid)]
+    #[case(PdmAddress::Pdm31, 1, ChannelLoad::Solenoid)]
+    #[case(PdmAddress::Pdm30, 0, ChannelLoad::Light)]
+    /// PDM configs from the generator read back as they were written, with
+    /// every channel configured.
+    fn test_read_write_pdm_to_config_file(
+        #[case] pdm_address: PdmAddress,
+        #[case] bed_location_id: u8,
+        #[case] load: ChannelLoad,
+    ) {
+        let write_config = PdmConfig::new(pdm_address, bed_location_id).with_channels(load);
+        let path = std::env::temp_dir().join(format!("onyx-pdm-config-{}.yaml", Uuid::new_v4()));
+        crate::utils::configgen::write_config(&path, &write_config).unwrap();
 
+        let read_config = PdmConfig::try_from_file(&path).unwrap();
         assert_eq!(write_config, read_config, "Failed to be created equally");
+        assert_eq!(read_config.channels(), (1..=frames::CHANNEL_COUNT).collect::<Vec<_>>());
+        assert!(PdmConfig::new(pdm_address, bed_location_id).channels().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    /// Channel loads are read as they are written on the command line.
+    fn test_channel_load_from_str() {
+        for load in [ChannelLoad::Solenoid, ChannelLoad::Light] {
+            assert_eq!(load.to_string().parse::<ChannelLoad>(), Ok(load));
+        }
+        assert_eq!("Lights".parse::<ChannelLoad>(), Ok(ChannelLoad::Light));
+        assert!("pump".parse::<ChannelLoad>().unwrap_err().contains("pump"));
     }
 }
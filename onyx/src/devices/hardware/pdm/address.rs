@@ -0,0 +1,165 @@
+use serde::{de::Visitor, Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+
+/// Source address of an ix-3212 on the canbus. The address is set by pin
+/// strapping four wires on the PDM harness, so only these four exist in our
+/// wiring and anything else would be a PDM that never answers.
+///
+/// Serialised as the raw address so existing configs are unchanged, and
+/// deserialised from the raw address or the name, i.e. `30` or `pdm_30`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PdmAddress {
+    /// Source address 30.
+    Pdm30,
+    /// Source address 31.
+    Pdm31,
+    /// Source address 32.
+    Pdm32,
+    /// Source address 33.
+    Pdm33,
+}
+
+impl PdmAddress {
+    /// Raw J1939 source address used by the driver.
+    pub fn raw(self) -> u8 {
+        match self {
+            PdmAddress::Pdm30 => 30,
+            PdmAddress::Pdm31 => 31,
+            PdmAddress::Pdm32 => 32,
+            PdmAddress::Pdm33 => 33,
+        }
+    }
+}
+
+impl TryFrom<u8> for PdmAddress {
+    type Error = String;
+
+    fn try_from(address: u8) -> Result<Self, Self::Error> {
+        match address {
+            30 => Ok(PdmAddress::Pdm30),
+            31 => Ok(PdmAddress::Pdm31),
+            32 => Ok(PdmAddress::Pdm32),
+            33 => Ok(PdmAddress::Pdm33),
+            other => Err(format!(
+                "PDM address {other} is not one of the pin strapped addresses 30 to 33"
+            )),
+        }
+    }
+}
+
+impl From<PdmAddress> for u8 {
+    fn from(address: PdmAddress) -> Self {
+        address.raw()
+    }
+}
+
+impl Display for PdmAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw())
+    }
+}
+
+impl FromStr for PdmAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalised = s.trim().to_lowercase().replace(['_', '-', ' '], "");
+        normalised
+            .strip_prefix("pdm")
+            .unwrap_or(&normalised)
+            .parse::<u8>()
+            .map_err(|e| format!("Unknown PDM address {s}: {e}"))
+            .and_then(PdmAddress::try_from)
+    }
+}
+
+impl Serialize for PdmAddress {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_u8(self.raw())
+    }
+}
+
+impl<'de> Deserialize<'de> for PdmAddress {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(PdmAddressVisitor)
+    }
+}
+
+/// Visitor accepting both the raw addresses and their names.
+struct PdmAddressVisitor;
+
+impl<'de> Visitor<'de> for PdmAddressVisitor {
+    type Value = PdmAddress;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str("a PDM address between 30 and 33, or its name such as pdm_30")
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        u8::try_from(value)
+            .map_err(|e| E::custom(format!("PDM address {value} out of range: {e}")))
+            .and_then(|address| PdmAddress::try_from(address).map_err(E::custom))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        u8::try_from(value)
+            .map_err(|e| E::custom(format!("PDM address {value} out of range: {e}")))
+            .and_then(|address| PdmAddress::try_from(address).map_err(E::custom))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        PdmAddress::from_str(value).map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// Stand in for a PDM config with an address field.
+    #[derive(Deserialize, Serialize, Debug, PartialEq)]
+    struct Config {
+        /// Address under test.
+        address: PdmAddress,
+    }
+
+    #[rstest]
+    #[case("address: 30", PdmAddress::Pdm30)]
+    #[case("address: 33", PdmAddress::Pdm33)]
+    #[case("address: pdm_31", PdmAddress::Pdm31)]
+    #[case("address: Pdm32", PdmAddress::Pdm32)]
+    /// Existing numeric configs and the names should both parse from yaml
+    /// the same way PDM configs are read.
+    fn test_pdm_address_from_yaml(#[case] yaml: &str, #[case] expected: PdmAddress) {
+        let config = serde_yaml::from_str::<Config>(yaml).expect("Failed to parse config");
+        assert_eq!(config.address, expected);
+        assert_eq!(serde_yaml::to_string(&config).unwrap(), format!("address: {}\n", expected.raw()));
+    }
+
+    #[rstest]
+    #[case("address: 3")]
+    #[case("address: 34")]
+    #[case("address: 300")]
+    #[case("address: pdm_3")]
+    /// Typos in the address are rejected rather than producing a PDM that
+    /// never answers.
+    fn test_pdm_address_rejects_unstrapped(#[case] yaml: &str) {
+        assert!(serde_yaml::from_str::<Config>(yaml).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use crate::utils::location::GeoPosition;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Instant,
+};
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncWriteExt, BufWriter},
+    sync::mpsc::{self, error::TrySendError},
+};
+use tracing::warn;
+
+/// Actuations kept in memory for [`ActuationAudit::recent`].
+const AUDIT_CAPACITY: usize = 1024;
+
+/// Actuations waiting to be written to the log file before new ones are
+/// dropped from the file, they are still kept in memory.
+const AUDIT_QUEUE_DEPTH: usize = 256;
+
+/// One call to actuate channels on a PDM.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ActuationAuditRecord {
+    /// UTC time the actuation was sent.
+    pub utc: DateTime<Utc>,
+    /// Microseconds since the PDM was created, for ordering and intervals
+    /// that are not affected by the clock being set.
+    pub monotonic_us: u64,
+    /// Channels actuated.
+    pub channels: Vec<u8>,
+    /// Duty cycle the channels were set to in percent.
+    pub pwm: f32,
+    /// Source address commanding the PDM.
+    pub command_id: u8,
+    /// Sent for a manual spray by the operator rather than a detection.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub manual: bool,
+    /// Where the machine was, from the weed message or the latest GPS fix,
+    /// so a treatment map can be built from the log.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<GeoPosition>,
+}
+
+/// Record of every actuation sent to a PDM, kept in a ring in memory and
+/// optionally appended to a json lines file. The file is written by its
+/// own task fed through a bounded channel, so a slow disk drops lines from
+/// the file rather than delaying actuation.
+#[derive(Debug)]
+pub struct ActuationAudit {
+    /// Most recent actuations, oldest first.
+    recent: Mutex<VecDeque<ActuationAuditRecord>>,
+    /// File the actuations are appended to, if any.
+    log_path: Option<PathBuf>,
+    /// Channel to the file writer, started by the first actuation as the
+    /// PDM may be created outside of the runtime.
+    writer: OnceLock<mpsc::Sender<ActuationAuditRecord>>,
+    /// Actuations that could not be queued for the file.
+    dropped: AtomicU64,
+    /// When the audit was created, the origin of the monotonic times.
+    started: Instant,
+}
+
+impl ActuationAudit {
+    /// Create an empty audit.
+    ///
+    /// * `log_path`: json lines file to append actuations to, if any.
+    pub fn new(log_path: Option<PathBuf>) -> Self {
+        Self {
+            recent: Mutex::new(VecDeque::with_capacity(AUDIT_CAPACITY)),
+            log_path,
+            writer: OnceLock::new(),
+            dropped: AtomicU64::new(0),
+            started: Instant::now(),
+        }
+    }
+
+    /// Record an actuation. Never waits on the file, must be called from
+    /// within the runtime when a log file is set.
+    ///
+    /// * `command_id`: source address commanding the PDM.
+    /// * `channels`: channels actuated.
+    /// * `pwm`: duty cycle in percent.
+    /// * `position`: where the machine was, if known.
+    pub fn record(&self, command_id: u8, channels: &[u8], pwm: f32, position: Option<GeoPosition>) {
+        self.record_as(command_id, channels, pwm, false, position);
+    }
+
+    /// Record an actuation for a manual spray, marked so it can be told
+    /// apart from sprays of detections.
+    ///
+    /// * `command_id`: source address commanding the PDM.
+    /// * `channels`: channels actuated.
+    /// * `pwm`: duty cycle in percent.
+    /// * `position`: where the machine was, if known.
+    pub fn record_manual(&self, command_id: u8, channels: &[u8], pwm: f32, position: Option<GeoPosition>) {
+        self.record_as(command_id, channels, pwm, true, position);
+    }
+
+    /// Record an actuation, see [`ActuationAudit::record`].
+    ///
+    /// * `command_id`: source address commanding the PDM.
+    /// * `channels`: channels actuated.
+    /// * `pwm`: duty cycle in percent.
+    /// * `manual`: whether it was for a manual spray.
+    /// * `position`: where the machine was, if known.
+    #[allow(clippy::cast_possible_truncation)]
+    fn record_as(&self, command_id: u8, channels: &[u8], pwm: f32, manual: bool, position: Option<GeoPosition>) {
+        let record = ActuationAuditRecord {
+            utc: Utc::now(),
+            monotonic_us: self.started.elapsed().as_micros() as u64,
+            channels: channels.to_vec(),
+            pwm,
+            command_id,
+            manual,
+            position,
+        };
+        {
+            let mut recent = self.recent.lock().expect("Actuation audit poisoned");
+            if recent.len() == AUDIT_CAPACITY {
+                recent.pop_front();
+            }
+            recent.push_back(record.clone());
+        }
+        if let Some(log_path) = &self.log_path {
+            let writer = self.writer.get_or_init(|| spawn_writer(log_path.clone()));
+            match writer.try_send(record) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_) | TrySendError::Closed(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// The last `n` actuations, oldest first.
+    ///
+    /// * `n`: number of actuations.
+    pub fn recent(&self, n: usize) -> Vec<ActuationAuditRecord> {
+        let recent = self.recent.lock().expect("Actuation audit poisoned");
+        recent.iter().skip(recent.len().saturating_sub(n)).cloned().collect()
+    }
+
+    /// Actuations missing from the log file because the writer fell behind
+    /// or could not open it.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Start the task appending actuations to the log file, it finishes once
+/// the audit has been dropped and the queue written.
+///
+/// * `log_path`: json lines file to append to.
+fn spawn_writer(log_path: PathBuf) -> mpsc::Sender<ActuationAuditRecord> {
+    let (record_tx, mut record_rx) = mpsc::channel::<ActuationAuditRecord>(AUDIT_QUEUE_DEPTH);
+    tokio::spawn(async move {
+        let file = match OpenOptions::new().create(true).append(true).open(&log_path).await {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("Failed to open actuation log {:?}: {e}", log_path);
+                return;
+            }
+        };
+        let mut writer = BufWriter::new(file);
+        while let Some(record) = record_rx.recv().await {
+            let mut line = serde_json::to_vec(&record).expect("Failed to serialise actuation");
+            line.push(b'\n');
+            if let Err(e) = writer.write_all(&line).await {
+                warn!("Failed to write actuation log {:?}: {e}", log_path);
+                return;
+            }
+            // Flush once the queue is empty, so bursts share a write.
+            if record_rx.is_empty() {
+                if let Err(e) = writer.flush().await {
+                    warn!("Failed to write actuation log {:?}: {e}", log_path);
+                    return;
+                }
+            }
+        }
+    });
+    record_tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    /// Recent actuations come back oldest first and the ring keeps only
+    /// the latest.
+    fn test_recent_actuations_in_order() {
+        let audit = ActuationAudit::new(None);
+        for channel in 0..(AUDIT_CAPACITY + 10) {
+            audit.record(17, &[(channel % 12) as u8 + 1], 100.0, None);
+        }
+        let recent = audit.recent(3);
+        assert_eq!(recent.len(), 3);
+        assert!(recent.windows(2).all(|pair| pair[0].monotonic_us <= pair[1].monotonic_us));
+        assert_eq!(recent[2].channels, vec![((AUDIT_CAPACITY + 9) % 12) as u8 + 1]);
+        assert_eq!(audit.recent(usize::MAX).len(), AUDIT_CAPACITY);
+        assert_eq!(audit.dropped(), 0);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::cast_precision_loss, clippy::float_cmp)]
+    /// A burst larger than the queue returns at once, dropping lines from
+    /// the file rather than waiting, and the queued lines reach the file in
+    /// order.
+    async fn test_actuation_log_never_blocks() {
+        let log_path = std::env::temp_dir().join(format!("onyx-actuation-log-{}.jsonl", Uuid::new_v4()));
+        let audit = ActuationAudit::new(Some(log_path.clone()));
+        let burst = AUDIT_QUEUE_DEPTH * 4;
+
+        // The writer cannot run on the single test thread until this yields,
+        // so everything past the queue depth must be dropped, not awaited.
+        let started = Instant::now();
+        for index in 0..burst {
+            audit.record(17, &[1], index as f32, None);
+        }
+        assert!(started.elapsed() < Duration::from_millis(100), "Recording blocked");
+        assert_eq!(audit.dropped(), u64::try_from(burst - AUDIT_QUEUE_DEPTH).unwrap());
+
+        let mut lines = Vec::new();
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            lines = std::fs::read_to_string(&log_path).unwrap_or_default().lines().map(String::from).collect();
+            if lines.len() == AUDIT_QUEUE_DEPTH {
+                break;
+            }
+        }
+        assert_eq!(lines.len(), AUDIT_QUEUE_DEPTH);
+        let written: Vec<ActuationAuditRecord> =
+            lines.iter().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert!(written.iter().enumerate().all(|(index, record)| record.pwm == index as f32));
+        std::fs::remove_file(log_path).unwrap();
+    }
+
+    #[tokio::test]
+    /// Actuations carry the position of the machine into the log file for
+    /// treatment maps, and leave it out when there is none.
+    async fn test_actuation_log_position() {
+        let log_path = std::env::temp_dir().join(format!("onyx-actuation-log-{}.jsonl", Uuid::new_v4()));
+        let audit = ActuationAudit::new(Some(log_path.clone()));
+        let position = GeoPosition::new(-27.4698, 153.0251).with_heading(92.5);
+        audit.record(17, &[4], 100.0, Some(position));
+        audit.record_manual(17, &[5], 100.0, None);
+
+        let mut lines = Vec::new();
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            lines = std::fs::read_to_string(&log_path).unwrap_or_default().lines().map(String::from).collect();
+            if lines.len() == 2 {
+                break;
+            }
+        }
+        assert_eq!(lines.len(), 2);
+        let positioned: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(positioned["position"]["latitude"], -27.4698);
+        assert_eq!(positioned["position"]["heading_deg"], 92.5);
+        assert!(!lines[1].contains("position"), "{}", lines[1]);
+        assert_eq!(audit.recent(2)[0].position, Some(position));
+        std::fs::remove_file(log_path).unwrap();
+    }
+}
@@ -0,0 +1,707 @@
+use serde::Serialize;
+
+/// PGN of the J1939 request message, asks a node to send a PGN.
+pub const REQUEST_PGN: u32 = 0xEA00;
+/// PGN of the J1939 acknowledgement message.
+pub const ACKNOWLEDGEMENT_PGN: u32 = 0xE800;
+/// PGN of the J1939 address claimed message, every node answers a request
+/// for it so it is used to check the PDM is on the bus.
+pub const ADDRESS_CLAIMED_PGN: u32 = 0xEE00;
+/// Source address the control system uses on the canbus, the same address
+/// the spray and lighting components command the PDMs from.
+pub const CONTROLLER_ADDRESS: u8 = 17;
+/// Destination address meaning every node on the bus.
+pub const GLOBAL_ADDRESS: u8 = 0xFF;
+/// PGN of proprietary A, peer to peer messages laid out by the manufacturer.
+pub const PROPRIETARY_A_PGN: u32 = 0xEF00;
+/// First byte of a proprietary message asking for a channel configuration,
+/// combined with the configuration code.
+pub const CONFIGURATION_QUERY: u8 = 0xC0;
+/// First byte of a proprietary message reporting a channel configuration,
+/// combined with the configuration code.
+pub const CONFIGURATION_REPORT: u8 = 0xD0;
+
+/// First byte of the proprietary message the driver turns channels on and
+/// off with, followed by a little endian channel mask where bit 0 is
+/// channel 1, then the duty cycle in half percent steps.
+pub const OUTPUT_COMMAND: u8 = 0xA0;
+/// Duty cycle resolution of an output command in percent per bit.
+const DUTY_PERCENT_PER_BIT: f32 = 0.5;
+
+/// Channels set to a duty cycle by one output command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputCommand {
+    /// Channel numbers, in ascending order.
+    pub channels: Vec<u8>,
+    /// Duty cycle from 0 to 100 percent, 0 turns the channels off.
+    pub duty_percent: f32,
+}
+
+/// Group channels with the same duty cycle, as it goes out on the bus, into
+/// as few output commands as possible. An output command carries one duty
+/// cycle for any set of channels, so this is one command per distinct duty
+/// cycle. Commands turning channels off come first, and a channel listed
+/// twice takes its last duty cycle.
+///
+/// * `duties`: channel number and duty cycle in percent.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn pack_output_commands(duties: &[(u8, f32)]) -> Vec<OutputCommand> {
+    let mut last_duty = std::collections::BTreeMap::new();
+    for (channel, duty_percent) in duties {
+        let steps = (duty_percent.clamp(0.0, 100.0) / DUTY_PERCENT_PER_BIT).round() as u8;
+        last_duty.insert(*channel, steps);
+    }
+    let mut by_duty: std::collections::BTreeMap<u8, Vec<u8>> = std::collections::BTreeMap::new();
+    for (channel, steps) in last_duty {
+        by_duty.entry(steps).or_default().push(channel);
+    }
+    by_duty
+        .into_iter()
+        .map(|(steps, channels)| OutputCommand {
+            channels,
+            duty_percent: f32::from(steps) * DUTY_PERCENT_PER_BIT,
+        })
+        .collect()
+}
+
+/// PGN of the proprietary B message the PDM broadcasts current feedback on,
+/// one frame per channel.
+pub const CHANNEL_FEEDBACK_PGN: u32 = 0xFF10;
+/// Current feedback resolution in amps per bit.
+const FEEDBACK_AMPS_PER_BIT: f32 = 0.01;
+
+/// Current feedback for a single PDM channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelFeedback {
+    /// Channel number.
+    pub channel: u8,
+    /// Current drawn by the load in amps.
+    pub amps: f32,
+    /// Fault bits reported by the PDM, see the `FEEDBACK_` constants.
+    pub status_flags: u8,
+}
+
+impl ChannelFeedback {
+    /// Nothing is connected or the load is broken, e.g. a solenoid coil.
+    pub const FEEDBACK_OPEN_LOAD: u8 = 1;
+    /// The output is shorted.
+    pub const FEEDBACK_SHORT_CIRCUIT: u8 = 1 << 1;
+    /// The load drew more than the channel current limit.
+    pub const FEEDBACK_OVER_CURRENT: u8 = 1 << 2;
+    /// The channel has shut down on temperature.
+    pub const FEEDBACK_THERMAL_SHUTDOWN: u8 = 1 << 3;
+
+    /// Whether any fault bit is set.
+    pub fn has_fault(&self) -> bool {
+        self.status_flags != 0
+    }
+}
+
+/// PGN of the proprietary B message the PDM broadcasts its fault state on.
+pub const STATUS_PGN: u32 = 0xFF11;
+/// Number of output channels on an ix-3212.
+pub const CHANNEL_COUNT: u8 = 12;
+
+/// Faults reported for a single channel.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChannelFaults {
+    /// The channel has tripped on over current and is off.
+    pub over_current_trip: bool,
+    /// The channel driver is over temperature.
+    pub over_temperature: bool,
+}
+
+impl ChannelFaults {
+    /// Whether any fault is set.
+    pub fn any(&self) -> bool {
+        self.over_current_trip || self.over_temperature
+    }
+}
+
+/// Fault state of a PDM. Sent as the module bits, then little endian
+/// channel masks for over current trips and over temperature where bit 0
+/// is channel 1.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PdmStatus {
+    /// The PDM stopped hearing from the controller and has applied its
+    /// loss of communication outputs.
+    pub loss_of_can: bool,
+    /// The PDM as a whole is over temperature.
+    pub module_over_temperature: bool,
+    /// Faults for channels 1 to 12, index 0 is channel 1.
+    pub channels: [ChannelFaults; CHANNEL_COUNT as usize],
+}
+
+impl PdmStatus {
+    /// Loss of CAN bit in the first byte of the status frame.
+    const LOSS_OF_CAN: u8 = 1;
+    /// Module over temperature bit in the first byte of the status frame.
+    const MODULE_OVER_TEMPERATURE: u8 = 1 << 1;
+
+    /// Faults for a channel, `None` outside of 1 to 12.
+    ///
+    /// * `channel`: channel number.
+    pub fn channel(&self, channel: u8) -> Option<ChannelFaults> {
+        channel
+            .checked_sub(1)
+            .and_then(|index| self.channels.get(usize::from(index)))
+            .copied()
+    }
+
+    /// Channels that have tripped on over current.
+    pub fn tripped_channels(&self) -> Vec<u8> {
+        (1..=CHANNEL_COUNT)
+            .filter(|channel| self.channel(*channel).is_some_and(|faults| faults.over_current_trip))
+            .collect()
+    }
+
+    /// Channels whose driver is over temperature.
+    pub fn hot_channels(&self) -> Vec<u8> {
+        (1..=CHANNEL_COUNT)
+            .filter(|channel| self.channel(*channel).is_some_and(|faults| faults.over_temperature))
+            .collect()
+    }
+
+    /// Whether the PDM reports any fault.
+    pub fn has_fault(&self) -> bool {
+        self.loss_of_can || self.module_over_temperature || self.channels.iter().any(ChannelFaults::any)
+    }
+}
+
+/// A J1939 frame split into the fields of its 29 bit identifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct J1939Frame {
+    /// Priority, 0 is the highest.
+    pub priority: u8,
+    /// Parameter group number, with the destination removed for PDU1
+    /// messages.
+    pub pgn: u32,
+    /// Address of the node that sent the frame.
+    pub source: u8,
+    /// Address the frame was sent to, `None` for broadcast PDU2 messages.
+    pub destination: Option<u8>,
+    /// Up to eight bytes of payload.
+    pub data: Vec<u8>,
+}
+
+/// Response carried by an acknowledgement message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckControl {
+    /// The message was accepted.
+    Ack,
+    /// The message was rejected.
+    Nack,
+    /// The message was rejected for security reasons.
+    AccessDenied,
+    /// The node cannot respond at the moment.
+    CannotRespond,
+}
+
+/// Acknowledgement of a message sent to a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Acknowledgement {
+    /// Whether the message was accepted.
+    pub control: AckControl,
+    /// PGN of the message being acknowledged.
+    pub pgn: u32,
+}
+
+impl J1939Frame {
+    /// Split a 29 bit extended identifier into its fields.
+    ///
+    /// * `id`: raw canbus identifier.
+    /// * `data`: frame payload.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn from_id(id: u32, data: &[u8]) -> Self {
+        let pdu_format = (id >> 16) & 0xFF;
+        let pdu_specific = (id >> 8) & 0xFF;
+        let mut pgn = (id >> 8) & 0x3FFFF;
+        // Below 240 the PDU specific byte is the destination address rather
+        // than part of the group number.
+        let destination = if pdu_format < 240 {
+            pgn &= 0x3FF00;
+            Some(pdu_specific as u8)
+        } else {
+            None
+        };
+        Self {
+            priority: ((id >> 26) & 0x7) as u8,
+            pgn,
+            source: (id & 0xFF) as u8,
+            destination,
+            data: data.to_vec(),
+        }
+    }
+
+    /// The 29 bit extended identifier of the frame.
+    pub fn id(&self) -> u32 {
+        let destination = self.destination.map_or(0, u32::from);
+        (u32::from(self.priority & 0x7) << 26)
+            | ((self.pgn & 0x3FFFF) << 8)
+            | (destination << 8)
+            | u32::from(self.source)
+    }
+
+    /// Ask a node to send a parameter group.
+    ///
+    /// * `destination`: address of the node.
+    /// * `pgn`: parameter group being requested.
+    pub fn request(destination: u8, pgn: u32) -> Self {
+        let [pgn_0, pgn_1, pgn_2, _] = pgn.to_le_bytes();
+        Self {
+            priority: 6,
+            pgn: REQUEST_PGN,
+            source: CONTROLLER_ADDRESS,
+            destination: Some(destination),
+            data: vec![pgn_0, pgn_1, pgn_2],
+        }
+    }
+
+    /// Ask a node for the configuration of a channel.
+    ///
+    /// * `destination`: address of the node.
+    /// * `code`: configuration being asked for, below 16.
+    /// * `channel`: channel number.
+    pub fn configuration_query(destination: u8, code: u8, channel: u8) -> Self {
+        Self {
+            priority: 6,
+            pgn: PROPRIETARY_A_PGN,
+            source: CONTROLLER_ADDRESS,
+            destination: Some(destination),
+            data: vec![CONFIGURATION_QUERY | (code & 0xF), channel, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+        }
+    }
+
+    /// Report the configuration of a channel, up to six bytes of payload.
+    /// Sent by the PDM, and by stand in PDMs in tests.
+    ///
+    /// * `source`: address of the reporting node.
+    /// * `code`: configuration being reported, below 16.
+    /// * `channel`: channel number.
+    /// * `payload`: configuration bytes.
+    pub fn configuration_report(source: u8, code: u8, channel: u8, payload: &[u8]) -> Self {
+        let mut data = vec![CONFIGURATION_REPORT | (code & 0xF), channel];
+        data.extend_from_slice(payload);
+        Self {
+            priority: 6,
+            pgn: PROPRIETARY_A_PGN,
+            source,
+            destination: Some(CONTROLLER_ADDRESS),
+            data,
+        }
+    }
+
+    /// Read the frame as a configuration query, giving the code and channel.
+    pub fn as_configuration_query(&self) -> Option<(u8, u8)> {
+        match self.data.as_slice() {
+            [first, channel, ..] if self.pgn == PROPRIETARY_A_PGN && first & 0xF0 == CONFIGURATION_QUERY => {
+                Some((first & 0xF, *channel))
+            }
+            _ => None,
+        }
+    }
+
+    /// Read the frame as a configuration report, giving the code, channel
+    /// and configuration bytes.
+    pub fn as_configuration_report(&self) -> Option<(u8, u8, &[u8])> {
+        match self.data.as_slice() {
+            [first, channel, payload @ ..] if self.pgn == PROPRIETARY_A_PGN && first & 0xF0 == CONFIGURATION_REPORT => {
+                Some((first & 0xF, *channel, payload))
+            }
+            _ => None,
+        }
+    }
+
+    /// Read the frame as channel current feedback, sent as the channel, the
+    /// current in hundredths of an amp little endian, then the fault bits.
+    pub fn as_channel_feedback(&self) -> Option<ChannelFeedback> {
+        match self.data.as_slice() {
+            [channel, current_0, current_1, status_flags, ..] if self.pgn == CHANNEL_FEEDBACK_PGN => {
+                Some(ChannelFeedback {
+                    channel: *channel,
+                    amps: f32::from(u16::from_le_bytes([*current_0, *current_1])) * FEEDBACK_AMPS_PER_BIT,
+                    status_flags: *status_flags,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Read the frame as the fault state of the PDM.
+    pub fn as_status(&self) -> Option<PdmStatus> {
+        match self.data.as_slice() {
+            [module, trip_0, trip_1, temperature_0, temperature_1, ..] if self.pgn == STATUS_PGN => {
+                let trips = u16::from_le_bytes([*trip_0, *trip_1]);
+                let temperatures = u16::from_le_bytes([*temperature_0, *temperature_1]);
+                let mut status = PdmStatus {
+                    loss_of_can: module & PdmStatus::LOSS_OF_CAN != 0,
+                    module_over_temperature: module & PdmStatus::MODULE_OVER_TEMPERATURE != 0,
+                    ..PdmStatus::default()
+                };
+                for (bit, faults) in status.channels.iter_mut().enumerate() {
+                    faults.over_current_trip = trips & (1 << bit) != 0;
+                    faults.over_temperature = temperatures & (1 << bit) != 0;
+                }
+                Some(status)
+            }
+            _ => None,
+        }
+    }
+
+    /// Broadcast the fault state of a PDM, laid out as read by
+    /// [`J1939Frame::as_status`]. Sent by stand in PDMs in tests.
+    ///
+    /// * `source`: address of the reporting node.
+    /// * `status`: fault state to report.
+    pub fn status_report(source: u8, status: &PdmStatus) -> Self {
+        let mut module = 0;
+        if status.loss_of_can {
+            module |= PdmStatus::LOSS_OF_CAN;
+        }
+        if status.module_over_temperature {
+            module |= PdmStatus::MODULE_OVER_TEMPERATURE;
+        }
+        let mut trips: u16 = 0;
+        let mut temperatures: u16 = 0;
+        for (bit, faults) in status.channels.iter().enumerate() {
+            if faults.over_current_trip {
+                trips |= 1 << bit;
+            }
+            if faults.over_temperature {
+                temperatures |= 1 << bit;
+            }
+        }
+        let [trip_0, trip_1] = trips.to_le_bytes();
+        let [temperature_0, temperature_1] = temperatures.to_le_bytes();
+        Self {
+            priority: 6,
+            pgn: STATUS_PGN,
+            source,
+            destination: None,
+            data: vec![module, trip_0, trip_1, temperature_0, temperature_1, 0xFF, 0xFF, 0xFF],
+        }
+    }
+
+    /// Command channels of a node to a duty cycle, as the driver does when
+    /// actuating channels.
+    ///
+    /// * `destination`: address of the node.
+    /// * `channels`: channel numbers from 1 to 16.
+    /// * `duty_percent`: duty cycle from 0 to 100 percent.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn output_command(destination: u8, channels: &[u8], duty_percent: f32) -> Self {
+        let mask = channels
+            .iter()
+            .filter(|channel| (1..=16).contains(*channel))
+            .fold(0u16, |mask, channel| mask | (1 << (channel - 1)));
+        let [mask_0, mask_1] = mask.to_le_bytes();
+        let duty = (duty_percent.clamp(0.0, 100.0) / DUTY_PERCENT_PER_BIT).round() as u8;
+        Self {
+            priority: 3,
+            pgn: PROPRIETARY_A_PGN,
+            source: CONTROLLER_ADDRESS,
+            destination: Some(destination),
+            data: vec![OUTPUT_COMMAND, mask_0, mask_1, duty, 0xFF, 0xFF, 0xFF, 0xFF],
+        }
+    }
+
+    /// Read the frame as an output command.
+    pub fn as_output_command(&self) -> Option<OutputCommand> {
+        match self.data.as_slice() {
+            [OUTPUT_COMMAND, mask_0, mask_1, duty, ..] if self.pgn == PROPRIETARY_A_PGN => {
+                let mask = u16::from_le_bytes([*mask_0, *mask_1]);
+                Some(OutputCommand {
+                    channels: (1..=16).filter(|channel| mask & (1 << (channel - 1)) != 0).collect(),
+                    duty_percent: f32::from(*duty) * DUTY_PERCENT_PER_BIT,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Acknowledge a message sent to a node, laid out as read by
+    /// [`J1939Frame::acknowledgement`]. Sent by stand in PDMs in tests.
+    ///
+    /// * `source`: address of the acknowledging node.
+    /// * `control`: whether the message was accepted.
+    /// * `pgn`: parameter group being acknowledged.
+    pub fn acknowledge(source: u8, control: AckControl, pgn: u32) -> Self {
+        let control = match control {
+            AckControl::Ack => 0,
+            AckControl::Nack => 1,
+            AckControl::AccessDenied => 2,
+            AckControl::CannotRespond => 3,
+        };
+        let [pgn_0, pgn_1, pgn_2, _] = pgn.to_le_bytes();
+        Self {
+            priority: 6,
+            pgn: ACKNOWLEDGEMENT_PGN,
+            source,
+            destination: Some(GLOBAL_ADDRESS),
+            data: vec![control, 0xFF, 0xFF, 0xFF, CONTROLLER_ADDRESS, pgn_0, pgn_1, pgn_2],
+        }
+    }
+
+    /// Read the frame as an acknowledgement, if it is one.
+    pub fn acknowledgement(&self) -> Option<Acknowledgement> {
+        if self.pgn != ACKNOWLEDGEMENT_PGN || self.data.len() < 8 {
+            return None;
+        }
+        let control = match self.data[0] {
+            0 => AckControl::Ack,
+            1 => AckControl::Nack,
+            2 => AckControl::AccessDenied,
+            3 => AckControl::CannotRespond,
+            _ => return None,
+        };
+        Some(Acknowledgement {
+            control,
+            pgn: u32::from_le_bytes([self.data[5], self.data[6], self.data[7], 0]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(0x18EA_1E11, REQUEST_PGN, 0x11, Some(0x1E))]
+    #[case(0x18EE_FF1E, ADDRESS_CLAIMED_PGN, 0x1E, Some(GLOBAL_ADDRESS))]
+    #[case(0x18FE_CA1E, 0xFECA, 0x1E, None)]
+    /// PDU1 identifiers carry a destination, PDU2 do not, and both should
+    /// survive a round trip through the identifier.
+    fn test_split_identifier(
+        #[case] id: u32,
+        #[case] pgn: u32,
+        #[case] source: u8,
+        #[case] destination: Option<u8>,
+    ) {
+        let frame = J1939Frame::from_id(id, &[]);
+        assert_eq!(frame.priority, 6);
+        assert_eq!(frame.pgn, pgn);
+        assert_eq!(frame.source, source);
+        assert_eq!(frame.destination, destination);
+        assert_eq!(frame.id(), id);
+    }
+
+    #[test]
+    /// A rejection from the PDM names the PGN it rejected.
+    fn test_read_negative_acknowledgement() {
+        let nack = J1939Frame::from_id(
+            0x18E8_111E,
+            &[1, 0xFF, 0xFF, 0xFF, CONTROLLER_ADDRESS, 0x00, 0xEF, 0x00],
+        );
+        assert_eq!(
+            nack.acknowledgement(),
+            Some(Acknowledgement {
+                control: AckControl::Nack,
+                pgn: 0xEF00,
+            })
+        );
+        assert_eq!(J1939Frame::request(0x1E, ADDRESS_CLAIMED_PGN).acknowledgement(), None);
+    }
+
+    /// Parse a line of `candump can1` output into a frame.
+    ///
+    /// * `line`: e.g. `can1  18FF101E   [8]  03 F4 01 00 FF FF FF FF`.
+    fn from_candump(line: &str) -> J1939Frame {
+        let mut fields = line.split_whitespace().skip(1);
+        let id = u32::from_str_radix(fields.next().unwrap(), 16).unwrap();
+        let data: Vec<u8> = fields
+            .skip(1)
+            .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+            .collect();
+        J1939Frame::from_id(id, &data)
+    }
+
+    #[rstest]
+    #[case("can1  18FF101E   [8]  03 F4 01 00 FF FF FF FF", 0x1E, 3, 5.0, 0)]
+    #[case("can1  18FF101E   [8]  0C 00 00 01 FF FF FF FF", 0x1E, 12, 0.0, ChannelFeedback::FEEDBACK_OPEN_LOAD)]
+    #[case("can1  18FF101F   [8]  01 6A 05 04 FF FF FF FF", 0x1F, 1, 13.86, ChannelFeedback::FEEDBACK_OVER_CURRENT)]
+    /// Feedback captured from the PDMs on the bench decodes to the current
+    /// and faults shown on the PDM diagnostics.
+    fn test_decode_captured_channel_feedback(
+        #[case] line: &str,
+        #[case] source: u8,
+        #[case] channel: u8,
+        #[case] amps: f32,
+        #[case] status_flags: u8,
+    ) {
+        let frame = from_candump(line);
+        assert_eq!(frame.source, source);
+        let feedback = frame.as_channel_feedback().expect("Not a feedback frame");
+        assert_eq!(feedback.channel, channel);
+        assert!((feedback.amps - amps).abs() < 0.001, "{} amps", feedback.amps);
+        assert_eq!(feedback.status_flags, status_flags);
+        assert_eq!(feedback.has_fault(), status_flags != 0);
+    }
+
+    #[test]
+    /// Other traffic from the PDM is not mistaken for feedback.
+    fn test_ignore_other_frames_as_feedback() {
+        assert!(from_candump("can1  18E8111E   [8]  00 FF FF FF 11 00 EF 00")
+            .as_channel_feedback()
+            .is_none());
+        assert!(from_candump("can1  18FF101E   [2]  03 F4").as_channel_feedback().is_none());
+    }
+
+    #[test]
+    /// A PDM with nothing wrong reports an empty status.
+    fn test_decode_healthy_status() {
+        let status = J1939Frame::from_id(0x18FF_111E, &[0, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF])
+            .as_status()
+            .expect("Not a status frame");
+        assert_eq!(status, PdmStatus::default());
+        assert!(!status.has_fault());
+        assert!(status.tripped_channels().is_empty());
+        assert!(status.hot_channels().is_empty());
+    }
+
+    #[test]
+    /// Module and channel faults decode onto the right channels.
+    fn test_decode_faulted_status() {
+        // Loss of CAN, channels 1 and 12 tripped, channel 5 over temperature.
+        let status = J1939Frame::from_id(0x18FF_111F, &[0b01, 0x01, 0x08, 0x10, 0x00, 0xFF, 0xFF, 0xFF])
+            .as_status()
+            .expect("Not a status frame");
+        assert!(status.loss_of_can);
+        assert!(!status.module_over_temperature);
+        assert_eq!(status.tripped_channels(), vec![1, 12]);
+        assert_eq!(status.hot_channels(), vec![5]);
+        assert_eq!(
+            status.channel(5),
+            Some(ChannelFaults {
+                over_current_trip: false,
+                over_temperature: true,
+            })
+        );
+        assert_eq!(status.channel(0), None);
+        assert_eq!(status.channel(13), None);
+        assert!(status.has_fault());
+    }
+
+    #[test]
+    /// Short status frames and other PGNs are not decoded as status.
+    fn test_ignore_other_frames_as_status() {
+        assert!(J1939Frame::from_id(0x18FF_111E, &[0, 0, 0]).as_status().is_none());
+        assert!(J1939Frame::from_id(0x18FF_101E, &[0, 0, 0, 0, 0]).as_status().is_none());
+    }
+
+    #[test]
+    /// Status sent by a stand in PDM decodes to the same status.
+    fn test_status_report_round_trip() {
+        let mut status = PdmStatus {
+            loss_of_can: true,
+            ..PdmStatus::default()
+        };
+        status.channels[2].over_current_trip = true;
+        status.channels[11].over_temperature = true;
+        let frame = J1939Frame::status_report(0x1E, &status);
+        let frame = J1939Frame::from_id(frame.id(), &frame.data);
+        assert_eq!(frame.as_status(), Some(status));
+    }
+
+    #[rstest]
+    #[case(vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12], 0.0)]
+    #[case(vec![3], 100.0)]
+    #[case(vec![1, 12], 50.5)]
+    /// Output commands keep their channels and duty cycle on the bus.
+    fn test_output_command_round_trip(#[case] channels: Vec<u8>, #[case] duty_percent: f32) {
+        let frame = J1939Frame::output_command(0x1F, &channels, duty_percent);
+        let frame = J1939Frame::from_id(frame.id(), &frame.data);
+        assert_eq!(frame.destination, Some(0x1F));
+        assert_eq!(
+            frame.as_output_command(),
+            Some(OutputCommand {
+                channels,
+                duty_percent,
+            })
+        );
+        assert_eq!(frame.as_configuration_query(), None);
+    }
+
+    #[test]
+    /// Channels sharing a duty cycle go out in one command, in ascending
+    /// duty so channels are turned off first.
+    fn test_pack_output_commands() {
+        let commands = pack_output_commands(&[(1, 100.0), (2, 40.0), (3, 100.0), (4, 0.0), (5, 40.2)]);
+        assert_eq!(
+            commands,
+            vec![
+                OutputCommand {
+                    channels: vec![4],
+                    duty_percent: 0.0,
+                },
+                OutputCommand {
+                    channels: vec![2, 5],
+                    duty_percent: 40.0,
+                },
+                OutputCommand {
+                    channels: vec![1, 3],
+                    duty_percent: 100.0,
+                },
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case(&[], 0)]
+    #[case(&[(1, 100.0), (2, 100.0), (3, 100.0)], 1)]
+    #[case(&[(1, 10.0), (2, 20.0), (3, 30.0)], 3)]
+    #[case(&[(1, 10.0), (1, 100.0)], 1)]
+    #[case(&[(1, 150.0), (2, 100.0), (3, -5.0), (4, 0.0)], 2)]
+    /// Repeated channels and duty cycles out of range do not add frames.
+    fn test_pack_output_commands_frame_count(#[case] duties: &[(u8, f32)], #[case] frames: usize) {
+        let commands = pack_output_commands(duties);
+        assert_eq!(commands.len(), frames, "{commands:?}");
+        let mut channels: Vec<u8> = commands.iter().flat_map(|command| command.channels.clone()).collect();
+        channels.sort_unstable();
+        channels.dedup();
+        assert_eq!(
+            channels.len(),
+            commands.iter().map(|command| command.channels.len()).sum::<usize>(),
+            "A channel was sent twice"
+        );
+    }
+
+    #[test]
+    /// A channel listed twice keeps the duty cycle it was last given.
+    fn test_pack_output_commands_last_duty_wins() {
+        assert_eq!(
+            pack_output_commands(&[(7, 100.0), (7, 25.0)]),
+            vec![OutputCommand {
+                channels: vec![7],
+                duty_percent: 25.0,
+            }]
+        );
+    }
+
+    #[test]
+    /// Acknowledgements from a stand in PDM read back with their PGN.
+    fn test_acknowledge_round_trip() {
+        let frame = J1939Frame::acknowledge(0x1E, AckControl::Nack, PROPRIETARY_A_PGN);
+        let frame = J1939Frame::from_id(frame.id(), &frame.data);
+        assert_eq!(
+            frame.acknowledgement(),
+            Some(Acknowledgement {
+                control: AckControl::Nack,
+                pgn: PROPRIETARY_A_PGN,
+            })
+        );
+    }
+
+    #[test]
+    /// Queries and reports for a channel configuration read back as sent.
+    fn test_configuration_query_and_report() {
+        let query = J1939Frame::configuration_query(0x1E, 1, 7);
+        assert_eq!(query.as_configuration_query(), Some((1, 7)));
+        assert_eq!(query.as_configuration_report(), None);
+
+        let report = J1939Frame::configuration_report(0x1E, 1, 7, &[1, 2, 3]);
+        let report = J1939Frame::from_id(report.id(), &report.data);
+        assert_eq!(report.as_configuration_report(), Some((1, 7, &[1, 2, 3][..])));
+        assert_eq!(report.as_configuration_query(), None);
+    }
+}
@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// Time between saves of the usage state file while channels are actuated.
+const USAGE_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Wear on a single channel, used to swap solenoids before they reach their
+/// rated cycle life.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ChannelUsage {
+    /// Times the channel has been turned on from off.
+    pub on_transitions: u64,
+    /// Total time the channel has spent at a non zero duty cycle.
+    pub on_time_ms: u64,
+}
+
+/// Contents of the usage state file. Fields are defaulted and unknown
+/// fields ignored, so files written by older or newer versions still load.
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(default)]
+struct UsageState {
+    /// Usage keyed by channel number.
+    channels: BTreeMap<u8, ChannelUsage>,
+}
+
+/// Counts on transitions and on time for each channel of a PDM, and keeps
+/// them in a state file so they survive restarts.
+#[derive(Debug)]
+pub struct ChannelUsageTracker {
+    /// Usage of each channel, excluding the channels currently on.
+    usage: BTreeMap<u8, ChannelUsage>,
+    /// When each channel currently on was turned on.
+    on_since: HashMap<u8, Instant>,
+    /// File the usage is kept in, if any.
+    state_file: Option<PathBuf>,
+    /// When the usage was last saved.
+    last_saved: Instant,
+}
+
+impl ChannelUsageTracker {
+    /// Load the usage from the state file, starting from zero when there is
+    /// no file yet or it cannot be read.
+    ///
+    /// * `state_file`: file the usage is kept in, if any.
+    pub fn load(state_file: Option<PathBuf>) -> Self {
+        let usage = state_file
+            .as_ref()
+            .filter(|state_file| state_file.is_file())
+            .and_then(|state_file| {
+                let state = fs::read(state_file)
+                    .map_err(|e| e.to_string())
+                    .and_then(|bytes| serde_json::from_slice::<UsageState>(&bytes).map_err(|e| e.to_string()));
+                match state {
+                    Ok(state) => Some(state.channels),
+                    Err(e) => {
+                        warn!("Ignoring unreadable channel usage {:?}: {e}", state_file);
+                        None
+                    }
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            usage,
+            on_since: HashMap::new(),
+            state_file,
+            last_saved: Instant::now(),
+        }
+    }
+
+    /// Account for channels being set to a duty cycle, saving the usage when
+    /// it has not been saved for a while.
+    ///
+    /// * `channels`: channels actuated.
+    /// * `pwm`: duty cycle in percent.
+    /// * `now`: when the channels were actuated.
+    pub fn record(&mut self, channels: &[u8], pwm: f32, now: Instant) {
+        for channel in channels {
+            if pwm > 0.0 {
+                if !self.on_since.contains_key(channel) {
+                    self.on_since.insert(*channel, now);
+                    self.usage.entry(*channel).or_default().on_transitions += 1;
+                }
+            } else if let Some(on_since) = self.on_since.remove(channel) {
+                self.usage.entry(*channel).or_default().on_time_ms += millis(now.saturating_duration_since(on_since));
+            }
+        }
+        if now.saturating_duration_since(self.last_saved) > USAGE_SAVE_INTERVAL {
+            if let Err(e) = self.save(now) {
+                warn!("Failed to save channel usage {:?}: {e}", self.state_file);
+            }
+        }
+    }
+
+    /// Usage of every channel that has been turned on, including the time
+    /// channels that are still on have been on for.
+    ///
+    /// * `now`: time to count on time up to.
+    pub fn snapshot(&self, now: Instant) -> BTreeMap<u8, ChannelUsage> {
+        let mut usage = self.usage.clone();
+        for (channel, on_since) in &self.on_since {
+            usage.entry(*channel).or_default().on_time_ms += millis(now.saturating_duration_since(*on_since));
+        }
+        usage
+    }
+
+    /// Write the usage to the state file, through a temporary file so a
+    /// power cut never leaves a partial file.
+    ///
+    /// * `now`: time to count on time up to.
+    pub fn save(&mut self, now: Instant) -> io::Result<()> {
+        self.last_saved = now;
+        let Some(state_file) = &self.state_file else {
+            return Ok(());
+        };
+        let state = UsageState {
+            channels: self.snapshot(now),
+        };
+        let partial = state_file.with_extension("partial");
+        fs::write(&partial, serde_json::to_vec_pretty(&state)?)?;
+        fs::rename(partial, state_file)
+    }
+}
+
+/// Whole milliseconds of a duration.
+///
+/// * `duration`: duration to convert.
+#[allow(clippy::cast_possible_truncation)]
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    /// On transitions count once per turn on, repeats while on do not, and
+    /// on time accumulates only while the duty cycle is non zero.
+    fn test_usage_accumulates() {
+        let mut tracker = ChannelUsageTracker::load(None);
+        let start = Instant::now();
+        tracker.record(&[1, 2], 100.0, start);
+        tracker.record(&[1], 50.0, start + Duration::from_millis(100));
+        tracker.record(&[1, 2], 0.0, start + Duration::from_millis(200));
+        tracker.record(&[1], 0.0, start + Duration::from_millis(300));
+        tracker.record(&[1], 100.0, start + Duration::from_millis(1000));
+
+        let usage = tracker.snapshot(start + Duration::from_millis(1050));
+        assert_eq!(
+            usage[&1],
+            ChannelUsage {
+                on_transitions: 2,
+                on_time_ms: 250,
+            }
+        );
+        assert_eq!(
+            usage[&2],
+            ChannelUsage {
+                on_transitions: 1,
+                on_time_ms: 200,
+            }
+        );
+        assert!(!usage.contains_key(&3));
+    }
+
+    #[test]
+    /// Usage saved by one tracker is picked up by the next, as after a
+    /// restart.
+    fn test_usage_survives_reload() {
+        let state_file = std::env::temp_dir().join(format!("onyx-channel-usage-{}.json", Uuid::new_v4()));
+        let start = Instant::now();
+        let mut tracker = ChannelUsageTracker::load(Some(state_file.clone()));
+        tracker.record(&[4], 100.0, start);
+        tracker.record(&[4], 0.0, start + Duration::from_millis(40));
+        tracker.save(start + Duration::from_millis(40)).unwrap();
+
+        let mut reloaded = ChannelUsageTracker::load(Some(state_file.clone()));
+        reloaded.record(&[4], 100.0, start);
+        reloaded.record(&[4], 0.0, start + Duration::from_millis(10));
+        assert_eq!(
+            reloaded.snapshot(start)[&4],
+            ChannelUsage {
+                on_transitions: 2,
+                on_time_ms: 50,
+            }
+        );
+        fs::remove_file(state_file).unwrap();
+    }
+
+    #[test]
+    /// State files with missing or extra fields still load, and unreadable
+    /// ones start from zero rather than stopping the PDM.
+    fn test_usage_state_forward_compatible() {
+        let state_file = std::env::temp_dir().join(format!("onyx-channel-usage-{}.json", Uuid::new_v4()));
+        fs::write(
+            &state_file,
+            r#"{"version": 3, "channels": {"2": {"on_transitions": 7, "peak_amps": 1.5}}}"#,
+        )
+        .unwrap();
+        let tracker = ChannelUsageTracker::load(Some(state_file.clone()));
+        assert_eq!(
+            tracker.snapshot(Instant::now())[&2],
+            ChannelUsage {
+                on_transitions: 7,
+                on_time_ms: 0,
+            }
+        );
+
+        fs::write(&state_file, "not json").unwrap();
+        assert!(ChannelUsageTracker::load(Some(state_file.clone()))
+            .snapshot(Instant::now())
+            .is_empty());
+        fs::remove_file(state_file).unwrap();
+    }
+}
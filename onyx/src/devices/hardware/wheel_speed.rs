@@ -0,0 +1,396 @@
+use crate::devices::hardware::pdm::frames::J1939Frame;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "canbus")]
+use socketcan::{tokio::CanSocket as AsyncCanSocket, EmbeddedFrame, Frame};
+use std::{
+    hash::{Hash, Hasher},
+    io,
+    path::PathBuf,
+    time::Duration,
+};
+use tokio::{sync::watch, task::JoinHandle, time::Instant};
+use tracing::warn;
+
+/// Cruise control and vehicle speed parameter group, carrying the wheel
+/// based vehicle speed in bytes 2 and 3.
+pub const WHEEL_SPEED_PGN: u32 = 0xFEF1;
+
+/// Raw wheel based speed values from this up are error or not available.
+const WHEEL_SPEED_NOT_AVAILABLE: u16 = 0xFB00;
+
+/// Time between reads of a pulse counter when none is set in the config.
+const DEFAULT_SAMPLE_INTERVAL_MS: u64 = 50;
+
+/// Ground speed of the machine. Compared on the bits of the value so it can
+/// be carried by hashable queue messages.
+#[derive(Debug, Clone, Copy)]
+pub struct GroundSpeed(f64);
+
+impl GroundSpeed {
+    /// Ground speed from metres per second.
+    ///
+    /// * `mps`: speed in metres per second.
+    pub fn from_mps(mps: f64) -> Self {
+        Self(mps)
+    }
+
+    /// Speed in metres per second.
+    pub fn mps(self) -> f64 {
+        self.0
+    }
+}
+
+impl PartialEq for GroundSpeed {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for GroundSpeed {}
+
+impl Hash for GroundSpeed {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+/// Wheel based speed in a cruise control and vehicle speed frame, `None`
+/// for other frames or when the sender reports the speed as unavailable.
+///
+/// * `frame`: frame read from the bus.
+pub fn wheel_speed(frame: &J1939Frame) -> Option<GroundSpeed> {
+    if frame.pgn != WHEEL_SPEED_PGN || frame.data.len() < 3 {
+        return None;
+    }
+    let raw = u16::from_le_bytes([frame.data[1], frame.data[2]]);
+    if raw >= WHEEL_SPEED_NOT_AVAILABLE {
+        return None;
+    }
+    // 1/256 km/h per bit.
+    Some(GroundSpeed::from_mps(f64::from(raw) / 256.0 / 3.6))
+}
+
+/// Where ground speed readings come from, a sensor on the machine or a
+/// stand in for one.
+pub trait SpeedSource: Send + 'static {
+    /// Wait for the next reading, `None` once the source has stopped.
+    fn next_speed(&mut self) -> BoxFuture<'_, Option<GroundSpeed>>;
+}
+
+/// Wheel speed broadcast on the canbus by the tractor or a speed sensor
+/// module.
+#[cfg(feature = "canbus")]
+pub struct CanSpeedSource {
+    /// Socket listening on the bus.
+    socket: AsyncCanSocket,
+    /// Only take the speed from this node, any node when not set.
+    source_address: Option<u8>,
+}
+
+#[cfg(feature = "canbus")]
+impl CanSpeedSource {
+    /// Listen for wheel speed on a canbus.
+    ///
+    /// * `canbus_id`: String for the bus i.e., can0.
+    /// * `source_address`: node sending the speed, any node when not set.
+    pub fn open(canbus_id: &str, source_address: Option<u8>) -> io::Result<Self> {
+        Ok(Self {
+            socket: AsyncCanSocket::open(canbus_id)?,
+            source_address,
+        })
+    }
+}
+
+#[cfg(feature = "canbus")]
+impl SpeedSource for CanSpeedSource {
+    fn next_speed(&mut self) -> BoxFuture<'_, Option<GroundSpeed>> {
+        Box::pin(async move {
+            loop {
+                let frame = match self.socket.read_frame().await {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        warn!("Stopped reading wheel speed: {e}");
+                        return None;
+                    }
+                };
+                if !frame.is_extended() {
+                    continue;
+                }
+                let frame = J1939Frame::from_id(frame.raw_id(), frame.data());
+                if self.source_address.is_some_and(|address| address != frame.source) {
+                    continue;
+                }
+                if let Some(speed) = wheel_speed(&frame) {
+                    return Some(speed);
+                }
+            }
+        })
+    }
+}
+
+/// Quadrature encoder on a wheel, counted by the kernel counter subsystem
+/// and read from its sysfs count file.
+pub struct PulseSpeedSource {
+    /// Count file of the counter, e.g.
+    /// `/sys/bus/counter/devices/counter0/count0/count`.
+    counter_path: PathBuf,
+    /// Counts per metre travelled.
+    counts_per_metre: u32,
+    /// Time between reads of the count.
+    sample_interval: Duration,
+    /// Count at the last read and when it was read.
+    last: Option<(i64, Instant)>,
+}
+
+impl PulseSpeedSource {
+    /// Read speed from a pulse counter.
+    ///
+    /// * `counter_path`: count file of the counter.
+    /// * `counts_per_metre`: counts per metre travelled.
+    /// * `sample_interval`: time between reads of the count.
+    pub fn new(counter_path: PathBuf, counts_per_metre: u32, sample_interval: Duration) -> Self {
+        Self {
+            counter_path,
+            counts_per_metre,
+            sample_interval,
+            last: None,
+        }
+    }
+
+    /// Current value of the counter.
+    async fn read_count(&self) -> io::Result<i64> {
+        let count = tokio::fs::read_to_string(&self.counter_path).await?;
+        count
+            .trim()
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl SpeedSource for PulseSpeedSource {
+    #[allow(clippy::cast_precision_loss)]
+    fn next_speed(&mut self) -> BoxFuture<'_, Option<GroundSpeed>> {
+        Box::pin(async move {
+            loop {
+                if self.last.is_some() {
+                    tokio::time::sleep(self.sample_interval).await;
+                }
+                let count = match self.read_count().await {
+                    Ok(count) => count,
+                    Err(e) => {
+                        warn!("Stopped reading wheel speed from {:?}: {e}", self.counter_path);
+                        return None;
+                    }
+                };
+                let now = Instant::now();
+                // The encoder is quadrature so reversing counts down, the
+                // distance travelled is the same either way.
+                if let Some((last_count, last_read)) = self.last.replace((count, now)) {
+                    let elapsed = now.duration_since(last_read).as_secs_f64();
+                    if elapsed > 0.0 {
+                        let metres = count.abs_diff(last_count) as f64 / f64::from(self.counts_per_metre);
+                        return Some(GroundSpeed::from_mps(metres / elapsed));
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Default time between reads of a pulse counter.
+fn default_sample_interval_ms() -> u64 {
+    DEFAULT_SAMPLE_INTERVAL_MS
+}
+
+/// Where a component reads the ground speed from.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WheelSpeedConfig {
+    /// Wheel based vehicle speed broadcast on a canbus.
+    Can {
+        /// The addressable canbus interface ID.
+        canbus_id: String,
+        /// Node sending the speed, any node when not set.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source_address: Option<u8>,
+    },
+    /// Quadrature encoder read through the kernel counter subsystem.
+    Pulse {
+        /// Count file of the counter.
+        counter_path: PathBuf,
+        /// Counts per metre travelled.
+        counts_per_metre: u32,
+        /// Time between reads of the count.
+        #[serde(default = "default_sample_interval_ms")]
+        sample_interval_ms: u64,
+    },
+}
+
+impl WheelSpeedConfig {
+    /// Open the speed source described by the config.
+    pub fn open(&self) -> io::Result<Box<dyn SpeedSource>> {
+        Ok(match self {
+            #[cfg(feature = "canbus")]
+            WheelSpeedConfig::Can {
+                canbus_id,
+                source_address,
+            } => Box::new(CanSpeedSource::open(canbus_id, *source_address)?),
+            #[cfg(not(feature = "canbus"))]
+            WheelSpeedConfig::Can { .. } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Wheel speed on the canbus needs a build with the canbus feature",
+                ))
+            }
+            WheelSpeedConfig::Pulse {
+                counter_path,
+                counts_per_metre,
+                sample_interval_ms,
+            } => Box::new(PulseSpeedSource::new(
+                counter_path.clone(),
+                *counts_per_metre,
+                Duration::from_millis(*sample_interval_ms),
+            )),
+        })
+    }
+}
+
+/// Device publishing the ground speed read from a speed source. The speed
+/// is `None` until the first reading and again once the source stops.
+pub struct WheelSpeedSensor {
+    /// Latest ground speed.
+    speed_rx: watch::Receiver<Option<GroundSpeed>>,
+    /// Task reading the source.
+    task: JoinHandle<()>,
+}
+
+impl Drop for WheelSpeedSensor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl WheelSpeedSensor {
+    /// Start reading a speed source, must be called from within the runtime.
+    ///
+    /// * `source`: where the readings come from.
+    pub fn start(mut source: Box<dyn SpeedSource>) -> Self {
+        let (speed_tx, speed_rx) = watch::channel(None);
+        let task = tokio::spawn(async move {
+            while let Some(speed) = source.next_speed().await {
+                speed_tx.send_replace(Some(speed));
+            }
+            speed_tx.send_replace(None);
+        });
+        Self { speed_rx, task }
+    }
+
+    /// Receiver notified on every new reading.
+    pub fn subscribe(&self) -> watch::Receiver<Option<GroundSpeed>> {
+        self.speed_rx.clone()
+    }
+
+    /// Latest ground speed, `None` when there is no reading.
+    pub fn speed(&self) -> Option<GroundSpeed> {
+        *self.speed_rx.borrow()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use rstest::rstest;
+    use uuid::Uuid;
+
+    #[rstest]
+    #[case::stopped(0x0000, Some(0.0))]
+    #[case::ten_kph(0x0A00, Some(10.0 / 3.6))]
+    #[case::not_available(0xFFFF, None)]
+    #[case::error(0xFE00, None)]
+    fn test_wheel_speed_frame(#[case] raw: u16, #[case] expected_mps: Option<f64>) {
+        let [low, high] = raw.to_le_bytes();
+        let frame = J1939Frame {
+            priority: 6,
+            pgn: WHEEL_SPEED_PGN,
+            source: 0,
+            destination: None,
+            data: vec![0, low, high, 0, 0, 0, 0, 0],
+        };
+        let speed = wheel_speed(&frame).map(GroundSpeed::mps);
+        match (speed, expected_mps) {
+            (Some(speed), Some(expected)) => assert!((speed - expected).abs() < 1e-9, "Read {speed}m/s"),
+            (speed, expected) => assert_eq!(speed, expected),
+        }
+    }
+
+    #[test]
+    /// Frames of other parameter groups are not read as speed.
+    fn test_ignore_other_frames() {
+        let frame = J1939Frame {
+            priority: 6,
+            pgn: WHEEL_SPEED_PGN + 1,
+            source: 0,
+            destination: None,
+            data: vec![0, 0, 0x0A, 0, 0, 0, 0, 0],
+        };
+        assert_eq!(wheel_speed(&frame), None);
+    }
+
+    #[test]
+    fn test_parse_wheel_speed_config() {
+        let can: WheelSpeedConfig = serde_yaml::from_str(
+            r#"
+            can:
+              canbus_id: can1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            can,
+            WheelSpeedConfig::Can {
+                canbus_id: String::from("can1"),
+                source_address: None,
+            }
+        );
+        let pulse: WheelSpeedConfig = serde_yaml::from_str(
+            r#"
+            pulse:
+              counter_path: /sys/bus/counter/devices/counter0/count0/count
+              counts_per_metre: 2000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            pulse,
+            WheelSpeedConfig::Pulse {
+                counter_path: PathBuf::from("/sys/bus/counter/devices/counter0/count0/count"),
+                counts_per_metre: 2000,
+                sample_interval_ms: DEFAULT_SAMPLE_INTERVAL_MS,
+            }
+        );
+    }
+
+    #[tokio::test]
+    /// Speed from a pulse counter is the distance counted over the time
+    /// between reads, whichever way the encoder turns.
+    async fn test_pulse_speed() {
+        let counter_path = std::env::temp_dir().join(format!("onyx-wheel-counter-{}", Uuid::new_v4()));
+        std::fs::write(&counter_path, "1000\n").unwrap();
+        let mut source = PulseSpeedSource::new(counter_path.clone(), 1000, Duration::from_millis(100));
+
+        let writer_path = counter_path.clone();
+        let writer = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            tokio::fs::write(&writer_path, "800\n").await.unwrap();
+        });
+        let speed = source.next_speed().await.unwrap().mps();
+        writer.await.unwrap();
+        // 200 counts is 0.2m over roughly 100ms.
+        assert!(speed > 1.5 && speed <= 2.0, "Read {speed}m/s");
+
+        std::fs::remove_file(&counter_path).unwrap();
+        assert_eq!(source.next_speed().await, None);
+    }
+}
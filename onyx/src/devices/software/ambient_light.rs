@@ -0,0 +1,76 @@
+use crate::devices::hardware::ambient_light::LuxSource;
+use futures::future::BoxFuture;
+use tokio::sync::mpsc;
+
+/// Stand in for an ambient light sensor, reporting the readings set through
+/// its handle. Used to test how the lighting follows dusk and dawn.
+pub struct SimulatedLuxSource {
+    /// Readings set through the handle.
+    lux_rx: mpsc::UnboundedReceiver<u32>,
+}
+
+/// Handle setting the readings a simulated source reports, the source stops
+/// once the handle is dropped.
+#[derive(Clone)]
+pub struct SimulatedLuxHandle {
+    /// Readings sent to the source.
+    lux_tx: mpsc::UnboundedSender<u32>,
+}
+
+impl SimulatedLuxSource {
+    /// Create a simulated source and the handle driving it.
+    pub fn new() -> (Self, SimulatedLuxHandle) {
+        let (lux_tx, lux_rx) = mpsc::unbounded_channel();
+        (Self { lux_rx }, SimulatedLuxHandle { lux_tx })
+    }
+}
+
+impl LuxSource for SimulatedLuxSource {
+    fn next_lux(&mut self) -> BoxFuture<'_, Option<u32>> {
+        Box::pin(self.lux_rx.recv())
+    }
+}
+
+impl SimulatedLuxHandle {
+    /// Report a new reading.
+    ///
+    /// * `lux`: illuminance in lux.
+    pub fn set_lux(&self, lux: u32) {
+        // The source being gone only means nothing is listening any more.
+        let _ = self.lux_tx.send(lux);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::devices::hardware::ambient_light::AmbientLightSensor;
+    use std::time::Duration;
+
+    #[tokio::test]
+    /// The sensor publishes each reading set on the simulated source, and no
+    /// reading once the source stops.
+    async fn test_sensor_follows_simulated_lux() {
+        let (source, handle) = SimulatedLuxSource::new();
+        let sensor = AmbientLightSensor::start(Box::new(source));
+        let mut lux_rx = sensor.subscribe();
+        assert_eq!(sensor.lux(), None);
+
+        for lux in [400, 35] {
+            handle.set_lux(lux);
+            tokio::time::timeout(Duration::from_millis(100), lux_rx.changed())
+                .await
+                .expect("Reading was not published")
+                .unwrap();
+            assert_eq!(sensor.lux(), Some(lux));
+        }
+
+        drop(handle);
+        tokio::time::timeout(Duration::from_millis(100), lux_rx.changed())
+            .await
+            .expect("Stopping was not published")
+            .unwrap();
+        assert_eq!(sensor.lux(), None);
+    }
+}
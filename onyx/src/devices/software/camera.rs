@@ -0,0 +1,273 @@
+use crate::{
+    devices::{
+        hardware::camera::{CameraError, Capture, ImageDevice},
+        traits::{Device, DeviceConfig},
+    },
+    utils::{
+        config::{Validate, ValidationReport},
+        image::Roi,
+    },
+};
+use image::{DynamicImage, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, thread, time::Duration};
+use uuid::Uuid;
+
+/// Configuration for a simulated camera, used in place of an
+/// [`crate::devices::hardware::camera::OnyxCamera`] when testing components
+/// without the hardware.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SimulatedCameraConfig {
+    /// Location of the camera on the crop bed.
+    bed_location_id: Option<u8>,
+    /// Frame rate of the camera.
+    fps: u32,
+    /// Width of the generated frames.
+    width: u32,
+    /// Height of the generated frames.
+    height: u32,
+    /// Panic after this many frames to exercise recovery paths.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    panic_after_frames: Option<u64>,
+    /// Stop producing frames after this many to exercise the watchdog.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stall_after_frames: Option<u64>,
+    /// Keep stalling through a stream restart so only a rebuild recovers.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    stall_survives_stream_restart: bool,
+}
+
+impl SimulatedCameraConfig {
+    /// Create a new simulated camera config.
+    ///
+    /// * `bed_location_id`: location of the camera on the crop bed.
+    /// * `fps`: frames per second.
+    /// * `width`: frame width in pixels.
+    /// * `height`: frame height in pixels.
+    pub fn new(bed_location_id: Option<u8>, fps: u32, width: u32, height: u32) -> Self {
+        Self {
+            bed_location_id,
+            fps,
+            width,
+            height,
+            panic_after_frames: None,
+            stall_after_frames: None,
+            stall_survives_stream_restart: false,
+        }
+    }
+
+    /// Make the camera stop producing frames after a number of frames.
+    ///
+    /// * `frames`: frames to capture before stalling.
+    /// * `survives_stream_restart`: only recover when the device is rebuilt.
+    pub fn with_stall_after_frames(mut self, frames: u64, survives_stream_restart: bool) -> Self {
+        self.stall_after_frames = Some(frames);
+        self.stall_survives_stream_restart = survives_stream_restart;
+        self
+    }
+
+    /// Frames per second of the camera.
+    pub fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    /// Make the camera panic after a number of frames.
+    ///
+    /// * `frames`: frames to capture before panicking.
+    pub fn with_panic_after_frames(mut self, frames: u64) -> Self {
+        self.panic_after_frames = Some(frames);
+        self
+    }
+
+    /// Width and height of the generated frames.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+impl Validate for SimulatedCameraConfig {
+    fn validate(&self, report: &mut ValidationReport) {
+        if self.fps == 0 {
+            report.push("fps", "no frames are captured at 0 fps");
+        }
+        if self.width == 0 || self.height == 0 {
+            report.push("width", format!("frames of {}x{} have no pixels", self.width, self.height));
+        }
+    }
+}
+
+impl DeviceConfig for SimulatedCameraConfig {
+    type Device = SimulatedCamera;
+    type Error = Infallible;
+
+    fn location(&self) -> Option<u8> {
+        self.bed_location_id
+    }
+
+    fn build(self) -> Result<SimulatedCamera, Infallible> {
+        Ok(SimulatedCamera::new(self))
+    }
+}
+
+/// Camera that generates a gradient frame that shifts every capture.
+pub struct SimulatedCamera {
+    /// Unique id of the device.
+    uuid: Uuid,
+    /// Config the camera was built from.
+    config: SimulatedCameraConfig,
+    /// Frames captured so far.
+    frames: u64,
+    /// Set once the injected stall has been cleared, a device stalls once.
+    stall_cleared: bool,
+}
+
+impl SimulatedCamera {
+    /// Create a simulated camera from its config.
+    ///
+    /// * `config`: `SimulatedCameraConfig`
+    pub fn new(config: SimulatedCameraConfig) -> Self {
+        Self {
+            uuid: Uuid::new_v4(),
+            config,
+            frames: 0,
+            stall_cleared: false,
+        }
+    }
+
+    /// Whether the injected stall is holding back frames.
+    fn is_stalled(&self) -> bool {
+        !self.stall_cleared
+            && self
+                .config
+                .stall_after_frames
+                .is_some_and(|limit| self.frames >= limit)
+    }
+
+    /// Update the location of the camera on the crop bed.
+    ///
+    /// * `location_id`: location as per bill of materials.
+    pub fn set_location_id(&mut self, location_id: u8) {
+        self.config.bed_location_id = Some(location_id);
+    }
+}
+
+impl Device for SimulatedCamera {
+    fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    fn location(&self) -> Option<u8> {
+        self.config.bed_location_id
+    }
+}
+
+impl ImageDevice for SimulatedCamera {
+    type Stream = ();
+
+    fn get_uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    fn location_id(&self) -> Option<u8> {
+        self.config.bed_location_id
+    }
+
+    fn frame_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / f64::from(self.config.fps.max(1)))
+    }
+
+    #[allow(clippy::cast_possible_wrap)]
+    fn roi(&self) -> Option<Roi> {
+        Some(Roi {
+            x: 0,
+            y: 0,
+            w: self.config.width as i32,
+            h: self.config.height as i32,
+        })
+    }
+
+    fn open_stream(&mut self) -> Result<Self::Stream, CameraError> {
+        Ok(())
+    }
+
+    fn capture(&mut self, _stream: &mut Self::Stream) -> Capture {
+        if let Some(limit) = self.config.panic_after_frames {
+            assert!(self.frames < limit, "Simulated camera failed after {limit} frames");
+        }
+        if self.is_stalled() {
+            // Mimic a stream that has stopped filling buffers without
+            // spinning the capture loop.
+            thread::sleep(Duration::from_millis(1));
+            return Capture::Pending;
+        }
+        self.frames += 1;
+        #[allow(clippy::cast_possible_truncation)]
+        let shift = self.frames as u32;
+        let image = RgbImage::from_fn(self.config.width, self.config.height, |x, y| {
+            #[allow(clippy::cast_possible_truncation)]
+            image::Rgb([
+                (x.wrapping_add(shift) % 256) as u8,
+                (y % 256) as u8,
+                (shift % 256) as u8,
+            ])
+        });
+        Capture::Frame(DynamicImage::ImageRgb8(image))
+    }
+
+    fn restart_stream(&mut self, _stream: &mut Self::Stream) {
+        if self.is_stalled() && !self.config.stall_survives_stream_restart {
+            self.stall_cleared = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Cameras without a frame rate or with empty frames are reported.
+    fn test_validate_simulated_camera() {
+        let mut report = ValidationReport::new("camera_array.yaml");
+        SimulatedCameraConfig::new(None, 20, 8, 8).validate(&mut report);
+        assert!(report.is_valid(), "{report}");
+        SimulatedCameraConfig::new(None, 0, 8, 0).validate(&mut report);
+        assert_eq!(report.fields(), ["fps", "width"], "{report}");
+    }
+
+    #[test]
+    fn test_simulated_camera_frames_change() {
+        let mut camera = SimulatedCamera::new(SimulatedCameraConfig::new(Some(0), 10, 8, 4));
+        let mut stream = camera.open_stream().unwrap();
+        let Capture::Frame(first) = camera.capture(&mut stream) else {
+            panic!("Simulated camera did not produce a frame");
+        };
+        let Capture::Frame(second) = camera.capture(&mut stream) else {
+            panic!("Simulated camera did not produce a frame");
+        };
+        assert_eq!((first.width(), first.height()), (8, 4));
+        assert_ne!(first.as_bytes(), second.as_bytes());
+    }
+
+    #[test]
+    /// A stall clears on a stream restart unless it is set to survive it.
+    fn test_simulated_camera_stall() {
+        for survives in [false, true] {
+            let config = SimulatedCameraConfig::new(None, 10, 2, 2).with_stall_after_frames(1, survives);
+            let mut camera = SimulatedCamera::new(config);
+            assert!(matches!(camera.capture(&mut ()), Capture::Frame(_)));
+            assert!(matches!(camera.capture(&mut ()), Capture::Pending));
+            camera.restart_stream(&mut ());
+            assert_eq!(matches!(camera.capture(&mut ()), Capture::Frame(_)), !survives);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Simulated camera failed after 1 frames")]
+    fn test_simulated_camera_panics_after_limit() {
+        let config = SimulatedCameraConfig::new(None, 10, 2, 2).with_panic_after_frames(1);
+        let mut camera = SimulatedCamera::new(config);
+        camera.capture(&mut ());
+        camera.capture(&mut ());
+    }
+}
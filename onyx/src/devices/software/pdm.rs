@@ -0,0 +1,390 @@
+use crate::devices::hardware::pdm::{
+    frames::{
+        AckControl, J1939Frame, ADDRESS_CLAIMED_PGN, CHANNEL_COUNT, CONTROLLER_ADDRESS,
+        GLOBAL_ADDRESS, PROPRIETARY_A_PGN, REQUEST_PGN,
+    },
+    next_frame, send_frame, PdmAddress, PdmConfig, PdmError, PdmStatus,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use socketcan::tokio::CanSocket as AsyncCanSocket;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+use tokio::{sync::Mutex, task::JoinHandle, time::Instant};
+use tracing::warn;
+
+/// Time without hearing from the controller after which an ix-3212 applies
+/// its loss of communication outputs.
+pub const LOSS_OF_CAN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Why the outputs of a simulated PDM changed.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ActuationCause {
+    /// The controller sent an output command.
+    Command,
+    /// Nothing was heard from the controller within the loss of CAN timeout
+    /// and every output was turned off.
+    LossOfCan,
+}
+
+/// One change to the outputs of a simulated PDM.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActuationRecord {
+    /// Channels set, in ascending order.
+    pub channels: Vec<u8>,
+    /// Duty cycle the channels were set to in percent.
+    pub duty_percent: f32,
+    /// When the change happened.
+    pub at: DateTime<Utc>,
+    /// Why the outputs changed.
+    pub cause: ActuationCause,
+    /// Command id the output command was sent with, `None` when the outputs
+    /// were cut on loss of CAN.
+    pub command_id: Option<u8>,
+}
+
+/// State of a simulated PDM shared with its handle.
+#[derive(Debug, Default)]
+struct SimulatedPdmState {
+    /// Every change to the outputs, oldest first.
+    actuations: Vec<ActuationRecord>,
+    /// Duty cycle of channels 1 to 12, index 0 is channel 1.
+    outputs: [f32; CHANNEL_COUNT as usize],
+    /// Configuration frames acknowledged.
+    configurations_received: usize,
+    /// Fault state broadcast on the bus.
+    status: PdmStatus,
+}
+
+/// Stand in for an ix-3212 on a (v)can interface, used to test the crop
+/// bed components without the hardware. It answers the address claim
+/// request, acknowledges configuration frames, reports the configuration
+/// from its config when asked, records every output command and turns every
+/// output off when the controller goes quiet for [`LOSS_OF_CAN_TIMEOUT`].
+#[derive(Debug)]
+pub struct SimulatedPdm {
+    /// Address to answer on and configuration to report.
+    config: PdmConfig,
+    /// Time without traffic from the controller before the outputs are cut.
+    loss_of_can_timeout: Duration,
+}
+
+impl SimulatedPdm {
+    /// Create a simulated PDM.
+    ///
+    /// * `config`: address to answer on and configuration to report when
+    ///   read back, typically the config given to the component under test.
+    pub fn new(config: PdmConfig) -> Self {
+        Self {
+            config,
+            loss_of_can_timeout: LOSS_OF_CAN_TIMEOUT,
+        }
+    }
+
+    /// Override the time without traffic before the outputs are cut.
+    ///
+    /// * `timeout`: loss of CAN timeout.
+    pub fn with_loss_of_can_timeout(mut self, timeout: Duration) -> Self {
+        self.loss_of_can_timeout = timeout;
+        self
+    }
+
+    /// Start answering on the interface.
+    ///
+    /// * `canbus_id`: interface to listen on, i.e. vcan0.
+    pub fn start(self, canbus_id: &str) -> Result<SimulatedPdmHandle, PdmError> {
+        let socket = AsyncCanSocket::open(canbus_id)?;
+        let state = Arc::new(StdMutex::new(SimulatedPdmState::default()));
+        let address = self.config.address;
+        let task = tokio::spawn(run(
+            socket,
+            address.raw(),
+            self.config.actuate_command_id(),
+            self.config.configuration_payloads(),
+            self.loss_of_can_timeout,
+            state.clone(),
+        ));
+        Ok(SimulatedPdmHandle {
+            address,
+            canbus_id: canbus_id.to_string(),
+            state,
+            task,
+        })
+    }
+}
+
+/// Handle to a running simulated PDM, the PDM stops when it is dropped.
+#[derive(Debug)]
+pub struct SimulatedPdmHandle {
+    /// Address the PDM answers on.
+    address: PdmAddress,
+    /// Interface the PDM is on.
+    canbus_id: String,
+    /// State shared with the task.
+    state: Arc<StdMutex<SimulatedPdmState>>,
+    /// Task answering on the bus.
+    task: JoinHandle<()>,
+}
+
+impl Drop for SimulatedPdmHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl SimulatedPdmHandle {
+    /// Address the PDM answers on.
+    pub fn address(&self) -> PdmAddress {
+        self.address
+    }
+
+    /// Every change to the outputs so far, oldest first.
+    pub fn actuations(&self) -> Vec<ActuationRecord> {
+        self.state().actuations.clone()
+    }
+
+    /// Changes to the outputs that include a channel, oldest first.
+    ///
+    /// * `channel`: channel number.
+    pub fn actuations_of(&self, channel: u8) -> Vec<ActuationRecord> {
+        self.state()
+            .actuations
+            .iter()
+            .filter(|record| record.channels.contains(&channel))
+            .cloned()
+            .collect()
+    }
+
+    /// Current duty cycle of a channel in percent, `None` outside of 1 to 12.
+    ///
+    /// * `channel`: channel number.
+    pub fn output(&self, channel: u8) -> Option<f32> {
+        let index = usize::from(channel.checked_sub(1)?);
+        self.state().outputs.get(index).copied()
+    }
+
+    /// Configuration frames acknowledged so far.
+    pub fn configurations_received(&self) -> usize {
+        self.state().configurations_received
+    }
+
+    /// Fault state the PDM is broadcasting.
+    pub fn status(&self) -> PdmStatus {
+        self.state().status.clone()
+    }
+
+    /// Report a fault state as the PDM would on a fault, e.g. an over
+    /// temperature. A loss of CAN reported is cleared as usual once the
+    /// controller is heard again.
+    ///
+    /// * `status`: fault state to broadcast.
+    pub async fn report_status(&self, status: PdmStatus) -> Result<(), PdmError> {
+        self.state().status = status.clone();
+        let socket = Mutex::new(AsyncCanSocket::open(&self.canbus_id)?);
+        send_frame(&socket, &J1939Frame::status_report(self.address.raw(), &status)).await
+    }
+
+    /// Lock the shared state.
+    fn state(&self) -> std::sync::MutexGuard<'_, SimulatedPdmState> {
+        self.state.lock().expect("Simulated PDM state poisoned")
+    }
+}
+
+/// Answer on the bus until the socket fails or the handle is dropped.
+///
+/// * `socket`: socket on the interface.
+/// * `address`: address to answer on.
+/// * `actuate_command_id`: command id output commands are expected from.
+/// * `reports`: configuration bytes keyed by configuration code and channel.
+/// * `loss_of_can_timeout`: time without traffic before the outputs are cut.
+/// * `state`: state shared with the handle.
+async fn run(
+    socket: AsyncCanSocket,
+    address: u8,
+    actuate_command_id: u8,
+    reports: HashMap<(u8, u8), Vec<u8>>,
+    loss_of_can_timeout: Duration,
+    state: Arc<StdMutex<SimulatedPdmState>>,
+) {
+    let socket = Mutex::new(socket);
+    // The timeout only starts once the controller has been heard, as the
+    // PDM powers up with its outputs off.
+    let mut last_heard: Option<Instant> = None;
+    loop {
+        let deadline = last_heard.map_or_else(
+            || Instant::now() + loss_of_can_timeout,
+            |last_heard| last_heard + loss_of_can_timeout,
+        );
+        let frame = match next_frame(&socket, deadline).await {
+            Ok(Some(frame)) => frame,
+            Ok(None) => {
+                if last_heard.take().is_some() {
+                    let status = loss_of_can(&state);
+                    broadcast_status(&socket, address, &status).await;
+                }
+                continue;
+            }
+            Err(e) => {
+                warn!("Simulated PDM {address} stopped: {e}");
+                break;
+            }
+        };
+        let addressed = matches!(
+            frame.destination,
+            Some(destination) if destination == address || destination == GLOBAL_ADDRESS
+        );
+        // Output commands go out with the command id of the config, which
+        // is the controller address unless the firmware expects another.
+        let from_controller = frame.source == CONTROLLER_ADDRESS || frame.source == actuate_command_id;
+        if !from_controller || !addressed {
+            continue;
+        }
+        last_heard = Some(Instant::now());
+        let recovered = {
+            let mut state = state.lock().expect("Simulated PDM state poisoned");
+            let recovered = state.status.loss_of_can;
+            state.status.loss_of_can = false;
+            recovered.then(|| state.status.clone())
+        };
+        if let Some(status) = recovered {
+            broadcast_status(&socket, address, &status).await;
+        }
+
+        let reply = if frame.pgn == REQUEST_PGN {
+            Some(J1939Frame {
+                priority: 6,
+                pgn: ADDRESS_CLAIMED_PGN,
+                source: address,
+                destination: Some(GLOBAL_ADDRESS),
+                data: vec![0; 8],
+            })
+        } else if let Some((code, channel)) = frame.as_configuration_query() {
+            reports
+                .get(&(code, channel))
+                .map(|payload| J1939Frame::configuration_report(address, code, channel, payload))
+        } else if let Some(command) = frame.as_output_command() {
+            let mut state = state.lock().expect("Simulated PDM state poisoned");
+            for channel in &command.channels {
+                if let Some(output) = state.outputs.get_mut(usize::from(*channel - 1)) {
+                    *output = command.duty_percent;
+                }
+            }
+            state.actuations.push(ActuationRecord {
+                channels: command.channels,
+                duty_percent: command.duty_percent,
+                at: Utc::now(),
+                cause: ActuationCause::Command,
+                command_id: Some(frame.source),
+            });
+            None
+        } else if frame.pgn == PROPRIETARY_A_PGN {
+            // Anything else addressed to the PDM is configuration.
+            state
+                .lock()
+                .expect("Simulated PDM state poisoned")
+                .configurations_received += 1;
+            Some(J1939Frame::acknowledge(address, AckControl::Ack, PROPRIETARY_A_PGN))
+        } else {
+            None
+        };
+        if let Some(reply) = reply {
+            if let Err(e) = send_frame(&socket, &reply).await {
+                warn!("Simulated PDM {address} failed to reply: {e}");
+            }
+        }
+    }
+}
+
+/// Turn every output off as the PDM does on loss of CAN, returning the new
+/// fault state.
+///
+/// * `state`: state shared with the handle.
+fn loss_of_can(state: &StdMutex<SimulatedPdmState>) -> PdmStatus {
+    let mut state = state.lock().expect("Simulated PDM state poisoned");
+    state.outputs = [0.0; CHANNEL_COUNT as usize];
+    state.actuations.push(ActuationRecord {
+        channels: (1..=CHANNEL_COUNT).collect(),
+        duty_percent: 0.0,
+        at: Utc::now(),
+        cause: ActuationCause::LossOfCan,
+        command_id: None,
+    });
+    state.status.loss_of_can = true;
+    state.status.clone()
+}
+
+/// Broadcast the fault state, logging rather than stopping on failure.
+///
+/// * `socket`: socket on the interface.
+/// * `address`: address of the PDM.
+/// * `status`: fault state to report.
+async fn broadcast_status(socket: &Mutex<AsyncCanSocket>, address: u8, status: &PdmStatus) {
+    if let Err(e) = send_frame(socket, &J1939Frame::status_report(address, status)).await {
+        warn!("Simulated PDM {address} failed to send status: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::hardware::pdm::Pdm;
+    use serial_test::serial;
+
+    /// Virtual canbus interface used by the `vcan_test` tests.
+    fn vcan_interface() -> String {
+        std::env::var("ONYX_VCAN").unwrap_or_else(|_| String::from("vcan0"))
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// Output commands are recorded, and going quiet for longer than the
+    /// timeout turns the outputs off and reports loss of CAN to the
+    /// component's PDM.
+    async fn test_simulated_pdm_loss_of_can() {
+        let simulated = SimulatedPdm::new(PdmConfig::new(PdmAddress::Pdm32, 0))
+            .start(&vcan_interface())
+            .expect("Failed to start simulated PDM");
+        let mut pdm = Pdm::new(PdmConfig::new(PdmAddress::Pdm32, 0));
+        let mut status_rx = pdm.watch_status(&vcan_interface()).expect("Failed to watch status");
+        let interface = Arc::new(Mutex::new(
+            AsyncCanSocket::open(&vcan_interface()).expect("Failed to open vcan interface"),
+        ));
+        pdm.initialise(interface.clone()).await.expect("Failed to initialise");
+
+        send_frame(&interface, &J1939Frame::output_command(PdmAddress::Pdm32.raw(), &[4, 5], 100.0))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(simulated.output(4), Some(100.0));
+        let actuations = simulated.actuations();
+        assert_eq!(actuations.len(), 1);
+        assert_eq!(actuations[0].channels, vec![4, 5]);
+        assert_eq!(actuations[0].cause, ActuationCause::Command);
+        assert_eq!(actuations[0].command_id, Some(CONTROLLER_ADDRESS));
+
+        tokio::time::timeout(LOSS_OF_CAN_TIMEOUT * 2, status_rx.changed())
+            .await
+            .expect("Loss of CAN was not reported")
+            .unwrap();
+        assert!(status_rx.borrow().loss_of_can);
+        assert_eq!(simulated.output(4), Some(0.0));
+        assert_eq!(
+            simulated.actuations_of(5).last().map(|record| record.cause),
+            Some(ActuationCause::LossOfCan)
+        );
+
+        // Hearing from the controller again clears the fault.
+        pdm.reinitialise().await.expect("Failed to reinitialise");
+        tokio::time::timeout(Duration::from_millis(200), status_rx.changed())
+            .await
+            .expect("Recovery was not reported")
+            .unwrap();
+        assert!(!pdm.status().loss_of_can);
+        assert!(!simulated.status().loss_of_can);
+    }
+}
@@ -0,0 +1,1355 @@
+use crate::{
+    components::{
+        prelude::*,
+        traits::{ComponentConfig, ComponentController, ComponentError, ComponentHandle},
+    },
+    devices::{
+        hardware::{
+            pdm::{frames::CHANNEL_COUNT, PdmAddress, PdmConfig, PdmStatus},
+            wheel_speed::WheelSpeedSensor,
+        },
+        software::{
+            pdm::{ActuationCause, ActuationRecord, SimulatedPdm, SimulatedPdmHandle},
+            wheel_speed::{SimulatedSpeedHandle, SimulatedSpeedSource},
+        },
+    },
+    messages::control::{
+        light::LightMessage,
+        response::ControlResponse,
+        weed::{full_intensity, WeedMessage, FULL_INTENSITY},
+    },
+    utils::{
+        bus::MessageBus,
+        client::ComponentClient,
+        config::{ConfigFile, Validate, ValidationReport},
+        location::CropBed,
+    },
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Time in milliseconds a channel may turn on either side of when it is
+/// expected to, when not set in the expectation.
+pub const DEFAULT_TOLERANCE_MS: u64 = 50;
+
+/// Components of one crop bed run in a scenario, each started from its
+/// config file as its binary would. The PDMs of the power and lighting are
+/// simulated on their canbus interfaces, which are virtual on a bench.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SimBed {
+    /// Config file of the crop bed power, the crop bed of the bed.
+    pub power: PathBuf,
+    /// Config file of the crop bed lighting, not run when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lighting: Option<PathBuf>,
+    /// Config file of a camera array of simulated cameras, strobing the
+    /// lights on its triggers. Not run when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera_array: Option<PathBuf>,
+}
+
+impl SimBed {
+    /// Bed running only the crop bed power.
+    ///
+    /// * `power`: config file of the crop bed power.
+    pub fn new(power: impl Into<PathBuf>) -> Self {
+        Self {
+            power: power.into(),
+            lighting: None,
+            camera_array: None,
+        }
+    }
+
+    /// Run the crop bed lighting of the bed.
+    ///
+    /// * `lighting`: config file of the crop bed lighting.
+    pub fn with_lighting(mut self, lighting: impl Into<PathBuf>) -> Self {
+        self.lighting = Some(lighting.into());
+        self
+    }
+
+    /// Run a camera array of simulated cameras on the bed.
+    ///
+    /// * `camera_array`: config file of the camera array.
+    pub fn with_camera_array(mut self, camera_array: impl Into<PathBuf>) -> Self {
+        self.camera_array = Some(camera_array.into());
+        self
+    }
+}
+
+/// What happens at a point in a scenario.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SimAction {
+    /// Weed message sent to the crop bed power of a bed, as the AI system
+    /// sends them.
+    Weed {
+        /// Crop bed the message is for.
+        crop_bed: CropBed,
+        /// Zero based channels to open.
+        channels: Vec<u8>,
+        /// Camera the message claims to come from.
+        #[serde(default)]
+        cam_id: u8,
+        /// Time in milliseconds from sending the message to the spray.
+        start_in_ms: i64,
+        /// Time in milliseconds the spray lasts.
+        duration_ms: i64,
+        /// Duty cycle of the channels in percent.
+        #[serde(default = "full_intensity")]
+        intensity: u8,
+        /// Speed in metres per second the times were worked out at, used
+        /// as sent when not set.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        assumed_speed_mps: Option<f64>,
+    },
+    /// Light message sent to the crop bed lighting of a bed.
+    Light {
+        /// Crop bed the message is for.
+        crop_bed: CropBed,
+        /// Light channels the message is for.
+        channels: Vec<u8>,
+        /// Camera the lights are for.
+        #[serde(default)]
+        cam_id: u8,
+        /// Turn the channels on rather than off.
+        on: bool,
+        /// Duty cycle in percent the lights are dimmed to when on.
+        #[serde(default = "full_intensity")]
+        level: u8,
+        /// Pulse the channels on the camera triggers rather than holding
+        /// them on.
+        #[serde(default)]
+        strobe: bool,
+    },
+    /// Ground speed of the machine, followed by the crop bed power of
+    /// every bed.
+    Speed {
+        /// Speed in metres per second.
+        mps: f64,
+    },
+    /// Fault state reported by a simulated PDM. A fault with nothing set
+    /// clears the one reported before.
+    PdmFault {
+        /// Crop bed whose power or lighting drives the PDM.
+        crop_bed: CropBed,
+        /// Address of the PDM.
+        address: PdmAddress,
+        /// The PDM stopped hearing from the controller.
+        #[serde(default)]
+        loss_of_can: bool,
+        /// The PDM as a whole is over temperature.
+        #[serde(default)]
+        module_over_temperature: bool,
+        /// Channels tripped on over current, 1 to 12.
+        #[serde(default)]
+        over_current_channels: Vec<u8>,
+        /// Channels whose driver is over temperature, 1 to 12.
+        #[serde(default)]
+        over_temperature_channels: Vec<u8>,
+    },
+    /// Emergency stop of the crop bed power of a bed.
+    EmergencyStop {
+        /// Crop bed stopped.
+        crop_bed: CropBed,
+    },
+}
+
+impl SimAction {
+    /// Name of the action in the report.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Weed { .. } => "weed",
+            Self::Light { .. } => "light",
+            Self::Speed { .. } => "speed",
+            Self::PdmFault { .. } => "pdm_fault",
+            Self::EmergencyStop { .. } => "emergency_stop",
+        }
+    }
+
+    /// Crop bed the action is for, `None` for the whole machine.
+    fn crop_bed(&self) -> Option<CropBed> {
+        match self {
+            Self::Weed { crop_bed, .. }
+            | Self::Light { crop_bed, .. }
+            | Self::PdmFault { crop_bed, .. }
+            | Self::EmergencyStop { crop_bed } => Some(*crop_bed),
+            Self::Speed { .. } => None,
+        }
+    }
+}
+
+/// Action taken at a time in a scenario.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SimEvent {
+    /// Time in milliseconds from the start of the scenario.
+    pub at_ms: u64,
+    /// What happens.
+    pub action: SimAction,
+}
+
+/// What a channel of a simulated PDM is expected to have done by the end
+/// of a scenario. Every channel is expected to be off at the end.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SimExpectation {
+    /// Crop bed whose power or lighting drives the PDM.
+    pub crop_bed: CropBed,
+    /// Address of the PDM.
+    pub address: PdmAddress,
+    /// Channel on the PDM, 1 to 12.
+    pub channel: u8,
+    /// Times the channel is turned on.
+    pub turned_on: usize,
+    /// Times in milliseconds from the start of the scenario the channel is
+    /// turned on at, not checked when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_at_ms: Vec<u64>,
+    /// Time in milliseconds either side of each time it may turn on at,
+    /// see [`DEFAULT_TOLERANCE_MS`] when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tolerance_ms: Option<u64>,
+}
+
+impl SimExpectation {
+    /// Expect a channel to be turned on a number of times.
+    ///
+    /// * `crop_bed`: crop bed whose power or lighting drives the PDM.
+    /// * `address`: address of the PDM.
+    /// * `channel`: channel on the PDM, 1 to 12.
+    /// * `turned_on`: times the channel is turned on.
+    pub fn new(crop_bed: impl Into<CropBed>, address: PdmAddress, channel: u8, turned_on: usize) -> Self {
+        Self {
+            crop_bed: crop_bed.into(),
+            address,
+            channel,
+            turned_on,
+            on_at_ms: Vec::new(),
+            tolerance_ms: None,
+        }
+    }
+
+    /// Expect the channel to turn on at times, within the tolerance.
+    ///
+    /// * `on_at_ms`: times in milliseconds from the start of the scenario.
+    pub fn with_on_at(mut self, on_at_ms: impl Into<Vec<u64>>) -> Self {
+        self.on_at_ms = on_at_ms.into();
+        self
+    }
+
+    /// Set the time either side of each time the channel may turn on at.
+    ///
+    /// * `tolerance_ms`: time in milliseconds.
+    pub fn with_tolerance(mut self, tolerance_ms: u64) -> Self {
+        self.tolerance_ms = Some(tolerance_ms);
+        self
+    }
+
+    /// Problems with what the channel did, none when it met the
+    /// expectation.
+    ///
+    /// * `turned_on_at_ms`: times the channel was turned on.
+    /// * `left_on`: whether the channel was on at the end.
+    fn problems(&self, turned_on_at_ms: &[i64], left_on: bool) -> Vec<String> {
+        let mut problems = Vec::new();
+        if turned_on_at_ms.len() != self.turned_on {
+            problems.push(format!(
+                "turned on {} times, expected {}",
+                turned_on_at_ms.len(),
+                self.turned_on
+            ));
+        }
+        let tolerance_ms = self.tolerance_ms.unwrap_or(DEFAULT_TOLERANCE_MS);
+        for expected in &self.on_at_ms {
+            let expected_ms = i64::try_from(*expected).unwrap_or(i64::MAX);
+            if !turned_on_at_ms
+                .iter()
+                .any(|on| on.abs_diff(expected_ms) <= tolerance_ms)
+            {
+                problems.push(format!("not turned on within {tolerance_ms}ms of {expected}ms"));
+            }
+        }
+        if left_on {
+            problems.push(String::from("left on at the end of the scenario"));
+        }
+        problems
+    }
+}
+
+/// Scenario run against the real components in one process, with their
+/// PDMs, cameras and wheel speed simulated. Events are applied at their
+/// times, then the components are shut down and what the simulated PDMs
+/// did is checked against the expectations.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SimScenario {
+    /// Crop beds run, each with a crop bed of its own.
+    pub beds: Vec<SimBed>,
+    /// Time in milliseconds from the start the components are run for.
+    pub duration_ms: u64,
+    /// Actions taken, in any order.
+    #[serde(default)]
+    pub events: Vec<SimEvent>,
+    /// What the channels are expected to have done.
+    #[serde(default)]
+    pub expectations: Vec<SimExpectation>,
+}
+
+/// Crop bed of a bed with the PDMs driven by its power and lighting.
+struct BedPdms {
+    /// Crop bed of the power.
+    crop_bed: CropBed,
+    /// Canbus interface and address of each PDM, power first.
+    pdms: Vec<(String, PdmAddress)>,
+    /// Whether the lighting is run.
+    lit: bool,
+}
+
+/// Components of a bed once started, with the clients sending them
+/// messages.
+struct RunningBed {
+    /// Crop bed of the bed.
+    crop_bed: CropBed,
+    /// Running crop bed power.
+    power: CropBedPowerHandle,
+    /// Client of the weed message socket.
+    power_client: ComponentClient,
+    /// Running crop bed lighting and the client of its socket.
+    lighting: Option<(CropBedLightingHandle, ComponentClient)>,
+    /// Running camera array.
+    camera_array: Option<CameraArrayHandle>,
+}
+
+impl RunningBed {
+    /// Stop the cameras, then the lights, then the power, leaving every
+    /// channel off.
+    async fn shutdown(self) {
+        if let Some(camera_array) = self.camera_array {
+            camera_array.shutdown().await;
+        }
+        if let Some((lighting, _)) = self.lighting {
+            lighting.shutdown().await;
+        }
+        let status = self.power.shutdown().await;
+        info!("Crop bed power of {} shut down, {status}", self.crop_bed);
+    }
+}
+
+/// Time in milliseconds from the start of the scenario to an instant, before
+/// the start when negative.
+///
+/// * `started_at`: start of the scenario.
+/// * `at`: instant.
+fn since_start_ms(started_at: DateTime<Utc>, at: DateTime<Utc>) -> i64 {
+    (at - started_at).num_milliseconds()
+}
+
+/// Times a channel was turned on from off, in milliseconds from the start.
+///
+/// * `actuations`: changes to the outputs of the PDM, oldest first.
+/// * `channel`: channel on the PDM.
+/// * `started_at`: start of the scenario.
+fn turned_on_at(actuations: &[ActuationRecord], channel: u8, started_at: DateTime<Utc>) -> Vec<i64> {
+    let mut on = false;
+    let mut turned_on = Vec::new();
+    for record in actuations.iter().filter(|record| record.channels.contains(&channel)) {
+        let now_on = record.duty_percent > 0.0;
+        if now_on && !on {
+            turned_on.push(since_start_ms(started_at, record.at));
+        }
+        on = now_on;
+    }
+    turned_on
+}
+
+/// Fault state of a simulated PDM.
+///
+/// * `loss_of_can`: the PDM stopped hearing from the controller.
+/// * `module_over_temperature`: the PDM as a whole is over temperature.
+/// * `over_current_channels`: channels tripped on over current.
+/// * `over_temperature_channels`: channels whose driver is over temperature.
+fn fault_status(
+    loss_of_can: bool,
+    module_over_temperature: bool,
+    over_current_channels: &[u8],
+    over_temperature_channels: &[u8],
+) -> PdmStatus {
+    let mut status = PdmStatus {
+        loss_of_can,
+        module_over_temperature,
+        ..PdmStatus::default()
+    };
+    for channel in over_current_channels {
+        if let Some(faults) = channel
+            .checked_sub(1)
+            .and_then(|index| status.channels.get_mut(usize::from(index)))
+        {
+            faults.over_current_trip = true;
+        }
+    }
+    for channel in over_temperature_channels {
+        if let Some(faults) = channel
+            .checked_sub(1)
+            .and_then(|index| status.channels.get_mut(usize::from(index)))
+        {
+            faults.over_temperature = true;
+        }
+    }
+    status
+}
+
+/// Whether a channel is one of the 12 on a PDM.
+///
+/// * `channel`: channel number.
+fn on_pdm(channel: u8) -> bool {
+    (1..=CHANNEL_COUNT).contains(&channel)
+}
+
+/// Path of a file a scenario refers to, relative paths resolved against
+/// the directory of the scenario.
+///
+/// * `dir`: directory of the scenario file.
+/// * `path`: path in the scenario.
+fn resolve(dir: &Path, path: &Path) -> PathBuf {
+    if path.is_relative() {
+        dir.join(path)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+impl SimScenario {
+    /// Scenario without beds, events or expectations.
+    ///
+    /// * `duration_ms`: time in milliseconds the components are run for.
+    pub fn new(duration_ms: u64) -> Self {
+        Self {
+            beds: Vec::new(),
+            duration_ms,
+            events: Vec::new(),
+            expectations: Vec::new(),
+        }
+    }
+
+    /// Run the components of a crop bed.
+    ///
+    /// * `bed`: config files of the components.
+    pub fn add_bed(mut self, bed: SimBed) -> Self {
+        self.beds.push(bed);
+        self
+    }
+
+    /// Take an action at a time.
+    ///
+    /// * `at_ms`: time in milliseconds from the start.
+    /// * `action`: what happens.
+    pub fn add_event(mut self, at_ms: u64, action: SimAction) -> Self {
+        self.events.push(SimEvent { at_ms, action });
+        self
+    }
+
+    /// Check what a channel has done by the end.
+    ///
+    /// * `expectation`: what the channel is expected to have done.
+    pub fn expect(mut self, expectation: SimExpectation) -> Self {
+        self.expectations.push(expectation);
+        self
+    }
+
+    /// Crop bed and PDMs of each bed whose configs load, for the checks
+    /// across the beds, adding the problems of the files to a report.
+    ///
+    /// * `report`: report of the scenario.
+    fn bed_pdms(&self, report: &mut ValidationReport) -> Vec<BedPdms> {
+        let mut beds = Vec::new();
+        for (index, bed) in self.beds.iter().enumerate() {
+            let field = format!("beds.{index}");
+            let Some(power) = report.validate_file::<CropBedPowerConfig>(format!("{field}.power"), &bed.power) else {
+                continue;
+            };
+            let mut pdms: Vec<(String, PdmAddress)> = power
+                .pdm_configs()
+                .unwrap_or_default()
+                .into_values()
+                .map(|config| (power.canbus_id().to_string(), config.address))
+                .collect();
+            if let Some(lighting) = &bed.lighting {
+                if let Some(lighting) =
+                    report.validate_file::<CropBedLightingConfig>(format!("{field}.lighting"), lighting)
+                {
+                    pdms.extend(
+                        lighting
+                            .pdm_configs()
+                            .unwrap_or_default()
+                            .into_values()
+                            .map(|config| (lighting.canbus_id().to_string(), config.address)),
+                    );
+                }
+            }
+            if let Some(camera_array) = &bed.camera_array {
+                report.validate_file::<CameraArrayConfig>(format!("{field}.camera_array"), camera_array);
+            }
+            pdms.sort_unstable_by_key(|(_, address)| *address);
+            if let Some(pair) = pdms.windows(2).find(|pair| pair[0].1 == pair[1].1) {
+                report.push(
+                    &field,
+                    format!("PDM {} is driven by the power and the lighting", pair[0].1),
+                );
+            }
+            beds.push(BedPdms {
+                crop_bed: power.crop_bed(),
+                pdms,
+                lit: bed.lighting.is_some(),
+            });
+        }
+        beds
+    }
+
+    /// Canbus interface and address of every PDM simulated for the beds,
+    /// keyed by crop bed and address.
+    ///
+    /// * `beds`: configs of the beds.
+    fn simulated_pdms(
+        beds: &[(CropBedPowerConfig, Option<CropBedLightingConfig>)],
+    ) -> Result<BTreeMap<(CropBed, PdmAddress), (String, PdmConfig)>, ComponentError> {
+        let mut pdms = BTreeMap::new();
+        for (power, lighting) in beds {
+            for config in power.pdm_configs()?.into_values() {
+                pdms.insert(
+                    (power.crop_bed(), config.address),
+                    (power.canbus_id().to_string(), config),
+                );
+            }
+            if let Some(lighting) = lighting {
+                for config in lighting.pdm_configs()?.into_values() {
+                    pdms.insert(
+                        (lighting.crop_bed(), config.address),
+                        (lighting.canbus_id().to_string(), config),
+                    );
+                }
+            }
+        }
+        Ok(pdms)
+    }
+
+    /// Start the components of a bed on the bus, the power following the
+    /// simulated wheel speed. Components already started are shut down
+    /// when a later one fails.
+    ///
+    /// * `bed`: config files of the components.
+    /// * `power_config`: config of the crop bed power.
+    /// * `lighting_config`: config of the crop bed lighting.
+    /// * `bus`: bus shared by every component of the scenario.
+    /// * `speed`: source of the ground speed followed.
+    async fn start_bed(
+        bed: &SimBed,
+        power_config: &CropBedPowerConfig,
+        lighting_config: Option<&CropBedLightingConfig>,
+        bus: &MessageBus,
+        speed: SimulatedSpeedSource,
+    ) -> Result<RunningBed, ComponentError> {
+        let crop_bed = power_config.crop_bed();
+        let power = CropBedPowerController::from_file(&bed.power)?
+            .with_bus(bus.clone())
+            .with_speed_sensor(WheelSpeedSensor::start(Box::new(speed)));
+        let power = CropBedPowerController::start(power).await?;
+        let power_client = match power_config.connect_client().await {
+            Ok(client) => client,
+            Err(e) => {
+                power.shutdown().await;
+                return Err(ComponentError::io(format!(
+                    "Failed to connect to the crop bed power of {crop_bed}"
+                ))(e));
+            }
+        };
+        let mut running = RunningBed {
+            crop_bed,
+            power,
+            power_client,
+            lighting: None,
+            camera_array: None,
+        };
+        if let (Some(path), Some(lighting_config)) = (&bed.lighting, lighting_config) {
+            let started = match CropBedLightingController::from_file(path) {
+                Ok(lighting) => CropBedLightingController::start(lighting.with_bus(bus.clone())).await,
+                Err(e) => Err(e),
+            };
+            let lighting = match started {
+                Ok(lighting) => lighting,
+                Err(e) => {
+                    running.shutdown().await;
+                    return Err(e);
+                }
+            };
+            match lighting_config.connect_client().await {
+                Ok(client) => running.lighting = Some((lighting, client)),
+                Err(e) => {
+                    lighting.shutdown().await;
+                    running.shutdown().await;
+                    return Err(ComponentError::io(format!(
+                        "Failed to connect to the crop bed lighting of {crop_bed}"
+                    ))(e));
+                }
+            }
+        }
+        if let Some(path) = &bed.camera_array {
+            let started = match CameraArrayController::from_file(path) {
+                Ok(camera_array) => CameraArrayController::start(camera_array.with_bus(bus.clone())),
+                Err(e) => Err(e),
+            };
+            match started {
+                Ok(camera_array) => running.camera_array = Some(camera_array),
+                Err(e) => {
+                    running.shutdown().await;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(running)
+    }
+
+    /// Apply an action to the running beds, returning whether it was acted
+    /// on and why not.
+    ///
+    /// * `index`: position of the event in the scenario, naming its message.
+    /// * `action`: what happens.
+    /// * `beds`: running beds.
+    /// * `speeds`: handles setting the speed each power follows.
+    /// * `pdms`: simulated PDMs keyed by crop bed and address.
+    async fn apply(
+        index: usize,
+        action: &SimAction,
+        beds: &mut [RunningBed],
+        speeds: &[SimulatedSpeedHandle],
+        pdms: &BTreeMap<(CropBed, PdmAddress), SimulatedPdmHandle>,
+    ) -> Result<(), String> {
+        let bed = action
+            .crop_bed()
+            .and_then(|crop_bed| beds.iter_mut().find(|bed| bed.crop_bed == crop_bed));
+        let response: io::Result<ControlResponse> = match (action, bed) {
+            (
+                SimAction::Weed {
+                    crop_bed,
+                    channels,
+                    cam_id,
+                    start_in_ms,
+                    duration_ms,
+                    intensity,
+                    assumed_speed_mps,
+                },
+                Some(bed),
+            ) => {
+                let start_spray_time = Utc::now() + chrono::Duration::milliseconds(*start_in_ms);
+                let mut message = WeedMessage::new(
+                    *crop_bed,
+                    *cam_id,
+                    start_spray_time,
+                    start_spray_time + chrono::Duration::milliseconds(*duration_ms),
+                )
+                .channels(channels.clone())
+                .intensity(*intensity)
+                .message_id(format!("sim-{index}"));
+                if let Some(assumed_speed_mps) = assumed_speed_mps {
+                    message = message.assumed_speed(*assumed_speed_mps);
+                }
+                bed.power_client.send(&message).await
+            }
+            (
+                SimAction::Light {
+                    crop_bed,
+                    channels,
+                    cam_id,
+                    on,
+                    level,
+                    strobe,
+                },
+                Some(RunningBed {
+                    lighting: Some((_, client)),
+                    ..
+                }),
+            ) => {
+                let mut message = LightMessage::new(*crop_bed, *cam_id)
+                    .channels(channels.clone())
+                    .level(*level);
+                message = if *on { message.on() } else { message.off() };
+                if *strobe {
+                    message = message.strobe();
+                }
+                client.send(&message).await
+            }
+            (SimAction::Speed { mps }, _) => {
+                for speed in speeds {
+                    speed.set_speed(*mps);
+                }
+                return Ok(());
+            }
+            (
+                SimAction::PdmFault {
+                    crop_bed,
+                    address,
+                    loss_of_can,
+                    module_over_temperature,
+                    over_current_channels,
+                    over_temperature_channels,
+                },
+                _,
+            ) => {
+                let Some(pdm) = pdms.get(&(*crop_bed, *address)) else {
+                    return Err(format!("No PDM {address} on {crop_bed}"));
+                };
+                let status = fault_status(
+                    *loss_of_can,
+                    *module_over_temperature,
+                    over_current_channels,
+                    over_temperature_channels,
+                );
+                return pdm.report_status(status).await.map_err(|e| e.to_string());
+            }
+            (SimAction::EmergencyStop { .. }, Some(bed)) => {
+                bed.power.component().lock().await.emergency_stop().await;
+                return Ok(());
+            }
+            (action, _) => return Err(format!("The {} is for a component that is not run", action.name())),
+        };
+        match response {
+            Ok(response) if response.is_accepted() => Ok(()),
+            Ok(response) => Err(response.reason().unwrap_or("refused").to_string()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Run the scenario: simulate every PDM of the beds, start the
+    /// components of each bed on one bus, apply the events at their times,
+    /// then shut the components down and check what the simulated PDMs
+    /// did against the expectations. The scenario is expected to have been
+    /// validated, a component that does not start fails the run.
+    pub async fn run(&self) -> Result<SimReport, ComponentError> {
+        let configs = self
+            .beds
+            .iter()
+            .map(|bed| {
+                let power = CropBedPowerConfig::load_validated(&bed.power)?;
+                let lighting = bed
+                    .lighting
+                    .as_ref()
+                    .map(CropBedLightingConfig::load_validated)
+                    .transpose()?;
+                Ok((power, lighting))
+            })
+            .collect::<Result<Vec<_>, ValidationReport>>()?;
+        let mut pdms = BTreeMap::new();
+        for (key, (canbus_id, config)) in Self::simulated_pdms(&configs)? {
+            let simulated = SimulatedPdm::new(config)
+                .start(&canbus_id)
+                .map_err(|source| ComponentError::Canbus { canbus_id, source })?;
+            pdms.insert(key, simulated);
+        }
+
+        let bus = MessageBus::new();
+        let mut beds = Vec::new();
+        let mut speeds = Vec::new();
+        for (bed, (power, lighting)) in self.beds.iter().zip(&configs) {
+            let (source, speed) = SimulatedSpeedSource::new();
+            match Self::start_bed(bed, power, lighting.as_ref(), &bus, source).await {
+                Ok(running) => beds.push(running),
+                Err(e) => {
+                    for running in beds {
+                        running.shutdown().await;
+                    }
+                    return Err(e);
+                }
+            }
+            speeds.push(speed);
+        }
+
+        let mut events: Vec<(usize, &SimEvent)> = self.events.iter().enumerate().collect();
+        events.sort_by_key(|(_, event)| event.at_ms);
+        let start = Instant::now();
+        let started_at = Utc::now();
+        let mut outcomes = Vec::new();
+        for (index, event) in events {
+            tokio::time::sleep_until(start + Duration::from_millis(event.at_ms)).await;
+            let sent_at_ms = since_start_ms(started_at, Utc::now());
+            let result = Self::apply(index, &event.action, &mut beds, &speeds, &pdms).await;
+            if let Err(reason) = &result {
+                warn!(
+                    "The {} at {}ms was not acted on: {reason}",
+                    event.action.name(),
+                    event.at_ms
+                );
+            }
+            outcomes.push(SimEventOutcome {
+                index,
+                action: event.action.name(),
+                at_ms: sent_at_ms,
+                acted_on: result.is_ok(),
+                reason: result.err(),
+            });
+        }
+        tokio::time::sleep_until(start + Duration::from_millis(self.duration_ms)).await;
+        for running in beds {
+            running.shutdown().await;
+        }
+
+        let mut actuations = Vec::new();
+        for ((crop_bed, address), pdm) in &pdms {
+            actuations.extend(pdm.actuations().into_iter().map(|record| SimActuation {
+                crop_bed: *crop_bed,
+                address: *address,
+                at_ms: since_start_ms(started_at, record.at),
+                channels: record.channels,
+                duty_percent: record.duty_percent,
+                cause: record.cause,
+            }));
+        }
+        actuations.sort_by_key(|actuation| actuation.at_ms);
+        let expectations = self
+            .expectations
+            .iter()
+            .map(|expectation| {
+                let (turned_on_at_ms, left_on) = match pdms.get(&(expectation.crop_bed, expectation.address)) {
+                    Some(pdm) => (
+                        turned_on_at(&pdm.actuations(), expectation.channel, started_at),
+                        pdm.output(expectation.channel).is_some_and(|duty| duty > 0.0),
+                    ),
+                    None => (Vec::new(), false),
+                };
+                SimOutcome {
+                    problems: expectation.problems(&turned_on_at_ms, left_on),
+                    expectation: expectation.clone(),
+                    turned_on_at_ms,
+                }
+            })
+            .collect();
+        Ok(SimReport {
+            started_at,
+            duration_ms: self.duration_ms,
+            events: outcomes,
+            actuations,
+            expectations,
+        })
+    }
+}
+
+impl Validate for SimScenario {
+    fn validate(&self, report: &mut ValidationReport) {
+        if self.beds.is_empty() {
+            report.push("beds", "no beds are run");
+        }
+        let beds = self.bed_pdms(report);
+        for (index, bed) in beds.iter().enumerate() {
+            if let Some(other) = beds[..index].iter().position(|other| other.crop_bed == bed.crop_bed) {
+                report.push(
+                    format!("beds.{index}.power"),
+                    format!("crop bed {} is already run by bed {other}", bed.crop_bed),
+                );
+            }
+            for (other_index, other) in beds[..index].iter().enumerate() {
+                if let Some((canbus_id, address)) = bed.pdms.iter().find(|pdm| other.pdms.contains(pdm)) {
+                    report.push(
+                        format!("beds.{index}"),
+                        format!("PDM {address} on {canbus_id} is already simulated for bed {other_index}"),
+                    );
+                }
+            }
+        }
+        let find_bed = |crop_bed: CropBed| beds.iter().find(|bed| bed.crop_bed == crop_bed);
+        let has_pdm =
+            |bed: &BedPdms, address: PdmAddress| bed.pdms.iter().any(|(_, pdm_address)| *pdm_address == address);
+
+        for (index, event) in self.events.iter().enumerate() {
+            let field = format!("events.{index}");
+            if event.at_ms > self.duration_ms {
+                report.push(
+                    format!("{field}.at_ms"),
+                    format!(
+                        "{}ms is after the end of the scenario at {}ms",
+                        event.at_ms, self.duration_ms
+                    ),
+                );
+            }
+            // Beds whose configs did not load are reported on their own.
+            let bed = event.action.crop_bed().map(|crop_bed| (crop_bed, find_bed(crop_bed)));
+            if beds.len() == self.beds.len() {
+                if let Some((crop_bed, None)) = bed {
+                    report.push(format!("{field}.action"), format!("no bed runs crop bed {crop_bed}"));
+                }
+            }
+            let field = format!("{field}.action.{}", event.action.name());
+            match &event.action {
+                SimAction::Weed { intensity, .. } if *intensity > FULL_INTENSITY => {
+                    report.push(
+                        format!("{field}.intensity"),
+                        format!("{intensity} is outside 0 to {FULL_INTENSITY}"),
+                    );
+                }
+                SimAction::Light { level, .. } if *level > FULL_INTENSITY => {
+                    report.push(
+                        format!("{field}.level"),
+                        format!("{level} is outside 0 to {FULL_INTENSITY}"),
+                    );
+                }
+                SimAction::Light { crop_bed, .. } if find_bed(*crop_bed).is_some_and(|bed| !bed.lit) => {
+                    report.push(field, format!("the lighting of crop bed {crop_bed} is not run"));
+                }
+                SimAction::Speed { mps } if !mps.is_finite() || *mps < 0.0 => {
+                    report.push(format!("{field}.mps"), format!("{mps} is not a ground speed"));
+                }
+                SimAction::PdmFault {
+                    address,
+                    over_current_channels,
+                    over_temperature_channels,
+                    ..
+                } => {
+                    if let Some((_, Some(bed))) = bed {
+                        if !has_pdm(bed, *address) {
+                            report.push(
+                                format!("{field}.address"),
+                                format!("PDM {address} is not driven on crop bed {}", bed.crop_bed),
+                            );
+                        }
+                    }
+                    for channel in over_current_channels.iter().chain(over_temperature_channels) {
+                        if !on_pdm(*channel) {
+                            report.push(&field, format!("channel {channel} is outside 1 to {CHANNEL_COUNT}"));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (index, expectation) in self.expectations.iter().enumerate() {
+            let field = format!("expectations.{index}");
+            match find_bed(expectation.crop_bed) {
+                Some(bed) if !has_pdm(bed, expectation.address) => report.push(
+                    format!("{field}.address"),
+                    format!(
+                        "PDM {} is not driven on crop bed {}",
+                        expectation.address, expectation.crop_bed
+                    ),
+                ),
+                None if beds.len() == self.beds.len() => report.push(
+                    format!("{field}.crop_bed"),
+                    format!("no bed runs crop bed {}", expectation.crop_bed),
+                ),
+                _ => {}
+            }
+            if !on_pdm(expectation.channel) {
+                report.push(
+                    format!("{field}.channel"),
+                    format!("{} is outside 1 to {CHANNEL_COUNT}", expectation.channel),
+                );
+            }
+            if expectation.on_at_ms.len() > expectation.turned_on {
+                report.push(
+                    format!("{field}.on_at_ms"),
+                    format!(
+                        "{} times given for a channel turned on {} times",
+                        expectation.on_at_ms.len(),
+                        expectation.turned_on
+                    ),
+                );
+            }
+        }
+    }
+
+    fn loaded_from(mut self, path: &Path) -> Self {
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for bed in &mut self.beds {
+            bed.power = resolve(dir, &bed.power);
+            bed.lighting = bed.lighting.as_deref().map(|lighting| resolve(dir, lighting));
+            bed.camera_array = bed
+                .camera_array
+                .as_deref()
+                .map(|camera_array| resolve(dir, camera_array));
+        }
+        self
+    }
+}
+
+/// Event of a scenario as it was applied.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SimEventOutcome {
+    /// Position of the event in the scenario.
+    pub index: usize,
+    /// Name of the action.
+    pub action: &'static str,
+    /// Time in milliseconds from the start it was applied at.
+    pub at_ms: i64,
+    /// Whether the component, or the simulated PDM, acted on it.
+    pub acted_on: bool,
+    /// Why it was not acted on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Change to the outputs of a simulated PDM during a scenario.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SimActuation {
+    /// Crop bed whose power or lighting drives the PDM.
+    pub crop_bed: CropBed,
+    /// Address of the PDM.
+    pub address: PdmAddress,
+    /// Time in milliseconds from the start, negative while the components
+    /// were starting.
+    pub at_ms: i64,
+    /// Channels set, in ascending order.
+    pub channels: Vec<u8>,
+    /// Duty cycle the channels were set to in percent.
+    pub duty_percent: f32,
+    /// Why the outputs changed.
+    pub cause: ActuationCause,
+}
+
+/// Expectation of a scenario and what the channel did.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SimOutcome {
+    /// What the channel was expected to do.
+    pub expectation: SimExpectation,
+    /// Times in milliseconds from the start the channel was turned on.
+    pub turned_on_at_ms: Vec<i64>,
+    /// How the channel fell short, empty when it met the expectation.
+    pub problems: Vec<String>,
+}
+
+impl SimOutcome {
+    /// Whether the channel met the expectation.
+    pub fn passed(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Report of a scenario run: the events as applied, every actuation of
+/// the simulated PDMs and each expectation with what the channel did.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SimReport {
+    /// When the scenario started.
+    pub started_at: DateTime<Utc>,
+    /// Time in milliseconds the components were run for.
+    pub duration_ms: u64,
+    /// Events in the order they were applied.
+    pub events: Vec<SimEventOutcome>,
+    /// Changes to the outputs of every simulated PDM, oldest first.
+    pub actuations: Vec<SimActuation>,
+    /// Expectations in the order of the scenario.
+    pub expectations: Vec<SimOutcome>,
+}
+
+impl SimReport {
+    /// Whether every expectation was met.
+    pub fn passed(&self) -> bool {
+        self.expectations.iter().all(SimOutcome::passed)
+    }
+}
+
+impl Display for SimReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let failed = self.expectations.iter().filter(|outcome| !outcome.passed()).count();
+        write!(
+            f,
+            "{} of {} expectations met over {}ms, {} actuations, {} events not acted on",
+            self.expectations.len() - failed,
+            self.expectations.len(),
+            self.duration_ms,
+            self.actuations.len(),
+            self.events.iter().filter(|event| !event.acted_on).count()
+        )?;
+        for outcome in self.expectations.iter().filter(|outcome| !outcome.passed()) {
+            let expectation = &outcome.expectation;
+            write!(
+                f,
+                "\n  {} PDM {} channel {}: {}",
+                expectation.crop_bed,
+                expectation.address,
+                expectation.channel,
+                outcome.problems.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use uuid::Uuid;
+
+    /// Virtual canbus interface used by the `vcan_test` tests.
+    fn vcan_interface() -> String {
+        std::env::var("ONYX_VCAN").unwrap_or_else(|_| String::from("vcan0"))
+    }
+
+    /// Write the config of a crop bed power driving one PDM to a directory,
+    /// returning the power config file.
+    ///
+    /// * `dir`: directory written to.
+    /// * `crop_bed`: crop bed of the power.
+    /// * `address`: address of the PDM.
+    /// * `canbus_id`: canbus interface of the PDM.
+    /// * `port`: port the power listens on.
+    fn write_power(dir: &Path, crop_bed: CropBed, address: PdmAddress, canbus_id: &str, port: i32) -> PathBuf {
+        let pdm_config_file = dir.join(format!("pdm_{crop_bed}.yaml"));
+        let pdm_config = PdmConfig::new(address, 0).with_response_timeout(Duration::from_millis(200));
+        serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+        let config = CropBedPowerConfig::new(crop_bed, canbus_id.to_string(), port, None)
+            .add_pdm_config_file(pdm_config_file, 0);
+        let config_file = dir.join(format!("power_{crop_bed}.yaml"));
+        serde_yaml::to_writer(std::fs::File::create(&config_file).unwrap(), &config).unwrap();
+        config_file
+    }
+
+    /// Write a scenario to a directory, returning the scenario file.
+    ///
+    /// * `dir`: directory written to.
+    /// * `scenario`: scenario written.
+    fn write_scenario(dir: &Path, scenario: &SimScenario) -> PathBuf {
+        let scenario_file = dir.join("scenario.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&scenario_file).unwrap(), scenario).unwrap();
+        scenario_file
+    }
+
+    /// New empty directory in the temporary directory.
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("onyx-scenario-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    /// A scenario is read with the actions in their map form and the
+    /// defaults filled in, the files of the beds relative to the scenario.
+    fn test_scenario_read_from_yaml() {
+        let yaml = "
+beds:
+  - power: left/power.yaml
+    lighting: /etc/onyx/lighting.yaml
+duration_ms: 1000
+events:
+  - at_ms: 100
+    action:
+      weed:
+        crop_bed: left_boom
+        channels: [0, 1]
+        start_in_ms: 300
+        duration_ms: 200
+  - at_ms: 200
+    action:
+      speed:
+        mps: 1.5
+  - at_ms: 300
+    action:
+      pdm_fault:
+        crop_bed: left_boom
+        address: pdm_30
+        over_current_channels: [2]
+expectations:
+  - crop_bed: left_boom
+    address: 30
+    channel: 1
+    turned_on: 1
+";
+        let scenario = serde_yaml::from_str::<SimScenario>(yaml)
+            .unwrap()
+            .loaded_from(Path::new("/bench/scenario.yaml"));
+        assert_eq!(scenario.beds[0].power, PathBuf::from("/bench/left/power.yaml"));
+        assert_eq!(
+            scenario.beds[0].lighting,
+            Some(PathBuf::from("/etc/onyx/lighting.yaml"))
+        );
+        assert_eq!(scenario.beds[0].camera_array, None);
+        assert_eq!(
+            scenario.events[0].action,
+            SimAction::Weed {
+                crop_bed: CropBed::LeftBoom,
+                channels: vec![0, 1],
+                cam_id: 0,
+                start_in_ms: 300,
+                duration_ms: 200,
+                intensity: FULL_INTENSITY,
+                assumed_speed_mps: None,
+            }
+        );
+        assert_eq!(scenario.events[1].action, SimAction::Speed { mps: 1.5 });
+        let SimAction::PdmFault {
+            address,
+            loss_of_can,
+            over_current_channels,
+            ..
+        } = &scenario.events[2].action
+        else {
+            panic!("{:?}", scenario.events[2]);
+        };
+        assert_eq!(
+            (*address, *loss_of_can, over_current_channels.as_slice()),
+            (PdmAddress::Pdm30, false, &[2][..])
+        );
+        assert_eq!(
+            scenario.expectations,
+            vec![SimExpectation::new(CropBed::LeftBoom, PdmAddress::Pdm30, 1, 1)]
+        );
+    }
+
+    #[test]
+    /// A scenario driving a PDM from two beds, or acting on what is not
+    /// run, is refused naming each field.
+    fn test_invalid_scenario_refused() {
+        let dir = temp_dir();
+        let left = write_power(&dir, CropBed::LeftBoom, PdmAddress::Pdm30, "vcan0", 17704);
+        let right = write_power(&dir, CropBed::RightBoom, PdmAddress::Pdm30, "vcan0", 17705);
+        let scenario = SimScenario::new(500)
+            .add_bed(SimBed::new("power_left_boom.yaml"))
+            .add_bed(SimBed::new(right))
+            .add_bed(SimBed::new(left))
+            .add_event(600, SimAction::Speed { mps: 1.0 })
+            .add_event(
+                100,
+                SimAction::Light {
+                    crop_bed: CropBed::LeftBoom,
+                    channels: vec![0],
+                    cam_id: 0,
+                    on: true,
+                    level: FULL_INTENSITY,
+                    strobe: false,
+                },
+            )
+            .add_event(
+                100,
+                SimAction::EmergencyStop {
+                    crop_bed: CropBed::Centre,
+                },
+            )
+            .expect(SimExpectation::new(CropBed::LeftBoom, PdmAddress::Pdm31, 13, 1).with_on_at([100, 200]));
+        let report = SimScenario::load_validated(write_scenario(&dir, &scenario)).unwrap_err();
+        assert_eq!(
+            report.fields(),
+            vec![
+                "beds.1",
+                "beds.2.power",
+                "beds.2",
+                "beds.2",
+                "events.0.at_ms",
+                "events.1.action.light",
+                "events.2.action",
+                "expectations.0.address",
+                "expectations.0.channel",
+                "expectations.0.on_at_ms",
+            ],
+            "{report}"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// A channel counts as turned on each time it goes from off to on, and
+    /// falls short when turned on too often, away from its times or left
+    /// on.
+    fn test_expectation_problems() {
+        let started_at = Utc::now();
+        let record = |channels: Vec<u8>, duty_percent: f32, at_ms: i64| ActuationRecord {
+            channels,
+            duty_percent,
+            at: started_at + chrono::Duration::milliseconds(at_ms),
+            cause: ActuationCause::Command,
+            command_id: None,
+        };
+        let actuations = vec![
+            record(vec![1, 2], 0.0, -20),
+            record(vec![1], 100.0, 100),
+            record(vec![1], 40.0, 150),
+            record(vec![1], 0.0, 300),
+            record(vec![2], 100.0, 320),
+            record(vec![1], 100.0, 500),
+        ];
+        let turned_on = turned_on_at(&actuations, 1, started_at);
+        assert_eq!(turned_on, vec![100, 500]);
+
+        let expectation = SimExpectation::new(CropBed::Centre, PdmAddress::Pdm30, 1, 2).with_on_at([120, 500]);
+        assert!(expectation.problems(&turned_on, false).is_empty());
+        assert_eq!(
+            expectation.with_tolerance(10).problems(&turned_on[..1], true),
+            vec![
+                String::from("turned on 1 times, expected 2"),
+                String::from("not turned on within 10ms of 120ms"),
+                String::from("not turned on within 10ms of 500ms"),
+                String::from("left on at the end of the scenario"),
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[serial]
+    #[cfg_attr(not(feature = "vcan_test"), ignore)]
+    /// Two beds sent weed messages at once each turn their own simulated
+    /// PDM on at the time of the message and off again, while following
+    /// the simulated speed, and a fault reported by a PDM is acted on.
+    async fn test_scenario_run_on_two_beds() {
+        let dir = temp_dir();
+        let left = write_power(&dir, CropBed::LeftBoom, PdmAddress::Pdm30, &vcan_interface(), 17704);
+        let right = write_power(&dir, CropBed::RightBoom, PdmAddress::Pdm31, &vcan_interface(), 17705);
+        let weed = |crop_bed| SimAction::Weed {
+            crop_bed,
+            channels: vec![0],
+            cam_id: 0,
+            start_in_ms: 300,
+            duration_ms: 200,
+            intensity: FULL_INTENSITY,
+            assumed_speed_mps: None,
+        };
+        let scenario = SimScenario::new(1200)
+            .add_bed(SimBed::new(left))
+            .add_bed(SimBed::new(right))
+            .add_event(50, SimAction::Speed { mps: 1.5 })
+            .add_event(100, weed(CropBed::LeftBoom))
+            .add_event(100, weed(CropBed::RightBoom))
+            .add_event(
+                900,
+                SimAction::PdmFault {
+                    crop_bed: CropBed::RightBoom,
+                    address: PdmAddress::Pdm31,
+                    loss_of_can: false,
+                    module_over_temperature: false,
+                    over_current_channels: vec![3],
+                    over_temperature_channels: Vec::new(),
+                },
+            )
+            .expect(
+                SimExpectation::new(CropBed::LeftBoom, PdmAddress::Pdm30, 1, 1)
+                    .with_on_at([400])
+                    .with_tolerance(100),
+            )
+            .expect(
+                SimExpectation::new(CropBed::RightBoom, PdmAddress::Pdm31, 1, 1)
+                    .with_on_at([400])
+                    .with_tolerance(100),
+            )
+            .expect(SimExpectation::new(CropBed::LeftBoom, PdmAddress::Pdm30, 2, 0));
+        let scenario =
+            SimScenario::load_validated(write_scenario(&dir, &scenario)).unwrap_or_else(|report| panic!("{report}"));
+
+        let report = scenario.run().await.unwrap_or_else(|e| panic!("{e}"));
+        assert!(report.passed(), "{report}");
+        assert!(report.events.iter().all(|event| event.acted_on), "{:?}", report.events);
+        assert!(
+            report
+                .actuations
+                .iter()
+                .any(|actuation| actuation.crop_bed == CropBed::RightBoom && actuation.address == PdmAddress::Pdm31),
+            "{:?}",
+            report.actuations
+        );
+        assert!(
+            report.to_string().starts_with("3 of 3 expectations met over 1200ms"),
+            "{report}"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
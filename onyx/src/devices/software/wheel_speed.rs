@@ -0,0 +1,76 @@
+use crate::devices::hardware::wheel_speed::{GroundSpeed, SpeedSource};
+use futures::future::BoxFuture;
+use tokio::sync::mpsc;
+
+/// Stand in for a wheel speed sensor, reporting the speeds set through its
+/// handle. Used to test how components follow changes in ground speed.
+pub struct SimulatedSpeedSource {
+    /// Speeds set through the handle.
+    speed_rx: mpsc::UnboundedReceiver<GroundSpeed>,
+}
+
+/// Handle setting the speed a simulated source reports, the source stops
+/// once the handle is dropped.
+#[derive(Clone)]
+pub struct SimulatedSpeedHandle {
+    /// Speeds sent to the source.
+    speed_tx: mpsc::UnboundedSender<GroundSpeed>,
+}
+
+impl SimulatedSpeedSource {
+    /// Create a simulated source and the handle driving it.
+    pub fn new() -> (Self, SimulatedSpeedHandle) {
+        let (speed_tx, speed_rx) = mpsc::unbounded_channel();
+        (Self { speed_rx }, SimulatedSpeedHandle { speed_tx })
+    }
+}
+
+impl SpeedSource for SimulatedSpeedSource {
+    fn next_speed(&mut self) -> BoxFuture<'_, Option<GroundSpeed>> {
+        Box::pin(self.speed_rx.recv())
+    }
+}
+
+impl SimulatedSpeedHandle {
+    /// Report a new ground speed.
+    ///
+    /// * `mps`: speed in metres per second.
+    pub fn set_speed(&self, mps: f64) {
+        // The source being gone only means nothing is listening any more.
+        let _ = self.speed_tx.send(GroundSpeed::from_mps(mps));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::devices::hardware::wheel_speed::WheelSpeedSensor;
+    use std::time::Duration;
+
+    #[tokio::test]
+    /// The sensor publishes each speed set on the simulated source, and no
+    /// speed once the source stops.
+    async fn test_sensor_follows_simulated_speed() {
+        let (source, handle) = SimulatedSpeedSource::new();
+        let sensor = WheelSpeedSensor::start(Box::new(source));
+        let mut speed_rx = sensor.subscribe();
+        assert_eq!(sensor.speed(), None);
+
+        for mps in [1.5, 2.25] {
+            handle.set_speed(mps);
+            tokio::time::timeout(Duration::from_millis(100), speed_rx.changed())
+                .await
+                .expect("Speed was not published")
+                .unwrap();
+            assert_eq!(sensor.speed(), Some(GroundSpeed::from_mps(mps)));
+        }
+
+        drop(handle);
+        tokio::time::timeout(Duration::from_millis(100), speed_rx.changed())
+            .await
+            .expect("Stopping was not published")
+            .unwrap();
+        assert_eq!(sensor.speed(), None);
+    }
+}
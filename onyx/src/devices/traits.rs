@@ -0,0 +1,91 @@
+use crate::utils::config::ConfigFile;
+use std::error::Error;
+use uuid::Uuid;
+
+/// A device on a crop bed, or one standing in for it, as the components
+/// see it whatever drives it.
+pub trait Device {
+    /// Unique identifier of the device, for trouble shooting and logging.
+    fn uuid(&self) -> Uuid;
+
+    /// Location of the device on the crop bed as per the bill of
+    /// materials, `None` when it has not been placed.
+    fn location(&self) -> Option<u8>;
+}
+
+/// Config a device is built from, loaded from its file through the shared
+/// loader and validated before the device is built.
+pub trait DeviceConfig: ConfigFile {
+    /// Device built from the config.
+    type Device: Device;
+    /// Why the device could not be built, i.e. it is not on the network.
+    type Error: Error;
+
+    /// Location the device is built at, as per the bill of materials.
+    fn location(&self) -> Option<u8>;
+
+    /// Build the device by consuming the config, as its `new` does.
+    fn build(self) -> Result<Self::Device, Self::Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "canbus")]
+    use crate::devices::hardware::pdm::{PdmAddress, PdmConfig};
+    use crate::devices::software::camera::SimulatedCameraConfig;
+    use serde::Serialize;
+    use std::path::{Path, PathBuf};
+
+    /// Write a config to a new file in the temporary directory.
+    ///
+    /// * `config`: config written as yaml.
+    fn write<C: Serialize>(config: &C) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("onyx-device-{}.yaml", Uuid::new_v4()));
+        serde_yaml::to_writer(std::fs::File::create(&path).unwrap(), config).unwrap();
+        path
+    }
+
+    /// Build a device from its config file as a component would, returning
+    /// the location of the config and the device.
+    ///
+    /// * `path`: path of the config file.
+    fn build_from_file<C: DeviceConfig>(path: &Path) -> (Option<u8>, C::Device) {
+        let config = C::load_validated(path).unwrap_or_else(|report| panic!("{report}"));
+        let location = config.location();
+        (location, config.build().unwrap_or_else(|e| panic!("{e}")))
+    }
+
+    #[test]
+    /// Simulated cameras are built from their files at the location of
+    /// their configs, each with an identifier of its own.
+    fn test_devices_built_at_config_location() {
+        let camera_file = write(&SimulatedCameraConfig::new(Some(3), 10, 8, 8));
+        let (location, first) = build_from_file::<SimulatedCameraConfig>(&camera_file);
+        let (_, second) = build_from_file::<SimulatedCameraConfig>(&camera_file);
+        assert_eq!((location, first.location()), (Some(3), Some(3)));
+        assert_ne!(first.uuid(), second.uuid());
+        std::fs::remove_file(camera_file).unwrap();
+    }
+
+    #[cfg(feature = "canbus")]
+    #[test]
+    /// PDMs are built from their files at the location of their configs,
+    /// without being sent anything until initialised.
+    fn test_pdm_built_at_config_location() {
+        let pdm_file = write(&PdmConfig::new(PdmAddress::Pdm31, 1));
+        let (location, pdm) = build_from_file::<PdmConfig>(&pdm_file);
+        assert_eq!((location, pdm.location()), (Some(1), Some(1)));
+        assert!(!pdm.is_initialised());
+        std::fs::remove_file(pdm_file).unwrap();
+    }
+
+    #[test]
+    /// A config the device would refuse is not built.
+    fn test_invalid_device_config_refused() {
+        let camera_file = write(&SimulatedCameraConfig::new(Some(0), 0, 8, 8));
+        let report = SimulatedCameraConfig::load_validated(&camera_file).unwrap_err();
+        assert!(report.to_string().contains("fps"), "{report}");
+        std::fs::remove_file(camera_file).unwrap();
+    }
+}
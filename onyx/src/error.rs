@@ -0,0 +1,132 @@
+use crate::utils::tasks::{EXIT_INVALID_CONFIG, EXIT_START_FAILED};
+pub use crate::{
+    components::traits::ComponentError,
+    devices::hardware::{camera::CameraError, pdm::PdmError},
+    messages::encoding::MessageError,
+    utils::config::ConfigError,
+};
+
+/// Any error of the crate, for a binary to log and exit on. The library
+/// returns the error of the module it failed in, a binary turns it into
+/// its exit code here.
+#[derive(Debug, thiserror::Error)]
+pub enum OnyxError {
+    /// A config file did not load.
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    /// A network camera could not be built or streamed from.
+    #[error(transparent)]
+    Camera(#[from] CameraError),
+    /// A PDM could not be reached on its canbus interface.
+    #[error(transparent)]
+    Pdm(#[from] PdmError),
+    /// A component could not be built or started.
+    #[error(transparent)]
+    Component(#[from] ComponentError),
+    /// A response from a component could not be read.
+    #[error(transparent)]
+    Message(#[from] MessageError),
+}
+
+impl OnyxError {
+    /// Whether the config was at fault rather than what was found when
+    /// starting, so restarting the container will not help.
+    pub fn is_config(&self) -> bool {
+        match self {
+            Self::Config(_) | Self::Camera(CameraError::Config(_)) => true,
+            Self::Component(e) => e.is_config(),
+            Self::Camera(_) | Self::Pdm(_) | Self::Message(_) => false,
+        }
+    }
+
+    /// Exit code of a binary failing on the error, [`EXIT_INVALID_CONFIG`]
+    /// for a config at fault and [`EXIT_START_FAILED`] otherwise.
+    pub fn exit_code(&self) -> u8 {
+        if self.is_config() {
+            EXIT_INVALID_CONFIG
+        } else {
+            EXIT_START_FAILED
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "camera-hw")]
+    use crate::devices::hardware::camera::OnyxCamera;
+    use crate::{components::prelude::*, devices::hardware::pdm::PdmConfig, utils::location::CropBed};
+    use std::path::PathBuf;
+
+    #[test]
+    /// A config file that is missing, or one that refers to a missing file,
+    /// exits as an invalid config whichever module it failed in, keeping
+    /// the message it was logged with.
+    fn test_config_errors_exit_invalid() {
+        let missing = PathBuf::from("/nonexistent/onyx/config.yaml");
+        let mut errors = vec![
+            OnyxError::from(PdmConfig::try_from_file(&missing).unwrap_err()),
+            OnyxError::from(
+                CameraArray::new(
+                    CameraArrayConfig::new(String::from("images"), CropBed::LeftBoom)
+                        .add_camera_config_file(missing.clone(), 0),
+                )
+                .err()
+                .expect("Component built"),
+            ),
+        ];
+        #[cfg(feature = "camera-hw")]
+        errors.push(OnyxError::from(
+            OnyxCamera::from_config_file(&missing).err().expect("Camera built"),
+        ));
+        #[cfg(feature = "canbus")]
+        errors.extend([
+            OnyxError::from(CropBedPower::from_config_file(&missing).err().expect("Component built")),
+            OnyxError::from(
+                CropBedPower::new(
+                    CropBedPowerConfig::new(CropBed::LeftBoom, String::from("can0"), 17701, None)
+                        .add_pdm_config_file(missing.clone(), 0),
+                )
+                .err()
+                .expect("Component built"),
+            ),
+        ]);
+        for error in errors {
+            assert_eq!(error.exit_code(), EXIT_INVALID_CONFIG, "{error:?}");
+            assert!(
+                error
+                    .to_string()
+                    .starts_with("Could not read the config file \"/nonexistent/onyx/config.yaml\""),
+                "{error}"
+            );
+        }
+    }
+
+    #[cfg(feature = "canbus")]
+    #[tokio::test]
+    /// A component that is valid but cannot reach its devices exits as
+    /// failing to start.
+    async fn test_start_errors_exit_start_failed() {
+        let component = CropBedPower::new(CropBedPowerConfig::new(
+            CropBed::LeftBoom,
+            String::from("nocan0"),
+            17702,
+            None,
+        ))
+        .unwrap();
+        let error = OnyxError::from(
+            CropBedPowerController::start(component)
+                .await
+                .err()
+                .expect("Component started"),
+        );
+        assert_eq!(error.exit_code(), EXIT_START_FAILED);
+        assert!(
+            error
+                .to_string()
+                .starts_with("Failed to create canbus socket on nocan0"),
+            "{error}"
+        );
+        assert_eq!(OnyxError::from(MessageError::Timeout).exit_code(), EXIT_START_FAILED);
+    }
+}
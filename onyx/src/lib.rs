@@ -13,6 +13,9 @@ pub mod components;
 /// Devices that are an atomic unit, and can be composed 
 /// with other devices into components to perform some function.
 pub mod devices;
+/// Errors of the components and devices, and the exit code of a binary
+/// failing on one.
+pub mod error;
 /// Message structure for communication into and out of the 
 /// control system, such as process communication for the 
 /// AI system.
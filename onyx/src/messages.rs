@@ -7,10 +7,40 @@ pub mod control {
     /// for when a PDM should fire.
     pub mod weed;
     /// Light messages come from another control loop. 
-    /// TODO: Decide if this needs to be synchronised 
-    /// with the camera software trigger.
     pub mod light;
+    /// Trigger messages come from the camera array for
+    /// each capture, the lights can be strobed on them.
+    pub mod trigger;
+    /// PDM control messages come from the operator, e.g. to
+    /// recover a PDM without restarting the component.
+    pub mod pdm;
+    /// Manual spray messages come from the operator while
+    /// commissioning, firing channels by hand.
+    pub mod manual;
+    /// Heartbeats sent by the running components, so
+    /// the others know they are alive.
+    pub mod heartbeat;
+    /// Responses written back by the components for every
+    /// message they receive.
+    pub mod response;
 }
 
-/// TODO: Schedule impacted ability to implement logging.
-pub mod logging {}
+/// Envelope carrying the id, version and send time of any message,
+/// read alongside the bare messages of older senders.
+pub mod envelope;
+
+/// Encodings the messages and responses are written in on a connection,
+/// json, CBOR or MessagePack.
+pub mod encoding;
+
+/// Structured events logged by the components, one line of json
+/// each so nothing is lost once it scrolls past.
+pub mod logging;
+
+/// Snapshots of the queue, camera and PDM state of the components,
+/// shipped to the fleet backend at an interval.
+pub mod telemetry;
+
+/// JSON Schema documents of the messages, for senders written in
+/// other languages.
+pub mod schema;
@@ -0,0 +1,125 @@
+use crate::utils::location::CropBed;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Kind of component sending a heartbeat.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentKind {
+    /// Crop bed power, firing the solenoids.
+    Power,
+    /// Crop bed lighting.
+    Lighting,
+    /// Camera array of a crop bed.
+    CameraArray,
+}
+
+impl std::fmt::Display for ComponentKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Power => write!(f, "power"),
+            Self::Lighting => write!(f, "lighting"),
+            Self::CameraArray => write!(f, "camera array"),
+        }
+    }
+}
+
+/// Sent by a running component at a steady interval, so the AI system and
+/// other components know it is alive before a spray fails to happen.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Heartbeat {
+    /// Kind of component sending it, the key telling a heartbeat apart
+    /// from the other messages on a socket.
+    pub heartbeat: ComponentKind,
+    /// Unique id of the component.
+    pub component: Uuid,
+    /// Crop bed the component is attached to.
+    pub crop_bed_id: CropBed,
+    /// Seconds since the component was created.
+    pub uptime_s: f64,
+    /// UTC time the heartbeat was sent.
+    pub sent_at: DateTime<Utc>,
+    /// Actions waiting in the queue, for components with one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_depth: Option<usize>,
+    /// Frames captured so far, for components with cameras.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_count: Option<u64>,
+}
+
+impl Heartbeat {
+    /// Heartbeat of a component sent now.
+    ///
+    /// * `heartbeat`: kind of component.
+    /// * `component`: unique id of the component.
+    /// * `crop_bed_id`: crop bed the component is attached to.
+    /// * `uptime_s`: seconds since the component was created.
+    pub fn new(heartbeat: ComponentKind, component: Uuid, crop_bed_id: CropBed, uptime_s: f64) -> Self {
+        Self {
+            heartbeat,
+            component,
+            crop_bed_id,
+            uptime_s,
+            sent_at: Utc::now(),
+            queue_depth: None,
+            frame_count: None,
+        }
+    }
+
+    /// Set the actions waiting in the queue.
+    ///
+    /// * `queue_depth`: actions queued.
+    pub fn with_queue_depth(mut self, queue_depth: usize) -> Self {
+        self.queue_depth = Some(queue_depth);
+        self
+    }
+
+    /// Set the frames captured so far.
+    ///
+    /// * `frame_count`: frames captured.
+    pub fn with_frame_count(mut self, frame_count: u64) -> Self {
+        self.frame_count = Some(frame_count);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::messages::control::{light::LightMessage, manual::ManualSprayMessage, weed::WeedMessage};
+
+    #[test]
+    /// Heartbeats survive a round trip through json, leaving out what the
+    /// component does not count.
+    fn test_heartbeat_round_trip() {
+        let heartbeat =
+            Heartbeat::new(ComponentKind::Power, Uuid::new_v4(), CropBed::RightBoom, 12.5).with_queue_depth(4);
+        let json = serde_json::to_string(&heartbeat).unwrap();
+        assert!(json.contains(r#""heartbeat":"power""#), "{json}");
+        assert!(!json.contains("frame_count"), "{json}");
+        assert_eq!(serde_json::from_str::<Heartbeat>(&json).unwrap(), heartbeat);
+
+        let heartbeat =
+            Heartbeat::new(ComponentKind::CameraArray, Uuid::new_v4(), CropBed::Centre, 3.0).with_frame_count(912);
+        let json = serde_json::to_string(&heartbeat).unwrap();
+        assert_eq!(serde_json::from_str::<Heartbeat>(&json).unwrap(), heartbeat);
+    }
+
+    #[test]
+    /// Heartbeats are not mistaken for the other messages on the sockets
+    /// they are sent to, nor those for heartbeats.
+    fn test_heartbeat_apart_from_messages() {
+        let heartbeat = Heartbeat::new(ComponentKind::Lighting, Uuid::new_v4(), CropBed::LeftBoom, 1.0);
+        let json = serde_json::to_string(&heartbeat).unwrap();
+        assert!(serde_json::from_str::<WeedMessage>(&json).is_err());
+        assert!(serde_json::from_str::<LightMessage>(&json).is_err());
+        assert!(serde_json::from_str::<ManualSprayMessage>(&json).is_err());
+        assert!(serde_json::from_str::<Heartbeat>(
+            r#"{"channels": [3], "is_on": true, "cam_id": 4, "crop_bed_id": 2}"#
+        )
+        .is_err());
+        assert!(serde_json::from_str::<Heartbeat>(r#"{"heartbeat": "sprayer", "component": "00000000-0000-0000-0000-000000000000", "crop_bed_id": 2, "uptime_s": 1.0, "sent_at": "2023-08-25T05:14:14Z"}"#).is_err());
+    }
+}
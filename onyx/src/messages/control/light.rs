@@ -1,7 +1,10 @@
-use serde::Deserialize;
+use crate::messages::control::weed::{deserialize_intensity, full_intensity, FULL_INTENSITY};
+use crate::utils::location::CropBed;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 
 /// Light message generated from another system.
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
 pub struct LightMessage {
     /// The channels of the PDM to turn on.
     /// TODO: The lighting system was created on the fly when the machine got to
@@ -9,12 +12,227 @@ pub struct LightMessage {
     /// frame (and lack of switch on site). As a result the channels that the
     /// lights have been connected to are not matched (i.e. crop be 0 - to channel 1)
     pub channels: Vec<u8>,
-    /// If true, set the PWM of the output channel to 100, else to 0.
+    /// If true, set the PWM of the output channel to the level, else to 0.
     pub is_on: bool,
+    /// Duty cycle in percent the lights are dimmed to when on, fully on
+    /// when not set.
+    #[serde(default = "full_intensity", deserialize_with = "deserialize_intensity")]
+    pub level: u8,
+    /// If true with `is_on`, pulse the channels on the camera triggers at
+    /// the level rather than holding them on. Any other message for the
+    /// channels takes them out of strobe mode.
+    #[serde(default)]
+    pub strobe: bool,
     /// Camera id associated with the light.
-    cam_id: u8,
+    pub cam_id: u8,
     /// Crop bed id associated with the light.
-    crop_bed_id: u8,
+    pub crop_bed_id: CropBed,
+}
+
+impl LightMessage {
+    /// Light message turning no channels off. Build it up with the setters
+    /// before sending it.
+    ///
+    /// * `crop_bed_id`: crop bed the lights are on.
+    /// * `cam_id`: camera the lights are for.
+    pub fn new(crop_bed_id: impl Into<CropBed>, cam_id: u8) -> Self {
+        Self {
+            channels: Vec::new(),
+            is_on: false,
+            level: FULL_INTENSITY,
+            strobe: false,
+            cam_id,
+            crop_bed_id: crop_bed_id.into(),
+        }
+    }
+
+    /// Set the channels the message is for.
+    ///
+    /// * `channels`: channels in the light messages.
+    pub fn channels(mut self, channels: impl Into<Vec<u8>>) -> Self {
+        self.channels = channels.into();
+        self
+    }
+
+    /// Turn the channels on.
+    pub fn on(mut self) -> Self {
+        self.is_on = true;
+        self
+    }
+
+    /// Turn the channels off.
+    pub fn off(mut self) -> Self {
+        self.is_on = false;
+        self
+    }
+
+    /// Set the level the channels are dimmed to when on, panics over 100
+    /// percent as the message would be rejected.
+    ///
+    /// * `level`: duty cycle in percent.
+    pub fn level(mut self, level: u8) -> Self {
+        assert!(level <= FULL_INTENSITY, "Level {level} is outside 0 to {FULL_INTENSITY}");
+        self.level = level;
+        self
+    }
+
+    /// Pulse the channels on the camera triggers rather than holding them on.
+    pub fn strobe(mut self) -> Self {
+        self.strobe = true;
+        self
+    }
+
+    /// Duty cycle in percent asked for the channels, 0 when turning them
+    /// off.
+    pub fn duty_percent(&self) -> u8 {
+        if self.is_on {
+            self.level
+        } else {
+            0
+        }
+    }
+}
+
+/// Why a light message was rejected by the lighting component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightRejection {
+    /// The message is for another crop bed.
+    WrongBed(CropBed),
+    /// The camera is not one the component knows of.
+    UnknownCamera(u8),
+    /// The channel is not in the light channel map.
+    UnmappedChannel(u8),
+    /// The channel is mapped outside the channels of a PDM.
+    InvalidPdmChannel(u8),
+}
+
+impl std::fmt::Display for LightRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WrongBed(crop_bed) => write!(f, "it is for crop bed {crop_bed}"),
+            Self::UnknownCamera(cam_id) => write!(f, "camera {cam_id} is not a known camera"),
+            Self::UnmappedChannel(channel) => write!(f, "channel {channel} is not in the light channel map"),
+            Self::InvalidPdmChannel(channel) => {
+                write!(f, "channel {channel} is mapped outside the channels of its PDM")
+            }
+        }
+    }
+}
+
+/// Count of light messages rejected for each reason.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LightRejections {
+    /// Messages for another crop bed.
+    pub wrong_bed: u64,
+    /// Messages from a camera the component does not know of.
+    pub unknown_camera: u64,
+    /// Messages with a channel not in the light channel map.
+    pub unmapped_channel: u64,
+    /// Messages with a channel mapped outside the channels of a PDM.
+    pub invalid_pdm_channel: u64,
+}
+
+impl LightRejections {
+    /// Count a rejected message.
+    ///
+    /// * `rejection`: why it was rejected.
+    pub fn record(&mut self, rejection: LightRejection) {
+        let count = match rejection {
+            LightRejection::WrongBed(_) => &mut self.wrong_bed,
+            LightRejection::UnknownCamera(_) => &mut self.unknown_camera,
+            LightRejection::UnmappedChannel(_) => &mut self.unmapped_channel,
+            LightRejection::InvalidPdmChannel(_) => &mut self.invalid_pdm_channel,
+        };
+        *count += 1;
+    }
+}
+
+/// Outcome of the start up self-test for a light channel, from the current
+/// feedback of its PDM while it was on.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum SelfTestResult {
+    /// The light drew current.
+    Pass {
+        /// Current drawn in amps.
+        amps: f32,
+    },
+    /// The light drew no current, a broken bar or harness.
+    NoLoad {
+        /// Current drawn in amps.
+        amps: f32,
+    },
+    /// The PDM reported a fault on the channel.
+    Fault {
+        /// Fault bits of the feedback.
+        status_flags: u8,
+    },
+    /// No feedback arrived while the light was on, so it was not checked.
+    NoFeedback,
+}
+
+impl SelfTestResult {
+    /// Whether the light was seen working.
+    pub fn passed(&self) -> bool {
+        matches!(self, Self::Pass { .. })
+    }
+}
+
+impl std::fmt::Display for SelfTestResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pass { amps } => write!(f, "pass, drew {amps:.2}A"),
+            Self::NoLoad { amps } => write!(f, "fail, drew {amps:.2}A"),
+            Self::Fault { status_flags } => write!(f, "fail, PDM fault {status_flags:#04b}"),
+            Self::NoFeedback => write!(f, "unknown, no current feedback"),
+        }
+    }
+}
+
+/// Requests the lighting component answers on the connection they were
+/// sent on.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum LightRequest {
+    /// State of every light channel, see [`LightStatusResponse`].
+    Status,
+}
+
+/// Request from the HMI for the state of the lights, sent on the same port
+/// as the light messages.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct LightStatusRequest {
+    /// What is asked for.
+    pub request: LightRequest,
+}
+
+/// State of the lights of a crop bed, the answer to a [`LightStatusRequest`].
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct LightStatusResponse {
+    /// Crop bed the lights are on.
+    pub crop_bed_id: CropBed,
+    /// Level in percent each light channel was last set to, after its cap.
+    /// Channels never set are left out.
+    pub channels: BTreeMap<u8, u8>,
+    /// Level in percent of the pulses of each light channel in strobe mode.
+    pub strobed: BTreeMap<u8, u8>,
+    /// Seconds since the component was created.
+    pub uptime_s: f64,
+    /// Whether each PDM has been initialised, keyed by bed position.
+    pub pdms_initialised: BTreeMap<u8, bool>,
+    /// Whether a PDM configuration has drifted and messages are ignored.
+    pub drifted: bool,
+    /// Light messages rejected for each reason.
+    #[serde(default)]
+    pub rejected: LightRejections,
+    /// Outcome of the start up self-test for each light channel, empty when
+    /// it was not run.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub self_test: BTreeMap<u8, SelfTestResult>,
+    /// Light channels held at their safe level while they cool down.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub cooling_down: BTreeSet<u8>,
 }
 
 #[cfg(test)]
@@ -91,7 +309,9 @@ mod tests {
     , LightMessage {
             cam_id: 5,
             is_on: false,
-            crop_bed_id: 2,
+            level: 100,
+            strobe: false,
+            crop_bed_id: CropBed::RightBoom,
             channels: vec![7, 8, 9],
 
         }))]
@@ -101,7 +321,9 @@ mod tests {
     , LightMessage {
             cam_id: 4,
             is_on: true,
-            crop_bed_id: 2,
+            level: 100,
+            strobe: false,
+            crop_bed_id: CropBed::RightBoom,
             channels: vec![0],
         } ))]
     fn test_parse_and_compare_weed_message(#[case] args: (&str, LightMessage)) {
@@ -109,4 +331,65 @@ mod tests {
 
         assert_eq!(parsed, args.1, "Failed to parse message correctly");
     }
+
+    #[rstest]
+    #[case(LightMessage::new(2, 4).channels([4]).on().strobe(), r#"{"channels": [4], "is_on": true, "strobe": true, "cam_id": 4, "crop_bed_id": 2}"#)]
+    #[case(LightMessage::new(2, 4).channels([3]).on().level(65), r#"{"channels": [3], "is_on": true, "level": 65, "cam_id": 4, "crop_bed_id": 2}"#)]
+    #[case(LightMessage::new(CropBed::Centre, 1).channels([1, 2]).on().off(), r#"{"channels": [1, 2], "is_on": false, "cam_id": 1, "crop_bed_id": 1}"#)]
+    /// Messages built by tools are read back as sent, and as the same
+    /// message written by hand.
+    fn test_light_message_round_trip(#[case] message: LightMessage, #[case] raw_string: &str) {
+        let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(serde_json::from_str::<LightMessage>(&json).unwrap(), message);
+        assert_eq!(serde_json::from_str::<LightMessage>(raw_string).unwrap(), message);
+    }
+
+    #[rstest]
+    #[case(LightMessage::new(2, 4).channels([3]).on(), 100)]
+    #[case(LightMessage::new(2, 4).channels([3]).on().level(65), 65)]
+    #[case(LightMessage::new(2, 4).channels([3]).level(65), 0)]
+    /// Lights are fully on unless dimmed, and off whatever the level.
+    fn test_light_duty_percent(#[case] message: LightMessage, #[case] duty_percent: u8) {
+        assert_eq!(message.duty_percent(), duty_percent);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside 0 to 100")]
+    fn test_build_out_of_range_level() {
+        let _ = LightMessage::new(2, 4).level(101);
+    }
+
+    #[test]
+    /// Status requests are told apart from light messages, and the response
+    /// survives a round trip through json.
+    fn test_light_status_messages() {
+        let request: LightStatusRequest = serde_json::from_str(r#"{"request": "status"}"#).unwrap();
+        assert_eq!(request.request, LightRequest::Status);
+        assert!(serde_json::from_str::<LightStatusRequest>(r#"{"request": "status", "channels": [1]}"#).is_err());
+        assert!(serde_json::from_str::<LightMessage>(r#"{"request": "status"}"#).is_err());
+
+        let response = LightStatusResponse {
+            crop_bed_id: CropBed::Centre,
+            channels: BTreeMap::from([(1, 100), (7, 40)]),
+            strobed: BTreeMap::from([(9, 100)]),
+            uptime_s: 12.5,
+            pdms_initialised: BTreeMap::from([(0, true)]),
+            drifted: false,
+            rejected: LightRejections {
+                wrong_bed: 2,
+                ..LightRejections::default()
+            },
+            self_test: BTreeMap::from([(1, SelfTestResult::Pass { amps: 1.5 }), (7, SelfTestResult::NoFeedback)]),
+            cooling_down: BTreeSet::from([7]),
+        };
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(serde_json::from_str::<LightStatusResponse>(&json).unwrap(), response);
+    }
+
+    #[test]
+    /// Levels over 100 percent are rejected rather than clipped.
+    fn test_reject_out_of_range_level() {
+        let raw_string = r#"{"channels": [3], "is_on": true, "level": 101, "cam_id": 4, "crop_bed_id": 2}"#;
+        assert!(serde_json::from_str::<LightMessage>(raw_string).is_err());
+    }
 }
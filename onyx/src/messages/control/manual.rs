@@ -0,0 +1,73 @@
+use crate::messages::control::weed::{deserialize_intensity, full_intensity};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Spray fired by hand from the operator while commissioning, e.g. open
+/// channel 7 for 500 ms, sent on the same port as the weed messages. It
+/// starts as soon as it is received.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct ManualSprayMessage {
+    /// Tag telling a manual spray apart from a detection, must be true.
+    #[serde(deserialize_with = "deserialize_manual_tag")]
+    pub manual: bool,
+    /// Channels of the crop bed to open, numbered from 1 as on the harness
+    /// and translated by the channel map like weed messages.
+    pub channels: Vec<u8>,
+    /// Time in milliseconds the channels are kept open.
+    pub duration_ms: u64,
+    /// Duty cycle in percent, fully on when not set.
+    #[serde(default = "full_intensity", deserialize_with = "deserialize_intensity")]
+    pub pwm: u8,
+    /// Identifier echoed in the response.
+    #[serde(default)]
+    pub message_id: Option<String>,
+}
+
+/// Read the manual tag, refusing anything but true so a message has to
+/// ask for manual control explicitly.
+///
+/// * `deserializer`: deserializer of the message.
+fn deserialize_manual_tag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    if bool::deserialize(deserializer)? {
+        Ok(true)
+    } else {
+        Err(serde::de::Error::custom("manual spray messages must be tagged manual: true"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(
+        r#"{"manual": true, "channels": [7], "duration_ms": 500}"#,
+        ManualSprayMessage { manual: true, channels: vec![7], duration_ms: 500, pwm: 100, message_id: None }
+    )]
+    #[case(
+        r#"{"manual": true, "channels": [1, 2], "duration_ms": 250, "pwm": 40, "message_id": "hmi-3"}"#,
+        ManualSprayMessage {
+            manual: true,
+            channels: vec![1, 2],
+            duration_ms: 250,
+            pwm: 40,
+            message_id: Some(String::from("hmi-3")),
+        }
+    )]
+    fn test_parse_manual_spray_message(#[case] raw_string: &str, #[case] expected: ManualSprayMessage) {
+        let parsed: ManualSprayMessage = serde_json::from_str(raw_string).unwrap();
+        assert_eq!(parsed, expected, "Failed to parse message correctly");
+    }
+
+    #[rstest]
+    #[case::untagged(r#"{"channels": [7], "duration_ms": 500}"#)]
+    #[case::tagged_false(r#"{"manual": false, "channels": [7], "duration_ms": 500}"#)]
+    #[case::pwm_over_100(r#"{"manual": true, "channels": [7], "duration_ms": 500, "pwm": 120}"#)]
+    #[case::weed_message(r#"{"channels_to_open": [0], "cam_id": 4, "crop_bed_id": 2}"#)]
+    /// Only messages explicitly tagged manual, with a valid duty cycle, are
+    /// manual sprays.
+    fn test_reject_other_messages(#[case] raw_string: &str) {
+        assert!(serde_json::from_str::<ManualSprayMessage>(raw_string).is_err());
+    }
+}
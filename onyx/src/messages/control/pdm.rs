@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+/// Time in milliseconds each channel is on in a test pattern when not set.
+pub const DEFAULT_TEST_PATTERN_ON_MS: u64 = 500;
+
+/// Time in milliseconds between channels in a test pattern when not set.
+pub const DEFAULT_TEST_PATTERN_GAP_MS: u64 = 500;
+
+/// Control message for the PDMs of a crop bed, sent to the crop bed power
+/// component on the same port as the weed messages.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PdmControlMessage {
+    /// Send the configuration to a PDM again and read it back, e.g. after
+    /// a brown out, without restarting the component.
+    Reinitialise {
+        /// Bed position of the PDM, every PDM of the component when not set.
+        #[serde(default)]
+        bed_position: Option<u8>,
+    },
+    /// Fire every channel in turn, so a technician walking the boom after
+    /// re-plumbing a bed can spot swapped hoses.
+    TestPattern(TestPattern),
+    /// Keep queueing and timing sprays without turning any channel on, so
+    /// the machine can be driven through a field to check the detections.
+    DryRun {
+        /// Whether the dry run is started or ended.
+        enabled: bool,
+    },
+}
+
+/// Chase of every configured channel, each on for a time with a gap before
+/// the next, in channel map order. Every channel is turned off at the end.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TestPattern {
+    /// Time in milliseconds each channel is on.
+    #[serde(default = "default_on_ms")]
+    pub on_ms: u64,
+    /// Time in milliseconds between one channel turning off and the next
+    /// turning on.
+    #[serde(default = "default_gap_ms")]
+    pub gap_ms: u64,
+    /// Times the chase is run through every channel.
+    #[serde(default = "default_loops")]
+    pub loops: u32,
+    /// Run the chase even while weed messages are queued, they are refused
+    /// otherwise.
+    #[serde(default)]
+    pub force: bool,
+}
+
+impl Default for TestPattern {
+    fn default() -> Self {
+        Self {
+            on_ms: DEFAULT_TEST_PATTERN_ON_MS,
+            gap_ms: DEFAULT_TEST_PATTERN_GAP_MS,
+            loops: 1,
+            force: false,
+        }
+    }
+}
+
+/// Default time each channel is on, for serde.
+fn default_on_ms() -> u64 {
+    DEFAULT_TEST_PATTERN_ON_MS
+}
+
+/// Default time between channels, for serde.
+fn default_gap_ms() -> u64 {
+    DEFAULT_TEST_PATTERN_GAP_MS
+}
+
+/// Default times through every channel, for serde.
+fn default_loops() -> u32 {
+    1
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(r#"{"reinitialise": {"bed_position": 1}}"#, PdmControlMessage::Reinitialise { bed_position: Some(1) })]
+    #[case(r#"{"reinitialise": {}}"#, PdmControlMessage::Reinitialise { bed_position: None })]
+    #[case(r#"{"test_pattern": {}}"#, PdmControlMessage::TestPattern(TestPattern::default()))]
+    #[case(
+        r#"{"test_pattern": {"on_ms": 200, "gap_ms": 100, "loops": 3, "force": true}}"#,
+        PdmControlMessage::TestPattern(TestPattern { on_ms: 200, gap_ms: 100, loops: 3, force: true })
+    )]
+    #[case(r#"{"dry_run": {"enabled": true}}"#, PdmControlMessage::DryRun { enabled: true })]
+    fn test_parse_pdm_control_message(#[case] raw_string: &str, #[case] expected: PdmControlMessage) {
+        let parsed: PdmControlMessage = serde_json::from_str(raw_string).unwrap();
+        assert_eq!(parsed, expected, "Failed to parse message correctly");
+    }
+
+    #[rstest]
+    #[case(r#"{"channels_to_open": [0], "cam_id": 4, "crop_bed_id": 2}"#)]
+    #[case(r#"{"reboot": {"bed_position": 1}}"#)]
+    /// Weed messages and unknown commands are not mistaken for control
+    /// messages.
+    fn test_reject_other_messages(#[case] raw_string: &str) {
+        assert!(serde_json::from_str::<PdmControlMessage>(raw_string).is_err());
+    }
+}
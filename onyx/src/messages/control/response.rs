@@ -0,0 +1,158 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Reason given for data that is not a message the component understands.
+pub const MALFORMED_REASON: &str = "malformed message";
+
+/// What a component did with a message, the same on every socket.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ResponseStatus {
+    /// The message was acted on or queued.
+    Accepted,
+    /// The message was refused.
+    Rejected {
+        /// Why it was refused, readable by the operator.
+        reason: String,
+    },
+    /// The message was dropped as a retry of one already received.
+    Duplicate,
+    /// The message arrived after it was due to be acted on.
+    Late,
+}
+
+/// One line of json written back for each message a component receives.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ControlResponse {
+    /// What was done with the message.
+    #[serde(flatten)]
+    pub status: ResponseStatus,
+    /// Id of the message responded to, if it carried one.
+    pub correlation_id: Option<String>,
+    /// Component that handled the message.
+    pub component: Uuid,
+    /// Further detail particular to the message, e.g. the actions queued.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub details: BTreeMap<String, serde_json::Value>,
+}
+
+impl ControlResponse {
+    /// Response to a message.
+    ///
+    /// * `status`: what was done with the message.
+    /// * `correlation_id`: id of the message.
+    pub fn new(status: ResponseStatus, correlation_id: Option<String>) -> Self {
+        Self {
+            status,
+            correlation_id,
+            component: Uuid::nil(),
+            details: BTreeMap::new(),
+        }
+    }
+
+    /// Response to a message acted on.
+    ///
+    /// * `correlation_id`: id of the message.
+    pub fn accepted(correlation_id: Option<String>) -> Self {
+        Self::new(ResponseStatus::Accepted, correlation_id)
+    }
+
+    /// Response to a message refused.
+    ///
+    /// * `correlation_id`: id of the message.
+    /// * `reason`: why it was refused.
+    pub fn rejected(correlation_id: Option<String>, reason: impl Into<String>) -> Self {
+        Self::new(ResponseStatus::Rejected { reason: reason.into() }, correlation_id)
+    }
+
+    /// Response to a retry of a message already received.
+    ///
+    /// * `correlation_id`: id of the message.
+    pub fn duplicate(correlation_id: Option<String>) -> Self {
+        Self::new(ResponseStatus::Duplicate, correlation_id)
+    }
+
+    /// Response to a message arriving too late to act on.
+    ///
+    /// * `correlation_id`: id of the message.
+    pub fn late(correlation_id: Option<String>) -> Self {
+        Self::new(ResponseStatus::Late, correlation_id)
+    }
+
+    /// Set the component responding.
+    ///
+    /// * `component`: uuid of the component.
+    pub fn with_component(mut self, component: Uuid) -> Self {
+        self.component = component;
+        self
+    }
+
+    /// Add a detail, panics if the value cannot be written as json.
+    ///
+    /// * `key`: name of the detail.
+    /// * `value`: the detail.
+    pub fn with_detail(mut self, key: &str, value: impl Serialize) -> Self {
+        let value = serde_json::to_value(value).expect("Failed to serialise response detail");
+        self.details.insert(String::from(key), value);
+        self
+    }
+
+    /// Whether the message was acted on.
+    pub fn is_accepted(&self) -> bool {
+        self.status == ResponseStatus::Accepted
+    }
+
+    /// Why the message was refused, `None` unless it was.
+    pub fn reason(&self) -> Option<&str> {
+        match &self.status {
+            ResponseStatus::Rejected { reason } => Some(reason.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Read a detail back, `None` if it is missing or of another type.
+    ///
+    /// * `key`: name of the detail.
+    pub fn detail<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        serde_json::from_value(self.details.get(key)?.clone()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(
+        ControlResponse::accepted(Some(String::from("cam0-1"))).with_detail("queued_actions", 2),
+        r#"{"status":"accepted","correlation_id":"cam0-1","component":"00000000-0000-0000-0000-000000000000","details":{"queued_actions":2}}"#
+    )]
+    #[case(
+        ControlResponse::rejected(None, "manual sprays are not allowed"),
+        r#"{"status":"rejected","reason":"manual sprays are not allowed","correlation_id":null,"component":"00000000-0000-0000-0000-000000000000"}"#
+    )]
+    #[case(
+        ControlResponse::late(Some(String::from("cam0-2"))),
+        r#"{"status":"late","correlation_id":"cam0-2","component":"00000000-0000-0000-0000-000000000000"}"#
+    )]
+    fn test_serialise_control_response(#[case] response: ControlResponse, #[case] expected: &str) {
+        assert_eq!(serde_json::to_string(&response).unwrap(), expected);
+        assert_eq!(serde_json::from_str::<ControlResponse>(expected).unwrap(), response);
+    }
+
+    #[test]
+    /// Details and reasons read back as they were set.
+    fn test_response_helpers() {
+        let component = Uuid::new_v4();
+        let response = ControlResponse::duplicate(None)
+            .with_component(component)
+            .with_detail("queued_actions", 0);
+        assert_eq!(response.component, component);
+        assert_eq!(response.detail::<usize>("queued_actions"), Some(0));
+        assert_eq!(response.detail::<String>("queued_actions"), None);
+        assert_eq!((response.is_accepted(), response.reason()), (false, None));
+        assert_eq!(ControlResponse::rejected(None, "drifted").reason(), Some("drifted"));
+    }
+}
@@ -0,0 +1,48 @@
+use crate::utils::location::CropBed;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Trigger event published by the camera array for each frame a camera
+/// captures, the lights are strobed around it.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriggerMessage {
+    /// When the camera was triggered.
+    pub triggered_at: DateTime<Utc>,
+    /// Bed position of the camera that was triggered.
+    pub cam_id: u8,
+    /// Crop bed the camera is on.
+    pub crop_bed_id: CropBed,
+}
+
+impl TriggerMessage {
+    /// Trigger event of a camera.
+    ///
+    /// * `triggered_at`: when the camera was triggered.
+    /// * `cam_id`: bed position of the camera.
+    /// * `crop_bed_id`: crop bed the camera is on.
+    pub fn new(triggered_at: DateTime<Utc>, cam_id: u8, crop_bed_id: CropBed) -> Self {
+        Self {
+            triggered_at,
+            cam_id,
+            crop_bed_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Trigger events survive a round trip through json, and crop beds are
+    /// read by name or legacy id.
+    fn test_trigger_message_round_trip() {
+        let message = TriggerMessage::new(Utc::now(), 3, CropBed::Centre);
+        let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(serde_json::from_str::<TriggerMessage>(&json).unwrap(), message);
+
+        let legacy: TriggerMessage =
+            serde_json::from_str(r#"{"triggered_at": "2024-03-01T10:00:00Z", "cam_id": 1, "crop_bed_id": 2}"#).unwrap();
+        assert_eq!(legacy.crop_bed_id, CropBed::RightBoom);
+    }
+}
@@ -1,9 +1,35 @@
-use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use crate::{
+    messages::control::response::{ControlResponse, ResponseStatus, MALFORMED_REASON},
+    utils::location::{CropBed, GeoPosition},
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeSet;
+use strum_macros::IntoStaticStr;
+use uuid::Uuid;
+
+/// Duty cycle in percent sprayed at when a message does not set one.
+pub const FULL_INTENSITY: u8 = 100;
+
+/// Newest weed message version this build reads.
+pub const WEED_MESSAGE_VERSION: u8 = 2;
+
+/// Fields only sent in version 2 weed messages, a message without a
+/// `version` carrying any of them is read as version 2.
+pub const WEED_MESSAGE_V2_FIELDS: [&str; 6] = [
+    "message_id",
+    "intensity",
+    "assumed_speed_mps",
+    "latitude",
+    "longitude",
+    "heading_deg",
+];
 
 /// Weed message to be generated by the AI system and
-/// ingested by control system.
-#[derive(Deserialize, Debug, PartialEq)]
+/// ingested by control system. Every version sent is read into this one
+/// shape, which the queueing code works on, see [`VersionedWeedMessage`].
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(from = "VersionedWeedMessage")]
 pub struct WeedMessage {
     /// Channels to open to spray the weed.
     pub channels_to_open: Vec<u8>,
@@ -19,10 +45,566 @@ pub struct WeedMessage {
     pub time_diff_capture_to_start_spray_no_offset: f64,
     /// Distance from the weed to the solenoid at the read of the machine.
     pub distance_to_solenoid_mm: f64,
+    /// Ground speed the spray times were computed for, they are shifted
+    /// when the measured speed differs. Times are used as sent when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assumed_speed_mps: Option<f64>,
+    /// Identifier echoed in the response so the AI system can match it to
+    /// the message, older senders leave it out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    /// Duty cycle in percent to spray at, to modulate the dose by weed
+    /// size. Sprays fully on when not set, values over 100 are rejected.
+    pub intensity: u8,
+    /// Latitude of the machine when the weed was captured in decimal
+    /// degrees, for treatment maps. Older senders leave the position out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    /// Longitude of the machine when the weed was captured in decimal
+    /// degrees.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+    /// Heading of the machine when the weed was captured in degrees
+    /// clockwise from true north.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heading_deg: Option<f64>,
     /// Which camera has generated this message.
     cam_id: u8,
     /// Which crop bed this message is directed to.
-    crop_bed_id: u8,
+    crop_bed_id: CropBed,
+}
+
+impl WeedMessage {
+    /// Weed message spraying no channels between two times, captured and
+    /// created now. Build it up with the setters before sending it.
+    ///
+    /// * `crop_bed_id`: crop bed the message is directed to.
+    /// * `cam_id`: camera that generated the message.
+    /// * `start_spray_time`: time to start spraying.
+    /// * `end_spray_time`: time to stop spraying.
+    pub fn new(
+        crop_bed_id: impl Into<CropBed>,
+        cam_id: u8,
+        start_spray_time: DateTime<Utc>,
+        end_spray_time: DateTime<Utc>,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            channels_to_open: Vec::new(),
+            start_spray_time,
+            end_spray_time,
+            message_created_at: now,
+            capture_time: now,
+            time_diff_capture_to_start_spray_no_offset: 0.0,
+            distance_to_solenoid_mm: 0.0,
+            assumed_speed_mps: None,
+            message_id: None,
+            intensity: FULL_INTENSITY,
+            latitude: None,
+            longitude: None,
+            heading_deg: None,
+            cam_id,
+            crop_bed_id: crop_bed_id.into(),
+        }
+    }
+
+    /// Set the channels to open.
+    ///
+    /// * `channels`: zero based channels.
+    pub fn channels(mut self, channels: impl Into<Vec<u8>>) -> Self {
+        self.channels_to_open = channels.into();
+        self
+    }
+
+    /// Set when the image of the weed was captured.
+    ///
+    /// * `capture_time`: UTC time of the capture.
+    pub fn captured_at(mut self, capture_time: DateTime<Utc>) -> Self {
+        self.capture_time = capture_time;
+        self
+    }
+
+    /// Set the distance from the weed to the solenoid.
+    ///
+    /// * `distance_to_solenoid_mm`: distance in millimetres.
+    pub fn distance_to_solenoid(mut self, distance_to_solenoid_mm: f64) -> Self {
+        self.distance_to_solenoid_mm = distance_to_solenoid_mm;
+        self
+    }
+
+    /// Set the ground speed the spray times were computed for.
+    ///
+    /// * `assumed_speed_mps`: speed in metres per second.
+    pub fn assumed_speed(mut self, assumed_speed_mps: f64) -> Self {
+        self.assumed_speed_mps = Some(assumed_speed_mps);
+        self
+    }
+
+    /// Set the identifier echoed in the response.
+    ///
+    /// * `message_id`: identifier of the message.
+    pub fn message_id(mut self, message_id: impl Into<String>) -> Self {
+        self.message_id = Some(message_id.into());
+        self
+    }
+
+    /// Set the duty cycle to spray at, panics over 100 percent as the
+    /// message would be rejected.
+    ///
+    /// * `intensity`: duty cycle in percent.
+    pub fn intensity(mut self, intensity: u8) -> Self {
+        assert!(intensity <= FULL_INTENSITY, "Intensity {intensity} is outside 0 to {FULL_INTENSITY}");
+        self.intensity = intensity;
+        self
+    }
+
+    /// Set where the machine was when the weed was captured.
+    ///
+    /// * `position`: latitude, longitude and heading if known.
+    pub fn positioned_at(mut self, position: GeoPosition) -> Self {
+        self.latitude = Some(position.latitude);
+        self.longitude = Some(position.longitude);
+        self.heading_deg = position.heading_deg;
+        self
+    }
+
+    /// Where the machine was when the weed was captured, `None` unless
+    /// both the latitude and longitude are set.
+    pub fn position(&self) -> Option<GeoPosition> {
+        Some(GeoPosition {
+            latitude: self.latitude?,
+            longitude: self.longitude?,
+            heading_deg: self.heading_deg,
+        })
+    }
+
+    /// Check the message makes sense before anything is queued from it,
+    /// returning the first problem found.
+    ///
+    /// * `constraints`: limits of the component the message is sent to.
+    pub fn validate(&self, constraints: &MessageConstraints) -> Result<(), ValidationError> {
+        if self.end_spray_time < self.start_spray_time {
+            return Err(ValidationError::TimeOrder);
+        }
+        if self.channels_to_open.is_empty() {
+            return Err(ValidationError::EmptyChannels);
+        }
+        if let Some(channel) = self
+            .channels_to_open
+            .iter()
+            .find(|channel| !constraints.channels.contains(channel))
+        {
+            return Err(ValidationError::ChannelOutOfRange { channel: *channel });
+        }
+        let duration = self.end_spray_time - self.start_spray_time;
+        if let Some(max_spray_duration) = constraints.max_spray_duration {
+            if duration > max_spray_duration {
+                return Err(ValidationError::DurationTooLong {
+                    duration_ms: duration.num_milliseconds(),
+                });
+            }
+        }
+        let lead = self.start_spray_time - Utc::now();
+        if lead > constraints.max_lead {
+            return Err(ValidationError::TooFarInFuture {
+                lead_ms: lead.num_milliseconds(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Camera that generated the message.
+    pub fn cam_id(&self) -> u8 {
+        self.cam_id
+    }
+
+    /// Crop bed the message is directed to.
+    pub fn crop_bed_id(&self) -> CropBed {
+        self.crop_bed_id
+    }
+}
+
+/// Intensity of messages that do not set one.
+pub(crate) fn full_intensity() -> u8 {
+    FULL_INTENSITY
+}
+
+/// Read an intensity, rejecting duty cycles over 100 percent so the message
+/// is reported as malformed rather than clipped.
+///
+/// * `deserializer`: deserializer of the message.
+pub(crate) fn deserialize_intensity<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    let intensity = u8::deserialize(deserializer)?;
+    if intensity > FULL_INTENSITY {
+        return Err(serde::de::Error::custom(format!(
+            "intensity {intensity} is outside 0 to {FULL_INTENSITY}"
+        )));
+    }
+    Ok(intensity)
+}
+
+/// Weed message as first sent by the AI system, before it could be
+/// correlated, dosed or positioned.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct WeedMessageV1 {
+    /// Channels to open to spray the weed.
+    pub channels_to_open: Vec<u8>,
+    /// UTC time set to start spraying.
+    pub start_spray_time: DateTime<Utc>,
+    /// UTC time set to stop spraying.
+    pub end_spray_time: DateTime<Utc>,
+    /// UTC time that the message was created.
+    pub message_created_at: DateTime<Utc>,
+    /// UTC time stamp of when the image was captured.
+    pub capture_time: DateTime<Utc>,
+    /// Offset to account for time for the spray to hit the weed from height,
+    /// left out by the senders in the field.
+    #[serde(default)]
+    pub time_diff_capture_to_start_spray_no_offset: f64,
+    /// Distance from the weed to the solenoid at the rear of the machine.
+    pub distance_to_solenoid_mm: f64,
+    /// Which camera has generated this message.
+    pub cam_id: u8,
+    /// Which crop bed this message is directed to.
+    pub crop_bed_id: CropBed,
+}
+
+/// Weed message adding an identifier, a dose, the ground speed its times
+/// were computed for and the position of the machine to version 1.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct WeedMessageV2 {
+    /// Fields carried over from version 1.
+    #[serde(flatten)]
+    pub base: WeedMessageV1,
+    /// Ground speed the spray times were computed for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assumed_speed_mps: Option<f64>,
+    /// Identifier echoed in the response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    /// Duty cycle in percent to spray at, fully on when not set.
+    #[serde(default = "full_intensity", deserialize_with = "deserialize_intensity")]
+    pub intensity: u8,
+    /// Latitude of the machine in decimal degrees.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    /// Longitude of the machine in decimal degrees.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+    /// Heading of the machine in degrees clockwise from true north.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heading_deg: Option<f64>,
+}
+
+/// Weed message of any version this build reads. The version is taken
+/// from the `version` field when sent, otherwise from the fields present,
+/// and versions newer than [`WEED_MESSAGE_VERSION`] are refused rather
+/// than read with fields missing.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum VersionedWeedMessage {
+    /// Message of the first AI systems.
+    V1(WeedMessageV1),
+    /// Message with an identifier, dose, assumed speed and position.
+    V2(WeedMessageV2),
+}
+
+impl VersionedWeedMessage {
+    /// Version of the message.
+    pub fn version(&self) -> u8 {
+        match self {
+            Self::V1(_) => 1,
+            Self::V2(_) => 2,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for VersionedWeedMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let version = match value.get("version") {
+            None | Some(serde_json::Value::Null) => {
+                if WEED_MESSAGE_V2_FIELDS.iter().any(|field| value.get(field).is_some()) {
+                    2
+                } else {
+                    1
+                }
+            }
+            Some(version) => version.as_u64().ok_or_else(|| {
+                serde::de::Error::custom(format!("weed message version {version} is not a whole number"))
+            })?,
+        };
+        match version {
+            1 => serde_json::from_value(value).map(Self::V1),
+            2 => serde_json::from_value(value).map(Self::V2),
+            _ => {
+                return Err(serde::de::Error::custom(format!(
+                    "weed message version {version} is not supported, this component reads versions 1 to \
+                     {WEED_MESSAGE_VERSION}"
+                )))
+            }
+        }
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+impl From<WeedMessageV1> for WeedMessage {
+    fn from(message: WeedMessageV1) -> Self {
+        Self {
+            channels_to_open: message.channels_to_open,
+            start_spray_time: message.start_spray_time,
+            end_spray_time: message.end_spray_time,
+            message_created_at: message.message_created_at,
+            capture_time: message.capture_time,
+            time_diff_capture_to_start_spray_no_offset: message.time_diff_capture_to_start_spray_no_offset,
+            distance_to_solenoid_mm: message.distance_to_solenoid_mm,
+            assumed_speed_mps: None,
+            message_id: None,
+            intensity: FULL_INTENSITY,
+            latitude: None,
+            longitude: None,
+            heading_deg: None,
+            cam_id: message.cam_id,
+            crop_bed_id: message.crop_bed_id,
+        }
+    }
+}
+
+impl From<WeedMessageV2> for WeedMessage {
+    fn from(message: WeedMessageV2) -> Self {
+        Self {
+            assumed_speed_mps: message.assumed_speed_mps,
+            message_id: message.message_id,
+            intensity: message.intensity,
+            latitude: message.latitude,
+            longitude: message.longitude,
+            heading_deg: message.heading_deg,
+            ..Self::from(message.base)
+        }
+    }
+}
+
+impl From<VersionedWeedMessage> for WeedMessage {
+    fn from(message: VersionedWeedMessage) -> Self {
+        match message {
+            VersionedWeedMessage::V1(message) => message.into(),
+            VersionedWeedMessage::V2(message) => message.into(),
+        }
+    }
+}
+
+/// Limits a weed message is checked against before it is queued.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageConstraints {
+    /// Zero based channels a message may open, those wired to a PDM.
+    pub channels: BTreeSet<u8>,
+    /// Longest spray accepted, longer sprays are clamped by the component
+    /// rather than refused when not set.
+    pub max_spray_duration: Option<Duration>,
+    /// Furthest ahead of now a spray may start.
+    pub max_lead: Duration,
+}
+
+/// Why a weed message was refused before it was queued.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ValidationError {
+    /// The spray ends before it starts.
+    TimeOrder,
+    /// The message opens no channels.
+    EmptyChannels,
+    /// A channel is not wired to a PDM.
+    ChannelOutOfRange {
+        /// Zero based channel of the message.
+        channel: u8,
+    },
+    /// The spray is longer than the maximum spray duration.
+    DurationTooLong {
+        /// Duration of the spray in milliseconds.
+        duration_ms: i64,
+    },
+    /// The spray starts further ahead than any weed in view of a camera.
+    TooFarInFuture {
+        /// Time in milliseconds from now to the start of the spray.
+        lead_ms: i64,
+    },
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TimeOrder => write!(f, "the spray ends before it starts"),
+            Self::EmptyChannels => write!(f, "no channels to open"),
+            Self::ChannelOutOfRange { channel } => write!(f, "channel {channel} is not wired to a PDM"),
+            Self::DurationTooLong { duration_ms } => {
+                write!(f, "the spray of {duration_ms}ms is longer than the maximum")
+            }
+            Self::TooFarInFuture { lead_ms } => write!(f, "the spray starts {lead_ms}ms from now"),
+        }
+    }
+}
+
+/// Count of weed messages refused for each reason.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValidationCounts {
+    /// Sprays ending before they start.
+    pub time_order: u64,
+    /// Messages opening no channels.
+    pub empty_channels: u64,
+    /// Messages with a channel not wired to a PDM.
+    pub channel_out_of_range: u64,
+    /// Sprays longer than the maximum.
+    pub duration_too_long: u64,
+    /// Sprays starting too far ahead.
+    pub too_far_in_future: u64,
+}
+
+impl ValidationCounts {
+    /// Count a refused message.
+    ///
+    /// * `error`: why it was refused.
+    pub fn record(&mut self, error: ValidationError) {
+        let count = match error {
+            ValidationError::TimeOrder => &mut self.time_order,
+            ValidationError::EmptyChannels => &mut self.empty_channels,
+            ValidationError::ChannelOutOfRange { .. } => &mut self.channel_out_of_range,
+            ValidationError::DurationTooLong { .. } => &mut self.duration_too_long,
+            ValidationError::TooFarInFuture { .. } => &mut self.too_far_in_future,
+        };
+        *count += 1;
+    }
+}
+
+/// What the control system did with a message sent on the weed message
+/// socket.
+#[derive(Serialize, Deserialize, IntoStaticStr, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum WeedMessageStatus {
+    /// The message was queued, or the control message acted on.
+    Accepted,
+    /// The spray was due to start before the message arrived, so it was
+    /// dropped.
+    Late,
+    /// The message could not be parsed.
+    Malformed,
+    /// The message was understood but refused, e.g. a manual spray while
+    /// manual control is interlocked.
+    Rejected,
+    /// The message was queued with its spray cut to the maximum duration.
+    Truncated,
+    /// The message was dropped as a retry of one already received.
+    Duplicate,
+}
+
+impl WeedMessageStatus {
+    /// What was done with a message, from the response written back for
+    /// it, e.g. to compare a replay with the journal.
+    ///
+    /// * `response`: response to the message.
+    pub fn of(response: &ControlResponse) -> Self {
+        match &response.status {
+            ResponseStatus::Accepted if response.detail::<bool>("truncated") == Some(true) => Self::Truncated,
+            ResponseStatus::Accepted => Self::Accepted,
+            ResponseStatus::Rejected { reason } if reason == MALFORMED_REASON => Self::Malformed,
+            ResponseStatus::Rejected { .. } => Self::Rejected,
+            ResponseStatus::Duplicate => Self::Duplicate,
+            ResponseStatus::Late => Self::Late,
+        }
+    }
+}
+
+/// One line of json written back on the socket for each message received.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WeedMessageResponse {
+    /// What was done with the message.
+    pub status: WeedMessageStatus,
+    /// Identifier sent with the message, if any.
+    pub message_id: Option<String>,
+    /// Number of on and off actions added to the queue.
+    pub queued_actions: usize,
+    /// Why the message was refused, for a message failing validation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejection: Option<ValidationError>,
+    /// Why the message was refused, for a refusal other than validation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl WeedMessageResponse {
+    /// Response to a message.
+    ///
+    /// * `status`: what was done with the message.
+    /// * `message_id`: identifier sent with the message.
+    /// * `queued_actions`: actions added to the queue.
+    pub fn new(status: WeedMessageStatus, message_id: Option<String>, queued_actions: usize) -> Self {
+        Self {
+            status,
+            message_id,
+            queued_actions,
+            rejection: None,
+            reason: None,
+        }
+    }
+
+    /// Response to a message refused for a reason other than validation.
+    ///
+    /// * `message_id`: identifier sent with the message.
+    /// * `reason`: why it was refused.
+    pub fn refused(message_id: Option<String>, reason: impl Into<String>) -> Self {
+        Self {
+            reason: Some(reason.into()),
+            ..Self::new(WeedMessageStatus::Rejected, message_id, 0)
+        }
+    }
+
+    /// The response as written back to the sender.
+    ///
+    /// * `component`: uuid of the component responding.
+    pub fn to_control(&self, component: Uuid) -> ControlResponse {
+        let message_id = self.message_id.clone();
+        let response = match self.status {
+            WeedMessageStatus::Accepted => ControlResponse::accepted(message_id),
+            WeedMessageStatus::Truncated => ControlResponse::accepted(message_id).with_detail("truncated", true),
+            WeedMessageStatus::Late => ControlResponse::late(message_id),
+            WeedMessageStatus::Duplicate => ControlResponse::duplicate(message_id),
+            WeedMessageStatus::Malformed => ControlResponse::rejected(message_id, MALFORMED_REASON),
+            WeedMessageStatus::Rejected => match (self.rejection, &self.reason) {
+                (Some(rejection), _) => {
+                    ControlResponse::rejected(message_id, rejection.to_string()).with_detail("rejection", rejection)
+                }
+                (None, Some(reason)) => ControlResponse::rejected(message_id, reason.as_str()),
+                (None, None) => ControlResponse::rejected(message_id, "refused"),
+            },
+        };
+        response
+            .with_component(component)
+            .with_detail("queued_actions", self.queued_actions)
+    }
+
+    /// Response to a message refused by validation.
+    ///
+    /// * `message_id`: identifier sent with the message.
+    /// * `rejection`: why it was refused.
+    pub fn invalid(message_id: Option<String>, rejection: ValidationError) -> Self {
+        Self {
+            rejection: Some(rejection),
+            ..Self::new(WeedMessageStatus::Rejected, message_id, 0)
+        }
+    }
+
+    /// Response to data that is not a valid message, echoing the message
+    /// id if the data is json carrying one, or the id of its envelope.
+    ///
+    /// * `data`: bytes read from the socket.
+    pub fn malformed(data: &[u8]) -> Self {
+        let message_id = serde_json::from_slice::<serde_json::Value>(data).ok().and_then(|value| {
+            let payload = value.get("payload").unwrap_or(&value);
+            let id = payload.get("message_id").or_else(|| value.get("id"))?;
+            id.as_str().map(String::from)
+        });
+        Self::new(WeedMessageStatus::Malformed, message_id, 0)
+    }
 }
 
 #[cfg(test)]
@@ -144,8 +726,92 @@ mod tests {
                     "capture_time": "2023-07-30 04:05:48.408300000 UTC",
                     "cam_id": 4, "crop_bed_id": 2}"#
     )]
+    /// Payloads captured from the AI systems in the field are read as
+    /// version 1, spraying fully on with no identifier or position.
     fn test_parse_weed_message(#[case] raw_string: &str) {
-        let _parsed: WeedMessage = serde_json::from_str(raw_string).unwrap();
+        let parsed: WeedMessage = serde_json::from_str(raw_string).unwrap();
+        let versioned: VersionedWeedMessage = serde_json::from_str(raw_string).unwrap();
+        assert_eq!(versioned.version(), 1);
+        assert_eq!(WeedMessage::from(versioned), parsed);
+        assert_eq!((parsed.intensity, parsed.message_id, parsed.position()), (FULL_INTENSITY, None, None));
+    }
+
+    /// Payload captured in the field from a version 1 sender.
+    fn v1_payload() -> serde_json::Value {
+        serde_json::json!({
+            "channels_to_open": [7, 8, 9],
+            "start_spray_time": "2023-07-30 04:11:27.481525000 UTC",
+            "end_spray_time": "2023-07-30 04:11:27.572274000 UTC",
+            "message_created_at": "2023-07-30 04:11:27.572274000 UTC",
+            "distance_to_solenoid_mm": 541.74,
+            "capture_time": "2023-07-30 04:11:27.237741000 UTC",
+            "cam_id": 5, "crop_bed_id": 2})
+    }
+
+    #[rstest]
+    #[case::bare(None, None, 1)]
+    #[case::shaped_v2(None, Some(("message_id", serde_json::json!("cam5-1"))), 2)]
+    #[case::dosed(None, Some(("intensity", serde_json::json!(40))), 2)]
+    #[case::null_version(Some(serde_json::Value::Null), Some(("latitude", serde_json::json!(-27.5))), 2)]
+    #[case::explicit_v1(Some(serde_json::json!(1)), Some(("message_id", serde_json::json!("cam5-1"))), 1)]
+    #[case::explicit_v2(Some(serde_json::json!(2)), None, 2)]
+    /// The version is read from the message when sent, otherwise from the
+    /// fields it carries, and an explicit version 1 ignores newer fields.
+    fn test_detect_version(
+        #[case] version: Option<serde_json::Value>,
+        #[case] field: Option<(&str, serde_json::Value)>,
+        #[case] expected: u8,
+    ) {
+        let sends_id = field.as_ref().is_some_and(|(key, _)| *key == "message_id");
+        let mut payload = v1_payload();
+        if let Some(version) = version {
+            payload["version"] = version;
+        }
+        if let Some((key, value)) = field {
+            payload[key] = value;
+        }
+        let versioned: VersionedWeedMessage = serde_json::from_value(payload.clone()).unwrap();
+        assert_eq!(versioned.version(), expected);
+        let message: WeedMessage = serde_json::from_value(payload).unwrap();
+        assert_eq!(message, WeedMessage::from(versioned));
+        assert_eq!(message.channels_to_open, vec![7, 8, 9]);
+        assert_eq!(message.message_id.is_some(), expected == 2 && sends_id);
+    }
+
+    #[test]
+    /// A version 2 message keeps every field it adds once normalised, and
+    /// is written in a shape read back as version 2.
+    fn test_convert_v2() {
+        let mut payload = v1_payload();
+        payload["version"] = serde_json::json!(2);
+        payload["message_id"] = serde_json::json!("cam5-88");
+        payload["intensity"] = serde_json::json!(70);
+        payload["assumed_speed_mps"] = serde_json::json!(1.5);
+        payload["latitude"] = serde_json::json!(-27.4698);
+        payload["longitude"] = serde_json::json!(153.0251);
+        let message: WeedMessage = serde_json::from_value(payload).unwrap();
+        assert_eq!((message.message_id.as_deref(), message.intensity), (Some("cam5-88"), 70));
+        assert_eq!(message.assumed_speed_mps, Some(1.5));
+        assert_eq!(message.position(), Some(GeoPosition::new(-27.4698, 153.0251)));
+        assert_eq!((message.cam_id(), message.crop_bed_id()), (5, CropBed::RightBoom));
+
+        let written = serde_json::to_value(&message).unwrap();
+        let versioned: VersionedWeedMessage = serde_json::from_value(written).unwrap();
+        assert_eq!(versioned.version(), 2);
+        assert_eq!(WeedMessage::from(versioned), message);
+    }
+
+    #[rstest]
+    #[case::future(serde_json::json!(3), "weed message version 3 is not supported")]
+    #[case::zero(serde_json::json!(0), "weed message version 0 is not supported")]
+    #[case::text(serde_json::json!("two"), "is not a whole number")]
+    /// Versions this build does not know are refused with an error naming
+    /// the version, rather than read with fields missing.
+    fn test_unknown_version(#[case] version: serde_json::Value, #[case] expected: &str) {
+        let mut payload = v1_payload();
+        payload["version"] = version;
+        let error = serde_json::from_value::<WeedMessage>(payload).unwrap_err();
+        assert!(error.to_string().contains(expected), "Unhelpful error {error}");
     }
 
     #[rstest]
@@ -159,15 +825,20 @@ mod tests {
                "cam_id": 5, "crop_bed_id": 2}"#
     , WeedMessage {
             cam_id: 5,
-            crop_bed_id: 2,
+            crop_bed_id: CropBed::RightBoom,
             channels_to_open: vec![7, 8, 9],
             start_spray_time: "2023-07-30 04:11:27.481525000 UTC".parse().unwrap(),
             capture_time: "2023-07-30 04:11:27.237741000 UTC".parse().unwrap(),
             end_spray_time: "2023-07-30 04:11:27.572274000 UTC".parse().unwrap(),
             message_created_at: "2023-07-30 04:11:27.572274000 UTC".parse().unwrap(),
-            time_diff_capture_to_start_spray_no_offset: "2023-07-30 04:05:48.711152000 UTC".parse().unwrap(),
-            distance_to_solenoid_mm: 541.74
-
+            time_diff_capture_to_start_spray_no_offset: 0.0,
+            distance_to_solenoid_mm: 541.74,
+            assumed_speed_mps: None,
+            message_id: None,
+            intensity: FULL_INTENSITY,
+            latitude: None,
+            longitude: None,
+            heading_deg: None,
         }))]
     #[case((
         r#"{"channels_to_open": [0],
@@ -179,18 +850,271 @@ mod tests {
                 "cam_id": 4, "crop_bed_id": 2}"#
     , WeedMessage{
             cam_id: 4,
-            crop_bed_id: 2,
+            crop_bed_id: CropBed::RightBoom,
             channels_to_open: vec![0],
             start_spray_time: "2023-07-30 04:05:48.614496000 UTC".parse().unwrap(),
             capture_time: "2023-07-30 04:05:48.408300000 UTC".parse().unwrap(),
             end_spray_time: "2023-07-30 04:05:48.711152000 UTC".parse().unwrap(),
             message_created_at: "2023-07-30 04:05:48.711152000 UTC".parse().unwrap(),
-            time_diff_capture_to_start_spray_no_offset: "2023-07-30 04:05:48.711152000 UTC".parse().unwrap(),
-            distance_to_solenoid_mm: 458.21
+            time_diff_capture_to_start_spray_no_offset: 0.0,
+            distance_to_solenoid_mm: 458.21,
+            assumed_speed_mps: None,
+            message_id: None,
+            intensity: FULL_INTENSITY,
+            latitude: None,
+            longitude: None,
+            heading_deg: None,
         } ))]
     fn test_parse_and_compare_weed_message(#[case] args: (&str, WeedMessage)) {
         let parsed: WeedMessage = serde_json::from_str(args.0).unwrap();
 
         assert_eq!(parsed, args.1, "Failed to parse message correctly");
     }
+
+    #[test]
+    fn test_parse_assumed_speed() {
+        let parsed: WeedMessage = serde_json::from_str(
+            r#"{"channels_to_open": [3],
+                "start_spray_time": "2023-07-30 04:05:48.495824000 UTC",
+                "end_spray_time": "2023-07-30 04:05:48.783107000 UTC",
+                "message_created_at": "2023-07-30 04:11:27.572274000 UTC",
+                "time_diff_capture_to_start_spray_no_offset": 0.0,
+                "distance_to_solenoid_mm": 194.5,
+                "capture_time": "2023-07-30 04:05:48.408300000 UTC",
+                "assumed_speed_mps": 1.25,
+                "cam_id": 4, "crop_bed_id": 2}"#,
+        )
+        .unwrap();
+        assert_eq!(parsed.assumed_speed_mps, Some(1.25));
+        assert_eq!(parsed.intensity, FULL_INTENSITY);
+        assert_eq!(parsed.position(), None);
+    }
+
+    #[test]
+    /// The position is read when the AI system sends one, left out when
+    /// written without one, and incomplete positions are not used.
+    fn test_parse_position() {
+        let mut message = serde_json::json!({
+            "channels_to_open": [3],
+            "start_spray_time": "2023-07-30 04:05:48.495824000 UTC",
+            "end_spray_time": "2023-07-30 04:05:48.783107000 UTC",
+            "message_created_at": "2023-07-30 04:11:27.572274000 UTC",
+            "time_diff_capture_to_start_spray_no_offset": 0.0,
+            "distance_to_solenoid_mm": 194.5,
+            "capture_time": "2023-07-30 04:05:48.408300000 UTC",
+            "latitude": -27.4698,
+            "longitude": 153.0251,
+            "heading_deg": 92.5,
+            "cam_id": 4, "crop_bed_id": 2});
+        let parsed: WeedMessage = serde_json::from_value(message.clone()).unwrap();
+        assert_eq!(
+            parsed.position(),
+            Some(GeoPosition::new(-27.4698, 153.0251).with_heading(92.5))
+        );
+        let written = serde_json::to_value(&parsed).unwrap();
+        assert_eq!(serde_json::from_value::<WeedMessage>(written).unwrap(), parsed);
+
+        message.as_object_mut().unwrap().remove("longitude");
+        let parsed: WeedMessage = serde_json::from_value(message).unwrap();
+        assert_eq!((parsed.latitude, parsed.position()), (Some(-27.4698), None));
+
+        let now = Utc::now();
+        let unpositioned = serde_json::to_string(&WeedMessage::new(CropBed::Centre, 1, now, now)).unwrap();
+        assert!(!unpositioned.contains("latitude"), "{unpositioned}");
+        let positioned = WeedMessage::new(CropBed::Centre, 1, now, now).positioned_at(GeoPosition::new(1.5, 2.5));
+        assert_eq!((positioned.heading_deg, positioned.position()), (None, Some(GeoPosition::new(1.5, 2.5))));
+    }
+
+    #[rstest]
+    #[case::off(Some(0), Some(0))]
+    #[case::half(Some(50), Some(50))]
+    #[case::full(Some(100), Some(100))]
+    #[case::missing(None, Some(FULL_INTENSITY))]
+    #[case::over(Some(101), None)]
+    #[case::negative(Some(-1), None)]
+    /// Intensities default to fully on and are checked to be a duty cycle
+    /// when parsed.
+    fn test_parse_intensity(#[case] intensity: Option<i32>, #[case] expected: Option<u8>) {
+        let mut message = serde_json::json!({
+            "channels_to_open": [3],
+            "start_spray_time": "2023-07-30 04:05:48.495824000 UTC",
+            "end_spray_time": "2023-07-30 04:05:48.783107000 UTC",
+            "message_created_at": "2023-07-30 04:11:27.572274000 UTC",
+            "time_diff_capture_to_start_spray_no_offset": 0.0,
+            "distance_to_solenoid_mm": 194.5,
+            "capture_time": "2023-07-30 04:05:48.408300000 UTC",
+            "cam_id": 4,
+            "crop_bed_id": 2,
+        });
+        if let Some(intensity) = intensity {
+            message["intensity"] = serde_json::json!(intensity);
+        }
+        let parsed = serde_json::from_value::<WeedMessage>(message);
+        assert_eq!(parsed.ok().map(|parsed| parsed.intensity), expected);
+    }
+
+    #[test]
+    /// Messages built by tools survive a round trip through json, leaving
+    /// out the optional fields they do not set.
+    fn test_weed_message_round_trip() {
+        let start_spray_time = Utc::now();
+        let end_spray_time = start_spray_time + chrono::Duration::milliseconds(80);
+        let message = WeedMessage::new(CropBed::Centre, 4, start_spray_time, end_spray_time)
+            .channels([3, 4])
+            .distance_to_solenoid(194.5)
+            .intensity(60)
+            .message_id("cam4-7");
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["channels_to_open"], serde_json::json!([3, 4]));
+        assert!(json.get("assumed_speed_mps").is_none());
+        assert_eq!(serde_json::from_value::<WeedMessage>(json).unwrap(), message);
+
+        let message = message.assumed_speed(1.25);
+        let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(serde_json::from_str::<WeedMessage>(&json).unwrap(), message);
+        assert_eq!((message.cam_id(), message.crop_bed_id()), (4, CropBed::Centre));
+    }
+
+    /// Constraints of a crop bed of 24 channels rejecting sprays over a
+    /// second.
+    fn constraints() -> MessageConstraints {
+        MessageConstraints {
+            channels: (0..24).collect(),
+            max_spray_duration: Some(Duration::seconds(1)),
+            max_lead: Duration::seconds(10),
+        }
+    }
+
+    #[rstest]
+    #[case::good(0, 100, &[0, 23], Ok(()))]
+    #[case::end_before_start(0, -10, &[3], Err(ValidationError::TimeOrder))]
+    #[case::no_channels(0, 100, &[], Err(ValidationError::EmptyChannels))]
+    #[case::channel_24(0, 100, &[3, 24], Err(ValidationError::ChannelOutOfRange { channel: 24 }))]
+    #[case::too_long(0, 1500, &[3], Err(ValidationError::DurationTooLong { duration_ms: 1500 }))]
+    /// Messages are refused for the first problem found.
+    fn test_validate_weed_message(
+        #[case] start_ms: i64,
+        #[case] duration_ms: i64,
+        #[case] channels: &[u8],
+        #[case] expected: Result<(), ValidationError>,
+    ) {
+        let start_spray_time = Utc::now() + Duration::milliseconds(start_ms);
+        let message = WeedMessage::new(0, 4, start_spray_time, start_spray_time + Duration::milliseconds(duration_ms))
+            .channels(channels);
+        assert_eq!(message.validate(&constraints()), expected);
+    }
+
+    #[test]
+    /// Sprays starting further ahead than the lead are refused, and long
+    /// sprays pass when the component clamps them.
+    fn test_validate_lead_and_clamped_duration() {
+        let start_spray_time = Utc::now() + Duration::minutes(5);
+        let message = WeedMessage::new(0, 4, start_spray_time, start_spray_time + Duration::seconds(5)).channels([1]);
+        assert!(matches!(
+            message.validate(&constraints()),
+            Err(ValidationError::DurationTooLong { .. })
+        ));
+        let clamped = MessageConstraints {
+            max_spray_duration: None,
+            ..constraints()
+        };
+        let Err(ValidationError::TooFarInFuture { lead_ms }) = message.validate(&clamped) else {
+            panic!("Message five minutes ahead was accepted");
+        };
+        assert!(lead_ms > 290_000 && lead_ms <= 300_000, "Lead of {lead_ms}ms");
+    }
+
+    #[test]
+    /// Refusals are counted by reason and reported in the response.
+    fn test_validation_counts_and_response() {
+        let mut counts = ValidationCounts::default();
+        counts.record(ValidationError::EmptyChannels);
+        counts.record(ValidationError::ChannelOutOfRange { channel: 30 });
+        counts.record(ValidationError::ChannelOutOfRange { channel: 31 });
+        assert_eq!((counts.empty_channels, counts.channel_out_of_range, counts.time_order), (1, 2, 0));
+
+        let response = WeedMessageResponse::invalid(None, ValidationError::ChannelOutOfRange { channel: 30 });
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"status":"rejected","message_id":null,"queued_actions":0,"rejection":{"reason":"channel_out_of_range","channel":30}}"#
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "outside 0 to 100")]
+    fn test_build_out_of_range_intensity() {
+        let _ = WeedMessage::new(0, 0, Utc::now(), Utc::now()).intensity(101);
+    }
+
+    #[rstest]
+    #[case(
+        WeedMessageResponse::new(WeedMessageStatus::Accepted, Some(String::from("cam4-1182")), 2),
+        r#"{"status":"accepted","message_id":"cam4-1182","queued_actions":2}"#
+    )]
+    #[case(
+        WeedMessageResponse::new(WeedMessageStatus::Late, None, 0),
+        r#"{"status":"late","message_id":null,"queued_actions":0}"#
+    )]
+    #[case(
+        WeedMessageResponse::new(WeedMessageStatus::Truncated, None, 2),
+        r#"{"status":"truncated","message_id":null,"queued_actions":2}"#
+    )]
+    #[case(
+        WeedMessageResponse::new(WeedMessageStatus::Duplicate, Some(String::from("cam0-5")), 0),
+        r#"{"status":"duplicate","message_id":"cam0-5","queued_actions":0}"#
+    )]
+    fn test_serialise_response(#[case] response: WeedMessageResponse, #[case] expected: &str) {
+        assert_eq!(serde_json::to_string(&response).unwrap(), expected);
+    }
+
+    #[test]
+    /// Every outcome is written back as a control response, keeping the
+    /// reason a message was refused.
+    fn test_response_to_control() {
+        let component = Uuid::new_v4();
+        let id = Some(String::from("cam0-1"));
+        let response = WeedMessageResponse::new(WeedMessageStatus::Truncated, id.clone(), 2).to_control(component);
+        assert!(response.is_accepted());
+        assert_eq!((response.correlation_id.clone(), response.component), (id.clone(), component));
+        assert_eq!(response.detail::<usize>("queued_actions"), Some(2));
+        assert_eq!(response.detail::<bool>("truncated"), Some(true));
+
+        let rejection = ValidationError::ChannelOutOfRange { channel: 24 };
+        let response = WeedMessageResponse::invalid(id.clone(), rejection).to_control(component);
+        assert_eq!(response.reason(), Some("channel 24 is not wired to a PDM"));
+        assert_eq!(response.detail::<ValidationError>("rejection"), Some(rejection));
+        let response = WeedMessageResponse::refused(None, "manual sprays are not allowed").to_control(component);
+        assert_eq!(response.reason(), Some("manual sprays are not allowed"));
+        let response = WeedMessageResponse::malformed(b"not json").to_control(component);
+        assert_eq!(response.reason(), Some("malformed message"));
+        let response = WeedMessageResponse::new(WeedMessageStatus::Late, id, 0).to_control(component);
+        assert_eq!(response.status, ResponseStatus::Late);
+    }
+
+    #[rstest]
+    #[case(WeedMessageStatus::Accepted)]
+    #[case(WeedMessageStatus::Late)]
+    #[case(WeedMessageStatus::Malformed)]
+    #[case(WeedMessageStatus::Rejected)]
+    #[case(WeedMessageStatus::Truncated)]
+    #[case(WeedMessageStatus::Duplicate)]
+    /// The outcome is read back from the response written for it.
+    fn test_status_of_response(#[case] status: WeedMessageStatus) {
+        let response = WeedMessageResponse::new(status, None, 0).to_control(Uuid::nil());
+        assert_eq!(WeedMessageStatus::of(&response), status);
+    }
+
+    #[rstest]
+    #[case(br#"{"message_id": "cam4-1182", "channels_to_open": "seven"}"#, Some("cam4-1182"))]
+    #[case(
+        br#"{"id": "0b6c3c36-59a3-4c5e-9f2e-7d1c6a0e5b21", "payload": {"channels_to_open": "seven"}}"#,
+        Some("0b6c3c36-59a3-4c5e-9f2e-7d1c6a0e5b21")
+    )]
+    #[case(b"not json", None)]
+    /// The message id is echoed when the data is json carrying one.
+    fn test_malformed_response(#[case] data: &[u8], #[case] expected: Option<&str>) {
+        let response = WeedMessageResponse::malformed(data);
+        assert_eq!(response.status, WeedMessageStatus::Malformed);
+        assert_eq!(response.message_id.as_deref(), expected);
+    }
 }
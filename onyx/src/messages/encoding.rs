@@ -0,0 +1,290 @@
+use crate::utils::net::Framing;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// How messages and responses are written on a connection. Every encoding
+/// carries the same document as json, so a message has one schema
+/// whichever way it is sent.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// Json documents, one per line unless framed otherwise.
+    #[default]
+    Json,
+    /// CBOR documents, length prefixed.
+    Cbor,
+    /// MessagePack documents, length prefixed.
+    MessagePack,
+}
+
+/// Frame that could not be read as a document of its encoding.
+#[derive(Debug, thiserror::Error)]
+pub enum FrameError {
+    /// Not a json document, or not the message expected.
+    #[error("{0}")]
+    Json(serde_json::Error),
+    /// Not a CBOR document.
+    #[error("malformed CBOR frame, {0}")]
+    Cbor(String),
+    /// Not a MessagePack document.
+    #[error("malformed MessagePack frame, {0}")]
+    MessagePack(String),
+}
+
+/// Why a response could not be read off a connection to a component.
+#[derive(Debug, thiserror::Error)]
+pub enum MessageError {
+    /// Nothing arrived within the time waited.
+    #[error("No response from the component")]
+    Timeout,
+    /// Reading the connection failed.
+    #[error("Failed to read the response: {0}")]
+    Read(#[from] std::io::Error),
+    /// The component closed the connection before writing anything.
+    #[error("Component closed the connection without responding")]
+    Closed,
+    /// The frame read is not a response in the encoding.
+    #[error("Response {frame:?} is not valid, {source}")]
+    Invalid {
+        /// Frame read, lossily as text.
+        frame: String,
+        /// Why it could not be decoded.
+        source: FrameError,
+    },
+}
+
+impl Encoding {
+    /// Whether documents are binary, so they cannot be framed by lines.
+    pub fn is_binary(self) -> bool {
+        !matches!(self, Self::Json)
+    }
+
+    /// Framing used when the config does not set one, lines for json as
+    /// the AI system has always sent.
+    pub fn framing(self) -> Framing {
+        if self.is_binary() {
+            Framing::LengthPrefixed
+        } else {
+            Framing::Lines
+        }
+    }
+
+    /// Framing of connections in the encoding, the default unless one is
+    /// set. Panics for binary documents framed by lines, as they may hold
+    /// a new line.
+    ///
+    /// * `framing`: framing set in the config.
+    pub fn framing_or(self, framing: Option<Framing>) -> Framing {
+        let framing = framing.unwrap_or(self.framing());
+        assert!(
+            !(self.is_binary() && framing == Framing::Lines),
+            "{self:?} documents cannot be framed by lines, they may hold a new line"
+        );
+        framing
+    }
+
+    /// Write a message as a document, without any framing. Panics if the
+    /// message cannot be written as json.
+    ///
+    /// * `message`: message or response sent.
+    pub fn encode<T: Serialize>(self, message: &T) -> Vec<u8> {
+        let document = || serde_json::to_value(message).expect("Failed to serialise message");
+        match self {
+            Self::Json => serde_json::to_vec(message).expect("Failed to serialise message"),
+            Self::Cbor => {
+                let mut encoded = Vec::new();
+                ciborium::ser::into_writer(&document(), &mut encoded).expect("Failed to encode CBOR frame");
+                encoded
+            }
+            Self::MessagePack => rmp_serde::to_vec_named(&document()).expect("Failed to encode MessagePack frame"),
+        }
+    }
+
+    /// Read a message from a frame.
+    ///
+    /// * `frame`: frame as read by [`crate::utils::net::FramedCodec`].
+    pub fn decode<T: DeserializeOwned>(self, frame: &[u8]) -> Result<T, FrameError> {
+        serde_json::from_slice(&self.to_json(frame)?).map_err(FrameError::Json)
+    }
+
+    /// The json document carried by a frame, so it is handled as a line of
+    /// json would be. Json is passed through unread.
+    ///
+    /// * `frame`: frame as read by [`crate::utils::net::FramedCodec`].
+    pub fn to_json(self, frame: &[u8]) -> Result<Cow<'_, [u8]>, FrameError> {
+        let document: serde_json::Value = match self {
+            Self::Json => return Ok(Cow::Borrowed(frame)),
+            Self::Cbor => ciborium::de::from_reader(frame).map_err(|e| FrameError::Cbor(e.to_string()))?,
+            Self::MessagePack => rmp_serde::from_slice(frame).map_err(|e| FrameError::MessagePack(e.to_string()))?,
+        };
+        Ok(Cow::Owned(serde_json::to_vec(&document).map_err(FrameError::Json)?))
+    }
+
+    /// Whether a frame carries nothing and is skipped, blank json or an
+    /// empty binary frame.
+    ///
+    /// * `frame`: frame read.
+    pub fn is_blank(self, frame: &[u8]) -> bool {
+        match self {
+            Self::Json => frame.iter().all(u8::is_ascii_whitespace),
+            Self::Cbor | Self::MessagePack => frame.is_empty(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{
+        control::{
+            heartbeat::{ComponentKind, Heartbeat},
+            light::{LightMessage, LightRejections, LightRequest, LightStatusRequest, LightStatusResponse},
+            manual::ManualSprayMessage,
+            pdm::{PdmControlMessage, TestPattern},
+            response::ControlResponse,
+            trigger::TriggerMessage,
+            weed::WeedMessage,
+        },
+        envelope::{Envelope, Incoming},
+    };
+    use crate::utils::{
+        location::CropBed,
+        net::{FrameRead, FramedCodec},
+    };
+    use chrono::{Duration, Utc};
+    use rstest::rstest;
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        fmt::Debug,
+    };
+    use uuid::Uuid;
+
+    /// Frame a message, read it back off a connection and decode it.
+    ///
+    /// * `encoding`: encoding of the frame.
+    /// * `message`: message sent.
+    async fn send_and_read<S: Serialize, T: DeserializeOwned>(encoding: Encoding, message: &S) -> T {
+        let mut codec = FramedCodec::new(encoding.framing());
+        let sent = codec.frame(&encoding.encode(message));
+        let mut reader = &sent[..];
+        let mut frame = Vec::new();
+        assert_eq!(
+            codec.read_frame(&mut reader, &mut frame).await.unwrap(),
+            FrameRead::Frame
+        );
+        assert!(reader.is_empty(), "Frame was not read whole");
+        encoding.decode(&frame).unwrap()
+    }
+
+    /// Messages survive a round trip through a frame unchanged.
+    ///
+    /// * `encoding`: encoding of the frame.
+    /// * `message`: message sent.
+    async fn assert_round_trip<T: Serialize + DeserializeOwned + PartialEq + Debug>(encoding: Encoding, message: &T) {
+        assert_eq!(&send_and_read::<T, T>(encoding, message).await, message);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    /// Every message and response survives a round trip in every encoding,
+    /// and those only ever received read from the document a sender writes.
+    async fn test_round_trip_every_message(
+        #[values(Encoding::Json, Encoding::Cbor, Encoding::MessagePack)] encoding: Encoding,
+    ) {
+        let now = Utc::now();
+        let weed = || {
+            WeedMessage::new(CropBed::Centre, 3, now, now + Duration::milliseconds(40))
+                .channels([10, 11])
+                .distance_to_solenoid(762.5)
+                .message_id("cam3-17")
+                .intensity(60)
+        };
+        assert_round_trip(encoding, &weed()).await;
+        assert_round_trip(encoding, &Envelope::new(weed())).await;
+        let incoming: Incoming<WeedMessage> = send_and_read(encoding, &Envelope::new(weed())).await;
+        assert!(incoming.id().is_some());
+        assert_round_trip(encoding, &LightMessage::new(2, 4).channels([3]).on().level(65).strobe()).await;
+        assert_round_trip(
+            encoding,
+            &LightStatusRequest {
+                request: LightRequest::Status,
+            },
+        )
+        .await;
+        assert_round_trip(
+            encoding,
+            &LightStatusResponse {
+                crop_bed_id: CropBed::RightBoom,
+                channels: BTreeMap::from([(1, 80), (2, 0)]),
+                strobed: BTreeMap::from([(3, 100)]),
+                uptime_s: 12.25,
+                pdms_initialised: BTreeMap::from([(0, true)]),
+                drifted: false,
+                rejected: LightRejections::default(),
+                self_test: BTreeMap::new(),
+                cooling_down: BTreeSet::from([2]),
+            },
+        )
+        .await;
+        assert_round_trip(encoding, &TriggerMessage::new(now, 5, CropBed::LeftBoom)).await;
+        assert_round_trip(
+            encoding,
+            &Heartbeat::new(ComponentKind::Power, Uuid::new_v4(), CropBed::Centre, 3.5).with_queue_depth(2),
+        )
+        .await;
+        assert_round_trip(
+            encoding,
+            &ControlResponse::rejected(Some(String::from("cam3-17")), "manual sprays are not allowed")
+                .with_component(Uuid::new_v4())
+                .with_detail("queued_actions", 0),
+        )
+        .await;
+
+        let manual: ManualSprayMessage = send_and_read(
+            encoding,
+            &serde_json::json!({"manual": true, "channels": [7], "duration_ms": 500, "message_id": "hmi-3"}),
+        )
+        .await;
+        assert_eq!((manual.channels, manual.duration_ms, manual.pwm), (vec![7], 500, 100));
+        let control: PdmControlMessage = send_and_read(
+            encoding,
+            &serde_json::json!({"test_pattern": {"on_ms": 100, "gap_ms": 50}}),
+        )
+        .await;
+        assert_eq!(
+            control,
+            PdmControlMessage::TestPattern(TestPattern {
+                on_ms: 100,
+                gap_ms: 50,
+                loops: 1,
+                force: false
+            })
+        );
+    }
+
+    #[rstest]
+    #[case::json(Encoding::Json, b"{\"channels\": [3], \"is_on".to_vec(), "EOF")]
+    #[case::cbor(Encoding::Cbor, vec![0xff], "malformed CBOR frame")]
+    #[case::message_pack(Encoding::MessagePack, vec![0xc1], "malformed MessagePack frame")]
+    /// A frame that is not a document of its encoding is an error naming
+    /// the encoding, not a message, and blank frames are told apart.
+    fn test_malformed_frame(#[case] encoding: Encoding, #[case] malformed: Vec<u8>, #[case] expected: &str) {
+        let error = encoding.decode::<LightMessage>(&malformed).unwrap_err();
+        assert!(error.to_string().contains(expected), "Unhelpful error {error}");
+        assert!(encoding.to_json(&malformed).is_err() || !encoding.is_binary());
+        assert!(!encoding.is_blank(&malformed));
+        assert!(encoding.is_blank(&[]));
+    }
+
+    #[test]
+    /// Binary encodings are length prefixed unless told otherwise, never by
+    /// lines, and the encodings are written in the configs by name.
+    fn test_encoding_config() {
+        assert_eq!(Encoding::default().framing(), Framing::Lines);
+        assert_eq!(Encoding::Cbor.framing(), Framing::LengthPrefixed);
+        assert_eq!(Encoding::Json.framing_or(Some(Framing::Detect)), Framing::Detect);
+        assert!(std::panic::catch_unwind(|| Encoding::Cbor.framing_or(Some(Framing::Lines))).is_err());
+        assert_eq!(serde_yaml::to_string(&Encoding::MessagePack).unwrap(), "message_pack\n");
+        assert_eq!(serde_yaml::from_str::<Encoding>("cbor").unwrap(), Encoding::Cbor);
+    }
+}
@@ -0,0 +1,175 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+/// Version of the envelope written by this build.
+pub const ENVELOPE_VERSION: u8 = 1;
+
+/// Difference in milliseconds between when a message says it was sent and
+/// when it was received past which the clocks are taken to disagree, far
+/// more than the network adds.
+pub const MAX_CLOCK_SKEW_MS: i64 = 5000;
+
+/// Identity, version and send time wrapped around a message, so messages
+/// can be correlated and the protocol changed without breaking senders.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Envelope<T> {
+    /// Unique id of the message, echoed in responses and used to drop
+    /// retries.
+    pub id: Uuid,
+    /// Version of the envelope and payload.
+    pub version: u8,
+    /// UTC time the message was sent.
+    pub sent_at: DateTime<Utc>,
+    /// The message itself.
+    pub payload: T,
+}
+
+impl<T> Envelope<T> {
+    /// Wrap a message to send it now.
+    ///
+    /// * `payload`: the message.
+    pub fn new(payload: T) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            version: ENVELOPE_VERSION,
+            sent_at: Utc::now(),
+            payload,
+        }
+    }
+}
+
+/// Message as received, in an envelope or bare from a sender that predates
+/// them. A json object with an `id` and a `payload` is read as an envelope,
+/// anything else as a bare message so its parse errors stay readable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Incoming<T> {
+    /// Message sent in an envelope.
+    Enveloped(Envelope<T>),
+    /// Message sent on its own.
+    Bare(T),
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Incoming<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let enveloped = value.get("id").is_some() && value.get("payload").is_some();
+        if enveloped {
+            serde_json::from_value(value).map(Self::Enveloped)
+        } else {
+            serde_json::from_value(value).map(Self::Bare)
+        }
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+impl<T> Incoming<T> {
+    /// The message, whichever way it was sent.
+    pub fn payload(&self) -> &T {
+        match self {
+            Self::Enveloped(envelope) => &envelope.payload,
+            Self::Bare(payload) => payload,
+        }
+    }
+
+    /// Take the message out, whichever way it was sent.
+    pub fn into_payload(self) -> T {
+        match self {
+            Self::Enveloped(envelope) => envelope.payload,
+            Self::Bare(payload) => payload,
+        }
+    }
+
+    /// Id of the envelope, `None` for a bare message.
+    pub fn id(&self) -> Option<Uuid> {
+        match self {
+            Self::Enveloped(envelope) => Some(envelope.id),
+            Self::Bare(_) => None,
+        }
+    }
+
+    /// Time from the message being sent to it being received, `None` for a
+    /// bare message. Negative when the sender's clock is ahead.
+    ///
+    /// * `received_at`: when the message was received.
+    pub fn clock_skew(&self, received_at: DateTime<Utc>) -> Option<Duration> {
+        match self {
+            Self::Enveloped(envelope) => Some(received_at - envelope.sent_at),
+            Self::Bare(_) => None,
+        }
+    }
+
+    /// Log when the sender's clock disagrees with ours by more than
+    /// [`MAX_CLOCK_SKEW_MS`], as every spray time it sends is out by as
+    /// much. Also logs envelopes of a newer version than this build writes.
+    ///
+    /// * `received_at`: when the message was received.
+    pub fn warn_clock_skew(&self, received_at: DateTime<Utc>) {
+        let Self::Enveloped(envelope) = self else {
+            return;
+        };
+        let skew = received_at - envelope.sent_at;
+        if skew.num_milliseconds().abs() > MAX_CLOCK_SKEW_MS {
+            warn!(
+                "Message {} was sent at {} and received {}ms later, check the clocks of the sender are in sync",
+                envelope.id,
+                envelope.sent_at,
+                skew.num_milliseconds()
+            );
+        }
+        if envelope.version > ENVELOPE_VERSION {
+            warn!(
+                "Message {} is envelope version {}, newer than {ENVELOPE_VERSION}, reading it as {ENVELOPE_VERSION}",
+                envelope.id, envelope.version
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::control::light::LightMessage;
+
+    #[test]
+    /// Enveloped messages survive a round trip through json.
+    fn test_envelope_round_trip() {
+        let envelope = Envelope::new(LightMessage::new(2, 4).channels([3]).on().level(65));
+        let json = serde_json::to_string(&envelope).unwrap();
+        assert_eq!(serde_json::from_str::<Envelope<LightMessage>>(&json).unwrap(), envelope);
+
+        let incoming: Incoming<LightMessage> = serde_json::from_str(&json).unwrap();
+        assert_eq!(incoming.id(), Some(envelope.id));
+        assert_eq!(incoming, Incoming::Enveloped(envelope.clone()));
+        assert_eq!(incoming.into_payload(), envelope.payload);
+    }
+
+    #[test]
+    /// Bare messages from older senders are still read, and keep the
+    /// errors of the message when malformed.
+    fn test_legacy_bare_message() {
+        let raw = r#"{"channels": [3], "is_on": true, "cam_id": 4, "crop_bed_id": 2}"#;
+        let incoming: Incoming<LightMessage> = serde_json::from_str(raw).unwrap();
+        assert_eq!(incoming.id(), None);
+        assert_eq!(incoming.clock_skew(Utc::now()), None);
+        assert_eq!(*incoming.payload(), LightMessage::new(2, 4).channels([3]).on());
+
+        let error = serde_json::from_str::<Incoming<LightMessage>>(r#"{"channels": [3], "cam_id": 4}"#).unwrap_err();
+        assert!(error.to_string().contains("is_on"), "Unhelpful error {error}");
+        assert!(serde_json::from_str::<Incoming<LightMessage>>(r#"{"id": 1, "payload": {}}"#).is_err());
+    }
+
+    #[test]
+    /// The skew is the time from sending to receiving.
+    fn test_clock_skew() {
+        let envelope = Envelope::new(LightMessage::new(2, 4));
+        let incoming = Incoming::Enveloped(envelope.clone());
+        let received_at = envelope.sent_at + Duration::milliseconds(12);
+        assert_eq!(incoming.clock_skew(received_at), Some(Duration::milliseconds(12)));
+        assert_eq!(
+            incoming.clock_skew(envelope.sent_at - Duration::seconds(30)),
+            Some(Duration::seconds(-30))
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{messages::control::heartbeat::ComponentKind, utils::location::CropBed};
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+use strum_macros::IntoStaticStr;
+use uuid::Uuid;
+
+/// How much an event matters to the operator.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    /// Detail only looked at while trouble shooting.
+    Debug,
+    /// Normal running, e.g. a message received.
+    Info,
+    /// Something was refused or worked around, the component carries on.
+    Warn,
+    /// Something failed, the component or part of it has stopped.
+    Error,
+}
+
+/// What happened, so events can be filtered and counted without reading
+/// the text.
+#[derive(Deserialize, Serialize, IntoStaticStr, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum EventCode {
+    /// A task or thread of the component did not stop cleanly.
+    TaskFailed,
+    /// The component has shut down.
+    ShutDown,
+    /// An emergency stop turned every channel off at once.
+    EmergencyStop,
+    /// The periodic status of the component.
+    Status,
+    /// A feature asked for in the config is not available.
+    Unavailable,
+    /// A PDM is being configured again.
+    PdmReinitialising,
+    /// A PDM came back after being configured again.
+    PdmRecovered,
+    /// A PDM did not come back after being configured again.
+    PdmFailed,
+    /// A PDM did not read back the configuration written to it.
+    PdmMismatch,
+    /// The configuration of a PDM has drifted, the component stops acting.
+    PdmDrifted,
+    /// A PDM reported a tripped channel or is over temperature.
+    PdmFault,
+    /// A message names a PDM or channel that is not configured.
+    Unrouted,
+    /// Every channel of a PDM was turned off.
+    ChannelsOff,
+    /// A channel drew no current, or reported a fault, once actuated.
+    SolenoidFault,
+    /// A message was received.
+    MessageReceived,
+    /// A message was refused.
+    MessageRejected,
+    /// A message was dropped as a retry of one already received.
+    MessageDuplicate,
+    /// A message, or a spray it queued, was too late to act on.
+    MessageLate,
+    /// Data received was not a message the component understands.
+    MessageMalformed,
+    /// A message asked for more than allowed and was cut down to it.
+    Clamped,
+    /// The time from receiving a message to acting on it.
+    Latency,
+    /// The system clock was stepped against the monotonic clock.
+    ClockStepped,
+    /// A connection was closed, by the peer or for being idle.
+    ConnectionClosed,
+    /// Reading from or writing to a connection failed.
+    ConnectionFailed,
+    /// A connection was refused.
+    ConnectionRefused,
+    /// The ground speed followed was lost, or never found.
+    GroundSpeed,
+    /// A camera stopped delivering frames.
+    CameraStalled,
+    /// A camera thread exited or was restarted.
+    CameraRestarted,
+    /// An image could not be written.
+    ImageWriteFailed,
+    /// Old images could not be removed.
+    RetentionFailed,
+}
+
+/// One line of the structured log, written by every component in the same
+/// shape so the logs of a crop bed can be read together.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct LogEvent {
+    /// UTC time the event happened.
+    pub timestamp: DateTime<Utc>,
+    /// How much it matters.
+    pub level: LogLevel,
+    /// Kind of component it happened in.
+    pub kind: ComponentKind,
+    /// Unique id of the component.
+    pub component: Uuid,
+    /// Crop bed the component is attached to.
+    pub crop_bed_id: CropBed,
+    /// What happened.
+    pub code: EventCode,
+    /// What happened, readable by the operator.
+    pub message: String,
+    /// Further detail particular to the event, e.g. the PDM address.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, serde_json::Value>,
+}
+
+impl LogEvent {
+    /// Event happening now.
+    ///
+    /// * `level`: how much it matters.
+    /// * `kind`: kind of component.
+    /// * `component`: unique id of the component.
+    /// * `crop_bed_id`: crop bed the component is attached to.
+    /// * `code`: what happened.
+    /// * `message`: what happened, readable by the operator.
+    pub fn new(
+        level: LogLevel,
+        kind: ComponentKind,
+        component: Uuid,
+        crop_bed_id: CropBed,
+        code: EventCode,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            level,
+            kind,
+            component,
+            crop_bed_id,
+            code,
+            message: message.into(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    /// Add a field, panics if the value cannot be written as json.
+    ///
+    /// * `key`: name of the field.
+    /// * `value`: the field.
+    pub fn with_field(mut self, key: &str, value: impl Serialize) -> Self {
+        let value = serde_json::to_value(value).expect("Failed to serialise log field");
+        self.fields.insert(String::from(key), value);
+        self
+    }
+
+    /// Read a field back, `None` if it is missing or of another type.
+    ///
+    /// * `key`: name of the field.
+    pub fn field<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        serde_json::from_value(self.fields.get(key)?.clone()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Events survive a round trip through json, leaving out empty fields
+    /// and writing the level and code by name.
+    fn test_log_event_round_trip() {
+        let event = LogEvent::new(
+            LogLevel::Warn,
+            ComponentKind::Power,
+            Uuid::new_v4(),
+            CropBed::Centre,
+            EventCode::PdmMismatch,
+            "PDM 31 did not read back its channel map",
+        )
+        .with_field("pdm", 31);
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(json.contains(r#""level":"warn""#), "{json}");
+        assert!(json.contains(r#""code":"pdm_mismatch""#), "{json}");
+        assert_eq!(<&str>::from(event.code), "pdm_mismatch");
+        assert_eq!(serde_json::from_str::<LogEvent>(&json).unwrap(), event);
+        assert_eq!(event.field::<u8>("pdm"), Some(31));
+        assert_eq!(event.field::<String>("pdm"), None);
+
+        let event = LogEvent::new(
+            LogLevel::Info,
+            ComponentKind::CameraArray,
+            Uuid::new_v4(),
+            CropBed::LeftBoom,
+            EventCode::ShutDown,
+            "Camera array shut down",
+        );
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(!json.contains("fields"), "{json}");
+        assert_eq!(serde_json::from_str::<LogEvent>(&json).unwrap(), event);
+    }
+}
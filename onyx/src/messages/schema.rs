@@ -0,0 +1,370 @@
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Draft of JSON Schema the documents are written in.
+pub const SCHEMA_DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Ending of the file names the documents are exported to.
+pub const SCHEMA_EXTENSION: &str = "schema.json";
+
+/// Whole number between two bounds.
+///
+/// * `description`: what the number is.
+/// * `maximum`: largest value read.
+fn integer(description: &str, maximum: u64) -> Value {
+    json!({"type": "integer", "minimum": 0, "maximum": maximum, "description": description})
+}
+
+/// UTC time written in RFC 3339.
+///
+/// * `description`: what the time is.
+fn date_time(description: &str) -> Value {
+    json!({"type": "string", "format": "date-time", "description": description})
+}
+
+/// Channels numbered as the message they are in numbers them.
+///
+/// * `description`: what the channels are.
+fn channels(description: &str) -> Value {
+    json!({"type": "array", "items": {"type": "integer", "minimum": 0, "maximum": 255}, "description": description})
+}
+
+/// Duty cycle in percent, read up to fully on.
+///
+/// * `description`: what the duty cycle is of.
+fn duty_cycle(description: &str) -> Value {
+    json!({"type": "integer", "minimum": 0, "maximum": 100, "default": 100, "description": description})
+}
+
+/// Identifier a sender may leave out.
+///
+/// * `description`: what the identifier is for.
+fn identifier(description: &str) -> Value {
+    json!({"type": ["string", "null"], "description": description})
+}
+
+/// Crop bed, by name or legacy integer id.
+///
+/// * `description`: what the crop bed is of.
+fn crop_bed(description: &str) -> Value {
+    json!({
+        "oneOf": [
+            {"enum": ["left_boom", "centre", "right_boom"]},
+            {"type": "integer", "minimum": 0, "maximum": 255}
+        ],
+        "description": description
+    })
+}
+
+/// Schema document of an object.
+///
+/// * `title`: name of the message.
+/// * `description`: what the message is for.
+/// * `properties`: schema of each field.
+/// * `required`: fields every message sends.
+fn object(title: &str, description: &str, properties: Value, required: &[&str]) -> Value {
+    json!({
+        "$schema": SCHEMA_DRAFT,
+        "title": title,
+        "description": description,
+        "type": "object",
+        "properties": properties,
+        "required": required
+    })
+}
+
+/// Weed message of either version, as the power component reads it.
+fn weed_message() -> Value {
+    object(
+        "WeedMessage",
+        "Weed message generated by the AI system. Version 2 fields are optional, a message without a version is \
+         read as version 2 when it carries any of them.",
+        json!({
+            "version": {
+                "enum": [1, 2],
+                "description": "Version of the message, taken from the fields sent when left out."
+            },
+            "channels_to_open": channels("Channels to open to spray the weed, numbered from 0."),
+            "start_spray_time": date_time("UTC time set to start spraying."),
+            "end_spray_time": date_time("UTC time set to stop spraying."),
+            "message_created_at": date_time("UTC time that the message was created."),
+            "capture_time": date_time("UTC time stamp of when the image was captured."),
+            "time_diff_capture_to_start_spray_no_offset": {
+                "type": "number",
+                "default": 0.0,
+                "description": "Offset to account for time for the spray to hit the weed from height."
+            },
+            "distance_to_solenoid_mm": {
+                "type": "number",
+                "description": "Distance from the weed to the solenoid at the rear of the machine."
+            },
+            "cam_id": integer("Which camera has generated this message.", 255),
+            "crop_bed_id": crop_bed("Which crop bed this message is directed to."),
+            "assumed_speed_mps": {"type": "number", "description": "Ground speed the spray times were computed for."},
+            "message_id": identifier("Identifier echoed in the response."),
+            "intensity": duty_cycle("Duty cycle in percent to spray at."),
+            "latitude": {"type": "number", "description": "Latitude of the machine in decimal degrees."},
+            "longitude": {"type": "number", "description": "Longitude of the machine in decimal degrees."},
+            "heading_deg": {
+                "type": "number",
+                "description": "Heading of the machine in degrees clockwise from true north."
+            }
+        }),
+        &[
+            "channels_to_open",
+            "start_spray_time",
+            "end_spray_time",
+            "message_created_at",
+            "capture_time",
+            "distance_to_solenoid_mm",
+            "cam_id",
+            "crop_bed_id",
+        ],
+    )
+}
+
+/// Light message, as the lighting component reads it.
+fn light_message() -> Value {
+    object(
+        "LightMessage",
+        "Turns the lights of a crop bed on, off or into strobe mode.",
+        json!({
+            "channels": channels("The channels of the PDM to turn on."),
+            "is_on": {
+                "type": "boolean",
+                "description": "If true, set the PWM of the channels to the level, else to 0."
+            },
+            "level": duty_cycle("Duty cycle in percent the lights are dimmed to when on."),
+            "strobe": {
+                "type": "boolean",
+                "default": false,
+                "description": "If true with is_on, pulse the channels on the camera triggers rather than hold them on."
+            },
+            "cam_id": integer("Camera id associated with the light.", 255),
+            "crop_bed_id": crop_bed("Crop bed id associated with the light.")
+        }),
+        &["channels", "is_on", "cam_id", "crop_bed_id"],
+    )
+}
+
+/// Manual spray message, as the power component reads it.
+fn manual_spray_message() -> Value {
+    object(
+        "ManualSprayMessage",
+        "Spray fired by hand from the operator while commissioning, starting as soon as it is received.",
+        json!({
+            "manual": {"const": true, "description": "Tag telling a manual spray apart from a detection."},
+            "channels": channels("Channels of the crop bed to open, numbered from 1 as on the harness."),
+            "duration_ms": {
+                "type": "integer",
+                "minimum": 0,
+                "description": "Time in milliseconds the channels are kept open."
+            },
+            "pwm": duty_cycle("Duty cycle in percent."),
+            "message_id": identifier("Identifier echoed in the response.")
+        }),
+        &["manual", "channels", "duration_ms"],
+    )
+}
+
+/// Response written back by every component.
+fn control_response() -> Value {
+    object(
+        "ControlResponse",
+        "One line written back for each message a component receives.",
+        json!({
+            "status": {
+                "enum": ["accepted", "rejected", "duplicate", "late"],
+                "description": "What was done with the message."
+            },
+            "reason": {"type": "string", "description": "Why the message was refused, sent when it was rejected."},
+            "correlation_id": identifier("Id of the message responded to, if it carried one."),
+            "component": {"type": "string", "format": "uuid", "description": "Component that handled the message."},
+            "details": {
+                "type": "object",
+                "description": "Further detail particular to the message, e.g. the actions queued."
+            }
+        }),
+        &["status", "correlation_id", "component"],
+    )
+}
+
+/// Envelope wrapped around any of the messages.
+fn envelope() -> Value {
+    object(
+        "Envelope",
+        "Identity, version and send time wrapped around a message, read alongside bare messages.",
+        json!({
+            "id": {
+                "type": "string",
+                "format": "uuid",
+                "description": "Unique id of the message, echoed in responses and used to drop retries."
+            },
+            "version": integer("Version of the envelope and payload.", 255),
+            "sent_at": date_time("UTC time the message was sent."),
+            "payload": {"description": "The message itself, any of the other messages."}
+        }),
+        &["id", "version", "sent_at", "payload"],
+    )
+}
+
+/// Schema document of every message sent to or written by the components,
+/// keyed by the name it is exported under.
+pub fn schemas() -> BTreeMap<&'static str, Value> {
+    BTreeMap::from([
+        ("weed_message", weed_message()),
+        ("light_message", light_message()),
+        ("manual_spray_message", manual_spray_message()),
+        ("control_response", control_response()),
+        ("envelope", envelope()),
+    ])
+}
+
+/// Write every schema document to a directory, created if it does not
+/// exist, returning the files written.
+///
+/// * `path`: directory the documents are written to, one file each.
+pub fn export_schemas(path: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(&path)?;
+    schemas()
+        .into_iter()
+        .map(|(name, schema)| {
+            let file = path.as_ref().join(format!("{name}.{SCHEMA_EXTENSION}"));
+            let mut document = serde_json::to_string_pretty(&schema).expect("Failed to serialise schema");
+            document.push('\n');
+            std::fs::write(&file, document).map(|()| file)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        messages::{
+            control::{
+                light::LightMessage,
+                manual::ManualSprayMessage,
+                response::ControlResponse,
+                weed::{VersionedWeedMessage, WeedMessageV1, WeedMessageV2},
+            },
+            envelope::Envelope,
+        },
+        utils::location::CropBed,
+    };
+    use chrono::Utc;
+    use rstest::rstest;
+    use std::collections::BTreeSet;
+    use uuid::Uuid;
+
+    /// Directory the schemas are kept in, next to the manifest.
+    fn golden_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("schemas")
+    }
+
+    /// Names of the fields of a json object.
+    ///
+    /// * `value`: the object.
+    fn keys(value: &Value) -> BTreeSet<String> {
+        value.as_object().expect("Not an object").keys().cloned().collect()
+    }
+
+    /// Version 2 weed message with every optional field set.
+    fn weed_message_v2() -> VersionedWeedMessage {
+        VersionedWeedMessage::V2(WeedMessageV2 {
+            base: WeedMessageV1 {
+                channels_to_open: vec![3],
+                start_spray_time: Utc::now(),
+                end_spray_time: Utc::now(),
+                message_created_at: Utc::now(),
+                capture_time: Utc::now(),
+                time_diff_capture_to_start_spray_no_offset: 0.0,
+                distance_to_solenoid_mm: 400.0,
+                cam_id: 1,
+                crop_bed_id: CropBed::Centre,
+            },
+            assumed_speed_mps: Some(1.5),
+            message_id: Some(String::from("cam1-1")),
+            intensity: 60,
+            latitude: Some(-27.47),
+            longitude: Some(153.02),
+            heading_deg: Some(90.0),
+        })
+    }
+
+    #[test]
+    /// The schemas kept in the repository are those of the current types,
+    /// regenerate them when a message changes.
+    fn test_schemas_match_golden_files() {
+        for (name, schema) in schemas() {
+            let file = golden_dir().join(format!("{name}.{SCHEMA_EXTENSION}"));
+            let golden = std::fs::read_to_string(&file).unwrap_or_else(|e| panic!("Missing schema {file:?}: {e}"));
+            let golden: Value = serde_json::from_str(&golden).unwrap();
+            assert_eq!(
+                golden, schema,
+                "Schema {file:?} is out of date, regenerate it with `cargo run -p spray -- --dump-schemas onyx/schemas`"
+            );
+        }
+    }
+
+    #[rstest]
+    #[case::weed_message("weed_message", serde_json::to_value(weed_message_v2()).unwrap(), &["version"])]
+    #[case::light_message(
+        "light_message",
+        serde_json::to_value(LightMessage::new(CropBed::Centre, 0).channels([1]).on().level(50).strobe()).unwrap(),
+        &[]
+    )]
+    #[case::manual_spray_message(
+        "manual_spray_message",
+        serde_json::to_value(ManualSprayMessage {
+            manual: true,
+            channels: vec![7],
+            duration_ms: 500,
+            pwm: 40,
+            message_id: Some(String::from("hmi-1")),
+        }).unwrap(),
+        &[]
+    )]
+    #[case::control_response(
+        "control_response",
+        serde_json::to_value(ControlResponse::rejected(Some(String::from("cam0-1")), "malformed").with_detail("at", 3))
+            .unwrap(),
+        &[]
+    )]
+    #[case::envelope("envelope", serde_json::to_value(Envelope::new(serde_json::json!({}))).unwrap(), &[])]
+    /// Every field a message is written with is in its schema, and every
+    /// field of the schema is written, besides those only ever read.
+    fn test_schema_follows_type(#[case] name: &str, #[case] written: Value, #[case] read_only: &[&str]) {
+        let schema = &schemas()[name];
+        let mut expected = keys(&written);
+        expected.extend(read_only.iter().map(|field| String::from(*field)));
+        assert_eq!(
+            keys(&schema["properties"]),
+            expected,
+            "Schema {name} does not match its type"
+        );
+        for required in schema["required"].as_array().unwrap() {
+            assert!(
+                written.get(required.as_str().unwrap()).is_some(),
+                "{name} does not write {required}"
+            );
+        }
+    }
+
+    #[test]
+    /// Every schema is written to its own file, reading back as it was.
+    fn test_export_schemas() {
+        let dir = std::env::temp_dir().join(format!("onyx-schemas-{}", Uuid::new_v4()));
+        let files = export_schemas(&dir).unwrap();
+        assert_eq!(files.len(), schemas().len());
+        let envelope: Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join("envelope.schema.json")).unwrap()).unwrap();
+        assert_eq!(envelope, schemas()["envelope"]);
+        assert_eq!(envelope["$schema"], SCHEMA_DRAFT);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
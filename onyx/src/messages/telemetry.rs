@@ -0,0 +1,247 @@
+use crate::utils::location::{CropBed, GeoPosition};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Version of the telemetry written, raised when a field changes meaning
+/// or is removed so the fleet backend can still read older components.
+pub const TELEMETRY_VERSION: u32 = 1;
+
+/// Fields every telemetry snapshot starts with.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TelemetryHeader {
+    /// Version of the telemetry, see [`TELEMETRY_VERSION`].
+    pub version: u32,
+    /// Unique id of the component the snapshot was taken of.
+    pub component: Uuid,
+    /// Crop bed the component is attached to.
+    pub crop_bed_id: CropBed,
+    /// UTC time the snapshot was taken.
+    pub taken_at: DateTime<Utc>,
+}
+
+impl TelemetryHeader {
+    /// Header of a snapshot taken now, at the current version.
+    ///
+    /// * `component`: unique id of the component.
+    /// * `crop_bed_id`: crop bed the component is attached to.
+    pub fn new(component: Uuid, crop_bed_id: CropBed) -> Self {
+        Self {
+            version: TELEMETRY_VERSION,
+            component,
+            crop_bed_id,
+            taken_at: Utc::now(),
+        }
+    }
+}
+
+/// What the spray queue of a power component is doing.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SprayTelemetry {
+    /// Version, component and time of the snapshot.
+    #[serde(flatten)]
+    pub header: TelemetryHeader,
+    /// Actions waiting in the queue.
+    pub queue_depth: usize,
+    /// Weed messages and manual sprays queued so far.
+    pub accepted: u64,
+    /// Weed messages that arrived after their spray was due so far.
+    pub late: u64,
+    /// Queued actions discarded for being too far behind their time.
+    pub late_discarded: u64,
+    /// When a message was last sent to a PDM, heartbeats aside.
+    pub last_fired_at: Option<DateTime<Utc>>,
+    /// Where the machine was when a message was last sent to a PDM, when
+    /// known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_fired_position: Option<GeoPosition>,
+}
+
+/// Frame rate and losses of one camera.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct CameraFrameTelemetry {
+    /// Frames captured a second since the previous snapshot.
+    pub fps: f64,
+    /// Frames captured so far.
+    pub frames_captured: u64,
+    /// Frames discarded for taking longer than the frame interval so far.
+    pub dropped_frames: u64,
+}
+
+/// What the cameras of an array are doing.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CameraTelemetry {
+    /// Version, component and time of the snapshot.
+    #[serde(flatten)]
+    pub header: TelemetryHeader,
+    /// Frame rate and losses of each camera keyed by bed position.
+    pub cameras: BTreeMap<u8, CameraFrameTelemetry>,
+    /// Images that failed to be written so far.
+    pub write_failures: u64,
+    /// Images dropped because the writer queue was full so far.
+    pub images_dropped: u64,
+}
+
+/// Faults and wear of one PDM.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PdmStateTelemetry {
+    /// Source address of the PDM.
+    pub address: u8,
+    /// The PDM stopped hearing from the controller.
+    pub loss_of_can: bool,
+    /// The PDM as a whole is over temperature.
+    pub over_temperature: bool,
+    /// Channels tripped on over current.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tripped_channels: Vec<u8>,
+    /// Channels whose driver is over temperature.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hot_channels: Vec<u8>,
+    /// Total time in milliseconds each channel has been on, keyed by the
+    /// channel on the PDM.
+    pub channel_on_time_ms: BTreeMap<u8, u64>,
+}
+
+/// State of the PDMs of a power component.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PdmTelemetry {
+    /// Version, component and time of the snapshot.
+    #[serde(flatten)]
+    pub header: TelemetryHeader,
+    /// State of each PDM keyed by bed position.
+    pub pdms: BTreeMap<u8, PdmStateTelemetry>,
+}
+
+/// Any telemetry snapshot, written with the kind of snapshot under the
+/// `telemetry` key so the fleet backend can tell them apart.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "telemetry", rename_all = "snake_case")]
+pub enum Telemetry {
+    /// Spray queue of a power component.
+    Spray(SprayTelemetry),
+    /// Cameras of an array.
+    Camera(CameraTelemetry),
+    /// PDMs of a power component.
+    Pdm(PdmTelemetry),
+}
+
+impl Telemetry {
+    /// Kind of snapshot, as written under the `telemetry` key.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Spray(_) => "spray",
+            Self::Camera(_) => "camera",
+            Self::Pdm(_) => "pdm",
+        }
+    }
+
+    /// Version, component and time of the snapshot.
+    pub fn header(&self) -> &TelemetryHeader {
+        match self {
+            Self::Spray(spray) => &spray.header,
+            Self::Camera(camera) => &camera.header,
+            Self::Pdm(pdm) => &pdm.header,
+        }
+    }
+}
+
+impl From<SprayTelemetry> for Telemetry {
+    fn from(spray: SprayTelemetry) -> Self {
+        Self::Spray(spray)
+    }
+}
+
+impl From<CameraTelemetry> for Telemetry {
+    fn from(camera: CameraTelemetry) -> Self {
+        Self::Camera(camera)
+    }
+}
+
+impl From<PdmTelemetry> for Telemetry {
+    fn from(pdm: PdmTelemetry) -> Self {
+        Self::Pdm(pdm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// Header of a snapshot of a new component.
+    fn header() -> TelemetryHeader {
+        TelemetryHeader::new(Uuid::new_v4(), CropBed::Centre)
+    }
+
+    #[rstest]
+    #[case::spray(
+        SprayTelemetry {
+            header: header(),
+            queue_depth: 4,
+            accepted: 120,
+            late: 3,
+            late_discarded: 1,
+            last_fired_at: Some(Utc::now()),
+            last_fired_position: Some(GeoPosition::new(-27.47, 153.02)),
+        }.into(),
+        "spray"
+    )]
+    #[case::camera(
+        CameraTelemetry {
+            header: header(),
+            cameras: BTreeMap::from([(0, CameraFrameTelemetry { fps: 14.5, frames_captured: 900, dropped_frames: 2 })]),
+            write_failures: 1,
+            images_dropped: 0,
+        }.into(),
+        "camera"
+    )]
+    #[case::pdm(
+        PdmTelemetry {
+            header: header(),
+            pdms: BTreeMap::from([(0, PdmStateTelemetry {
+                address: 31,
+                loss_of_can: false,
+                over_temperature: false,
+                tripped_channels: vec![4],
+                hot_channels: Vec::new(),
+                channel_on_time_ms: BTreeMap::from([(1, 1500), (4, 20)]),
+            })]),
+        }.into(),
+        "pdm"
+    )]
+    /// Snapshots survive a round trip through json, written with their
+    /// kind and version alongside the fields of the header.
+    fn test_telemetry_round_trip(#[case] telemetry: Telemetry, #[case] kind: &str) {
+        let json = serde_json::to_value(&telemetry).unwrap();
+        assert_eq!(json["telemetry"], kind);
+        assert_eq!(json["version"], TELEMETRY_VERSION);
+        assert_eq!(json["crop_bed_id"], serde_json::to_value(CropBed::Centre).unwrap());
+        assert_eq!(telemetry.kind(), kind);
+        assert_eq!(serde_json::from_value::<Telemetry>(json).unwrap(), telemetry);
+    }
+
+    #[test]
+    /// Faults that are not set are left out, and a snapshot of one kind is
+    /// not read as another.
+    fn test_telemetry_apart() {
+        let pdm = PdmTelemetry {
+            header: header(),
+            pdms: BTreeMap::from([(
+                1,
+                PdmStateTelemetry {
+                    address: 30,
+                    loss_of_can: true,
+                    over_temperature: false,
+                    tripped_channels: Vec::new(),
+                    hot_channels: Vec::new(),
+                    channel_on_time_ms: BTreeMap::new(),
+                },
+            )]),
+        };
+        let json = serde_json::to_string(&Telemetry::from(pdm)).unwrap();
+        assert!(!json.contains("tripped_channels"), "{json}");
+        let renamed = json.replace(r#""telemetry":"pdm""#, r#""telemetry":"spray""#);
+        assert!(serde_json::from_str::<Telemetry>(&renamed).is_err());
+    }
+}
@@ -1,4 +1,53 @@
+/// In-process bus fanning events out between the components run in one
+/// binary.
+pub mod bus;
+/// Sending messages to a running component and reading its responses,
+/// as the AI system does.
+pub mod client;
+/// Loading the configs of the components and devices from yaml files.
+pub mod config;
+/// Generating the config files of the devices and components, and the
+/// ones kept in the crate.
+pub mod configgen;
+/// Liveness and readiness of a component, probed by the orchestrator over
+/// HTTP with the `health` feature.
+pub mod health;
+/// Sending the heartbeats of a component and keeping those of the others.
+pub mod heartbeat;
 /// Utilities for working with images.
 pub mod image;
+/// Identity of the crop bed modules on the machine and where the
+/// machine is.
+pub mod location;
+/// Emitting the structured log of a component to a file or collector.
+pub mod logging;
+/// Prometheus metrics counted by the components, kept and exported with
+/// the `metrics` feature and dropped without it.
+pub mod metrics;
+/// Bridge carrying telemetry, logs, heartbeats and commands between the
+/// components and an MQTT broker.
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+/// Framing of the messages sent over the sockets of the components.
+pub mod net;
+/// Paths of the configs and other resources in the repository, checked
+/// when they are used.
+pub mod paths;
+/// Reading the responses of the components, as a client does.
+pub mod responses;
+/// Serialisation helpers shared by the configs.
+pub mod serde;
+/// Shared memory ring for handing images to another process.
+pub mod shm;
+/// Running, joining and stopping the tokio tasks of a component.
+pub mod tasks;
+/// Shipping the telemetry snapshots of a component over UDP or to disk.
+pub mod telemetry;
+/// Mapping the monotonic clock to UTC, for timing sprays through steps of
+/// the system clock.
+pub mod time;
 /// Helper functions used for tests and file locations.
 pub mod tests;
+/// Reporting readiness, progress and shutdown to systemd with the
+/// `systemd` feature.
+pub mod watchdog;
@@ -0,0 +1,335 @@
+use crate::{
+    devices::hardware::{pdm::frames::PdmStatus, wheel_speed::GroundSpeed},
+    messages::control::trigger::TriggerMessage,
+    utils::location::CropBed,
+};
+use std::{collections::BTreeMap, sync::Arc};
+use strum_macros::IntoStaticStr;
+use tokio::{
+    sync::broadcast::{
+        self,
+        error::{RecvError, TryRecvError},
+    },
+    task::JoinHandle,
+};
+use tracing::{info, warn};
+
+/// Events held on each topic for the slowest subscriber, when not set,
+/// past which it misses the oldest.
+pub const DEFAULT_BUS_CAPACITY: usize = 256;
+
+/// Kind of event carried by the bus. Each topic has a channel of its own,
+/// so a burst of camera triggers cannot push a shutdown request out.
+#[derive(IntoStaticStr, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[strum(serialize_all = "snake_case")]
+pub enum Topic {
+    /// A camera was triggered.
+    CameraTrigger,
+    /// The ground speed of the machine changed.
+    GroundSpeed,
+    /// The components of the process are asked to stop.
+    ShutdownRequested,
+    /// A PDM reported a fault.
+    PdmFault,
+}
+
+impl Topic {
+    /// Every topic, in order.
+    pub const ALL: [Topic; 4] = [
+        Topic::CameraTrigger,
+        Topic::GroundSpeed,
+        Topic::ShutdownRequested,
+        Topic::PdmFault,
+    ];
+}
+
+/// Event published on the bus.
+#[derive(Debug, Clone)]
+pub enum BusEvent {
+    /// A camera was triggered, for the lights to strobe on.
+    CameraTrigger(TriggerMessage),
+    /// Ground speed followed by a power component, `None` once it is lost.
+    GroundSpeed(Option<GroundSpeed>),
+    /// Every component in the process is asked to stop.
+    ShutdownRequested,
+    /// A PDM reported a tripped channel, over temperature or loss of CAN.
+    PdmFault {
+        /// Crop bed of the component driving the PDM.
+        crop_bed_id: CropBed,
+        /// Source address of the PDM.
+        address: u8,
+        /// Fault state the PDM reported.
+        status: PdmStatus,
+    },
+}
+
+impl BusEvent {
+    /// Topic the event is published on.
+    pub fn topic(&self) -> Topic {
+        match self {
+            Self::CameraTrigger(_) => Topic::CameraTrigger,
+            Self::GroundSpeed(_) => Topic::GroundSpeed,
+            Self::ShutdownRequested => Topic::ShutdownRequested,
+            Self::PdmFault { .. } => Topic::PdmFault,
+        }
+    }
+}
+
+/// Typed broadcast bus shared by the components running in one binary, in
+/// place of a channel for each pair of them. Cheap to clone, every clone
+/// publishes to the same subscribers.
+#[derive(Debug, Clone)]
+pub struct MessageBus {
+    /// Channel of each topic.
+    senders: Arc<BTreeMap<Topic, broadcast::Sender<BusEvent>>>,
+}
+
+impl Default for MessageBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageBus {
+    /// Bus holding the default number of events on each topic.
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_BUS_CAPACITY)
+    }
+
+    /// Bus holding a number of events on each topic for the slowest
+    /// subscriber.
+    ///
+    /// * `capacity`: events held, at least one.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            senders: Arc::new(
+                Topic::ALL
+                    .into_iter()
+                    .map(|topic| (topic, broadcast::channel(capacity.max(1)).0))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Channel of a topic.
+    ///
+    /// * `topic`: topic of the channel.
+    fn sender(&self, topic: Topic) -> &broadcast::Sender<BusEvent> {
+        &self.senders[&topic]
+    }
+
+    /// Publish an event to the subscribers of its topic, returning how many
+    /// there are. Never waits, an event without subscribers is dropped.
+    ///
+    /// * `event`: event published.
+    pub fn publish(&self, event: BusEvent) -> usize {
+        self.sender(event.topic()).send(event).unwrap_or(0)
+    }
+
+    /// Ask every component subscribed to stop.
+    pub fn request_shutdown(&self) -> usize {
+        self.publish(BusEvent::ShutdownRequested)
+    }
+
+    /// Receive the events of a topic published from now on.
+    ///
+    /// * `topic`: topic subscribed to.
+    /// * `name`: name of the subscriber, for the log of events it misses.
+    pub fn subscribe(&self, topic: Topic, name: impl Into<String>) -> BusSubscriber {
+        BusSubscriber {
+            name: name.into(),
+            topic,
+            receiver: self.sender(topic).subscribe(),
+            dropped: 0,
+        }
+    }
+
+    /// Spawn a task calling `stop` once a shutdown is requested on the bus,
+    /// subscribed before returning so no request is missed.
+    ///
+    /// * `name`: name of the component stopped.
+    /// * `stop`: asks the component to stop.
+    pub fn on_shutdown(&self, name: impl Into<String>, stop: impl FnOnce() + Send + 'static) -> JoinHandle<()> {
+        let mut subscriber = self.subscribe(Topic::ShutdownRequested, name);
+        tokio::spawn(async move {
+            if subscriber.recv().await.is_some() {
+                info!("Shutdown requested on the bus, stopping {}", subscriber.name);
+                stop();
+            }
+        })
+    }
+}
+
+/// Subscriber to one topic of the bus, counting the events it missed by
+/// falling behind.
+#[derive(Debug)]
+pub struct BusSubscriber {
+    /// Name of the subscriber.
+    name: String,
+    /// Topic subscribed to.
+    topic: Topic,
+    /// Events of the topic not yet received.
+    receiver: broadcast::Receiver<BusEvent>,
+    /// Events missed so far.
+    dropped: u64,
+}
+
+impl BusSubscriber {
+    /// Wait for the next event, skipping past those missed by falling
+    /// behind. `None` once the bus has gone.
+    pub async fn recv(&mut self) -> Option<BusEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => return Some(event),
+                Err(RecvError::Lagged(missed)) => self.lagged(missed),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Take the next event without waiting, skipping past those missed by
+    /// falling behind. `None` when there is none or the bus has gone.
+    pub fn try_recv(&mut self) -> Option<BusEvent> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => return Some(event),
+                Err(TryRecvError::Lagged(missed)) => self.lagged(missed),
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Count and log events missed.
+    ///
+    /// * `missed`: events overwritten before they were received.
+    fn lagged(&mut self, missed: u64) {
+        self.dropped += missed;
+        let topic: &'static str = self.topic.into();
+        warn!(
+            "{} fell behind on the bus and missed {missed} {topic} events, {} so far",
+            self.name, self.dropped
+        );
+    }
+
+    /// Topic subscribed to.
+    pub fn topic(&self) -> Topic {
+        self.topic
+    }
+
+    /// Events missed so far by falling behind.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
+    };
+
+    /// Trigger event of a camera on the centre bed.
+    ///
+    /// * `cam_id`: bed position of the camera.
+    fn trigger(cam_id: u8) -> BusEvent {
+        BusEvent::CameraTrigger(TriggerMessage::new(Utc::now(), cam_id, CropBed::Centre))
+    }
+
+    /// Bed position of the camera of a trigger event.
+    ///
+    /// * `event`: event received.
+    fn cam_id(event: Option<BusEvent>) -> Option<u8> {
+        match event {
+            Some(BusEvent::CameraTrigger(trigger)) => Some(trigger.cam_id),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    /// Every subscriber of a topic receives every event in order, and only
+    /// the events of its topic.
+    async fn test_multiple_subscribers() {
+        let bus = MessageBus::new();
+        let mut lighting = bus.subscribe(Topic::CameraTrigger, "lighting");
+        let mut recorder = bus.clone().subscribe(Topic::CameraTrigger, "recorder");
+        let mut power = bus.subscribe(Topic::GroundSpeed, "power");
+        assert_eq!(bus.publish(trigger(1)), 2);
+        assert_eq!(bus.clone().publish(trigger(2)), 2);
+        assert_eq!(bus.publish(BusEvent::GroundSpeed(Some(GroundSpeed::from_mps(1.5)))), 1);
+        assert_eq!(bus.publish(BusEvent::ShutdownRequested), 0);
+
+        for subscriber in [&mut lighting, &mut recorder] {
+            assert_eq!(cam_id(subscriber.recv().await), Some(1));
+            assert_eq!(cam_id(subscriber.recv().await), Some(2));
+            assert!(subscriber.try_recv().is_none());
+            assert_eq!(subscriber.dropped(), 0);
+        }
+        let Some(BusEvent::GroundSpeed(Some(speed))) = power.recv().await else {
+            panic!("Ground speed not received");
+        };
+        assert!((speed.mps() - 1.5).abs() < f64::EPSILON);
+        assert!(power.try_recv().is_none());
+        assert_eq!(power.topic(), Topic::GroundSpeed);
+    }
+
+    #[tokio::test]
+    /// A subscriber falling behind misses the oldest events and counts
+    /// them, without holding up the publisher or the other subscribers.
+    async fn test_slow_subscriber_lag() {
+        let bus = MessageBus::with_capacity(4);
+        let mut fast = bus.subscribe(Topic::CameraTrigger, "fast");
+        let mut slow = bus.subscribe(Topic::CameraTrigger, "slow");
+        for position in 0..10 {
+            bus.publish(trigger(position));
+            assert_eq!(cam_id(fast.recv().await), Some(position));
+        }
+        assert_eq!(cam_id(slow.recv().await), Some(6));
+        assert_eq!(slow.dropped(), 6);
+        assert_eq!(
+            (1..4).map(|_| cam_id(slow.try_recv())).collect::<Vec<_>>(),
+            [Some(7), Some(8), Some(9)]
+        );
+        assert_eq!((fast.dropped(), slow.dropped()), (0, 6));
+
+        drop(bus);
+        assert!(slow.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    /// A shutdown requested on the bus stops every component waiting on it,
+    /// and the tasks waiting end without stopping once the bus has gone.
+    async fn test_shutdown_propagation() {
+        let bus = MessageBus::new();
+        let stopped: Vec<Arc<AtomicBool>> = (0..3).map(|_| Arc::new(AtomicBool::new(false))).collect();
+        let waiting: Vec<JoinHandle<()>> = stopped
+            .iter()
+            .map(|stopped| {
+                let stopped = stopped.clone();
+                bus.on_shutdown("component", move || stopped.store(true, Ordering::Relaxed))
+            })
+            .collect();
+        let mut camera_array = bus.subscribe(Topic::ShutdownRequested, "camera array");
+        bus.publish(trigger(0));
+        assert!(stopped.iter().all(|stopped| !stopped.load(Ordering::Relaxed)));
+
+        assert_eq!(bus.request_shutdown(), 4);
+        for task in waiting {
+            tokio::time::timeout(Duration::from_secs(1), task)
+                .await
+                .expect("Shutdown did not propagate")
+                .unwrap();
+        }
+        assert!(stopped.iter().all(|stopped| stopped.load(Ordering::Relaxed)));
+        assert!(matches!(camera_array.try_recv(), Some(BusEvent::ShutdownRequested)));
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let task_stopped = stopped.clone();
+        let waiting = bus.on_shutdown("late", move || task_stopped.store(true, Ordering::Relaxed));
+        drop(bus);
+        waiting.await.unwrap();
+        assert!(!stopped.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,254 @@
+use crate::messages::{control::response::ControlResponse, encoding::Encoding};
+use crate::utils::{
+    net::{FrameRead, FramedCodec, Framing},
+    responses::parse_response,
+};
+use serde::Serialize;
+use std::{io, net::SocketAddr, time::Duration};
+use tokio::{
+    io::BufReader,
+    net::{
+        lookup_host,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream, ToSocketAddrs, UdpSocket,
+    },
+};
+
+/// Time waited for a component to answer a message, when not set on the
+/// client.
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Size in bytes of the largest response datagram read, the most UDP
+/// carries.
+const MAX_RESPONSE_DATAGRAM_BYTES: usize = 65_535;
+
+/// Socket a client sends its messages on.
+#[derive(Debug)]
+enum Connection {
+    /// Connection carrying framed documents both ways.
+    Tcp {
+        /// Read half, buffered for the framing.
+        reader: BufReader<OwnedReadHalf>,
+        /// Write half.
+        writer: OwnedWriteHalf,
+        /// Framing of the connection.
+        codec: FramedCodec,
+    },
+    /// Socket connected to the component, one json document per datagram.
+    Udp(UdpSocket),
+}
+
+/// Sends messages to a component over its message socket and reads the
+/// responses, as the AI system does. Used by the tools driving the
+/// components on the bench.
+#[derive(Debug)]
+pub struct ComponentClient {
+    /// Socket the messages are sent on.
+    connection: Connection,
+    /// Encoding of the messages and responses.
+    encoding: Encoding,
+    /// Time waited for each response.
+    timeout: Duration,
+}
+
+/// Error returned when a socket fails part way through an exchange.
+///
+/// * `kind`: what went wrong.
+/// * `message`: description of the failure.
+fn exchange_error(kind: io::ErrorKind, message: impl Into<String>) -> io::Error {
+    io::Error::new(kind, message.into())
+}
+
+impl ComponentClient {
+    /// Connect to a component over TCP. Panics for binary encodings framed
+    /// by lines, as the component would.
+    ///
+    /// * `address`: address of the message socket, e.g. `127.0.0.1:17652`.
+    /// * `encoding`: encoding the component is configured with.
+    /// * `framing`: framing the component is configured with, the default
+    ///   of the encoding when not set.
+    pub async fn connect_tcp(
+        address: impl ToSocketAddrs,
+        encoding: Encoding,
+        framing: Option<Framing>,
+    ) -> io::Result<Self> {
+        let codec = FramedCodec::new(encoding.framing_or(framing));
+        let (reader, writer) = TcpStream::connect(address).await?.into_split();
+        Ok(Self {
+            connection: Connection::Tcp {
+                reader: BufReader::new(reader),
+                writer,
+                codec,
+            },
+            encoding,
+            timeout: DEFAULT_RESPONSE_TIMEOUT,
+        })
+    }
+
+    /// Connect to a component over UDP, datagrams being json whatever the
+    /// encoding of its TCP connections.
+    ///
+    /// * `address`: address of the message socket, e.g. `127.0.0.1:17652`.
+    pub async fn connect_udp(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let target = lookup_host(address)
+            .await?
+            .next()
+            .ok_or_else(|| exchange_error(io::ErrorKind::NotFound, "Address did not resolve"))?;
+        let local: SocketAddr = if target.is_ipv4() {
+            "0.0.0.0:0".parse().expect("Unspecified address is valid")
+        } else {
+            "[::]:0".parse().expect("Unspecified address is valid")
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(target).await?;
+        Ok(Self {
+            connection: Connection::Udp(socket),
+            encoding: Encoding::Json,
+            timeout: DEFAULT_RESPONSE_TIMEOUT,
+        })
+    }
+
+    /// Set the time waited for each response.
+    ///
+    /// * `timeout`: time waited.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Encoding of the messages and responses.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Send a message and wait for the component to answer it.
+    ///
+    /// * `message`: message, bare or in an envelope.
+    pub async fn send<T: Serialize>(&mut self, message: &T) -> io::Result<ControlResponse> {
+        let document = self.encoding.encode(message);
+        self.exchange(&document).await
+    }
+
+    /// Send a line of json as it was received, e.g. from a journal, written
+    /// in the encoding of the client. Lines that are not json are sent as
+    /// they are, to be refused as they were originally.
+    ///
+    /// * `line`: line without its new line.
+    pub async fn send_json_line(&mut self, line: &str) -> io::Result<ControlResponse> {
+        let document = match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(message) if self.encoding.is_binary() => self.encoding.encode(&message),
+            _ => line.as_bytes().to_vec(),
+        };
+        self.exchange(&document).await
+    }
+
+    /// Send one document and read the response to it.
+    ///
+    /// * `document`: document without any framing.
+    async fn exchange(&mut self, document: &[u8]) -> io::Result<ControlResponse> {
+        let timed_out = |_| exchange_error(io::ErrorKind::TimedOut, "No response from the component");
+        match &mut self.connection {
+            Connection::Tcp { reader, writer, codec } => {
+                codec.write_frame(writer, document).await?;
+                let mut frame = Vec::new();
+                let read = tokio::time::timeout(self.timeout, codec.read_frame(reader, &mut frame))
+                    .await
+                    .map_err(timed_out)??;
+                if read != FrameRead::Frame {
+                    return Err(exchange_error(
+                        io::ErrorKind::UnexpectedEof,
+                        "Component closed the connection without responding",
+                    ));
+                }
+                self.encoding
+                    .decode(&frame)
+                    .map_err(|e| exchange_error(io::ErrorKind::InvalidData, e.to_string()))
+            }
+            Connection::Udp(socket) => {
+                socket.send(document).await?;
+                let mut datagram = vec![0; MAX_RESPONSE_DATAGRAM_BYTES];
+                let length = tokio::time::timeout(self.timeout, socket.recv(&mut datagram))
+                    .await
+                    .map_err(timed_out)??;
+                parse_response(&datagram[..length])
+                    .map_err(|e| exchange_error(io::ErrorKind::InvalidData, e.to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    #[tokio::test]
+    /// Messages are framed in the encoding of the connection and each is
+    /// answered on it, datagrams being answered to the sender.
+    async fn test_component_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let component = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = socket.into_split();
+            let mut reader = BufReader::new(reader);
+            let mut codec = FramedCodec::new(Framing::LengthPrefixed);
+            let mut frame = Vec::new();
+            let mut received = Vec::new();
+            while codec.read_frame(&mut reader, &mut frame).await.unwrap() == FrameRead::Frame {
+                received.push(Encoding::Cbor.decode::<serde_json::Value>(&frame).unwrap());
+                frame.clear();
+                let response = Encoding::Cbor.encode(&ControlResponse::accepted(None));
+                codec.write_frame(&mut writer, &response).await.unwrap();
+                writer.flush().await.unwrap();
+            }
+            received
+        });
+        let mut client = ComponentClient::connect_tcp(address, Encoding::Cbor, None)
+            .await
+            .unwrap();
+        assert!(client
+            .send(&serde_json::json!({"cam_id": 1}))
+            .await
+            .unwrap()
+            .is_accepted());
+        assert!(client.send_json_line("{\"cam_id\":2}").await.unwrap().is_accepted());
+        drop(client);
+        let received = component.await.unwrap();
+        assert_eq!(
+            received,
+            [serde_json::json!({"cam_id": 1}), serde_json::json!({"cam_id": 2})]
+        );
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+        let component = tokio::spawn(async move {
+            let mut datagram = vec![0; 1024];
+            let (length, sender) = socket.recv_from(&mut datagram).await.unwrap();
+            let response = serde_json::to_vec(&ControlResponse::duplicate(None)).unwrap();
+            socket.send_to(&response, sender).await.unwrap();
+            datagram.truncate(length);
+            datagram
+        });
+        let mut client = ComponentClient::connect_udp(address).await.unwrap();
+        assert_eq!(client.encoding(), Encoding::Json);
+        assert_eq!(
+            client.send_json_line("not json").await.unwrap(),
+            ControlResponse::duplicate(None)
+        );
+        assert_eq!(component.await.unwrap(), b"not json");
+    }
+
+    #[tokio::test]
+    /// A component that never answers times out rather than hanging the
+    /// client.
+    async fn test_component_client_timeout() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut client = ComponentClient::connect_udp(socket.local_addr().unwrap())
+            .await
+            .unwrap()
+            .with_timeout(Duration::from_millis(50));
+        let error = client.send(&serde_json::json!({})).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+}
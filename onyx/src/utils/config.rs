@@ -0,0 +1,535 @@
+use serde::de::DeserializeOwned;
+use serde_yaml::Value;
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Why a config file could not be loaded.
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    /// The file is missing or could not be read.
+    #[error("Could not read the config file {path:?}: {error}")]
+    Read {
+        /// Path of the file.
+        path: PathBuf,
+        /// Error reading it.
+        #[source]
+        error: std::io::Error,
+    },
+    /// The file is not yaml, or does not fit the config.
+    #[error("Invalid config file {path:?}{}", invalid_detail(.field.as_deref(), .location.is_some(), .message))]
+    Invalid {
+        /// Path of the file.
+        path: PathBuf,
+        /// Dotted path of the field that is wrong, i.e. `timing.lead_ms`,
+        /// when the error is inside one.
+        field: Option<String>,
+        /// Line and column the error is at, when known.
+        location: Option<(usize, usize)>,
+        /// Error from serde.
+        message: String,
+    },
+}
+
+impl ConfigError {
+    /// Path of the file that could not be loaded.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Read { path, .. } | Self::Invalid { path, .. } => path,
+        }
+    }
+
+    /// Dotted path of the field that is wrong, when known.
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            Self::Read { .. } => None,
+            Self::Invalid { field, .. } => field.as_deref(),
+        }
+    }
+
+    /// Line and column the error is at, when known.
+    pub fn location(&self) -> Option<(usize, usize)> {
+        match self {
+            Self::Read { .. } => None,
+            Self::Invalid { location, .. } => *location,
+        }
+    }
+
+    /// Error for a file that is not yaml or does not fit the config.
+    ///
+    /// * `path`: path of the file.
+    /// * `error`: error deserialising it, with the path to the field.
+    fn invalid(path: &Path, error: serde_path_to_error::Error<serde_yaml::Error>) -> Self {
+        let field = error.path().iter().next().map(|_| error.path().to_string());
+        let error = error.into_inner();
+        Self::Invalid {
+            path: path.to_path_buf(),
+            field,
+            location: error.location().map(|location| (location.line(), location.column())),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// What follows the path of a file that is not yaml or does not fit the
+/// config. Errors read from the file name the field and where it is in
+/// their message, those from layered files know neither.
+///
+/// * `field`: dotted path of the field that is wrong, when known.
+/// * `located`: whether the line and column of the error are known.
+/// * `message`: error from serde.
+fn invalid_detail(field: Option<&str>, located: bool, message: &str) -> String {
+    match field {
+        Some(field) if !located => format!(", {field}: {message}"),
+        _ => format!(": {message}"),
+    }
+}
+
+/// Contents of a config file.
+///
+/// * `path`: path of the file.
+fn read(path: &Path) -> Result<String, ConfigError> {
+    fs::read_to_string(path).map_err(|error| ConfigError::Read {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// Load a config from a yaml file, the error naming the field that is wrong
+/// and the line and column it is at.
+///
+/// * `path`: path of the file.
+pub fn load_yaml<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, ConfigError> {
+    let path = path.as_ref();
+    let yaml = read(path)?;
+    serde_path_to_error::deserialize(serde_yaml::Deserializer::from_str(&yaml))
+        .map_err(|error| ConfigError::invalid(path, error))
+}
+
+/// Lay a tree of values over another, mappings merged key by key and any
+/// other value replacing the one under it.
+///
+/// * `base`: values laid over.
+/// * `layer`: values taking precedence.
+fn merge(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Mapping(base), Value::Mapping(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(under) => merge(under, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+/// Load a config from a yaml file laid over a file of defaults, so a file
+/// for each machine only holds what differs from the fleet. Mappings are
+/// merged key by key, anything else in the file replaces the default. As
+/// the files are merged first, errors in the config name the field but
+/// not the line.
+///
+/// * `defaults`: path of the file of defaults.
+/// * `path`: path of the file taking precedence.
+pub fn load_yaml_layered<T: DeserializeOwned>(
+    defaults: impl AsRef<Path>,
+    path: impl AsRef<Path>,
+) -> Result<T, ConfigError> {
+    let path = path.as_ref();
+    let mut merged: Value = load_yaml(defaults)?;
+    merge(&mut merged, load_yaml(path)?);
+    serde_path_to_error::deserialize(merged).map_err(|error| ConfigError::invalid(path, error))
+}
+
+/// Something wrong with a config that loads, or with a file it refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    /// Dotted path of the field that is wrong, i.e. `pdm_config_files.0`,
+    /// through the files it refers to.
+    pub field: String,
+    /// What is wrong with it.
+    pub message: String,
+}
+
+impl Display for ConfigProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Problems found validating a config file and the files it refers to,
+/// empty when the component can be started from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Path of the file validated.
+    path: PathBuf,
+    /// Problems in the order they were found.
+    problems: Vec<ConfigProblem>,
+}
+
+impl ValidationReport {
+    /// Report without problems.
+    ///
+    /// * `path`: path of the file validated.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            problems: Vec::new(),
+        }
+    }
+
+    /// Path of the file validated.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Problems in the order they were found.
+    pub fn problems(&self) -> &[ConfigProblem] {
+        &self.problems
+    }
+
+    /// Fields with a problem, in the order they were found.
+    pub fn fields(&self) -> Vec<&str> {
+        self.problems.iter().map(|problem| problem.field.as_str()).collect()
+    }
+
+    /// Whether no problems were found.
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Add a problem.
+    ///
+    /// * `field`: dotted path of the field that is wrong.
+    /// * `message`: what is wrong with it.
+    pub fn push(&mut self, field: impl Display, message: impl Into<String>) {
+        self.problems.push(ConfigProblem {
+            field: field.to_string(),
+            message: message.into(),
+        });
+    }
+
+    /// Add the problems of a config held in a field, under the field.
+    ///
+    /// * `field`: dotted path of the field holding the config.
+    /// * `nested`: report of the config.
+    pub fn nest(&mut self, field: impl Display, nested: ValidationReport) {
+        for problem in nested.problems {
+            self.push(format!("{field}.{}", problem.field), problem.message);
+        }
+    }
+
+    /// Load and validate a config file a field refers to, adding its
+    /// problems under the field. Returns the config when it loads, for
+    /// checks across the files.
+    ///
+    /// * `field`: dotted path of the field holding the path.
+    /// * `path`: path of the file.
+    pub fn validate_file<T: DeserializeOwned + Validate>(&mut self, field: impl Display, path: &Path) -> Option<T> {
+        match load_yaml::<T>(path) {
+            Ok(config) => {
+                let config = config.loaded_from(path);
+                let mut nested = ValidationReport::new(path);
+                config.validate(&mut nested);
+                self.nest(field, nested);
+                Some(config)
+            }
+            Err(e) => {
+                self.push(field, e.to_string());
+                None
+            }
+        }
+    }
+
+    /// Check a port can be listened on.
+    ///
+    /// * `field`: dotted path of the field holding the port.
+    /// * `port`: port in the config.
+    pub fn check_port(&mut self, field: impl Display, port: i32) {
+        if !(1..=i32::from(u16::MAX)).contains(&port) {
+            self.push(field, format!("{port} is not a port, ports run 1 to {}", u16::MAX));
+        }
+    }
+
+    /// Check the directory a file is written to exists, relative paths
+    /// resolved against the working directory as the components do.
+    ///
+    /// * `field`: dotted path of the field holding the path.
+    /// * `path`: path of the file.
+    pub fn check_parent_dir(&mut self, field: impl Display, path: &Path) {
+        match path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            Some(parent) if !parent.is_dir() => {
+                self.push(field, format!("directory {parent:?} does not exist"));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.problems.is_empty() {
+            return write!(f, "Config file {:?} is valid", self.path);
+        }
+        write!(f, "Config file {:?} has {} problems:", self.path, self.problems.len())?;
+        for problem in &self.problems {
+            write!(f, "\n  {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationReport {}
+
+/// Config checked beyond fitting its type before a component is started
+/// from it, reading the files it refers to without touching any hardware.
+pub trait Validate {
+    /// Add what is wrong with the config to a report.
+    ///
+    /// * `report`: report of the file the config was loaded from.
+    fn validate(&self, report: &mut ValidationReport);
+
+    /// Adjust a config loaded from a file the way its component does when
+    /// started from the file, i.e. resolving paths against the directory
+    /// of the file. Left as it is by default.
+    ///
+    /// * `path`: path of the file.
+    fn loaded_from(self, _path: &Path) -> Self
+    where
+        Self: Sized,
+    {
+        self
+    }
+}
+
+/// Load a config file and validate it and the files it refers to.
+///
+/// * `path`: path of the file.
+pub fn validate_file<T: DeserializeOwned + Validate>(path: impl AsRef<Path>) -> ValidationReport {
+    let path = path.as_ref();
+    let mut report = ValidationReport::new(path);
+    match load_yaml::<T>(path) {
+        Ok(config) => config.loaded_from(path).validate(&mut report),
+        Err(e) => report.push(e.field().unwrap_or("file"), e.to_string()),
+    }
+    report
+}
+
+/// Config of a device or component kept in a yaml file, loaded through
+/// [`load_yaml`] the same way for all of them.
+pub trait ConfigFile: DeserializeOwned + Validate {
+    /// Load the config from a yaml file, adjusted as its component does
+    /// when started from the file. The error names the field that is wrong
+    /// and where it is.
+    ///
+    /// * `path`: path of the file.
+    fn load_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        load_yaml::<Self>(path).map(|config| config.loaded_from(path))
+    }
+
+    /// Load the config from a yaml file and validate it and the files it
+    /// refers to, refusing it with the problems found.
+    ///
+    /// * `path`: path of the file.
+    fn load_validated(path: impl AsRef<Path>) -> Result<Self, ValidationReport> {
+        let path = path.as_ref();
+        let mut report = ValidationReport::new(path);
+        match Self::load_file(path) {
+            Ok(config) => {
+                config.validate(&mut report);
+                if report.is_valid() {
+                    Ok(config)
+                } else {
+                    Err(report)
+                }
+            }
+            Err(e) => {
+                report.push(e.field().unwrap_or("file"), e.to_string());
+                Err(report)
+            }
+        }
+    }
+}
+
+impl<T: DeserializeOwned + Validate> ConfigFile for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use serde::Deserialize;
+    use uuid::Uuid;
+
+    /// Rate of a part of the test config.
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Timing {
+        /// Frames a second.
+        fps: u32,
+        /// Lead in milliseconds.
+        lead_ms: u64,
+    }
+
+    /// Config shaped as the component configs are.
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct TestConfig {
+        /// Name of the component.
+        name: String,
+        /// Port it listens on.
+        port: u16,
+        /// Timing of the component.
+        timing: Timing,
+    }
+
+    impl Validate for TestConfig {
+        fn validate(&self, report: &mut ValidationReport) {
+            report.check_port("port", i32::from(self.port));
+            if self.timing.fps == 0 {
+                report.push("timing.fps", "no frames are captured at 0 fps");
+            }
+        }
+    }
+
+    /// Write yaml to a new file in the temporary directory.
+    ///
+    /// * `yaml`: contents of the file.
+    fn write(yaml: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("onyx-config-{}.yaml", Uuid::new_v4()));
+        fs::write(&path, yaml).unwrap();
+        path
+    }
+
+    #[test]
+    /// A well formed file loads into the config.
+    fn test_load_yaml() {
+        let path = write("name: power\nport: 17650\ntiming:\n  fps: 10\n  lead_ms: 20\n");
+        let config: TestConfig = load_yaml(&path).unwrap();
+        assert_eq!(
+            config,
+            TestConfig {
+                name: String::from("power"),
+                port: 17650,
+                timing: Timing { fps: 10, lead_ms: 20 },
+            }
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    /// A config is loaded validated, and refused with its problems when it
+    /// does not load or is invalid.
+    fn test_load_validated() {
+        let path = write("name: power\nport: 17650\ntiming:\n  fps: 10\n  lead_ms: 20\n");
+        assert_eq!(TestConfig::load_validated(&path).unwrap().port, 17650);
+        fs::write(&path, "name: power\nport: 17650\ntiming:\n  fps: 0\n  lead_ms: 20\n").unwrap();
+        let report = TestConfig::load_validated(&path).unwrap_err();
+        assert_eq!(report.problems().len(), 1, "{report}");
+        fs::write(&path, "name: power\nport: seventeen\n").unwrap();
+        let report = TestConfig::load_validated(&path).unwrap_err();
+        assert!(report.to_string().contains("port"), "{report}");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[rstest]
+    #[case(
+        "name: power\nport: seventeen\ntiming:\n  fps: 10\n  lead_ms: 20\n",
+        Some("port"),
+        Some(2)
+    )]
+    #[case(
+        "name: power\nport: 17650\ntiming:\n  fps: 10\n  lead_ms: -20\n",
+        Some("timing.lead_ms"),
+        Some(5)
+    )]
+    #[case("name: power\nport: 17650\ntiming:\n  fps: 10\n", Some("timing"), None)]
+    #[case("name: power\nport: [17650\n", None, None)]
+    /// Malformed files are refused naming the field that is wrong and where
+    /// it is in the file.
+    fn test_malformed_yaml(#[case] yaml: &str, #[case] field: Option<&str>, #[case] line: Option<usize>) {
+        let path = write(yaml);
+        let error = load_yaml::<TestConfig>(&path).unwrap_err();
+        assert_eq!(error.field(), field, "{error}");
+        if let Some(line) = line {
+            assert_eq!(error.location().map(|(line, _)| line), Some(line), "{error}");
+        }
+        assert_eq!(error.path(), path);
+        let message = error.to_string();
+        assert!(message.contains(&format!("{path:?}")), "{message}");
+        if let Some(field) = field {
+            assert!(message.contains(field), "{message}");
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    /// A missing file is a read error naming the file.
+    fn test_missing_file() {
+        let path = std::env::temp_dir().join(format!("onyx-config-{}.yaml", Uuid::new_v4()));
+        let error = load_yaml::<TestConfig>(&path).unwrap_err();
+        assert!(matches!(error, ConfigError::Read { .. }), "{error}");
+        assert!(error.to_string().contains(&format!("{path:?}")), "{error}");
+    }
+
+    #[test]
+    /// A file laid over defaults only replaces what it sets, mappings
+    /// merged key by key, and errors still name the field.
+    fn test_load_yaml_layered() {
+        let defaults = write("name: power\nport: 17650\ntiming:\n  fps: 10\n  lead_ms: 20\n");
+        let machine = write("port: 17652\ntiming:\n  lead_ms: 35\n");
+        let config: TestConfig = load_yaml_layered(&defaults, &machine).unwrap();
+        assert_eq!(
+            config,
+            TestConfig {
+                name: String::from("power"),
+                port: 17652,
+                timing: Timing { fps: 10, lead_ms: 35 },
+            }
+        );
+
+        let bad = write("timing:\n  fps: fast\n");
+        let error = load_yaml_layered::<TestConfig>(&defaults, &bad).unwrap_err();
+        assert_eq!(error.field(), Some("timing.fps"), "{error}");
+        assert_eq!(error.path(), bad);
+        assert!(error.to_string().contains("timing.fps"), "{error}");
+        for path in [defaults, machine, bad] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    /// A config that loads is checked beyond its type, one that does not is
+    /// a single problem naming why, and the report names every field.
+    fn test_validate_file() {
+        let valid = write("name: power\nport: 17650\ntiming:\n  fps: 10\n  lead_ms: 20\n");
+        let report = validate_file::<TestConfig>(&valid);
+        assert!(report.is_valid(), "{report}");
+        assert_eq!(report.to_string(), format!("Config file {valid:?} is valid"));
+
+        let broken = write("name: power\nport: 0\ntiming:\n  fps: 0\n  lead_ms: 20\n");
+        let report = validate_file::<TestConfig>(&broken);
+        assert_eq!(report.fields(), ["port", "timing.fps"]);
+        let message = report.to_string();
+        assert!(message.contains("has 2 problems"), "{message}");
+        assert!(message.contains("\n  port: 0 is not a port"), "{message}");
+
+        let malformed = write("name: power\nport: 17650\ntiming:\n  fps: fast\n  lead_ms: 20\n");
+        let report = validate_file::<TestConfig>(&malformed);
+        assert_eq!(report.fields(), ["timing.fps"]);
+
+        let mut parent = ValidationReport::new("parent.yaml");
+        assert!(parent.validate_file::<TestConfig>("configs.1", &broken).is_some());
+        assert!(parent.validate_file::<TestConfig>("configs.2", &malformed).is_none());
+        parent.check_parent_dir("log", &std::env::temp_dir().join("missing-onyx-dir/log.jsonl"));
+        parent.check_parent_dir("journal", Path::new("journal.jsonl"));
+        assert_eq!(parent.fields(), ["configs.1.port", "configs.1.timing.fps", "configs.2", "log"]);
+        for path in [valid, broken, malformed] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,348 @@
+#[cfg(feature = "canbus")]
+use crate::components::crop_bed::actuating::{lighting::CropBedLightingConfig, power::CropBedPowerConfig};
+use crate::{
+    components::crop_bed::sensing::camera_array::CameraArrayConfig,
+    devices::hardware::{
+        camera::OnyxCameraConfig,
+        pdm::{ChannelLoad, PdmAddress, PdmConfig},
+    },
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+};
+
+/// Frames per second the crop bed cameras capture at.
+pub const DEFAULT_FPS: u32 = 3;
+
+/// Directory the camera arrays save their images to.
+pub const DEFAULT_IMAGE_PATH: &str = "./images";
+
+/// Address and bed position of each camera on the machine, two to a crop
+/// bed in order.
+const CAMERAS: [(Ipv4Addr, u8); 6] = [
+    (Ipv4Addr::new(169, 254, 8, 10), 0),
+    (Ipv4Addr::new(169, 254, 8, 11), 1),
+    (Ipv4Addr::new(169, 254, 8, 12), 0),
+    (Ipv4Addr::new(169, 254, 8, 13), 1),
+    (Ipv4Addr::new(169, 254, 8, 14), 0),
+    (Ipv4Addr::new(169, 254, 8, 15), 1),
+];
+
+/// Canbus interface, port and channel map of the power component of each
+/// crop bed, the maps following how each harness was wired.
+#[cfg(feature = "canbus")]
+const POWER: [(u8, &str, i32, Option<&str>); 3] = [
+    (0, "can0", 17650, None),
+    (1, "can1", 17651, Some("1-2=11-12@0,3-14=13-24@1")),
+    (2, "can2", 17652, Some("1-12=24-13@1,13-24=12-1@0")),
+];
+
+/// Config file generated, with where it goes in the crate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedConfig {
+    /// Path relative to the crate.
+    pub path: PathBuf,
+    /// Contents of the file.
+    pub yaml: String,
+}
+
+impl GeneratedConfig {
+    /// Serialise a config to go at a path.
+    ///
+    /// * `path`: path relative to the crate.
+    /// * `config`: config written to the file.
+    fn new<T: Serialize>(path: impl Into<PathBuf>, config: &T) -> Self {
+        Self {
+            path: path.into(),
+            yaml: to_yaml(config),
+        }
+    }
+}
+
+/// Yaml of a config as it is written to its file.
+///
+/// * `config`: config to serialise.
+pub fn to_yaml<T: Serialize>(config: &T) -> String {
+    serde_yaml::to_string(config).expect("Failed to serialise config")
+}
+
+/// Write a config to a yaml file, creating the directory it is in and
+/// replacing anything already there.
+///
+/// * `path`: path of the file.
+/// * `config`: config to write.
+pub fn write_config<T: Serialize>(path: impl AsRef<Path>, config: &T) -> std::io::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, to_yaml(config))
+}
+
+/// Parse a map of channels onto the channels of PDMs, written as comma
+/// separated `channels=channels@pdm` entries where either side is a
+/// channel or a range counting up or down, e.g. `1-12=24-13@1` maps
+/// channel 1 to channel 24 on PDM 1 through to channel 12 to channel 13.
+///
+/// Returns the channel on the PDM and the PDM for each channel.
+///
+/// * `spec`: map to parse.
+pub fn parse_channel_map(spec: &str) -> Result<HashMap<u8, (u8, u8)>, String> {
+    let mut channel_map = HashMap::new();
+    for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (channels, targets) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("{entry} is not written as channels=channels@pdm"))?;
+        let (targets, pdm_id) = targets
+            .split_once('@')
+            .ok_or_else(|| format!("{entry} does not name a PDM after the @"))?;
+        let pdm_id = parse_number(pdm_id)?;
+        let (channels, targets) = (parse_range(channels)?, parse_range(targets)?);
+        if channels.len() != targets.len() {
+            return Err(format!(
+                "{entry} maps {} channels onto {}",
+                channels.len(),
+                targets.len()
+            ));
+        }
+        for (channel, target) in channels.into_iter().zip(targets) {
+            if channel_map.insert(channel, (target, pdm_id)).is_some() {
+                return Err(format!("Channel {channel} is mapped twice"));
+            }
+        }
+    }
+    if channel_map.is_empty() {
+        return Err(String::from("The channel map is empty"));
+    }
+    Ok(channel_map)
+}
+
+/// Channels of a single channel or a range, in the order written.
+///
+/// * `range`: channel or range, e.g. `3` or `24-13`.
+fn parse_range(range: &str) -> Result<Vec<u8>, String> {
+    match range.split_once('-') {
+        Some((first, last)) => {
+            let (first, last) = (parse_number(first)?, parse_number(last)?);
+            Ok(if first <= last {
+                (first..=last).collect()
+            } else {
+                (last..=first).rev().collect()
+            })
+        }
+        None => parse_number(range).map(|channel| vec![channel]),
+    }
+}
+
+/// Channel or PDM id of a channel map.
+///
+/// * `number`: number to parse.
+fn parse_number(number: &str) -> Result<u8, String> {
+    let number = number.trim();
+    number
+        .parse()
+        .map_err(|e| format!("{number} is not a channel or PDM id: {e}"))
+}
+
+/// Every config file kept in the crate, as the machine is wired. The
+/// configs of the actuating components are left out of a build without
+/// the `canbus` feature.
+pub fn canonical_configs() -> Vec<GeneratedConfig> {
+    let devices = Path::new("config/devices/crop_bed");
+    let components = Path::new("config/components/crop_bed");
+    let mut configs: Vec<GeneratedConfig> = CAMERAS
+        .iter()
+        .enumerate()
+        .map(|(id, (ip_address, bed_location_id))| {
+            GeneratedConfig::new(
+                devices.join(format!("camera_{id}.yaml")),
+                &OnyxCameraConfig::crop_bed(*ip_address, DEFAULT_FPS, *bed_location_id),
+            )
+        })
+        .collect();
+
+    for (name, address, bed_location_id, load) in [
+        ("pdm_0", PdmAddress::Pdm30, 0, ChannelLoad::Solenoid),
+        ("pdm_1", PdmAddress::Pdm31, 1, ChannelLoad::Solenoid),
+        ("pdm_utilities", PdmAddress::Pdm30, 0, ChannelLoad::Light),
+    ] {
+        configs.push(GeneratedConfig::new(
+            devices.join(format!("{name}.yaml")),
+            &PdmConfig::new(address, bed_location_id).with_channels(load),
+        ));
+    }
+
+    for crop_bed_id in 0..3 {
+        let config = (0..2).fold(
+            CameraArrayConfig::new(String::from(DEFAULT_IMAGE_PATH), crop_bed_id),
+            |config, bed_position| {
+                let camera_id = crop_bed_id * 2 + bed_position;
+                config.add_camera_config_file(
+                    format!("./config/devices/crop_bed/camera_{camera_id}.yaml"),
+                    bed_position,
+                )
+            },
+        );
+        configs.push(GeneratedConfig::new(
+            components.join(format!("sensing/camera_array/crop_bed_camera_array_{crop_bed_id}.yaml")),
+            &config,
+        ));
+    }
+
+    #[cfg(feature = "canbus")]
+    configs.extend(actuating_configs(components));
+    configs
+}
+
+/// Configs of the power and lighting components of every crop bed.
+///
+/// * `components`: directory of the component configs.
+#[cfg(feature = "canbus")]
+fn actuating_configs(components: &Path) -> Vec<GeneratedConfig> {
+    let mut configs = Vec::new();
+    for (crop_bed_id, canbus_id, port, channel_map) in POWER {
+        let config = |channel_map| {
+            CropBedPowerConfig::new(crop_bed_id, String::from(canbus_id), port, channel_map)
+                .add_pdm_config_file("./config/devices/crop_bed/pdm_0.yaml", 0)
+                .add_pdm_config_file("./config/devices/crop_bed/pdm_1.yaml", 1)
+        };
+        let channel_map = channel_map.map(|spec| parse_channel_map(spec).expect("Invalid canonical channel map"));
+        let power = components.join("actuating/power");
+        configs.push(GeneratedConfig::new(
+            power.join(format!("crop_bed_power_{crop_bed_id}.yaml")),
+            &config(channel_map),
+        ));
+        configs.push(GeneratedConfig::new(
+            power.join(format!("crop_bed_power_{crop_bed_id}_no_map.yaml")),
+            &config(None),
+        ));
+    }
+
+    let lighting = parse_channel_map("1-12=1-12@0")
+        .expect("Invalid canonical light channel map")
+        .into_iter()
+        .fold(
+            CropBedLightingConfig::new(0, String::from("can3"), 17653)
+                .add_pdm_config_file("./config/devices/crop_bed/pdm_utilities.yaml", 0)
+                .with_any_bed(),
+            |config, (channel, (pdm_channel, pdm_id))| config.map_light_channel(channel, pdm_id, pdm_channel),
+        );
+    configs.push(GeneratedConfig::new(
+        components.join("actuating/lighting/crop_bed_lighting.yaml"),
+        &lighting,
+    ));
+    configs
+}
+
+/// Write every config file kept in the crate under a directory.
+/// Returns the paths written.
+///
+/// * `root`: directory standing in for the crate.
+pub fn write_canonical_configs(root: impl AsRef<Path>) -> std::io::Result<Vec<PathBuf>> {
+    canonical_configs()
+        .into_iter()
+        .map(|config| {
+            let path = root.as_ref().join(&config.path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, config.yaml)?;
+            Ok(path)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::paths::repo_relative;
+    use rstest::rstest;
+    use uuid::Uuid;
+
+    #[test]
+    /// The config files in the crate are exactly what the generator writes,
+    /// so the format only changes along with the configs. After changing a
+    /// config on purpose regenerate them with
+    /// `cargo run -p configgen -- canonical --root onyx`.
+    fn test_canonical_configs_match_golden_files() {
+        let configs = canonical_configs();
+        assert_eq!(configs.len(), if cfg!(feature = "canbus") { 19 } else { 12 });
+        for config in configs {
+            let path = repo_relative(&config.path).unwrap_or_else(|e| panic!("{e}"));
+            let golden = std::fs::read_to_string(&path).unwrap();
+            assert_eq!(
+                config.yaml,
+                golden,
+                "{} differs from the generator",
+                config.path.display()
+            );
+        }
+    }
+
+    #[cfg(feature = "canbus")]
+    #[test]
+    /// Every generated config reads back as the config it was written from
+    /// and is valid. The files they refer to are resolved in the crate,
+    /// which holds the same files.
+    fn test_canonical_configs_round_trip() {
+        use crate::utils::config::{load_yaml, validate_file, Validate};
+        use serde::de::DeserializeOwned;
+
+        /// Read a generated config back, checking it writes out the same.
+        ///
+        /// * `path`: file written by the generator.
+        fn round_trip<T: DeserializeOwned + Serialize + Validate>(path: &Path) {
+            let config: T = load_yaml(path).unwrap_or_else(|e| panic!("{e}"));
+            assert_eq!(to_yaml(&config), std::fs::read_to_string(path).unwrap());
+            let report = validate_file::<T>(path);
+            assert!(report.is_valid(), "{report}");
+        }
+
+        let root = std::env::temp_dir().join(format!("onyx-configgen-{}", Uuid::new_v4()));
+        let written = write_canonical_configs(&root).unwrap();
+        assert_eq!(written.len(), canonical_configs().len());
+        for path in &written {
+            let name = path.file_name().unwrap().to_string_lossy();
+            if name.starts_with("camera_") {
+                round_trip::<OnyxCameraConfig>(path);
+            } else if name.starts_with("pdm_") {
+                round_trip::<PdmConfig>(path);
+            } else if name.starts_with("crop_bed_camera_array_") {
+                round_trip::<CameraArrayConfig>(path);
+            } else if name.starts_with("crop_bed_power_") {
+                round_trip::<CropBedPowerConfig>(path);
+            } else {
+                round_trip::<CropBedLightingConfig>(path);
+            }
+        }
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[rstest]
+    #[case("1-2=11-12@0, 3-14=13-24@1", &[(1, (11, 0)), (2, (12, 0)), (3, (13, 1)), (14, (24, 1))])]
+    #[case("1-12=24-13@1,13-24=12-1@0", &[(1, (24, 1)), (12, (13, 1)), (13, (12, 0)), (24, (1, 0))])]
+    #[case("5=7@2", &[(5, (7, 2))])]
+    /// Ranges map in the order written, up or down.
+    fn test_parse_channel_map(#[case] spec: &str, #[case] expected: &[(u8, (u8, u8))]) {
+        let channel_map = parse_channel_map(spec).unwrap();
+        for (channel, target) in expected {
+            assert_eq!(channel_map.get(channel), Some(target), "{spec}");
+        }
+    }
+
+    #[rstest]
+    #[case("", "empty")]
+    #[case("1-12", "channels=channels@pdm")]
+    #[case("1-12=13-24", "PDM")]
+    #[case("1-12=13-23@1", "maps 12 channels onto 11")]
+    #[case("1-2=1-2@0,2=3@1", "Channel 2 is mapped twice")]
+    #[case("1-x=1-2@0", "x is not a channel")]
+    /// Maps that cannot be read name what is wrong with them.
+    fn test_parse_channel_map_errors(#[case] spec: &str, #[case] expected: &str) {
+        let error = parse_channel_map(spec).unwrap_err();
+        assert!(error.contains(expected), "{error}");
+    }
+}
@@ -0,0 +1,241 @@
+use futures::FutureExt;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex},
+};
+#[cfg(feature = "health")]
+use {
+    axum::{extract::State, http::StatusCode, routing::get, Json, Router},
+    std::{io, net::TcpListener},
+};
+
+/// What a component reports about itself to the orchestrator.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct HealthReport {
+    /// Tasks of the component that panicked.
+    pub panicked: Vec<String>,
+    /// Whether the canbus socket is open and the PDMs are heard on it.
+    pub canbus_open: bool,
+    /// Whether the message sockets are bound and accepting.
+    pub listening: bool,
+    /// Whether each PDM, keyed by bed position, is initialised, verified
+    /// and reporting no fault.
+    pub pdms: BTreeMap<u8, bool>,
+}
+
+impl HealthReport {
+    /// Whether the component is alive, no task having panicked.
+    pub fn is_live(&self) -> bool {
+        self.panicked.is_empty()
+    }
+
+    /// Whether the component is alive and can take messages, with the
+    /// canbus open, its sockets bound and every PDM ready.
+    pub fn is_ready(&self) -> bool {
+        self.is_live() && self.canbus_open && self.listening && self.pdms.values().all(|ready| *ready)
+    }
+}
+
+/// Health of a running component, updated by the controller as it starts
+/// and as faults are detected and recovered from. Clones share the state.
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    /// Latest report, shared with the server.
+    report: Arc<Mutex<HealthReport>>,
+}
+
+impl Health {
+    /// Create the health of a component that has not started.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Change the report.
+    ///
+    /// * `update`: change made with the report locked.
+    fn update(&self, update: impl FnOnce(&mut HealthReport)) {
+        update(&mut self.report.lock().expect("Health poisoned"));
+    }
+
+    /// Set whether the canbus socket is open and the PDMs are heard on it.
+    ///
+    /// * `open`: whether it is open.
+    pub fn set_canbus_open(&self, open: bool) {
+        self.update(|report| report.canbus_open = open);
+    }
+
+    /// Set whether the message sockets are bound and accepting.
+    ///
+    /// * `listening`: whether they are.
+    pub fn set_listening(&self, listening: bool) {
+        self.update(|report| report.listening = listening);
+    }
+
+    /// Set whether a PDM is initialised, verified and reporting no fault.
+    ///
+    /// * `bed_position`: key of the PDM in the component.
+    /// * `ready`: whether it is ready.
+    pub fn set_pdm_ready(&self, bed_position: u8, ready: bool) {
+        self.update(|report| {
+            report.pdms.insert(bed_position, ready);
+        });
+    }
+
+    /// Record a task of the component that panicked, the component is no
+    /// longer alive.
+    ///
+    /// * `name`: name of the task.
+    pub fn task_panicked(&self, name: &str) {
+        self.update(|report| report.panicked.push(name.to_string()));
+    }
+
+    /// Latest report.
+    pub fn report(&self) -> HealthReport {
+        self.report.lock().expect("Health poisoned").clone()
+    }
+
+    /// Run a task, recording it when it panics. The panic carries on to
+    /// whatever joins the task.
+    ///
+    /// * `name`: name of the task.
+    /// * `task`: future run by the task.
+    pub async fn supervise<F: Future>(self, name: &'static str, task: F) -> F::Output {
+        match AssertUnwindSafe(task).catch_unwind().await {
+            Ok(output) => output,
+            Err(panic) => {
+                self.task_panicked(name);
+                std::panic::resume_unwind(panic)
+            }
+        }
+    }
+}
+
+/// Build the routes probed by the orchestrator, `/healthz` answering ok
+/// while the component is alive and `/readyz` while it can take messages,
+/// both with the report.
+///
+/// * `health`: health of the component.
+#[cfg(feature = "health")]
+pub fn router(health: Health) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(health)
+}
+
+/// Status code of a probe.
+///
+/// * `ok`: whether the probe passed.
+#[cfg(feature = "health")]
+fn probe_status(ok: bool) -> StatusCode {
+    if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+/// `GET /healthz`, unavailable once a task has panicked.
+#[cfg(feature = "health")]
+async fn healthz(State(health): State<Health>) -> (StatusCode, Json<HealthReport>) {
+    let report = health.report();
+    (probe_status(report.is_live()), Json(report))
+}
+
+/// `GET /readyz`, unavailable until the component has started and while a
+/// PDM or the canbus has a fault.
+#[cfg(feature = "health")]
+async fn readyz(State(health): State<Health>) -> (StatusCode, Json<HealthReport>) {
+    let report = health.report();
+    (probe_status(report.is_ready()), Json(report))
+}
+
+/// Serve the probes until the process exits. Served on the runtime of the
+/// component, so a runtime that has stopped answering fails the probe.
+///
+/// * `listener`: bound listener, use port 0 in tests.
+/// * `health`: health of the component.
+#[cfg(feature = "health")]
+pub async fn serve(listener: TcpListener, health: Health) -> io::Result<()> {
+    listener.set_nonblocking(true)?;
+    axum::Server::from_tcp(listener)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        .serve(router(health).into_make_service())
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// A component is ready once the canbus is open, it is listening and
+    /// every PDM is ready, and stops being alive when a task panics.
+    fn test_readiness() {
+        let health = Health::new();
+        assert!(health.report().is_live());
+        assert!(!health.report().is_ready());
+        health.set_canbus_open(true);
+        health.set_pdm_ready(0, true);
+        health.set_listening(true);
+        assert!(health.report().is_ready());
+
+        health.set_pdm_ready(1, false);
+        assert!(!health.report().is_ready());
+        health.set_pdm_ready(1, true);
+        assert!(health.clone().report().is_ready());
+
+        health.task_panicked("firing task");
+        let report = health.report();
+        assert_eq!((report.is_live(), report.is_ready()), (false, false));
+        assert_eq!(report.panicked, ["firing task"]);
+    }
+
+    #[tokio::test]
+    /// A supervised task that panics is recorded and still reported as a
+    /// panic when joined.
+    async fn test_supervise_panic() {
+        let health = Health::new();
+        assert_eq!(
+            tokio::spawn(health.clone().supervise("returning", async { 3 }))
+                .await
+                .unwrap(),
+            3
+        );
+        let panicked = tokio::spawn(health.clone().supervise("panicking", async { panic!("Task failed") }));
+        assert!(panicked.await.unwrap_err().is_panic());
+        assert_eq!(health.report().panicked, ["panicking"]);
+    }
+
+    #[cfg(feature = "health")]
+    #[tokio::test]
+    /// The probes answer ok or unavailable with the report.
+    async fn test_probes() {
+        let health = Health::new();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve(listener, health.clone()));
+
+        let readyz = reqwest::get(format!("{url}/readyz")).await.unwrap();
+        assert_eq!(readyz.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        let report: serde_json::Value = readyz.json().await.unwrap();
+        assert_eq!(
+            report,
+            serde_json::json!({"panicked": [], "canbus_open": false, "listening": false, "pdms": {}})
+        );
+        health.set_canbus_open(true);
+        health.set_listening(true);
+        let readyz = reqwest::get(format!("{url}/readyz")).await.unwrap();
+        assert_eq!(readyz.status(), reqwest::StatusCode::OK);
+
+        let healthz = reqwest::get(format!("{url}/healthz")).await.unwrap();
+        assert_eq!(healthz.status(), reqwest::StatusCode::OK);
+        health.task_panicked("listener");
+        let healthz = reqwest::get(format!("{url}/healthz")).await.unwrap();
+        assert_eq!(healthz.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,279 @@
+use crate::messages::control::heartbeat::Heartbeat;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, future::Future, time::Duration};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpStream, UdpSocket},
+};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Time in milliseconds between heartbeats, when not set in the config.
+pub const DEFAULT_HEARTBEAT_INTERVAL_MS: u64 = 1000;
+
+/// How heartbeats reach their destination.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HeartbeatTransport {
+    /// One datagram per heartbeat, nothing is held open.
+    #[default]
+    Udp,
+    /// One line per heartbeat over a connection kept open, reconnected
+    /// when it drops.
+    Tcp,
+}
+
+/// Where and how often a component sends its heartbeats.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HeartbeatEmitterConfig {
+    /// Address the heartbeats are sent to, e.g. `127.0.0.1:17652`.
+    pub destination: String,
+    /// How they are sent, UDP when not set.
+    #[serde(default)]
+    pub transport: HeartbeatTransport,
+    /// Time in milliseconds between heartbeats.
+    #[serde(default = "default_heartbeat_interval_ms")]
+    pub interval_ms: u64,
+}
+
+/// Default heartbeat interval for serde.
+fn default_heartbeat_interval_ms() -> u64 {
+    DEFAULT_HEARTBEAT_INTERVAL_MS
+}
+
+impl HeartbeatEmitterConfig {
+    /// Send heartbeats to an address over UDP at the default interval.
+    ///
+    /// * `destination`: address the heartbeats are sent to.
+    pub fn new(destination: impl Into<String>) -> Self {
+        Self {
+            destination: destination.into(),
+            transport: HeartbeatTransport::default(),
+            interval_ms: DEFAULT_HEARTBEAT_INTERVAL_MS,
+        }
+    }
+
+    /// Set how the heartbeats are sent.
+    ///
+    /// * `transport`: UDP or TCP.
+    pub fn with_transport(mut self, transport: HeartbeatTransport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Set the time between heartbeats.
+    ///
+    /// * `interval_ms`: time in milliseconds.
+    pub fn with_interval(mut self, interval_ms: u64) -> Self {
+        self.interval_ms = interval_ms;
+        self
+    }
+}
+
+/// Sends heartbeats to the destination of a config, remembering the socket
+/// between them.
+struct HeartbeatSender {
+    /// Where and how the heartbeats go.
+    config: HeartbeatEmitterConfig,
+    /// Socket the datagrams are sent from, once bound.
+    socket: Option<UdpSocket>,
+    /// Connection the lines are written to, once connected.
+    stream: Option<TcpStream>,
+}
+
+impl HeartbeatSender {
+    /// Send one heartbeat, dropping the socket on failure so the next one
+    /// starts afresh.
+    ///
+    /// * `heartbeat`: heartbeat sent.
+    async fn send(&mut self, heartbeat: &Heartbeat) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(heartbeat).expect("Failed to serialise heartbeat");
+        let sent = match self.config.transport {
+            HeartbeatTransport::Udp => {
+                if self.socket.is_none() {
+                    self.socket = Some(UdpSocket::bind("0.0.0.0:0").await?);
+                }
+                let socket = self.socket.as_ref().expect("Socket bound above");
+                socket.send_to(&line, &self.config.destination).await.map(|_| ())
+            }
+            HeartbeatTransport::Tcp => {
+                if self.stream.is_none() {
+                    self.stream = Some(TcpStream::connect(&self.config.destination).await?);
+                }
+                line.push(b'\n');
+                let stream = self.stream.as_mut().expect("Connected above");
+                stream.write_all(&line).await
+            }
+        };
+        if sent.is_err() {
+            self.socket = None;
+            self.stream = None;
+        }
+        sent
+    }
+}
+
+/// Send a heartbeat at every interval until the component stops giving
+/// them. A destination that cannot be reached is logged once, when it
+/// first fails, and again once it is reached.
+///
+/// * `config`: where and how often the heartbeats go.
+/// * `beat`: builds the heartbeat to send, `None` once the component has
+///   stopped.
+pub async fn emit_heartbeats<F, Fut>(config: HeartbeatEmitterConfig, mut beat: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<Heartbeat>>,
+{
+    let mut interval = tokio::time::interval(Duration::from_millis(config.interval_ms.max(1)));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let destination = config.destination.clone();
+    let mut sender = HeartbeatSender {
+        config,
+        socket: None,
+        stream: None,
+    };
+    let mut reachable = true;
+    loop {
+        interval.tick().await;
+        let Some(heartbeat) = beat().await else {
+            break;
+        };
+        match sender.send(&heartbeat).await {
+            Ok(()) if !reachable => {
+                info!("Sending heartbeats to {destination} again");
+                reachable = true;
+            }
+            Err(e) if reachable => {
+                warn!("Failed to send a heartbeat to {destination}, carrying on without: {e}");
+                reachable = false;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Latest heartbeat received from each component, so one component can
+/// tell which of the others are alive.
+#[derive(Debug, Clone, Default)]
+pub struct HeartbeatPeers {
+    /// Latest heartbeat of each component and when it was received.
+    latest: BTreeMap<Uuid, (DateTime<Utc>, Heartbeat)>,
+}
+
+impl HeartbeatPeers {
+    /// Keep a heartbeat received, logging components heard from for the
+    /// first time.
+    ///
+    /// * `heartbeat`: heartbeat received.
+    /// * `received_at`: when it was received.
+    pub fn record(&mut self, heartbeat: Heartbeat, received_at: DateTime<Utc>) {
+        if !self.latest.contains_key(&heartbeat.component) {
+            debug!(
+                "Heartbeat from {} component {} on crop bed {}",
+                heartbeat.heartbeat, heartbeat.component, heartbeat.crop_bed_id
+            );
+        }
+        self.latest.insert(heartbeat.component, (received_at, heartbeat));
+    }
+
+    /// Latest heartbeat of a component, with when it was received.
+    ///
+    /// * `component`: unique id of the component.
+    pub fn latest(&self, component: Uuid) -> Option<&(DateTime<Utc>, Heartbeat)> {
+        self.latest.get(&component)
+    }
+
+    /// Components heard from, in id order.
+    pub fn components(&self) -> impl Iterator<Item = &Heartbeat> {
+        self.latest.values().map(|(_, heartbeat)| heartbeat)
+    }
+
+    /// Components not heard from within a time of now, e.g. three of their
+    /// intervals.
+    ///
+    /// * `now`: current time.
+    /// * `timeout`: time without a heartbeat after which a component is
+    ///   silent.
+    pub fn silent(&self, now: DateTime<Utc>, timeout: chrono::Duration) -> Vec<Uuid> {
+        self.latest
+            .iter()
+            .filter(|(_, (received_at, _))| now - *received_at > timeout)
+            .map(|(component, _)| *component)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{messages::control::heartbeat::ComponentKind, utils::location::CropBed};
+    use tokio::{
+        io::{AsyncBufReadExt, BufReader},
+        net::TcpListener,
+    };
+
+    /// Heartbeat of a lighting component.
+    fn lighting_heartbeat() -> Heartbeat {
+        Heartbeat::new(ComponentKind::Lighting, Uuid::new_v4(), CropBed::Centre, 1.0)
+    }
+
+    #[tokio::test]
+    /// Heartbeats are sent at the interval over either transport, and the
+    /// emitter ends once the component stops giving them.
+    async fn test_emit_heartbeats() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = HeartbeatEmitterConfig::new(socket.local_addr().unwrap().to_string()).with_interval(20);
+        let heartbeat = lighting_heartbeat();
+        let mut remaining = 3;
+        let beats = heartbeat.clone();
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            emit_heartbeats(config, || {
+                remaining -= 1;
+                std::future::ready((remaining >= 0).then(|| beats.clone()))
+            }),
+        )
+        .await
+        .expect("Emitter did not end");
+        let mut data = vec![0; 1024];
+        for _ in 0..3 {
+            let length = socket.recv(&mut data).await.unwrap();
+            assert_eq!(serde_json::from_slice::<Heartbeat>(&data[..length]).unwrap(), heartbeat);
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = HeartbeatEmitterConfig::new(listener.local_addr().unwrap().to_string())
+            .with_transport(HeartbeatTransport::Tcp)
+            .with_interval(20);
+        let beats = heartbeat.clone();
+        let emitter = tokio::spawn(emit_heartbeats(config, move || std::future::ready(Some(beats.clone()))));
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        for _ in 0..2 {
+            let line = lines.next_line().await.unwrap().unwrap();
+            assert_eq!(serde_json::from_str::<Heartbeat>(&line).unwrap(), heartbeat);
+        }
+        emitter.abort();
+    }
+
+    #[test]
+    /// The latest heartbeat of each component is kept, and those gone quiet
+    /// are picked out.
+    fn test_heartbeat_peers() {
+        let mut peers = HeartbeatPeers::default();
+        let now = Utc::now();
+        let quiet = lighting_heartbeat();
+        let alive = lighting_heartbeat();
+        peers.record(quiet.clone(), now - chrono::Duration::seconds(10));
+        peers.record(alive.clone(), now - chrono::Duration::seconds(10));
+        peers.record(alive.clone(), now);
+        assert_eq!(peers.components().count(), 2);
+        assert_eq!(
+            peers.latest(alive.component).map(|(received_at, _)| *received_at),
+            Some(now)
+        );
+        assert_eq!(peers.silent(now, chrono::Duration::seconds(3)), vec![quiet.component]);
+    }
+}
@@ -1,59 +1,22 @@
-use aravis::PixelFormat;
-use serde::{de::Visitor, Deserialize, Serialize, Serializer};
-
-/// Wrapper type for implementing serde for pixel format
-/// configuration.
-#[derive(Copy, Clone, PartialEq, Eq)]
-pub struct CameraPixelFormat(pub PixelFormat);
-
-impl Serialize for CameraPixelFormat {
-    // TODO: add in the other variants of this.
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        match self.0 {
-            PixelFormat::BAYER_RG_8 => {
-                serializer.serialize_unit_variant("PixelFormat", 0, "BAYER_RG_8")
-            }
-            _ => panic!("Un configured pixel format"),
-        }
-    }
-}
-
-impl<'de> Deserialize<'de> for CameraPixelFormat {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        deserializer.deserialize_str(PixelFormatVisitor {})
-    }
-}
+use image::{
+    imageops::FilterType, DynamicImage, GrayAlphaImage, GrayImage, ImageFormat, ImageResult, Rgb, RgbImage, RgbaImage,
+};
+use std::io::Cursor;
 
-/// Wrapper type for serde implementation.
-pub struct PixelFormatVisitor {}
-
-impl<'de> Visitor<'de> for PixelFormatVisitor {
-    type Value = CameraPixelFormat;
-
-    // TODO: Update function to elided lifetime below. Good first issue.
-    #[allow(unused_attributes)]
-    #[allow(elided_lifetimes_in_paths)]
-    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("Could not deserialise CameraPixelFormat")
-    }
-
-    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
-    where
-        E: serde::de::Error,
-    {
-        match v {
-            "RGB_8_PACKER" => Ok(CameraPixelFormat(PixelFormat::RGB_8_PACKED)),
-            "BAYER_RG_8" => Ok(CameraPixelFormat(PixelFormat::BAYER_RG_8)),
-            "RGB_8_PLANAR" => Ok(CameraPixelFormat(PixelFormat::RGB_8_PLANAR)),
-            _ => Err(serde::de::Error::custom("Unknown pixel format {v:?}")),
-        }
-    }
+/// Pixel format of the camera frames, named as in the GenICam standard.
+/// Kept apart from the aravis formats so the configs load in a build
+/// without the `camera-hw` feature.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub enum CameraPixelFormat {
+    /// Bayer mosaic with a red first pixel, 8 bits a pixel.
+    #[serde(rename = "BAYER_RG_8")]
+    BayerRg8,
+    /// Red, green and blue packed into each pixel, 8 bits a channel.
+    #[serde(rename = "RGB_8_PACKED", alias = "RGB_8_PACKER")]
+    Rgb8Packed,
+    /// Red, green and blue in planes of their own, 8 bits a channel.
+    #[serde(rename = "RGB_8_PLANAR")]
+    Rgb8Planar,
 }
 
 /// Region of interest to select from within a camera frame.
@@ -71,3 +34,1102 @@ pub struct Roi {
     /// Height in y.
     pub h: i32,
 }
+
+/// Why a region of interest cannot be set on a sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoiError {
+    /// An increment of the sensor is zero or negative.
+    InvalidIncrement {
+        /// Increment in x.
+        x_inc: i32,
+        /// Increment in y.
+        y_inc: i32,
+    },
+    /// The region is zero or negative in size.
+    Empty {
+        /// Width in x.
+        w: i32,
+        /// Height in y.
+        h: i32,
+    },
+    /// The region starts before the top left of the sensor.
+    NegativeOffset {
+        /// X offset.
+        x: i32,
+        /// Y offset.
+        y: i32,
+    },
+    /// The region runs past the edge of the sensor.
+    OutsideSensor {
+        /// Width of the sensor.
+        sensor_w: i32,
+        /// Height of the sensor.
+        sensor_h: i32,
+    },
+    /// A field is not a multiple of the increment of its direction.
+    Misaligned {
+        /// Name of the field.
+        field: &'static str,
+        /// Value of the field.
+        value: i32,
+        /// Increment it must be a multiple of.
+        increment: i32,
+    },
+}
+
+impl std::fmt::Display for RoiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidIncrement { x_inc, y_inc } => {
+                write!(f, "increments ({x_inc}, {y_inc}) must be positive")
+            }
+            Self::Empty { w, h } => write!(f, "region {w}x{h} has no area"),
+            Self::NegativeOffset { x, y } => write!(f, "offset ({x}, {y}) is outside the sensor"),
+            Self::OutsideSensor { sensor_w, sensor_h } => {
+                write!(f, "region runs past the edge of the {sensor_w}x{sensor_h} sensor")
+            }
+            Self::Misaligned { field, value, increment } => {
+                write!(f, "{field} {value} is not a multiple of {increment}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RoiError {}
+
+/// Round a value to the nearest multiple of an increment, halves rounding
+/// up. Increments below 1 leave the value as it is.
+///
+/// * `value`: value rounded.
+/// * `increment`: step of the multiples.
+fn round_to(value: i32, increment: i32) -> i32 {
+    if increment <= 1 {
+        return value;
+    }
+    (value.saturating_add(increment / 2)).div_euclid(increment) * increment
+}
+
+impl Roi {
+    /// X coordinate one past the right hand edge.
+    pub fn right(&self) -> i32 {
+        self.x.saturating_add(self.w)
+    }
+
+    /// Y coordinate one past the bottom edge.
+    pub fn bottom(&self) -> i32 {
+        self.y.saturating_add(self.h)
+    }
+
+    /// Pixels in the region, 0 when it is zero or negative in size.
+    pub fn area(&self) -> u64 {
+        u64::from(self.w.max(0).unsigned_abs()) * u64::from(self.h.max(0).unsigned_abs())
+    }
+
+    /// Check the region can be set on a sensor, within it and a multiple of
+    /// its increments. The x offset and width step by `x_inc`, the y offset
+    /// and height by `y_inc`.
+    ///
+    /// * `sensor_w`: width of the sensor.
+    /// * `sensor_h`: height of the sensor.
+    /// * `x_inc`: increment in x.
+    /// * `y_inc`: increment in y.
+    pub fn validate(&self, sensor_w: i32, sensor_h: i32, x_inc: i32, y_inc: i32) -> Result<(), RoiError> {
+        if x_inc <= 0 || y_inc <= 0 {
+            return Err(RoiError::InvalidIncrement { x_inc, y_inc });
+        }
+        if self.w <= 0 || self.h <= 0 {
+            return Err(RoiError::Empty { w: self.w, h: self.h });
+        }
+        if self.x < 0 || self.y < 0 {
+            return Err(RoiError::NegativeOffset { x: self.x, y: self.y });
+        }
+        if self.right() > sensor_w || self.bottom() > sensor_h {
+            return Err(RoiError::OutsideSensor { sensor_w, sensor_h });
+        }
+        for (field, value, increment) in [
+            ("x", self.x, x_inc),
+            ("w", self.w, x_inc),
+            ("y", self.y, y_inc),
+            ("h", self.h, y_inc),
+        ] {
+            if value % increment != 0 {
+                return Err(RoiError::Misaligned { field, value, increment });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether another region lies wholly within this one, edges included.
+    ///
+    /// * `other`: region checked.
+    pub fn contains(&self, other: &Roi) -> bool {
+        other.x >= self.x && other.y >= self.y && other.right() <= self.right() && other.bottom() <= self.bottom()
+    }
+
+    /// Region shared with another, `None` when they do not overlap or
+    /// only share an edge.
+    ///
+    /// * `other`: region intersected with.
+    pub fn intersect(&self, other: &Roi) -> Option<Roi> {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let (right, bottom) = (self.right().min(other.right()), self.bottom().min(other.bottom()));
+        (right > x && bottom > y).then(|| Roi {
+            x,
+            y,
+            w: right - x,
+            h: bottom - y,
+        })
+    }
+
+    /// Region rounded to the nearest multiples of the increments, the size
+    /// at least one increment. Check the result with [`Roi::validate`] as it
+    /// can still fall outside the sensor.
+    ///
+    /// * `x_inc`: increment of the x offset and width.
+    /// * `y_inc`: increment of the y offset and height.
+    pub fn aligned_to(&self, x_inc: i32, y_inc: i32) -> Roi {
+        Roi {
+            x: round_to(self.x, x_inc),
+            y: round_to(self.y, y_inc),
+            w: round_to(self.w, x_inc).max(x_inc.max(1)),
+            h: round_to(self.h, y_inc).max(y_inc.max(1)),
+        }
+    }
+}
+
+/// Order of the colour filters over the top left 2x2 pixels of a Bayer
+/// mosaic, named as GenICam names the pixel formats.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BayerPattern {
+    /// Red, green on the first row, green, blue on the second, as sent in
+    /// `BAYER_RG_8`.
+    Rggb,
+    /// Blue, green on the first row, green, red on the second.
+    Bggr,
+    /// Green, red on the first row, blue, green on the second.
+    Grbg,
+    /// Green, blue on the first row, red, green on the second.
+    Gbrg,
+}
+
+/// Colour filter over one pixel of a mosaic. Green pixels are told apart by
+/// the colour sharing their row, which sets where their red neighbours are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BayerSite {
+    /// Red pixel.
+    Red,
+    /// Green pixel on a row with red pixels.
+    GreenRedRow,
+    /// Green pixel on a row with blue pixels.
+    GreenBlueRow,
+    /// Blue pixel.
+    Blue,
+}
+
+impl BayerSite {
+    /// Index of the channel the pixel measures in an RGB pixel.
+    fn channel(self) -> usize {
+        match self {
+            Self::Red => 0,
+            Self::GreenRedRow | Self::GreenBlueRow => 1,
+            Self::Blue => 2,
+        }
+    }
+}
+
+impl BayerPattern {
+    /// Filters over the top left 2x2 pixels, row by row.
+    fn sites(self) -> [BayerSite; 4] {
+        use BayerSite::{Blue, GreenBlueRow, GreenRedRow, Red};
+        match self {
+            Self::Rggb => [Red, GreenRedRow, GreenBlueRow, Blue],
+            Self::Bggr => [Blue, GreenBlueRow, GreenRedRow, Red],
+            Self::Grbg => [GreenRedRow, Red, Blue, GreenBlueRow],
+            Self::Gbrg => [GreenBlueRow, Blue, Red, GreenRedRow],
+        }
+    }
+}
+
+/// Rounded mean of the values summed.
+///
+/// * `sum`: total of the values.
+/// * `count`: number of values, at least one.
+fn mean(sum: u32, count: u32) -> u8 {
+    u8::try_from((sum + count / 2) / count).unwrap_or(u8::MAX)
+}
+
+/// Interpolate a pixel of the mosaic from the pixels around it, skipping
+/// those outside the image, for the pixels on its edges.
+///
+/// * `raw`: the mosaic, one byte per pixel row by row.
+/// * `w`: width of the mosaic.
+/// * `h`: height of the mosaic.
+/// * `x`: column of the pixel.
+/// * `y`: row of the pixel.
+/// * `sites`: filters over the top left 2x2 pixels.
+fn debayer_edge(raw: &[u8], w: usize, h: usize, x: usize, y: usize, sites: &[BayerSite; 4]) -> [u8; 3] {
+    let own = sites[(y & 1) * 2 + (x & 1)].channel();
+    let (mut sum, mut count) = ([0u32; 3], [0u32; 3]);
+    for ny in y.saturating_sub(1)..=(y + 1).min(h - 1) {
+        for nx in x.saturating_sub(1)..=(x + 1).min(w - 1) {
+            let channel = sites[(ny & 1) * 2 + (nx & 1)].channel();
+            sum[channel] += u32::from(raw[ny * w + nx]);
+            count[channel] += 1;
+        }
+    }
+    let mut pixel = [0; 3];
+    for (channel, value) in pixel.iter_mut().enumerate() {
+        if channel == own {
+            *value = raw[y * w + x];
+        } else if count[channel] > 0 {
+            *value = mean(sum[channel], count[channel]);
+        }
+    }
+    pixel
+}
+
+/// Interpolate a pixel of the mosaic with all eight of its neighbours in
+/// the image.
+///
+/// * `raw`: the mosaic, one byte per pixel row by row.
+/// * `w`: width of the mosaic.
+/// * `i`: index of the pixel.
+/// * `site`: filter over the pixel.
+fn debayer_interior(raw: &[u8], w: usize, i: usize, site: BayerSite) -> [u8; 3] {
+    let at = |j: usize| u32::from(raw[j]);
+    let orthogonal = || mean(at(i - w) + at(i + w) + at(i - 1) + at(i + 1), 4);
+    let diagonal = || mean(at(i - w - 1) + at(i - w + 1) + at(i + w - 1) + at(i + w + 1), 4);
+    let horizontal = || mean(at(i - 1) + at(i + 1), 2);
+    let vertical = || mean(at(i - w) + at(i + w), 2);
+    match site {
+        BayerSite::Red => [raw[i], orthogonal(), diagonal()],
+        BayerSite::Blue => [diagonal(), orthogonal(), raw[i]],
+        BayerSite::GreenRedRow => [horizontal(), raw[i], vertical()],
+        BayerSite::GreenBlueRow => [vertical(), raw[i], horizontal()],
+    }
+}
+
+/// Turn a raw Bayer mosaic, as captured in `BAYER_RG_8` and its kin, into
+/// an RGB image by bilinear interpolation. Each missing colour of a pixel
+/// is the mean of the neighbouring pixels measuring it, the pixels on the
+/// edges only using the neighbours inside the image. Panics if `raw` holds
+/// fewer than `w * h` bytes.
+///
+/// * `raw`: the mosaic, one byte per pixel row by row.
+/// * `w`: width of the mosaic.
+/// * `h`: height of the mosaic.
+/// * `pattern`: order of the filters over the top left 2x2 pixels.
+pub fn debayer(raw: &[u8], w: u32, h: u32, pattern: BayerPattern) -> DynamicImage {
+    let (width, height) = (
+        usize::try_from(w).expect("Width fits in memory"),
+        usize::try_from(h).expect("Height fits in memory"),
+    );
+    assert!(
+        raw.len() >= width * height,
+        "Mosaic of {} bytes is smaller than {w}x{h}",
+        raw.len()
+    );
+    if width == 0 || height == 0 {
+        return DynamicImage::ImageRgb8(RgbImage::new(w, h));
+    }
+    let sites = pattern.sites();
+    let mut rgb = vec![0; width * height * 3];
+    for (y, row) in rgb.chunks_exact_mut(width * 3).enumerate() {
+        let interior_row = y > 0 && y + 1 < height;
+        let row_sites = [sites[(y & 1) * 2], sites[(y & 1) * 2 + 1]];
+        for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+            let value = if interior_row && x > 0 && x + 1 < width {
+                debayer_interior(raw, width, y * width + x, row_sites[x & 1])
+            } else {
+                debayer_edge(raw, width, height, x, y, &sites)
+            };
+            pixel.copy_from_slice(&value);
+        }
+    }
+    DynamicImage::ImageRgb8(RgbImage::from_raw(w, h, rgb).expect("Buffer sized to the image"))
+}
+
+/// Shrink an image to fit within `max_width`, keeping the aspect ratio.
+/// Images that are already narrow enough are returned as they are.
+///
+/// * `image`: image to shrink.
+/// * `max_width`: widest the returned image may be.
+pub fn downscale(image: &DynamicImage, max_width: u32) -> DynamicImage {
+    fit_within(image, max_width, u32::MAX)
+}
+
+/// Shrink an image to fit within a size, keeping the aspect ratio. Most of
+/// the way is taken with the box filter, only the last step of less than
+/// 2x being resized with a triangle filter. Images that already fit are
+/// returned as they are.
+///
+/// * `image`: image to shrink.
+/// * `max_width`: widest the returned image may be, at least 1.
+/// * `max_height`: tallest the returned image may be, at least 1.
+fn fit_within(image: &DynamicImage, max_width: u32, max_height: u32) -> DynamicImage {
+    let (max_width, max_height) = (max_width.max(1), max_height.max(1));
+    if image.width() <= max_width && image.height() <= max_height {
+        return image.clone();
+    }
+    let ratio = (image.width() / max_width).max(image.height() / max_height);
+    let boxed = if ratio >= 2 {
+        downscale_box(image, 1 << ratio.ilog2())
+    } else {
+        image.clone()
+    };
+    if boxed.width() <= max_width && boxed.height() <= max_height {
+        return boxed;
+    }
+    boxed.resize(max_width, max_height, FilterType::Triangle)
+}
+
+/// Average each block of `factor` by `factor` pixels of an image stored
+/// row by row, dropping the columns and rows past the last whole block.
+///
+/// * `raw`: the pixels, `channels` bytes each.
+/// * `width`: width of the image.
+/// * `height`: height of the image.
+/// * `channels`: bytes in a pixel.
+/// * `factor`: side of a block, between 2 and the smaller side.
+fn box_filter(raw: &[u8], width: u32, height: u32, channels: usize, factor: u32) -> (u32, u32, Vec<u8>) {
+    let (out_w, out_h) = (width / factor, height / factor);
+    let side = usize::try_from(factor).expect("Factor fits in memory");
+    let row_len = usize::try_from(width).expect("Width fits in memory") * channels;
+    let out_row_len = usize::try_from(out_w).expect("Width fits in memory") * channels;
+    let rows = usize::try_from(out_h).expect("Height fits in memory");
+    let block = factor * factor;
+    // Blocks of 2x2 and 4x4 are the common case, divided by a shift.
+    let shift = block.is_power_of_two().then(|| block.trailing_zeros());
+    let mut sums = vec![0u32; out_row_len];
+    let mut out = Vec::with_capacity(out_row_len * rows);
+    for block_rows in raw.chunks_exact(row_len * side).take(rows) {
+        sums.fill(0);
+        for row in block_rows.chunks_exact(row_len) {
+            for (sum, pixels) in sums.chunks_exact_mut(channels).zip(row.chunks_exact(side * channels)) {
+                for pixel in pixels.chunks_exact(channels) {
+                    for (total, value) in sum.iter_mut().zip(pixel) {
+                        *total += u32::from(*value);
+                    }
+                }
+            }
+        }
+        out.extend(sums.iter().map(|&sum| {
+            let value = match shift {
+                Some(shift) => (sum + block / 2) >> shift,
+                None => (sum + block / 2) / block,
+            };
+            u8::try_from(value).unwrap_or(u8::MAX)
+        }));
+    }
+    (out_w, out_h, out)
+}
+
+/// Shrink an image by a whole factor with a box filter, each pixel the
+/// mean of a block of `factor` by `factor` pixels. Much cheaper than
+/// [`DynamicImage::resize`], and fastest for factors of 2 and 4. Columns
+/// and rows past the last whole block are dropped, factors above the
+/// smaller side are taken as the smaller side, and factors of 0 or 1
+/// return the image as it is. Images of more than 8 bits per channel are
+/// returned as 8 bit RGB.
+///
+/// * `image`: image to shrink.
+/// * `factor`: side of the blocks averaged.
+pub fn downscale_box(image: &DynamicImage, factor: u32) -> DynamicImage {
+    let factor = factor.min(image.width()).min(image.height());
+    if factor <= 1 {
+        return image.clone();
+    }
+    let error = "Buffer sized to the image";
+    match image {
+        DynamicImage::ImageLuma8(gray) => {
+            let (w, h, raw) = box_filter(gray.as_raw(), gray.width(), gray.height(), 1, factor);
+            DynamicImage::ImageLuma8(GrayImage::from_raw(w, h, raw).expect(error))
+        }
+        DynamicImage::ImageLumaA8(gray) => {
+            let (w, h, raw) = box_filter(gray.as_raw(), gray.width(), gray.height(), 2, factor);
+            DynamicImage::ImageLumaA8(GrayAlphaImage::from_raw(w, h, raw).expect(error))
+        }
+        DynamicImage::ImageRgba8(rgba) => {
+            let (w, h, raw) = box_filter(rgba.as_raw(), rgba.width(), rgba.height(), 4, factor);
+            DynamicImage::ImageRgba8(RgbaImage::from_raw(w, h, raw).expect(error))
+        }
+        DynamicImage::ImageRgb8(rgb) => {
+            let (w, h, raw) = box_filter(rgb.as_raw(), rgb.width(), rgb.height(), 3, factor);
+            DynamicImage::ImageRgb8(RgbImage::from_raw(w, h, raw).expect(error))
+        }
+        image => downscale_box(&DynamicImage::ImageRgb8(image.to_rgb8()), factor),
+    }
+}
+
+/// Part of an image inside a region of interest, the region clipped to the
+/// image. `None` when the region misses the image.
+///
+/// * `image`: image cropped.
+/// * `roi`: region kept, in pixels of the image.
+pub fn crop_to_roi(image: &DynamicImage, roi: &Roi) -> Option<DynamicImage> {
+    let bounds = Roi {
+        x: 0,
+        y: 0,
+        w: i32::try_from(image.width()).unwrap_or(i32::MAX),
+        h: i32::try_from(image.height()).unwrap_or(i32::MAX),
+    };
+    let roi = bounds.intersect(roi)?;
+    Some(image.crop_imm(
+        roi.x.unsigned_abs(),
+        roi.y.unsigned_abs(),
+        roi.w.unsigned_abs(),
+        roi.h.unsigned_abs(),
+    ))
+}
+
+/// Encode a thumbnail of an image, shrunk so its longer edge is at most
+/// `max_edge` and keeping the aspect ratio. Images with an alpha channel
+/// lose it when encoded as jpeg.
+///
+/// * `image`: full size image.
+/// * `max_edge`: longest the edges of the thumbnail may be.
+/// * `format`: format the thumbnail is encoded in.
+pub fn encode_thumbnail(image: &DynamicImage, max_edge: u32, format: ImageFormat) -> ImageResult<Vec<u8>> {
+    let mut thumbnail = fit_within(image, max_edge, max_edge);
+    if format == ImageFormat::Jpeg && thumbnail.color().has_alpha() {
+        thumbnail = DynamicImage::ImageRgb8(thumbnail.to_rgb8());
+    }
+    let mut bytes = Cursor::new(Vec::new());
+    thumbnail.write_to(&mut bytes, format)?;
+    Ok(bytes.into_inner())
+}
+
+/// Columns of the grid a difference hash is taken over, one more than the
+/// bits of a row.
+const DHASH_WIDTH: u32 = 9;
+
+/// Rows of the grid a difference hash is taken over.
+const DHASH_HEIGHT: u32 = 8;
+
+/// Difference hash of an image. The image is shrunk to a 9x8 grayscale
+/// grid, and each bit says whether a pixel is darker than the one to its
+/// right, row by row from the top left in the high bit. Near identical
+/// frames hash the same or a few bits apart whatever their size, compare
+/// them with [`hamming_distance`].
+///
+/// * `image`: image hashed.
+pub fn dhash(image: &DynamicImage) -> u64 {
+    let grid = fit_within(image, DHASH_WIDTH * 8, DHASH_HEIGHT * 8)
+        .resize_exact(DHASH_WIDTH, DHASH_HEIGHT, FilterType::Triangle)
+        .to_luma8();
+    let mut hash = 0;
+    for y in 0..DHASH_HEIGHT {
+        for x in 0..DHASH_WIDTH - 1 {
+            hash = (hash << 1) | u64::from(grid.get_pixel(x, y)[0] < grid.get_pixel(x + 1, y)[0]);
+        }
+    }
+    hash
+}
+
+/// Bits two hashes differ by, 0 for the same image and 64 at most.
+///
+/// * `a`: first hash.
+/// * `b`: second hash.
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Colour the channel bands are drawn over an image in.
+pub const BAND_COLOUR: Rgb<u8> = Rgb([255, 255, 0]);
+
+/// Width of a glyph of the annotation font.
+const GLYPH_WIDTH: i64 = 3;
+
+/// Pixels a label moves on by each character, the glyph and a column of
+/// space.
+const GLYPH_ADVANCE: i64 = GLYPH_WIDTH + 1;
+
+/// Pixels from the edge of an annotation to its label.
+const LABEL_INSET: i64 = 2;
+
+/// Something drawn over an image to show which part of it is which, e.g.
+/// when lining the sprays up with what the cameras see.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Annotation {
+    /// Outline of a region in a colour, labelled inside its top left
+    /// corner.
+    Rect(Roi, Rgb<u8>, String),
+    /// Columns from the first x up to the second, outlined down both edges
+    /// in [`BAND_COLOUR`] and labelled at the top, e.g. the part of a frame
+    /// a spray channel covers.
+    VerticalBand(u32, u32, String),
+}
+
+/// Rows of a glyph of the 3x5 annotation font, top first, with the left
+/// pixel in the high bit of the three. Letters are drawn upper case,
+/// characters without a glyph are left as a space.
+///
+/// * `c`: character drawn.
+fn glyph(c: char) -> Option<[u8; 5]> {
+    let rows = match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        _ => return None,
+    };
+    Some(rows)
+}
+
+/// Set a pixel, ignoring those outside the canvas.
+///
+/// * `canvas`: image drawn on.
+/// * `x`: column of the pixel.
+/// * `y`: row of the pixel.
+/// * `colour`: colour it is set to.
+fn put_pixel(canvas: &mut RgbImage, x: i64, y: i64, colour: Rgb<u8>) {
+    if let (Ok(x), Ok(y)) = (u32::try_from(x), u32::try_from(y)) {
+        if x < canvas.width() && y < canvas.height() {
+            canvas.put_pixel(x, y, colour);
+        }
+    }
+}
+
+/// Draw a label in the annotation font, clipped to the canvas.
+///
+/// * `canvas`: image drawn on.
+/// * `x`: column of the top left of the label.
+/// * `y`: row of the top left of the label.
+/// * `text`: label drawn.
+/// * `colour`: colour of the label.
+fn draw_text(canvas: &mut RgbImage, x: i64, y: i64, text: &str, colour: Rgb<u8>) {
+    for (column, c) in (0..).zip(text.chars()) {
+        let Some(rows) = glyph(c) else {
+            continue;
+        };
+        let left = x + column * GLYPH_ADVANCE;
+        for (dy, row) in (0..).zip(rows) {
+            for dx in 0..GLYPH_WIDTH {
+                if row & (0b100 >> dx) != 0 {
+                    put_pixel(canvas, left + dx, y + dy, colour);
+                }
+            }
+        }
+    }
+}
+
+/// Draw the outline of a region one pixel wide, clipped to the canvas.
+///
+/// * `canvas`: image drawn on.
+/// * `roi`: region outlined, not empty.
+/// * `colour`: colour of the outline.
+fn draw_rect(canvas: &mut RgbImage, roi: &Roi, colour: Rgb<u8>) {
+    let (x, y) = (i64::from(roi.x), i64::from(roi.y));
+    let (right, bottom) = (i64::from(roi.right()), i64::from(roi.bottom()));
+    for column in x.max(0)..right.min(i64::from(canvas.width())) {
+        put_pixel(canvas, column, y, colour);
+        put_pixel(canvas, column, bottom - 1, colour);
+    }
+    for row in y.max(0)..bottom.min(i64::from(canvas.height())) {
+        put_pixel(canvas, x, row, colour);
+        put_pixel(canvas, right - 1, row, colour);
+    }
+}
+
+/// Copy of an image with annotations drawn over it in order, each one
+/// clipped to the image. Empty regions and bands are not drawn.
+///
+/// * `image`: image annotated.
+/// * `items`: annotations drawn, later ones over earlier ones.
+pub fn annotate(image: &DynamicImage, items: &[Annotation]) -> DynamicImage {
+    let mut canvas = image.to_rgb8();
+    for item in items {
+        match item {
+            Annotation::Rect(roi, colour, label) if roi.w > 0 && roi.h > 0 => {
+                draw_rect(&mut canvas, roi, *colour);
+                let (x, y) = (i64::from(roi.x), i64::from(roi.y));
+                draw_text(&mut canvas, x + LABEL_INSET, y + LABEL_INSET, label, *colour);
+            }
+            Annotation::VerticalBand(x0, x1, label) if x1 > x0 => {
+                let (x0, x1) = (i64::from(*x0), i64::from(*x1));
+                for row in 0..i64::from(canvas.height()) {
+                    put_pixel(&mut canvas, x0, row, BAND_COLOUR);
+                    put_pixel(&mut canvas, x1 - 1, row, BAND_COLOUR);
+                }
+                draw_text(&mut canvas, x0 + LABEL_INSET, LABEL_INSET, label, BAND_COLOUR);
+            }
+            Annotation::Rect(..) | Annotation::VerticalBand(..) => {}
+        }
+    }
+    DynamicImage::ImageRgb8(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::paths::repo_relative;
+    use rstest::rstest;
+    use std::path::Path;
+
+    /// Region from its offset and size.
+    fn roi(x: i32, y: i32, w: i32, h: i32) -> Roi {
+        Roi { x, y, w, h }
+    }
+
+    #[rstest]
+    #[case(roi(0, 0, 640, 480), Ok(()))]
+    #[case(roi(16, 24, 1920, 1200), Err(RoiError::OutsideSensor { sensor_w: 1936, sensor_h: 1216 }))]
+    #[case(roi(16, 16, 1920, 1200), Ok(()))]
+    #[case(roi(0, 0, 0, 480), Err(RoiError::Empty { w: 0, h: 480 }))]
+    #[case(roi(0, 0, 640, -2), Err(RoiError::Empty { w: 640, h: -2 }))]
+    #[case(roi(-8, 0, 640, 480), Err(RoiError::NegativeOffset { x: -8, y: 0 }))]
+    #[case(roi(0, -2, 640, 480), Err(RoiError::NegativeOffset { x: 0, y: -2 }))]
+    #[case(roi(4, 0, 640, 480), Err(RoiError::Misaligned { field: "x", value: 4, increment: 8 }))]
+    #[case(roi(0, 0, 644, 480), Err(RoiError::Misaligned { field: "w", value: 644, increment: 8 }))]
+    #[case(roi(0, 3, 640, 480), Err(RoiError::Misaligned { field: "y", value: 3, increment: 2 }))]
+    #[case(roi(0, 0, 640, 481), Err(RoiError::Misaligned { field: "h", value: 481, increment: 2 }))]
+    #[case(roi(1296, 0, 640, 480), Ok(()))]
+    #[case(roi(1304, 0, 640, 480), Err(RoiError::OutsideSensor { sensor_w: 1936, sensor_h: 1216 }))]
+    #[case(roi(i32::MAX - 7, 0, 640, 480), Err(RoiError::OutsideSensor { sensor_w: 1936, sensor_h: 1216 }))]
+    /// Regions are checked against a 1936x1216 sensor stepping by 8 in x
+    /// and 2 in y, offsets as well as sizes.
+    fn test_validate(#[case] region: Roi, #[case] expected: Result<(), RoiError>) {
+        assert_eq!(region.validate(1936, 1216, 8, 2), expected);
+    }
+
+    #[rstest]
+    #[case("BAYER_RG_8", CameraPixelFormat::BayerRg8)]
+    #[case("RGB_8_PACKED", CameraPixelFormat::Rgb8Packed)]
+    #[case("RGB_8_PLANAR", CameraPixelFormat::Rgb8Planar)]
+    /// Pixel formats are read and written with their GenICam names.
+    fn test_pixel_format_names(#[case] name: &str, #[case] pixel_format: CameraPixelFormat) {
+        assert_eq!(serde_yaml::from_str::<CameraPixelFormat>(name).unwrap(), pixel_format);
+        assert_eq!(serde_yaml::to_string(&pixel_format).unwrap().trim_end(), name);
+    }
+
+    #[test]
+    /// Configs written with the misspelt packed format still load.
+    fn test_pixel_format_alias() {
+        assert_eq!(
+            serde_yaml::from_str::<CameraPixelFormat>("RGB_8_PACKER").unwrap(),
+            CameraPixelFormat::Rgb8Packed
+        );
+        assert!(serde_yaml::from_str::<CameraPixelFormat>("MONO_8").is_err());
+    }
+
+    #[test]
+    /// Increments that are not positive are refused before anything else.
+    fn test_validate_increments() {
+        assert_eq!(
+            roi(0, 0, 0, 0).validate(1936, 1216, 0, 2),
+            Err(RoiError::InvalidIncrement { x_inc: 0, y_inc: 2 })
+        );
+        assert!(roi(0, 0, 8, 8).validate(1936, 1216, 8, -1).is_err());
+        assert!(roi(3, 5, 7, 9).validate(1936, 1216, 1, 1).is_ok());
+    }
+
+    #[rstest]
+    #[case(roi(0, 0, 640, 480), 307_200)]
+    #[case(roi(-10, -10, 3, 4), 12)]
+    #[case(roi(0, 0, 0, 480), 0)]
+    #[case(roi(0, 0, -640, 480), 0)]
+    #[case(roi(0, 0, i32::MAX, i32::MAX), 4_611_686_014_132_420_609)]
+    fn test_area(#[case] region: Roi, #[case] expected: u64) {
+        assert_eq!(region.area(), expected);
+    }
+
+    #[rstest]
+    #[case(roi(0, 0, 100, 100), roi(10, 10, 50, 50), true)]
+    #[case(roi(0, 0, 100, 100), roi(0, 0, 100, 100), true)]
+    #[case(roi(0, 0, 100, 100), roi(50, 50, 51, 10), false)]
+    #[case(roi(0, 0, 100, 100), roi(-1, 0, 10, 10), false)]
+    #[case(roi(-50, -50, 100, 100), roi(-10, -10, 20, 20), true)]
+    #[case(roi(0, 0, 100, 100), roi(100, 100, 0, 0), true)]
+    #[case(roi(0, 0, 0, 0), roi(0, 0, 1, 1), false)]
+    /// Regions touching the edges are contained, as is an empty region on
+    /// the far corner.
+    fn test_contains(#[case] outer: Roi, #[case] inner: Roi, #[case] expected: bool) {
+        assert_eq!(outer.contains(&inner), expected);
+    }
+
+    #[rstest]
+    #[case(roi(0, 0, 100, 100), roi(50, 50, 100, 100), Some(roi(50, 50, 50, 50)))]
+    #[case(roi(0, 0, 100, 100), roi(10, 10, 20, 20), Some(roi(10, 10, 20, 20)))]
+    #[case(roi(0, 0, 100, 100), roi(100, 0, 10, 10), None)]
+    #[case(roi(0, 0, 100, 100), roi(200, 200, 10, 10), None)]
+    #[case(roi(-20, -20, 40, 40), roi(-30, 0, 20, 100), Some(roi(-20, 0, 10, 20)))]
+    #[case(roi(0, 0, 100, 100), roi(10, 10, 0, 50), None)]
+    /// The intersection is the same either way round, and regions sharing
+    /// only an edge or with no area do not intersect.
+    fn test_intersect(#[case] a: Roi, #[case] b: Roi, #[case] expected: Option<Roi>) {
+        assert_eq!(a.intersect(&b), expected);
+        assert_eq!(b.intersect(&a), expected);
+        if let Some(shared) = expected {
+            assert!(a.contains(&shared) && b.contains(&shared));
+        }
+    }
+
+    #[rstest]
+    #[case(roi(0, 0, 640, 480), roi(0, 0, 640, 480))]
+    #[case(roi(3, 1, 643, 481), roi(0, 2, 640, 482))]
+    #[case(roi(4, 3, 644, 479), roi(8, 4, 648, 480))]
+    #[case(roi(-3, -3, 2, 0), roi(0, -2, 8, 2))]
+    #[case(roi(-5, -1, -20, -7), roi(-8, 0, 8, 2))]
+    /// Offsets and sizes round to the nearest multiple with halves going
+    /// up, negative values included, and sizes never round below one step.
+    fn test_aligned_to(#[case] region: Roi, #[case] expected: Roi) {
+        let aligned = region.aligned_to(8, 2);
+        assert_eq!(aligned, expected);
+        assert_eq!(aligned.aligned_to(8, 2), aligned);
+    }
+
+    #[test]
+    /// An aligned region inside the sensor validates, and increments below
+    /// 1 leave the region as it is.
+    fn test_aligned_validates() {
+        let aligned = roi(13, 7, 637, 477).aligned_to(8, 2);
+        assert_eq!(aligned.validate(1936, 1216, 8, 2), Ok(()));
+        assert_eq!(roi(13, 7, 637, 477).aligned_to(0, 1), roi(13, 7, 637, 477));
+    }
+
+    /// Mosaic a flat colour under a pattern.
+    ///
+    /// * `w`: width of the mosaic.
+    /// * `h`: height of the mosaic.
+    /// * `colour`: red, green and blue of every pixel.
+    /// * `pattern`: order of the filters.
+    fn flat_mosaic(w: usize, h: usize, colour: [u8; 3], pattern: BayerPattern) -> Vec<u8> {
+        let sites = pattern.sites();
+        (0..w * h)
+            .map(|i| colour[sites[((i / w) & 1) * 2 + ((i % w) & 1)].channel()])
+            .collect()
+    }
+
+    /// Mosaic whose every pixel differs, so each interpolation shows.
+    ///
+    /// * `w`: width of the mosaic.
+    /// * `h`: height of the mosaic.
+    fn varied_mosaic(w: usize, h: usize) -> Vec<u8> {
+        (0..w * h).map(|i| u8::try_from((i * 37 + i / w * 11) % 256).unwrap()).collect()
+    }
+
+    #[test]
+    /// Every missing colour of an RGGB mosaic is the mean of its
+    /// neighbours measuring it, the edges only using those in the image.
+    fn test_debayer_known_mosaic() {
+        let raw: Vec<u8> = (0..16).map(|i| i * 10).collect();
+        let expected: [[u8; 3]; 16] = [
+            [0, 25, 50],
+            [10, 10, 50],
+            [20, 33, 60],
+            [20, 30, 70],
+            [40, 40, 50],
+            [50, 50, 50],
+            [60, 60, 60],
+            [60, 67, 70],
+            [80, 83, 90],
+            [90, 90, 90],
+            [100, 100, 100],
+            [100, 110, 110],
+            [80, 120, 130],
+            [90, 117, 130],
+            [100, 140, 140],
+            [100, 125, 150],
+        ];
+        let rgb = debayer(&raw, 4, 4, BayerPattern::Rggb).to_rgb8();
+        for (i, pixel) in rgb.pixels().enumerate() {
+            assert_eq!(pixel.0, expected[i], "Pixel {i} interpolated wrong");
+        }
+    }
+
+    #[rstest]
+    #[case(BayerPattern::Rggb)]
+    #[case(BayerPattern::Bggr)]
+    #[case(BayerPattern::Grbg)]
+    #[case(BayerPattern::Gbrg)]
+    /// A flat colour comes back unchanged everywhere, edges and odd sizes
+    /// included, whichever the pattern.
+    fn test_debayer_flat_colour(#[case] pattern: BayerPattern) {
+        let colour = [200, 120, 40];
+        let rgb = debayer(&flat_mosaic(5, 3, colour, pattern), 5, 3, pattern).to_rgb8();
+        assert_eq!(rgb.dimensions(), (5, 3));
+        assert!(rgb.pixels().all(|pixel| pixel.0 == colour));
+    }
+
+    #[rstest]
+    #[case(1, 0, BayerPattern::Grbg)]
+    #[case(0, 1, BayerPattern::Gbrg)]
+    #[case(1, 1, BayerPattern::Bggr)]
+    /// An RGGB mosaic cropped by a column or row is the mosaic of the other
+    /// patterns, and reads the same away from the edges.
+    fn test_debayer_shifted_patterns(#[case] dx: usize, #[case] dy: usize, #[case] pattern: BayerPattern) {
+        let (w, h) = (8, 6);
+        let raw = varied_mosaic(w, h);
+        let full = debayer(&raw, 8, 6, BayerPattern::Rggb).to_rgb8();
+        let (cw, ch) = (w - dx, h - dy);
+        let cropped: Vec<u8> = (dy..h).flat_map(|y| raw[y * w + dx..(y + 1) * w].to_vec()).collect();
+        let shifted = debayer(&cropped, u32::try_from(cw).unwrap(), u32::try_from(ch).unwrap(), pattern).to_rgb8();
+        for y in 1..ch - 1 {
+            for x in 1..cw - 1 {
+                let (sx, sy) = (u32::try_from(x).unwrap(), u32::try_from(y).unwrap());
+                let (fx, fy) = (u32::try_from(x + dx).unwrap(), u32::try_from(y + dy).unwrap());
+                assert_eq!(shifted.get_pixel(sx, sy), full.get_pixel(fx, fy), "Pixel ({x}, {y}) differs");
+            }
+        }
+    }
+
+    #[test]
+    /// The interior pixels are interpolated as if they were on an edge with
+    /// every neighbour in the image.
+    fn test_debayer_interior_matches_edges() {
+        let (w, h) = (7, 5);
+        let raw = varied_mosaic(w, h);
+        for pattern in [BayerPattern::Rggb, BayerPattern::Bggr, BayerPattern::Grbg, BayerPattern::Gbrg] {
+            let sites = pattern.sites();
+            for y in 1..h - 1 {
+                for x in 1..w - 1 {
+                    let site = sites[(y & 1) * 2 + (x & 1)];
+                    assert_eq!(
+                        debayer_interior(&raw, w, y * w + x, site),
+                        debayer_edge(&raw, w, h, x, y, &sites)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    /// Empty mosaics give empty images, and mosaics shorter than their size
+    /// are refused.
+    fn test_debayer_sizes() {
+        assert_eq!(debayer(&[], 0, 4, BayerPattern::Rggb).width(), 0);
+        let single = debayer(&[90], 1, 1, BayerPattern::Bggr).to_rgb8();
+        assert_eq!(single.get_pixel(0, 0).0, [0, 0, 90]);
+        assert!(std::panic::catch_unwind(|| debayer(&[0; 15], 4, 4, BayerPattern::Rggb)).is_err());
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, ignore)]
+    /// A full frame of the cameras on the machine is debayered well within
+    /// a frame interval, run with `cargo test --release`.
+    fn test_debayer_speed() {
+        let raw = varied_mosaic(1280, 1024);
+        debayer(&raw, 1280, 1024, BayerPattern::Rggb);
+        let started = std::time::Instant::now();
+        for _ in 0..10 {
+            debayer(&raw, 1280, 1024, BayerPattern::Rggb);
+        }
+        let per_frame = started.elapsed() / 10;
+        assert!(per_frame < std::time::Duration::from_millis(10), "Took {per_frame:?} a frame");
+    }
+
+    #[test]
+    /// Each pixel shrunk by the box filter is the rounded mean of its block,
+    /// for factors divided by a shift and those that are not.
+    fn test_downscale_box_means() {
+        let gray = GrayImage::from_raw(4, 4, (0..16).map(|i| i * 10).collect()).unwrap();
+        let halved = downscale_box(&DynamicImage::ImageLuma8(gray.clone()), 2).to_luma8();
+        assert_eq!(halved.dimensions(), (2, 2));
+        assert_eq!(halved.as_raw(), &[25, 45, 105, 125]);
+        let quartered = downscale_box(&DynamicImage::ImageLuma8(gray), 4).to_luma8();
+        assert_eq!(quartered.as_raw(), &[75]);
+
+        let rgb = RgbImage::from_fn(7, 4, |x, y| {
+            image::Rgb([u8::try_from(x * 30).unwrap(), 200, u8::try_from(y).unwrap()])
+        });
+        let thirds = downscale_box(&DynamicImage::ImageRgb8(rgb), 3).to_rgb8();
+        assert_eq!(thirds.dimensions(), (2, 1), "Partial blocks were kept");
+        assert_eq!(thirds.get_pixel(0, 0).0, [30, 200, 1]);
+        assert_eq!(thirds.get_pixel(1, 0).0, [120, 200, 1]);
+    }
+
+    #[test]
+    /// Factors of 0 and 1 leave the image as it is, factors larger than the
+    /// image average it whole, and the colour type is kept.
+    fn test_downscale_box_factors() {
+        let rgba = DynamicImage::ImageRgba8(RgbaImage::from_pixel(6, 4, image::Rgba([10, 20, 30, 40])));
+        assert_eq!(downscale_box(&rgba, 0), rgba);
+        assert_eq!(downscale_box(&rgba, 1), rgba);
+        let whole = downscale_box(&rgba, 16);
+        assert_eq!((whole.width(), whole.height()), (1, 1));
+        assert_eq!(whole.to_rgba8().get_pixel(0, 0).0, [10, 20, 30, 40]);
+        let wide = DynamicImage::ImageRgb16(image::ImageBuffer::from_pixel(4, 4, image::Rgb([u16::MAX; 3])));
+        assert_eq!(downscale_box(&wide, 2), DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, image::Rgb([255; 3]))));
+    }
+
+    #[test]
+    /// Images are shrunk to the width asked for keeping their aspect, when
+    /// the factor is a power of two or when a last resize is needed.
+    fn test_downscale_width() {
+        let image = DynamicImage::ImageRgb8(RgbImage::new(128, 64));
+        assert_eq!(downscale(&image, 128), image);
+        let boxed = downscale(&image, 32);
+        assert_eq!((boxed.width(), boxed.height()), (32, 16));
+        let resized = downscale(&image, 48);
+        assert_eq!((resized.width(), resized.height()), (48, 24));
+    }
+
+    #[rstest]
+    #[case(roi(2, 1, 3, 2), Some((2, 1, 3, 2)))]
+    #[case(roi(-2, 2, 4, 10), Some((0, 2, 2, 2)))]
+    #[case(roi(6, 0, 2, 2), None)]
+    #[case(roi(0, 4, 6, 1), None)]
+    /// Regions are clipped to the image before cropping, and those missing
+    /// it crop nothing.
+    fn test_crop_to_roi(#[case] region: Roi, #[case] expected: Option<(u32, u32, u32, u32)>) {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(6, 4, |x, y| {
+            image::Rgb([u8::try_from(x).unwrap(), u8::try_from(y).unwrap(), 0])
+        }));
+        let cropped = crop_to_roi(&image, &region);
+        assert_eq!(cropped.as_ref().map(|c| (c.width(), c.height())), expected.map(|(_, _, w, h)| (w, h)));
+        if let (Some(cropped), Some((x, y, _, _))) = (cropped, expected) {
+            let top_left = cropped.to_rgb8().get_pixel(0, 0).0;
+            assert_eq!(top_left, [u8::try_from(x).unwrap(), u8::try_from(y).unwrap(), 0]);
+        }
+    }
+
+    #[rstest]
+    #[case(ImageFormat::Png)]
+    #[case(ImageFormat::Jpeg)]
+    /// Thumbnails decode to an image whose longer edge is the edge asked
+    /// for, alpha and all.
+    fn test_encode_thumbnail(#[case] format: ImageFormat) {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(300, 120, image::Rgba([0, 128, 255, 255])));
+        let bytes = encode_thumbnail(&image, 50, format).unwrap();
+        let thumbnail = image::load_from_memory_with_format(&bytes, format).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (50, 20));
+
+        let small = DynamicImage::ImageLuma8(GrayImage::new(20, 10));
+        let bytes = encode_thumbnail(&small, 50, format).unwrap();
+        let thumbnail = image::load_from_memory_with_format(&bytes, format).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (20, 10), "Small image was resized");
+    }
+
+    /// Compare an annotated image with a golden png, writing the png first
+    /// when `ONYX_BLESS_GOLDEN` is set.
+    ///
+    /// * `annotated`: image annotated.
+    /// * `path`: path of the golden png.
+    fn assert_golden(annotated: &DynamicImage, path: &Path) {
+        if std::env::var_os("ONYX_BLESS_GOLDEN").is_some() {
+            annotated.save(path).unwrap();
+        }
+        let golden = image::open(path).unwrap().to_rgb8();
+        assert!(golden == annotated.to_rgb8(), "Annotated image differs from {}", path.display());
+    }
+
+    #[test]
+    /// Regions are outlined and labelled in their colour, clipped where
+    /// they run off the image.
+    fn test_annotate_rect_golden() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(24, 16, Rgb([32, 32, 32])));
+        let annotated = annotate(
+            &image,
+            &[
+                Annotation::Rect(roi(2, 3, 18, 11), Rgb([255, 0, 0]), "A1".to_string()),
+                Annotation::Rect(roi(-4, 10, 10, 10), Rgb([0, 255, 0]), String::new()),
+            ],
+        );
+        assert_golden(&annotated, &repo_relative("tests/golden/annotate_rect.png").unwrap());
+    }
+
+    #[test]
+    /// Channel bands are drawn down the full height of a grey image and
+    /// labelled at the top.
+    fn test_annotate_bands_golden() {
+        let image = DynamicImage::ImageLuma8(GrayImage::from_pixel(20, 12, image::Luma([64])));
+        let annotated = annotate(
+            &image,
+            &[
+                Annotation::VerticalBand(0, 10, "0".to_string()),
+                Annotation::VerticalBand(10, 20, "1".to_string()),
+            ],
+        );
+        assert_golden(&annotated, &repo_relative("tests/golden/annotate_bands.png").unwrap());
+    }
+
+    #[test]
+    /// Labels are drawn upper case, characters without a glyph as a space,
+    /// and empty annotations leave the image as it was.
+    fn test_annotate_labels_and_empty() {
+        let image = DynamicImage::ImageRgb8(RgbImage::new(32, 12));
+        let colour = Rgb([255, 255, 255]);
+        let label = |text: &str| annotate(&image, &[Annotation::Rect(roi(0, 0, 32, 12), colour, text.to_string())]);
+        assert_eq!(label("ch-1"), label("CH-1"));
+        assert_eq!(label("A~B"), label("A B"));
+        assert_ne!(label("A B"), label("AB"));
+        assert_eq!(
+            annotate(
+                &image,
+                &[
+                    Annotation::Rect(roi(4, 4, 0, 8), colour, "X".to_string()),
+                    Annotation::VerticalBand(6, 6, "X".to_string()),
+                ]
+            ),
+            image
+        );
+    }
+
+    /// Smooth pattern varying across and down the frame, moved right by a
+    /// number of pixels.
+    ///
+    /// * `shift`: pixels the pattern is moved by.
+    /// * `inverted`: dark and light swapped.
+    fn pattern(shift: u32, inverted: bool) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(288, 256, |x, y| {
+            let phase = std::f64::consts::TAU * f64::from(x + shift) / 192.0 + f64::from(y) / 40.0;
+            let value = (128.0 + 100.0 * phase.sin()).round().clamp(0.0, 255.0);
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let value = value as u8;
+            image::Luma([if inverted { 255 - value } else { value }])
+        }))
+    }
+
+    #[test]
+    /// Identical frames hash the same, frames moved a few pixels hash a
+    /// few bits apart and different frames most of the bits apart.
+    fn test_dhash() {
+        let frame = dhash(&pattern(0, false));
+        assert_eq!(frame, dhash(&pattern(0, false)));
+        assert_eq!(hamming_distance(frame, frame), 0);
+        let shifted = hamming_distance(frame, dhash(&pattern(3, false)));
+        assert!(shifted <= 8, "Shifted frame is {shifted} bits apart");
+        let different = hamming_distance(frame, dhash(&pattern(0, true)));
+        assert!(different >= 48, "Different frame is only {different} bits apart");
+        assert_eq!(dhash(&pattern(0, false).to_rgb8().into()), frame);
+        assert_eq!(hamming_distance(0, u64::MAX), 64);
+    }
+}
@@ -0,0 +1,216 @@
+use serde::{de::Visitor, Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+
+/// Crop bed modules on the machine. The numbering in earlier configs was
+/// never agreed between disciplines, the legacy integers map as
+/// 0 = left boom, 1 = centre, 2 = right boom. Any other integer is kept as
+/// `Numbered` so machines with more modules still parse.
+///
+/// Serialised as its name (`left_boom`, `centre`, `right_boom`) or the
+/// integer for numbered modules, and deserialised from either form.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CropBed {
+    /// Boom on the left hand side in the direction of travel.
+    LeftBoom,
+    /// Fixed width centre module.
+    Centre,
+    /// Boom on the right hand side in the direction of travel.
+    RightBoom,
+    /// Module outside of the three named ones, build with
+    /// [`CropBed::from`] so 0 to 2 map to the named modules.
+    Numbered(u8),
+}
+
+impl CropBed {
+    /// Legacy integer id of the module.
+    pub fn id(&self) -> u8 {
+        match self {
+            CropBed::LeftBoom => 0,
+            CropBed::Centre => 1,
+            CropBed::RightBoom => 2,
+            CropBed::Numbered(id) => *id,
+        }
+    }
+}
+
+impl From<u8> for CropBed {
+    fn from(id: u8) -> Self {
+        match id {
+            0 => CropBed::LeftBoom,
+            1 => CropBed::Centre,
+            2 => CropBed::RightBoom,
+            id => CropBed::Numbered(id),
+        }
+    }
+}
+
+impl Display for CropBed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CropBed::LeftBoom => write!(f, "left_boom"),
+            CropBed::Centre => write!(f, "centre"),
+            CropBed::RightBoom => write!(f, "right_boom"),
+            CropBed::Numbered(id) => write!(f, "{id}"),
+        }
+    }
+}
+
+impl FromStr for CropBed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace(['_', '-', ' '], "").as_str() {
+            "leftboom" | "left" => Ok(CropBed::LeftBoom),
+            "centre" | "center" => Ok(CropBed::Centre),
+            "rightboom" | "right" => Ok(CropBed::RightBoom),
+            other => other
+                .parse::<u8>()
+                .map(CropBed::from)
+                .map_err(|e| format!("Unknown crop bed {s}: {e}")),
+        }
+    }
+}
+
+impl Serialize for CropBed {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            CropBed::Numbered(id) => serializer.serialize_u8(*id),
+            named => serializer.serialize_str(&named.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CropBed {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(CropBedVisitor)
+    }
+}
+
+/// Visitor accepting both the legacy integers and the module names.
+struct CropBedVisitor;
+
+impl<'de> Visitor<'de> for CropBedVisitor {
+    type Value = CropBed;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str("a crop bed name or an integer id between 0 and 255")
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        u8::try_from(value)
+            .map(CropBed::from)
+            .map_err(|e| E::custom(format!("Crop bed id {value} out of range: {e}")))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        u8::try_from(value)
+            .map(CropBed::from)
+            .map_err(|e| E::custom(format!("Crop bed id {value} out of range: {e}")))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        CropBed::from_str(value).map_err(E::custom)
+    }
+}
+
+/// Where the machine was on the ground, as a GPS fix or the position the
+/// AI system tagged a weed with.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct GeoPosition {
+    /// Latitude in decimal degrees, north positive.
+    pub latitude: f64,
+    /// Longitude in decimal degrees, east positive.
+    pub longitude: f64,
+    /// Heading in degrees clockwise from true north, when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heading_deg: Option<f64>,
+}
+
+impl GeoPosition {
+    /// Position without a heading.
+    ///
+    /// * `latitude`: decimal degrees, north positive.
+    /// * `longitude`: decimal degrees, east positive.
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+            heading_deg: None,
+        }
+    }
+
+    /// Set the heading.
+    ///
+    /// * `heading_deg`: degrees clockwise from true north.
+    pub fn with_heading(mut self, heading_deg: f64) -> Self {
+        self.heading_deg = Some(heading_deg);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// Stand in for a component config with a crop bed field.
+    #[derive(Deserialize, Serialize, Debug, PartialEq)]
+    struct Config {
+        /// Crop bed under test.
+        crop_bed_id: CropBed,
+    }
+
+    #[rstest]
+    #[case("crop_bed_id: 0", CropBed::LeftBoom)]
+    #[case("crop_bed_id: 1", CropBed::Centre)]
+    #[case("crop_bed_id: 2", CropBed::RightBoom)]
+    #[case("crop_bed_id: 7", CropBed::Numbered(7))]
+    #[case("crop_bed_id: left_boom", CropBed::LeftBoom)]
+    #[case("crop_bed_id: Centre", CropBed::Centre)]
+    #[case("crop_bed_id: RightBoom", CropBed::RightBoom)]
+    /// Legacy integer configs and the new names should both parse from
+    /// yaml the same way component configs are read.
+    fn test_crop_bed_from_yaml(#[case] yaml: &str, #[case] expected: CropBed) {
+        let config = serde_yaml::from_str::<Config>(yaml).expect("Failed to parse config");
+        assert_eq!(config.crop_bed_id, expected);
+    }
+
+    #[rstest]
+    #[case(CropBed::LeftBoom)]
+    #[case(CropBed::Centre)]
+    #[case(CropBed::RightBoom)]
+    #[case(CropBed::Numbered(12))]
+    /// Round trip through yaml and json, and through the integer id.
+    fn test_crop_bed_round_trip(#[case] crop_bed: CropBed) {
+        let config = Config { crop_bed_id: crop_bed };
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert_eq!(serde_yaml::from_str::<Config>(&yaml).unwrap(), config);
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(serde_json::from_str::<Config>(&json).unwrap(), config);
+        assert_eq!(CropBed::from(crop_bed.id()), crop_bed);
+        assert_eq!(crop_bed.to_string().parse::<CropBed>().unwrap(), crop_bed);
+    }
+
+    #[test]
+    /// Out of range and unknown values are rejected.
+    fn test_crop_bed_rejects_unknown() {
+        assert!(serde_json::from_str::<CropBed>("256").is_err());
+        assert!(serde_json::from_str::<CropBed>("-1").is_err());
+        assert!("middle".parse::<CropBed>().is_err());
+    }
+}
@@ -0,0 +1,601 @@
+use crate::{
+    messages::{
+        control::heartbeat::ComponentKind,
+        logging::{EventCode, LogEvent, LogLevel},
+    },
+    utils::location::CropBed,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    fs::OpenOptions,
+    io::{self, Write},
+    net::TcpStream,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+use tracing::{info, warn, Level, Span};
+use tracing_subscriber::EnvFilter;
+use uuid::Uuid;
+
+/// Events held for the writer, when not set in the config. Events past it
+/// are dropped rather than holding up the component.
+pub const DEFAULT_LOG_CAPACITY: usize = 1024;
+
+/// Filter the events are printed through when neither the command line nor
+/// `RUST_LOG` sets one.
+pub const DEFAULT_LOG_FILTER: &str = "info";
+
+/// Where the events of a component are written, one line of json each.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogSink {
+    /// Appended to a file, created if it does not exist.
+    File(PathBuf),
+    /// Forwarded to a collector over a connection kept open, reconnected
+    /// when it drops.
+    Tcp(String),
+}
+
+impl LogSink {
+    /// Open the file or connect to the collector.
+    fn open(&self) -> io::Result<Box<dyn Write>> {
+        Ok(match self {
+            Self::File(path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+            Self::Tcp(address) => Box::new(TcpStream::connect(address)?),
+        })
+    }
+}
+
+impl Display for LogSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Tcp(address) => write!(f, "{address}"),
+        }
+    }
+}
+
+/// Where a component writes its structured log.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LogConfig {
+    /// File or collector the events go to.
+    pub sink: LogSink,
+    /// Events held for the writer before they are dropped.
+    #[serde(default = "default_log_capacity")]
+    pub capacity: usize,
+}
+
+/// Default capacity for serde.
+fn default_log_capacity() -> usize {
+    DEFAULT_LOG_CAPACITY
+}
+
+impl LogConfig {
+    /// Append the events to a file.
+    ///
+    /// * `path`: file the events are appended to.
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self {
+            sink: LogSink::File(path.into()),
+            capacity: DEFAULT_LOG_CAPACITY,
+        }
+    }
+
+    /// Forward the events to a collector over TCP.
+    ///
+    /// * `address`: address of the collector, e.g. `127.0.0.1:17690`.
+    pub fn tcp(address: impl Into<String>) -> Self {
+        Self {
+            sink: LogSink::Tcp(address.into()),
+            capacity: DEFAULT_LOG_CAPACITY,
+        }
+    }
+
+    /// Set the events held for the writer.
+    ///
+    /// * `capacity`: events held before they are dropped.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+}
+
+/// How a binary prints the events of its components and devices, set from
+/// its command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TracingConfig {
+    /// Filter directives such as `debug` or `info,onyx::devices=debug`,
+    /// `RUST_LOG` then [`DEFAULT_LOG_FILTER`] when not set.
+    filter: Option<String>,
+    /// Print each event as a line of json along with its spans.
+    json: bool,
+    /// Print to stderr, leaving stdout to the output of a tool.
+    stderr: bool,
+}
+
+impl TracingConfig {
+    /// Config from the command line of a binary, printing to stdout.
+    ///
+    /// * `filter`: filter directives, `RUST_LOG` when not set.
+    /// * `json`: print the events as json rather than text.
+    pub fn new(filter: Option<String>, json: bool) -> Self {
+        Self {
+            filter,
+            json,
+            stderr: false,
+        }
+    }
+
+    /// Print to stderr rather than stdout.
+    pub fn with_stderr(mut self) -> Self {
+        self.stderr = true;
+        self
+    }
+
+    /// Filter from the directives of the config, `RUST_LOG` or the
+    /// default, in that order. Panics when the directives cannot be read,
+    /// so a typo does not quietly hide every event.
+    pub fn env_filter(&self) -> EnvFilter {
+        let directives = self
+            .filter
+            .clone()
+            .or_else(|| std::env::var(EnvFilter::DEFAULT_ENV).ok())
+            .filter(|directives| !directives.is_empty())
+            .unwrap_or_else(|| String::from(DEFAULT_LOG_FILTER));
+        EnvFilter::try_new(&directives).unwrap_or_else(|e| panic!("Invalid log filter {directives:?}, {e}"))
+    }
+
+    /// Install the subscriber printing the events of the process, for the
+    /// rest of it. Panics if one has been installed already.
+    pub fn init(&self) {
+        let builder = tracing_subscriber::fmt().with_env_filter(self.env_filter());
+        match (self.json, self.stderr) {
+            (true, true) => builder.json().with_writer(io::stderr).init(),
+            (true, false) => builder.json().init(),
+            (false, true) => builder.with_writer(io::stderr).init(),
+            (false, false) => builder.init(),
+        }
+    }
+}
+
+/// Spawn a thread running in the current span, so the events of the thread
+/// carry the fields of the component that started it.
+///
+/// * `f`: body of the thread.
+pub fn spawn_in_current_span<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let span = Span::current();
+    std::thread::spawn(move || span.in_scope(f))
+}
+
+/// Emits the events of one component, tracing each and handing it to the
+/// writer of the structured log when there is one. Emitting never waits on
+/// the writer, so a slow disk or collector cannot hold up the firing loop.
+#[derive(Debug, Clone)]
+pub struct LogEmitter {
+    /// Kind of component the events come from.
+    kind: ComponentKind,
+    /// Unique id of the component.
+    component: Uuid,
+    /// Crop bed the component is attached to.
+    crop_bed_id: CropBed,
+    /// Events queued for the writer, `None` to only trace them.
+    sender: Option<SyncSender<LogEvent>>,
+    /// Events dropped because the writer had fallen behind.
+    dropped: Arc<AtomicU64>,
+}
+
+impl LogEmitter {
+    /// Emitter tracing the events of a component, without a structured
+    /// log until one is started.
+    ///
+    /// * `kind`: kind of component.
+    /// * `component`: unique id of the component.
+    /// * `crop_bed_id`: crop bed the component is attached to.
+    pub fn new(kind: ComponentKind, component: Uuid, crop_bed_id: CropBed) -> Self {
+        Self {
+            kind,
+            component,
+            crop_bed_id,
+            sender: None,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Start writing the events of this emitter, and those cloned from it
+    /// after, to the sink of a config. The writer thread ends once every
+    /// clone has been dropped and the events queued have been written.
+    ///
+    /// * `config`: where the events go.
+    pub fn start(&mut self, config: &LogConfig) -> JoinHandle<()> {
+        let (sender, receiver) = sync_channel(config.capacity);
+        self.sender = Some(sender);
+        let sink = config.sink.clone();
+        std::thread::spawn(move || write_events(&receiver, &sink))
+    }
+
+    /// Start writing the events to the sink of a config if there is one,
+    /// leaving the writer to end with the component.
+    ///
+    /// * `config`: where the events go, they are only traced when `None`.
+    pub fn with_config(mut self, config: Option<&LogConfig>) -> Self {
+        if let Some(config) = config {
+            drop(self.start(config));
+        }
+        self
+    }
+
+    /// Event of the component happening now, to add fields to before it
+    /// is emitted.
+    ///
+    /// * `level`: how much it matters.
+    /// * `code`: what happened.
+    /// * `message`: what happened, readable by the operator.
+    pub fn event(&self, level: LogLevel, code: EventCode, message: impl Into<String>) -> LogEvent {
+        LogEvent::new(level, self.kind, self.component, self.crop_bed_id, code, message)
+    }
+
+    /// Span the tasks and threads of the component run in, so the events
+    /// of its devices carry the component they belong to.
+    pub fn span(&self) -> Span {
+        tracing::info_span!(
+            "component",
+            kind = %self.kind,
+            component = %self.component,
+            crop_bed_id = %self.crop_bed_id
+        )
+    }
+
+    /// Trace an event at its level and queue it for the writer, dropping it
+    /// if the writer has fallen behind. The fields added to the event are
+    /// traced as one json object.
+    ///
+    /// * `event`: event emitted.
+    pub fn emit(&self, event: LogEvent) {
+        let code: &'static str = event.code.into();
+        let fields = (!event.fields.is_empty())
+            .then(|| serde_json::to_string(&event.fields).expect("Failed to serialise log fields"));
+        macro_rules! trace_event {
+            ($level:expr) => {
+                tracing::event!(
+                    $level,
+                    kind = %event.kind,
+                    component = %event.component,
+                    crop_bed_id = %event.crop_bed_id,
+                    code,
+                    fields = fields.as_deref(),
+                    "{}",
+                    event.message
+                )
+            };
+        }
+        match event.level {
+            LogLevel::Debug => trace_event!(Level::DEBUG),
+            LogLevel::Info => trace_event!(Level::INFO),
+            LogLevel::Warn => trace_event!(Level::WARN),
+            LogLevel::Error => trace_event!(Level::ERROR),
+        }
+        if let Some(sender) = &self.sender {
+            if let Err(TrySendError::Full(_)) = sender.try_send(event) {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Emit an event of normal running.
+    ///
+    /// * `code`: what happened.
+    /// * `message`: what happened, readable by the operator.
+    pub fn info(&self, code: EventCode, message: impl Into<String>) {
+        self.emit(self.event(LogLevel::Info, code, message));
+    }
+
+    /// Emit an event the component carries on from.
+    ///
+    /// * `code`: what happened.
+    /// * `message`: what happened, readable by the operator.
+    pub fn warn(&self, code: EventCode, message: impl Into<String>) {
+        self.emit(self.event(LogLevel::Warn, code, message));
+    }
+
+    /// Emit a failure.
+    ///
+    /// * `code`: what happened.
+    /// * `message`: what happened, readable by the operator.
+    pub fn error(&self, code: EventCode, message: impl Into<String>) {
+        self.emit(self.event(LogLevel::Error, code, message));
+    }
+
+    /// Events dropped so far because the writer had fallen behind, counted
+    /// across the clones of the emitter.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Write each event received as a line of json until every emitter is
+/// dropped. A sink that cannot be written is logged once, when it first
+/// fails, and opened again for the next event.
+///
+/// * `receiver`: events queued by the emitters.
+/// * `sink`: file or collector the events go to.
+fn write_events(receiver: &Receiver<LogEvent>, sink: &LogSink) {
+    let mut writer = None;
+    let mut reachable = true;
+    for event in receiver {
+        let mut line = serde_json::to_vec(&event).expect("Failed to serialise log event");
+        line.push(b'\n');
+        let written = match writer.take() {
+            Some(writer) => Ok(writer),
+            None => sink.open(),
+        }
+        .and_then(|mut writer| writer.write_all(&line).map(|()| writer));
+        match written {
+            Ok(written) => {
+                if !reachable {
+                    info!("Writing log events to {sink} again");
+                    reachable = true;
+                }
+                writer = Some(written);
+            }
+            Err(e) if reachable => {
+                warn!("Failed to write log events to {sink}, dropping them: {e}");
+                reachable = false;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// Subscriber the tests capture events with. It is installed for the whole
+/// process the first time it is asked for, as the components trace from
+/// threads and tasks of their own, so the tests pick out their events by
+/// the fields of the component or device that emitted them.
+#[cfg(test)]
+pub(crate) mod capture {
+    use std::{
+        collections::BTreeMap,
+        fmt::Debug,
+        sync::{Arc, Mutex, OnceLock},
+    };
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id},
+        Event, Level, Subscriber,
+    };
+    use tracing_subscriber::{filter::LevelFilter, layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+    /// Event traced while the capture was installed.
+    #[derive(Debug, Clone)]
+    pub(crate) struct CapturedEvent {
+        /// Level of the event.
+        pub level: Level,
+        /// Fields of the spans the event was in, outermost first, then of
+        /// the event itself, its text under `message`. Values are as they
+        /// were formatted.
+        pub fields: BTreeMap<String, String>,
+    }
+
+    impl CapturedEvent {
+        /// Value of a field of the event or its spans.
+        ///
+        /// * `key`: name of the field.
+        pub fn field(&self, key: &str) -> Option<&str> {
+            self.fields.get(key).map(String::as_str)
+        }
+    }
+
+    /// Fields a span was created with.
+    struct SpanFields(BTreeMap<String, String>);
+
+    /// Records fields into a map, strings without their quotes.
+    struct FieldMap<'a>(&'a mut BTreeMap<String, String>);
+
+    impl Visit for FieldMap<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.insert(field.name().to_string(), format!("{value:?}"));
+        }
+    }
+
+    /// Layer adding each event to the list the tests read.
+    struct CaptureLayer(Arc<Mutex<Vec<CapturedEvent>>>);
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for CaptureLayer {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            let mut fields = BTreeMap::new();
+            attrs.record(&mut FieldMap(&mut fields));
+            if let Some(span) = ctx.span(id) {
+                span.extensions_mut().insert(SpanFields(fields));
+            }
+        }
+
+        fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+            let mut fields = BTreeMap::new();
+            if let Some(scope) = ctx.event_scope(event) {
+                for span in scope.from_root() {
+                    if let Some(SpanFields(span_fields)) = span.extensions().get::<SpanFields>() {
+                        fields.extend(span_fields.clone());
+                    }
+                }
+            }
+            event.record(&mut FieldMap(&mut fields));
+            self.0.lock().expect("Captured events poisoned").push(CapturedEvent {
+                level: *event.metadata().level(),
+                fields,
+            });
+        }
+    }
+
+    /// Events captured since the capture was installed.
+    static CAPTURED: OnceLock<Arc<Mutex<Vec<CapturedEvent>>>> = OnceLock::new();
+
+    /// Capture the events at debug and above from now on, if not already.
+    pub(crate) fn capture_events() {
+        CAPTURED.get_or_init(|| {
+            let captured = Arc::new(Mutex::new(Vec::new()));
+            tracing_subscriber::registry()
+                .with(LevelFilter::DEBUG)
+                .with(CaptureLayer(captured.clone()))
+                .try_init()
+                .expect("Another subscriber was installed before the capture");
+            captured
+        });
+    }
+
+    /// Events captured so far with a field of a value, e.g. the uuid of a
+    /// component.
+    ///
+    /// * `key`: name of the field.
+    /// * `value`: value of the field as it was formatted.
+    pub(crate) fn captured_with(key: &str, value: &str) -> Vec<CapturedEvent> {
+        CAPTURED
+            .get()
+            .expect("Events are not being captured")
+            .lock()
+            .expect("Captured events poisoned")
+            .iter()
+            .filter(|event| event.field(key) == Some(value))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        time::{Duration, Instant},
+    };
+    use tracing::level_filters::LevelFilter;
+
+    /// Emitter of a power component.
+    fn power_emitter() -> LogEmitter {
+        LogEmitter::new(ComponentKind::Power, Uuid::new_v4(), CropBed::Centre)
+    }
+
+    #[test]
+    /// Events are appended to the file as lines of json reading back as
+    /// they were emitted, once every clone of the emitter is dropped.
+    fn test_write_jsonl_file() {
+        let path = std::env::temp_dir().join(format!("onyx-log-{}.jsonl", Uuid::new_v4()));
+        let mut emitter = power_emitter();
+        let writer = emitter.start(&LogConfig::file(&path));
+        let event = emitter
+            .event(LogLevel::Warn, EventCode::PdmMismatch, "PDM 31 did not read back")
+            .with_field("pdm", 31);
+        emitter.emit(event.clone());
+        let clone = emitter.clone();
+        clone.info(EventCode::ShutDown, "Crop bed power on can0 shut down");
+        drop((emitter, clone));
+        writer.join().unwrap();
+
+        let events: Vec<LogEvent> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0], event);
+        assert_eq!((events[1].level, events[1].code), (LogLevel::Info, EventCode::ShutDown));
+        assert_eq!(events[1].component, event.component);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    /// Events are forwarded to a collector over TCP.
+    fn test_forward_to_collector() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut emitter = power_emitter();
+        let writer = emitter.start(&LogConfig::tcp(listener.local_addr().unwrap().to_string()));
+        emitter.error(EventCode::PdmDrifted, "PDM configuration has drifted");
+        let (stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        let event: LogEvent = serde_json::from_str(&line).unwrap();
+        assert_eq!((event.level, event.code), (LogLevel::Error, EventCode::PdmDrifted));
+        drop(emitter);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    /// Events are traced at their level with the fields of the component,
+    /// those added to the event as json, and inside the span of the
+    /// component when one is entered.
+    fn test_emit_traces_event() {
+        capture::capture_events();
+        let emitter = power_emitter();
+        let component = emitter.component.to_string();
+        emitter.emit(
+            emitter
+                .event(LogLevel::Warn, EventCode::PdmMismatch, "PDM 31 did not read back")
+                .with_field("pdm", 31),
+        );
+        emitter.span().in_scope(|| tracing::debug!(channel = 4, "Device event"));
+
+        let events = capture::captured_with("component", &component);
+        assert_eq!(events.len(), 2, "{events:?}");
+        assert_eq!(events[0].level, Level::WARN);
+        assert_eq!(events[0].field("message"), Some("PDM 31 did not read back"));
+        assert_eq!(events[0].field("code"), Some("pdm_mismatch"));
+        assert_eq!(events[0].field("kind"), Some(ComponentKind::Power.to_string().as_str()));
+        assert_eq!(
+            events[0].field("crop_bed_id"),
+            Some(CropBed::Centre.to_string().as_str())
+        );
+        assert_eq!(events[0].field("fields"), Some(r#"{"pdm":31}"#));
+        assert_eq!(events[1].level, Level::DEBUG);
+        assert_eq!(
+            (events[1].field("channel"), events[1].field("fields")),
+            (Some("4"), None)
+        );
+    }
+
+    #[test]
+    /// The filter of the command line is read as directives, whatever
+    /// `RUST_LOG` is set to.
+    fn test_env_filter() {
+        let filter = TracingConfig::new(Some(String::from("debug")), false).env_filter();
+        assert_eq!(filter.max_level_hint(), Some(LevelFilter::DEBUG));
+        let filter = TracingConfig::new(Some(String::from("warn,onyx::devices=trace")), true).env_filter();
+        assert_eq!(filter.max_level_hint(), Some(LevelFilter::TRACE));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid log filter")]
+    /// A filter that cannot be read is refused rather than hiding every
+    /// event.
+    fn test_env_filter_refuses_invalid_directives() {
+        let _ = TracingConfig::new(Some(String::from("onyx=loud")), false).env_filter();
+    }
+
+    #[test]
+    /// A writer that has stopped taking events never holds up the caller,
+    /// the events past the capacity are dropped and counted.
+    fn test_emit_never_blocks() {
+        let (sender, _receiver) = sync_channel(2);
+        let mut emitter = power_emitter();
+        emitter.sender = Some(sender);
+        let started = Instant::now();
+        for _ in 0..100 {
+            emitter.info(EventCode::Status, "status");
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(emitter.dropped(), 98);
+        assert_eq!(emitter.clone().dropped(), 98);
+    }
+}
@@ -0,0 +1,256 @@
+#![cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+
+#[cfg(feature = "metrics")]
+use prometheus::{Encoder, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+#[cfg(feature = "metrics")]
+use std::{
+    io,
+    net::TcpListener,
+    sync::OnceLock,
+    thread::{self, JoinHandle},
+};
+
+/// Every metric family exported, registered on first use.
+#[cfg(feature = "metrics")]
+struct Families {
+    /// Registry scraped by the exporter.
+    registry: Registry,
+    /// Weed messages received by how they were handled.
+    weed_messages: IntCounterVec,
+    /// Messages waiting in the spray queue of each canbus interface.
+    queue_depth: IntGaugeVec,
+    /// Output commands sent to each PDM for sprays.
+    actuations: IntCounterVec,
+    /// Heartbeats sent to each PDM.
+    heartbeats: IntCounterVec,
+    /// Faults reported by each PDM.
+    pdm_faults: IntCounterVec,
+    /// Frames captured by each camera.
+    frames_captured: IntCounterVec,
+    /// Frames from each camera that were not saved.
+    frames_dropped: IntCounterVec,
+    /// Images that failed to be written.
+    image_write_failures: IntCounter,
+}
+
+#[cfg(feature = "metrics")]
+impl Families {
+    /// Create and register every family.
+    fn register() -> Self {
+        let registry = Registry::new();
+        let counter_vec = |name: &str, help: &str, labels: &[&str]| {
+            let family = IntCounterVec::new(Opts::new(name, help), labels).expect("Invalid metric");
+            registry.register(Box::new(family.clone())).expect("Failed to register metric");
+            family
+        };
+        let weed_messages = counter_vec(
+            "onyx_weed_messages_total",
+            "Weed messages received by how they were handled.",
+            &["status"],
+        );
+        let actuations = counter_vec(
+            "onyx_pdm_actuations_total",
+            "Output commands sent to each PDM for sprays.",
+            &["bed_position"],
+        );
+        let heartbeats = counter_vec("onyx_pdm_heartbeats_total", "Heartbeats sent to each PDM.", &["bed_position"]);
+        let pdm_faults = counter_vec(
+            "onyx_pdm_faults_total",
+            "Faults reported by each PDM.",
+            &["bed_position", "fault"],
+        );
+        let frames_captured = counter_vec(
+            "onyx_camera_frames_captured_total",
+            "Frames captured by each camera.",
+            &["camera"],
+        );
+        let frames_dropped = counter_vec(
+            "onyx_camera_frames_dropped_total",
+            "Frames from each camera that were not saved.",
+            &["camera", "reason"],
+        );
+        let queue_depth = IntGaugeVec::new(
+            Opts::new("onyx_spray_queue_depth", "Messages waiting in the spray queue."),
+            &["canbus_id"],
+        )
+        .expect("Invalid metric");
+        registry
+            .register(Box::new(queue_depth.clone()))
+            .expect("Failed to register metric");
+        let image_write_failures =
+            IntCounter::new("onyx_image_write_failures_total", "Images that failed to be written.")
+                .expect("Invalid metric");
+        registry
+            .register(Box::new(image_write_failures.clone()))
+            .expect("Failed to register metric");
+        Self {
+            registry,
+            weed_messages,
+            queue_depth,
+            actuations,
+            heartbeats,
+            pdm_faults,
+            frames_captured,
+            frames_dropped,
+            image_write_failures,
+        }
+    }
+}
+
+/// Families of the process, registered on first use.
+#[cfg(feature = "metrics")]
+fn families() -> &'static Families {
+    static FAMILIES: OnceLock<Families> = OnceLock::new();
+    FAMILIES.get_or_init(Families::register)
+}
+
+/// Count a weed message received.
+///
+/// * `status`: how it was handled, i.e. accepted.
+pub fn weed_message(status: &str) {
+    #[cfg(feature = "metrics")]
+    families().weed_messages.with_label_values(&[status]).inc();
+}
+
+/// Set the messages waiting in a spray queue.
+///
+/// * `canbus_id`: interface of the component.
+/// * `depth`: messages queued.
+pub fn queue_depth(canbus_id: &str, depth: usize) {
+    #[cfg(feature = "metrics")]
+    families()
+        .queue_depth
+        .with_label_values(&[canbus_id])
+        .set(i64::try_from(depth).unwrap_or(i64::MAX));
+}
+
+/// Count an output command sent to a PDM for a spray.
+///
+/// * `bed_position`: key of the PDM in the component.
+pub fn actuation(bed_position: u8) {
+    #[cfg(feature = "metrics")]
+    families()
+        .actuations
+        .with_label_values(&[&bed_position.to_string()])
+        .inc();
+}
+
+/// Count a heartbeat sent to a PDM.
+///
+/// * `bed_position`: key of the PDM in the component.
+pub fn heartbeat(bed_position: u8) {
+    #[cfg(feature = "metrics")]
+    families()
+        .heartbeats
+        .with_label_values(&[&bed_position.to_string()])
+        .inc();
+}
+
+/// Count a fault reported by a PDM.
+///
+/// * `bed_position`: key of the PDM in the component.
+/// * `fault`: what went wrong, i.e. `loss_of_can`.
+pub fn pdm_fault(bed_position: u8, fault: &str) {
+    #[cfg(feature = "metrics")]
+    families()
+        .pdm_faults
+        .with_label_values(&[&bed_position.to_string(), fault])
+        .inc();
+}
+
+/// Count a frame captured by a camera.
+///
+/// * `camera`: bed position of the camera, or its uuid when not placed.
+pub fn frame_captured(camera: &str) {
+    #[cfg(feature = "metrics")]
+    families().frames_captured.with_label_values(&[camera]).inc();
+}
+
+/// Count a frame from a camera that was not saved.
+///
+/// * `camera`: bed position of the camera, or its uuid when not placed.
+/// * `reason`: why, i.e. `late` or `queue_full`.
+pub fn frame_dropped(camera: &str, reason: &str) {
+    #[cfg(feature = "metrics")]
+    families().frames_dropped.with_label_values(&[camera, reason]).inc();
+}
+
+/// Count an image that failed to be written.
+pub fn image_write_failure() {
+    #[cfg(feature = "metrics")]
+    families().image_write_failures.inc();
+}
+
+/// Every metric in the Prometheus text format.
+#[cfg(feature = "metrics")]
+pub fn gather() -> String {
+    let mut text = Vec::new();
+    TextEncoder::new()
+        .encode(&families().registry.gather(), &mut text)
+        .expect("Failed to encode metrics");
+    String::from_utf8(text).expect("Metrics are not utf-8")
+}
+
+/// Serve `GET /metrics` to the Prometheus scraper until the process exits.
+///
+/// * `listener`: bound listener, use port 0 in tests.
+#[cfg(feature = "metrics")]
+pub async fn serve(listener: TcpListener) -> io::Result<()> {
+    listener.set_nonblocking(true)?;
+    let router = axum::Router::new().route("/metrics", axum::routing::get(|| async { gather() }));
+    axum::Server::from_tcp(listener)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        .serve(router.into_make_service())
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+/// Run [`serve`] on its own runtime in a new thread, so synchronous and
+/// tokio binaries alike export their metrics.
+///
+/// * `listener`: bound listener.
+#[cfg(feature = "metrics")]
+pub fn spawn(listener: TcpListener) -> JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(serve(listener))
+    })
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    /// Each family is exported once it has been counted.
+    async fn test_scrape_every_family() {
+        weed_message("accepted");
+        queue_depth("vcan-metrics", 3);
+        actuation(0);
+        heartbeat(0);
+        pdm_fault(0, "loss_of_can");
+        frame_captured("1");
+        frame_dropped("1", "late");
+        image_write_failure();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/metrics", listener.local_addr().unwrap());
+        spawn(listener);
+        let text = reqwest::get(url).await.unwrap().text().await.unwrap();
+        for family in [
+            "onyx_weed_messages_total",
+            "onyx_spray_queue_depth",
+            "onyx_pdm_actuations_total",
+            "onyx_pdm_heartbeats_total",
+            "onyx_pdm_faults_total",
+            "onyx_camera_frames_captured_total",
+            "onyx_camera_frames_dropped_total",
+            "onyx_image_write_failures_total",
+        ] {
+            assert!(text.contains(&format!("# TYPE {family} ")), "{family} missing from\n{text}");
+        }
+        assert!(text.contains("onyx_spray_queue_depth{canbus_id=\"vcan-metrics\"} 3"), "{text}");
+    }
+}
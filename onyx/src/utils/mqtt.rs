@@ -0,0 +1,998 @@
+use crate::{
+    messages::{
+        control::{
+            heartbeat::{ComponentKind, Heartbeat},
+            light::LightMessage,
+            manual::ManualSprayMessage,
+            pdm::PdmControlMessage,
+            response::ControlResponse,
+            weed::{deserialize_intensity, full_intensity},
+        },
+        logging::LogEvent,
+        telemetry::Telemetry,
+    },
+    utils::{
+        config::{load_yaml, ConfigError},
+        location::CropBed,
+        net::{FrameRead, FramedCodec, Framing},
+        responses::parse_response,
+    },
+};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use std::{ffi::OsStr, fmt::Display, io, path::Path, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tracing::{info, warn};
+
+/// Port of the broker when the url does not give one.
+pub const DEFAULT_MQTT_PORT: u16 = 1883;
+
+/// Topic prefix of the telemetry snapshots when not set.
+pub const DEFAULT_TELEMETRY_PREFIX: &str = "onyx/telemetry";
+
+/// Topic prefix of the heartbeats when not set.
+pub const DEFAULT_HEARTBEAT_PREFIX: &str = "onyx/heartbeat";
+
+/// Topic prefix of the log events when not set.
+pub const DEFAULT_LOG_PREFIX: &str = "onyx/log";
+
+/// Topic prefix of the commands from the operator when not set.
+pub const DEFAULT_COMMAND_PREFIX: &str = "onyx/command";
+
+/// Command topic turning lights on or off.
+pub const LIGHT_COMMAND: &str = "light";
+
+/// Command topic starting or ending a dry run.
+pub const DRY_RUN_COMMAND: &str = "dry_run";
+
+/// Command topic firing a manual spray.
+pub const MANUAL_SPRAY_COMMAND: &str = "manual_spray";
+
+/// Topic under the command prefix the responses of the components are
+/// published on.
+pub const RESPONSE_TOPIC: &str = "response";
+
+/// Time in milliseconds a component is given to respond to a command.
+pub const COMPONENT_RESPONSE_TIMEOUT_MS: u64 = 1000;
+
+/// Publications held for the broker before the listeners wait on it.
+const PUBLICATION_CAPACITY: usize = 64;
+
+/// Broker the bridge connects to, the topics it uses and the components it
+/// carries messages between.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MqttBridgeConfig {
+    /// Url of the broker, e.g. `mqtt://192.168.1.10:1883`, on port
+    /// [`DEFAULT_MQTT_PORT`] when it gives none.
+    pub broker_url: String,
+    /// Client id the bridge connects with, unique on the broker.
+    pub client_id: String,
+    /// User the bridge connects as, anonymous when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Password of the user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Prefix of the telemetry topics.
+    #[serde(default = "default_telemetry_prefix")]
+    pub telemetry_prefix: String,
+    /// Prefix of the heartbeat topics.
+    #[serde(default = "default_heartbeat_prefix")]
+    pub heartbeat_prefix: String,
+    /// Prefix of the log topics.
+    #[serde(default = "default_log_prefix")]
+    pub log_prefix: String,
+    /// Prefix of the command topics subscribed to.
+    #[serde(default = "default_command_prefix")]
+    pub command_prefix: String,
+    /// Crop bed the commands are for.
+    pub crop_bed_id: CropBed,
+    /// Address the telemetry of the components is received on over UDP,
+    /// none is published when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub telemetry_listen: Option<String>,
+    /// Address the heartbeats of the components are received on over UDP,
+    /// none are published when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heartbeat_listen: Option<String>,
+    /// Address the log events of the components are collected on over
+    /// TCP, none are published when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_listen: Option<String>,
+    /// Address of the lighting component, light commands are refused when
+    /// not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lighting_address: Option<String>,
+    /// Address of the power component, dry run and manual spray commands
+    /// are refused when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power_address: Option<String>,
+}
+
+/// Default telemetry prefix for serde.
+fn default_telemetry_prefix() -> String {
+    String::from(DEFAULT_TELEMETRY_PREFIX)
+}
+
+/// Default heartbeat prefix for serde.
+fn default_heartbeat_prefix() -> String {
+    String::from(DEFAULT_HEARTBEAT_PREFIX)
+}
+
+/// Default log prefix for serde.
+fn default_log_prefix() -> String {
+    String::from(DEFAULT_LOG_PREFIX)
+}
+
+/// Default command prefix for serde.
+fn default_command_prefix() -> String {
+    String::from(DEFAULT_COMMAND_PREFIX)
+}
+
+impl MqttBridgeConfig {
+    /// Bridge to a broker on the default topics, carrying nothing until
+    /// the listeners and components are set.
+    ///
+    /// * `broker_url`: url of the broker.
+    /// * `client_id`: client id the bridge connects with.
+    /// * `crop_bed_id`: crop bed the commands are for.
+    pub fn new(broker_url: impl Into<String>, client_id: impl Into<String>, crop_bed_id: CropBed) -> Self {
+        Self {
+            broker_url: broker_url.into(),
+            client_id: client_id.into(),
+            username: None,
+            password: None,
+            telemetry_prefix: default_telemetry_prefix(),
+            heartbeat_prefix: default_heartbeat_prefix(),
+            log_prefix: default_log_prefix(),
+            command_prefix: default_command_prefix(),
+            crop_bed_id,
+            telemetry_listen: None,
+            heartbeat_listen: None,
+            log_listen: None,
+            lighting_address: None,
+            power_address: None,
+        }
+    }
+
+    /// Set the user the bridge connects as.
+    ///
+    /// * `username`: user on the broker.
+    /// * `password`: password of the user.
+    pub fn with_credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.username = Some(username.into());
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Set the prefix of every topic, e.g. `farm/weeder` for
+    /// `farm/weeder/telemetry`.
+    ///
+    /// * `prefix`: prefix the kinds of topic are put under.
+    pub fn with_topic_prefix(mut self, prefix: &str) -> Self {
+        self.telemetry_prefix = format!("{prefix}/telemetry");
+        self.heartbeat_prefix = format!("{prefix}/heartbeat");
+        self.log_prefix = format!("{prefix}/log");
+        self.command_prefix = format!("{prefix}/command");
+        self
+    }
+
+    /// Set the address the telemetry is received on.
+    ///
+    /// * `address`: UDP address the components ship telemetry to.
+    pub fn with_telemetry_listen(mut self, address: impl Into<String>) -> Self {
+        self.telemetry_listen = Some(address.into());
+        self
+    }
+
+    /// Set the address the heartbeats are received on.
+    ///
+    /// * `address`: UDP address the components send heartbeats to.
+    pub fn with_heartbeat_listen(mut self, address: impl Into<String>) -> Self {
+        self.heartbeat_listen = Some(address.into());
+        self
+    }
+
+    /// Set the address the log events are collected on.
+    ///
+    /// * `address`: TCP address the components forward log events to.
+    pub fn with_log_listen(mut self, address: impl Into<String>) -> Self {
+        self.log_listen = Some(address.into());
+        self
+    }
+
+    /// Set the address of the lighting component.
+    ///
+    /// * `address`: TCP address the component listens on.
+    pub fn with_lighting_address(mut self, address: impl Into<String>) -> Self {
+        self.lighting_address = Some(address.into());
+        self
+    }
+
+    /// Set the address of the power component.
+    ///
+    /// * `address`: TCP address the component listens on.
+    pub fn with_power_address(mut self, address: impl Into<String>) -> Self {
+        self.power_address = Some(address.into());
+        self
+    }
+
+    /// Build the config by reading a file, this is a helper function.
+    ///
+    /// * `filepath`: path to config.
+    pub fn from_file<F: AsRef<OsStr>>(filepath: F) -> Result<Self, ConfigError> {
+        load_yaml(Path::new(&filepath))
+    }
+
+    /// Host and port of the broker, panics if the port is not a number.
+    pub fn broker(&self) -> (String, u16) {
+        let address = self
+            .broker_url
+            .strip_prefix("mqtt://")
+            .or_else(|| self.broker_url.strip_prefix("tcp://"))
+            .unwrap_or(&self.broker_url)
+            .trim_end_matches('/');
+        match address.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port
+                    .parse()
+                    .unwrap_or_else(|e| panic!("Invalid port in broker url {}: {e}", self.broker_url));
+                (String::from(host), port)
+            }
+            None => (String::from(address), DEFAULT_MQTT_PORT),
+        }
+    }
+
+    /// Topic a telemetry snapshot is published on, by kind and component.
+    ///
+    /// * `telemetry`: snapshot published.
+    pub fn telemetry_topic(&self, telemetry: &Telemetry) -> String {
+        format!(
+            "{}/{}/{}",
+            self.telemetry_prefix,
+            telemetry.kind(),
+            telemetry.header().component
+        )
+    }
+
+    /// Topic a heartbeat is published on, by kind and component.
+    ///
+    /// * `heartbeat`: heartbeat published.
+    pub fn heartbeat_topic(&self, heartbeat: &Heartbeat) -> String {
+        format!(
+            "{}/{}/{}",
+            self.heartbeat_prefix,
+            topic_segment(&heartbeat.heartbeat),
+            heartbeat.component
+        )
+    }
+
+    /// Topic a log event is published on, by level.
+    ///
+    /// * `event`: event published.
+    pub fn log_topic(&self, event: &LogEvent) -> String {
+        format!("{}/{}", self.log_prefix, topic_segment(&event.level))
+    }
+
+    /// Topic filter of every command.
+    pub fn command_filter(&self) -> String {
+        format!("{}/+", self.command_prefix)
+    }
+
+    /// Topic the responses to the commands are published on.
+    pub fn response_topic(&self) -> String {
+        format!("{}/{RESPONSE_TOPIC}", self.command_prefix)
+    }
+
+    /// Address of the component a command is forwarded to, if set.
+    ///
+    /// * `kind`: kind of component.
+    fn component_address(&self, kind: ComponentKind) -> Option<&str> {
+        match kind {
+            ComponentKind::Lighting => self.lighting_address.as_deref(),
+            ComponentKind::Power => self.power_address.as_deref(),
+            ComponentKind::CameraArray => None,
+        }
+    }
+}
+
+/// Name a value is serialised to, for a segment of a topic.
+///
+/// * `value`: a unit variant serialised as a string.
+fn topic_segment(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(segment)) => segment,
+        _ => String::from("unknown"),
+    }
+}
+
+/// Message published to, or received from, the broker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Publication {
+    /// Topic of the message.
+    pub topic: String,
+    /// Body of the message, json for everything the bridge publishes.
+    pub payload: Vec<u8>,
+}
+
+impl Publication {
+    /// Message on a topic.
+    ///
+    /// * `topic`: topic of the message.
+    /// * `payload`: body of the message.
+    pub fn new(topic: impl Into<String>, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            topic: topic.into(),
+            payload: payload.into(),
+        }
+    }
+}
+
+/// Turn lights on or off, published on the light command topic.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LightCommand {
+    /// Channels of the lights.
+    pub channels: Vec<u8>,
+    /// Whether the channels are turned on.
+    pub on: bool,
+    /// Duty cycle in percent the lights are dimmed to, fully on when not
+    /// set.
+    #[serde(default = "full_intensity", deserialize_with = "deserialize_intensity")]
+    pub level: u8,
+    /// Camera the lights are for, 0 when not set.
+    #[serde(default)]
+    pub cam_id: u8,
+}
+
+/// Start or end a dry run, published on the dry run command topic.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DryRunCommand {
+    /// Whether the dry run is started.
+    pub enabled: bool,
+}
+
+/// Fire a manual spray, published on the manual spray command topic.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManualSprayCommand {
+    /// Channels of the crop bed to open, numbered from 1.
+    pub channels: Vec<u8>,
+    /// Time in milliseconds the channels are kept open.
+    pub duration_ms: u64,
+    /// Duty cycle in percent, fully on when not set.
+    #[serde(default = "full_intensity", deserialize_with = "deserialize_intensity")]
+    pub pwm: u8,
+    /// Identifier echoed in the response.
+    #[serde(default)]
+    pub message_id: Option<String>,
+}
+
+/// Command received from the broker, for one of the components.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BridgeCommand {
+    /// Lights turned on or off by the lighting component.
+    Light(LightCommand),
+    /// Dry run started or ended by the power component.
+    DryRun(DryRunCommand),
+    /// Manual spray fired by the power component.
+    ManualSpray(ManualSprayCommand),
+}
+
+/// Why a command from the broker was not forwarded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    /// The topic is not one of the commands.
+    Unknown(String),
+    /// The payload is not the command of its topic.
+    Malformed {
+        /// Command of the topic.
+        command: &'static str,
+        /// Why the payload could not be read.
+        reason: String,
+    },
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown(topic) => write!(f, "{topic} is not a command"),
+            Self::Malformed { command, reason } => write!(f, "malformed {command} command, {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// Read the payload of a command.
+///
+/// * `command`: command of the topic.
+/// * `payload`: body of the message.
+fn read_command<T: serde::de::DeserializeOwned>(command: &'static str, payload: &[u8]) -> Result<T, CommandError> {
+    serde_json::from_slice(payload).map_err(|e| CommandError::Malformed {
+        command,
+        reason: e.to_string(),
+    })
+}
+
+impl BridgeCommand {
+    /// Read a command from a message under the command prefix.
+    ///
+    /// * `command_prefix`: prefix of the command topics.
+    /// * `publication`: message received.
+    pub fn parse(command_prefix: &str, publication: &Publication) -> Result<Self, CommandError> {
+        let command = publication
+            .topic
+            .strip_prefix(command_prefix)
+            .and_then(|command| command.strip_prefix('/'));
+        match command {
+            Some(LIGHT_COMMAND) => read_command(LIGHT_COMMAND, &publication.payload).map(Self::Light),
+            Some(DRY_RUN_COMMAND) => read_command(DRY_RUN_COMMAND, &publication.payload).map(Self::DryRun),
+            Some(MANUAL_SPRAY_COMMAND) => {
+                read_command(MANUAL_SPRAY_COMMAND, &publication.payload).map(Self::ManualSpray)
+            }
+            _ => Err(CommandError::Unknown(publication.topic.clone())),
+        }
+    }
+
+    /// Kind of component the command is forwarded to.
+    pub fn target(&self) -> ComponentKind {
+        match self {
+            Self::Light(_) => ComponentKind::Lighting,
+            Self::DryRun(_) | Self::ManualSpray(_) => ComponentKind::Power,
+        }
+    }
+
+    /// Id of the command echoed in the response, if it carries one.
+    pub fn correlation_id(&self) -> Option<String> {
+        match self {
+            Self::ManualSpray(spray) => spray.message_id.clone(),
+            Self::Light(_) | Self::DryRun(_) => None,
+        }
+    }
+
+    /// The command as the line of json its component listens for.
+    ///
+    /// * `crop_bed_id`: crop bed the command is for.
+    pub fn to_line(&self, crop_bed_id: CropBed) -> Vec<u8> {
+        let line = match self {
+            Self::Light(light) => {
+                let message = LightMessage::new(crop_bed_id, light.cam_id).channels(light.channels.clone());
+                let message = if light.on { message.on() } else { message.off() };
+                serde_json::to_vec(&message.level(light.level))
+            }
+            Self::DryRun(dry_run) => serde_json::to_vec(&PdmControlMessage::DryRun {
+                enabled: dry_run.enabled,
+            }),
+            Self::ManualSpray(spray) => serde_json::to_vec(&ManualSprayMessage {
+                manual: true,
+                channels: spray.channels.clone(),
+                duration_ms: spray.duration_ms,
+                pwm: spray.pwm,
+                message_id: spray.message_id.clone(),
+            }),
+        };
+        FramedCodec::new(Framing::Lines).frame(&line.expect("Failed to serialise command"))
+    }
+}
+
+/// Send a line to a component and read its response.
+///
+/// * `address`: TCP address of the component.
+/// * `line`: message sent, ending in a new line.
+async fn forward(address: &str, line: &[u8]) -> io::Result<ControlResponse> {
+    let mut stream = TcpStream::connect(address).await?;
+    stream.write_all(line).await?;
+    let mut reader = BufReader::new(stream);
+    let mut frame = Vec::new();
+    let timeout = Duration::from_millis(COMPONENT_RESPONSE_TIMEOUT_MS);
+    let read = tokio::time::timeout(
+        timeout,
+        FramedCodec::new(Framing::Lines).read_frame(&mut reader, &mut frame),
+    )
+    .await
+    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no response in time"))??;
+    if read != FrameRead::Frame {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed without a response",
+        ));
+    }
+    parse_response(&frame).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Forward a command from the broker to its component, answering with the
+/// response of the component or why it was not forwarded.
+///
+/// * `config`: topics and components of the bridge.
+/// * `publication`: command received.
+pub async fn handle_command(config: &MqttBridgeConfig, publication: &Publication) -> ControlResponse {
+    let command = match BridgeCommand::parse(&config.command_prefix, publication) {
+        Ok(command) => command,
+        Err(e) => {
+            warn!("Refused a command from the broker, {e}");
+            return ControlResponse::rejected(None, e.to_string());
+        }
+    };
+    let target = command.target();
+    let Some(address) = config.component_address(target) else {
+        return ControlResponse::rejected(command.correlation_id(), format!("no {target} component is bridged"));
+    };
+    match forward(address, &command.to_line(config.crop_bed_id)).await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("Failed to forward {command:?} to the {target} component at {address}: {e}");
+            ControlResponse::rejected(
+                command.correlation_id(),
+                format!("{target} component did not respond, {e}"),
+            )
+        }
+    }
+}
+
+/// Publish each datagram received that reads as a message, on the topic
+/// given for it, until the broker side has gone.
+///
+/// * `socket`: socket the datagrams are received on.
+/// * `publications`: messages for the broker.
+/// * `topic`: topic of a datagram, `None` when it is not a message.
+async fn publish_datagrams<F>(socket: UdpSocket, publications: mpsc::Sender<Publication>, topic: F)
+where
+    F: Fn(&[u8]) -> Option<String>,
+{
+    let mut data = vec![0; 65536];
+    loop {
+        let length = match socket.recv(&mut data).await {
+            Ok(length) => length,
+            Err(e) => {
+                warn!("Failed to receive on {:?}: {e}", socket.local_addr());
+                continue;
+            }
+        };
+        let Some(topic) = topic(&data[..length]) else {
+            warn!("Dropping a datagram that is not telemetry or a heartbeat");
+            continue;
+        };
+        if publications
+            .send(Publication::new(topic, &data[..length]))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// Publish the log events of every component connecting, a line at a
+/// time, until the broker side has gone.
+///
+/// * `listener`: listener the components connect to.
+/// * `config`: topics of the bridge.
+/// * `publications`: messages for the broker.
+async fn publish_log_events(listener: TcpListener, config: MqttBridgeConfig, publications: mpsc::Sender<Publication>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!("Failed to accept a log connection: {e}");
+                continue;
+            }
+        };
+        let (config, publications) = (config.clone(), publications.clone());
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stream).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let Ok(event) = serde_json::from_str::<LogEvent>(&line) else {
+                    warn!("Dropping a line that is not a log event");
+                    continue;
+                };
+                if publications
+                    .send(Publication::new(config.log_topic(&event), line))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+        if publications.is_closed() {
+            break;
+        }
+    }
+}
+
+/// Carry messages between the components and a broker until a stop is
+/// requested or the broker side has gone. The telemetry, heartbeats and
+/// log events received are published as they arrive, and each command is
+/// forwarded to its component with the response published after. The
+/// client of the broker is left to the caller, so the bridge can be run
+/// without one.
+///
+/// * `config`: topics and components of the bridge, panics if a listen
+///   address cannot be bound.
+/// * `commands`: messages received on the command topics.
+/// * `publications`: messages for the broker.
+/// * `stop_rx`: set to true to stop the bridge.
+pub async fn run_bridge(
+    config: MqttBridgeConfig,
+    mut commands: mpsc::Receiver<Publication>,
+    publications: mpsc::Sender<Publication>,
+    mut stop_rx: watch::Receiver<bool>,
+) {
+    let mut listeners: Vec<JoinHandle<()>> = Vec::new();
+    if let Some(address) = &config.telemetry_listen {
+        let socket = UdpSocket::bind(address)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to bind the telemetry listener on {address}: {e}"));
+        let topics = config.clone();
+        listeners.push(tokio::spawn(publish_datagrams(
+            socket,
+            publications.clone(),
+            move |data| {
+                let telemetry = serde_json::from_slice::<Telemetry>(data).ok()?;
+                Some(topics.telemetry_topic(&telemetry))
+            },
+        )));
+    }
+    if let Some(address) = &config.heartbeat_listen {
+        let socket = UdpSocket::bind(address)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to bind the heartbeat listener on {address}: {e}"));
+        let topics = config.clone();
+        listeners.push(tokio::spawn(publish_datagrams(
+            socket,
+            publications.clone(),
+            move |data| {
+                let heartbeat = serde_json::from_slice::<Heartbeat>(data).ok()?;
+                Some(topics.heartbeat_topic(&heartbeat))
+            },
+        )));
+    }
+    if let Some(address) = &config.log_listen {
+        let listener = TcpListener::bind(address)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to bind the log listener on {address}: {e}"));
+        listeners.push(tokio::spawn(publish_log_events(
+            listener,
+            config.clone(),
+            publications.clone(),
+        )));
+    }
+
+    while !*stop_rx.borrow() {
+        tokio::select! {
+            changed = stop_rx.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+            }
+            command = commands.recv() => {
+                let Some(command) = command else {
+                    break;
+                };
+                let response = handle_command(&config, &command).await;
+                let payload = serde_json::to_vec(&response).expect("Failed to serialise response");
+                if publications.send(Publication::new(config.response_topic(), payload)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    for listener in listeners {
+        listener.abort();
+    }
+}
+
+/// Connect to the broker of a config and bridge it to the components until
+/// a stop is requested. The connection is retried every second while the
+/// broker cannot be reached, subscribing to the commands again each time
+/// it is made.
+///
+/// * `config`: broker, topics and components of the bridge.
+/// * `stop_rx`: set to true to stop the bridge.
+pub async fn run(config: MqttBridgeConfig, mut stop_rx: watch::Receiver<bool>) {
+    let (host, port) = config.broker();
+    let mut options = MqttOptions::new(config.client_id.clone(), host, port);
+    options.set_keep_alive(Duration::from_secs(5));
+    if let Some(username) = &config.username {
+        options.set_credentials(username.clone(), config.password.clone().unwrap_or_default());
+    }
+    let (client, mut eventloop) = AsyncClient::new(options, PUBLICATION_CAPACITY);
+
+    let (command_tx, command_rx) = mpsc::channel(PUBLICATION_CAPACITY);
+    let (publication_tx, mut publication_rx) = mpsc::channel::<Publication>(PUBLICATION_CAPACITY);
+    let bridge = tokio::spawn(run_bridge(config.clone(), command_rx, publication_tx, stop_rx.clone()));
+    let publisher = tokio::spawn({
+        let client = client.clone();
+        async move {
+            while let Some(publication) = publication_rx.recv().await {
+                if let Err(e) = client
+                    .publish(publication.topic, QoS::AtLeastOnce, false, publication.payload)
+                    .await
+                {
+                    warn!("Failed to publish to the broker: {e}");
+                }
+            }
+        }
+    });
+
+    let mut connected = false;
+    while !*stop_rx.borrow() {
+        tokio::select! {
+            changed = stop_rx.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+            }
+            event = eventloop.poll() => match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("Connected to the broker at {}", config.broker_url);
+                    connected = true;
+                    // Queued for the event loop, which is polled here, so it
+                    // must not wait on it.
+                    if let Err(e) = client.try_subscribe(config.command_filter(), QoS::AtLeastOnce) {
+                        warn!("Failed to subscribe to {}: {e}", config.command_filter());
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    if command_tx.send(Publication::new(publish.topic, publish.payload.to_vec())).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    if connected {
+                        warn!("Lost the broker at {}, retrying: {e}", config.broker_url);
+                        connected = false;
+                    }
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+    drop(command_tx);
+    if let Err(e) = client.try_disconnect() {
+        warn!("Failed to disconnect from the broker: {e}");
+    }
+    bridge.await.expect("MQTT bridge panicked");
+    publisher.abort();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{
+        control::response::ResponseStatus,
+        logging::{EventCode, LogLevel},
+        telemetry::{SprayTelemetry, TelemetryHeader},
+    };
+    use crate::utils::logging::{LogConfig, LogEmitter};
+    use rstest::rstest;
+    use serial_test::serial;
+    use uuid::Uuid;
+
+    /// Component listening on a port that records each line it receives
+    /// and answers it as accepted.
+    async fn fake_component() -> (String, mpsc::Receiver<serde_json::Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (tx, rx) = mpsc::channel(8);
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let (read, mut write) = stream.into_split();
+                let mut lines = BufReader::new(read).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    tx.send(serde_json::from_str(&line).unwrap()).await.unwrap();
+                    let response = ControlResponse::accepted(None);
+                    let mut answer = serde_json::to_vec(&response).unwrap();
+                    answer.push(b'\n');
+                    write.write_all(&answer).await.unwrap();
+                }
+            }
+        });
+        (address, rx)
+    }
+
+    /// Next message the bridge publishes.
+    ///
+    /// * `publications`: messages for the broker.
+    async fn published(publications: &mut mpsc::Receiver<Publication>) -> Publication {
+        tokio::time::timeout(Duration::from_secs(1), publications.recv())
+            .await
+            .expect("Nothing published")
+            .expect("Bridge has gone")
+    }
+
+    #[rstest]
+    #[case("mqtt://192.168.1.10:1884", ("192.168.1.10", 1884))]
+    #[case("mqtt://broker.local", ("broker.local", DEFAULT_MQTT_PORT))]
+    #[case("localhost:1883/", ("localhost", 1883))]
+    fn test_broker_address(#[case] broker_url: &str, #[case] expected: (&str, u16)) {
+        let (host, port) = MqttBridgeConfig::new(broker_url, "onyx", CropBed::Centre).broker();
+        assert_eq!((host.as_str(), port), expected);
+    }
+
+    #[test]
+    /// The bridge config survives a round trip through a YAML file, with
+    /// the topics defaulted when the file leaves them out, and a missing
+    /// file is an error rather than a panic.
+    fn test_config_from_file() {
+        let path = std::env::temp_dir().join(format!("onyx-mqtt-{}.yaml", Uuid::new_v4()));
+        let config = MqttBridgeConfig::new("mqtt://broker.local", "weeder-1", CropBed::LeftBoom)
+            .with_credentials("weeder", "secret")
+            .with_topic_prefix("farm/weeder")
+            .with_telemetry_listen("127.0.0.1:17653")
+            .with_power_address("127.0.0.1:17650");
+        std::fs::write(&path, serde_yaml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(MqttBridgeConfig::from_file(&path).unwrap(), config);
+        assert_eq!(config.command_filter(), "farm/weeder/command/+");
+
+        std::fs::write(
+            &path,
+            "broker_url: mqtt://broker.local\nclient_id: weeder-2\ncrop_bed_id: centre\n",
+        )
+        .unwrap();
+        let config = MqttBridgeConfig::from_file(&path).unwrap();
+        assert_eq!(config.telemetry_prefix, DEFAULT_TELEMETRY_PREFIX);
+        assert_eq!((config.username, config.lighting_address), (None, None));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            MqttBridgeConfig::from_file(&path),
+            Err(ConfigError::Read { .. })
+        ));
+    }
+
+    #[rstest]
+    #[case(
+        "onyx/command/light",
+        r#"{"channels": [2, 3], "on": true, "level": 40}"#,
+        serde_json::json!({
+            "channels": [2, 3], "is_on": true, "level": 40, "strobe": false, "cam_id": 0, "crop_bed_id": "right_boom"
+        })
+    )]
+    #[case(
+        "onyx/command/dry_run",
+        r#"{"enabled": true}"#,
+        serde_json::json!({"dry_run": {"enabled": true}})
+    )]
+    #[case(
+        "onyx/command/manual_spray",
+        r#"{"channels": [7], "duration_ms": 500, "message_id": "ops-1"}"#,
+        serde_json::json!({"manual": true, "channels": [7], "duration_ms": 500, "pwm": 100, "message_id": "ops-1"})
+    )]
+    /// Commands from the broker become the control messages their
+    /// components already read.
+    fn test_convert_command(#[case] topic: &str, #[case] payload: &str, #[case] expected: serde_json::Value) {
+        let command = BridgeCommand::parse(DEFAULT_COMMAND_PREFIX, &Publication::new(topic, payload)).unwrap();
+        let line = command.to_line(CropBed::RightBoom);
+        assert_eq!(line.last(), Some(&b'\n'));
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&line).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("onyx/command/reboot", "{}")]
+    #[case("onyx/telemetry/light", r#"{"channels": [2], "on": true}"#)]
+    #[case("onyx/command/light", r#"{"channels": [2], "on": true, "level": 140}"#)]
+    #[case("onyx/command/dry_run", "on")]
+    /// Unknown topics and payloads that are not their command are refused.
+    fn test_refuse_command(#[case] topic: &str, #[case] payload: &str) {
+        assert!(BridgeCommand::parse(DEFAULT_COMMAND_PREFIX, &Publication::new(topic, payload)).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    /// Against a mocked client, telemetry, heartbeats and log events from
+    /// the components are published on their topics, and commands are
+    /// forwarded to their components with the responses published back.
+    async fn test_bridge_messages() {
+        let (lighting_address, mut lighting) = fake_component().await;
+        let (power_address, mut power) = fake_component().await;
+        let config = MqttBridgeConfig::new("mqtt://localhost", "onyx-test", CropBed::Centre)
+            .with_telemetry_listen("127.0.0.1:17682")
+            .with_heartbeat_listen("127.0.0.1:17683")
+            .with_log_listen("127.0.0.1:17684")
+            .with_lighting_address(lighting_address)
+            .with_power_address(power_address);
+        let (command_tx, command_rx) = mpsc::channel(8);
+        let (publication_tx, mut publications) = mpsc::channel(8);
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let bridge = tokio::spawn(run_bridge(config.clone(), command_rx, publication_tx, stop_rx));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let component = Uuid::new_v4();
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let telemetry = Telemetry::Spray(SprayTelemetry {
+            header: TelemetryHeader::new(component, CropBed::Centre),
+            queue_depth: 2,
+            accepted: 5,
+            late: 0,
+            late_discarded: 0,
+            last_fired_at: None,
+            last_fired_position: None,
+        });
+        let data = serde_json::to_vec(&telemetry).unwrap();
+        socket.send_to(b"not telemetry", "127.0.0.1:17682").await.unwrap();
+        socket.send_to(&data, "127.0.0.1:17682").await.unwrap();
+        let publication = published(&mut publications).await;
+        assert_eq!(publication.topic, format!("onyx/telemetry/spray/{component}"));
+        assert_eq!(
+            serde_json::from_slice::<Telemetry>(&publication.payload).unwrap(),
+            telemetry
+        );
+
+        let heartbeat = Heartbeat::new(ComponentKind::CameraArray, component, CropBed::Centre, 3.0);
+        let data = serde_json::to_vec(&heartbeat).unwrap();
+        socket.send_to(&data, "127.0.0.1:17683").await.unwrap();
+        let publication = published(&mut publications).await;
+        assert_eq!(publication.topic, format!("onyx/heartbeat/camera_array/{component}"));
+
+        let mut log = LogEmitter::new(ComponentKind::Power, component, CropBed::Centre);
+        let writer = log.start(&LogConfig::tcp("127.0.0.1:17684"));
+        log.warn(EventCode::PdmMismatch, "PDM 31 did not read back");
+        let publication = published(&mut publications).await;
+        assert_eq!(publication.topic, "onyx/log/warn");
+        let event: LogEvent = serde_json::from_slice(&publication.payload).unwrap();
+        assert_eq!((event.level, event.code), (LogLevel::Warn, EventCode::PdmMismatch));
+        drop(log);
+        tokio::task::spawn_blocking(move || writer.join().unwrap())
+            .await
+            .unwrap();
+
+        let light = Publication::new("onyx/command/light", r#"{"channels": [1], "on": false}"#);
+        command_tx.send(light).await.unwrap();
+        let forwarded = lighting.recv().await.unwrap();
+        assert_eq!(forwarded["channels"], serde_json::json!([1]));
+        assert_eq!(forwarded["is_on"], false);
+        let publication = published(&mut publications).await;
+        assert_eq!(publication.topic, "onyx/command/response");
+        assert!(parse_response(&publication.payload).unwrap().is_accepted());
+
+        command_tx
+            .send(Publication::new("onyx/command/dry_run", r#"{"enabled": true}"#))
+            .await
+            .unwrap();
+        assert_eq!(
+            power.recv().await.unwrap(),
+            serde_json::json!({"dry_run": {"enabled": true}})
+        );
+        assert!(parse_response(&published(&mut publications).await.payload)
+            .unwrap()
+            .is_accepted());
+
+        command_tx
+            .send(Publication::new("onyx/command/reboot", "{}"))
+            .await
+            .unwrap();
+        let response = parse_response(&published(&mut publications).await.payload).unwrap();
+        assert!(matches!(response.status, ResponseStatus::Rejected { .. }));
+
+        stop_tx.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), bridge)
+            .await
+            .expect("Bridge did not stop")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    /// Commands for a component that is not bridged, or cannot be reached,
+    /// are answered as rejected with the id of the command.
+    async fn test_reject_unreachable_component() {
+        let config =
+            MqttBridgeConfig::new("mqtt://localhost", "onyx-test", CropBed::Centre).with_power_address("127.0.0.1:1");
+        let light = Publication::new("onyx/command/light", r#"{"channels": [1], "on": true}"#);
+        let response = handle_command(&config, &light).await;
+        assert_eq!(
+            response,
+            ControlResponse::rejected(None, "no lighting component is bridged")
+        );
+
+        let spray = r#"{"channels": [7], "duration_ms": 500, "message_id": "ops-2"}"#;
+        let response = handle_command(&config, &Publication::new("onyx/command/manual_spray", spray)).await;
+        assert_eq!(response.correlation_id.as_deref(), Some("ops-2"));
+        assert!(matches!(response.status, ResponseStatus::Rejected { .. }));
+    }
+}
@@ -0,0 +1,281 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Size in bytes of the length written ahead of every length prefixed
+/// frame, a big endian `u32`.
+pub const FRAME_PREFIX_BYTES: usize = 4;
+
+/// Size in bytes of the largest frame read, when not set on the codec.
+/// Weed messages are a few hundred bytes.
+pub const DEFAULT_MAX_FRAME_BYTES: usize = 1 << 20;
+
+/// How frames are told apart on a connection.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Framing {
+    /// One frame per line, as the AI system has always sent. A frame
+    /// cannot hold a new line, so pretty printed json is split apart.
+    Lines,
+    /// Each frame follows its length as a big endian `u32`, so it may hold
+    /// anything.
+    LengthPrefixed,
+    /// Length prefixed, unless the first byte of the connection shows the
+    /// peer is sending lines. The length of any frame under 16 MiB starts
+    /// with a zero byte, which no line of json does.
+    Detect,
+}
+
+/// What reading the next frame from a connection found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameRead {
+    /// A whole frame was read.
+    Frame,
+    /// The peer closed the connection between frames.
+    Closed,
+    /// The peer closed the connection part way through a frame, what was
+    /// read of it is left in the buffer.
+    CutShort,
+}
+
+/// Reads and writes the frames of one connection. With [`Framing::Detect`]
+/// the framing is settled by the first frame read, so use a codec for each
+/// connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramedCodec {
+    /// Framing of the connection, settled once detected.
+    framing: Framing,
+    /// Size in bytes of the largest frame read.
+    max_frame_bytes: usize,
+}
+
+impl FramedCodec {
+    /// Codec for a framing, reading frames up to
+    /// [`DEFAULT_MAX_FRAME_BYTES`].
+    ///
+    /// * `framing`: how frames are told apart.
+    pub fn new(framing: Framing) -> Self {
+        Self {
+            framing,
+            max_frame_bytes: DEFAULT_MAX_FRAME_BYTES,
+        }
+    }
+
+    /// Set the largest frame read, a longer frame is an error.
+    ///
+    /// * `max_frame_bytes`: size in bytes.
+    pub fn with_max_frame_bytes(mut self, max_frame_bytes: usize) -> Self {
+        self.max_frame_bytes = max_frame_bytes;
+        self
+    }
+
+    /// Framing of the connection, [`Framing::Detect`] until the first frame
+    /// has been read.
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    /// Read the next frame into a buffer, appended to what it holds. Lines
+    /// keep their new line, which counts towards the maximum, length
+    /// prefixed frames are left without their length. A frame longer than
+    /// the maximum is an error, as the frames after it cannot be found.
+    ///
+    /// * `reader`: read half of the connection.
+    /// * `frame`: buffer the frame is read into.
+    pub async fn read_frame<R: AsyncBufRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+        frame: &mut Vec<u8>,
+    ) -> io::Result<FrameRead> {
+        if self.framing == Framing::Detect {
+            let Some(&first) = reader.fill_buf().await?.first() else {
+                return Ok(FrameRead::Closed);
+            };
+            self.framing = if first == 0 {
+                Framing::LengthPrefixed
+            } else {
+                Framing::Lines
+            };
+        }
+        let max_frame_bytes = u64::try_from(self.max_frame_bytes).unwrap_or(u64::MAX);
+        if self.framing == Framing::Lines {
+            let read = (&mut *reader)
+                .take(max_frame_bytes.saturating_add(1))
+                .read_until(b'\n', frame)
+                .await?;
+            return match read {
+                0 => Ok(FrameRead::Closed),
+                _ if read > self.max_frame_bytes => Err(self.too_long(read)),
+                _ if frame.last() == Some(&b'\n') => Ok(FrameRead::Frame),
+                _ => Ok(FrameRead::CutShort),
+            };
+        }
+
+        let mut prefix = [0; FRAME_PREFIX_BYTES];
+        let mut filled = 0;
+        while filled < FRAME_PREFIX_BYTES {
+            match reader.read(&mut prefix[filled..]).await? {
+                0 if filled == 0 => return Ok(FrameRead::Closed),
+                0 => return Ok(FrameRead::CutShort),
+                read => filled += read,
+            }
+        }
+        let length = u32::from_be_bytes(prefix);
+        if u64::from(length) > max_frame_bytes {
+            return Err(self.too_long(usize::try_from(length).unwrap_or(usize::MAX)));
+        }
+        let read = (&mut *reader).take(u64::from(length)).read_to_end(frame).await?;
+        Ok(if read < usize::try_from(length).unwrap_or(usize::MAX) {
+            FrameRead::CutShort
+        } else {
+            FrameRead::Frame
+        })
+    }
+
+    /// A frame with its new line or length, ready to write. Frames are
+    /// length prefixed while the framing is still to be detected.
+    ///
+    /// * `payload`: contents of the frame, without a new line for lines.
+    pub fn frame(&self, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(payload.len() + FRAME_PREFIX_BYTES);
+        if self.framing == Framing::Lines {
+            frame.extend_from_slice(payload);
+            frame.push(b'\n');
+        } else {
+            let length = u32::try_from(payload.len()).expect("Frame is too large to send");
+            frame.extend_from_slice(&length.to_be_bytes());
+            frame.extend_from_slice(payload);
+        }
+        frame
+    }
+
+    /// Write one frame in a single write, so frames from tasks sharing the
+    /// connection are not interleaved.
+    ///
+    /// * `writer`: write half of the connection.
+    /// * `payload`: contents of the frame, without a new line for lines.
+    pub async fn write_frame<W: AsyncWrite + Unpin>(&self, writer: &mut W, payload: &[u8]) -> io::Result<()> {
+        writer.write_all(&self.frame(payload)).await
+    }
+
+    /// Error for a frame past the maximum.
+    ///
+    /// * `length`: size in bytes of the frame.
+    fn too_long(&self, length: usize) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {length} bytes is larger than {} bytes", self.max_frame_bytes),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::time::Duration;
+    use tokio::io::BufReader;
+
+    #[rstest]
+    #[case::lines(Framing::Lines)]
+    #[case::length_prefixed(Framing::LengthPrefixed)]
+    #[tokio::test]
+    /// Frames written a few bytes at a time are read whole, and one cut
+    /// short by the peer closing is told apart from closing between frames.
+    async fn test_partial_reads(#[case] framing: Framing) {
+        let mut codec = FramedCodec::new(framing);
+        let mut sent = codec.frame(br#"{"channels": [3], "is_on": true}"#);
+        sent.extend(codec.frame(b"{}"));
+        let cut_short = codec.frame(br#"{"channels": [4]}"#);
+        sent.extend(&cut_short[..cut_short.len() - 2]);
+
+        let (mut client, server) = tokio::io::duplex(8);
+        tokio::spawn(async move {
+            for chunk in sent.chunks(3) {
+                client.write_all(chunk).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        });
+        let mut reader = BufReader::new(server);
+        let mut frame = Vec::new();
+        assert_eq!(
+            codec.read_frame(&mut reader, &mut frame).await.unwrap(),
+            FrameRead::Frame
+        );
+        assert!(frame.starts_with(br#"{"channels": [3], "is_on": true}"#));
+        frame.clear();
+        assert_eq!(
+            codec.read_frame(&mut reader, &mut frame).await.unwrap(),
+            FrameRead::Frame
+        );
+        assert!(frame.starts_with(b"{}"));
+        frame.clear();
+        assert_eq!(
+            codec.read_frame(&mut reader, &mut frame).await.unwrap(),
+            FrameRead::CutShort
+        );
+        frame.clear();
+        assert_eq!(
+            codec.read_frame(&mut reader, &mut frame).await.unwrap(),
+            FrameRead::Closed
+        );
+    }
+
+    #[rstest]
+    #[case::lines(Framing::Lines, b"0123456789abcdef\n".to_vec())]
+    #[case::length_prefixed(Framing::LengthPrefixed, vec![0, 0, 0, 17])]
+    #[tokio::test]
+    /// A frame past the maximum is an error rather than being read on.
+    async fn test_oversized_frame(#[case] framing: Framing, #[case] sent: Vec<u8>) {
+        let mut codec = FramedCodec::new(framing).with_max_frame_bytes(16);
+        let error = codec.read_frame(&mut &sent[..], &mut Vec::new()).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut frame = Vec::new();
+        let fits = FramedCodec::new(framing).frame(b"0123456789abcde");
+        assert_eq!(
+            codec.read_frame(&mut &fits[..], &mut frame).await.unwrap(),
+            FrameRead::Frame
+        );
+    }
+
+    #[tokio::test]
+    /// Detected connections settle on the framing of their first frame, so
+    /// legacy senders of lines are still served, pretty printed json is
+    /// read whole once length prefixed and responses follow the peer.
+    async fn test_detect_framing() {
+        let pretty = b"{\n  \"request\": \"status\"\n}";
+        let mut codec = FramedCodec::new(Framing::Detect);
+        let sent = FramedCodec::new(Framing::LengthPrefixed).frame(pretty);
+        let mut frame = Vec::new();
+        assert_eq!(
+            codec.read_frame(&mut &sent[..], &mut frame).await.unwrap(),
+            FrameRead::Frame
+        );
+        assert_eq!((codec.framing(), &frame[..]), (Framing::LengthPrefixed, &pretty[..]));
+        assert_eq!(codec.frame(b"{}"), vec![0, 0, 0, 2, b'{', b'}']);
+
+        let mut codec = FramedCodec::new(Framing::Detect);
+        let legacy = b"{\"request\": \"status\"}\n{}\n";
+        let mut reader = &legacy[..];
+        frame.clear();
+        assert_eq!(
+            codec.read_frame(&mut reader, &mut frame).await.unwrap(),
+            FrameRead::Frame
+        );
+        assert_eq!((codec.framing(), &frame[..]), (Framing::Lines, &legacy[..22]));
+        frame.clear();
+        assert_eq!(
+            codec.read_frame(&mut reader, &mut frame).await.unwrap(),
+            FrameRead::Frame
+        );
+        assert_eq!(codec.frame(b"{}"), b"{}\n");
+
+        let mut codec = FramedCodec::new(Framing::Detect);
+        assert_eq!(
+            codec.read_frame(&mut &b""[..], &mut frame).await.unwrap(),
+            FrameRead::Closed
+        );
+        assert_eq!(codec.framing(), Framing::Detect);
+    }
+}
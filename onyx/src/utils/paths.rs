@@ -0,0 +1,181 @@
+use std::{
+    ffi::OsString,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+/// Environment variable naming the directory repository relative paths are
+/// resolved against in place of the crate, e.g. a checkout where the config
+/// tree has been generated.
+pub const CONFIG_DIR_ENV: &str = "ONYX_CONFIG_DIR";
+
+/// Why a repository relative path could not be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    /// Nothing exists at the path.
+    Missing {
+        /// Path resolved.
+        path: PathBuf,
+        /// Whether it was resolved against [`CONFIG_DIR_ENV`] rather than
+        /// the crate.
+        from_env: bool,
+    },
+    /// The path exists but is not a directory.
+    NotADirectory {
+        /// Path resolved.
+        path: PathBuf,
+    },
+}
+
+impl PathError {
+    /// Path resolved.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Missing { path, .. } | Self::NotADirectory { path } => path,
+        }
+    }
+}
+
+impl Display for PathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing { path, from_env: true } => {
+                write!(f, "{} does not exist under {CONFIG_DIR_ENV}", path.display())
+            }
+            Self::Missing { path, from_env: false } => write!(
+                f,
+                "{} does not exist, generate the config tree or point {CONFIG_DIR_ENV} at one",
+                path.display()
+            ),
+            Self::NotADirectory { path } => write!(f, "{} is not a directory", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// Directory paths are resolved against, the override when it is set and
+/// not empty, otherwise the crate.
+///
+/// * `config_dir`: value of [`CONFIG_DIR_ENV`], if set.
+fn root_from(config_dir: Option<OsString>) -> (PathBuf, bool) {
+    match config_dir.filter(|dir| !dir.is_empty()) {
+        Some(dir) => (PathBuf::from(dir), true),
+        None => (PathBuf::from(env!("CARGO_MANIFEST_DIR")), false),
+    }
+}
+
+/// Resolve a path against a directory, checking something exists there.
+///
+/// * `root`: directory resolved against and whether it came from the
+///   environment.
+/// * `path`: path relative to the directory, a leading `/` is ignored.
+fn resolve((root, from_env): (PathBuf, bool), path: impl AsRef<Path>) -> Result<PathBuf, PathError> {
+    let path = path.as_ref();
+    let resolved = root.join(path.strip_prefix("/").unwrap_or(path));
+    if resolved.exists() {
+        Ok(resolved)
+    } else {
+        Err(PathError::Missing {
+            path: resolved,
+            from_env,
+        })
+    }
+}
+
+/// Path of a file or directory in the repository, e.g. a config used by a
+/// test, checked to exist when it is resolved rather than when the crate
+/// is built. Resolved against [`CONFIG_DIR_ENV`] when it is set, otherwise
+/// the crate.
+///
+/// * `path`: path relative to the crate, a leading `/` is ignored.
+pub fn repo_relative(path: impl AsRef<Path>) -> Result<PathBuf, PathError> {
+    resolve(root_from(std::env::var_os(CONFIG_DIR_ENV)), path)
+}
+
+/// Path of a directory in the repository, resolved as [`repo_relative`]
+/// and checked to be a directory.
+///
+/// * `path`: path relative to the crate, a leading `/` is ignored.
+pub fn require_dir(path: impl AsRef<Path>) -> Result<PathBuf, PathError> {
+    repo_relative(path).and_then(into_dir)
+}
+
+/// Check a resolved path is a directory.
+///
+/// * `resolved`: path that exists.
+fn into_dir(resolved: PathBuf) -> Result<PathBuf, PathError> {
+    if resolved.is_dir() {
+        Ok(resolved)
+    } else {
+        Err(PathError::NotADirectory { path: resolved })
+    }
+}
+
+/// Path of a resource for the deprecated test path macros, panicking when
+/// it does not exist.
+///
+/// * `path`: path relative to the crate.
+#[doc(hidden)]
+#[deprecated(note = "use `utils::paths::repo_relative`, which checks the path exists when the test runs")]
+pub fn legacy_path(path: &str) -> String {
+    repo_relative(path)
+        .unwrap_or_else(|e| panic!("{e}"))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    /// Paths resolve against the crate, with or without a leading `/`, and
+    /// a missing one names where it was looked for.
+    fn test_repo_relative() {
+        let root = root_from(None);
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
+        assert_eq!(root, (manifest.to_path_buf(), false));
+        assert_eq!(resolve(root.clone(), "/Cargo.toml"), Ok(manifest.join("Cargo.toml")));
+        assert_eq!(resolve(root.clone(), "src/utils.rs"), Ok(manifest.join("src/utils.rs")));
+        let missing = resolve(root, "config/missing.yaml").unwrap_err();
+        assert_eq!(missing.path(), manifest.join("config/missing.yaml"));
+        assert!(missing.to_string().contains(CONFIG_DIR_ENV), "{missing}");
+        assert_eq!(
+            resolve(root_from(None), "src").and_then(into_dir),
+            Ok(manifest.join("src"))
+        );
+        assert!(matches!(
+            resolve(root_from(None), "Cargo.toml").and_then(into_dir),
+            Err(PathError::NotADirectory { .. })
+        ));
+    }
+
+    #[test]
+    /// The override takes the place of the crate when it is set, and is
+    /// ignored when empty.
+    fn test_config_dir_override() {
+        let dir = std::env::temp_dir().join(format!("onyx-paths-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("config")).unwrap();
+        std::fs::write(dir.join("config/generated.yaml"), "").unwrap();
+
+        let root = root_from(Some(dir.clone().into_os_string()));
+        assert_eq!(root, (dir.clone(), true));
+        assert_eq!(
+            resolve(root.clone(), "/config/generated.yaml"),
+            Ok(dir.join("config/generated.yaml"))
+        );
+        let missing = resolve(root, "Cargo.toml").unwrap_err();
+        assert_eq!(
+            missing,
+            PathError::Missing {
+                path: dir.join("Cargo.toml"),
+                from_env: true
+            }
+        );
+        assert!(missing.to_string().contains("under"), "{missing}");
+        assert!(!root_from(Some(OsString::new())).1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+use crate::messages::{
+    control::response::ControlResponse,
+    encoding::{Encoding, MessageError},
+};
+use crate::utils::net::{FrameRead, FramedCodec, Framing};
+use std::time::Duration;
+use tokio::io::AsyncBufRead;
+
+/// Parse one line written back by a component.
+///
+/// * `line`: the line, with or without its trailing new line.
+pub fn parse_response(line: &[u8]) -> Result<ControlResponse, serde_json::Error> {
+    serde_json::from_slice(line)
+}
+
+/// Read the next response from a connection to a component, failing if
+/// none arrives within the timeout or it is not a response. Meant for tests
+/// and tools talking to a component as the AI system does.
+///
+/// * `reader`: read half of the connection.
+/// * `timeout`: time to wait for the response.
+pub async fn read_response<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    timeout: Duration,
+) -> Result<ControlResponse, MessageError> {
+    read_encoded_response(reader, &mut FramedCodec::new(Framing::Lines), Encoding::Json, timeout).await
+}
+
+/// Read the next response from a connection in a framing and encoding,
+/// failing as [`read_response`] does.
+///
+/// * `reader`: read half of the connection.
+/// * `codec`: framing of the connection.
+/// * `encoding`: encoding of the connection.
+/// * `timeout`: time to wait for the response.
+pub async fn read_encoded_response<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    codec: &mut FramedCodec,
+    encoding: Encoding,
+    timeout: Duration,
+) -> Result<ControlResponse, MessageError> {
+    let mut frame = Vec::new();
+    let read = tokio::time::timeout(timeout, codec.read_frame(reader, &mut frame))
+        .await
+        .map_err(|_| MessageError::Timeout)??;
+    if read == FrameRead::Closed {
+        return Err(MessageError::Closed);
+    }
+    encoding.decode(&frame).map_err(|source| MessageError::Invalid {
+        frame: String::from_utf8_lossy(&frame).into_owned(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::control::response::ResponseStatus;
+
+    #[tokio::test]
+    /// Responses are read a line or frame at a time.
+    async fn test_read_response() {
+        let lines = b"{\"status\":\"duplicate\",\"correlation_id\":\"cam0-1\",\"component\":\"00000000-0000-0000-0000-000000000000\"}\n{\"status\":\"late\",\"correlation_id\":null,\"component\":\"00000000-0000-0000-0000-000000000000\"}\n";
+        let mut reader = &lines[..];
+        let response = read_response(&mut reader, Duration::from_millis(100)).await.unwrap();
+        assert_eq!(response, ControlResponse::duplicate(Some(String::from("cam0-1"))));
+        let response = read_response(&mut reader, Duration::from_millis(100)).await.unwrap();
+        assert_eq!(response.status, ResponseStatus::Late);
+        assert!(parse_response(b"not json\n").is_err());
+
+        let mut codec = FramedCodec::new(Framing::LengthPrefixed);
+        let framed = codec.frame(&Encoding::MessagePack.encode(&ControlResponse::accepted(None)));
+        let timeout = Duration::from_millis(100);
+        let response = read_encoded_response(&mut &framed[..], &mut codec, Encoding::MessagePack, timeout).await;
+        assert!(response.unwrap().is_accepted());
+    }
+
+    #[tokio::test]
+    /// A connection that stays quiet, closes or writes something other than
+    /// a response fails the read with the reason.
+    async fn test_read_response_errors() {
+        let timeout = Duration::from_millis(50);
+        let (quiet, _peer) = tokio::io::duplex(64);
+        let error = read_response(&mut tokio::io::BufReader::new(quiet), timeout)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, MessageError::Timeout), "{error}");
+
+        let error = read_response(&mut &b""[..], timeout).await.unwrap_err();
+        assert_eq!(error.to_string(), "Component closed the connection without responding");
+
+        let error = read_response(&mut &b"not json\n"[..], timeout).await.unwrap_err();
+        assert!(matches!(error, MessageError::Invalid { .. }), "{error}");
+        assert!(
+            error.to_string().starts_with("Response \"not json\\n\" is not valid, "),
+            "{error}"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+
+/// Serialise a map with its keys in order. A `HashMap` is written in a
+/// different order every run, so config files written from one would be
+/// rewritten needlessly (i.e. same information in different order) and
+/// churn in git. Used with `#[serde(serialize_with = "ordered_map")]`.
+///
+/// * `value`: map serialised.
+/// * `serializer`: Serializer
+pub fn ordered_map<S, K, V>(value: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    K: Ord + Serialize,
+    V: Serialize,
+{
+    let ordered: BTreeMap<_, _> = value.iter().collect();
+    ordered.serialize(serializer)
+}
+
+/// Serialise an optional map with its keys in order, see [`ordered_map`].
+/// Used with `#[serde(serialize_with = "ordered_optional_map")]`.
+///
+/// * `value`: map serialised, if set.
+/// * `serializer`: Serializer
+pub fn ordered_optional_map<S, K, V>(value: &Option<HashMap<K, V>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    K: Ord + Serialize,
+    V: Serialize,
+{
+    let ordered: Option<BTreeMap<_, _>> = value.as_ref().map(|value| value.iter().collect());
+    ordered.serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    /// Config with maps of each kind, as the components have.
+    #[derive(Deserialize, Serialize, Debug, PartialEq)]
+    struct MapConfig {
+        /// Map written in order.
+        #[serde(serialize_with = "ordered_map")]
+        files: HashMap<u8, String>,
+        /// Optional map written in order.
+        #[serde(serialize_with = "ordered_optional_map")]
+        channels: Option<HashMap<u8, (u8, u8)>>,
+    }
+
+    /// Config with the same entries each call, in maps hashed differently.
+    ///
+    /// * `channels`: whether the optional map is set.
+    fn config(channels: bool) -> MapConfig {
+        MapConfig {
+            files: (0..32).rev().map(|id| (id, format!("pdm_{id}.yaml"))).collect(),
+            channels: channels.then(|| (0..32).map(|id| (id, (id / 12, id % 12))).collect()),
+        }
+    }
+
+    #[test]
+    /// Maps built apart with the same entries write byte-identical yaml,
+    /// keys in order, and read back to the same maps.
+    fn test_ordered_maps_are_deterministic() {
+        let yaml = serde_yaml::to_string(&config(true)).unwrap();
+        for _ in 0..8 {
+            assert_eq!(serde_yaml::to_string(&config(true)).unwrap(), yaml);
+        }
+        let keys: Vec<u8> = yaml
+            .lines()
+            .take_while(|line| !line.starts_with("channels"))
+            .filter_map(|line| line.trim().split(':').next()?.parse().ok())
+            .collect();
+        assert_eq!(keys, (0..32).collect::<Vec<_>>());
+        assert_eq!(serde_yaml::from_str::<MapConfig>(&yaml).unwrap(), config(true));
+    }
+
+    #[test]
+    /// An optional map that is not set is written as null.
+    fn test_ordered_optional_map_unset() {
+        let yaml = serde_yaml::to_string(&config(false)).unwrap();
+        assert!(yaml.contains("channels: null"), "{yaml}");
+        assert_eq!(serde_yaml::from_str::<MapConfig>(&yaml).unwrap(), config(false));
+    }
+}
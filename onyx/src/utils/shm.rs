@@ -0,0 +1,602 @@
+use crate::utils::image::Roi;
+use chrono::{DateTime, TimeZone, Utc};
+use memmap2::MmapMut;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    os::unix::net::UnixDatagram,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Identifies a mapped region as an onyx image ring ("ONYXSHM1").
+const SHM_MAGIC: u64 = 0x4f4e_5958_5348_4d31;
+/// Bytes reserved at the start of the region for the ring header.
+const HEADER_SIZE: usize = 64;
+/// Bytes reserved at the start of every slot for the frame metadata.
+const SLOT_HEADER_SIZE: usize = 64;
+/// Images coming out of the aravis bayer conversion are 8 bit RGB.
+pub const BYTES_PER_PIXEL: usize = 3;
+/// Location id written into a slot when the frame has no location.
+const NO_LOCATION: u64 = u64::MAX;
+
+/// Word offsets into the ring header.
+mod header {
+    /// Magic number used to check the region was created by onyx.
+    pub const MAGIC: usize = 0;
+    /// Number of slots in the ring.
+    pub const SLOT_COUNT: usize = 8;
+    /// Data capacity of each slot in bytes.
+    pub const SLOT_CAPACITY: usize = 16;
+    /// Total number of frames the writer has published.
+    pub const WRITE_COUNT: usize = 24;
+    /// Next frame the reader expects, published by the reader.
+    pub const READ_CURSOR: usize = 32;
+    /// Frames the writer overwrote before the reader got to them.
+    pub const OVERWRITTEN: usize = 40;
+}
+
+/// Word offsets into a slot header.
+mod slot {
+    /// Sequence lock, odd while the slot is being written.
+    pub const SEQUENCE: usize = 0;
+    /// Frame number stored in the slot.
+    pub const FRAME: usize = 8;
+    /// Capture time in nanoseconds since the unix epoch.
+    pub const CAPTURED_AT: usize = 16;
+    /// Width of the frame in pixels.
+    pub const WIDTH: usize = 24;
+    /// Height of the frame in pixels.
+    pub const HEIGHT: usize = 32;
+    /// Location id of the device that took the image.
+    pub const LOCATION: usize = 40;
+    /// Number of valid data bytes in the slot.
+    pub const LEN: usize = 48;
+}
+
+/// Configuration for handing images to another process through shared
+/// memory instead of writing them to disk. Both containers need to share
+/// `/dev/shm` for this to work.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShmSinkConfig {
+    /// Name of the region under `/dev/shm`, the control socket is created
+    /// next to it with a `.sock` suffix.
+    pub name: String,
+    /// Number of frames held in the ring before the oldest is overwritten.
+    pub slots: usize,
+}
+
+/// Information stored alongside every frame in the ring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShmFrameMetadata {
+    /// Monotonically increasing frame number assigned by the writer.
+    pub frame: u64,
+    /// Image capture time.
+    pub captured_at: DateTime<Utc>,
+    /// Width of the image in pixels.
+    pub width: u32,
+    /// Height of the image in pixels.
+    pub height: u32,
+    /// Location of device that took the image.
+    pub location_id: Option<u8>,
+    /// Number of bytes of pixel data.
+    pub len: usize,
+}
+
+/// Borrow an atomic word out of the mapped region.
+///
+/// * `map`: mapped region.
+/// * `offset`: byte offset of the word, must be 8 byte aligned.
+fn atomic_at(map: &[u8], offset: usize) -> &AtomicU64 {
+    assert!(
+        offset % 8 == 0 && offset + 8 <= map.len(),
+        "Shared memory word out of bounds {offset}"
+    );
+    // SAFETY: the mapping is page aligned and every offset used is a multiple
+    // of 8, AtomicU64 has the same size and alignment as u64 and the bounds
+    // are checked above.
+    #[allow(unsafe_code)]
+    unsafe {
+        &*map.as_ptr().add(offset).cast::<AtomicU64>()
+    }
+}
+
+/// The sequence lock value that marks a slot as holding a complete `frame`.
+fn complete_sequence(frame: u64) -> u64 {
+    2 * frame + 2
+}
+
+/// Path of the shared memory region for a given ring name.
+fn region_path(name: &str) -> PathBuf {
+    PathBuf::from(format!("/dev/shm/{name}"))
+}
+
+/// Path of the control socket used to announce new frames.
+fn control_path(name: &str) -> PathBuf {
+    PathBuf::from(format!("/dev/shm/{name}.sock"))
+}
+
+/// Writes frames into a fixed number of slots in a shared memory region so
+/// the AI container can read them without copying through the kernel. The
+/// writer never waits on the reader; when the reader lags the oldest slot is
+/// overwritten and counted.
+pub struct ShmImageWriter {
+    /// Name of the ring.
+    name: String,
+    /// Mapping of the shared region.
+    map: MmapMut,
+    /// Number of slots in the ring.
+    slot_count: u64,
+    /// Data capacity of each slot in bytes.
+    slot_capacity: usize,
+    /// Socket used to announce frames to the reader.
+    control: UnixDatagram,
+}
+
+impl ShmImageWriter {
+    /// Create a new ring with each slot sized to hold one RGB frame of the
+    /// region of interest.
+    ///
+    /// * `config`: name and slot count of the ring.
+    /// * `roi`: largest region of interest that will be written.
+    pub fn create(config: &ShmSinkConfig, roi: &Roi) -> io::Result<Self> {
+        let width = usize::try_from(roi.w).unwrap_or(0);
+        let height = usize::try_from(roi.h).unwrap_or(0);
+        Self::with_capacity(&config.name, config.slots, width * height * BYTES_PER_PIXEL)
+    }
+
+    /// Create a new ring with an explicit slot capacity in bytes.
+    ///
+    /// * `name`: name of the region under `/dev/shm`.
+    /// * `slots`: number of slots in the ring.
+    /// * `slot_capacity`: bytes of pixel data each slot can hold.
+    pub fn with_capacity(name: &str, slots: usize, slot_capacity: usize) -> io::Result<Self> {
+        if slots == 0 || slot_capacity == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Shared memory ring requires at least one non empty slot",
+            ));
+        }
+        // Keep every slot 64 byte aligned so the atomics stay aligned.
+        let slot_capacity = (slot_capacity + 63) & !63;
+        let len = HEADER_SIZE + slots * (SLOT_HEADER_SIZE + slot_capacity);
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(region_path(name))?;
+        file.set_len(len as u64)?;
+
+        // SAFETY: the region is only modified through this writer and the
+        // sequence locks in each slot, readers treat it as a seqlock.
+        #[allow(unsafe_code)]
+        let map = unsafe { MmapMut::map_mut(&file)? };
+
+        let control = UnixDatagram::unbound()?;
+        control.set_nonblocking(true)?;
+
+        let writer = Self {
+            name: name.to_owned(),
+            map,
+            slot_count: slots as u64,
+            slot_capacity,
+            control,
+        };
+        atomic_at(&writer.map, header::SLOT_COUNT).store(slots as u64, Ordering::Relaxed);
+        atomic_at(&writer.map, header::SLOT_CAPACITY)
+            .store(slot_capacity as u64, Ordering::Relaxed);
+        atomic_at(&writer.map, header::WRITE_COUNT).store(0, Ordering::Relaxed);
+        atomic_at(&writer.map, header::READ_CURSOR).store(0, Ordering::Relaxed);
+        atomic_at(&writer.map, header::OVERWRITTEN).store(0, Ordering::Relaxed);
+        // Magic goes last so a reader never sees a half initialised header.
+        atomic_at(&writer.map, header::MAGIC).store(SHM_MAGIC, Ordering::Release);
+        Ok(writer)
+    }
+
+    /// Byte offset of the slot that holds `frame`.
+    fn slot_offset(&self, frame: u64) -> usize {
+        #[allow(clippy::cast_possible_truncation)]
+        let index = (frame % self.slot_count) as usize;
+        HEADER_SIZE + index * (SLOT_HEADER_SIZE + self.slot_capacity)
+    }
+
+    /// Write a frame into the next slot and announce it on the control
+    /// socket, returning the frame number that was assigned.
+    ///
+    /// * `captured_at`: image capture time.
+    /// * `location_id`: location of the device that took the image.
+    /// * `(width, height)`: dimensions of the image in pixels.
+    /// * `data`: raw pixel data.
+    pub fn write(
+        &mut self,
+        captured_at: DateTime<Utc>,
+        location_id: Option<u8>,
+        (width, height): (u32, u32),
+        data: &[u8],
+    ) -> io::Result<u64> {
+        if data.len() > self.slot_capacity {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Frame of {} bytes does not fit in a {} byte slot",
+                    data.len(),
+                    self.slot_capacity
+                ),
+            ));
+        }
+
+        let frame = atomic_at(&self.map, header::WRITE_COUNT).load(Ordering::Relaxed);
+        let read_cursor = atomic_at(&self.map, header::READ_CURSOR).load(Ordering::Acquire);
+        if frame >= self.slot_count && frame - self.slot_count >= read_cursor {
+            // The reader has not consumed the frame we are about to replace.
+            atomic_at(&self.map, header::OVERWRITTEN).fetch_add(1, Ordering::Relaxed);
+        }
+
+        let offset = self.slot_offset(frame);
+        atomic_at(&self.map, offset + slot::SEQUENCE).store(2 * frame + 1, Ordering::Relaxed);
+        std::sync::atomic::fence(Ordering::Release);
+
+        let nanos = captured_at.timestamp() * 1_000_000_000
+            + i64::from(captured_at.timestamp_subsec_nanos());
+        atomic_at(&self.map, offset + slot::FRAME).store(frame, Ordering::Relaxed);
+        #[allow(clippy::cast_sign_loss)]
+        atomic_at(&self.map, offset + slot::CAPTURED_AT).store(nanos as u64, Ordering::Relaxed);
+        atomic_at(&self.map, offset + slot::WIDTH).store(width.into(), Ordering::Relaxed);
+        atomic_at(&self.map, offset + slot::HEIGHT).store(height.into(), Ordering::Relaxed);
+        atomic_at(&self.map, offset + slot::LOCATION).store(
+            location_id.map_or(NO_LOCATION, u64::from),
+            Ordering::Relaxed,
+        );
+        atomic_at(&self.map, offset + slot::LEN).store(data.len() as u64, Ordering::Relaxed);
+
+        let start = offset + SLOT_HEADER_SIZE;
+        self.map[start..start + data.len()].copy_from_slice(data);
+
+        atomic_at(&self.map, offset + slot::SEQUENCE)
+            .store(complete_sequence(frame), Ordering::Release);
+        atomic_at(&self.map, header::WRITE_COUNT).store(frame + 1, Ordering::Release);
+
+        // Announcing is best effort, there may not be a reader listening yet
+        // and capture must never block on the AI container.
+        let _ = self
+            .control
+            .send_to(&frame.to_le_bytes(), control_path(&self.name));
+        Ok(frame)
+    }
+
+    /// Number of frames that were overwritten before the reader read them.
+    pub fn overwritten(&self) -> u64 {
+        atomic_at(&self.map, header::OVERWRITTEN).load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for ShmImageWriter {
+    fn drop(&mut self) {
+        // Readers that still have the region mapped keep their view.
+        let _ = fs::remove_file(region_path(&self.name));
+    }
+}
+
+/// A borrowed view into a slot of the ring. The data may be overwritten by
+/// the writer while it is being used, so check [`ShmFrame::is_intact`] once
+/// finished with the view and discard the result if it returns false.
+pub struct ShmFrame<'a> {
+    /// Metadata read from the slot header.
+    pub metadata: ShmFrameMetadata,
+    /// Pixel data of the frame.
+    pub data: &'a [u8],
+    /// Sequence lock of the slot the frame lives in.
+    sequence: &'a AtomicU64,
+}
+
+impl ShmFrame<'_> {
+    /// Returns true if the writer has not touched the slot since the view
+    /// was taken.
+    pub fn is_intact(&self) -> bool {
+        std::sync::atomic::fence(Ordering::Acquire);
+        self.sequence.load(Ordering::Relaxed) == complete_sequence(self.metadata.frame)
+    }
+}
+
+/// Reader side of the shared memory ring, used by the consuming process to
+/// map the region and walk through frames in order.
+pub struct ShmImageReader {
+    /// Mapping of the shared region.
+    map: MmapMut,
+    /// Number of slots in the ring.
+    slot_count: u64,
+    /// Data capacity of each slot in bytes.
+    slot_capacity: usize,
+    /// Next frame this reader expects.
+    next_frame: u64,
+    /// Frames skipped because the writer overwrote them or they were torn.
+    skipped: u64,
+    /// Socket the writer announces new frames on.
+    control: UnixDatagram,
+    /// Path of the bound control socket.
+    control_path: PathBuf,
+}
+
+impl ShmImageReader {
+    /// Open an existing ring and bind the control socket.
+    ///
+    /// * `name`: name of the region under `/dev/shm`.
+    pub fn open(name: &str) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(region_path(name))?;
+
+        // SAFETY: the reader only ever writes its own cursor word, the rest of
+        // the region is read under the slot sequence locks.
+        #[allow(unsafe_code)]
+        let map = unsafe { MmapMut::map_mut(&file)? };
+
+        if map.len() < HEADER_SIZE
+            || atomic_at(&map, header::MAGIC).load(Ordering::Acquire) != SHM_MAGIC
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{name} is not an onyx image ring"),
+            ));
+        }
+
+        let slot_count = atomic_at(&map, header::SLOT_COUNT).load(Ordering::Relaxed);
+        #[allow(clippy::cast_possible_truncation)]
+        let slot_capacity = atomic_at(&map, header::SLOT_CAPACITY).load(Ordering::Relaxed) as usize;
+        let next_frame = atomic_at(&map, header::WRITE_COUNT).load(Ordering::Acquire);
+        // The header comes from another process, so check the slots it
+        // describes fit the region before any are indexed into.
+        let ring_len = usize::try_from(slot_count)
+            .ok()
+            .filter(|slots| *slots > 0)
+            .and_then(|slots| slots.checked_mul(SLOT_HEADER_SIZE.checked_add(slot_capacity)?))
+            .and_then(|slots_len| slots_len.checked_add(HEADER_SIZE));
+        if !ring_len.is_some_and(|ring_len| ring_len <= map.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{name} has a header of {slot_count} slots of {slot_capacity} bytes, \
+                     which do not fit its {} bytes",
+                    map.len()
+                ),
+            ));
+        }
+
+        let control_path = control_path(name);
+        let _ = fs::remove_file(&control_path);
+        let control = UnixDatagram::bind(&control_path)?;
+
+        Ok(Self {
+            map,
+            slot_count,
+            slot_capacity,
+            next_frame,
+            skipped: 0,
+            control,
+            control_path,
+        })
+    }
+
+    /// Block until the writer announces a frame or the timeout elapses,
+    /// returning the announced frame number.
+    ///
+    /// * `timeout`: maximum time to wait.
+    pub fn wait(&self, timeout: Duration) -> io::Result<Option<u64>> {
+        self.control.set_read_timeout(Some(timeout))?;
+        let mut buffer = [0u8; 8];
+        match self.control.recv(&mut buffer) {
+            Ok(8) => Ok(Some(u64::from_le_bytes(buffer))),
+            Ok(_) => Ok(None),
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Return a view of the next complete frame, skipping any that were
+    /// overwritten. Returns `None` once the reader has caught up.
+    pub fn next_frame(&mut self) -> Option<ShmFrame<'_>> {
+        loop {
+            let written = atomic_at(&self.map, header::WRITE_COUNT).load(Ordering::Acquire);
+            if self.next_frame >= written {
+                return None;
+            }
+            if written - self.next_frame > self.slot_count {
+                let oldest = written - self.slot_count;
+                self.skipped += oldest - self.next_frame;
+                self.next_frame = oldest;
+            }
+
+            let frame = self.next_frame;
+            self.next_frame += 1;
+            atomic_at(&self.map, header::READ_CURSOR).store(self.next_frame, Ordering::Release);
+
+            #[allow(clippy::cast_possible_truncation)]
+            let offset =
+                HEADER_SIZE + (frame % self.slot_count) as usize * (SLOT_HEADER_SIZE + self.slot_capacity);
+            if atomic_at(&self.map, offset + slot::SEQUENCE).load(Ordering::Acquire)
+                != complete_sequence(frame)
+            {
+                // Overwritten or mid write, either way this frame is gone.
+                self.skipped += 1;
+                continue;
+            }
+
+            #[allow(clippy::cast_possible_truncation)]
+            let len = (atomic_at(&self.map, offset + slot::LEN).load(Ordering::Relaxed) as usize)
+                .min(self.slot_capacity);
+            #[allow(clippy::cast_possible_wrap)]
+            let nanos = atomic_at(&self.map, offset + slot::CAPTURED_AT).load(Ordering::Relaxed) as i64;
+            let location = atomic_at(&self.map, offset + slot::LOCATION).load(Ordering::Relaxed);
+            #[allow(clippy::cast_possible_truncation)]
+            let metadata = ShmFrameMetadata {
+                frame,
+                captured_at: Utc.timestamp_nanos(nanos),
+                width: atomic_at(&self.map, offset + slot::WIDTH).load(Ordering::Relaxed) as u32,
+                height: atomic_at(&self.map, offset + slot::HEIGHT).load(Ordering::Relaxed) as u32,
+                location_id: u8::try_from(location).ok(),
+                len,
+            };
+            let start = offset + SLOT_HEADER_SIZE;
+            return Some(ShmFrame {
+                metadata,
+                data: &self.map[start..start + len],
+                sequence: atomic_at(&self.map, offset + slot::SEQUENCE),
+            });
+        }
+    }
+
+    /// Frames this reader has missed because it lagged behind the writer.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Record a frame that was found to be torn after it was read.
+    pub fn mark_torn(&mut self) {
+        self.skipped += 1;
+    }
+}
+
+impl Drop for ShmImageReader {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.control_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+    };
+    use uuid::Uuid;
+
+    /// Unique ring name so tests can run in parallel.
+    fn ring_name() -> String {
+        format!("onyx-test-{}", Uuid::new_v4())
+    }
+
+    #[test]
+    fn test_reader_sees_frames_in_order() {
+        let name = ring_name();
+        let mut writer = ShmImageWriter::with_capacity(&name, 4, 16).unwrap();
+        let mut reader = ShmImageReader::open(&name).unwrap();
+
+        for value in 0u8..3 {
+            writer
+                .write(Utc::now(), Some(1), (4, 4), &[value; 16])
+                .unwrap();
+        }
+
+        for expected in 0u64..3 {
+            let frame = reader.next_frame().expect("Expected a frame");
+            assert_eq!(frame.metadata.frame, expected);
+            assert_eq!(frame.metadata.location_id, Some(1));
+            assert_eq!(frame.metadata.len, 16);
+            #[allow(clippy::cast_possible_truncation)]
+            let value = expected as u8;
+            assert!(frame.data.iter().all(|b| *b == value));
+            assert!(frame.is_intact());
+        }
+        assert!(reader.next_frame().is_none());
+        assert_eq!(reader.skipped(), 0);
+    }
+
+    #[test]
+    fn test_lagging_reader_skips_overwritten_frames() {
+        let name = ring_name();
+        let mut writer = ShmImageWriter::with_capacity(&name, 4, 8).unwrap();
+        let mut reader = ShmImageReader::open(&name).unwrap();
+
+        for value in 0u8..10 {
+            writer.write(Utc::now(), None, (1, 8), &[value; 8]).unwrap();
+        }
+
+        let first = reader.next_frame().expect("Expected a frame");
+        assert_eq!(first.metadata.frame, 6, "Reader should jump to the oldest slot");
+        assert_eq!(first.metadata.location_id, None);
+        assert_eq!(reader.skipped(), 6);
+        assert_eq!(writer.overwritten(), 6);
+    }
+
+    #[test]
+    fn test_reader_rejects_inconsistent_header() {
+        let name = ring_name();
+        let writer = ShmImageWriter::with_capacity(&name, 4, 64).unwrap();
+        for (slot_count, slot_capacity) in [(0, 64), (5, 64), (4, 128), (u64::MAX, 64)] {
+            atomic_at(&writer.map, header::SLOT_COUNT).store(slot_count, Ordering::Relaxed);
+            atomic_at(&writer.map, header::SLOT_CAPACITY).store(slot_capacity, Ordering::Relaxed);
+            let error = ShmImageReader::open(&name).err().expect("Opened an inconsistent ring");
+            assert_eq!(
+                error.kind(),
+                io::ErrorKind::InvalidData,
+                "{slot_count} slots of {slot_capacity}"
+            );
+        }
+        atomic_at(&writer.map, header::SLOT_COUNT).store(4, Ordering::Relaxed);
+        atomic_at(&writer.map, header::SLOT_CAPACITY).store(64, Ordering::Relaxed);
+        assert!(ShmImageReader::open(&name).is_ok());
+    }
+
+    #[test]
+    fn test_oversized_frames_are_rejected() {
+        let name = ring_name();
+        let mut writer = ShmImageWriter::with_capacity(&name, 2, 64).unwrap();
+        assert!(writer.write(Utc::now(), None, (1, 1), &[0; 65]).is_err());
+    }
+
+    #[test]
+    /// Run a writer and a reader concurrently through a small ring and
+    /// assert every frame the reader accepts is whole.
+    fn test_concurrent_reader_never_sees_torn_frames() {
+        let name = ring_name();
+        let frame_size = 4096;
+        let mut writer = ShmImageWriter::with_capacity(&name, 3, frame_size).unwrap();
+        let mut reader = ShmImageReader::open(&name).unwrap();
+        let done = Arc::new(AtomicBool::new(false));
+
+        let writer_done = done.clone();
+        let writer_handle = thread::spawn(move || {
+            for frame in 0u64..2000 {
+                #[allow(clippy::cast_possible_truncation)]
+                let data = vec![(frame % 251) as u8; frame_size];
+                writer.write(Utc::now(), Some(0), (64, 64), &data).unwrap();
+            }
+            writer_done.store(true, Ordering::Release);
+            writer
+        });
+
+        let mut accepted = 0;
+        loop {
+            let finished = done.load(Ordering::Acquire);
+            while let Some(frame) = reader.next_frame() {
+                #[allow(clippy::cast_possible_truncation)]
+                let expected = (frame.metadata.frame % 251) as u8;
+                let consistent = frame.data.iter().all(|b| *b == expected);
+                if frame.is_intact() {
+                    assert!(consistent, "Torn frame {}", frame.metadata.frame);
+                    accepted += 1;
+                } else {
+                    reader.mark_torn();
+                }
+            }
+            if finished {
+                break;
+            }
+        }
+
+        let _writer = writer_handle.join().expect("Writer thread panicked");
+        assert!(accepted > 0, "Reader did not accept any frames");
+        assert_eq!(accepted + reader.skipped(), 2000);
+    }
+}
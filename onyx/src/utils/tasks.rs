@@ -0,0 +1,90 @@
+use std::{
+    future::{poll_fn, Future},
+    pin::Pin,
+    task::Poll,
+    time::Duration,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    task::{JoinError, JoinHandle},
+};
+
+/// Time a binary waits for its component to shut down once a stop has
+/// been requested, before giving up on it and exiting with
+/// [`EXIT_SHUTDOWN_TIMEOUT`].
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Exit code of a binary whose component stopped on its own or had a
+/// task panic, so the container is restarted.
+pub const EXIT_TASK_FAILED: u8 = 1;
+
+/// Exit code of a binary whose component did not shut down within the
+/// timeout.
+pub const EXIT_SHUTDOWN_TIMEOUT: u8 = 2;
+
+/// Exit code of a binary whose config, or a file it refers to, does not
+/// load or is refused by the component.
+pub const EXIT_INVALID_CONFIG: u8 = 3;
+
+/// Exit code of a binary whose component could not be started, i.e. its
+/// devices are not on the network or a port is taken.
+pub const EXIT_START_FAILED: u8 = 4;
+
+/// Named task of a running component.
+pub type NamedTask = (&'static str, JoinHandle<()>);
+
+/// Wait for the container to be stopped, SIGTERM from docker or SIGINT
+/// from a terminal, returning the name of the signal received.
+pub async fn stop_requested() -> &'static str {
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            result.expect("Failed to listen for SIGINT");
+            "SIGINT"
+        }
+        _ = terminate.recv() => "SIGTERM",
+    }
+}
+
+/// Wait for the first of the tasks to end, removing it and returning its
+/// name and how it ended. Never returns when there are no tasks.
+///
+/// * `tasks`: running tasks.
+pub async fn first_finished(tasks: &mut Vec<NamedTask>) -> (&'static str, Result<(), JoinError>) {
+    let (index, result) = poll_fn(|cx| {
+        for (index, (_, task)) in tasks.iter_mut().enumerate() {
+            if let Poll::Ready(result) = Pin::new(task).poll(cx) {
+                return Poll::Ready((index, result));
+            }
+        }
+        Poll::Pending
+    })
+    .await;
+    let (name, _) = tasks.remove(index);
+    (name, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    /// The task that ends first is returned whichever order it is in, with
+    /// a panic reported as one.
+    async fn test_first_finished() {
+        let mut tasks: Vec<NamedTask> = vec![
+            ("pending", tokio::spawn(std::future::pending())),
+            ("panicking", tokio::spawn(async { panic!("Task failed") })),
+        ];
+        let (name, result) = first_finished(&mut tasks).await;
+        assert_eq!(name, "panicking");
+        assert!(result.unwrap_err().is_panic());
+        assert_eq!(tasks.len(), 1);
+
+        tasks.push(("returning", tokio::spawn(async {})));
+        let (name, result) = first_finished(&mut tasks).await;
+        assert_eq!(name, "returning");
+        assert!(result.is_ok());
+        assert_eq!(tasks[0].0, "pending");
+    }
+}
@@ -0,0 +1,300 @@
+use crate::messages::telemetry::Telemetry;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    net::UdpSocket,
+};
+use tracing::{info, warn};
+
+/// Time in milliseconds between telemetry shipments, when not set in the
+/// config.
+pub const DEFAULT_TELEMETRY_INTERVAL_MS: u64 = 5000;
+
+/// How telemetry reaches its destination.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TelemetryTransport {
+    /// One datagram per snapshot, nothing is held open.
+    #[default]
+    Udp,
+    /// One line per snapshot appended to a file, created if it does not
+    /// exist.
+    File,
+}
+
+/// Where and how often a component ships its telemetry.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TelemetryEmitterConfig {
+    /// Address the snapshots are sent to, e.g. `127.0.0.1:17653`, or the
+    /// file they are appended to.
+    pub destination: String,
+    /// How they are shipped, UDP when not set.
+    #[serde(default)]
+    pub transport: TelemetryTransport,
+    /// Time in milliseconds between shipments.
+    #[serde(default = "default_telemetry_interval_ms")]
+    pub interval_ms: u64,
+}
+
+/// Default telemetry interval for serde.
+fn default_telemetry_interval_ms() -> u64 {
+    DEFAULT_TELEMETRY_INTERVAL_MS
+}
+
+impl TelemetryEmitterConfig {
+    /// Ship telemetry to an address over UDP at the default interval.
+    ///
+    /// * `destination`: address the snapshots are sent to.
+    pub fn new(destination: impl Into<String>) -> Self {
+        Self {
+            destination: destination.into(),
+            transport: TelemetryTransport::default(),
+            interval_ms: DEFAULT_TELEMETRY_INTERVAL_MS,
+        }
+    }
+
+    /// Set how the snapshots are shipped.
+    ///
+    /// * `transport`: UDP or a file.
+    pub fn with_transport(mut self, transport: TelemetryTransport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Set the time between shipments.
+    ///
+    /// * `interval_ms`: time in milliseconds.
+    pub fn with_interval(mut self, interval_ms: u64) -> Self {
+        self.interval_ms = interval_ms;
+        self
+    }
+
+    /// Time between shipments, never zero.
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms.max(1))
+    }
+}
+
+/// Holds the latest snapshot of each kind fed by a component until it is
+/// shipped. Feeding only swaps the snapshot in, the snapshots are written
+/// out by [`TelemetryEmitter::ship`] away from the locks of the component.
+#[derive(Debug, Clone)]
+pub struct TelemetryEmitter {
+    /// Where and how often the snapshots go.
+    config: TelemetryEmitterConfig,
+    /// Latest snapshot of each kind not yet shipped.
+    latest: Arc<Mutex<BTreeMap<&'static str, Telemetry>>>,
+}
+
+impl TelemetryEmitter {
+    /// Emitter holding no snapshots.
+    ///
+    /// * `config`: where and how often the snapshots go.
+    pub fn new(config: TelemetryEmitterConfig) -> Self {
+        Self {
+            config,
+            latest: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Where and how often the snapshots go.
+    pub fn config(&self) -> &TelemetryEmitterConfig {
+        &self.config
+    }
+
+    /// Hand over a snapshot, replacing any of its kind not yet shipped.
+    ///
+    /// * `snapshot`: snapshot taken by the component.
+    pub fn feed(&self, snapshot: impl Into<Telemetry>) {
+        let snapshot = snapshot.into();
+        self.latest
+            .lock()
+            .expect("Telemetry lock poisoned")
+            .insert(snapshot.kind(), snapshot);
+    }
+
+    /// Take the snapshots not yet shipped, in order of kind.
+    fn take(&self) -> Vec<Telemetry> {
+        let mut latest = self.latest.lock().expect("Telemetry lock poisoned");
+        std::mem::take(&mut *latest).into_values().collect()
+    }
+
+    /// Ship the snapshots fed at every interval until every other clone of
+    /// the emitter has been dropped, shipping what is left then. A
+    /// destination that cannot be reached is logged once, when it first
+    /// fails, and again once it is reached.
+    pub async fn ship(self) {
+        let mut interval = tokio::time::interval(self.config.interval());
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut sender = TelemetrySender {
+            config: self.config.clone(),
+            socket: None,
+            file: None,
+        };
+        let mut reachable = true;
+        loop {
+            interval.tick().await;
+            let last = Arc::strong_count(&self.latest) == 1;
+            for snapshot in self.take() {
+                match sender.send(&snapshot).await {
+                    Ok(()) if !reachable => {
+                        info!("Shipping telemetry to {} again", self.config.destination);
+                        reachable = true;
+                    }
+                    Err(e) if reachable => {
+                        warn!(
+                            "Failed to ship {} telemetry to {}, carrying on without: {e}",
+                            snapshot.kind(),
+                            self.config.destination
+                        );
+                        reachable = false;
+                    }
+                    _ => {}
+                }
+            }
+            if last {
+                break;
+            }
+        }
+    }
+}
+
+/// Ships snapshots to the destination of a config, remembering the socket
+/// or file between them.
+struct TelemetrySender {
+    /// Where and how the snapshots go.
+    config: TelemetryEmitterConfig,
+    /// Socket the datagrams are sent from, once bound.
+    socket: Option<UdpSocket>,
+    /// File the lines are appended to, once opened.
+    file: Option<File>,
+}
+
+impl TelemetrySender {
+    /// Ship one snapshot, dropping the socket or file on failure so the
+    /// next one starts afresh.
+    ///
+    /// * `snapshot`: snapshot shipped.
+    async fn send(&mut self, snapshot: &Telemetry) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(snapshot).expect("Failed to serialise telemetry");
+        let sent = match self.config.transport {
+            TelemetryTransport::Udp => {
+                if self.socket.is_none() {
+                    self.socket = Some(UdpSocket::bind("0.0.0.0:0").await?);
+                }
+                let socket = self.socket.as_ref().expect("Socket bound above");
+                socket.send_to(&line, &self.config.destination).await.map(|_| ())
+            }
+            TelemetryTransport::File => {
+                if self.file.is_none() {
+                    let file = OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&self.config.destination)
+                        .await?;
+                    self.file = Some(file);
+                }
+                line.push(b'\n');
+                let file = self.file.as_mut().expect("Opened above");
+                match file.write_all(&line).await {
+                    Ok(()) => file.flush().await,
+                    Err(e) => Err(e),
+                }
+            }
+        };
+        if sent.is_err() {
+            self.socket = None;
+            self.file = None;
+        }
+        sent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        messages::telemetry::{SprayTelemetry, TelemetryHeader},
+        utils::location::CropBed,
+    };
+    use uuid::Uuid;
+
+    /// Snapshot of a spray queue holding some actions.
+    ///
+    /// * `queue_depth`: actions in the queue.
+    fn spray(queue_depth: usize) -> SprayTelemetry {
+        SprayTelemetry {
+            header: TelemetryHeader::new(Uuid::new_v4(), CropBed::Centre),
+            queue_depth,
+            accepted: 10,
+            late: 1,
+            late_discarded: 0,
+            last_fired_at: None,
+            last_fired_position: None,
+        }
+    }
+
+    #[tokio::test]
+    /// Only the latest snapshot of each kind is shipped over UDP at the
+    /// interval, and shipping ends once the feeders have gone.
+    async fn test_ship_over_udp() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = TelemetryEmitterConfig::new(socket.local_addr().unwrap().to_string()).with_interval(20);
+        let emitter = TelemetryEmitter::new(config);
+        emitter.feed(spray(1));
+        emitter.feed(spray(2));
+        let shipping = tokio::spawn(emitter.clone().ship());
+
+        let mut data = vec![0; 4096];
+        let length = socket.recv(&mut data).await.unwrap();
+        let Telemetry::Spray(shipped) = serde_json::from_slice(&data[..length]).unwrap() else {
+            panic!("Shipped telemetry of the wrong kind");
+        };
+        assert_eq!(shipped.queue_depth, 2);
+
+        emitter.feed(spray(3));
+        drop(emitter);
+        tokio::time::timeout(Duration::from_secs(1), shipping)
+            .await
+            .expect("Shipping did not end")
+            .unwrap();
+        let length = socket.recv(&mut data).await.unwrap();
+        let Telemetry::Spray(shipped) = serde_json::from_slice(&data[..length]).unwrap() else {
+            panic!("Shipped telemetry of the wrong kind");
+        };
+        assert_eq!(shipped.queue_depth, 3);
+    }
+
+    #[tokio::test]
+    /// Snapshots are appended to a file as lines of json, after anything
+    /// already in it.
+    async fn test_ship_to_file() {
+        let path = std::env::temp_dir().join(format!("onyx-telemetry-{}.jsonl", Uuid::new_v4()));
+        std::fs::write(&path, "{}\n").unwrap();
+        let config = TelemetryEmitterConfig::new(path.to_string_lossy())
+            .with_transport(TelemetryTransport::File)
+            .with_interval(10);
+        for queue_depth in [4, 5] {
+            let emitter = TelemetryEmitter::new(config.clone());
+            emitter.feed(spray(queue_depth));
+            emitter.ship().await;
+        }
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        for (line, queue_depth) in lines[1..].iter().zip([4, 5]) {
+            let Telemetry::Spray(shipped) = serde_json::from_str(line).unwrap() else {
+                panic!("Shipped telemetry of the wrong kind");
+            };
+            assert_eq!(shipped.queue_depth, queue_depth);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,23 +1,20 @@
-/// Macro that helps to check test file exist at compile time.
-/// [link](https://stackoverflow.com/questions/30003921/how-can-i-locate-resources-for-testing-with-cargo)
-/// [link](https://stackoverflow.com/questions/73187970/compile-time-check-if-file-at-path-exists-like-include-str)
+/// Path of a test file in the crate, kept for older tests. Deprecated, use
+/// [`crate::utils::paths::repo_relative`] which checks the file exists
+/// when the test runs rather than when the crate is built.
 #[macro_export]
 macro_rules! test_file_path {
     ($arg1:expr) => {{
-        // TODO: opportunity for improvement
-        let _ = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), $arg1));
-        let r = concat!(env!("CARGO_MANIFEST_DIR"), $arg1);
+        let r = $crate::utils::paths::legacy_path($arg1);
         r
     }};
 }
 
-/// Macro to test if directories exist.
+/// Path of a test directory in the crate, kept for older tests. Deprecated,
+/// use [`crate::utils::paths::require_dir`].
 #[macro_export]
 macro_rules! test_dirs_path {
     ($arg1:expr) => {{
-        //TODO: opportunity for improvement
-        let _ = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), $arg1));
-        let r = concat!(env!("CARGO_MANIFEST_DIR"), $arg1);
+        let r = $crate::utils::paths::legacy_path($arg1);
         r
     }};
 }
@@ -0,0 +1,184 @@
+use chrono::{DateTime, Duration, Utc};
+use tokio::time::Instant;
+
+/// Change in the offset of the system clock from the monotonic clock past
+/// which it is taken to have been stepped, e.g. by NTP or GPS time.
+pub const DEFAULT_CLOCK_STEP_THRESHOLD_MS: i64 = 50;
+
+/// UTC time read off the monotonic clock, mapped to UTC once when the
+/// clock is created, so the system clock being stepped while running does
+/// not show up as latency or move sprays already queued.
+///
+/// Times in the timeline of the clock are deadlines on the monotonic
+/// clock written as UTC, times from elsewhere are brought onto it once
+/// with [`MonotonicClock::to_clock`].
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    /// Monotonic instant the clock was created.
+    started: Instant,
+    /// UTC time the clock was created.
+    started_utc: DateTime<Utc>,
+    /// Offset of the system clock from this clock when a step was last
+    /// reported, zero until then.
+    reported_skew: Duration,
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MonotonicClock {
+    /// Clock mapped to UTC now.
+    pub fn new() -> Self {
+        Self::with_anchor(Instant::now(), Utc::now())
+    }
+
+    /// Clock mapping a monotonic instant to a UTC time.
+    ///
+    /// * `started`: monotonic instant of the anchor.
+    /// * `started_utc`: UTC time of the anchor.
+    pub fn with_anchor(started: Instant, started_utc: DateTime<Utc>) -> Self {
+        Self {
+            started,
+            started_utc,
+            reported_skew: Duration::zero(),
+        }
+    }
+
+    /// Current time, the UTC time the clock was created plus the monotonic
+    /// time since.
+    pub fn now(&self) -> DateTime<Utc> {
+        self.utc_at(Instant::now())
+    }
+
+    /// UTC time of a monotonic instant, in the timeline of the clock.
+    ///
+    /// * `instant`: instant at or after the clock was created.
+    pub fn utc_at(&self, instant: Instant) -> DateTime<Utc> {
+        self.started_utc
+            + Duration::from_std(instant.saturating_duration_since(self.started))
+                .unwrap_or_else(|_| Duration::max_value())
+    }
+
+    /// Monotonic instant a time in the timeline of the clock falls due,
+    /// times before the clock was created falling due when it was.
+    ///
+    /// * `time`: time in the timeline of the clock.
+    pub fn instant_at(&self, time: DateTime<Utc>) -> Instant {
+        (time - self.started_utc)
+            .to_std()
+            .map_or(self.started, |since| self.started + since)
+    }
+
+    /// How far the system clock is ahead of this clock, negative when
+    /// behind.
+    ///
+    /// * `wall_now`: time read off the system clock now.
+    pub fn skew(&self, wall_now: DateTime<Utc>) -> Duration {
+        wall_now - self.now()
+    }
+
+    /// Bring a time read off the system clock, or another machine synced
+    /// to it, onto the timeline of the clock. Done once as a message is
+    /// queued, so a later step of the system clock leaves it in place.
+    ///
+    /// * `time`: time off the system clock.
+    /// * `wall_now`: time read off the system clock now.
+    pub fn to_clock(&self, time: DateTime<Utc>, wall_now: DateTime<Utc>) -> DateTime<Utc> {
+        time - self.skew(wall_now)
+    }
+
+    /// Change in the offset of the system clock since a step was last
+    /// reported, when past the threshold. The change is only reported
+    /// once, a clock drifting slowly is reported each time it drifts past
+    /// the threshold again.
+    ///
+    /// * `wall_now`: time read off the system clock now.
+    /// * `threshold`: change taken to be a step.
+    pub fn detect_step(&mut self, wall_now: DateTime<Utc>, threshold: Duration) -> Option<Duration> {
+        let skew = self.skew(wall_now);
+        let step = skew - self.reported_skew;
+        if step.abs() > threshold {
+            self.reported_skew = skew;
+            Some(step)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Threshold of the tests.
+    fn threshold() -> Duration {
+        Duration::milliseconds(DEFAULT_CLOCK_STEP_THRESHOLD_MS)
+    }
+
+    #[test]
+    /// Times off the monotonic clock follow the UTC time it was created at.
+    fn test_monotonic_clock() {
+        let clock = MonotonicClock::new();
+        let first = clock.now();
+        assert!((Utc::now() - first).num_milliseconds().abs() < 50);
+        assert!(clock.now() >= first);
+    }
+
+    #[test]
+    /// Instants and times map back and forth about the anchor, times before
+    /// it falling due at once.
+    fn test_instant_at() {
+        let started = Instant::now();
+        let started_utc = Utc::now();
+        let clock = MonotonicClock::with_anchor(started, started_utc);
+        let later = started + std::time::Duration::from_millis(1500);
+        assert_eq!(clock.utc_at(later), started_utc + Duration::milliseconds(1500));
+        assert_eq!(clock.instant_at(started_utc + Duration::milliseconds(1500)), later);
+        assert_eq!(clock.instant_at(started_utc - Duration::seconds(1)), started);
+        assert_eq!(clock.utc_at(started), started_utc);
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// A step of the system clock is reported once, and leaves the deadline
+    /// of a time queued before it, and the interval between times queued
+    /// either side of it, as they were.
+    async fn test_wall_clock_step() {
+        let mut clock = MonotonicClock::new();
+        let wall = clock.now();
+        assert!(clock.detect_step(wall, threshold()).is_none());
+
+        // The AI system asks for a spray in a second, then another 200ms
+        // after it, sent once the system clock was stepped 2s ahead.
+        let first = clock.to_clock(wall + Duration::seconds(1), wall);
+        let first_due = clock.instant_at(first);
+        tokio::time::advance(std::time::Duration::from_millis(100)).await;
+        let stepped = wall + Duration::milliseconds(100) + Duration::seconds(2);
+        let second = clock.to_clock(stepped + Duration::milliseconds(1100), stepped);
+
+        assert_eq!(clock.detect_step(stepped, threshold()), Some(Duration::seconds(2)));
+        assert!(clock.detect_step(stepped, threshold()).is_none());
+        assert_eq!(clock.skew(stepped), Duration::seconds(2));
+        assert_eq!(clock.instant_at(first), first_due);
+        assert_eq!(second - first, Duration::milliseconds(200));
+        assert_eq!(
+            clock.instant_at(second),
+            first_due + std::time::Duration::from_millis(200)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Drift under the threshold is not a step, until it adds up past it.
+    async fn test_slow_drift() {
+        let mut clock = MonotonicClock::new();
+        let wall = clock.now();
+        let drift = Duration::milliseconds(DEFAULT_CLOCK_STEP_THRESHOLD_MS / 2);
+        assert!(clock.detect_step(wall + drift, threshold()).is_none());
+        assert!(clock.detect_step(wall - drift, threshold()).is_none());
+        let past = wall + drift * 3;
+        assert_eq!(clock.detect_step(past, threshold()), Some(drift * 3));
+        assert!(clock.detect_step(past + drift, threshold()).is_none());
+    }
+}
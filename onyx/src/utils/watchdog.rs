@@ -0,0 +1,325 @@
+use crate::utils::health::Health;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tracing::{info, warn};
+
+/// Time between the checks for a component becoming ready.
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// State of a component reported to the service manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceState {
+    /// The component has started and can take messages, `READY=1`.
+    Ready,
+    /// The processing loops have made progress since the last ping,
+    /// `WATCHDOG=1`.
+    Watchdog,
+    /// The component is shutting down, `STOPPING=1`.
+    Stopping,
+}
+
+/// Where the state of a component is reported, systemd or a double in
+/// tests.
+pub trait Notifier: Send + Sync {
+    /// Report a state.
+    ///
+    /// * `state`: state of the component.
+    fn notify(&self, state: ServiceState);
+}
+
+/// Count of the passes a processing loop has made, ticked by the loop and
+/// read by the watchdog. Clones share the count.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    /// Passes made.
+    count: Arc<AtomicU64>,
+}
+
+impl Progress {
+    /// Create the count of a loop that has not started.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a pass of the loop.
+    pub fn tick(&self) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Passes made.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+/// A loop followed by the watchdog.
+struct WatchedLoop {
+    /// Name of the loop, logged when it stalls.
+    name: &'static str,
+    /// Count ticked by the loop.
+    progress: Progress,
+    /// Count when it was last checked.
+    seen: u64,
+}
+
+/// Pings the service manager only while every loop followed has made
+/// progress, so a loop that has stopped or hangs on a lock gets the
+/// component restarted.
+pub struct Watchdog {
+    /// Where the pings are sent.
+    notifier: Arc<dyn Notifier>,
+    /// Loops followed.
+    loops: Vec<WatchedLoop>,
+}
+
+impl Watchdog {
+    /// Create a watchdog following no loop, which pings at every check.
+    ///
+    /// * `notifier`: where the pings are sent.
+    pub fn new(notifier: Arc<dyn Notifier>) -> Self {
+        Self {
+            notifier,
+            loops: Vec::new(),
+        }
+    }
+
+    /// Follow a loop, which must make progress between the checks.
+    ///
+    /// * `name`: name of the loop, logged when it stalls.
+    /// * `progress`: count ticked by the loop.
+    pub fn with_loop(mut self, name: &'static str, progress: Progress) -> Self {
+        let seen = progress.count();
+        self.loops.push(WatchedLoop { name, progress, seen });
+        self
+    }
+
+    /// Loops that have made no progress since the last check, taking the
+    /// counts seen now for the next check.
+    pub fn stalled(&mut self) -> Vec<&'static str> {
+        let mut stalled = Vec::new();
+        for watched in &mut self.loops {
+            let count = watched.progress.count();
+            if count == watched.seen {
+                stalled.push(watched.name);
+            }
+            watched.seen = count;
+        }
+        stalled
+    }
+
+    /// Ping the service manager if every loop has made progress since the
+    /// last check, returning whether it was pinged.
+    pub fn check(&mut self) -> bool {
+        let stalled = self.stalled();
+        if stalled.is_empty() {
+            self.notifier.notify(ServiceState::Watchdog);
+            true
+        } else {
+            warn!(
+                "No progress from {} since the last check, watchdog not pinged",
+                stalled.join(", ")
+            );
+            false
+        }
+    }
+
+    /// Check the loops at an interval until the process exits.
+    ///
+    /// * `interval`: time between the checks, inside the watchdog timeout
+    ///   of the service manager.
+    pub async fn run(mut self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick is immediate, the loops are given an interval to
+        // make progress.
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            self.check();
+        }
+    }
+}
+
+/// Report the component ready once its health is.
+///
+/// * `notifier`: where the state is reported.
+/// * `health`: health of the component.
+pub async fn notify_ready(notifier: Arc<dyn Notifier>, health: Health) {
+    while !health.report().is_ready() {
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+    }
+    info!("Component ready, notifying the service manager");
+    notifier.notify(ServiceState::Ready);
+}
+
+/// Notifier over the socket systemd passes in `NOTIFY_SOCKET`.
+#[cfg(feature = "systemd")]
+pub struct SystemdNotifier;
+
+#[cfg(feature = "systemd")]
+impl Notifier for SystemdNotifier {
+    fn notify(&self, state: ServiceState) {
+        let state = match state {
+            ServiceState::Ready => sd_notify::NotifyState::Ready,
+            ServiceState::Watchdog => sd_notify::NotifyState::Watchdog,
+            ServiceState::Stopping => sd_notify::NotifyState::Stopping,
+        };
+        if let Err(e) = sd_notify::notify(false, &[state]) {
+            warn!("Failed to notify systemd: {e}");
+        }
+    }
+}
+
+/// Notifier of the service manager the process runs under, `None` without
+/// the `systemd` feature or when not run by systemd.
+pub fn service_notifier() -> Option<Arc<dyn Notifier>> {
+    #[cfg(feature = "systemd")]
+    if std::env::var_os("NOTIFY_SOCKET").is_some() {
+        return Some(Arc::new(SystemdNotifier));
+    }
+    None
+}
+
+/// Time between the watchdog checks, half the `WatchdogSec` of the unit,
+/// `None` without the `systemd` feature or when the watchdog is not
+/// enabled.
+pub fn watchdog_interval() -> Option<Duration> {
+    #[cfg(feature = "systemd")]
+    {
+        let mut usec = 0;
+        if sd_notify::watchdog_enabled(false, &mut usec) {
+            return Some(Duration::from_micros(usec) / 2);
+        }
+    }
+    None
+}
+
+/// Report a component to the service manager it runs under: ready once its
+/// health is and, when the watchdog is enabled, pinged while its loops make
+/// progress. Returns the notifier the shutdown is reported on.
+///
+/// * `health`: health of the component.
+/// * `loops`: names and counts of the processing loops of the component.
+pub fn notify_service(health: Health, loops: Vec<(&'static str, Progress)>) -> Option<Arc<dyn Notifier>> {
+    let notifier = service_notifier()?;
+    tokio::spawn(notify_ready(notifier.clone(), health));
+    if let Some(interval) = watchdog_interval() {
+        let watchdog = loops
+            .into_iter()
+            .fold(Watchdog::new(notifier.clone()), |watchdog, (name, progress)| {
+                watchdog.with_loop(name, progress)
+            });
+        tokio::spawn(watchdog.run(interval));
+    }
+    Some(notifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Notifier recording the states reported.
+    #[derive(Default)]
+    struct RecordingNotifier {
+        /// States reported, in order.
+        states: Mutex<Vec<ServiceState>>,
+    }
+
+    impl Notifier for RecordingNotifier {
+        fn notify(&self, state: ServiceState) {
+            self.states.lock().unwrap().push(state);
+        }
+    }
+
+    impl RecordingNotifier {
+        /// States reported so far.
+        fn states(&self) -> Vec<ServiceState> {
+            self.states.lock().unwrap().clone()
+        }
+    }
+
+    #[test]
+    /// The watchdog pings only when every loop has ticked since the last
+    /// check, and pings again once a stalled loop picks up.
+    fn test_pings_on_progress() {
+        let notifier = Arc::new(RecordingNotifier::default());
+        let firing = Progress::new();
+        let listener = Progress::new();
+        let mut watchdog = Watchdog::new(notifier.clone())
+            .with_loop("firing task", firing.clone())
+            .with_loop("listener", listener.clone());
+        assert_eq!(watchdog.stalled(), ["firing task", "listener"]);
+
+        firing.tick();
+        listener.tick();
+        assert!(watchdog.check());
+        assert_eq!(notifier.states(), [ServiceState::Watchdog]);
+
+        firing.tick();
+        firing.tick();
+        assert!(!watchdog.check());
+        assert_eq!(notifier.states(), [ServiceState::Watchdog]);
+        assert_eq!(firing.count(), 3);
+
+        firing.tick();
+        assert_eq!(watchdog.stalled(), ["listener"]);
+        firing.tick();
+        listener.tick();
+        assert!(watchdog.check());
+        assert_eq!(notifier.states(), [ServiceState::Watchdog, ServiceState::Watchdog]);
+    }
+
+    #[test]
+    /// Ticks made before a loop is followed are not taken as progress.
+    fn test_progress_before_watching() {
+        let notifier = Arc::new(RecordingNotifier::default());
+        let firing = Progress::new();
+        firing.tick();
+        let mut watchdog = Watchdog::new(notifier.clone()).with_loop("firing task", firing.clone());
+        assert!(!watchdog.check());
+        assert!(Watchdog::new(notifier.clone()).check());
+        assert_eq!(notifier.states(), [ServiceState::Watchdog]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Run, the watchdog pings at each of the three intervals the loop ticked
+    /// in and stops pinging once the loop stops.
+    async fn test_run() {
+        let notifier = Arc::new(RecordingNotifier::default());
+        let firing = Progress::new();
+        let watchdog = Watchdog::new(notifier.clone()).with_loop("firing task", firing.clone());
+        tokio::spawn(watchdog.run(Duration::from_secs(1)));
+        let ticking = tokio::spawn({
+            let firing = firing.clone();
+            async move {
+                for _ in 0..25 {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    firing.tick();
+                }
+            }
+        });
+        ticking.await.unwrap();
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        assert_eq!(notifier.states(), [ServiceState::Watchdog; 3]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// The component is reported ready once, when its health is.
+    async fn test_notify_ready() {
+        let notifier = Arc::new(RecordingNotifier::default());
+        let health = Health::new();
+        let ready = tokio::spawn(notify_ready(notifier.clone(), health.clone()));
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(notifier.states().is_empty());
+
+        health.set_canbus_open(true);
+        health.set_listening(true);
+        ready.await.unwrap();
+        assert_eq!(notifier.states(), [ServiceState::Ready]);
+    }
+}
@@ -0,0 +1,102 @@
+//! The crate in each combination of the hardware features, the tests of a
+//! combination gated on its features. Every combination is run with
+//! `make run_feature_tests`.
+use onyx::{
+    components::prelude::*,
+    devices::{
+        hardware::{
+            ambient_light::AmbientLightConfig,
+            camera::OnyxCameraConfig,
+            pdm::{PdmAddress, PdmConfig},
+            wheel_speed::WheelSpeedConfig,
+        },
+        software::camera::SimulatedCameraConfig,
+    },
+    utils::location::CropBed,
+};
+use std::{io, net::Ipv4Addr};
+
+/// Camera array with a network camera and a simulated one.
+fn mixed_array() -> CameraArrayConfig {
+    CameraArrayConfig::new(String::from("./images"), CropBed::Centre)
+        .add_camera_config(OnyxCameraConfig::crop_bed(Ipv4Addr::new(169, 254, 8, 10), 3, 0), 0)
+        .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 1)
+}
+
+/// Errors opening the wheel speed and ambient light sensors on an
+/// interface that does not exist.
+fn canbus_sensor_errors() -> Vec<io::Error> {
+    let wheel_speed: WheelSpeedConfig = serde_yaml::from_str("can:\n  canbus_id: nocan0").unwrap();
+    let ambient_light: AmbientLightConfig = serde_yaml::from_str("can:\n  canbus_id: nocan0").unwrap();
+    vec![
+        wheel_speed
+            .open()
+            .err()
+            .expect("Wheel speed opened on a missing interface"),
+        ambient_light
+            .open()
+            .err()
+            .expect("Ambient light opened on a missing interface"),
+    ]
+}
+
+#[test]
+/// The device configs read back as they were written in every build, the
+/// camera settings with their GenICam names.
+fn test_configs_in_every_build() {
+    let camera = OnyxCameraConfig::crop_bed(Ipv4Addr::new(169, 254, 8, 10), 3, 0);
+    let yaml = serde_yaml::to_string(&camera).unwrap();
+    assert!(yaml.contains("pixel_format: BAYER_RG_8"), "{yaml}");
+    assert!(yaml.contains("acquisition_mode: Continuous"), "{yaml}");
+    assert_eq!(serde_yaml::from_str::<OnyxCameraConfig>(&yaml).unwrap(), camera);
+
+    let pdm = PdmConfig::new(PdmAddress::Pdm30, 0);
+    let yaml = serde_yaml::to_string(&pdm).unwrap();
+    assert_eq!(serde_yaml::from_str::<PdmConfig>(&yaml).unwrap(), pdm);
+
+    let array = CameraArrayConfig::new(String::from("./images"), CropBed::Centre)
+        .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 0);
+    assert!(CameraArray::new(array).is_ok());
+}
+
+#[cfg(feature = "camera-hw")]
+#[test]
+/// Network cameras are built into an array alongside simulated ones.
+fn test_network_cameras_with_camera_hw() {
+    assert!(CameraArray::new(mixed_array()).is_ok());
+}
+
+#[cfg(not(feature = "camera-hw"))]
+#[test]
+/// An array with a network camera is refused as a config the build
+/// cannot run, naming the feature.
+fn test_network_cameras_without_camera_hw() {
+    let error = CameraArray::new(mixed_array())
+        .err()
+        .expect("Network camera accepted without camera-hw");
+    assert!(error.is_config(), "{error}");
+    assert!(error.to_string().contains("camera-hw"), "{error}");
+}
+
+#[cfg(feature = "canbus")]
+#[tokio::test]
+/// The canbus sensors and PDMs fail on the interface rather than on the
+/// build.
+async fn test_canbus_devices_with_canbus() {
+    for error in canbus_sensor_errors() {
+        assert_ne!(error.kind(), io::ErrorKind::Unsupported, "{error}");
+    }
+    assert!(onyx::devices::hardware::pdm::open_interface("nocan0").is_err());
+    let power = CropBedPowerConfig::new(CropBed::Centre, String::from("nocan0"), 17703, None);
+    assert!(CropBedPower::new(power).is_ok());
+}
+
+#[cfg(not(feature = "canbus"))]
+#[tokio::test]
+/// The canbus sensors are refused as unsupported, naming the feature.
+async fn test_canbus_devices_without_canbus() {
+    for error in canbus_sensor_errors() {
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported, "{error}");
+        assert!(error.to_string().contains("canbus feature"), "{error}");
+    }
+}
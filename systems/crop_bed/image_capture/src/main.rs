@@ -1,6 +1,36 @@
 //! Image capture binary.
 use clap::Parser;
-use onyx::components::prelude::*;
+use onyx::{
+    components::prelude::*,
+    error::OnyxError,
+    utils::{
+        config::validate_file,
+        logging::TracingConfig,
+        metrics,
+        tasks::{
+            DEFAULT_SHUTDOWN_TIMEOUT, EXIT_SHUTDOWN_TIMEOUT, EXIT_START_FAILED, EXIT_TASK_FAILED,
+        },
+    },
+};
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    flag,
+};
+use std::{
+    net::TcpListener,
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use tracing::{error, info, warn};
+
+/// How often the main thread checks whether a stop signal has arrived.
+const SIGNAL_POLL: Duration = Duration::from_millis(100);
 
 /// Arguments required for starting the program from the command line.
 #[derive(Parser, Debug)]
@@ -8,14 +38,161 @@ struct Args {
     /// Path to the config file for the Lighting Component.
     #[arg(short, long)]
     filepath: String,
+    /// Port the HMI reads the status of the array and controls it on,
+    /// overrides the status port of the config file.
+    #[arg(short = 's', long, visible_alias = "status-port")]
+    http_port: Option<u16>,
+    /// Port the Prometheus metrics are exported on, not exported when not
+    /// set.
+    #[arg(short, long)]
+    metrics_port: Option<u16>,
+    /// Check the config file and the camera configs it refers to, print the
+    /// problems found and exit, without opening the cameras.
+    #[arg(long)]
+    validate: bool,
+    /// Events printed, a level such as `debug` or `RUST_LOG` style
+    /// directives, `RUST_LOG` then `info` when not set.
+    #[arg(long)]
+    log_level: Option<String>,
+    /// Print each event as a line of json, with the fields of the component
+    /// or device it came from.
+    #[arg(long)]
+    log_json: bool,
+    /// Time in milliseconds between the lines of statistics logged for
+    /// each camera and the array, not logged when not set.
+    #[arg(long)]
+    stats_interval_ms: Option<u64>,
 }
 
-fn main() {
+fn main() -> ExitCode {
     let args = Args::parse();
-    let component = CameraArray::from_config_file(args.filepath);
-    let (_handles, _signal) = CameraArrayController::start(component);
-    #[allow(clippy::empty_loop)]
-    loop {
-        // busy loop implement http listener here which can act as the HMI controller
+    TracingConfig::new(args.log_level.clone(), args.log_json).init();
+    if args.validate {
+        let report = validate_file::<CameraArrayConfig>(&args.filepath);
+        println!("{report}");
+        return if report.is_valid() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+    if let Some(port) = args.metrics_port {
+        match TcpListener::bind(("0.0.0.0", port)) {
+            Ok(listener) => {
+                metrics::spawn(listener);
+            }
+            Err(e) => {
+                error!("Failed to bind metrics port {port}: {e}");
+                return ExitCode::from(EXIT_START_FAILED);
+            }
+        }
+    }
+    let component = match CameraArray::from_config_file(args.filepath) {
+        Ok(component) => component,
+        Err(e) => {
+            error!("{e}");
+            return ExitCode::from(OnyxError::from(e).exit_code());
+        }
+    };
+    let http_port = args.http_port.or(component.status_port());
+    // Listen before starting the cameras, so a stop sent while they are
+    // opening is not lost to the default handler killing the process. A
+    // second signal exits at once, for a stop that hangs.
+    let signalled = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        let registered = flag::register_conditional_shutdown(
+            signal,
+            EXIT_SHUTDOWN_TIMEOUT.into(),
+            signalled.clone(),
+        )
+        .and_then(|_| flag::register(signal, signalled.clone()));
+        if let Err(e) = registered {
+            error!("Failed to listen for signals: {e}");
+            return ExitCode::from(EXIT_START_FAILED);
+        }
+    }
+    let handle = match CameraArrayController::start(component) {
+        Ok(handle) => handle,
+        Err(e) => {
+            error!("{e}");
+            return ExitCode::from(OnyxError::from(e).exit_code());
+        }
+    };
+    let monitor = handle.monitor();
+    info!("Camera array running");
+    // Exits on its own once the array has been asked to stop.
+    let reporter = args
+        .stats_interval_ms
+        .map(|interval_ms| report::spawn(handle.monitor(), Duration::from_millis(interval_ms)));
+
+    // The HMI reads the status and config of the array, restarts cameras
+    // and shuts it down over http, served from a small runtime of its own
+    // while the cameras capture on threads. The server exits once the
+    // array has been asked to stop.
+    // The cameras are already capturing, so they are stopped before
+    // exiting when the port cannot be bound.
+    let mut server = None;
+    if let Some(port) = http_port {
+        match TcpListener::bind(("0.0.0.0", port)) {
+            Ok(listener) => server = Some(http::spawn(listener, handle.monitor())),
+            Err(e) => {
+                error!("Failed to bind http port {port}: {e}");
+                handle.stop();
+                return ExitCode::from(EXIT_START_FAILED);
+            }
+        }
+    }
+
+    // Join the array on a thread of its own, so the wait for it to stop
+    // once a signal arrives can be bounded.
+    let (stopped_tx, stopped_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = stopped_tx.send(handle.wait());
+    });
+    let stopped = loop {
+        match stopped_rx.recv_timeout(SIGNAL_POLL) {
+            Err(RecvTimeoutError::Timeout) if signalled.load(Ordering::Relaxed) => {
+                info!("Camera array received a stop signal, stopping the cameras");
+                monitor.request_stop();
+                break stopped_rx.recv_timeout(DEFAULT_SHUTDOWN_TIMEOUT);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            stopped => break stopped,
+        }
+    };
+    let stats = match stopped {
+        Ok(stats) => stats,
+        Err(RecvTimeoutError::Timeout) => {
+            error!(
+                "Camera array did not stop within {}s",
+                DEFAULT_SHUTDOWN_TIMEOUT.as_secs()
+            );
+            return ExitCode::from(EXIT_SHUTDOWN_TIMEOUT);
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+            error!("Camera array thread panicked while stopping");
+            return ExitCode::from(EXIT_TASK_FAILED);
+        }
+    };
+    info!("Camera array stopped, waiting for the status server");
+    let mut exit_code = ExitCode::SUCCESS;
+    if reporter.is_some_and(|reporter| reporter.join().is_err()) {
+        error!("Stats reporter thread panicked");
+        exit_code = ExitCode::from(EXIT_TASK_FAILED);
+    }
+    if let Some(server) = server {
+        match server.join() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("Status server failed {e}"),
+            Err(_) => {
+                error!("Status server thread panicked");
+                exit_code = ExitCode::from(EXIT_TASK_FAILED);
+            }
+        }
+    }
+    info!("Camera array exited\n{stats}");
+    if stats.threads_panicked > 0 {
+        exit_code = ExitCode::from(EXIT_TASK_FAILED);
     }
+    exit_code
 }
@@ -0,0 +1,89 @@
+//! The image capture binary stopping cleanly on a signal.
+use onyx::{
+    components::prelude::*, devices::software::camera::SimulatedCameraConfig, utils::tasks::DEFAULT_SHUTDOWN_TIMEOUT,
+};
+use std::{
+    io::{BufRead, BufReader},
+    path::Path,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// Start the binary against a config and wait for it to report it is
+/// running.
+///
+/// * `config_file`: config of the camera array.
+fn start(config_file: &Path) -> Child {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_image_capture"))
+        .arg("--filepath")
+        .arg(config_file)
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start image capture");
+    let stdout = child.stdout.take().unwrap();
+    let (running_tx, running_rx) = mpsc::channel();
+    // Keep reading once it is running, so the binary does not fail logging
+    // its shutdown to a closed pipe.
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if line.contains("Camera array running") {
+                let _ = running_tx.send(());
+            }
+        }
+    });
+    running_rx
+        .recv_timeout(Duration::from_secs(10))
+        .expect("Image capture did not start");
+    child
+}
+
+/// Send a signal to the binary and wait for it to exit, failing when it
+/// takes longer than the shutdown timeout allows.
+///
+/// * `child`: running binary.
+/// * `signal`: name of the signal as `kill` takes it.
+fn stop(mut child: Child, signal: &str) -> ExitStatus {
+    let sent = Command::new("kill")
+        .arg(format!("-{signal}"))
+        .arg(child.id().to_string())
+        .status()
+        .expect("Failed to run kill");
+    assert!(sent.success(), "Failed to send SIG{signal}");
+    let deadline = Instant::now() + DEFAULT_SHUTDOWN_TIMEOUT + Duration::from_secs(5);
+    loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            return status;
+        }
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("Image capture did not exit after SIG{signal}");
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+/// SIGTERM from docker and SIGINT from a terminal both stop the cameras
+/// and exit with success.
+fn test_signal_exits_cleanly() {
+    for signal in ["TERM", "INT"] {
+        let dir = std::env::temp_dir().join(format!("image-capture-signals-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = CameraArrayConfig::new(dir.join("images").to_string_lossy().into_owned(), 0)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 0)
+            .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 1);
+        let config_file = dir.join("camera_array.yaml");
+        serde_yaml::to_writer(std::fs::File::create(&config_file).unwrap(), &config).unwrap();
+
+        let status = stop(start(&config_file), signal);
+        assert_eq!(
+            status.code(),
+            Some(0),
+            "Image capture exited with {status} after SIG{signal}"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
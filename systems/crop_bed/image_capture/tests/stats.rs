@@ -0,0 +1,74 @@
+//! The image capture binary logging the statistics of a simulated array.
+use onyx::{components::prelude::*, devices::software::camera::SimulatedCameraConfig};
+use std::{
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// Frames captured over the whole array in a line of statistics.
+///
+/// * `line`: line logged by the binary.
+fn total_captured(line: &str) -> u64 {
+    let (_, total) = line.rsplit_once("total captured ").expect("No totals in the line");
+    total
+        .split_whitespace()
+        .next()
+        .and_then(|frames| frames.parse().ok())
+        .expect("No frame count in the totals")
+}
+
+#[test]
+/// A line of statistics is logged at each interval for every camera and
+/// the array, the frame counts moving between them.
+fn test_stats_lines_move() {
+    let dir = std::env::temp_dir().join(format!("image-capture-stats-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = CameraArrayConfig::new(dir.join("images").to_string_lossy().into_owned(), 0)
+        .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 0)
+        .add_simulated_camera(SimulatedCameraConfig::new(None, 20, 8, 8), 1);
+    let config_file = dir.join("camera_array.yaml");
+    serde_yaml::to_writer(std::fs::File::create(&config_file).unwrap(), &config).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_image_capture"))
+        .arg("--filepath")
+        .arg(&config_file)
+        .arg("--stats-interval-ms")
+        .arg("300")
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start image capture");
+    let stdout = child.stdout.take().unwrap();
+    let (line_tx, line_rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if line.contains("Camera array stats") {
+                let _ = line_tx.send(line);
+            }
+        }
+    });
+    let lines: Vec<String> = (0..3)
+        .map(|_| line_rx.recv_timeout(Duration::from_secs(10)).expect("No stats logged"))
+        .collect();
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    for line in &lines {
+        assert!(
+            line.contains("bed_location_0 captured") && line.contains("bed_location_1 captured"),
+            "{line}"
+        );
+        assert!(line.contains("write failures 0, dropped 0, disk free"), "{line}");
+        assert!(!line.contains("disk free unknown"), "{line}");
+    }
+    let totals: Vec<u64> = lines.iter().map(|line| total_captured(line)).collect();
+    assert!(
+        totals.windows(2).all(|pair| pair[1] > pair[0]),
+        "Frames did not move {totals:?}"
+    );
+    assert!(!lines[2].contains("at 0.0fps"), "{}", lines[2]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
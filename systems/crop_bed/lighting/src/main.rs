@@ -1,6 +1,18 @@
 //! Lighting system binary
 use clap::Parser;
-use onyx::components::prelude::*;
+use onyx::{
+    components::prelude::*,
+    error::OnyxError,
+    utils::{
+        config::validate_file,
+        health,
+        logging::TracingConfig,
+        tasks::{stop_requested, DEFAULT_SHUTDOWN_TIMEOUT, EXIT_SHUTDOWN_TIMEOUT, EXIT_START_FAILED, EXIT_TASK_FAILED},
+        watchdog::{self, ServiceState},
+    },
+};
+use std::{net::TcpListener, process::ExitCode};
+use tracing::{error, info};
 
 /// Arguments required for starting the program from the command line.
 #[derive(Parser, Debug)]
@@ -8,13 +20,105 @@ struct Args {
     /// Path to the config file for the Lighting Component.
     #[arg(short, long)]
     filepath: String,
+    /// Check the config file and the files it refers to, print the problems
+    /// found and exit, without touching the PDMs.
+    #[arg(long)]
+    validate: bool,
+    /// Events printed, a level such as `debug` or `RUST_LOG` style
+    /// directives, `RUST_LOG` then `info` when not set.
+    #[arg(long)]
+    log_level: Option<String>,
+    /// Print each event as a line of json, with the fields of the component
+    /// or device it came from.
+    #[arg(long)]
+    log_json: bool,
+    /// Port the liveness and readiness probes are answered on, at
+    /// `/healthz` and `/readyz`, not answered when not set.
+    #[arg(long)]
+    health_port: Option<u16>,
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     let args = Args::parse();
-    let component = CropBedLighting::from_config_file(args.filepath);
-    CropBedLightingController::start(component).await;
+    TracingConfig::new(args.log_level.clone(), args.log_json).init();
+    if args.validate {
+        let report = validate_file::<CropBedLightingConfig>(&args.filepath);
+        println!("{report}");
+        return if report.is_valid() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+    let component = match CropBedLighting::from_config_file(args.filepath) {
+        Ok(component) => component,
+        Err(e) => {
+            error!("{e}");
+            return ExitCode::from(OnyxError::from(e).exit_code());
+        }
+    };
+    // Answered from before the PDMs are initialised, so the component is
+    // seen to be starting rather than gone.
+    if let Some(port) = args.health_port {
+        let listener = match TcpListener::bind(("0.0.0.0", port)) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to bind health port {port}: {e}");
+                return ExitCode::from(EXIT_START_FAILED);
+            }
+        };
+        let health = component.health();
+        tokio::spawn(async move {
+            if let Err(e) = health::serve(listener, health).await {
+                error!("Health probes on port {port} stopped: {e}");
+            }
+        });
+    }
+    // Reported ready to systemd once the PDMs are initialised, and the
+    // watchdog pinged while the listener goes round.
+    let notifier = watchdog::notify_service(component.health(), vec![("listener", component.listener_progress())]);
+    let mut handle = match CropBedLightingController::start(component).await {
+        Ok(handle) => handle,
+        Err(e) => {
+            error!("{e}");
+            return ExitCode::from(OnyxError::from(e).exit_code());
+        }
+    };
+    info!("Crop bed lighting running");
+    let exit_code = tokio::select! {
+        signal = stop_requested() => {
+            info!("Crop bed lighting received {signal}, shutting down");
+            ExitCode::SUCCESS
+        }
+        stopped = handle.stopped() => {
+            match stopped {
+                Ok(task) => error!("Crop bed lighting {task} stopped unexpectedly, shutting down"),
+                Err(e) => error!("Crop bed lighting task failed: {e}, shutting down"),
+            }
+            ExitCode::from(EXIT_TASK_FAILED)
+        }
+    };
+    // Turn every light off explicitly rather than leave them to the PDM
+    // loss of CAN cutoff.
+    if let Some(notifier) = &notifier {
+        notifier.notify(ServiceState::Stopping);
+    }
+    match tokio::time::timeout(DEFAULT_SHUTDOWN_TIMEOUT, handle.shutdown()).await {
+        Ok(0) => {
+            info!("Crop bed lighting shut down");
+            exit_code
+        }
+        Ok(tasks_failed) => {
+            error!("Crop bed lighting shut down, {tasks_failed} tasks failed");
+            ExitCode::from(EXIT_TASK_FAILED)
+        }
+        Err(_) => {
+            error!(
+                "Crop bed lighting did not shut down within {}s",
+                DEFAULT_SHUTDOWN_TIMEOUT.as_secs()
+            );
+            ExitCode::from(EXIT_SHUTDOWN_TIMEOUT)
+        }
+    }
 }
-
-
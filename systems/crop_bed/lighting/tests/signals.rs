@@ -0,0 +1,118 @@
+//! The lighting binary stopping cleanly on a signal.
+use onyx::{
+    components::prelude::*,
+    devices::{
+        hardware::pdm::{PdmAddress, PdmConfig},
+        software::pdm::{ActuationCause, SimulatedPdm},
+    },
+    utils::tasks::DEFAULT_SHUTDOWN_TIMEOUT,
+};
+use std::{
+    io::{BufRead, BufReader},
+    path::Path,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// Start the binary against a config and wait for it to report it is
+/// running.
+///
+/// * `config_file`: config of the crop bed lighting.
+fn start(config_file: &Path) -> Child {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lighting"))
+        .arg("--filepath")
+        .arg(config_file)
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start lighting");
+    let stdout = child.stdout.take().unwrap();
+    let (running_tx, running_rx) = mpsc::channel();
+    // Keep reading once it is running, so the binary does not fail logging
+    // its shutdown to a closed pipe.
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if line.contains("Crop bed lighting running") {
+                let _ = running_tx.send(());
+            }
+        }
+    });
+    running_rx
+        .recv_timeout(Duration::from_secs(10))
+        .expect("Lighting did not start");
+    child
+}
+
+/// Send a signal to the binary and wait for it to exit, failing when it
+/// takes longer than the shutdown timeout allows.
+///
+/// * `child`: running binary.
+/// * `signal`: name of the signal as `kill` takes it.
+fn stop(mut child: Child, signal: &str) -> ExitStatus {
+    let sent = Command::new("kill")
+        .arg(format!("-{signal}"))
+        .arg(child.id().to_string())
+        .status()
+        .expect("Failed to run kill");
+    assert!(sent.success(), "Failed to send SIG{signal}");
+    let deadline = Instant::now() + DEFAULT_SHUTDOWN_TIMEOUT + Duration::from_secs(5);
+    loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            return status;
+        }
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("Lighting did not exit after SIG{signal}");
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Virtual canbus interface the tests run on.
+fn vcan_interface() -> String {
+    std::env::var("ONYX_VCAN").unwrap_or_else(|_| String::from("vcan0"))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[cfg_attr(not(feature = "vcan_test"), ignore)]
+/// SIGTERM from docker and SIGINT from a terminal both turn the lights of
+/// the simulated PDM off and exit with success.
+async fn test_signal_exits_cleanly() {
+    let dir = std::env::temp_dir().join(format!("lighting-signals-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let pdm_config = PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(Duration::from_millis(200));
+    let pdm_config_file = dir.join("pdm_0.yaml");
+    serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+    let config = CropBedLightingConfig::new(0, vcan_interface(), 17692)
+        .add_pdm_config_file(pdm_config_file, 0)
+        .map_light_channel(7, 0, 3);
+    let config_file = dir.join("lighting.yaml");
+    serde_yaml::to_writer(std::fs::File::create(&config_file).unwrap(), &config).unwrap();
+    let simulated = SimulatedPdm::new(pdm_config)
+        .start(&vcan_interface())
+        .expect("Failed to start simulated PDM");
+
+    let mut sent = 0;
+    for signal in ["TERM", "INT"] {
+        let config_file = config_file.clone();
+        let status = tokio::task::spawn_blocking(move || stop(start(&config_file), signal))
+            .await
+            .unwrap();
+        assert_eq!(
+            status.code(),
+            Some(0),
+            "Lighting exited with {status} after SIG{signal}"
+        );
+        let commands: Vec<_> = simulated
+            .actuations()
+            .into_iter()
+            .filter(|record| record.cause == ActuationCause::Command)
+            .collect();
+        assert!(commands.len() > sent, "Nothing was turned off after SIG{signal}");
+        assert!(commands.last().unwrap().duty_percent.abs() < f32::EPSILON);
+        sent = commands.len();
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,47 @@
+//! Replay a weed message journal to a running spray system on the bench.
+
+use clap::Parser;
+use onyx::components::crop_bed::actuating::power::journal::{read_journal, replay};
+use onyx::messages::control::weed::WeedMessageStatus;
+
+/// Arguments required for starting the program from the command line.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Journals to replay, oldest first, e.g. `weed.jsonl.2 weed.jsonl.1 weed.jsonl`.
+    #[arg(required = true)]
+    journals: Vec<String>,
+    /// Address of the weed message socket of the component.
+    #[arg(short, long, default_value = "127.0.0.1:17652")]
+    address: String,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    let mut entries = Vec::new();
+    for journal in &args.journals {
+        entries.extend(read_journal(journal).unwrap_or_else(|e| panic!("Failed to read journal {journal}: {e}")));
+    }
+    println!("Replaying {} messages to {}", entries.len(), args.address);
+
+    let responses = replay(&entries, &args.address).await.expect("Replay failed");
+    let mut differed = 0;
+    for (entry, response) in entries.iter().zip(&responses) {
+        let status = WeedMessageStatus::of(response);
+        if entry.status != status {
+            differed += 1;
+            println!(
+                "Message received at {} was {:?} in the field, {:?} on replay",
+                entry.received_at, entry.status, status
+            );
+        }
+    }
+    let accepted = responses
+        .iter()
+        .filter(|response| WeedMessageStatus::of(response) == WeedMessageStatus::Accepted)
+        .count();
+    println!(
+        "Replayed {} messages, {accepted} accepted, {differed} handled differently to the field",
+        responses.len()
+    );
+}
@@ -1,20 +1,176 @@
 //! Spray system binary
 
 use clap::Parser;
-use onyx::components::prelude::*;
+use onyx::{
+    components::prelude::{shutdown::ShutdownMode, *},
+    error::OnyxError,
+    messages::schema,
+    utils::{
+        config::validate_file,
+        health,
+        logging::TracingConfig,
+        metrics,
+        tasks::{
+            stop_requested, DEFAULT_SHUTDOWN_TIMEOUT, EXIT_INVALID_CONFIG, EXIT_SHUTDOWN_TIMEOUT, EXIT_START_FAILED,
+            EXIT_TASK_FAILED,
+        },
+        watchdog::{self, ServiceState},
+    },
+};
+use std::{net::TcpListener, path::PathBuf, process::ExitCode};
+use tracing::{error, info};
 
 /// Arguments required for starting the program from the command line.
 #[derive(Parser, Debug)]
 struct Args {
     /// Path to the config file for the Crop Bed Power Component.
+    #[arg(short, long, required_unless_present = "dump_schemas")]
+    filepath: Option<String>,
+    /// Port the Prometheus metrics are exported on, not exported when not
+    /// set.
     #[arg(short, long)]
-    filepath: String,
+    metrics_port: Option<u16>,
+    /// Write the JSON Schema of every message to a directory and exit,
+    /// without starting the component.
+    #[arg(long, value_name = "DIR")]
+    dump_schemas: Option<PathBuf>,
+    /// Check the config file and the files it refers to, print the problems
+    /// found and exit, without touching the PDMs.
+    #[arg(long)]
+    validate: bool,
+    /// Events printed, a level such as `debug` or `RUST_LOG` style
+    /// directives, `RUST_LOG` then `info` when not set.
+    #[arg(long)]
+    log_level: Option<String>,
+    /// Print each event as a line of json, with the fields of the component
+    /// or device it came from.
+    #[arg(long)]
+    log_json: bool,
+    /// Port the liveness and readiness probes are answered on, at
+    /// `/healthz` and `/readyz`, not answered when not set.
+    #[arg(long)]
+    health_port: Option<u16>,
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     let args = Args::parse();
-    let component = CropBedPower::from_config_file(args.filepath);
-    CropBedPowerController::start(component).await;
+    TracingConfig::new(args.log_level.clone(), args.log_json).init();
+    if let Some(dir) = args.dump_schemas {
+        return match schema::export_schemas(dir) {
+            Ok(files) => {
+                for file in files {
+                    println!("Wrote {}", file.display());
+                }
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                error!("Failed to write the schemas: {e}");
+                ExitCode::from(EXIT_START_FAILED)
+            }
+        };
+    }
+    let Some(filepath) = args.filepath else {
+        error!("The config file is required");
+        return ExitCode::from(EXIT_INVALID_CONFIG);
+    };
+    if args.validate {
+        let report = validate_file::<CropBedPowerConfig>(&filepath);
+        println!("{report}");
+        return if report.is_valid() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+    if let Some(port) = args.metrics_port {
+        match TcpListener::bind(("0.0.0.0", port)) {
+            Ok(listener) => {
+                metrics::spawn(listener);
+            }
+            Err(e) => {
+                error!("Failed to bind metrics port {port}: {e}");
+                return ExitCode::from(EXIT_START_FAILED);
+            }
+        }
+    }
+    let component = match CropBedPower::from_config_file(filepath) {
+        Ok(component) => component,
+        Err(e) => {
+            error!("{e}");
+            return ExitCode::from(OnyxError::from(e).exit_code());
+        }
+    };
+    // Answered from before the PDMs are initialised, so the component is
+    // seen to be starting rather than gone.
+    if let Some(port) = args.health_port {
+        let listener = match TcpListener::bind(("0.0.0.0", port)) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to bind health port {port}: {e}");
+                return ExitCode::from(EXIT_START_FAILED);
+            }
+        };
+        let health = component.health();
+        tokio::spawn(async move {
+            if let Err(e) = health::serve(listener, health).await {
+                error!("Health probes on port {port} stopped: {e}");
+            }
+        });
+    }
+    // Reported ready to systemd once the PDMs are initialised, and the
+    // watchdog pinged while the firing task goes round.
+    let notifier = watchdog::notify_service(component.health(), vec![("firing task", component.firing_progress())]);
+    let mut handle = match CropBedPowerController::start(component).await {
+        Ok(handle) => handle,
+        Err(e) => {
+            error!("{e}");
+            return ExitCode::from(OnyxError::from(e).exit_code());
+        }
+    };
+    info!("Crop bed power running");
+    // A task stopping on its own takes the container down with a failure,
+    // so it is restarted rather than left running without firing. A signal
+    // shuts down in the mode of the config, an emergency stop from /estop
+    // has already turned every channel off and stops the tasks, so it is
+    // checked first.
+    let (mut exit_code, mode) = tokio::select! {
+        biased;
+        () = handle.emergency_stopped() => {
+            error!("Crop bed power emergency stopped, shutting down");
+            (ExitCode::SUCCESS, Some(ShutdownMode::Immediate))
+        }
+        signal = stop_requested() => {
+            info!("Crop bed power received {signal}, shutting down");
+            (ExitCode::SUCCESS, None)
+        }
+        stopped = handle.stopped() => {
+            match stopped {
+                Ok(task) => error!("Crop bed power {task} stopped unexpectedly, shutting down"),
+                Err(e) => error!("Crop bed power task failed: {e}, shutting down"),
+            }
+            (ExitCode::from(EXIT_TASK_FAILED), None)
+        }
+    };
+    // Turn every channel off explicitly rather than leave the solenoids to
+    // the PDM loss of CAN cutoff.
+    if let Some(notifier) = &notifier {
+        notifier.notify(ServiceState::Stopping);
+    }
+    match handle.shutdown_within(mode, DEFAULT_SHUTDOWN_TIMEOUT).await {
+        Some(status) => {
+            info!("Crop bed power shut down\n{status}");
+            if status.tasks_failed > 0 {
+                exit_code = ExitCode::from(EXIT_TASK_FAILED);
+            }
+            exit_code
+        }
+        None => {
+            error!(
+                "Crop bed power did not shut down within {}s",
+                DEFAULT_SHUTDOWN_TIMEOUT.as_secs()
+            );
+            ExitCode::from(EXIT_SHUTDOWN_TIMEOUT)
+        }
+    }
 }
-
@@ -0,0 +1,122 @@
+//! The probes of the spray binary following the simulated PDM.
+use onyx::{
+    components::prelude::*,
+    devices::{
+        hardware::pdm::{PdmAddress, PdmConfig, PdmStatus},
+        software::pdm::SimulatedPdm,
+    },
+    utils::location::CropBed,
+};
+use std::{
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// Port the probes are answered on.
+const HEALTH_PORT: u16 = 17696;
+
+/// Virtual canbus interface the tests run on.
+fn vcan_interface() -> String {
+    std::env::var("ONYX_VCAN").unwrap_or_else(|_| String::from("vcan0"))
+}
+
+/// Status code a probe answers with.
+///
+/// * `probe`: path of the probe, i.e. `/readyz`.
+async fn probe(probe: &str) -> Option<u16> {
+    let response = reqwest::get(format!("http://127.0.0.1:{HEALTH_PORT}{probe}"))
+        .await
+        .ok()?;
+    Some(response.status().as_u16())
+}
+
+/// Wait for a probe to answer with a status code, failing after five
+/// seconds.
+///
+/// * `path`: path of the probe, i.e. `/readyz`.
+/// * `status`: status code waited for.
+async fn wait_for(path: &str, status: u16) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while probe(path).await != Some(status) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("{path} did not answer {status}"));
+}
+
+/// Stop the binary with SIGTERM and wait for it to exit.
+///
+/// * `child`: running binary.
+fn stop(mut child: Child) {
+    Command::new("kill")
+        .arg("-TERM")
+        .arg(child.id().to_string())
+        .status()
+        .expect("Failed to run kill");
+    child.wait().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[cfg_attr(not(feature = "vcan_test"), ignore)]
+/// The binary is ready once it has started against the simulated PDM, not
+/// ready while the PDM reports a fault and ready again once the PDM has
+/// been reinitialised after a loss of CAN, alive throughout.
+async fn test_probes_follow_pdm_faults() {
+    let dir = std::env::temp_dir().join(format!("spray-health-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let pdm_config = PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(Duration::from_millis(200));
+    let pdm_config_file = dir.join("pdm_0.yaml");
+    serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+    let config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), 17695, None)
+        .add_pdm_config_file(pdm_config_file, 0);
+    let config_file = dir.join("power.yaml");
+    serde_yaml::to_writer(std::fs::File::create(&config_file).unwrap(), &config).unwrap();
+    let simulated = SimulatedPdm::new(pdm_config)
+        .start(&vcan_interface())
+        .expect("Failed to start simulated PDM");
+
+    let child = Command::new(env!("CARGO_BIN_EXE_spray"))
+        .arg("--filepath")
+        .arg(&config_file)
+        .arg("--health-port")
+        .arg(HEALTH_PORT.to_string())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to start spray");
+    wait_for("/readyz", 200).await;
+    let report: serde_json::Value = reqwest::get(format!("http://127.0.0.1:{HEALTH_PORT}/readyz"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        report,
+        serde_json::json!({"panicked": [], "canbus_open": true, "listening": true, "pdms": {"0": true}})
+    );
+
+    let over_temperature = PdmStatus {
+        module_over_temperature: true,
+        ..PdmStatus::default()
+    };
+    simulated.report_status(over_temperature).await.unwrap();
+    wait_for("/readyz", 503).await;
+    assert_eq!(probe("/healthz").await, Some(200));
+
+    let configured = simulated.configurations_received();
+    let loss_of_can = PdmStatus {
+        loss_of_can: true,
+        ..PdmStatus::default()
+    };
+    simulated.report_status(loss_of_can).await.unwrap();
+    wait_for("/readyz", 200).await;
+    assert!(
+        simulated.configurations_received() > configured,
+        "PDM was not reinitialised"
+    );
+
+    tokio::task::spawn_blocking(move || stop(child)).await.unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,128 @@
+//! Camera bring up binary, finding the GigE cameras on the network and
+//! checking each against its config the way the components build them.
+use clap::{Parser, Subcommand};
+use onyx::{
+    devices::hardware::camera::{
+        diagnostics::{check_camera, discover, discovery_table, snap},
+        OnyxCamera, OnyxCameraConfig,
+    },
+    utils::{config::validate_file, logging::TracingConfig},
+};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+use tracing::error;
+
+/// Arguments required for starting the program from the command line.
+#[derive(Parser, Debug)]
+struct Args {
+    /// What to do with the cameras.
+    #[command(subcommand)]
+    command: Command,
+    /// Events printed to stderr, a level such as `debug` or `RUST_LOG`
+    /// style directives, `RUST_LOG` then `info` when not set.
+    #[arg(long, global = true)]
+    log_level: Option<String>,
+    /// Print each event as a line of json.
+    #[arg(long, global = true)]
+    log_json: bool,
+}
+
+/// Commands run against the cameras.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the GigE devices answering on the network, failing when none
+    /// answer.
+    Discover,
+    /// Connect to the camera of a config and check the device allows its
+    /// frame rate, region and exposure bounds, printing the packet size
+    /// and link speed.
+    Check {
+        /// Path to the config file of the camera.
+        #[arg(short, long)]
+        config: PathBuf,
+    },
+    /// Build the camera of a config as the camera array does and write one
+    /// frame from it.
+    Snap {
+        /// Path to the config file of the camera.
+        #[arg(short, long)]
+        config: PathBuf,
+        /// Image file written, the format following the extension.
+        #[arg(short, long)]
+        out: PathBuf,
+        /// Time in milliseconds waited for the frame.
+        #[arg(long, default_value_t = 5000)]
+        timeout_ms: u64,
+    },
+}
+
+/// Load a camera config, refusing one the components would refuse before
+/// any camera is opened.
+///
+/// * `path`: path to the config file.
+fn load_config(path: &Path) -> Result<OnyxCameraConfig, String> {
+    let report = validate_file::<OnyxCameraConfig>(path);
+    if !report.is_valid() {
+        return Err(report.to_string());
+    }
+    OnyxCameraConfig::try_from_file(path).map_err(|e| e.to_string())
+}
+
+/// Run a command against the cameras, returning whether it succeeded.
+///
+/// * `args`: arguments given on the command line.
+fn run(args: Args) -> Result<bool, String> {
+    match args.command {
+        Command::Discover => {
+            let cameras = discover();
+            println!("{}", discovery_table(&cameras));
+            Ok(!cameras.is_empty())
+        }
+        Command::Check { config } => {
+            let report = check_camera(&load_config(&config)?);
+            println!("{report}");
+            Ok(report.passed())
+        }
+        Command::Snap {
+            config,
+            out,
+            timeout_ms,
+        } => {
+            // Built by the component code, so a config the camera array
+            // would fail on gives the same error here.
+            let mut camera = OnyxCamera::new(load_config(&config)?).map_err(|e| e.to_string())?;
+            let image = snap(&mut camera, Duration::from_millis(timeout_ms))
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("No frame from the camera within {timeout_ms}ms"))?;
+            image
+                .save(&out)
+                .map_err(|e| format!("Failed to write {}: {e}", out.display()))?;
+            println!(
+                "Wrote a {}x{} frame to {}",
+                image.width(),
+                image.height(),
+                out.display()
+            );
+            Ok(true)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    // Stdout is left to what the commands print.
+    TracingConfig::new(args.log_level.clone(), args.log_json)
+        .with_stderr()
+        .init();
+    match run(args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            error!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
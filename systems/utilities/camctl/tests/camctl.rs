@@ -0,0 +1,78 @@
+//! The camera bring up tool against configs and the crop bed cameras.
+use onyx::{devices::hardware::camera::OnyxCameraConfig, utils::paths::repo_relative};
+use serial_test::serial;
+use std::{
+    net::Ipv4Addr,
+    path::PathBuf,
+    process::{Command, Output},
+};
+use uuid::Uuid;
+
+/// Config of the first crop bed camera in the repository.
+fn camera_0() -> PathBuf {
+    repo_relative("config/devices/crop_bed/camera_0.yaml").unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Run the tool to completion.
+///
+/// * `args`: subcommand and its arguments.
+fn camctl(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_onyx-camctl"))
+        .args(args)
+        .output()
+        .expect("Failed to run onyx-camctl")
+}
+
+#[test]
+/// A config the components would refuse is refused with its problems
+/// before any camera is opened.
+fn test_invalid_config_refused() {
+    let dir = std::env::temp_dir().join(format!("camctl-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config_file = dir.join("camera.yaml");
+    let config = OnyxCameraConfig::new(Ipv4Addr::UNSPECIFIED, 0);
+    serde_yaml::to_writer(std::fs::File::create(&config_file).unwrap(), &config).unwrap();
+
+    let output = camctl(&["check", "--config", config_file.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("fps") && stderr.contains("ip_address"), "{stderr}");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg_attr(not(feature = "hardware_test"), ignore)]
+#[test]
+#[serial]
+/// The first crop bed camera is found on the network and passes its checks.
+fn test_discover_and_check() {
+    let config = OnyxCameraConfig::try_from_file(camera_0()).unwrap();
+    let discover = camctl(&["discover"]);
+    assert!(discover.status.success(), "{discover:?}");
+    let table = String::from_utf8(discover.stdout).unwrap();
+    assert!(table.contains(&config.ip_address().to_string()), "{table}");
+
+    let check = camctl(&["check", "--config", camera_0().to_str().unwrap()]);
+    let report = String::from_utf8(check.stdout).unwrap();
+    assert!(check.status.success(), "{report}");
+    assert!(report.contains("packet size"), "{report}");
+}
+
+#[cfg_attr(not(feature = "hardware_test"), ignore)]
+#[test]
+#[serial]
+/// A frame from the first crop bed camera is written as a png.
+fn test_snap() {
+    let dir = std::env::temp_dir().join(format!("camctl-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("camera_0.png");
+    let snap = camctl(&[
+        "snap",
+        "--config",
+        camera_0().to_str().unwrap(),
+        "--out",
+        out.to_str().unwrap(),
+    ]);
+    assert!(snap.status.success(), "{snap:?}");
+    assert!(std::fs::metadata(&out).unwrap().len() > 0);
+    std::fs::remove_dir_all(dir).unwrap();
+}
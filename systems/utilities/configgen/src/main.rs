@@ -0,0 +1,192 @@
+//! Config generator binary.
+use clap::{Parser, Subcommand};
+use onyx::{
+    components::prelude::*,
+    devices::hardware::{
+        camera::OnyxCameraConfig,
+        pdm::{ChannelLoad, PdmAddress, PdmConfig},
+    },
+    utils::{
+        configgen::{
+            parse_channel_map, to_yaml, write_canonical_configs, write_config, DEFAULT_FPS, DEFAULT_IMAGE_PATH,
+        },
+        location::CropBed,
+    },
+};
+use serde::Serialize;
+use std::{collections::HashMap, net::Ipv4Addr, path::PathBuf};
+
+/// Arguments required for starting the program from the command line.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Config to generate.
+    #[command(subcommand)]
+    command: Command,
+    /// File the config is written to, printed when not set.
+    #[arg(short, long, global = true)]
+    output: Option<PathBuf>,
+}
+
+/// Configs the generator writes. Channel maps are written as comma
+/// separated `channels=channels@pdm` entries, e.g. `1-12=24-13@1`.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Config of a crop bed camera.
+    Camera {
+        /// Network address of the camera.
+        #[arg(long)]
+        ip: Ipv4Addr,
+        /// Frames per second captured.
+        #[arg(long, default_value_t = DEFAULT_FPS)]
+        fps: u32,
+        /// Location of the camera on the crop bed.
+        #[arg(long)]
+        bed_position: u8,
+    },
+    /// Config of a PDM with every channel configured for its load.
+    Pdm {
+        /// Pin strapped source address, i.e. 30 or pdm_30.
+        #[arg(long)]
+        address: PdmAddress,
+        /// Location of the PDM on the crop bed.
+        #[arg(long)]
+        bed_position: u8,
+        /// What the channels drive, solenoid or light.
+        #[arg(long, default_value_t = ChannelLoad::Solenoid)]
+        load: ChannelLoad,
+    },
+    /// Config of the camera array of a crop bed.
+    CameraArray {
+        /// Crop bed module, i.e. left_boom or the legacy id.
+        #[arg(long)]
+        crop_bed: CropBed,
+        /// Camera config files, at bed positions in the order given.
+        #[arg(long = "camera", required = true)]
+        cameras: Vec<PathBuf>,
+        /// Directory the images are saved to.
+        #[arg(long, default_value = DEFAULT_IMAGE_PATH)]
+        image_path: String,
+    },
+    /// Config of the crop bed power.
+    Power {
+        /// Crop bed module, i.e. left_boom or the legacy id.
+        #[arg(long)]
+        crop_bed: CropBed,
+        /// Canbus interface the PDMs are on, i.e. can0.
+        #[arg(long)]
+        canbus: String,
+        /// Port the weed messages are received on.
+        #[arg(long)]
+        port: i32,
+        /// PDM config files, at bed positions in the order given.
+        #[arg(long = "pdm", required = true)]
+        pdms: Vec<PathBuf>,
+        /// Crop bed channel each weed message channel fires, and its PDM.
+        #[arg(long, value_parser = parse_channel_map)]
+        channel_map: Option<HashMap<u8, (u8, u8)>>,
+    },
+    /// Config of the crop bed lighting.
+    Lighting {
+        /// Crop bed module, i.e. left_boom or the legacy id.
+        #[arg(long)]
+        crop_bed: CropBed,
+        /// Canbus interface the PDMs are on, i.e. can3.
+        #[arg(long)]
+        canbus: String,
+        /// Port the light messages are received on.
+        #[arg(long)]
+        port: i32,
+        /// PDM config files, at bed positions in the order given.
+        #[arg(long = "pdm", required = true)]
+        pdms: Vec<PathBuf>,
+        /// PDM channel each light message channel drives, and its PDM.
+        #[arg(long, value_parser = parse_channel_map)]
+        light_channels: Option<HashMap<u8, (u8, u8)>>,
+        /// Accept light messages for any crop bed.
+        #[arg(long)]
+        any_bed: bool,
+    },
+    /// Every config file kept in the onyx crate, as the machine is wired.
+    Canonical {
+        /// Directory standing in for the onyx crate.
+        #[arg(long)]
+        root: PathBuf,
+    },
+}
+
+/// Write a config to the output file, or print it when there is none.
+///
+/// * `output`: file the config is written to.
+/// * `config`: config generated.
+fn emit<T: Serialize>(output: Option<PathBuf>, config: &T) {
+    match output {
+        Some(path) => {
+            write_config(&path, config).unwrap_or_else(|e| panic!("Failed to write {}: {e}", path.display()));
+            println!("Wrote {}", path.display());
+        }
+        None => print!("{}", to_yaml(config)),
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    match args.command {
+        Command::Camera { ip, fps, bed_position } => {
+            emit(args.output, &OnyxCameraConfig::crop_bed(ip, fps, bed_position));
+        }
+        Command::Pdm {
+            address,
+            bed_position,
+            load,
+        } => emit(args.output, &PdmConfig::new(address, bed_position).with_channels(load)),
+        Command::CameraArray {
+            crop_bed,
+            cameras,
+            image_path,
+        } => {
+            let config = cameras.iter().zip(0..).fold(
+                CameraArrayConfig::new(image_path, crop_bed),
+                |config, (camera, bed_position)| config.add_camera_config_file(camera, bed_position),
+            );
+            emit(args.output, &config);
+        }
+        Command::Power {
+            crop_bed,
+            canbus,
+            port,
+            pdms,
+            channel_map,
+        } => {
+            let config = pdms.iter().zip(0..).fold(
+                CropBedPowerConfig::new(crop_bed, canbus, port, channel_map),
+                |config, (pdm, pdm_id)| config.add_pdm_config_file(pdm, pdm_id),
+            );
+            emit(args.output, &config);
+        }
+        Command::Lighting {
+            crop_bed,
+            canbus,
+            port,
+            pdms,
+            light_channels,
+            any_bed,
+        } => {
+            let config = pdms.iter().zip(0..).fold(
+                CropBedLightingConfig::new(crop_bed, canbus, port),
+                |config, (pdm, pdm_id)| config.add_pdm_config_file(pdm, pdm_id),
+            );
+            let config = light_channels
+                .into_iter()
+                .flatten()
+                .fold(config, |config, (channel, (pdm_channel, pdm_id))| {
+                    config.map_light_channel(channel, pdm_id, pdm_channel)
+                });
+            emit(args.output, &if any_bed { config.with_any_bed() } else { config });
+        }
+        Command::Canonical { root } => {
+            for path in write_canonical_configs(&root).expect("Failed to write the configs") {
+                println!("Wrote {}", path.display());
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! The config generator writing the config files kept in the onyx crate.
+use onyx::utils::{configgen::canonical_configs, paths::repo_relative};
+use std::{
+    path::Path,
+    process::{Command, Output},
+};
+use uuid::Uuid;
+
+/// Run the generator to completion.
+///
+/// * `args`: subcommand and its arguments.
+fn generate(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_configgen"))
+        .args(args)
+        .output()
+        .expect("Failed to run configgen")
+}
+
+/// Contents of a config file kept in the onyx crate.
+///
+/// * `path`: path relative to the crate.
+fn golden(path: &str) -> String {
+    std::fs::read_to_string(repo_relative(path).unwrap_or_else(|e| panic!("{e}"))).unwrap()
+}
+
+#[test]
+/// A camera and a PDM printed from the command line are the files kept in
+/// the crate.
+fn test_print_device_configs() {
+    let camera = generate(&["camera", "--ip", "169.254.8.13", "--bed-position", "1"]);
+    assert!(camera.status.success(), "{camera:?}");
+    assert_eq!(
+        String::from_utf8(camera.stdout).unwrap(),
+        golden("config/devices/crop_bed/camera_3.yaml")
+    );
+
+    let pdm = generate(&["pdm", "--address", "pdm_30", "--bed-position", "0", "--load", "light"]);
+    assert!(pdm.status.success(), "{pdm:?}");
+    assert_eq!(
+        String::from_utf8(pdm.stdout).unwrap(),
+        golden("config/devices/crop_bed/pdm_utilities.yaml")
+    );
+}
+
+#[test]
+/// Component configs written from the command line, channel maps and all,
+/// are the files kept in the crate.
+fn test_write_component_configs() {
+    let dir = std::env::temp_dir().join(format!("onyx-configgen-cli-{}", Uuid::new_v4()));
+    let power_file = dir.join("power/crop_bed_power_2.yaml");
+    let power = generate(&[
+        "power",
+        "--crop-bed",
+        "right_boom",
+        "--canbus",
+        "can2",
+        "--port",
+        "17652",
+        "--pdm",
+        "./config/devices/crop_bed/pdm_0.yaml",
+        "--pdm",
+        "./config/devices/crop_bed/pdm_1.yaml",
+        "--channel-map",
+        "1-12=24-13@1,13-24=12-1@0",
+        "--output",
+        power_file.to_str().unwrap(),
+    ]);
+    assert!(power.status.success(), "{power:?}");
+    assert_eq!(
+        std::fs::read_to_string(&power_file).unwrap(),
+        golden("config/components/crop_bed/actuating/power/crop_bed_power_2.yaml")
+    );
+
+    let lighting_file = dir.join("crop_bed_lighting.yaml");
+    let lighting = generate(&[
+        "lighting",
+        "--crop-bed",
+        "0",
+        "--canbus",
+        "can3",
+        "--port",
+        "17653",
+        "--pdm",
+        "./config/devices/crop_bed/pdm_utilities.yaml",
+        "--light-channels",
+        "1-12=1-12@0",
+        "--any-bed",
+        "-o",
+        lighting_file.to_str().unwrap(),
+    ]);
+    assert!(lighting.status.success(), "{lighting:?}");
+    assert_eq!(
+        std::fs::read_to_string(&lighting_file).unwrap(),
+        golden("config/components/crop_bed/actuating/lighting/crop_bed_lighting.yaml")
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+/// The canonical configs are all written under the root given.
+fn test_write_canonical_configs() {
+    let root = std::env::temp_dir().join(format!("onyx-configgen-canonical-{}", Uuid::new_v4()));
+    let output = generate(&["canonical", "--root", root.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+    for config in canonical_configs() {
+        let written = std::fs::read_to_string(root.join(&config.path)).unwrap();
+        assert_eq!(written, config.yaml, "{}", config.path.display());
+        assert_eq!(written, golden(&config.path.to_string_lossy()));
+    }
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+/// A channel map that cannot be read is refused before anything is
+/// written.
+fn test_reject_bad_channel_map() {
+    let dir = std::env::temp_dir().join(format!("onyx-configgen-bad-{}", Uuid::new_v4()));
+    let output_file = dir.join("power.yaml");
+    let output = generate(&[
+        "power",
+        "--crop-bed",
+        "centre",
+        "--canbus",
+        "can1",
+        "--port",
+        "17651",
+        "--pdm",
+        "pdm_0.yaml",
+        "--channel-map",
+        "1-12=13-23@1",
+        "-o",
+        output_file.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("maps 12 channels onto 11"));
+    assert!(!Path::new(&output_file).exists());
+}
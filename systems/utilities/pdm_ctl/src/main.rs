@@ -0,0 +1,223 @@
+//! PDM bench control binary, driving the channels of one PDM without any
+//! of the components, over the same wrapper and shared canbus socket.
+use clap::{Parser, Subcommand};
+use onyx::{
+    devices::hardware::pdm::{frames::CHANNEL_COUNT, open_interface, Pdm, PdmConfig},
+    utils::logging::TracingConfig,
+};
+use std::{path::PathBuf, process::ExitCode, time::Duration};
+use tokio::time::Instant;
+use tracing::error;
+
+/// Longest time a channel is left without a command while it is held on,
+/// well inside the second after which the PDM applies its loss of
+/// communication outputs.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Arguments required for starting the program from the command line.
+#[derive(Parser, Debug)]
+struct Args {
+    /// What to do with the PDM.
+    #[command(subcommand)]
+    command: Command,
+    /// Canbus interface the PDM is on, i.e. can0.
+    #[arg(short, long, default_value = "can0")]
+    interface: String,
+    /// Path to the config file of the PDM.
+    #[arg(short, long)]
+    config: PathBuf,
+    /// Events printed to stderr, a level such as `debug` or `RUST_LOG`
+    /// style directives, `RUST_LOG` then `info` when not set.
+    #[arg(long, global = true)]
+    log_level: Option<String>,
+    /// Print each event as a line of json.
+    #[arg(long, global = true)]
+    log_json: bool,
+}
+
+/// Commands sent to the PDM. Commands other than init expect the PDM to
+/// have been configured, by init or a component.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Send the configuration of every channel and read it back.
+    Init,
+    /// Turn channels on at full duty cycle.
+    On {
+        /// Channels from 1, comma separated.
+        #[arg(long, required = true, value_delimiter = ',', value_parser = channel_parser())]
+        channel: Vec<u8>,
+        /// Time in milliseconds the channels are held on before they are
+        /// turned off, left to the PDM when not set.
+        #[arg(long)]
+        hold_ms: Option<u64>,
+    },
+    /// Turn channels off.
+    Off {
+        /// Channels from 1, comma separated.
+        #[arg(long, required = true, value_delimiter = ',', value_parser = channel_parser())]
+        channel: Vec<u8>,
+    },
+    /// Set channels to a duty cycle.
+    Pwm {
+        /// Channels from 1, comma separated.
+        #[arg(long, required = true, value_delimiter = ',', value_parser = channel_parser())]
+        channel: Vec<u8>,
+        /// Duty cycle in percent.
+        #[arg(long, value_parser = parse_duty)]
+        value: f32,
+        /// Time in milliseconds the channels are held before they are
+        /// turned off, left to the PDM when not set.
+        #[arg(long)]
+        hold_ms: Option<u64>,
+    },
+    /// Listen for the fault state and current feedback the PDM broadcasts
+    /// and print the latest.
+    Status {
+        /// Time in milliseconds listened for.
+        #[arg(long, default_value_t = 1000)]
+        listen_ms: u64,
+    },
+    /// Turn the configured channels on one at a time, to walk the boom.
+    Chase {
+        /// Time in milliseconds each channel is on.
+        #[arg(long, default_value_t = 500)]
+        dwell_ms: u64,
+        /// Duty cycle in percent.
+        #[arg(long, default_value_t = 100.0, value_parser = parse_duty)]
+        value: f32,
+        /// Number of walks along the channels.
+        #[arg(long, default_value_t = 1)]
+        cycles: u32,
+    },
+}
+
+/// Channels of an ix-3212.
+fn channel_parser() -> clap::builder::RangedI64ValueParser<u8> {
+    clap::value_parser!(u8).range(1..=i64::from(CHANNEL_COUNT))
+}
+
+/// Read a duty cycle, which must be from 0 to 100 percent.
+///
+/// * `value`: value given on the command line.
+fn parse_duty(value: &str) -> Result<f32, String> {
+    let duty: f32 = value.parse().map_err(|e| format!("{e}"))?;
+    if (0.0..=100.0).contains(&duty) {
+        Ok(duty)
+    } else {
+        Err(format!("{duty} is outside 0 to 100 percent"))
+    }
+}
+
+/// Set channels to a duty cycle and print what was sent.
+///
+/// * `pdm`: PDM attached to the interface.
+/// * `channels`: channel numbers.
+/// * `duty`: duty cycle in percent.
+async fn actuate(pdm: &Pdm, channels: &[u8], duty: f32) {
+    pdm.actuate_channels(channels.to_vec(), duty).await;
+    println!("PDM {} channels {channels:?} at {duty}%", pdm.address());
+}
+
+/// Keep channels at a duty cycle for a time then turn them off, commanding
+/// them again so the PDM does not take the controller to be gone.
+///
+/// * `pdm`: PDM attached to the interface.
+/// * `channels`: channel numbers.
+/// * `duty`: duty cycle in percent.
+/// * `hold`: time the channels are held.
+async fn hold(pdm: &Pdm, channels: &[u8], duty: f32, hold: Duration) {
+    actuate(pdm, channels, duty).await;
+    let until = Instant::now() + hold;
+    while Instant::now() + KEEP_ALIVE_INTERVAL < until {
+        tokio::time::sleep(KEEP_ALIVE_INTERVAL).await;
+        pdm.actuate_channels(channels.to_vec(), duty).await;
+    }
+    tokio::time::sleep_until(until).await;
+    actuate(pdm, channels, 0.0).await;
+}
+
+/// Run a command against the PDM, returning whether it succeeded.
+///
+/// * `args`: arguments given on the command line.
+async fn run(args: Args) -> Result<bool, String> {
+    let config = PdmConfig::try_from_file(&args.config).map_err(|e| e.to_string())?;
+    let channels = config.channels();
+    let interface = open_interface(&args.interface).map_err(|e| e.to_string())?;
+    let mut pdm = Pdm::new(config);
+    if !matches!(args.command, Command::Init) {
+        pdm.attach(interface.clone());
+    }
+    match args.command {
+        Command::Init => {
+            pdm.initialise(interface).await.map_err(|e| e.to_string())?;
+            let mismatches = pdm.verify_configuration().await.map_err(|e| e.to_string())?;
+            for mismatch in &mismatches {
+                println!("PDM {} does not match its config, {mismatch}", pdm.address());
+            }
+            if mismatches.is_empty() {
+                println!("PDM {} configured on {}", pdm.address(), args.interface);
+            }
+            return Ok(mismatches.is_empty());
+        }
+        Command::On { channel, hold_ms } => match hold_ms {
+            Some(hold_ms) => hold(&pdm, &channel, 100.0, Duration::from_millis(hold_ms)).await,
+            None => actuate(&pdm, &channel, 100.0).await,
+        },
+        Command::Off { channel } => actuate(&pdm, &channel, 0.0).await,
+        Command::Pwm {
+            channel,
+            value,
+            hold_ms,
+        } => match hold_ms {
+            Some(hold_ms) => hold(&pdm, &channel, value, Duration::from_millis(hold_ms)).await,
+            None => actuate(&pdm, &channel, value).await,
+        },
+        Command::Status { listen_ms } => {
+            // The status and feedback are read on a socket of their own, as
+            // the components do.
+            let _status_rx = pdm.watch_status(&args.interface).map_err(|e| e.to_string())?;
+            tokio::time::sleep(Duration::from_millis(listen_ms)).await;
+            println!(
+                "{}",
+                serde_json::to_string(&pdm.status()).expect("Failed to serialise status")
+            );
+            for channel in channels {
+                if let Some(feedback) = pdm.channel_feedback(channel) {
+                    println!(
+                        "channel {channel}: {:.2} A, fault flags {:#06b}",
+                        feedback.amps, feedback.status_flags
+                    );
+                }
+            }
+        }
+        Command::Chase {
+            dwell_ms,
+            value,
+            cycles,
+        } => {
+            for _ in 0..cycles {
+                for channel in &channels {
+                    hold(&pdm, &[*channel], value, Duration::from_millis(dwell_ms)).await;
+                }
+            }
+        }
+    }
+    Ok(true)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    // Stdout is left to what the commands print.
+    TracingConfig::new(args.log_level.clone(), args.log_json)
+        .with_stderr()
+        .init();
+    match run(args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            error!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! The bench tool driving the simulated PDM on a virtual canbus.
+use onyx::devices::{
+    hardware::pdm::{frames::CHANNEL_COUNT, ChannelLoad, PdmAddress, PdmConfig},
+    software::pdm::{ActuationCause, SimulatedPdm, SimulatedPdmHandle},
+};
+use serial_test::serial;
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// Virtual canbus interface the tests run on.
+fn vcan_interface() -> String {
+    std::env::var("ONYX_VCAN").unwrap_or_else(|_| String::from("vcan0"))
+}
+
+/// Config of the PDM on the bench, with every channel configured.
+fn bench_config() -> PdmConfig {
+    PdmConfig::new(PdmAddress::Pdm30, 0)
+        .with_channels(ChannelLoad::Solenoid)
+        .with_response_timeout(Duration::from_millis(200))
+}
+
+/// Write the config to a directory of its own and start the simulated PDM
+/// answering for it.
+fn start_simulated() -> (PathBuf, SimulatedPdmHandle) {
+    let dir = std::env::temp_dir().join(format!("pdm-ctl-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config_file = dir.join("pdm_0.yaml");
+    serde_yaml::to_writer(std::fs::File::create(&config_file).unwrap(), &bench_config()).unwrap();
+    let simulated = SimulatedPdm::new(bench_config())
+        .start(&vcan_interface())
+        .expect("Failed to start simulated PDM");
+    (dir, simulated)
+}
+
+/// Run the tool to completion, failing when it does not succeed.
+///
+/// * `dir`: directory holding the config.
+/// * `args`: subcommand and its arguments.
+async fn pdm_ctl(dir: &Path, args: &[&str]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_pdm_ctl"));
+    command
+        .arg("--interface")
+        .arg(vcan_interface())
+        .arg("--config")
+        .arg(dir.join("pdm_0.yaml"))
+        .args(args);
+    let output = tokio::task::spawn_blocking(move || command.output().expect("Failed to run pdm_ctl"))
+        .await
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    output
+}
+
+/// Duty cycles the simulated PDM was commanded to on a channel, in order.
+///
+/// * `simulated`: running simulated PDM.
+/// * `channel`: channel number.
+fn commanded(simulated: &SimulatedPdmHandle, channel: u8) -> Vec<f32> {
+    simulated
+        .actuations_of(channel)
+        .into_iter()
+        .filter(|record| record.cause == ActuationCause::Command)
+        .map(|record| record.duty_percent)
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+#[cfg_attr(not(feature = "vcan_test"), ignore)]
+/// The PDM is configured by init, its channels set by on, pwm and off, and
+/// its status printed as json.
+async fn test_init_and_actuate() {
+    let (dir, simulated) = start_simulated();
+    let init = pdm_ctl(&dir, &["init"]).await;
+    assert!(simulated.configurations_received() > 0);
+    assert!(String::from_utf8(init.stdout).unwrap().contains("configured"));
+
+    pdm_ctl(&dir, &["pwm", "--channel", "3", "--value", "40"]).await;
+    assert_eq!(simulated.output(3), Some(40.0));
+    pdm_ctl(&dir, &["on", "--channel", "1,2"]).await;
+    assert_eq!((simulated.output(1), simulated.output(2)), (Some(100.0), Some(100.0)));
+    pdm_ctl(&dir, &["off", "--channel", "1,2,3"]).await;
+    assert_eq!(simulated.output(3), Some(0.0));
+    pdm_ctl(&dir, &["on", "--channel", "4", "--hold-ms", "100"]).await;
+    assert_eq!(commanded(&simulated, 4).last(), Some(&0.0));
+
+    let status = pdm_ctl(&dir, &["status", "--listen-ms", "100"]).await;
+    let status = String::from_utf8(status.stdout).unwrap();
+    let status: serde_json::Value = serde_json::from_str(status.lines().next().unwrap()).unwrap();
+    assert_eq!(status["module_over_temperature"], false);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+#[cfg_attr(not(feature = "vcan_test"), ignore)]
+/// A chase turns each channel on and off again in turn, and a channel out
+/// of range is refused without sending anything.
+async fn test_chase() {
+    let (dir, simulated) = start_simulated();
+    pdm_ctl(&dir, &["init"]).await;
+    pdm_ctl(&dir, &["chase", "--dwell-ms", "20", "--value", "60"]).await;
+    for channel in 1..=CHANNEL_COUNT {
+        assert_eq!(commanded(&simulated, channel), [60.0, 0.0], "channel {channel}");
+    }
+    let turned_on: Vec<_> = simulated
+        .actuations()
+        .into_iter()
+        .filter(|record| record.cause == ActuationCause::Command && record.duty_percent > 0.0)
+        .map(|record| record.channels)
+        .collect();
+    assert_eq!(turned_on, (1..=CHANNEL_COUNT).map(|channel| vec![channel]).collect::<Vec<_>>());
+
+    let refused = Command::new(env!("CARGO_BIN_EXE_pdm_ctl"))
+        .args([
+            "--config",
+            dir.join("pdm_0.yaml").to_str().unwrap(),
+            "on",
+            "--channel",
+            "13",
+        ])
+        .output()
+        .unwrap();
+    assert!(!refused.status.success());
+    std::fs::remove_dir_all(dir).unwrap();
+}
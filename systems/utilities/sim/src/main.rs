@@ -0,0 +1,79 @@
+//! Simulation binary, running the crop bed components against simulated
+//! PDMs, cameras and wheel speed from a scenario file and reporting what
+//! the PDMs did.
+use clap::Parser;
+use onyx::{
+    components::traits::ComponentError,
+    devices::software::scenario::{SimReport, SimScenario},
+    error::OnyxError,
+    utils::{config::ConfigFile, logging::TracingConfig},
+};
+use std::{io, path::PathBuf, process::ExitCode};
+use tracing::{error, info};
+
+/// Arguments required for starting the program from the command line.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Scenario file, the files of the beds relative to it.
+    #[arg(short, long)]
+    scenario: PathBuf,
+    /// File the report is written to as yaml, printed to stdout when not
+    /// set.
+    #[arg(short, long)]
+    report: Option<PathBuf>,
+    /// Events printed to stderr, a level such as `debug` or `RUST_LOG`
+    /// style directives, `RUST_LOG` then `info` when not set.
+    #[arg(long)]
+    log_level: Option<String>,
+    /// Print each event as a line of json.
+    #[arg(long)]
+    log_json: bool,
+}
+
+/// Write the report to its file, or to stdout.
+///
+/// * `report`: report of the run.
+/// * `path`: file written to.
+fn write_report(report: &SimReport, path: Option<&PathBuf>) -> io::Result<()> {
+    let yaml = serde_yaml::to_string(report).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    match path {
+        Some(path) => std::fs::write(path, yaml),
+        None => {
+            print!("{yaml}");
+            Ok(())
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    // Stdout is left to the report.
+    TracingConfig::new(args.log_level.clone(), args.log_json)
+        .with_stderr()
+        .init();
+    let scenario = match SimScenario::load_validated(&args.scenario) {
+        Ok(scenario) => scenario,
+        Err(report) => {
+            error!("{report}");
+            return ExitCode::from(OnyxError::from(ComponentError::from(report)).exit_code());
+        }
+    };
+    let report = match scenario.run().await {
+        Ok(report) => report,
+        Err(e) => {
+            error!("{e}");
+            return ExitCode::from(OnyxError::from(e).exit_code());
+        }
+    };
+    if let Err(e) = write_report(&report, args.report.as_ref()) {
+        error!("Failed to write the report: {e}");
+        return ExitCode::FAILURE;
+    }
+    info!("{report}");
+    if report.passed() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,129 @@
+//! The simulation binary running scenarios against the simulated devices.
+use onyx::{
+    components::prelude::*,
+    devices::{
+        hardware::pdm::{PdmAddress, PdmConfig},
+        software::scenario::{SimAction, SimBed, SimExpectation, SimScenario},
+    },
+    utils::{location::CropBed, tasks::EXIT_INVALID_CONFIG},
+};
+use serial_test::serial;
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+use uuid::Uuid;
+
+/// Virtual canbus interface the tests run on.
+fn vcan_interface() -> String {
+    std::env::var("ONYX_VCAN").unwrap_or_else(|_| String::from("vcan0"))
+}
+
+/// Write a scenario with one bed to a directory of its own, the power
+/// driving Pdm30 on the virtual canbus. Returns the directory and the
+/// scenario file.
+///
+/// * `scenario`: scenario run, the bed added to it.
+fn write_scenario(scenario: SimScenario) -> (PathBuf, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("onyx-sim-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let pdm_config_file = dir.join("pdm_0.yaml");
+    serde_yaml::to_writer(
+        std::fs::File::create(&pdm_config_file).unwrap(),
+        &PdmConfig::new(PdmAddress::Pdm30, 0),
+    )
+    .unwrap();
+    let power =
+        CropBedPowerConfig::new(CropBed::Centre, vcan_interface(), 17706, None).add_pdm_config_file(pdm_config_file, 0);
+    serde_yaml::to_writer(std::fs::File::create(dir.join("power.yaml")).unwrap(), &power).unwrap();
+    let scenario_file = dir.join("scenario.yaml");
+    serde_yaml::to_writer(
+        std::fs::File::create(&scenario_file).unwrap(),
+        &scenario.add_bed(SimBed::new("power.yaml")),
+    )
+    .unwrap();
+    (dir, scenario_file)
+}
+
+/// Run the binary on a scenario to completion.
+///
+/// * `scenario_file`: scenario run.
+/// * `report_file`: file the report is written to.
+fn sim(scenario_file: &Path, report_file: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_onyx-sim"))
+        .arg("--scenario")
+        .arg(scenario_file)
+        .arg("--report")
+        .arg(report_file)
+        .output()
+        .expect("Failed to run onyx-sim")
+}
+
+#[test]
+/// A scenario acting on a crop bed no bed runs is refused as an invalid
+/// config before anything is started.
+fn test_invalid_scenario_exits() {
+    let (dir, scenario_file) = write_scenario(SimScenario::new(100).add_event(
+        50,
+        SimAction::EmergencyStop {
+            crop_bed: CropBed::LeftBoom,
+        },
+    ));
+    let report_file = dir.join("report.yaml");
+    let output = sim(&scenario_file, &report_file);
+    assert_eq!(output.status.code(), Some(i32::from(EXIT_INVALID_CONFIG)), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("events.0.action"),
+        "{output:?}"
+    );
+    assert!(!report_file.exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+#[serial]
+#[cfg_attr(not(feature = "vcan_test"), ignore)]
+/// A scenario met by the components succeeds, the report written to its
+/// file with the actuations of the simulated PDM.
+fn test_scenario_report_written() {
+    let scenario = SimScenario::new(1000)
+        .add_event(
+            100,
+            SimAction::Weed {
+                crop_bed: CropBed::Centre,
+                channels: vec![0],
+                cam_id: 0,
+                start_in_ms: 300,
+                duration_ms: 200,
+                intensity: 100,
+                assumed_speed_mps: None,
+            },
+        )
+        .expect(
+            SimExpectation::new(CropBed::Centre, PdmAddress::Pdm30, 1, 1)
+                .with_on_at([400])
+                .with_tolerance(100),
+        );
+    let (dir, scenario_file) = write_scenario(scenario);
+    let report_file = dir.join("report.yaml");
+    let output = sim(&scenario_file, &report_file);
+    assert!(output.status.success(), "{output:?}");
+
+    let report: serde_yaml::Value = serde_yaml::from_reader(std::fs::File::open(&report_file).unwrap()).unwrap();
+    assert_eq!(
+        report["expectations"][0]["problems"],
+        serde_yaml::Value::Sequence(Vec::new()),
+        "{report:?}"
+    );
+    assert_eq!(
+        report["expectations"][0]["expectation"]["address"],
+        serde_yaml::Value::from(30)
+    );
+    assert!(
+        report["actuations"]
+            .as_sequence()
+            .is_some_and(|actuations| actuations.iter().any(|actuation| actuation["cause"] == "command")),
+        "{report:?}"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,207 @@
+//! Weed message injector binary, driving the crop bed power on the bench or
+//! in the field without the AI system.
+use chrono::{Duration, Utc};
+use clap::{Parser, Subcommand};
+use onyx::{
+    components::crop_bed::actuating::power::{
+        journal::{read_journal, replay_with},
+        udp::Transport,
+    },
+    messages::{
+        control::{
+            response::ControlResponse,
+            weed::{WeedMessage, FULL_INTENSITY},
+        },
+        encoding::Encoding,
+        envelope::Envelope,
+    },
+    utils::{client::ComponentClient, location::CropBed, logging::TracingConfig, net::Framing},
+};
+use serde::de::DeserializeOwned;
+use std::{io, path::PathBuf, process::ExitCode};
+use tracing::{error, info};
+
+/// Arguments required for starting the program from the command line.
+#[derive(Parser, Debug)]
+struct Args {
+    /// What to send.
+    #[command(subcommand)]
+    command: Command,
+    /// Address of the weed message socket, i.e. 127.0.0.1:17652.
+    #[arg(short, long, global = true, default_value = "127.0.0.1:17652")]
+    target: String,
+    /// Transport the messages are sent over, tcp or udp.
+    #[arg(long, global = true, default_value = "tcp", value_parser = parse_transport)]
+    transport: Transport,
+    /// Encoding of the TCP connection, json, cbor or message_pack.
+    #[arg(long, global = true, default_value = "json", value_parser = parse_setting::<Encoding>)]
+    encoding: Encoding,
+    /// Framing of the TCP connection, lines or length_prefixed, the default
+    /// of the encoding when not set.
+    #[arg(long, global = true, value_parser = parse_setting::<Framing>)]
+    framing: Option<Framing>,
+    /// Time in milliseconds waited for each response.
+    #[arg(long, global = true, default_value_t = 2000)]
+    timeout_ms: u64,
+    /// Events printed to stderr, a level such as `debug` or `RUST_LOG`
+    /// style directives, `RUST_LOG` then `info` when not set.
+    #[arg(long, global = true)]
+    log_level: Option<String>,
+    /// Print each event as a line of json.
+    #[arg(long, global = true)]
+    log_json: bool,
+}
+
+/// Messages the injector sends.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Build weed messages and send them one after another.
+    Send {
+        /// Crop bed module, i.e. left_boom or the legacy id.
+        #[arg(long)]
+        crop_bed: CropBed,
+        /// Camera the messages claim to come from.
+        #[arg(long, default_value_t = 0)]
+        cam_id: u8,
+        /// Zero based channels to open, comma separated.
+        #[arg(long, required = true, value_delimiter = ',')]
+        channels: Vec<u8>,
+        /// Time in milliseconds from sending each message to the spray.
+        #[arg(long, default_value_t = 500)]
+        start_in_ms: i64,
+        /// Time in milliseconds each spray lasts.
+        #[arg(long, default_value_t = 100)]
+        duration_ms: i64,
+        /// Duty cycle of the channels in percent.
+        #[arg(long, default_value_t = FULL_INTENSITY,
+              value_parser = clap::value_parser!(u8).range(0..=i64::from(FULL_INTENSITY)))]
+        intensity: u8,
+        /// Number of messages sent.
+        #[arg(long, default_value_t = 1)]
+        repeat: u32,
+        /// Time in milliseconds between sending the messages.
+        #[arg(long, default_value_t = 0)]
+        gap_ms: u64,
+        /// Prefix of the message ids, numbered from 0. A prefix from the
+        /// time of the run when not set, so a second run is not dropped as
+        /// duplicates.
+        #[arg(long)]
+        message_id_prefix: Option<String>,
+        /// Wrap each message in an envelope.
+        #[arg(long)]
+        envelope: bool,
+    },
+    /// Replay a weed message journal with the time between the lines it was
+    /// received with.
+    Replay {
+        /// Journal file, current or rotated.
+        #[arg(long)]
+        journal: PathBuf,
+    },
+}
+
+/// Read a setting as it is written in the configs.
+///
+/// * `value`: value given on the command line.
+fn parse_setting<T: DeserializeOwned>(value: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::from(value)).map_err(|e| e.to_string())
+}
+
+/// Read the transport, which must be one of tcp or udp.
+///
+/// * `value`: value given on the command line.
+fn parse_transport(value: &str) -> Result<Transport, String> {
+    match parse_setting(value)? {
+        Transport::Both => Err(String::from("messages are sent over one of tcp or udp")),
+        transport => Ok(transport),
+    }
+}
+
+/// Print a response as a line of json, as the component wrote it.
+///
+/// * `response`: response to a message.
+fn print_response(response: &ControlResponse) {
+    println!(
+        "{}",
+        serde_json::to_string(response).expect("Failed to serialise response")
+    );
+}
+
+/// Connect to the component over the transport chosen.
+///
+/// * `args`: arguments given on the command line.
+async fn connect(args: &Args) -> io::Result<ComponentClient> {
+    let client = match args.transport {
+        Transport::Udp => ComponentClient::connect_udp(args.target.as_str()).await?,
+        _ => ComponentClient::connect_tcp(args.target.as_str(), args.encoding, args.framing).await?,
+    };
+    Ok(client.with_timeout(std::time::Duration::from_millis(args.timeout_ms)))
+}
+
+/// Send what the command asks for, printing each response.
+///
+/// * `args`: arguments given on the command line.
+async fn run(args: &Args) -> io::Result<()> {
+    let mut client = connect(args).await?;
+    match &args.command {
+        Command::Send {
+            crop_bed,
+            cam_id,
+            channels,
+            start_in_ms,
+            duration_ms,
+            intensity,
+            repeat,
+            gap_ms,
+            message_id_prefix,
+            envelope,
+        } => {
+            let prefix = message_id_prefix
+                .clone()
+                .unwrap_or_else(|| format!("inject-{}", Utc::now().timestamp_millis()));
+            for index in 0..*repeat {
+                if index > 0 {
+                    tokio::time::sleep(std::time::Duration::from_millis(*gap_ms)).await;
+                }
+                let start_spray_time = Utc::now() + Duration::milliseconds(*start_in_ms);
+                let message = WeedMessage::new(
+                    *crop_bed,
+                    *cam_id,
+                    start_spray_time,
+                    start_spray_time + Duration::milliseconds(*duration_ms),
+                )
+                .channels(channels.clone())
+                .intensity(*intensity)
+                .message_id(format!("{prefix}-{index}"));
+                let response = if *envelope {
+                    client.send(&Envelope::new(message)).await?
+                } else {
+                    client.send(&message).await?
+                };
+                print_response(&response);
+            }
+        }
+        Command::Replay { journal } => {
+            let entries = read_journal(journal)?;
+            info!("Replaying {} journal entries from {}", entries.len(), journal.display());
+            replay_with(&entries, &mut client, |_, response| print_response(response)).await?;
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    // Stdout is left to the responses.
+    TracingConfig::new(args.log_level.clone(), args.log_json)
+        .with_stderr()
+        .init();
+    match run(&args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("Failed to send to {}: {e}", args.target);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! The injector driving a running crop bed power, over each transport and
+//! from its journal.
+use onyx::{
+    components::{crop_bed::actuating::power::udp::Transport, prelude::*},
+    devices::{
+        hardware::pdm::{PdmAddress, PdmConfig},
+        software::pdm::{ActuationCause, SimulatedPdm, SimulatedPdmHandle},
+    },
+    messages::control::response::ControlResponse,
+    utils::location::CropBed,
+};
+use std::{path::Path, process::Command};
+use uuid::Uuid;
+
+/// Virtual canbus interface the tests run on.
+fn vcan_interface() -> String {
+    std::env::var("ONYX_VCAN").unwrap_or_else(|_| String::from("vcan0"))
+}
+
+/// Run the injector to completion and read the responses it printed,
+/// failing when it does not succeed.
+///
+/// * `args`: subcommand and its arguments.
+async fn inject(args: &[&str]) -> Vec<ControlResponse> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let output = tokio::task::spawn_blocking(move || {
+        Command::new(env!("CARGO_BIN_EXE_weed_injector"))
+            .args(args)
+            .output()
+            .expect("Failed to run weed_injector")
+    })
+    .await
+    .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{line:?} is not a response, {e}")))
+        .collect()
+}
+
+/// Config of a crop bed power taking messages over TCP and UDP, and the
+/// simulated PDM it drives.
+///
+/// * `dir`: directory the configs are written to.
+/// * `port`: port the messages are received on.
+fn power_with_simulated_pdm(dir: &Path, port: i32) -> (CropBedPowerConfig, SimulatedPdm) {
+    let pdm_config = PdmConfig::new(PdmAddress::Pdm30, 0).with_response_timeout(std::time::Duration::from_millis(200));
+    let pdm_config_file = dir.join("pdm_0.yaml");
+    serde_yaml::to_writer(std::fs::File::create(&pdm_config_file).unwrap(), &pdm_config).unwrap();
+    let config = CropBedPowerConfig::new(CropBed::LeftBoom, vcan_interface(), port, None)
+        .add_pdm_config_file(pdm_config_file, 0)
+        .with_transport(Transport::Both);
+    let simulated = SimulatedPdm::new(pdm_config);
+    (config, simulated)
+}
+
+/// Times the simulated PDM turned channels on at the command of the
+/// component.
+///
+/// * `simulated`: running simulated PDM.
+fn sprays(simulated: &SimulatedPdmHandle) -> usize {
+    simulated
+        .actuations()
+        .into_iter()
+        .filter(|record| record.cause == ActuationCause::Command && record.duty_percent > 0.0)
+        .count()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[cfg_attr(not(feature = "vcan_test"), ignore)]
+/// Messages built by the injector are acked over TCP and UDP and reach the
+/// simulated PDM, and a journal of them replays onto a restarted component
+/// with an ack for each line.
+async fn test_inject_and_replay() {
+    let port = 17693;
+    let target = format!("127.0.0.1:{port}");
+    let dir = std::env::temp_dir().join(format!("weed-injector-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let journal = dir.join("weed.jsonl");
+    let (config, simulated) = power_with_simulated_pdm(&dir, port);
+    let config = config.with_journal(journal.clone());
+    let simulated = simulated
+        .start(&vcan_interface())
+        .expect("Failed to start simulated PDM");
+    let component = CropBedPowerController::start(CropBedPower::new(config.clone()).unwrap())
+        .await
+        .unwrap();
+
+    let send = [
+        "send",
+        "--target",
+        &target,
+        "--crop-bed",
+        "left_boom",
+        "--channels",
+        "1",
+        "--start-in-ms",
+        "300",
+        "--duration-ms",
+        "100",
+        "--gap-ms",
+        "250",
+    ];
+    let tcp = inject(&[&send[..], &["--repeat", "2", "--message-id-prefix", "tcp"]].concat()).await;
+    let correlation: Vec<_> = tcp.iter().map(|response| response.correlation_id.as_deref()).collect();
+    assert_eq!(correlation, [Some("tcp-0"), Some("tcp-1")]);
+    // Clear of the last spray, so the channel is turned on again.
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    let udp = inject(
+        &[
+            &send[..],
+            &["--transport", "udp", "--message-id-prefix", "udp", "--envelope"],
+        ]
+        .concat(),
+    )
+    .await;
+    assert_eq!(udp.len(), 1);
+    assert!(
+        tcp.iter().chain(&udp).all(ControlResponse::is_accepted),
+        "{tcp:?} {udp:?}"
+    );
+    tokio::time::sleep(std::time::Duration::from_millis(800)).await;
+    component.shutdown().await;
+    assert_eq!(sprays(&simulated), 3, "PDM saw {:?}", simulated.actuations());
+
+    let component =
+        CropBedPowerController::start(CropBedPower::new(config.with_journal(dir.join("replay.jsonl"))).unwrap())
+            .await
+            .unwrap();
+    let replayed = inject(&["replay", "--target", &target, "--journal", journal.to_str().unwrap()]).await;
+    assert_eq!(replayed.len(), 3);
+    assert!(replayed.iter().all(ControlResponse::is_accepted), "{replayed:?}");
+    tokio::time::sleep(std::time::Duration::from_millis(800)).await;
+    component.shutdown().await;
+    assert_eq!(sprays(&simulated), 6, "PDM saw {:?}", simulated.actuations());
+    std::fs::remove_dir_all(dir).unwrap();
+}